@@ -0,0 +1,6 @@
+// Compiles the gRPC schema with the vendored protoc, so no system install is needed.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    tonic_prost_build::compile_protos("proto/exchange.proto")?;
+    Ok(())
+}
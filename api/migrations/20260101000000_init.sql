@@ -0,0 +1,33 @@
+CREATE TABLE users (
+    id BIGINT PRIMARY KEY,
+    name TEXT NOT NULL,
+    balance DOUBLE PRECISION NOT NULL
+);
+
+-- `version` increases with every write; writers that read an older version lose.
+CREATE TABLE baskets (
+    id BIGINT PRIMARY KEY,
+    assets JSONB NOT NULL,
+    version BIGINT NOT NULL DEFAULT 1,
+    auctioned BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE TABLE bids (
+    id BIGINT PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users (id),
+    basket_id BIGINT NOT NULL REFERENCES baskets (id),
+    bid_type TEXT NOT NULL CHECK (bid_type IN ('XOR', 'OR')),
+    price DOUBLE PRECISION NOT NULL,
+    quantity DOUBLE PRECISION
+);
+
+CREATE INDEX bids_basket_id ON bids (basket_id);
+
+CREATE TABLE auction_outcomes (
+    auction_id BIGINT PRIMARY KEY,
+    settlement_id BIGINT NOT NULL UNIQUE,
+    basket_id BIGINT NOT NULL UNIQUE REFERENCES baskets (id),
+    mechanism TEXT NOT NULL,
+    outcome JSONB NOT NULL,
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,7 @@
+-- Basket NAVs snapshotted from the marks, `at` in Unix milliseconds.
+CREATE TABLE nav_snapshots (
+    basket_id BIGINT NOT NULL REFERENCES baskets (id),
+    at BIGINT NOT NULL,
+    nav DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (basket_id, at)
+);
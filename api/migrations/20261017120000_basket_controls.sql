@@ -0,0 +1,6 @@
+-- Bidding deadlines, `closes_at` in Unix milliseconds, and the soft-close moves of them.
+CREATE TABLE basket_controls (
+    basket_id BIGINT PRIMARY KEY REFERENCES baskets (id),
+    closes_at BIGINT,
+    extensions INTEGER NOT NULL DEFAULT 0
+);
@@ -0,0 +1,10 @@
+-- The clearing ledger, one row per journal entry in id order.
+CREATE TABLE ledger_entries (
+    id BIGINT PRIMARY KEY,
+    entry JSONB NOT NULL
+);
+
+-- Baskets whose auction an operator halted.
+CREATE TABLE halted_baskets (
+    basket_id BIGINT PRIMARY KEY REFERENCES baskets (id)
+);
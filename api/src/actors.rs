@@ -0,0 +1,361 @@
+//! The exchange as actors that own their state and talk over bounded channels:
+//!
+//! - the bid book owns the [`Exchange`]: users, baskets, resting bids, the escrow they
+//!   lock and the clearing ledger, so every change to them is made by one task in
+//!   mailbox order;
+//! - an auction actor per basket takes the basket's bids from the book, each funded by
+//!   its escrow there, and determines the winners on the blocking pool, away from
+//!   everything else. A bid the user cannot fund beside their bids on other baskets is
+//!   refused by the book, so auctions of different baskets never spend the same funds;
+//! - the clearing actor settles solved auctions one at a time through the book and
+//!   publishes each outcome;
+//! - the market data actor keeps the latest mark of each asset, at which clock auctions
+//!   open.
+//!
+//! Mailboxes are bounded, so a caller waits when an actor falls behind rather than
+//! queueing without limit. Auction actors are supervised: one that dies is replaced on
+//! its basket's next auction, and its caller gets an error instead of hanging.
+
+use std::collections::HashMap;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio::task::JoinHandle;
+use model::ids::{BasketId, BidId, UserId};
+use model::model::{AssetInfo, Basket, BidType, User};
+use crate::error::ApiError;
+use crate::exchange::{AuctionOutcome, AuctionRequest, BidRecord, Exchange, Mechanism, PendingAuction, SolvedAuction};
+
+
+/// Mailbox sizes of the actors.
+#[derive(Debug, Clone)]
+pub struct ActorSettings {
+    pub mailbox: usize,
+    /// Settled outcomes buffered per subscriber; a subscriber further behind skips ahead.
+    pub outcomes: usize,
+}
+
+impl Default for ActorSettings {
+    fn default() -> Self {
+        ActorSettings { mailbox: 256, outcomes: 1024 }
+    }
+}
+
+type Reply<T> = oneshot::Sender<Result<T, ApiError>>;
+
+enum BookCommand {
+    RegisterUser { name: String, balance: f64, reply: Reply<User> },
+    User { user_id: UserId, reply: Reply<User> },
+    CreateBasket { assets: Vec<AssetInfo>, reply: Reply<Basket> },
+    SubmitBid { user_id: UserId, basket_id: BasketId, bid_type: BidType, price: f64, quantity: Option<f64>, reply: Reply<BidRecord> },
+    CancelBid { bid_id: BidId, reply: Reply<BidRecord> },
+    Mark { base: String, price: f64 },
+    Open { request: AuctionRequest, reply: Reply<PendingAuction> },
+    Settle { solved: Box<SolvedAuction>, reply: Reply<AuctionOutcome> },
+}
+
+struct RunAuction {
+    request: AuctionRequest,
+    reply: Reply<AuctionOutcome>,
+}
+
+struct Settle {
+    solved: SolvedAuction,
+    reply: Reply<AuctionOutcome>,
+}
+
+enum MarketDataCommand {
+    Mark { base: String, price: f64 },
+    Marks { reply: oneshot::Sender<HashMap<String, f64>> },
+}
+
+fn stopped() -> ApiError {
+    ApiError::Internal("exchange is shutting down".to_string())
+}
+
+/// Sends the command `make` builds around a reply channel and waits for the answer.
+async fn ask<C, T>(mailbox: &mpsc::Sender<C>, make: impl FnOnce(Reply<T>) -> C) -> Result<T, ApiError> {
+    let (reply, answer) = oneshot::channel();
+    mailbox.send(make(reply)).await.map_err(|_| stopped())?;
+    answer.await.map_err(|_| ApiError::Internal("actor stopped before answering".to_string()))?
+}
+
+
+async fn run_book(mut exchange: Exchange, mut mailbox: mpsc::Receiver<BookCommand>) -> Exchange {
+    while let Some(command) = mailbox.recv().await {
+        // A caller that gave up is no reason to stop, so failed replies are ignored
+        match command {
+            BookCommand::RegisterUser { name, balance, reply } => {
+                let _ = reply.send(exchange.register_user(&name, balance));
+            }
+            BookCommand::User { user_id, reply } => {
+                let _ = reply.send(exchange.user(user_id).cloned());
+            }
+            BookCommand::CreateBasket { assets, reply } => {
+                let _ = reply.send(exchange.create_basket(assets));
+            }
+            BookCommand::SubmitBid { user_id, basket_id, bid_type, price, quantity, reply } => {
+                let _ = reply.send(exchange.submit_bid(user_id, basket_id, bid_type, price, quantity));
+            }
+            BookCommand::CancelBid { bid_id, reply } => {
+                let _ = reply.send(exchange.cancel_bid(bid_id));
+            }
+            BookCommand::Mark { base, price } => {
+                exchange.update_mark(&base, price);
+            }
+            BookCommand::Open { request, reply } => {
+                let _ = reply.send(exchange.open_auction(&request));
+            }
+            BookCommand::Settle { solved, reply } => {
+                let _ = reply.send(exchange.settle_auction(*solved));
+            }
+        }
+    }
+    exchange
+}
+
+async fn run_clearing(book: mpsc::Sender<BookCommand>, outcomes: broadcast::Sender<AuctionOutcome>, mut mailbox: mpsc::Receiver<Settle>) {
+    while let Some(Settle { solved, reply }) = mailbox.recv().await {
+        let settled = ask(&book, |reply| BookCommand::Settle { solved: Box::new(solved), reply }).await;
+        if let Ok(outcome) = &settled {
+            // No subscribers is not an error
+            let _ = outcomes.send(outcome.clone());
+        }
+        let _ = reply.send(settled);
+    }
+}
+
+async fn run_market_data(mut mailbox: mpsc::Receiver<MarketDataCommand>) {
+    let mut marks = HashMap::new();
+    while let Some(command) = mailbox.recv().await {
+        match command {
+            MarketDataCommand::Mark { base, price } => {
+                marks.insert(base, price);
+            }
+            MarketDataCommand::Marks { reply } => {
+                let _ = reply.send(marks.clone());
+            }
+        }
+    }
+}
+
+/// Everything an auction actor talks to.
+#[derive(Clone)]
+struct AuctionPeers {
+    book: mpsc::Sender<BookCommand>,
+    clearing: mpsc::Sender<Settle>,
+    market_data: mpsc::Sender<MarketDataCommand>,
+}
+
+impl AuctionPeers {
+    async fn run(&self, request: AuctionRequest) -> Result<AuctionOutcome, ApiError> {
+        let mut pending = ask(&self.book, |reply| BookCommand::Open { request, reply }).await?;
+        if pending.mechanism() == Mechanism::Cca {
+            let (reply, marks) = oneshot::channel();
+            self.market_data.send(MarketDataCommand::Marks { reply }).await.map_err(|_| stopped())?;
+            pending.reprice(&marks.await.map_err(|_| stopped())?);
+        }
+        let solved = tokio::task::spawn_blocking(move || pending.solve()).await
+            .map_err(|error| ApiError::Internal(format!("winner determination failed: {}", error)))?;
+        ask(&self.clearing, |reply| Settle { solved, reply }).await
+    }
+}
+
+async fn run_auction_actor(peers: AuctionPeers, mut mailbox: mpsc::Receiver<RunAuction>) {
+    while let Some(RunAuction { request, reply }) = mailbox.recv().await {
+        let _ = reply.send(peers.run(request).await);
+    }
+}
+
+/// Routes each auction to its basket's actor, starting the actor on first use and again
+/// if it has died, and waits for them all when the mailbox closes.
+async fn supervise_auctions(peers: AuctionPeers, mailbox_size: usize, mut mailbox: mpsc::Receiver<RunAuction>) {
+    let mut actors: HashMap<BasketId, (mpsc::Sender<RunAuction>, JoinHandle<()>)> = HashMap::new();
+    while let Some(command) = mailbox.recv().await {
+        let basket_id = command.request.basket_id;
+        if actors.get(&basket_id).is_some_and(|(sender, _)| sender.is_closed()) {
+            tracing::warn!(basket_id = basket_id.get(), "auction actor died; restarting it");
+            actors.remove(&basket_id);
+        }
+        let (sender, _) = actors.entry(basket_id).or_insert_with(|| {
+            let (sender, receiver) = mpsc::channel(mailbox_size);
+            (sender, tokio::spawn(run_auction_actor(peers.clone(), receiver)))
+        });
+        if let Err(mpsc::error::SendError(command)) = sender.send(command).await {
+            let _ = command.reply.send(Err(ApiError::Internal("auction actor stopped".to_string())));
+        }
+    }
+    for (_, (sender, task)) in actors.drain() {
+        drop(sender);
+        let _ = task.await;
+    }
+}
+
+
+/// Talks to a running exchange. Clones share the same actors.
+#[derive(Debug, Clone)]
+pub struct ExchangeHandle {
+    book: mpsc::Sender<BookCommand>,
+    auctions: mpsc::Sender<RunAuction>,
+    market_data: mpsc::Sender<MarketDataCommand>,
+    outcomes: broadcast::Sender<AuctionOutcome>,
+}
+
+/// The running actors, to stop once the handles are done with.
+#[derive(Debug)]
+pub struct ExchangeActors {
+    book: JoinHandle<Exchange>,
+    auctions: JoinHandle<()>,
+    clearing: JoinHandle<()>,
+    market_data: JoinHandle<()>,
+}
+
+/// Starts the actors around `exchange`. Must be called inside a tokio runtime.
+pub fn spawn(exchange: Exchange, settings: &ActorSettings) -> (ExchangeHandle, ExchangeActors) {
+    let (book, book_mailbox) = mpsc::channel(settings.mailbox);
+    let (clearing, clearing_mailbox) = mpsc::channel(settings.mailbox);
+    let (market_data, market_data_mailbox) = mpsc::channel(settings.mailbox);
+    let (auctions, auctions_mailbox) = mpsc::channel(settings.mailbox);
+    let (outcomes, _) = broadcast::channel(settings.outcomes);
+
+    let peers = AuctionPeers { book: book.clone(), clearing, market_data: market_data.clone() };
+    let actors = ExchangeActors {
+        book: tokio::spawn(run_book(exchange, book_mailbox)),
+        clearing: tokio::spawn(run_clearing(book.clone(), outcomes.clone(), clearing_mailbox)),
+        market_data: tokio::spawn(run_market_data(market_data_mailbox)),
+        auctions: tokio::spawn(supervise_auctions(peers, settings.mailbox, auctions_mailbox)),
+    };
+    (ExchangeHandle { book, auctions, market_data, outcomes }, actors)
+}
+
+impl ExchangeActors {
+    /// Waits for auctions under way to settle, then stops every actor and hands back the
+    /// exchange. Only returns once every [`ExchangeHandle`] has been dropped.
+    pub async fn join(self) -> Result<Exchange, ApiError> {
+        let failed = |error: tokio::task::JoinError| ApiError::Internal(format!("exchange actor failed: {}", error));
+        // Each actor stops once everything that can send to it has, in this order
+        self.auctions.await.map_err(failed)?;
+        self.clearing.await.map_err(failed)?;
+        self.market_data.await.map_err(failed)?;
+        self.book.await.map_err(failed)
+    }
+}
+
+impl ExchangeHandle {
+    pub async fn register_user(&self, name: &str, balance: f64) -> Result<User, ApiError> {
+        ask(&self.book, |reply| BookCommand::RegisterUser { name: name.to_string(), balance, reply }).await
+    }
+
+    pub async fn user(&self, user_id: UserId) -> Result<User, ApiError> {
+        ask(&self.book, |reply| BookCommand::User { user_id, reply }).await
+    }
+
+    pub async fn create_basket(&self, assets: Vec<AssetInfo>) -> Result<Basket, ApiError> {
+        ask(&self.book, |reply| BookCommand::CreateBasket { assets, reply }).await
+    }
+
+    pub async fn submit_bid(&self, user_id: UserId, basket_id: BasketId, bid_type: BidType, price: f64, quantity: Option<f64>) -> Result<BidRecord, ApiError> {
+        ask(&self.book, |reply| BookCommand::SubmitBid { user_id, basket_id, bid_type, price, quantity, reply }).await
+    }
+
+    pub async fn cancel_bid(&self, bid_id: BidId) -> Result<BidRecord, ApiError> {
+        ask(&self.book, |reply| BookCommand::CancelBid { bid_id, reply }).await
+    }
+
+    /// Auctions a basket on its own actor and waits for it to settle.
+    pub async fn run_auction(&self, request: AuctionRequest) -> Result<AuctionOutcome, ApiError> {
+        ask(&self.auctions, |reply| RunAuction { request, reply }).await
+    }
+
+    /// Records the latest price of the asset with base `base`, for clock auctions to open
+    /// at and the book's risk checks.
+    pub async fn mark(&self, base: &str, price: f64) -> Result<(), ApiError> {
+        self.book.send(BookCommand::Mark { base: base.to_string(), price }).await.map_err(|_| stopped())?;
+        self.market_data.send(MarketDataCommand::Mark { base: base.to_string(), price }).await.map_err(|_| stopped())
+    }
+
+    /// Every outcome settled from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<AuctionOutcome> {
+        self.outcomes.subscribe()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use auction::error::ClearingError;
+    use model::model::Asset;
+
+    #[tokio::test]
+    async fn test_auction_through_the_actors() {
+        let (exchange, actors) = spawn(Exchange::new(), &ActorSettings::default());
+        let mut outcomes = exchange.subscribe();
+        let alice = exchange.register_user("Alice", 100_000.0).await.unwrap().id;
+        let bob = exchange.register_user("Bob", 100_000.0).await.unwrap().id;
+        let basket = exchange.create_basket(vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60_000.0)]).await.unwrap().id;
+        exchange.submit_bid(alice, basket, BidType::XOR, 62_000.0, None).await.unwrap();
+        exchange.submit_bid(bob, basket, BidType::XOR, 61_000.0, None).await.unwrap();
+
+        let outcome = exchange.run_auction(AuctionRequest::new(basket, Mechanism::Xor)).await.unwrap();
+        assert_eq!(outcome.payments[&alice], 62_000.0);
+        assert_eq!(outcomes.recv().await.unwrap().auction_id, outcome.auction_id);
+        assert!(matches!(exchange.run_auction(AuctionRequest::new(basket, Mechanism::Xor)).await, Err(ApiError::Conflict(_))));
+        assert_eq!(exchange.user(alice).await.unwrap().balance, 38_000.0);
+
+        drop(exchange);
+        let exchange = actors.join().await.unwrap();
+        assert_eq!(exchange.outcome(outcome.auction_id).unwrap().basket_id, basket);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_the_book_refuses_bids_that_over_commit_escrow() {
+        let (exchange, actors) = spawn(Exchange::new(), &ActorSettings::default());
+        let alice = exchange.register_user("Alice", 100_000.0).await.unwrap().id;
+        let btc = exchange.create_basket(vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60_000.0)]).await.unwrap().id;
+        let eth = exchange.create_basket(vec![AssetInfo::new(Asset::new("ETH", "USD"), 20.0, 3_000.0)]).await.unwrap().id;
+        exchange.submit_bid(alice, btc, BidType::XOR, 61_000.0, None).await.unwrap();
+        let over_committed = exchange.submit_bid(alice, eth, BidType::XOR, 60_000.0, None).await;
+        assert!(matches!(over_committed, Err(ApiError::Rejected(ClearingError::InsufficientFunds { .. }))));
+        exchange.submit_bid(alice, eth, BidType::XOR, 39_000.0, None).await.unwrap();
+
+        let runs: Vec<_> = [btc, eth].into_iter()
+            .map(|basket| {
+                let exchange = exchange.clone();
+                tokio::spawn(async move { exchange.run_auction(AuctionRequest::new(basket, Mechanism::Xor)).await })
+            })
+            .collect();
+        for run in runs {
+            run.await.unwrap().unwrap();
+        }
+        assert_eq!(exchange.user(alice).await.unwrap().balance, 0.0);
+        drop(exchange);
+        actors.join().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_auctions_with_small_mailboxes() {
+        let (exchange, actors) = spawn(Exchange::new(), &ActorSettings { mailbox: 1, outcomes: 1 });
+        let alice = exchange.register_user("Alice", 1_000_000.0).await.unwrap().id;
+        let mut baskets = Vec::new();
+        for (base, price) in [("BTC", 60_000.0), ("ETH", 3_000.0), ("SOL", 150.0), ("XRP", 0.5)] {
+            let basket = exchange.create_basket(vec![AssetInfo::new(Asset::new(base, "USD"), 1.0, price)]).await.unwrap().id;
+            exchange.submit_bid(alice, basket, BidType::XOR, price, None).await.unwrap();
+            baskets.push(basket);
+        }
+        exchange.mark("BTC", 61_000.0).await.unwrap();
+
+        let runs: Vec<_> = baskets.iter()
+            .map(|&basket| {
+                let exchange = exchange.clone();
+                let mechanism = if basket == baskets[0] { Mechanism::Cca } else { Mechanism::Xor };
+                tokio::spawn(async move { exchange.run_auction(AuctionRequest::new(basket, mechanism)).await })
+            })
+            .collect();
+        let mut paid = 0.0;
+        for run in runs {
+            paid += run.await.unwrap().unwrap().payments.values().sum::<f64>();
+        }
+        assert_eq!(exchange.user(alice).await.unwrap().balance, 1_000_000.0 - paid);
+        drop(exchange);
+        assert_eq!(actors.join().await.unwrap().user(alice).unwrap().balance, 1_000_000.0 - paid);
+    }
+}
@@ -0,0 +1,101 @@
+use std::time::Duration;
+use axum::extract::{Path, State};
+use axum::routing::post;
+use axum::{Extension, Json, Router};
+use serde::{Deserialize, Serialize};
+use model::ids::{BasketId, BidId, UserId};
+use crate::auth::{self, Principal, Scope};
+use crate::error::ApiError;
+use crate::exchange::BidRecord;
+use crate::routes::SharedExchange;
+
+
+/// An operator's intervention in an auction, as recorded in the journal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum AdminAction {
+    /// Bidding and auctioning stop; an auction in flight is not settled.
+    Halt { basket_id: BasketId },
+    Resume { basket_id: BasketId },
+    /// Bidding now closes at `closes_at`, in Unix milliseconds.
+    Extend { basket_id: BasketId, closes_at: u64 },
+    /// The resting bids were cancelled and an auction in flight is not settled.
+    Cancel { basket_id: BasketId, cancelled_bids: Vec<BidId> },
+    /// Bids resting unchanged since before `before`, in Unix milliseconds, were expired.
+    ExpireStaleBids { before: u64, expired_bids: Vec<BidId> },
+}
+
+impl AdminAction {
+    pub fn basket_id(&self) -> Option<BasketId> {
+        match self {
+            AdminAction::Halt { basket_id }
+            | AdminAction::Resume { basket_id }
+            | AdminAction::Extend { basket_id, .. }
+            | AdminAction::Cancel { basket_id, .. } => Some(*basket_id),
+            AdminAction::ExpireStaleBids { .. } => None,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExtendBidding {
+    pub seconds: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExpireBids {
+    /// Bids resting unchanged for longer than this are expired.
+    pub older_than_seconds: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Extended {
+    pub basket_id: BasketId,
+    pub closes_at: u64,
+}
+
+
+/// The operator endpoints, all requiring the admin scope. They take effect at once and
+/// are journaled with the operator's user id, if the admin has one.
+pub fn router() -> Router<SharedExchange> {
+    Router::new()
+        .route("/admin/baskets/{id}/halt", post(halt))
+        .route("/admin/baskets/{id}/resume", post(resume))
+        .route("/admin/baskets/{id}/extend", post(extend))
+        .route("/admin/baskets/{id}/cancel", post(cancel))
+        .route("/admin/bids/expire", post(expire))
+}
+
+/// The admin calling, or `None` when the router is not secured.
+fn operator(caller: &Option<Extension<Principal>>) -> Result<Option<UserId>, ApiError> {
+    let principal = caller.as_ref().map(|Extension(principal)| principal);
+    auth::require(principal, Scope::Admin)?;
+    Ok(principal.and_then(|principal| principal.user_id))
+}
+
+async fn halt(State(exchange): State<SharedExchange>, caller: Option<Extension<Principal>>, Path(id): Path<BasketId>) -> Result<Json<AdminAction>, ApiError> {
+    let operator = operator(&caller)?;
+    Ok(Json(exchange.lock().unwrap().halt_auction(id, operator)?))
+}
+
+async fn resume(State(exchange): State<SharedExchange>, caller: Option<Extension<Principal>>, Path(id): Path<BasketId>) -> Result<Json<AdminAction>, ApiError> {
+    let operator = operator(&caller)?;
+    Ok(Json(exchange.lock().unwrap().resume_auction(id, operator)?))
+}
+
+async fn extend(State(exchange): State<SharedExchange>, caller: Option<Extension<Principal>>, Path(id): Path<BasketId>, Json(request): Json<ExtendBidding>) -> Result<Json<Extended>, ApiError> {
+    let operator = operator(&caller)?;
+    let closes_at = exchange.lock().unwrap().extend_bidding(id, Duration::from_secs(request.seconds), operator)?;
+    Ok(Json(Extended { basket_id: id, closes_at }))
+}
+
+async fn cancel(State(exchange): State<SharedExchange>, caller: Option<Extension<Principal>>, Path(id): Path<BasketId>) -> Result<Json<Vec<BidRecord>>, ApiError> {
+    let operator = operator(&caller)?;
+    Ok(Json(exchange.lock().unwrap().cancel_auction(id, operator)?))
+}
+
+async fn expire(State(exchange): State<SharedExchange>, caller: Option<Extension<Principal>>, Json(request): Json<ExpireBids>) -> Result<Json<Vec<BidRecord>>, ApiError> {
+    let operator = operator(&caller)?;
+    Ok(Json(exchange.lock().unwrap().expire_stale_bids(Duration::from_secs(request.older_than_seconds), operator)?))
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use model::ids::UserId;
+use crate::error::ApiError;
+
+/// Header carrying an API key; a JWT goes in `Authorization: Bearer <token>`.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+
+/// What a caller may do. Each scope includes the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// Baskets, bids and auctions, and the caller's own account.
+    ReadOnly,
+    /// Submitting and cancelling the caller's own bids.
+    Trade,
+    /// Users, baskets and auctions, on behalf of anyone.
+    Admin,
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Scope::ReadOnly => "read_only",
+            Scope::Trade => "trade",
+            Scope::Admin => "admin",
+        })
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read_only" | "read" => Ok(Scope::ReadOnly),
+            "trade" => Ok(Scope::Trade),
+            "admin" => Ok(Scope::Admin),
+            other => Err(format!("unknown scope '{}'", other)),
+        }
+    }
+}
+
+/// An authenticated caller: the user it acts as, if any, and its scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Principal {
+    pub user_id: Option<UserId>,
+    pub scope: Scope,
+}
+
+impl Principal {
+    pub fn user(user_id: UserId, scope: Scope) -> Self {
+        Principal { user_id: Some(user_id), scope }
+    }
+
+    pub fn admin() -> Self {
+        Principal { user_id: None, scope: Scope::Admin }
+    }
+
+    pub fn require(&self, scope: Scope) -> Result<(), ApiError> {
+        if self.scope >= scope {
+            Ok(())
+        } else {
+            Err(ApiError::Forbidden(match scope {
+                Scope::ReadOnly => "requires the read_only scope",
+                Scope::Trade => "requires the trade scope",
+                Scope::Admin => "requires the admin scope",
+            }))
+        }
+    }
+
+    /// Admins act for anyone; everyone else only for their own user.
+    pub fn require_owner(&self, user_id: UserId) -> Result<(), ApiError> {
+        if self.scope == Scope::Admin || self.user_id == Some(user_id) {
+            Ok(())
+        } else {
+            Err(ApiError::Forbidden("users can only act on their own account and bids"))
+        }
+    }
+}
+
+/// Checks for callers that may be unauthenticated: with no principal, authentication is
+/// switched off and everything is allowed.
+pub fn require(caller: Option<&Principal>, scope: Scope) -> Result<(), ApiError> {
+    caller.map_or(Ok(()), |principal| principal.require(scope))
+}
+
+pub fn require_owner(caller: Option<&Principal>, user_id: UserId) -> Result<(), ApiError> {
+    caller.map_or(Ok(()), |principal| principal.require_owner(user_id))
+}
+
+
+/// Claims of the exchange's JWTs: `sub` is the user id, or any other name for a service
+/// account, which acts for no user.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub scope: Scope,
+    /// Expiry, in Unix seconds.
+    pub exp: u64,
+}
+
+/// Verifies API keys and HS256-signed JWTs.
+#[derive(Clone, Default)]
+pub struct Authenticator {
+    api_keys: HashMap<String, Principal>,
+    jwt: Option<(EncodingKey, DecodingKey)>,
+}
+
+impl fmt::Debug for Authenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Authenticator")
+            .field("api_keys", &self.api_keys.len())
+            .field("jwt", &self.jwt.is_some())
+            .finish()
+    }
+}
+
+impl Authenticator {
+    pub fn new() -> Self {
+        Authenticator::default()
+    }
+
+    pub fn with_api_key(mut self, key: &str, principal: Principal) -> Self {
+        self.api_keys.insert(key.to_string(), principal);
+        self
+    }
+
+    /// Accepts JWTs signed with `secret`.
+    pub fn with_jwt_secret(mut self, secret: &[u8]) -> Self {
+        self.jwt = Some((EncodingKey::from_secret(secret), DecodingKey::from_secret(secret)));
+        self
+    }
+
+    /// Reads `API_KEYS`, comma-separated `key:scope[:user_id]` entries, and
+    /// `API_JWT_SECRET`. `None` when neither is set, i.e. authentication is off.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let keys = std::env::var("API_KEYS").ok();
+        let secret = std::env::var("API_JWT_SECRET").ok();
+        if keys.is_none() && secret.is_none() {
+            return Ok(None);
+        }
+        let mut authenticator = Authenticator::new();
+        for entry in keys.iter().flat_map(|keys| keys.split(',')).map(str::trim).filter(|entry| !entry.is_empty()) {
+            let mut parts = entry.split(':');
+            let (Some(key), Some(scope)) = (parts.next(), parts.next()) else {
+                return Err(format!("API key entry '{}' is not key:scope[:user_id]", entry));
+            };
+            let user_id = parts.next().map(|id| id.parse().map_err(|_| format!("bad user id in API key entry for scope {}", scope))).transpose()?;
+            authenticator = authenticator.with_api_key(key, Principal { user_id, scope: scope.parse()? });
+        }
+        if let Some(secret) = secret {
+            authenticator = authenticator.with_jwt_secret(secret.as_bytes());
+        }
+        Ok(Some(authenticator))
+    }
+
+    /// A JWT for `principal` valid for `ttl_secs`.
+    pub fn issue(&self, principal: &Principal, ttl_secs: u64) -> Result<String, ApiError> {
+        let (encoding, _) = self.jwt.as_ref().ok_or(ApiError::Internal("no JWT secret configured".to_string()))?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let claims = Claims {
+            sub: principal.user_id.map_or_else(|| "service".to_string(), |id| id.to_string()),
+            scope: principal.scope,
+            exp: now + ttl_secs,
+        };
+        jsonwebtoken::encode(&Header::new(Algorithm::HS256), &claims, encoding).map_err(|error| ApiError::Internal(error.to_string()))
+    }
+
+    /// The caller presenting `authorization` (a header value `Bearer <jwt>`) or `api_key`.
+    pub fn authenticate(&self, authorization: Option<&str>, api_key: Option<&str>) -> Result<Principal, ApiError> {
+        if let Some(key) = api_key {
+            return self.api_keys.get(key).copied().ok_or(ApiError::Unauthorized("unknown API key"));
+        }
+        let token = authorization
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(ApiError::Unauthorized("missing credentials"))?;
+        self.verify(token)
+    }
+
+    pub fn verify(&self, token: &str) -> Result<Principal, ApiError> {
+        let (_, decoding) = self.jwt.as_ref().ok_or(ApiError::Unauthorized("tokens are not accepted"))?;
+        let data = jsonwebtoken::decode::<Claims>(token, decoding, &Validation::new(Algorithm::HS256))
+            .map_err(|_| ApiError::Unauthorized("invalid or expired token"))?;
+        Ok(Principal { user_id: data.claims.sub.parse().ok(), scope: data.claims.scope })
+    }
+}
+
+
+/// REST middleware: authenticates every request and hands its [`Principal`] to the
+/// handlers as an extension.
+pub async fn authenticate(State(authenticator): State<Arc<Authenticator>>, mut request: Request, next: Next) -> Response {
+    let authenticated = {
+        let header = |name: &str| request.headers().get(name).and_then(|value| value.to_str().ok());
+        authenticator.authenticate(header("authorization"), header(API_KEY_HEADER))
+    };
+    match authenticated {
+        Ok(principal) => {
+            request.extensions_mut().insert(principal);
+            next.run(request).await
+        }
+        Err(error) => error.into_response(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_tokens_and_scopes() {
+        let authenticator = Authenticator::new()
+            .with_api_key("k-alice", Principal::user(UserId(7), Scope::Trade))
+            .with_jwt_secret(b"secret");
+        let alice = authenticator.authenticate(None, Some("k-alice")).unwrap();
+        assert_eq!(alice, Principal::user(UserId(7), Scope::Trade));
+        assert!(alice.require(Scope::ReadOnly).is_ok());
+        assert_eq!(alice.require(Scope::Admin), Err(ApiError::Forbidden("requires the admin scope")));
+        assert!(alice.require_owner(UserId(7)).is_ok() && alice.require_owner(UserId(8)).is_err());
+        assert!(Principal::admin().require_owner(UserId(8)).is_ok());
+
+        let token = authenticator.issue(&Principal::user(UserId(8), Scope::ReadOnly), 60).unwrap();
+        assert_eq!(authenticator.authenticate(Some(&format!("Bearer {}", token)), None), Ok(Principal::user(UserId(8), Scope::ReadOnly)));
+        let forged = Authenticator::new().with_jwt_secret(b"other").issue(&Principal::admin(), 60).unwrap();
+        assert_eq!(authenticator.verify(&forged), Err(ApiError::Unauthorized("invalid or expired token")));
+        assert_eq!(authenticator.authenticate(None, Some("k-nobody")), Err(ApiError::Unauthorized("unknown API key")));
+        assert_eq!(authenticator.authenticate(None, None), Err(ApiError::Unauthorized("missing credentials")));
+        assert!(require(None, Scope::Admin).is_ok());
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use auction::sim::{SimClock, SimRng};
+use model::ids::BidId;
+use model::model::{Asset, AssetInfo, BidType};
+use quanto_pricer::candles::{resample, Granularity};
+use quanto_pricer::replay::Fixtures;
+use quanto_pricer::realized::Candle;
+use crate::error::ApiError;
+use crate::exchange::{AuctionRequest, Exchange, Mechanism};
+
+
+/// Daily bars of one basket constituent, and how many units of it a basket holds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketSeries {
+    pub asset: Asset,
+    pub units: f64,
+    /// One bar per UTC day, oldest first.
+    pub days: Vec<Candle>,
+}
+
+impl MarketSeries {
+    /// From bars of any granularity, combined into days.
+    pub fn new(asset: Asset, units: f64, candles: &[Candle]) -> Self {
+        MarketSeries { asset, units, days: resample(candles, Granularity::Day1) }
+    }
+
+    /// From recorded candles of `instrument`, e.g. "BTC-PERPETUAL", at the finest
+    /// granularity recorded.
+    pub fn from_fixtures(fixtures: &Fixtures, instrument: &str, asset: Asset, units: f64) -> Result<Self, ApiError> {
+        let candles = [Granularity::Minute1, Granularity::Minute5, Granularity::Minute15, Granularity::Hour1, Granularity::Hour4, Granularity::Day1]
+            .iter()
+            .find_map(|granularity| fixtures.candles.get(&format!("{} {}", instrument, granularity.name())))
+            .ok_or(ApiError::NotFound("recorded candles"))?;
+        Ok(MarketSeries::new(asset, units, candles))
+    }
+}
+
+
+/// How a simulated bidder turns its private estimate of a basket's value into a bid.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum BidderStrategy {
+    /// Bids its estimate.
+    Truthful,
+    /// Bids its estimate less `fraction` of it.
+    Shade { fraction: f64 },
+    /// Ignores its own estimate and bids the opening value moved by `weight` times the
+    /// basket's previous daily return.
+    Momentum { weight: f64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimulatedBidder {
+    pub name: String,
+    /// Cash for the whole sample period.
+    pub budget: f64,
+    pub strategy: BidderStrategy,
+    /// Fraction of the basket bid for; the whole basket when `None`.
+    pub share: Option<f64>,
+}
+
+impl SimulatedBidder {
+    pub fn new(name: &str, budget: f64, strategy: BidderStrategy) -> Self {
+        SimulatedBidder { name: name.to_string(), budget, strategy, share: None }
+    }
+
+    pub fn share(mut self, share: f64) -> Self {
+        self.share = Some(share);
+        self
+    }
+}
+
+
+/// What to replay: the mechanism, the bidders, and how noisy their estimates are.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BacktestConfig {
+    pub mechanism: Mechanism,
+    pub bidders: Vec<SimulatedBidder>,
+    /// Standard deviation of each bidder's estimate around the day's closing value, as a
+    /// fraction of it.
+    pub signal_noise: f64,
+    pub seed: u64,
+}
+
+impl BacktestConfig {
+    pub fn new(mechanism: Mechanism, bidders: Vec<SimulatedBidder>) -> Self {
+        BacktestConfig { mechanism, bidders, signal_noise: 0.02, seed: 0 }
+    }
+
+    pub fn signal_noise(mut self, signal_noise: f64) -> Self {
+        self.signal_noise = signal_noise;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+
+/// One day's auction: the basket valued at the open, as the bidders saw it, and at the
+/// close, as the market later settled it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BacktestDay {
+    /// Open time of the day in Unix milliseconds.
+    pub timestamp: u64,
+    pub open_value: f64,
+    pub close_value: f64,
+    pub winners: usize,
+    /// Sum of the winners' own estimates of what they won.
+    pub welfare: f64,
+    pub revenue: f64,
+    /// Revenue per whole basket sold; `None` if nothing sold.
+    pub clearing_price: Option<f64>,
+}
+
+impl BacktestDay {
+    /// Relative distance of the clearing price from the closing value.
+    pub fn discovery_error(&self) -> Option<f64> {
+        self.clearing_price.map(|price| (price - self.close_value) / self.close_value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BacktestReport {
+    pub mechanism: Mechanism,
+    pub days: Vec<BacktestDay>,
+    pub welfare: f64,
+    pub revenue: f64,
+    /// Days on which nothing sold.
+    pub unsold_days: usize,
+    /// Mean signed discovery error over the days something sold; positive when
+    /// auctions cleared above the close.
+    pub discovery_bias: Option<f64>,
+    /// Root mean square discovery error over the same days.
+    pub discovery_rmse: Option<f64>,
+}
+
+impl BacktestReport {
+    fn new(mechanism: Mechanism, days: Vec<BacktestDay>) -> Self {
+        let errors: Vec<f64> = days.iter().filter_map(BacktestDay::discovery_error).collect();
+        let mean = |values: &mut dyn Iterator<Item = f64>| (!errors.is_empty()).then(|| values.sum::<f64>() / errors.len() as f64);
+        BacktestReport {
+            mechanism,
+            welfare: days.iter().map(|day| day.welfare).sum(),
+            revenue: days.iter().map(|day| day.revenue).sum(),
+            unsold_days: days.iter().filter(|day| day.clearing_price.is_none()).count(),
+            discovery_bias: mean(&mut errors.iter().copied()),
+            discovery_rmse: mean(&mut errors.iter().map(|error| error * error)).map(f64::sqrt),
+            days,
+        }
+    }
+}
+
+
+/// Replays `series` day by day: each day with a bar for every constituent, a basket of
+/// them is auctioned at the opening prices to `config.bidders`, who value it at the
+/// close blurred by their private noise. The whole run is reproducible from the seed.
+pub fn run_backtest(series: &[MarketSeries], config: &BacktestConfig) -> Result<BacktestReport, ApiError> {
+    if series.is_empty() || config.bidders.is_empty() {
+        return Err(ApiError::BadRequest("a backtest needs market data and bidders".to_string()));
+    }
+    let by_day: Vec<BTreeMap<u64, &Candle>> = series.iter()
+        .map(|series| series.days.iter().map(|day| (day.timestamp, day)).collect())
+        .collect();
+    let days: Vec<u64> = by_day[0].keys().copied().filter(|day| by_day.iter().all(|bars| bars.contains_key(day))).collect();
+
+    let clock = SimClock::new(days.first().copied().unwrap_or(0));
+    let mut rng = SimRng::new(config.seed);
+    let mut exchange = Exchange::new().with_clock(Arc::new(clock.clone())).with_seed(rng.next_u64());
+    let user_ids = config.bidders.iter()
+        .map(|bidder| exchange.register_user(&bidder.name, bidder.budget).map(|user| user.id))
+        .collect::<Result<Vec<_>, _>>()?;
+    let bid_type = if config.mechanism == Mechanism::Or { BidType::OR } else { BidType::XOR };
+
+    let mut results = Vec::new();
+    let mut previous_return: Option<f64> = None;
+    for day in days {
+        clock.set(day);
+        let bars: Vec<&Candle> = by_day.iter().map(|bars| bars[&day]).collect();
+        let value = |price: fn(&Candle) -> f64| series.iter().zip(&bars).map(|(series, bar)| series.units * price(bar)).sum::<f64>();
+        let (open_value, close_value) = (value(|bar| bar.open), value(|bar| bar.close));
+        let assets = series.iter().zip(&bars).map(|(series, bar)| AssetInfo::new(series.asset.clone(), series.units, bar.open)).collect();
+        let basket = exchange.create_basket(assets)?;
+
+        // Each bidder's estimate of a whole basket, by the id of its bid
+        let mut estimates: HashMap<BidId, (f64, f64)> = HashMap::new();
+        for (bidder, user_id) in config.bidders.iter().zip(&user_ids) {
+            let signal = close_value * (1.0 + config.signal_noise * rng.next_normal());
+            let price = match bidder.strategy {
+                BidderStrategy::Truthful => signal,
+                BidderStrategy::Shade { fraction } => signal * (1.0 - fraction),
+                BidderStrategy::Momentum { weight } => open_value * (1.0 + weight * previous_return.unwrap_or(0.0)),
+            };
+            let share = bidder.share.unwrap_or(1.0);
+            if price > 0.0 {
+                let bid = exchange.submit_bid(*user_id, basket.id, bid_type.clone(), price * share, bidder.share)?;
+                estimates.insert(bid.id, (signal, share));
+            }
+        }
+
+        let outcome = exchange.run_auction(&AuctionRequest::new(basket.id, config.mechanism))?;
+        let sold: f64 = outcome.winning_bids.iter().map(|id| estimates[id].1).sum();
+        let revenue: f64 = outcome.payments.values().sum();
+        results.push(BacktestDay {
+            timestamp: day,
+            open_value,
+            close_value,
+            winners: outcome.winning_bids.len(),
+            welfare: outcome.winning_bids.iter().map(|id| estimates[id].0 * estimates[id].1).sum(),
+            revenue,
+            clearing_price: (sold > 0.0).then(|| revenue / sold),
+        });
+        previous_return = Some(close_value / open_value - 1.0);
+    }
+    Ok(BacktestReport::new(config.mechanism, results))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quanto_pricer::history::MILLIS_PER_DAY;
+
+    /// Hourly bars drifting from `start` by `step` an hour.
+    fn hourly(days: u64, start: f64, step: f64) -> Vec<Candle> {
+        (0..days * 24).map(|hour| {
+            let open = start + step * hour as f64;
+            Candle { timestamp: 20_000 * MILLIS_PER_DAY + hour * 3_600_000, open, high: open + step.abs(), low: open - step.abs(), close: open + step, volume: 1.0 }
+        }).collect()
+    }
+
+    fn series() -> Vec<MarketSeries> {
+        vec![
+            MarketSeries::new(Asset::new("BTC", "USD"), 1.0, &hourly(5, 60_000.0, 10.0)),
+            MarketSeries::new(Asset::new("ETH", "USD"), 10.0, &hourly(6, 3_000.0, -0.5)),
+        ]
+    }
+
+    fn bidders() -> Vec<SimulatedBidder> {
+        vec![
+            SimulatedBidder::new("truthful", 1_000_000.0, BidderStrategy::Truthful),
+            SimulatedBidder::new("shading", 1_000_000.0, BidderStrategy::Shade { fraction: 0.05 }),
+            SimulatedBidder::new("momentum", 1_000_000.0, BidderStrategy::Momentum { weight: 1.0 }).share(0.5),
+        ]
+    }
+
+    #[test]
+    fn test_backtest_replays_each_common_day() {
+        let config = BacktestConfig::new(Mechanism::Xor, bidders()).seed(7);
+        let report = run_backtest(&series(), &config).unwrap();
+        assert_eq!(report.days.len(), 5);
+        assert_eq!(report.days[1].timestamp - report.days[0].timestamp, MILLIS_PER_DAY);
+        assert_eq!(report.days[0].open_value, 60_000.0 + 30_000.0);
+        assert_eq!(report.days[0].close_value, 60_240.0 + 29_880.0);
+        assert!(report.days.iter().all(|day| day.winners == 1));
+        assert_eq!(report.unsold_days, 0);
+        assert!(report.discovery_rmse.unwrap() < 0.1);
+        assert!(report.revenue > 0.0 && report.welfare > 0.0);
+
+        assert_eq!(run_backtest(&series(), &config).unwrap(), report);
+        assert_ne!(run_backtest(&series(), &config.clone().seed(8)).unwrap(), report);
+    }
+
+    #[test]
+    fn test_backtest_reads_recorded_candles() {
+        let mut fixtures = Fixtures::default();
+        fixtures.candles.insert("BTC-PERPETUAL 1h".to_string(), hourly(3, 60_000.0, 10.0));
+        let btc = MarketSeries::from_fixtures(&fixtures, "BTC-PERPETUAL", Asset::new("BTC", "USD"), 0.5).unwrap();
+        assert_eq!(btc.days.len(), 3);
+        assert_eq!(btc.days[0].close, 60_000.0 + 240.0);
+        assert!(MarketSeries::from_fixtures(&fixtures, "ETH-PERPETUAL", Asset::new("ETH", "USD"), 1.0).is_err());
+
+        let report = run_backtest(&[btc], &BacktestConfig::new(Mechanism::Vcg, bidders()).signal_noise(0.0)).unwrap();
+        assert_eq!(report.mechanism, Mechanism::Vcg);
+        assert!(report.days.iter().all(|day| day.clearing_price.is_some()));
+    }
+}
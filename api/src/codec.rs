@@ -0,0 +1,571 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use auction::ledger::{JournalEntry, LedgerAccount, Posting};
+use auction::report::{AllocatedAsset, AuctionMetadata, SettlementMode, SettlementReport, UserSettlement};
+use auction::scoring::{BidScore, BidderProfile};
+use auction::simple_auction::PartialFill;
+use model::ids::{BasketId, BidId, UserId};
+use model::model::{Asset, AssetInfo, Basket, User};
+use crate::admin::AdminAction;
+use crate::event_store::{ExchangeEvent, StoredEvent};
+use crate::exchange::{AuctionOutcome, BasketControls, BidRecord, EscrowLock, ExchangeSnapshot, FilledBid, Mark, ProfileRecord, ScoredBid, SNAPSHOT_VERSION};
+use crate::nav::NavSnapshot;
+use crate::grpc::proto;
+use crate::storage::StorageError;
+
+/// Version of the protobuf storage schema this build writes. Readers take any version:
+/// fields added since their own are skipped, and only kinds of event they have never
+/// heard of are refused.
+pub const SCHEMA_VERSION: u32 = 5;
+
+/// Starts every binary snapshot, so [`crate::exchange::Exchange::restore`] can tell it
+/// from JSON.
+pub const SNAPSHOT_MAGIC: &[u8; 4] = b"CDXS";
+/// Starts every binary event log, followed by the writer's schema version as four
+/// little-endian bytes.
+pub const LOG_MAGIC: &[u8; 4] = b"CDXE";
+
+
+/// How snapshots and the event log are written. JSON is readable by hand; protobuf is
+/// several times smaller and keeps reading across schema versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    Json,
+    Protobuf,
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "json" => Ok(Encoding::Json),
+            "protobuf" | "binary" => Ok(Encoding::Protobuf),
+            other => Err(format!("unknown encoding {}", other)),
+        }
+    }
+}
+
+
+fn required<T>(value: Option<T>, what: &str) -> Result<T, StorageError> {
+    value.ok_or_else(|| StorageError::Corrupt(format!("{} is missing", what)))
+}
+
+impl From<prost::DecodeError> for StorageError {
+    fn from(error: prost::DecodeError) -> Self {
+        StorageError::Corrupt(error.to_string())
+    }
+}
+
+impl From<&AuctionMetadata> for proto::AuctionMetadata {
+    fn from(metadata: &AuctionMetadata) -> Self {
+        proto::AuctionMetadata {
+            auction_id: metadata.auction_id.into(),
+            settlement_id: metadata.settlement_id,
+            basket_id: metadata.basket_id.into(),
+            mechanism: metadata.mechanism.clone(),
+            timestamp: metadata.timestamp,
+        }
+    }
+}
+
+impl From<SettlementMode> for proto::SettlementMode {
+    fn from(mode: SettlementMode) -> Self {
+        match mode {
+            SettlementMode::Physical => proto::SettlementMode::Physical,
+            SettlementMode::Cash => proto::SettlementMode::Cash,
+        }
+    }
+}
+
+impl From<proto::SettlementMode> for SettlementMode {
+    fn from(mode: proto::SettlementMode) -> Self {
+        match mode {
+            proto::SettlementMode::Physical => SettlementMode::Physical,
+            proto::SettlementMode::Cash => SettlementMode::Cash,
+        }
+    }
+}
+
+impl From<&SettlementReport> for proto::SettlementReport {
+    fn from(report: &SettlementReport) -> Self {
+        let settlements = report.settlements.iter()
+            .map(|settlement| proto::UserSettlement {
+                user_id: settlement.user_id.into(),
+                payment: settlement.payment,
+                fee: settlement.fee,
+                assets: settlement.assets.iter()
+                    .map(|asset| proto::AllocatedAsset { base: asset.base.clone(), quote: asset.quote.clone(), quantity: asset.quantity, unit_price: asset.unit_price, value: asset.value })
+                    .collect(),
+                reference_value: settlement.reference_value,
+            })
+            .collect();
+        proto::SettlementReport {
+            metadata: Some((&report.metadata).into()),
+            fee_rate: report.fee_rate,
+            settlements,
+            mode: proto::SettlementMode::from(report.mode).into(),
+            fixing_marks: report.fixing_marks.iter().map(|(base, mark)| (base.clone(), *mark)).collect(),
+        }
+    }
+}
+
+fn report(report: proto::SettlementReport) -> Result<SettlementReport, StorageError> {
+    let metadata = required(report.metadata, "report metadata")?;
+    let mode = proto::SettlementMode::try_from(report.mode).map_err(|_| StorageError::Corrupt(format!("unknown settlement mode {}", report.mode)))?;
+    Ok(SettlementReport {
+        metadata: AuctionMetadata {
+            auction_id: metadata.auction_id.into(),
+            settlement_id: metadata.settlement_id,
+            basket_id: metadata.basket_id.into(),
+            mechanism: metadata.mechanism,
+            timestamp: metadata.timestamp,
+        },
+        fee_rate: report.fee_rate,
+        settlements: report.settlements.into_iter()
+            .map(|settlement| UserSettlement {
+                user_id: settlement.user_id.into(),
+                payment: settlement.payment,
+                fee: settlement.fee,
+                assets: settlement.assets.into_iter()
+                    .map(|asset| AllocatedAsset { base: asset.base, quote: asset.quote, quantity: asset.quantity, unit_price: asset.unit_price, value: asset.value })
+                    .collect(),
+                reference_value: settlement.reference_value,
+            })
+            .collect(),
+        mode: mode.into(),
+        fixing_marks: report.fixing_marks.into_iter().collect(),
+    })
+}
+
+fn user(user: proto::User) -> User {
+    User::new(user.id, &user.name, user.balance)
+}
+
+fn asset_info(info: proto::AssetInfo) -> Result<AssetInfo, StorageError> {
+    let asset = required(info.asset, "asset")?;
+    Ok(AssetInfo::new(Asset::new(&asset.base, &asset.quote), info.quantity, info.price))
+}
+
+fn basket(basket: proto::Basket) -> Result<Basket, StorageError> {
+    Ok(Basket { id: basket.id.into(), assets: basket.assets.into_iter().map(asset_info).collect::<Result<_, _>>()? })
+}
+
+fn bid(bid: proto::Bid) -> Result<BidRecord, StorageError> {
+    let bid_type = proto::BidType::try_from(bid.bid_type).map_err(|_| StorageError::Corrupt(format!("unknown bid type {}", bid.bid_type)))?;
+    Ok(BidRecord { id: bid.id.into(), user_id: bid.user_id.into(), basket_id: bid.basket_id.into(), bid_type: bid_type.into(), price: bid.price, quantity: bid.quantity })
+}
+
+fn payments(payments: Vec<proto::Payment>) -> HashMap<UserId, f64> {
+    payments.into_iter().map(|payment| (UserId(payment.user_id), payment.amount)).collect()
+}
+
+fn sorted_payments(payments: &HashMap<UserId, f64>) -> Vec<proto::Payment> {
+    let mut payments: Vec<proto::Payment> = payments.iter().map(|(user_id, amount)| proto::Payment { user_id: user_id.get(), amount: *amount }).collect();
+    payments.sort_by_key(|payment| payment.user_id);
+    payments
+}
+
+fn bid_ids(ids: Vec<u64>) -> Vec<BidId> {
+    ids.into_iter().map(BidId).collect()
+}
+
+fn raw_bid_ids(ids: &[BidId]) -> Vec<u64> {
+    ids.iter().map(|id| id.get()).collect()
+}
+
+fn outcome(outcome: proto::AuctionOutcome) -> Result<AuctionOutcome, StorageError> {
+    let mechanism = proto::Mechanism::try_from(outcome.mechanism).map_err(|_| StorageError::Corrupt(format!("unknown mechanism {}", outcome.mechanism)))?;
+    let allocation = outcome.allocations.into_iter()
+        .map(|allocation| Ok((UserId(allocation.user_id), allocation.assets.into_iter().map(asset_info).collect::<Result<_, StorageError>>()?)))
+        .collect::<Result<_, StorageError>>()?;
+    Ok(AuctionOutcome {
+        auction_id: outcome.auction_id.into(),
+        basket_id: outcome.basket_id.into(),
+        mechanism: mechanism.into(),
+        winning_bids: bid_ids(outcome.winning_bids),
+        allocation,
+        payments: payments(outcome.payments),
+        report: report(required(outcome.report, "settlement report")?)?,
+        fills: outcome.fills.into_iter()
+            .map(|filled| FilledBid {
+                bid_id: BidId(filled.bid_id),
+                fill: PartialFill { user_id: filled.user_id.into(), share: filled.share, payment: filled.payment, budget_bound: filled.budget_bound },
+            })
+            .collect(),
+    })
+}
+
+impl From<&LedgerAccount> for proto::LedgerAccount {
+    fn from(account: &LedgerAccount) -> Self {
+        use proto::ledger_account::Account;
+        let account = match account {
+            LedgerAccount::Cash(owner) => Account::Cash(owner.get()),
+            LedgerAccount::Inventory(owner, asset) => Account::Inventory(proto::InventoryAccount {
+                owner: owner.get(),
+                asset: Some(proto::Asset { base: asset.base.clone(), quote: asset.quote.clone() }),
+            }),
+            LedgerAccount::Currency(owner, currency) => Account::Currency(proto::CurrencyAccount { owner: owner.get(), currency: currency.clone() }),
+            LedgerAccount::External => Account::External(true),
+            LedgerAccount::ExternalCurrency(currency) => Account::ExternalCurrency(currency.clone()),
+        };
+        proto::LedgerAccount { account: Some(account) }
+    }
+}
+
+fn account(account: proto::LedgerAccount) -> Result<LedgerAccount, StorageError> {
+    use proto::ledger_account::Account;
+    Ok(match required(account.account, "ledger account")? {
+        Account::Cash(owner) => LedgerAccount::Cash(owner.into()),
+        Account::Inventory(inventory) => {
+            let asset = required(inventory.asset, "inventory asset")?;
+            LedgerAccount::Inventory(inventory.owner.into(), Asset::new(&asset.base, &asset.quote))
+        }
+        Account::Currency(currency) => LedgerAccount::Currency(currency.owner.into(), currency.currency),
+        Account::External(_) => LedgerAccount::External,
+        Account::ExternalCurrency(currency) => LedgerAccount::ExternalCurrency(currency),
+    })
+}
+
+impl From<&JournalEntry> for proto::JournalEntry {
+    fn from(entry: &JournalEntry) -> Self {
+        let postings = entry.postings.iter()
+            .map(|posting| proto::Posting { account: Some((&posting.account).into()), debit: posting.debit, credit: posting.credit })
+            .collect();
+        proto::JournalEntry { id: entry.id, memo: entry.memo.clone(), postings }
+    }
+}
+
+fn journal_entry(entry: proto::JournalEntry) -> Result<JournalEntry, StorageError> {
+    let postings = entry.postings.into_iter()
+        .map(|posting| Ok(Posting { account: account(required(posting.account, "posting account")?)?, debit: posting.debit, credit: posting.credit }))
+        .collect::<Result<_, StorageError>>()?;
+    Ok(JournalEntry { id: entry.id, memo: entry.memo, postings })
+}
+
+impl From<&BasketControls> for proto::BasketControls {
+    fn from(controls: &BasketControls) -> Self {
+        proto::BasketControls { basket_id: controls.basket_id.get(), closes_at: controls.closes_at, extensions: controls.extensions }
+    }
+}
+
+fn basket_controls(controls: proto::BasketControls) -> BasketControls {
+    BasketControls { basket_id: controls.basket_id.into(), closes_at: controls.closes_at, extensions: controls.extensions }
+}
+
+impl From<&EscrowLock> for proto::EscrowLock {
+    fn from(lock: &EscrowLock) -> Self {
+        proto::EscrowLock { user_id: lock.user_id.get(), basket_id: lock.basket_id.get(), amount: lock.amount }
+    }
+}
+
+fn escrow_lock(lock: proto::EscrowLock) -> EscrowLock {
+    EscrowLock { user_id: lock.user_id.into(), basket_id: lock.basket_id.into(), amount: lock.amount }
+}
+
+impl From<&Mark> for proto::Mark {
+    fn from(mark: &Mark) -> Self {
+        proto::Mark { base: mark.base.clone(), at: mark.at, price: mark.price }
+    }
+}
+
+fn mark(mark: proto::Mark) -> Mark {
+    Mark { base: mark.base, at: mark.at, price: mark.price }
+}
+
+impl From<&NavSnapshot> for proto::NavSnapshot {
+    fn from(snapshot: &NavSnapshot) -> Self {
+        proto::NavSnapshot { basket_id: snapshot.basket_id.get(), at: snapshot.at, nav: snapshot.nav }
+    }
+}
+
+fn nav_snapshot(snapshot: proto::NavSnapshot) -> NavSnapshot {
+    NavSnapshot { basket_id: snapshot.basket_id.into(), at: snapshot.at, nav: snapshot.nav }
+}
+
+impl From<&ProfileRecord> for proto::BidderProfile {
+    fn from(record: &ProfileRecord) -> Self {
+        proto::BidderProfile { user_id: record.user_id.get(), credit_score: record.profile.credit_score, settlement_days: record.profile.settlement_days }
+    }
+}
+
+fn profile_record(profile: proto::BidderProfile) -> ProfileRecord {
+    ProfileRecord {
+        user_id: profile.user_id.into(),
+        profile: BidderProfile { credit_score: profile.credit_score, settlement_days: profile.settlement_days },
+    }
+}
+
+
+fn admin_action(action: &AdminAction, operator: Option<UserId>) -> proto::AdminAction {
+    use proto::admin_action::Action;
+    let action = match action {
+        AdminAction::Halt { basket_id } => Action::Halt(proto::BasketAction { basket_id: basket_id.get() }),
+        AdminAction::Resume { basket_id } => Action::Resume(proto::BasketAction { basket_id: basket_id.get() }),
+        AdminAction::Extend { basket_id, closes_at } => Action::Extend(proto::ExtendBidding { basket_id: basket_id.get(), closes_at: *closes_at }),
+        AdminAction::Cancel { basket_id, cancelled_bids } => Action::Cancel(proto::CancelAuction { basket_id: basket_id.get(), cancelled_bids: raw_bid_ids(cancelled_bids) }),
+        AdminAction::ExpireStaleBids { before, expired_bids } => Action::ExpireStaleBids(proto::ExpireStaleBids { before: *before, expired_bids: raw_bid_ids(expired_bids) }),
+    };
+    proto::AdminAction { action: Some(action), operator: operator.map(UserId::get) }
+}
+
+fn admin(action: proto::AdminAction) -> Result<ExchangeEvent, StorageError> {
+    use proto::admin_action::Action;
+    let operator = action.operator;
+    let action = match required(action.action, "admin action")? {
+        Action::Halt(halt) => AdminAction::Halt { basket_id: halt.basket_id.into() },
+        Action::Resume(resume) => AdminAction::Resume { basket_id: resume.basket_id.into() },
+        Action::Extend(extend) => AdminAction::Extend { basket_id: extend.basket_id.into(), closes_at: extend.closes_at },
+        Action::Cancel(cancel) => AdminAction::Cancel { basket_id: cancel.basket_id.into(), cancelled_bids: bid_ids(cancel.cancelled_bids) },
+        Action::ExpireStaleBids(expire) => AdminAction::ExpireStaleBids { before: expire.before, expired_bids: bid_ids(expire.expired_bids) },
+    };
+    Ok(ExchangeEvent::Admin { action, operator: operator.map(UserId) })
+}
+
+impl From<&ExchangeEvent> for proto::ExchangeEvent {
+    fn from(event: &ExchangeEvent) -> Self {
+        use proto::exchange_event::Event;
+        let event = match event {
+            ExchangeEvent::UserRegistered { user } => Event::UserRegistered(user.into()),
+            ExchangeEvent::BasketCreated { basket } => Event::BasketCreated(basket.into()),
+            ExchangeEvent::BidSubmitted { bid } => Event::BidSubmitted(bid.into()),
+            ExchangeEvent::BidCancelled { bid_id } => Event::BidCancelled(bid_id.get()),
+            ExchangeEvent::BidAmended { bid } => Event::BidAmended(bid.into()),
+            ExchangeEvent::BidRejected { bid_id, user_id, basket_id, reason } => Event::BidRejected(proto::BidRejected {
+                bid_id: bid_id.get(),
+                user_id: user_id.get(),
+                basket_id: basket_id.get(),
+                reason: reason.clone(),
+            }),
+            ExchangeEvent::RoundPriced { basket_id, round, prices, excess_demand, eligibility } => Event::RoundPriced(proto::RoundPriced {
+                basket_id: basket_id.get(),
+                round: *round as u64,
+                prices: prices.clone(),
+                excess_demand: excess_demand.clone(),
+                eligibility: eligibility.iter().map(|(user_id, points)| (user_id.get(), *points)).collect(),
+            }),
+            ExchangeEvent::BidderEliminated { basket_id, round, user_id } => Event::BidderEliminated(proto::BidderEliminated {
+                basket_id: basket_id.get(),
+                round: *round as u64,
+                user_id: user_id.get(),
+            }),
+            ExchangeEvent::BidsScored { basket_id, auction_id, rule, scores } => Event::BidsScored(proto::BidsScored {
+                basket_id: basket_id.get(),
+                auction_id: auction_id.get(),
+                rule: rule.clone(),
+                scores: scores.iter()
+                    .map(|scored| proto::BidScore {
+                        bid_id: scored.bid_id.get(),
+                        user_id: scored.score.user_id.get(),
+                        price: scored.score.price,
+                        credit_score: scored.score.credit_score,
+                        settlement_days: scored.score.settlement_days,
+                        score: scored.score.score,
+                    })
+                    .collect(),
+            }),
+            ExchangeEvent::WinnersSelected { basket_id, auction_id, winning_bids, payments } => Event::WinnersSelected(proto::WinnersSelected {
+                basket_id: basket_id.get(),
+                auction_id: auction_id.get(),
+                winning_bids: raw_bid_ids(winning_bids),
+                payments: sorted_payments(payments),
+            }),
+            ExchangeEvent::Settled { outcome, users } => Event::Settled(proto::Settled {
+                outcome: Some(outcome.into()),
+                users: users.iter().map(Into::into).collect(),
+            }),
+            ExchangeEvent::Admin { action, operator } => Event::Admin(admin_action(action, *operator)),
+            ExchangeEvent::BiddingExtended { basket_id, closes_at, extension } => Event::BiddingExtended(proto::BiddingExtended {
+                basket_id: basket_id.get(),
+                closes_at: *closes_at,
+                extension: *extension,
+            }),
+        };
+        proto::ExchangeEvent { event: Some(event) }
+    }
+}
+
+fn event(event: proto::ExchangeEvent, schema_version: u32) -> Result<ExchangeEvent, StorageError> {
+    use proto::exchange_event::Event;
+    // A oneof member this build does not know decodes as no member at all
+    let Some(event) = event.event else {
+        return Err(StorageError::Corrupt(format!("event of a kind unknown to schema version {} (written by version {})", SCHEMA_VERSION, schema_version)));
+    };
+    Ok(match event {
+        Event::UserRegistered(registered) => ExchangeEvent::UserRegistered { user: user(registered) },
+        Event::BasketCreated(created) => ExchangeEvent::BasketCreated { basket: basket(created)? },
+        Event::BidSubmitted(submitted) => ExchangeEvent::BidSubmitted { bid: bid(submitted)? },
+        Event::BidCancelled(bid_id) => ExchangeEvent::BidCancelled { bid_id: BidId(bid_id) },
+        Event::BidAmended(amended) => ExchangeEvent::BidAmended { bid: bid(amended)? },
+        Event::BidRejected(rejected) => ExchangeEvent::BidRejected {
+            bid_id: rejected.bid_id.into(),
+            user_id: rejected.user_id.into(),
+            basket_id: rejected.basket_id.into(),
+            reason: rejected.reason,
+        },
+        Event::RoundPriced(priced) => ExchangeEvent::RoundPriced {
+            basket_id: priced.basket_id.into(),
+            round: priced.round as usize,
+            prices: priced.prices,
+            excess_demand: priced.excess_demand,
+            eligibility: priced.eligibility.into_iter().map(|(user_id, points)| (UserId(user_id), points)).collect(),
+        },
+        Event::BidderEliminated(eliminated) => ExchangeEvent::BidderEliminated {
+            basket_id: eliminated.basket_id.into(),
+            round: eliminated.round as usize,
+            user_id: eliminated.user_id.into(),
+        },
+        Event::BidsScored(scored) => ExchangeEvent::BidsScored {
+            basket_id: scored.basket_id.into(),
+            auction_id: scored.auction_id.into(),
+            rule: scored.rule,
+            scores: scored.scores.into_iter()
+                .map(|score| ScoredBid {
+                    bid_id: BidId(score.bid_id),
+                    score: BidScore {
+                        user_id: score.user_id.into(),
+                        price: score.price,
+                        credit_score: score.credit_score,
+                        settlement_days: score.settlement_days,
+                        score: score.score,
+                    },
+                })
+                .collect(),
+        },
+        Event::WinnersSelected(selected) => ExchangeEvent::WinnersSelected {
+            basket_id: selected.basket_id.into(),
+            auction_id: selected.auction_id.into(),
+            winning_bids: bid_ids(selected.winning_bids),
+            payments: payments(selected.payments),
+        },
+        Event::Settled(settled) => ExchangeEvent::Settled {
+            outcome: outcome(required(settled.outcome, "settled outcome")?)?,
+            users: settled.users.into_iter().map(user).collect(),
+        },
+        Event::Admin(action) => admin(action)?,
+        Event::BiddingExtended(extended) => ExchangeEvent::BiddingExtended {
+            basket_id: extended.basket_id.into(),
+            closes_at: extended.closes_at,
+            extension: extended.extension,
+        },
+    })
+}
+
+
+/// `snapshot` as [`SNAPSHOT_MAGIC`] and a protobuf `Snapshot`.
+pub fn encode_snapshot(snapshot: &ExchangeSnapshot) -> Vec<u8> {
+    let message = proto::Snapshot {
+        schema_version: SCHEMA_VERSION,
+        last_id: snapshot.last_id,
+        users: snapshot.users.iter().map(Into::into).collect(),
+        baskets: snapshot.baskets.iter().map(Into::into).collect(),
+        bids: snapshot.bids.iter().map(Into::into).collect(),
+        outcomes: snapshot.outcomes.iter().map(Into::into).collect(),
+        ledger: snapshot.ledger.iter().map(Into::into).collect(),
+        halted: snapshot.halted.iter().map(|basket_id| basket_id.get()).collect(),
+        controls: snapshot.controls.iter().map(Into::into).collect(),
+        escrow: snapshot.escrow.iter().map(Into::into).collect(),
+        marks: snapshot.marks.iter().map(Into::into).collect(),
+        navs: snapshot.navs.iter().map(Into::into).collect(),
+        last_nav_at: snapshot.last_nav_at,
+        profiles: snapshot.profiles.iter().map(Into::into).collect(),
+    };
+    let mut bytes = SNAPSHOT_MAGIC.to_vec();
+    message.encode(&mut bytes).expect("a vector grows to fit");
+    bytes
+}
+
+/// Reads [`encode_snapshot`] bytes of any schema version. The result carries the JSON
+/// layout's [`SNAPSHOT_VERSION`], as it would have if read from JSON.
+pub fn decode_snapshot(bytes: &[u8]) -> Result<ExchangeSnapshot, StorageError> {
+    let body = bytes.strip_prefix(SNAPSHOT_MAGIC).ok_or_else(|| StorageError::Corrupt("not a binary snapshot".to_string()))?;
+    let message = proto::Snapshot::decode(body)?;
+    Ok(ExchangeSnapshot {
+        version: SNAPSHOT_VERSION,
+        last_id: message.last_id,
+        users: message.users.into_iter().map(user).collect(),
+        baskets: message.baskets.into_iter().map(basket).collect::<Result<_, _>>()?,
+        bids: message.bids.into_iter().map(bid).collect::<Result<_, _>>()?,
+        outcomes: message.outcomes.into_iter().map(outcome).collect::<Result<_, _>>()?,
+        ledger: message.ledger.into_iter().map(journal_entry).collect::<Result<_, _>>()?,
+        halted: message.halted.into_iter().map(BasketId).collect(),
+        controls: message.controls.into_iter().map(basket_controls).collect(),
+        escrow: message.escrow.into_iter().map(escrow_lock).collect(),
+        marks: message.marks.into_iter().map(mark).collect(),
+        navs: message.navs.into_iter().map(nav_snapshot).collect(),
+        last_nav_at: message.last_nav_at,
+        profiles: message.profiles.into_iter().map(profile_record).collect(),
+    })
+}
+
+/// What a binary event log starts with.
+pub fn log_header() -> Vec<u8> {
+    let mut header = LOG_MAGIC.to_vec();
+    header.extend_from_slice(&SCHEMA_VERSION.to_le_bytes());
+    header
+}
+
+/// Appends `stored` to `buffer` as a length-delimited protobuf `StoredEvent`.
+pub fn encode_event(stored: &StoredEvent, buffer: &mut Vec<u8>) {
+    let message = proto::StoredEvent { sequence: stored.sequence, timestamp: stored.timestamp, event: Some((&stored.event).into()) };
+    message.encode_length_delimited(buffer).expect("a vector grows to fit");
+}
+
+/// Every event of a binary log, header included.
+pub fn decode_log(bytes: &[u8]) -> Result<Vec<StoredEvent>, StorageError> {
+    let rest = bytes.strip_prefix(LOG_MAGIC).ok_or_else(|| StorageError::Corrupt("not a binary event log".to_string()))?;
+    let (version, mut records) = rest.split_first_chunk::<4>().ok_or_else(|| StorageError::Corrupt("event log header is truncated".to_string()))?;
+    let schema_version = u32::from_le_bytes(*version);
+    let mut events = Vec::new();
+    while !records.is_empty() {
+        let message = proto::StoredEvent::decode_length_delimited(&mut records)?;
+        let stored = StoredEvent {
+            sequence: message.sequence,
+            timestamp: message.timestamp,
+            event: event(required(message.event, "event")?, schema_version)?,
+        };
+        events.push(stored);
+    }
+    Ok(events)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::ids::BasketId;
+    use model::model::BidType;
+
+    #[test]
+    fn test_newer_fields_are_skipped_and_unknown_events_refused() {
+        let bid = BidRecord { id: BidId(7), user_id: UserId(1), basket_id: BasketId(2), bid_type: BidType::OR, price: 30_000.0, quantity: Some(0.5) };
+        let stored = StoredEvent { sequence: 1, timestamp: 5, event: ExchangeEvent::BidSubmitted { bid: bid.clone() } };
+        let mut log = log_header();
+        encode_event(&stored, &mut log);
+        // A later writer added field 99 to the event record
+        let mut newer = proto::StoredEvent { sequence: 2, timestamp: 6, event: Some((&ExchangeEvent::BidCancelled { bid_id: BidId(7) }).into()) }.encode_to_vec();
+        prost::encoding::string::encode(99, &"from the future".to_string(), &mut newer);
+        prost::encoding::encode_varint(newer.len() as u64, &mut log);
+        log.extend_from_slice(&newer);
+
+        let events = decode_log(&log).unwrap();
+        assert!(matches!(&events[0].event, ExchangeEvent::BidSubmitted { bid: decoded } if *decoded == bid));
+        assert!(matches!(events[1].event, ExchangeEvent::BidCancelled { bid_id: BidId(7) }));
+
+        // An event kind added later cannot be replayed, so it is refused
+        let mut unknown = log_header();
+        let (mut record, mut kind) = (Vec::new(), Vec::new());
+        prost::encoding::uint64::encode(14, &1, &mut kind);
+        prost::encoding::uint64::encode(1, &3, &mut record);
+        prost::encoding::bytes::encode(3, &kind, &mut record);
+        prost::encoding::encode_varint(record.len() as u64, &mut unknown);
+        unknown.extend_from_slice(&record);
+        assert!(matches!(decode_log(&unknown), Err(StorageError::Corrupt(message)) if message.contains("unknown")));
+        assert!(decode_log(b"CDXE").is_err());
+        assert_eq!("Protobuf".parse(), Ok(Encoding::Protobuf));
+    }
+}
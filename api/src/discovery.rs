@@ -0,0 +1,250 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use auction::wdp::WDPSolver;
+use model::ids::{AuctionId, BasketId, BidId, UserId};
+use model::model::{Basket, Bid, User};
+use quanto_pricer::provider::MarketDataProvider;
+use crate::admin::AdminAction;
+use crate::error::ApiError;
+use crate::event_store::{ExchangeEvent, StoredEvent};
+use crate::exchange::{AuctionOutcome, BidRecord, Mechanism};
+
+
+/// How one asset's price came out of the auction against the prices around it. The
+/// deviations are relative, e.g. 0.02 for a clearing price 2% above the mark.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AssetDiscovery {
+    pub base: String,
+    pub quote: String,
+    pub quantity: f64,
+    pub listed_price: f64,
+    /// The final clock price of a CCA, or the value allocated per unit otherwise.
+    pub clearing_price: Option<f64>,
+    pub pre_auction_mark: Option<f64>,
+    pub post_auction_price: Option<f64>,
+    pub vs_mark: Option<f64>,
+    pub vs_market: Option<f64>,
+}
+
+/// Welfare of the winning bids against the best the same bids could have achieved.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Efficiency {
+    pub welfare: f64,
+    pub optimal_welfare: f64,
+    /// `welfare / optimal_welfare`, 1 when there was nothing to win.
+    pub ratio: f64,
+}
+
+/// What the auction raised, against the winning bids and the basket's value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Revenue {
+    pub payments: f64,
+    pub fees: f64,
+    /// Payments as a share of welfare; below 1 under VCG.
+    pub payment_ratio: f64,
+    pub listed_value: f64,
+    /// The basket's value at the pre-auction marks, its listed prices where unmarked.
+    pub marked_value: f64,
+    pub vs_marked_value: Option<f64>,
+}
+
+/// How well an auction discovered prices and allocated the basket, for research and
+/// for tuning the mechanisms. Built from the event log once the auction settled.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriceDiscoveryReport {
+    pub auction_id: AuctionId,
+    pub basket_id: BasketId,
+    pub mechanism: Mechanism,
+    pub bids: usize,
+    pub assets: Vec<AssetDiscovery>,
+    pub efficiency: Efficiency,
+    pub revenue: Revenue,
+}
+
+fn deviation(price: Option<f64>, reference: Option<f64>) -> Option<f64> {
+    let (price, reference) = (price?, reference?);
+    (reference > 0.0 && price.is_finite()).then(|| price / reference - 1.0)
+}
+
+impl PriceDiscoveryReport {
+    /// The report on auction `auction_id` in `events`. `marks` are the prices by base the
+    /// exchange marked before the auction and `market` those on external venues after it,
+    /// see [`market_prices`]; assets missing from either are reported without.
+    pub fn from_events(events: &[StoredEvent], auction_id: AuctionId, marks: &HashMap<String, f64>, market: &HashMap<String, f64>) -> Result<Self, ApiError> {
+        let mut users: HashMap<UserId, User> = HashMap::new();
+        let mut baskets: HashMap<BasketId, Basket> = HashMap::new();
+        let mut resting: HashMap<BasketId, BTreeMap<BidId, BidRecord>> = HashMap::new();
+        let mut clock_prices: HashMap<BasketId, HashMap<String, f64>> = HashMap::new();
+        let mut settled = None;
+        for stored in events {
+            match &stored.event {
+                ExchangeEvent::UserRegistered { user } => {
+                    users.insert(user.id, user.clone());
+                }
+                ExchangeEvent::BasketCreated { basket } => {
+                    baskets.insert(basket.id, basket.clone());
+                }
+                ExchangeEvent::BidSubmitted { bid } | ExchangeEvent::BidAmended { bid } => {
+                    resting.entry(bid.basket_id).or_default().insert(bid.id, bid.clone());
+                }
+                ExchangeEvent::BidCancelled { bid_id } => resting.values_mut().for_each(|bids| { bids.remove(bid_id); }),
+                ExchangeEvent::Admin { action: AdminAction::Cancel { cancelled_bids: bid_ids, .. } | AdminAction::ExpireStaleBids { expired_bids: bid_ids, .. }, .. } => {
+                    for bid_id in bid_ids {
+                        resting.values_mut().for_each(|bids| { bids.remove(bid_id); });
+                    }
+                }
+                ExchangeEvent::RoundPriced { basket_id, prices, .. } => {
+                    clock_prices.insert(*basket_id, prices.clone());
+                }
+                ExchangeEvent::Settled { outcome, users: settled_users } => {
+                    if outcome.auction_id == auction_id {
+                        settled = Some(outcome.clone());
+                        break;
+                    }
+                    users.extend(settled_users.iter().map(|user| (user.id, user.clone())));
+                    resting.remove(&outcome.basket_id);
+                }
+                _ => {}
+            }
+        }
+        let outcome = settled.ok_or(ApiError::NotFound("settled auction"))?;
+        let basket = baskets.remove(&outcome.basket_id).ok_or(ApiError::NotFound("basket"))?;
+        let records: Vec<BidRecord> = resting.remove(&outcome.basket_id).unwrap_or_default().into_values().collect();
+        let clock = (outcome.mechanism == Mechanism::Cca).then(|| clock_prices.remove(&outcome.basket_id)).flatten();
+
+        let assets = basket.assets.iter()
+            .map(|info| {
+                let base = &info.asset.base;
+                let clearing_price = match &clock {
+                    Some(prices) => prices.get(base).copied(),
+                    None => allocated_unit_price(&outcome, base),
+                };
+                let (pre_auction_mark, post_auction_price) = (marks.get(base).copied(), market.get(base).copied());
+                AssetDiscovery {
+                    base: base.clone(),
+                    quote: info.asset.quote.clone(),
+                    quantity: info.quantity,
+                    listed_price: info.price,
+                    clearing_price,
+                    pre_auction_mark,
+                    post_auction_price,
+                    vs_mark: deviation(clearing_price, pre_auction_mark),
+                    vs_market: deviation(clearing_price, post_auction_price),
+                }
+            })
+            .collect();
+
+        let welfare: f64 = records.iter().filter(|bid| outcome.winning_bids.contains(&bid.id)).map(|bid| bid.price).sum();
+        let bids: Vec<Bid> = records.iter()
+            .filter_map(|bid| {
+                let user = Arc::new(users.get(&bid.user_id)?.clone());
+                Some(Bid::new(user, bid.basket_id, bid.bid_type.clone(), bid.price, bid.quantity))
+            })
+            .collect();
+        let (_, optimal_welfare) = WDPSolver::branch_and_bound(&bids, &basket);
+        // The solver only sees bids that were fundable as of the log; never report more
+        // than the auction actually achieved as out of reach
+        let optimal_welfare = optimal_welfare.max(welfare);
+        let ratio = if optimal_welfare > 0.0 { welfare / optimal_welfare } else { 1.0 };
+
+        let payments: f64 = outcome.payments.values().sum();
+        let fees = outcome.report.settlements.iter().map(|settlement| settlement.fee).sum();
+        let listed_value = basket.assets.iter().map(|info| info.quantity * info.price).sum();
+        let marked_value: f64 = basket.assets.iter().map(|info| info.quantity * marks.get(&info.asset.base).copied().unwrap_or(info.price)).sum();
+        Ok(PriceDiscoveryReport {
+            auction_id,
+            basket_id: outcome.basket_id,
+            mechanism: outcome.mechanism,
+            bids: records.len(),
+            assets,
+            efficiency: Efficiency { welfare, optimal_welfare, ratio },
+            revenue: Revenue {
+                payments,
+                fees,
+                payment_ratio: if welfare > 0.0 { payments / welfare } else { 0.0 },
+                listed_value,
+                marked_value,
+                vs_marked_value: deviation(Some(payments), Some(marked_value)),
+            },
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("discovery reports serialize to JSON")
+    }
+}
+
+/// Value allocated per unit of `base` across the winners.
+fn allocated_unit_price(outcome: &AuctionOutcome, base: &str) -> Option<f64> {
+    let (value, quantity) = outcome.report.settlements.iter()
+        .flat_map(|settlement| &settlement.assets)
+        .filter(|asset| asset.base == base)
+        .fold((0.0, 0.0), |(value, quantity), asset| (value + asset.value, quantity + asset.quantity));
+    (quantity > 0.0).then(|| value / quantity)
+}
+
+/// Spot prices of the basket's assets on `provider`, by base, to compare an auction
+/// with the market after it. Assets the venue cannot price are left out.
+pub async fn market_prices(provider: &dyn MarketDataProvider, basket: &Basket) -> HashMap<String, f64> {
+    let mut prices = HashMap::new();
+    for info in &basket.assets {
+        match provider.spot_price(&info.asset).await {
+            Ok(price) => {
+                prices.insert(info.asset.base.clone(), price);
+            }
+            Err(error) => tracing::warn!(venue = provider.venue(), base = %info.asset.base, %error, "no market price for the discovery report"),
+        }
+    }
+    prices
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use quanto_pricer::replay::{Fixtures, MockProvider};
+    use model::model::{Asset, AssetInfo, BidType};
+    use crate::event_store::{EventStore, MemoryEventStore};
+    use crate::exchange::{AuctionRequest, Exchange};
+
+    #[tokio::test]
+    async fn test_report_compares_clearing_prices_with_marks_and_the_market() {
+        let journal = Arc::new(MemoryEventStore::new());
+        let mut exchange = Exchange::new().with_journal(journal.clone());
+        let alice = exchange.register_user("Alice", 1_000_000.0).unwrap().id;
+        let bob = exchange.register_user("Bob", 1_000_000.0).unwrap().id;
+        let basket = exchange.create_basket(vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60_000.0)]).unwrap();
+        let alice_bid = exchange.submit_bid(alice, basket.id, BidType::XOR, 63_000.0, None).unwrap();
+        let bob_bid = exchange.submit_bid(bob, basket.id, BidType::XOR, 61_000.0, None).unwrap();
+        let cancelled = exchange.submit_bid(bob, basket.id, BidType::XOR, 70_000.0, None).unwrap();
+        exchange.cancel_bid(cancelled.id).unwrap();
+        let outcome = exchange.run_auction(&AuctionRequest::new(basket.id, Mechanism::Vcg)).unwrap();
+
+        let provider = MockProvider::new(Fixtures { venue: "deribit".to_string(), spot_prices: BTreeMap::from([("BTC/USD".to_string(), 62_000.0)]), ..Fixtures::default() });
+        let market = market_prices(&provider, &basket).await;
+        let marks = HashMap::from([("BTC".to_string(), 60_500.0)]);
+        let report = PriceDiscoveryReport::from_events(&journal.read_from(1).unwrap(), outcome.auction_id, &marks, &market).unwrap();
+
+        assert_eq!(report.bids, 2);
+        let welfare = [(alice_bid, 63_000.0), (bob_bid, 61_000.0)].iter()
+            .filter(|(bid, _)| outcome.winning_bids.contains(&bid.id))
+            .map(|(_, price)| price)
+            .sum();
+        assert_eq!(report.efficiency, Efficiency { welfare, optimal_welfare: welfare, ratio: 1.0 });
+        let payments: f64 = outcome.payments.values().sum();
+        assert_eq!(report.revenue.payments, payments);
+        assert_eq!(report.revenue.payment_ratio, payments / welfare);
+        assert_eq!(report.revenue.marked_value, 60_500.0);
+        let btc = &report.assets[0];
+        assert_eq!((btc.pre_auction_mark, btc.post_auction_price), (Some(60_500.0), Some(62_000.0)));
+        let clearing = btc.clearing_price.unwrap();
+        assert_eq!(btc.vs_market, Some(clearing / 62_000.0 - 1.0));
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["mechanism"], "vcg");
+        assert_eq!(json["efficiency"]["ratio"], 1.0);
+        assert_eq!(PriceDiscoveryReport::from_events(&[], outcome.auction_id, &marks, &market), Err(ApiError::NotFound("settled auction")));
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use model::ids::BasketId;
+use tracing::Instrument;
+use crate::error::ApiError;
+use crate::exchange::{AuctionOutcome, AuctionRequest};
+use crate::routes::SharedExchange;
+
+
+/// Auctions in flight and the signal that one has finished.
+#[derive(Debug, Default)]
+struct Running {
+    baskets: Mutex<HashSet<BasketId>>,
+    finished: Notify,
+}
+
+/// Takes a basket off the running set however its task ends, panics included.
+struct RunningGuard {
+    running: Arc<Running>,
+    basket_id: BasketId,
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        self.running.baskets.lock().unwrap().remove(&self.basket_id);
+        self.running.finished.notify_waiters();
+    }
+}
+
+/// An auction running on the engine.
+#[derive(Debug)]
+pub struct AuctionHandle {
+    pub basket_id: BasketId,
+    task: JoinHandle<Result<AuctionOutcome, ApiError>>,
+}
+
+impl AuctionHandle {
+    /// Waits for the auction to settle, or to fail.
+    pub async fn outcome(self) -> Result<AuctionOutcome, ApiError> {
+        match self.task.await {
+            Ok(outcome) => outcome,
+            Err(error) => Err(ApiError::Internal(format!("auction of basket {} failed: {}", self.basket_id, error))),
+        }
+    }
+}
+
+
+/// Runs auctions of many baskets at once over one shared exchange, whose users, escrow
+/// and clearing ledger they all settle against. A user's bids across baskets lock escrow
+/// in the one exchange, so concurrent auctions can never spend the same funds twice and
+/// each winner pays from its own lock. Each auction is its own task: bids are taken
+/// under the exchange's lock, winners are determined on the blocking pool without it,
+/// and the outcome is settled under the lock again. A panicking auction fails alone.
+/// Must be used inside a tokio runtime.
+#[derive(Debug, Clone)]
+pub struct ExchangeEngine {
+    exchange: SharedExchange,
+    running: Arc<Running>,
+    closed: Arc<AtomicBool>,
+}
+
+impl ExchangeEngine {
+    pub fn new(exchange: SharedExchange) -> Self {
+        ExchangeEngine { exchange, running: Arc::new(Running::default()), closed: Arc::new(AtomicBool::new(false)) }
+    }
+
+    pub fn exchange(&self) -> &SharedExchange {
+        &self.exchange
+    }
+
+    /// Baskets being auctioned.
+    pub fn running(&self) -> Vec<BasketId> {
+        let mut baskets: Vec<BasketId> = self.running.baskets.lock().unwrap().iter().copied().collect();
+        baskets.sort_unstable();
+        baskets
+    }
+
+    /// Starts auctioning a basket. Refused while the basket is already being auctioned
+    /// or once the engine is shutting down.
+    pub fn start(&self, request: AuctionRequest) -> Result<AuctionHandle, ApiError> {
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(ApiError::Conflict("engine is shutting down"));
+        }
+        if !self.running.baskets.lock().unwrap().insert(request.basket_id) {
+            return Err(ApiError::Conflict("basket is already being auctioned"));
+        }
+        let guard = RunningGuard { running: self.running.clone(), basket_id: request.basket_id };
+        let pending = self.exchange.lock().unwrap().open_auction(&request)?;
+
+        let exchange = self.exchange.clone();
+        let span = tracing::info_span!("auction", basket_id = request.basket_id.get(), mechanism = request.mechanism.name());
+        let task = tokio::spawn(async move {
+            let _guard = guard;
+            let solved = tokio::task::spawn_blocking(move || pending.solve()).await
+                .map_err(|error| ApiError::Internal(format!("winner determination failed: {}", error)))?;
+            let mut exchange = exchange.lock().map_err(|_| ApiError::Internal("exchange lock poisoned".to_string()))?;
+            exchange.settle_auction(solved)
+        }.instrument(span));
+        Ok(AuctionHandle { basket_id: request.basket_id, task })
+    }
+
+    /// Refuses new auctions and waits for the running ones to settle.
+    pub async fn shutdown(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        loop {
+            // Registered before checking, so a finish in between is not missed
+            let finished = self.running.finished.notified();
+            if self.running.baskets.lock().unwrap().is_empty() {
+                return;
+            }
+            finished.await;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use auction::error::ClearingError;
+    use model::ids::UserId;
+    use model::model::{Asset, AssetInfo, BidType};
+    use crate::exchange::{Exchange, Mechanism};
+
+    fn basket(exchange: &mut Exchange, base: &str, price: f64) -> BasketId {
+        exchange.create_basket(vec![AssetInfo::new(Asset::new(base, "USD"), 1.0, price)]).unwrap().id
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_auctions_share_balances() {
+        let mut exchange = Exchange::new();
+        let alice = exchange.register_user("Alice", 100_000.0).unwrap().id;
+        let bob = exchange.register_user("Bob", 100_000.0).unwrap().id;
+        let btc = basket(&mut exchange, "BTC", 60_000.0);
+        let eth = basket(&mut exchange, "ETH", 3_000.0);
+        let sol = basket(&mut exchange, "SOL", 150.0);
+        // Alice's BTC bid locks what her ETH bid would need, so the auctions cannot both spend it
+        exchange.submit_bid(alice, btc, BidType::XOR, 90_000.0, None).unwrap();
+        let over_committed = exchange.submit_bid(alice, eth, BidType::XOR, 20_000.0, None);
+        assert!(matches!(over_committed, Err(ApiError::Rejected(ClearingError::InsufficientFunds { .. }))));
+        exchange.submit_bid(bob, eth, BidType::XOR, 3_100.0, None).unwrap();
+        exchange.submit_bid(bob, sol, BidType::XOR, 200.0, None).unwrap();
+        let engine = ExchangeEngine::new(Arc::new(Mutex::new(exchange)));
+
+        let handles: Vec<AuctionHandle> = [btc, eth, sol].iter()
+            .map(|basket| engine.start(AuctionRequest::new(*basket, Mechanism::Xor)).unwrap())
+            .collect();
+        // Nothing has settled yet: the tasks only run once this one awaits
+        assert_eq!(engine.running(), vec![btc, eth, sol]);
+        assert!(matches!(engine.start(AuctionRequest::new(sol, Mechanism::Xor)), Err(ApiError::Conflict("basket is already being auctioned"))));
+        // Bids in flight keep their escrow until their auction settles
+        assert_eq!(engine.exchange().lock().unwrap().available_balance(alice).unwrap(), 10_000.0);
+        let mut outcomes = Vec::new();
+        for handle in handles {
+            outcomes.push(handle.outcome().await);
+        }
+
+        let payments: Vec<HashMap<UserId, f64>> = outcomes.into_iter().map(|outcome| outcome.unwrap().payments).collect();
+        assert_eq!(payments, [HashMap::from([(alice, 90_000.0)]), HashMap::from([(bob, 3_100.0)]), HashMap::from([(bob, 200.0)])]);
+        let exchange = engine.exchange().lock().unwrap();
+        assert_eq!(exchange.user(alice).unwrap().balance, 10_000.0);
+        assert_eq!(exchange.available_balance(alice).unwrap(), 10_000.0);
+        assert_eq!(exchange.user(bob).unwrap().balance, 100_000.0 - 3_100.0 - 200.0);
+        assert!(engine.running().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_waits_and_refuses_new_auctions() {
+        let mut exchange = Exchange::new();
+        let alice = exchange.register_user("Alice", 100_000.0).unwrap().id;
+        let btc = basket(&mut exchange, "BTC", 60_000.0);
+        let eth = basket(&mut exchange, "ETH", 3_000.0);
+        exchange.submit_bid(alice, btc, BidType::XOR, 61_000.0, None).unwrap();
+        let engine = ExchangeEngine::new(Arc::new(Mutex::new(exchange)));
+
+        let handle = engine.start(AuctionRequest::new(btc, Mechanism::Xor)).unwrap();
+        engine.shutdown().await;
+        assert!(engine.running().is_empty());
+        assert!(matches!(engine.start(AuctionRequest::new(eth, Mechanism::Xor)), Err(ApiError::Conflict("engine is shutting down"))));
+        assert_eq!(handle.outcome().await.unwrap().payments[&alice], 61_000.0);
+    }
+}
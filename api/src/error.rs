@@ -0,0 +1,92 @@
+use std::fmt;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::json;
+use auction::error::{AuctionError, ClearingError};
+use crate::storage::StorageError;
+
+
+/// Why a request failed; each maps to one HTTP status with a JSON `{"error": ...}` body.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiError {
+    NotFound(&'static str),
+    BadRequest(String),
+    /// The request is well formed but conflicts with the exchange's state, e.g. a second
+    /// auction of the same basket.
+    Conflict(&'static str),
+    /// Clearing refused the outcome, e.g. a winner could not pay.
+    Rejected(ClearingError),
+    /// The change could not be persisted and was not applied.
+    Storage(String),
+    /// The exchange failed while serving the request, e.g. an auction task panicked.
+    Internal(String),
+    /// No credentials, or ones that do not check out.
+    Unauthorized(&'static str),
+    /// The caller is known but may not do this, e.g. cancel someone else's bid.
+    Forbidden(&'static str),
+    /// A rate limit was hit; the caller may retry later.
+    TooManyRequests(&'static str),
+    /// A pre-trade risk check refused the bid, e.g. it would breach a position limit.
+    RiskLimit(String),
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Rejected(_) | ApiError::RiskLimit(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Storage(_) | ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::NotFound(what) => write!(f, "{} not found", what),
+            ApiError::BadRequest(message) | ApiError::Storage(message) | ApiError::Internal(message) | ApiError::RiskLimit(message) => write!(f, "{}", message),
+            ApiError::Conflict(message) => write!(f, "{}", message),
+            ApiError::Rejected(error) => write!(f, "{}", error),
+            ApiError::Unauthorized(message) | ApiError::Forbidden(message) => write!(f, "{}", message),
+            ApiError::TooManyRequests(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<StorageError> for ApiError {
+    fn from(error: StorageError) -> Self {
+        match error {
+            StorageError::Conflict(_) => ApiError::Conflict("stored state was changed by another writer"),
+            other => ApiError::Storage(other.to_string()),
+        }
+    }
+}
+
+impl From<ClearingError> for ApiError {
+    fn from(error: ClearingError) -> Self {
+        ApiError::Rejected(error)
+    }
+}
+
+impl From<AuctionError> for ApiError {
+    fn from(error: AuctionError) -> Self {
+        match error {
+            AuctionError::Clearing(error) => ApiError::Rejected(error),
+            other => ApiError::BadRequest(other.to_string()),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status(), Json(json!({ "error": self.to_string() }))).into_response()
+    }
+}
@@ -0,0 +1,362 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use serde::{Deserialize, Serialize};
+use auction::observer::{AuctionEvent, AuctionObserver};
+use auction::sim::{Clock, SystemClock};
+use model::ids::{AuctionId, BasketId, BidId, UserId};
+use model::model::{Basket, User};
+use crate::admin::AdminAction;
+use crate::codec::{self, Encoding};
+use crate::exchange::{AuctionOutcome, BidRecord, Exchange, ScoredBid};
+use crate::storage::StorageError;
+
+
+/// A change to the exchange. Replaying them in order rebuilds its state exactly; the round
+/// events are kept for audit and do not change state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum ExchangeEvent {
+    UserRegistered { user: User },
+    BasketCreated { basket: Basket },
+    BidSubmitted { bid: BidRecord },
+    BidCancelled { bid_id: BidId },
+    /// A bid with a new price or quantity, under the same id.
+    BidAmended { bid: BidRecord },
+    /// A bid refused at submission. It takes an id, so it is journaled to keep ids the
+    /// same on replay.
+    BidRejected { bid_id: BidId, user_id: UserId, basket_id: BasketId, reason: String },
+    RoundPriced {
+        basket_id: BasketId,
+        round: usize,
+        prices: HashMap<String, f64>,
+        excess_demand: HashMap<String, f64>,
+        #[serde(default)]
+        eligibility: HashMap<UserId, f64>,
+    },
+    BidderEliminated { basket_id: BasketId, round: usize, user_id: UserId },
+    /// Every bid's score under the scoring rule that picked the winner, with its inputs.
+    BidsScored { basket_id: BasketId, auction_id: AuctionId, rule: String, scores: Vec<ScoredBid> },
+    WinnersSelected {
+        basket_id: BasketId,
+        auction_id: AuctionId,
+        winning_bids: Vec<BidId>,
+        payments: HashMap<UserId, f64>,
+    },
+    /// The cleared auction with its winners' balances afterwards.
+    Settled { outcome: AuctionOutcome, users: Vec<User> },
+    /// An operator stepped in; `operator` is their user id, if they have one.
+    Admin { action: AdminAction, operator: Option<UserId> },
+    /// A new leading bid in the soft-close window moved the close of bidding to
+    /// `closes_at`, the basket's `extension`th such move.
+    BiddingExtended { basket_id: BasketId, closes_at: u64, extension: u32 },
+}
+
+impl ExchangeEvent {
+    /// The basket the event concerns, if any. Bid cancellations only name the bid, and
+    /// expiring stale bids concerns no one basket.
+    pub fn basket_id(&self) -> Option<BasketId> {
+        match self {
+            ExchangeEvent::BasketCreated { basket } => Some(basket.id),
+            ExchangeEvent::BidSubmitted { bid } | ExchangeEvent::BidAmended { bid } => Some(bid.basket_id),
+            ExchangeEvent::BidRejected { basket_id, .. } => Some(*basket_id),
+            ExchangeEvent::RoundPriced { basket_id, .. }
+            | ExchangeEvent::BidderEliminated { basket_id, .. }
+            | ExchangeEvent::BidsScored { basket_id, .. }
+            | ExchangeEvent::WinnersSelected { basket_id, .. }
+            | ExchangeEvent::BiddingExtended { basket_id, .. } => Some(*basket_id),
+            ExchangeEvent::Settled { outcome, .. } => Some(outcome.basket_id),
+            ExchangeEvent::Admin { action, .. } => action.basket_id(),
+            ExchangeEvent::UserRegistered { .. } | ExchangeEvent::BidCancelled { .. } => None,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredEvent {
+    /// Position in the log, from 1.
+    pub sequence: u64,
+    /// When it was appended, in Unix milliseconds.
+    pub timestamp: u64,
+    pub event: ExchangeEvent,
+}
+
+
+/// An append-only log of exchange events. Nothing once appended is changed or removed.
+pub trait EventStore: Send + Sync {
+    /// Appends `events` as one batch, all or none, and returns the sequence of the last.
+    fn append(&self, events: &[ExchangeEvent]) -> Result<u64, StorageError>;
+
+    /// Events from `sequence` on, in order.
+    fn read_from(&self, sequence: u64) -> Result<Vec<StoredEvent>, StorageError>;
+}
+
+fn stamp(events: &[ExchangeEvent], last_sequence: u64, clock: &dyn Clock) -> Vec<StoredEvent> {
+    let timestamp = clock.now_millis();
+    events.iter().zip(last_sequence + 1..)
+        .map(|(event, sequence)| StoredEvent { sequence, timestamp, event: event.clone() })
+        .collect()
+}
+
+
+#[derive(Debug)]
+pub struct MemoryEventStore {
+    events: Mutex<Vec<StoredEvent>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for MemoryEventStore {
+    fn default() -> Self {
+        MemoryEventStore::new()
+    }
+}
+
+impl MemoryEventStore {
+    pub fn new() -> Self {
+        MemoryEventStore::with_clock(Arc::new(SystemClock))
+    }
+
+    /// A store stamping events with the time of `clock`, e.g. a simulation's.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        MemoryEventStore { events: Mutex::new(Vec::new()), clock }
+    }
+}
+
+impl EventStore for MemoryEventStore {
+    fn append(&self, events: &[ExchangeEvent]) -> Result<u64, StorageError> {
+        let mut stored = self.events.lock().unwrap();
+        let batch = stamp(events, stored.len() as u64, self.clock.as_ref());
+        stored.extend(batch);
+        Ok(stored.len() as u64)
+    }
+
+    fn read_from(&self, sequence: u64) -> Result<Vec<StoredEvent>, StorageError> {
+        let stored = self.events.lock().unwrap();
+        Ok(stored.iter().skip(sequence.saturating_sub(1) as usize).cloned().collect())
+    }
+}
+
+
+/// Events in one file, synced to disk on every append: JSON lines, or a binary log of
+/// protobuf records after a [`codec::LOG_MAGIC`] header.
+#[derive(Debug)]
+pub struct FileEventStore {
+    path: PathBuf,
+    encoding: Encoding,
+    /// The open log and the sequence of its last event.
+    file: Mutex<(File, u64)>,
+}
+
+impl FileEventStore {
+    /// Opens the JSON log at `path`, creating it if needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        FileEventStore::open_as(path, Encoding::Json)
+    }
+
+    /// Opens the log at `path`, creating it in `encoding` if needed. A log that already
+    /// has events keeps the encoding it was started in.
+    pub fn open_as(path: impl AsRef<Path>, encoding: Encoding) -> Result<Self, StorageError> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new().create(true).append(true).read(true).open(&path).map_err(|e| StorageError::Backend(e.to_string()))?;
+        let encoding = match log_encoding(&path)? {
+            Some(existing) => existing,
+            None => {
+                if encoding == Encoding::Protobuf {
+                    file.write_all(&codec::log_header()).and_then(|_| file.sync_data()).map_err(|e| StorageError::Backend(e.to_string()))?;
+                }
+                encoding
+            }
+        };
+        let last_sequence = read_log(&path)?.last().map_or(0, |event| event.sequence);
+        Ok(FileEventStore { path, encoding, file: Mutex::new((file, last_sequence)) })
+    }
+}
+
+/// The encoding of the log at `path`, or `None` while it is empty.
+fn log_encoding(path: &Path) -> Result<Option<Encoding>, StorageError> {
+    let mut start = Vec::new();
+    File::open(path).and_then(|file| file.take(codec::LOG_MAGIC.len() as u64).read_to_end(&mut start)).map_err(|e| StorageError::Backend(e.to_string()))?;
+    Ok(match start.as_slice() {
+        [] => None,
+        start if start == codec::LOG_MAGIC => Some(Encoding::Protobuf),
+        _ => Some(Encoding::Json),
+    })
+}
+
+fn read_log(path: &Path) -> Result<Vec<StoredEvent>, StorageError> {
+    let events = match log_encoding(path)? {
+        Some(Encoding::Protobuf) => codec::decode_log(&fs::read(path).map_err(|e| StorageError::Backend(e.to_string()))?)?,
+        _ => {
+            let file = File::open(path).map_err(|e| StorageError::Backend(e.to_string()))?;
+            let mut events = Vec::new();
+            for line in BufReader::new(file).lines() {
+                let line = line.map_err(|e| StorageError::Backend(e.to_string()))?;
+                if !line.trim().is_empty() {
+                    events.push(serde_json::from_str::<StoredEvent>(&line)?);
+                }
+            }
+            events
+        }
+    };
+    for (expected, event) in (1..).zip(&events) {
+        if event.sequence != expected {
+            return Err(StorageError::Corrupt(format!("event {} out of sequence", event.sequence)));
+        }
+    }
+    Ok(events)
+}
+
+impl EventStore for FileEventStore {
+    fn append(&self, events: &[ExchangeEvent]) -> Result<u64, StorageError> {
+        let mut guard = self.file.lock().unwrap();
+        let (file, last_sequence) = &mut *guard;
+        let mut batch = Vec::new();
+        for event in stamp(events, *last_sequence, &SystemClock) {
+            match self.encoding {
+                Encoding::Json => {
+                    serde_json::to_writer(&mut batch, &event)?;
+                    batch.push(b'\n');
+                }
+                Encoding::Protobuf => codec::encode_event(&event, &mut batch),
+            }
+        }
+        // One write, so a batch is never interleaved with another
+        file.write_all(&batch).and_then(|_| file.sync_data()).map_err(|e| StorageError::Backend(e.to_string()))?;
+        *last_sequence += events.len() as u64;
+        Ok(*last_sequence)
+    }
+
+    fn read_from(&self, sequence: u64) -> Result<Vec<StoredEvent>, StorageError> {
+        let _guard = self.file.lock().unwrap();
+        Ok(read_log(&self.path)?.into_iter().filter(|event| event.sequence >= sequence).collect())
+    }
+}
+
+
+/// Rebuilds the exchange as it stood after event `until`, or after the last one. Auctions
+/// are not re-run: settlements apply as recorded, so replay is deterministic.
+pub fn replay(events: &[StoredEvent], until: Option<u64>) -> Exchange {
+    let mut exchange = Exchange::new();
+    for stored in events.iter().take_while(|stored| until.is_none_or(|until| stored.sequence <= until)) {
+        exchange.apply(&stored.event);
+    }
+    exchange
+}
+
+/// Everything that happened to one basket, from creation to settlement.
+pub fn auction_trail(events: &[StoredEvent], basket_id: BasketId) -> Vec<&StoredEvent> {
+    events.iter().filter(|stored| stored.event.basket_id() == Some(basket_id)).collect()
+}
+
+
+/// Collects the clock events of an auction in progress, to journal once it settles.
+#[derive(Debug, Default)]
+pub(crate) struct AuctionRecorder {
+    events: Mutex<Vec<ExchangeEvent>>,
+}
+
+impl AuctionRecorder {
+    pub(crate) fn take(&self) -> Vec<ExchangeEvent> {
+        std::mem::take(&mut self.events.lock().unwrap())
+    }
+}
+
+impl AuctionObserver for AuctionRecorder {
+    fn on_event(&self, event: &AuctionEvent) {
+        let recorded = match event {
+            AuctionEvent::Round { basket_id, round, prices, excess_demand, eligibility, .. } => ExchangeEvent::RoundPriced {
+                basket_id: *basket_id,
+                round: *round,
+                prices: prices.clone(),
+                excess_demand: excess_demand.clone(),
+                eligibility: eligibility.clone(),
+            },
+            AuctionEvent::BidderEliminated { basket_id, round, user_id } => ExchangeEvent::BidderEliminated { basket_id: *basket_id, round: *round, user_id: *user_id },
+            _ => return,
+        };
+        self.events.lock().unwrap().push(recorded);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use model::model::{Asset, AssetInfo, BidType};
+    use crate::exchange::{AuctionRequest, Mechanism};
+
+    fn trade(journal: Arc<dyn EventStore>) -> (Exchange, UserId, BasketId) {
+        let mut exchange = Exchange::new().with_journal(journal);
+        let alice = exchange.register_user("Alice", 100_000.0).unwrap().id;
+        let bob = exchange.register_user("Bob", 100_000.0).unwrap().id;
+        let carol = exchange.register_user("Carol", 50_000.0).unwrap().id;
+        let basket = exchange.create_basket(vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 30_000.0)]).unwrap().id;
+        exchange.submit_bid(alice, basket, BidType::XOR, 60_000.0, Some(1.0)).unwrap();
+        exchange.submit_bid(bob, basket, BidType::XOR, 40_000.0, Some(1.0)).unwrap();
+        exchange.submit_bid(carol, basket, BidType::XOR, 50_000.0, Some(1.0)).unwrap();
+        let cancelled = exchange.submit_bid(bob, basket, BidType::XOR, 1_000.0, None).unwrap();
+        exchange.cancel_bid(cancelled.id).unwrap();
+        exchange.run_auction(&AuctionRequest { basket_id: basket, mechanism: Mechanism::Cca, price_increment: Some(0.1), max_rounds: Some(3), settlement: None }).unwrap();
+        (exchange, alice, basket)
+    }
+
+    #[test]
+    fn test_replay_rebuilds_every_point_in_time() {
+        let journal = Arc::new(MemoryEventStore::new());
+        let (live, alice, basket) = trade(journal.clone());
+        let events = journal.read_from(1).unwrap();
+
+        let replayed = replay(&events, None);
+        assert_eq!(replayed.user(alice).unwrap().balance, live.user(alice).unwrap().balance);
+        assert_eq!(replayed.bids_for(basket), live.bids_for(basket));
+        let auction_id = events.iter().find_map(|stored| match &stored.event {
+            ExchangeEvent::Settled { outcome, .. } => Some(outcome.auction_id),
+            _ => None,
+        }).unwrap();
+        assert_eq!(replayed.outcome(auction_id).unwrap().winning_bids, live.outcome(auction_id).unwrap().winning_bids);
+
+        // The rounds keep each bidder's eligibility for audit
+        assert!(events.iter().any(|stored| matches!(&stored.event, ExchangeEvent::RoundPriced { eligibility, .. } if eligibility.get(&alice) == Some(&100.0))));
+
+        // Just before the auction the bids were still resting and nothing was paid
+        let before = events.iter().find(|stored| matches!(stored.event, ExchangeEvent::RoundPriced { .. })).unwrap().sequence - 1;
+        let earlier = replay(&events, Some(before));
+        assert_eq!(earlier.bids_for(basket).len(), 3);
+        assert_eq!(earlier.user(alice).unwrap().balance, 100_000.0);
+
+        let trail: Vec<&str> = auction_trail(&events, basket).iter()
+            .map(|stored| match stored.event {
+                ExchangeEvent::BasketCreated { .. } => "created",
+                ExchangeEvent::BidSubmitted { .. } => "bid",
+                ExchangeEvent::RoundPriced { .. } => "round",
+                ExchangeEvent::BidderEliminated { .. } => "eliminated",
+                ExchangeEvent::WinnersSelected { .. } => "winners",
+                ExchangeEvent::Settled { .. } => "settled",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(trail, ["created", "bid", "bid", "bid", "bid", "round", "round", "round", "winners", "settled"]);
+    }
+
+    #[test]
+    fn test_file_log_survives_reopening() {
+        for (encoding, extension) in [(Encoding::Json, "jsonl"), (Encoding::Protobuf, "bin")] {
+            let path = std::env::temp_dir().join(format!("combidex-events-{}.{}", std::process::id(), extension));
+            let _ = std::fs::remove_file(&path);
+            let (live, alice, _) = trade(Arc::new(FileEventStore::open_as(&path, encoding).unwrap()));
+
+            // The log keeps its encoding whatever it is reopened with
+            let reopened = FileEventStore::open(&path).unwrap();
+            let events = reopened.read_from(1).unwrap();
+            assert_eq!(replay(&events, None).user(alice).unwrap().balance, live.user(alice).unwrap().balance);
+            assert_eq!(reopened.append(&[ExchangeEvent::BidCancelled { bid_id: BidId(99) }]).unwrap(), events.len() as u64 + 1);
+            assert_eq!(reopened.read_from(events.len() as u64 + 1).unwrap().len(), 1);
+            assert_eq!(log_encoding(&path).unwrap(), Some(encoding));
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+}
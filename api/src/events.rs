@@ -0,0 +1,184 @@
+use std::io;
+use std::sync::Arc;
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_tungstenite::accept_hdr_async;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::tungstenite::Message;
+use model::ids::BasketId;
+use auction::observer::{AuctionEvent, AuctionObserver};
+use crate::auth::{Authenticator, Scope, API_KEY_HEADER};
+use crate::error::ApiError;
+
+
+/// Fans auction events out to every connected WebSocket client.
+#[derive(Debug, Clone)]
+pub struct EventBroadcaster {
+    sender: broadcast::Sender<Arc<AuctionEvent>>,
+}
+
+impl EventBroadcaster {
+    /// `capacity` events are buffered per client; a client further behind skips ahead.
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        EventBroadcaster { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<AuctionEvent>> {
+        self.sender.subscribe()
+    }
+}
+
+impl AuctionObserver for EventBroadcaster {
+    fn on_event(&self, event: &AuctionEvent) {
+        // No subscribers is not an error
+        let _ = self.sender.send(Arc::new(event.clone()));
+    }
+}
+
+
+fn query_param<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    request.uri().query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+}
+
+/// The `basket_id` query parameter of a handshake, if any.
+fn basket_filter(request: &Request) -> Option<BasketId> {
+    query_param(request, "basket_id").and_then(|id| id.parse().ok())
+}
+
+/// Checks a handshake's credentials for the read-only scope. Browsers cannot set headers
+/// on a WebSocket, so `?api_key=` and `?token=` are accepted as well.
+fn authorize(authenticator: &Authenticator, request: &Request) -> Result<(), ApiError> {
+    let header = |name: &str| request.headers().get(name).and_then(|value| value.to_str().ok());
+    let principal = match (query_param(request, "api_key"), query_param(request, "token")) {
+        (Some(key), _) => authenticator.authenticate(None, Some(key)),
+        (None, Some(token)) => authenticator.verify(token),
+        (None, None) => authenticator.authenticate(header("authorization"), header(API_KEY_HEADER)),
+    }?;
+    principal.require(Scope::ReadOnly)
+}
+
+/// Accepts WebSocket clients on `listener` and pushes each event to them as a JSON text
+/// frame. A client connecting with `?basket_id=N` only hears about that basket.
+pub async fn serve_events(listener: TcpListener, broadcaster: EventBroadcaster) -> io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(push_events(stream, broadcaster.subscribe(), None));
+    }
+}
+
+/// [`serve_events`] for authenticated clients only; others are turned away at the
+/// handshake with 401 or 403.
+pub async fn serve_authenticated_events(listener: TcpListener, broadcaster: EventBroadcaster, authenticator: Arc<Authenticator>) -> io::Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(push_events(stream, broadcaster.subscribe(), Some(authenticator.clone())));
+    }
+}
+
+// The handshake callback's error type is fixed by tungstenite
+#[allow(clippy::result_large_err)]
+async fn push_events(stream: TcpStream, mut events: broadcast::Receiver<Arc<AuctionEvent>>, authenticator: Option<Arc<Authenticator>>) {
+    let mut basket_id = None;
+    let handshake = |request: &Request, response: Response| {
+        if let Some(Err(error)) = authenticator.as_ref().map(|authenticator| authorize(authenticator, request)) {
+            let status = match error {
+                ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+                _ => StatusCode::UNAUTHORIZED,
+            };
+            let mut rejection = ErrorResponse::new(Some(error.to_string()));
+            *rejection.status_mut() = status;
+            return Err(rejection);
+        }
+        basket_id = basket_filter(request);
+        Ok(response)
+    };
+    let Ok(mut socket) = accept_hdr_async(stream, handshake).await else {
+        return;
+    };
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) if basket_id.is_none_or(|id| id == event.basket_id()) => {
+                    let Ok(text) = serde_json::to_string(&*event) else { continue };
+                    if socket.send(Message::Text(text)).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            },
+            message = socket.next() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use tokio_tungstenite::connect_async;
+    use model::ids::UserId;
+    use model::model::{Asset, AssetInfo, BidType};
+    use crate::exchange::{AuctionRequest, Exchange, Mechanism};
+
+    #[tokio::test]
+    async fn test_clients_hear_their_basket_from_first_round_to_settlement() {
+        let broadcaster = EventBroadcaster::new(64);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_events(listener, broadcaster.clone()));
+
+        let mut exchange = Exchange::new();
+        exchange.add_observer(Arc::new(broadcaster));
+        let alice = exchange.register_user("Alice", 100_000.0).unwrap().id;
+        let bob = exchange.register_user("Bob", 100_000.0).unwrap().id;
+        let quiet = exchange.create_basket(vec![AssetInfo::new(Asset::new("ETH", "USD"), 1.0, 2_000.0)]).unwrap().id;
+        let basket = exchange.create_basket(vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 30_000.0)]).unwrap().id;
+        exchange.submit_bid(alice, basket, BidType::XOR, 60_000.0, Some(1.0)).unwrap();
+        exchange.submit_bid(bob, basket, BidType::XOR, 40_000.0, Some(1.0)).unwrap();
+        exchange.submit_bid(alice, quiet, BidType::XOR, 2_500.0, None).unwrap();
+
+        let (mut client, _) = connect_async(format!("ws://{}/events?basket_id={}", addr, basket)).await.unwrap();
+        exchange.run_auction(&AuctionRequest { basket_id: quiet, mechanism: Mechanism::Xor, price_increment: Some(0.05), max_rounds: Some(20), settlement: None }).unwrap();
+        exchange.run_auction(&AuctionRequest { basket_id: basket, mechanism: Mechanism::Cca, price_increment: Some(0.1), max_rounds: Some(5), settlement: None }).unwrap();
+
+        let mut kinds = Vec::new();
+        while kinds.last() != Some(&"settled".to_string()) {
+            let Some(Ok(Message::Text(text))) = client.next().await else { panic!("stream ended early") };
+            let event: Value = serde_json::from_str(&text).unwrap();
+            assert_eq!(event["basket_id"].as_u64().or(event["report"]["metadata"]["basket_id"].as_u64()), Some(basket.get()));
+            kinds.push(event["event"].as_str().unwrap().to_string());
+        }
+        assert_eq!(kinds.first().map(String::as_str), Some("round"));
+        assert_eq!(&kinds[kinds.len() - 6..], ["provisional_allocation", "order_updated", "order_updated", "order_updated", "winners", "settled"]);
+    }
+
+    #[test]
+    fn test_basket_filter_reads_the_query() {
+        let request = Request::builder().uri("/events?x=1&basket_id=42").body(()).unwrap();
+        assert_eq!(basket_filter(&request), Some(BasketId(42)));
+        assert_eq!(basket_filter(&Request::builder().uri("/events").body(()).unwrap()), None);
+    }
+
+    #[tokio::test]
+    async fn test_authenticated_events_turn_away_unknown_clients() {
+        let authenticator = Authenticator::new().with_api_key("k-reader", crate::auth::Principal::user(UserId(1), Scope::ReadOnly));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_authenticated_events(listener, EventBroadcaster::new(8), Arc::new(authenticator)));
+
+        let rejected = connect_async(format!("ws://{}/events?api_key=k-nobody", addr)).await;
+        assert!(matches!(rejected, Err(tokio_tungstenite::tungstenite::Error::Http(response)) if response.status() == StatusCode::UNAUTHORIZED));
+        assert!(connect_async(format!("ws://{}/events?api_key=k-reader&basket_id=1", addr)).await.is_ok());
+    }
+}
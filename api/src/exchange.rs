@@ -0,0 +1,1798 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use auction::analysis::{self, MechanismComparison};
+use auction::cca_auction::CombiClockAuction;
+use auction::clearing::{ClearedSettlement, Clearing};
+use auction::config::{AuctionConfig, Config, FeeSchedule, SettlementConfig};
+use auction::error::ClearingError;
+use auction::escrow::Escrow;
+use auction::ledger::{JournalEntry, Ledger};
+use auction::margin::RateMargin;
+use auction::observer::{AuctionEvent, AuctionObserver, AuctionObservers};
+use auction::report::{AuctionMetadata, SettlementMode, SettlementReport};
+use auction::scoring::{self, BidScore, BidderProfile, ScoringRule};
+use auction::sim::{Clock, SimRng, SystemClock};
+use auction::stats::{AUCTION_BIDS, BIDS_SUBMITTED};
+use auction::simple_auction::{OrAuction, PartialFill, XorAuction};
+use auction::vcg_auction::VCGAuction;
+use model::ids::{AuctionId, BasketId, BidId, UserId};
+use model::model::{AssetInfo, Basket, Bid, BidType, User};
+use model::safe_math;
+use crate::admin::AdminAction;
+use crate::auth::Principal;
+use crate::codec::{self, Encoding};
+use crate::error::ApiError;
+use crate::event_store::{AuctionRecorder, EventStore, ExchangeEvent};
+use crate::nav::{AuctionPerformance, NavSnapshot};
+use crate::oms::OrderManager;
+use crate::rate_limit::{AuctionPhase, UserRateLimits};
+use crate::risk::{Exposure, RiskEngine};
+use crate::storage::{Repository, StorageError};
+
+
+/// A resting bid, by reference to its user so balances stay in one place.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BidRecord {
+    pub id: BidId,
+    pub user_id: UserId,
+    pub basket_id: BasketId,
+    pub bid_type: BidType,
+    pub price: f64,
+    pub quantity: Option<f64>,
+}
+
+/// A bid's score under the exchange's [`ScoringRule`], as journaled.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoredBid {
+    pub bid_id: BidId,
+    #[serde(flatten)]
+    pub score: BidScore,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mechanism {
+    Xor,
+    Or,
+    Vcg,
+    Cca,
+}
+
+impl Mechanism {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mechanism::Xor => "XOR",
+            Mechanism::Or => "OR",
+            Mechanism::Vcg => "VCG",
+            Mechanism::Cca => "CCA",
+        }
+    }
+}
+
+/// How to auction a basket. The clock settings only apply to CCA, which starts from the
+/// basket's own prices; those left out come from the exchange's [`AuctionConfig`], and
+/// the settlement mode from its [`SettlementConfig`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuctionRequest {
+    pub basket_id: BasketId,
+    pub mechanism: Mechanism,
+    #[serde(default)]
+    pub price_increment: Option<f64>,
+    #[serde(default)]
+    pub max_rounds: Option<usize>,
+    #[serde(default)]
+    pub settlement: Option<SettlementMode>,
+}
+
+impl AuctionRequest {
+    /// A request with the exchange's clock settings and settlement mode.
+    pub fn new(basket_id: BasketId, mechanism: Mechanism) -> Self {
+        AuctionRequest { basket_id, mechanism, price_increment: None, max_rounds: None, settlement: None }
+    }
+}
+
+/// A cleared auction. `payments` is what each winner was charged, which under VCG is
+/// below its bid.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuctionOutcome {
+    pub auction_id: AuctionId,
+    pub basket_id: BasketId,
+    pub mechanism: Mechanism,
+    pub winning_bids: Vec<BidId>,
+    pub allocation: HashMap<UserId, Vec<AssetInfo>>,
+    pub payments: HashMap<UserId, f64>,
+    pub report: SettlementReport,
+    /// How an XOR auction with partial fills cascaded the basket down its bids, in order.
+    #[serde(default)]
+    pub fills: Vec<FilledBid>,
+}
+
+/// A winning bid's share of the basket under partial fills.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FilledBid {
+    pub bid_id: BidId,
+    pub fill: PartialFill,
+}
+
+/// Version of the [`ExchangeSnapshot`] JSON layout; [`Exchange::restore`] reads older ones,
+/// whose missing fields take their defaults, and refuses newer. Binary snapshots carry a
+/// [`codec::SCHEMA_VERSION`] instead.
+pub const SNAPSHOT_VERSION: u32 = 3;
+
+const DAY_MILLIS: u64 = 86_400_000;
+
+/// Everything needed to bring up a copy of an exchange: its state plus the clearing
+/// ledger, which holds every cash movement. [`Exchange::snapshot`] refuses while an
+/// auction is in flight, so none is ever part of one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExchangeSnapshot {
+    pub version: u32,
+    pub last_id: u64,
+    pub users: Vec<User>,
+    pub baskets: Vec<Basket>,
+    pub bids: Vec<BidRecord>,
+    pub outcomes: Vec<AuctionOutcome>,
+    pub ledger: Vec<JournalEntry>,
+    /// Baskets an operator halted, since version 2.
+    #[serde(default)]
+    pub halted: Vec<BasketId>,
+    /// Bidding deadlines and their soft-close extensions, since version 2.
+    #[serde(default)]
+    pub controls: Vec<BasketControls>,
+    /// What resting bids reserve, since version 3; without it they lock escrow afresh.
+    #[serde(default)]
+    pub escrow: Vec<EscrowLock>,
+    /// Marks cash settlement may still fix at, since version 3.
+    #[serde(default)]
+    pub marks: Vec<Mark>,
+    /// Since version 3.
+    #[serde(default)]
+    pub navs: Vec<NavSnapshot>,
+    /// In Unix milliseconds, since version 3.
+    #[serde(default)]
+    pub last_nav_at: Option<u64>,
+    /// Since version 3.
+    #[serde(default)]
+    pub profiles: Vec<ProfileRecord>,
+}
+
+/// When bidding on a basket closes and how often the soft-close rule moved it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BasketControls {
+    pub basket_id: BasketId,
+    /// In Unix milliseconds, if an operator set a deadline.
+    pub closes_at: Option<u64>,
+    pub extensions: u32,
+}
+
+/// Funds a user's resting bids on a basket reserve.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EscrowLock {
+    pub user_id: UserId,
+    pub basket_id: BasketId,
+    pub amount: f64,
+}
+
+/// The price of the asset with base `base` at `at`, in Unix milliseconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mark {
+    pub base: String,
+    pub at: u64,
+    pub price: f64,
+}
+
+/// What scoring rules know of a user.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ProfileRecord {
+    pub user_id: UserId,
+    pub profile: BidderProfile,
+}
+
+/// Users, baskets, resting bids and cleared auctions, with the clearing that settles them.
+/// With a repository every change is written through to it first, and with a journal
+/// every change is also appended to it as an event.
+pub struct Exchange {
+    users: BTreeMap<UserId, User>,
+    baskets: BTreeMap<BasketId, Basket>,
+    bids: BTreeMap<BidId, BidRecord>,
+    outcomes: BTreeMap<AuctionId, AuctionOutcome>,
+    orders: OrderManager,
+    clearing: Clearing,
+    /// Funds every resting bid reserves until it is cancelled, loses or pays at settlement.
+    escrow: Escrow,
+    auction: AuctionConfig,
+    fees: FeeSchedule,
+    settlement: SettlementConfig,
+    /// Ranks XOR bids by score rather than price when set.
+    scoring: Option<Arc<dyn ScoringRule>>,
+    profiles: HashMap<UserId, BidderProfile>,
+    /// Every asset's marks by time, in Unix milliseconds, for cash-settlement fixings.
+    mark_history: BTreeMap<String, BTreeMap<u64, f64>>,
+    /// Every basket's NAV by time, snapshotted from the marks every `nav_interval`.
+    nav_history: BTreeMap<BasketId, BTreeMap<u64, f64>>,
+    nav_interval: Option<u64>,
+    last_nav_at: Option<u64>,
+    observers: AuctionObservers,
+    clock: Arc<dyn Clock>,
+    /// Breaks ties between equal bids when set; without it the latest of them wins.
+    rng: Option<SimRng>,
+    rate_limits: Option<UserRateLimits>,
+    risk: Option<RiskEngine>,
+    /// Baskets whose CCA is between [`Exchange::open_auction`] and its settlement.
+    final_rounds: BTreeSet<BasketId>,
+    /// Baskets an operator halted.
+    halted: BTreeSet<BasketId>,
+    /// When bidding on a basket closes, in Unix milliseconds.
+    deadlines: BTreeMap<BasketId, u64>,
+    /// Times a leading bid moved each basket's close under the soft-close rule.
+    extensions: BTreeMap<BasketId, u32>,
+    /// Times each basket's auction was halted or cancelled; an auction opened before the
+    /// count last changed is not settled.
+    interruptions: BTreeMap<BasketId, u64>,
+    /// Cloned into every [`PendingAuction`], so more than one reference means an auction
+    /// is in flight.
+    open_auctions: Arc<()>,
+    repository: Option<Arc<dyn Repository>>,
+    journal: Option<Arc<dyn EventStore>>,
+    last_id: u64,
+}
+
+impl fmt::Debug for Exchange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Exchange")
+            .field("users", &self.users.len())
+            .field("baskets", &self.baskets.len())
+            .field("bids", &self.bids.len())
+            .field("outcomes", &self.outcomes.len())
+            .field("persistent", &self.repository.is_some())
+            .field("journaled", &self.journal.is_some())
+            .finish()
+    }
+}
+
+impl Default for Exchange {
+    fn default() -> Self {
+        Exchange::new()
+    }
+}
+
+impl Exchange {
+    pub fn new() -> Self {
+        Exchange {
+            users: BTreeMap::new(),
+            baskets: BTreeMap::new(),
+            bids: BTreeMap::new(),
+            outcomes: BTreeMap::new(),
+            orders: OrderManager::new(),
+            clearing: Clearing::new(),
+            escrow: escrow_for(&FeeSchedule::default()),
+            auction: AuctionConfig::default(),
+            fees: FeeSchedule::default(),
+            settlement: SettlementConfig::default(),
+            scoring: None,
+            profiles: HashMap::new(),
+            mark_history: BTreeMap::new(),
+            nav_history: BTreeMap::new(),
+            nav_interval: None,
+            last_nav_at: None,
+            observers: AuctionObservers::default(),
+            clock: Arc::new(SystemClock),
+            rng: None,
+            rate_limits: None,
+            risk: None,
+            final_rounds: BTreeSet::new(),
+            halted: BTreeSet::new(),
+            deadlines: BTreeMap::new(),
+            extensions: BTreeMap::new(),
+            interruptions: BTreeMap::new(),
+            open_auctions: Arc::new(()),
+            repository: None,
+            journal: None,
+            last_id: 0,
+        }
+    }
+
+    /// Takes auction defaults, fees, settlement, bid rate limits and risk checks from `config`.
+    pub fn with_config(mut self, config: &Config) -> Self {
+        let relock = self.fees != config.fees || self.auction.partial_fills != config.auction.partial_fills;
+        self.auction = config.auction.clone();
+        self.fees = config.fees.clone();
+        self.settlement = config.settlement.clone();
+        self.clearing.accruals = config.settlement.accruals;
+        self.rate_limits = config.rate_limits.enabled.then(|| UserRateLimits::new(config.rate_limits.clone()));
+        self.risk = config.risk.enabled.then(|| {
+            // Marks restored from a snapshot price bids from the start
+            let mut risk = RiskEngine::new(config.risk.clone());
+            for (base, history) in &self.mark_history {
+                if let Some(&price) = history.values().next_back() {
+                    risk.update_mark(base, price);
+                }
+            }
+            risk
+        });
+        if relock {
+            self.relock();
+        }
+        self
+    }
+
+    /// Runs `risk`'s checks on every bid.
+    pub fn with_risk(mut self, risk: RiskEngine) -> Self {
+        self.risk = Some(risk);
+        self
+    }
+
+    /// Stamps auctions with the time of `clock`, e.g. a [`auction::sim::SimClock`].
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Breaks ties between equally priced bids with an RNG seeded by `seed`, so that a
+    /// simulated run replays exactly.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = Some(SimRng::new(seed));
+        self
+    }
+
+    /// Picks XOR winners by their score under `rule` instead of by price, journaling every
+    /// bid's score and its inputs with the auction.
+    pub fn with_scoring(mut self, rule: Arc<dyn ScoringRule>) -> Self {
+        self.scoring = Some(rule);
+        self
+    }
+
+    /// Snapshots every basket's NAV on the first mark at least `every` after the last
+    /// snapshot, writing the snapshots through to the repository.
+    pub fn with_nav_interval(mut self, every: Duration) -> Self {
+        self.nav_interval = Some(every.as_millis() as u64);
+        self
+    }
+
+    /// Appends every later change to `journal`.
+    pub fn with_journal(mut self, journal: Arc<dyn EventStore>) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Resumes the exchange by replaying `journal` and keeps appending to it.
+    pub fn from_journal(journal: Arc<dyn EventStore>) -> Result<Self, StorageError> {
+        let events = journal.read_from(1)?;
+        let mut exchange = crate::event_store::replay(&events, None);
+        exchange.restore_settlements();
+        Ok(exchange.with_journal(journal))
+    }
+
+    /// Resumes the exchange stored in `repository`, its clearing ledger and halted auctions
+    /// included, and keeps writing to it. Ids, including auction and settlement ids,
+    /// continue after the highest recovered ones.
+    pub fn recover(repository: Arc<dyn Repository>) -> Result<Self, StorageError> {
+        let stored = repository.load()?;
+        let mut exchange = Exchange::new();
+        exchange.last_id = stored.users.iter().map(|user| user.id.get())
+            .chain(stored.baskets.iter().map(|basket| basket.id.get()))
+            .chain(stored.bids.iter().map(|bid| bid.id.get()))
+            .max()
+            .unwrap_or(0);
+        exchange.users = stored.users.into_iter().map(|user| (user.id, user)).collect();
+        exchange.baskets = stored.baskets.into_iter().map(|basket| (basket.id, basket)).collect();
+        exchange.bids = stored.bids.into_iter().map(|bid| (bid.id, bid)).collect();
+        exchange.outcomes = stored.outcomes.into_iter().map(|outcome| (outcome.auction_id, outcome)).collect();
+        exchange.clearing.ledger = Ledger::from_entries(stored.ledger).map_err(|error| StorageError::Corrupt(error.to_string()))?;
+        exchange.restore_settlements();
+        exchange.halted = stored.halted.into_iter().collect();
+        for snapshot in stored.navs {
+            exchange.nav_history.entry(snapshot.basket_id).or_default().insert(snapshot.at, snapshot.nav);
+        }
+        exchange.restore_controls(stored.controls);
+        exchange.orders = OrderManager::resting(exchange.bids.values(), exchange.clock.now_millis());
+        exchange.relock();
+        exchange.repository = Some(repository);
+        Ok(exchange)
+    }
+
+    /// The whole exchange as JSON, for backups and moving it to another instance. Refused
+    /// while an auction is open, since its settlement could not be applied to the copy.
+    pub fn snapshot(&self) -> Result<Vec<u8>, ApiError> {
+        self.snapshot_as(Encoding::Json)
+    }
+
+    /// [`Exchange::snapshot`] in `encoding`.
+    pub fn snapshot_as(&self, encoding: Encoding) -> Result<Vec<u8>, ApiError> {
+        if Arc::strong_count(&self.open_auctions) > 1 {
+            return Err(ApiError::Conflict("an auction is in flight; settle it before taking a snapshot"));
+        }
+        let mut escrow: Vec<EscrowLock> = self.escrow.locks().map(|(user_id, basket_id, amount)| EscrowLock { user_id, basket_id, amount }).collect();
+        escrow.sort_by_key(|lock| (lock.user_id, lock.basket_id));
+        let mut profiles: Vec<ProfileRecord> = self.profiles.iter().map(|(&user_id, &profile)| ProfileRecord { user_id, profile }).collect();
+        profiles.sort_by_key(|record| record.user_id);
+        let snapshot = ExchangeSnapshot {
+            version: SNAPSHOT_VERSION,
+            last_id: self.last_id,
+            users: self.users.values().cloned().collect(),
+            baskets: self.baskets.values().cloned().collect(),
+            bids: self.bids.values().cloned().collect(),
+            outcomes: self.outcomes.values().cloned().collect(),
+            ledger: self.clearing.ledger.entries().to_vec(),
+            halted: self.halted.iter().copied().collect(),
+            controls: self.basket_controls(),
+            escrow,
+            marks: self.mark_history.iter()
+                .flat_map(|(base, history)| history.iter().map(|(&at, &price)| Mark { base: base.clone(), at, price }))
+                .collect(),
+            navs: self.nav_history.iter()
+                .flat_map(|(&basket_id, history)| history.iter().map(move |(&at, &nav)| NavSnapshot { basket_id, at, nav }))
+                .collect(),
+            last_nav_at: self.last_nav_at,
+            profiles,
+        };
+        Ok(match encoding {
+            Encoding::Json => serde_json::to_vec(&snapshot).expect("exchange state serializes to JSON"),
+            Encoding::Protobuf => codec::encode_snapshot(&snapshot),
+        })
+    }
+
+    /// Brings up an exchange from [`Exchange::snapshot_as`] bytes in either encoding. It
+    /// has no repository, journal or observers; attach them before serving. Auction and
+    /// settlement ids continue after the highest restored ones.
+    pub fn restore(bytes: &[u8]) -> Result<Self, StorageError> {
+        let snapshot = if bytes.starts_with(codec::SNAPSHOT_MAGIC) {
+            codec::decode_snapshot(bytes)?
+        } else {
+            let snapshot: ExchangeSnapshot = serde_json::from_slice(bytes)?;
+            if snapshot.version > SNAPSHOT_VERSION {
+                return Err(StorageError::Corrupt(format!("unsupported snapshot version {}", snapshot.version)));
+            }
+            snapshot
+        };
+        let mut exchange = Exchange::new();
+        exchange.clearing.ledger = Ledger::from_entries(snapshot.ledger).map_err(|error| StorageError::Corrupt(error.to_string()))?;
+        exchange.last_id = snapshot.last_id;
+        exchange.users = snapshot.users.into_iter().map(|user| (user.id, user)).collect();
+        exchange.baskets = snapshot.baskets.into_iter().map(|basket| (basket.id, basket)).collect();
+        exchange.bids = snapshot.bids.into_iter().map(|bid| (bid.id, bid)).collect();
+        exchange.outcomes = snapshot.outcomes.into_iter().map(|outcome| (outcome.auction_id, outcome)).collect();
+        exchange.halted = snapshot.halted.into_iter().collect();
+        exchange.restore_controls(snapshot.controls);
+        for mark in snapshot.marks {
+            exchange.mark_history.entry(mark.base).or_default().insert(mark.at, mark.price);
+        }
+        for nav in snapshot.navs {
+            exchange.nav_history.entry(nav.basket_id).or_default().insert(nav.at, nav.nav);
+        }
+        exchange.last_nav_at = snapshot.last_nav_at;
+        exchange.profiles = snapshot.profiles.into_iter().map(|record| (record.user_id, record.profile)).collect();
+        exchange.orders = OrderManager::resting(exchange.bids.values(), exchange.clock.now_millis());
+        if snapshot.escrow.is_empty() {
+            exchange.relock();
+        }
+        for lock in snapshot.escrow {
+            let user = exchange.users.get(&lock.user_id).ok_or_else(|| StorageError::Corrupt(format!("escrow locked for unknown user {}", lock.user_id.get())))?;
+            exchange.escrow.lock_amount(user, lock.basket_id, lock.amount).map_err(|error| StorageError::Corrupt(error.to_string()))?;
+        }
+        exchange.restore_settlements();
+        Ok(exchange)
+    }
+
+    /// Every basket's bidding deadline and soft-close extensions, where either is set.
+    fn basket_controls(&self) -> Vec<BasketControls> {
+        let baskets: BTreeSet<BasketId> = self.deadlines.keys().chain(self.extensions.keys()).copied().collect();
+        baskets.into_iter().map(|basket_id| self.controls(basket_id)).collect()
+    }
+
+    fn controls(&self, basket_id: BasketId) -> BasketControls {
+        BasketControls { basket_id, closes_at: self.closes_at(basket_id), extensions: self.extensions.get(&basket_id).copied().unwrap_or(0) }
+    }
+
+    /// The basket's controls once `event` is applied, if it changes them.
+    fn controls_after(&self, event: &ExchangeEvent) -> Option<BasketControls> {
+        match event {
+            ExchangeEvent::Admin { action: AdminAction::Extend { basket_id, closes_at }, .. } => Some(BasketControls { closes_at: Some(*closes_at), ..self.controls(*basket_id) }),
+            ExchangeEvent::BiddingExtended { basket_id, closes_at, extension } => Some(BasketControls { basket_id: *basket_id, closes_at: Some(*closes_at), extensions: *extension }),
+            _ => None,
+        }
+    }
+
+    fn restore_controls(&mut self, controls: Vec<BasketControls>) {
+        for control in controls {
+            if let Some(closes_at) = control.closes_at {
+                self.deadlines.insert(control.basket_id, closes_at);
+            }
+            if control.extensions > 0 {
+                self.extensions.insert(control.basket_id, control.extensions);
+            }
+        }
+    }
+
+    /// Records every recovered auction's settlement with the clearing, so retrying one is
+    /// a no-op, and continues auction and settlement ids after the highest of them.
+    fn restore_settlements(&mut self) {
+        for outcome in self.outcomes.values() {
+            let users = outcome.report.settlements.iter()
+                .filter_map(|settlement| Some((settlement.user_id, Arc::new(self.users.get(&settlement.user_id)?.clone()))))
+                .collect();
+            self.clearing.restore_settlement(ClearedSettlement { users, report: outcome.report.clone(), conversions: Vec::new() });
+        }
+    }
+
+    /// Locks escrow afresh for every resting bid, e.g. once the fees it covers changed or
+    /// the bids were recovered.
+    fn relock(&mut self) {
+        self.escrow = escrow_for(&self.fees);
+        let bidders: BTreeSet<(UserId, BasketId)> = self.bids.values().map(|bid| (bid.user_id, bid.basket_id)).collect();
+        for (user_id, basket_id) in bidders {
+            self.lock(user_id, basket_id);
+        }
+    }
+
+    /// Locks what the user's resting bids on the basket need afresh, or with partial fills
+    /// as much of it as the user has available. Every bid was checked against the user's
+    /// available balance before it was journaled, so a failure here leaves them resting
+    /// unfunded and their wins are refused at settlement.
+    fn lock(&mut self, user_id: UserId, basket_id: BasketId) {
+        self.escrow.unlock(user_id, basket_id);
+        let mut required = self.escrow_required(user_id, basket_id, None);
+        let Some(user) = self.users.get(&user_id) else { return };
+        if self.auction.partial_fills {
+            required = required.min(self.escrow.available_balance(user));
+        }
+        if required > 0.0 {
+            if let Err(error) = self.escrow.lock_amount(user, basket_id, required) {
+                tracing::warn!(user_id = user_id.get(), basket_id = basket_id.get(), %error, "bids rest without escrow");
+            }
+        }
+    }
+
+    /// Escrow the user's resting bids on the basket need, with `bid` in place of the
+    /// resting bid of its id, if given. Any of their OR bids may win, but at most one of
+    /// their XOR bids, so only the dearest of those is reserved.
+    fn escrow_required(&self, user_id: UserId, basket_id: BasketId, bid: Option<&BidRecord>) -> f64 {
+        let resting = self.bids.values()
+            .filter(|resting| resting.user_id == user_id && resting.basket_id == basket_id && bid.is_none_or(|bid| bid.id != resting.id));
+        let (mut xor, mut or) = (0.0_f64, 0.0);
+        for record in resting.chain(bid) {
+            let Some(model) = self.model_bid(record) else { continue };
+            let amount = self.escrow.required_amount(&model);
+            match record.bid_type {
+                BidType::XOR => xor = xor.max(amount),
+                BidType::OR => or += amount,
+            }
+        }
+        xor + or
+    }
+
+    /// Runs `write` against the repository, if there is one.
+    fn persist(&self, write: impl FnOnce(&dyn Repository) -> Result<(), StorageError>) -> Result<(), ApiError> {
+        match &self.repository {
+            Some(repository) => write(repository.as_ref()).map_err(ApiError::from),
+            None => Ok(()),
+        }
+    }
+
+    /// Journals `events`, then applies them and announces the orders they moved.
+    fn commit(&mut self, events: Vec<ExchangeEvent>) -> Result<(), ApiError> {
+        if let Some(journal) = &self.journal {
+            journal.append(&events)?;
+        }
+        let mut updates = Vec::new();
+        for event in &events {
+            updates.extend(self.apply(event));
+        }
+        for update in &updates {
+            self.observers.on_event(update);
+        }
+        Ok(())
+    }
+
+    /// The only place state changes, so replaying the journal rebuilds it exactly.
+    /// Returns the order updates the event caused.
+    pub(crate) fn apply(&mut self, event: &ExchangeEvent) -> Vec<AuctionEvent> {
+        let updates = self.orders.apply(event, self.clock.now_millis());
+        match event {
+            ExchangeEvent::UserRegistered { user } => {
+                self.last_id = self.last_id.max(user.id.get());
+                self.users.insert(user.id, user.clone());
+            }
+            ExchangeEvent::BasketCreated { basket } => {
+                self.last_id = self.last_id.max(basket.id.get());
+                self.baskets.insert(basket.id, basket.clone());
+            }
+            ExchangeEvent::BidSubmitted { bid } => {
+                self.last_id = self.last_id.max(bid.id.get());
+                self.bids.insert(bid.id, bid.clone());
+                self.lock(bid.user_id, bid.basket_id);
+            }
+            ExchangeEvent::BidAmended { bid } => {
+                self.bids.insert(bid.id, bid.clone());
+                self.lock(bid.user_id, bid.basket_id);
+            }
+            ExchangeEvent::BidRejected { bid_id, .. } => {
+                self.last_id = self.last_id.max(bid_id.get());
+            }
+            ExchangeEvent::BidCancelled { bid_id } => {
+                if let Some(bid) = self.bids.remove(bid_id) {
+                    self.lock(bid.user_id, bid.basket_id);
+                }
+            }
+            ExchangeEvent::Settled { outcome, users } => {
+                for user in users {
+                    self.users.insert(user.id, user.clone());
+                }
+                self.bids.retain(|_, bid| bid.basket_id != outcome.basket_id);
+                // The winners' locks became their payments; the losers' are freed
+                self.escrow.release_basket(outcome.basket_id);
+                self.outcomes.insert(outcome.auction_id, outcome.clone());
+            }
+            ExchangeEvent::Admin { action, .. } => match action {
+                AdminAction::Halt { basket_id } => {
+                    self.halted.insert(*basket_id);
+                    *self.interruptions.entry(*basket_id).or_insert(0) += 1;
+                }
+                AdminAction::Resume { basket_id } => {
+                    self.halted.remove(basket_id);
+                }
+                AdminAction::Extend { basket_id, closes_at } => {
+                    self.deadlines.insert(*basket_id, *closes_at);
+                }
+                AdminAction::Cancel { basket_id, cancelled_bids } => {
+                    for bid_id in cancelled_bids {
+                        if let Some(bid) = self.bids.remove(bid_id) {
+                            self.lock(bid.user_id, bid.basket_id);
+                        }
+                    }
+                    *self.interruptions.entry(*basket_id).or_insert(0) += 1;
+                }
+                AdminAction::ExpireStaleBids { expired_bids, .. } => {
+                    for bid_id in expired_bids {
+                        if let Some(bid) = self.bids.remove(bid_id) {
+                            self.lock(bid.user_id, bid.basket_id);
+                        }
+                    }
+                }
+            },
+            ExchangeEvent::BiddingExtended { basket_id, closes_at, extension } => {
+                self.deadlines.insert(*basket_id, *closes_at);
+                self.extensions.insert(*basket_id, *extension);
+            }
+            ExchangeEvent::RoundPriced { .. } | ExchangeEvent::BidderEliminated { .. } | ExchangeEvent::BidsScored { .. } | ExchangeEvent::WinnersSelected { .. } => {}
+        }
+        updates
+    }
+
+    /// Registers `observer` for the rounds, winners and settlement of every later auction.
+    pub fn add_observer(&mut self, observer: Arc<dyn AuctionObserver>) {
+        self.observers.add(observer);
+    }
+
+    /// Ids for users, baskets and bids come from one counter, so none is ever reused.
+    fn next_id(&mut self) -> u64 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    pub fn register_user(&mut self, name: &str, balance: f64) -> Result<User, ApiError> {
+        if name.trim().is_empty() {
+            return Err(ApiError::BadRequest("user name must not be empty".to_string()));
+        }
+        if safe_math::non_negative("balance", balance).is_err() {
+            return Err(ApiError::BadRequest("balance must not be negative".to_string()));
+        }
+        let user = User::new(self.next_id(), name, balance);
+        self.persist(|repository| repository.save_user(&user))?;
+        self.commit(vec![ExchangeEvent::UserRegistered { user: user.clone() }])?;
+        Ok(user)
+    }
+
+    pub fn user(&self, user_id: UserId) -> Result<&User, ApiError> {
+        self.users.get(&user_id).ok_or(ApiError::NotFound("user"))
+    }
+
+    /// The user's balance less the escrow their resting bids lock.
+    pub fn available_balance(&self, user_id: UserId) -> Result<f64, ApiError> {
+        Ok(self.escrow.available_balance(self.user(user_id)?))
+    }
+
+    pub fn create_basket(&mut self, assets: Vec<AssetInfo>) -> Result<Basket, ApiError> {
+        if assets.is_empty() {
+            return Err(ApiError::BadRequest("a basket needs at least one asset".to_string()));
+        }
+        if assets.iter().any(|info| safe_math::positive("quantity", info.quantity).is_err() || safe_math::non_negative("price", info.price).is_err()) {
+            return Err(ApiError::BadRequest("asset quantities must be positive and prices not negative".to_string()));
+        }
+        if let Err(error) = safe_math::checked_sum("basket value", assets.iter().map(AssetInfo::total_value)) {
+            return Err(ApiError::BadRequest(error.to_string()));
+        }
+        let basket = Basket { id: BasketId(self.next_id()), assets };
+        self.persist(|repository| repository.save_basket(&basket))?;
+        self.commit(vec![ExchangeEvent::BasketCreated { basket: basket.clone() }])?;
+        Ok(basket)
+    }
+
+    pub fn basket(&self, basket_id: BasketId) -> Result<&Basket, ApiError> {
+        self.baskets.get(&basket_id).ok_or(ApiError::NotFound("basket"))
+    }
+
+    pub fn baskets(&self) -> Vec<&Basket> {
+        self.baskets.values().collect()
+    }
+
+    fn is_auctioned(&self, basket_id: BasketId) -> bool {
+        self.outcomes.values().any(|outcome| outcome.basket_id == basket_id)
+    }
+
+    /// Where bidding on a basket stands, which decides the rate limit its bids are held to.
+    pub fn phase(&self, basket_id: BasketId) -> AuctionPhase {
+        if self.final_rounds.contains(&basket_id) { AuctionPhase::FinalRounds } else { AuctionPhase::Open }
+    }
+
+    fn check_rate(&self, user_id: UserId, basket_id: BasketId) -> Result<(), ApiError> {
+        match &self.rate_limits {
+            Some(limits) => limits.check(user_id, self.phase(basket_id), self.clock.now_millis()),
+            None => Ok(()),
+        }
+    }
+
+    /// Records the latest price of the asset with base `base`, against which the risk
+    /// checks value bids and cash settlement fixes allocations.
+    pub fn update_mark(&mut self, base: &str, price: f64) {
+        if let Some(risk) = &mut self.risk {
+            risk.update_mark(base, price);
+        }
+        let now = self.clock.now_millis();
+        let history = self.mark_history.entry(base.to_string()).or_default();
+        history.insert(now, price);
+        // A fixing is at most a day old; keep the mark in force two days back and later
+        if let Some(&kept) = history.range(..=now.saturating_sub(2 * DAY_MILLIS)).next_back().map(|(at, _)| at) {
+            *history = history.split_off(&kept);
+        }
+        let due = self.nav_interval.is_some_and(|interval| self.last_nav_at.is_none_or(|last| now >= last + interval));
+        if due {
+            if let Err(error) = self.snapshot_navs() {
+                tracing::warn!(%error, "NAV snapshot failed");
+            }
+        }
+    }
+
+    /// Records the NAV of every basket whose assets are all marked, at their latest marks.
+    pub fn snapshot_navs(&mut self) -> Result<Vec<NavSnapshot>, ApiError> {
+        let now = self.clock.now_millis();
+        let marks: HashMap<String, f64> = self.mark_history.iter()
+            .filter_map(|(base, history)| Some((base.clone(), *history.values().next_back()?)))
+            .collect();
+        let snapshots: Vec<NavSnapshot> = self.baskets.values().filter_map(|basket| NavSnapshot::of(basket, &marks, now)).collect();
+        self.persist(|repository| repository.save_navs(&snapshots))?;
+        for snapshot in &snapshots {
+            self.nav_history.entry(snapshot.basket_id).or_default().insert(snapshot.at, snapshot.nav);
+        }
+        self.last_nav_at = Some(now);
+        Ok(snapshots)
+    }
+
+    /// The NAV snapshots of `basket_id` between `from` and `to`, both inclusive and in
+    /// Unix milliseconds, in time order.
+    pub fn nav_history(&self, basket_id: BasketId, from: Option<u64>, to: Option<u64>) -> Result<Vec<NavSnapshot>, ApiError> {
+        self.basket(basket_id)?;
+        let Some(history) = self.nav_history.get(&basket_id) else {
+            return Ok(Vec::new());
+        };
+        Ok(history.range(from.unwrap_or(0)..=to.unwrap_or(u64::MAX))
+            .map(|(&at, &nav)| NavSnapshot { basket_id, at, nav })
+            .collect())
+    }
+
+    /// What auction `auction_id` charged against its basket's NAV then and now.
+    pub fn auction_performance(&self, auction_id: AuctionId) -> Result<AuctionPerformance, ApiError> {
+        let outcome = self.outcome(auction_id)?;
+        let history = self.nav_history(outcome.basket_id, None, None)?;
+        Ok(AuctionPerformance::of(outcome, &history))
+    }
+
+    /// How every mechanism would clear `basket_id`'s resting bids now, without running any
+    /// of them; the CCA clock takes the exchange's auction defaults.
+    pub fn compare_mechanisms(&self, basket_id: BasketId) -> Result<MechanismComparison, ApiError> {
+        let basket = self.basket(basket_id)?;
+        let bids: Vec<Bid> = self.model_bids(basket_id).into_iter().map(|(_, bid)| bid).collect();
+        Ok(analysis::compare(&bids, basket, &self.auction))
+    }
+
+    /// The marks cash settlement fixes `basket` at: each asset's last mark at or before
+    /// the most recent fixing time, or its latest mark without a fixing time. Assets
+    /// never marked are left out.
+    pub fn fixing_marks(&self, basket: &Basket) -> HashMap<String, f64> {
+        let now = self.clock.now_millis();
+        let fixing = match self.settlement.fixing_minute() {
+            Some(minute) => {
+                let today = now - now % DAY_MILLIS + u64::from(minute) * 60_000;
+                if today > now { today.saturating_sub(DAY_MILLIS) } else { today }
+            }
+            None => now,
+        };
+        basket.assets.iter()
+            .filter_map(|info| {
+                let (_, mark) = self.mark_history.get(&info.asset.base)?.range(..=fixing).next_back()?;
+                Some((info.asset.base.clone(), *mark))
+            })
+            .collect()
+    }
+
+    /// Sets what scoring rules know of `user_id`; users without a profile score as
+    /// [`BidderProfile::default`].
+    pub fn set_bidder_profile(&mut self, user_id: UserId, profile: BidderProfile) -> Result<(), ApiError> {
+        self.user(user_id)?;
+        let credit_in_range = (0.0..=1.0).contains(&profile.credit_score);
+        if !credit_in_range || safe_math::non_negative("settlement days", profile.settlement_days).is_err() {
+            return Err(ApiError::BadRequest("credit score must be in [0, 1] and settlement days not negative".to_string()));
+        }
+        self.profiles.insert(user_id, profile);
+        Ok(())
+    }
+
+    /// What `user_id` has at stake: their resting bids other than `excluding`. Bids in an
+    /// auction in flight keep resting until it settles, so its pending allocations count;
+    /// settled auctions have delivered theirs and no longer do.
+    pub fn exposure(&self, user_id: UserId, excluding: Option<BidId>) -> Exposure {
+        let mut exposure = Exposure::default();
+        for bid in self.bids.values().filter(|bid| bid.user_id == user_id && Some(bid.id) != excluding) {
+            if let Some(basket) = self.baskets.get(&bid.basket_id) {
+                exposure.add_bid(basket, bid.price, bid.quantity.unwrap_or(1.0));
+            }
+        }
+        exposure
+    }
+
+    fn check_risk(&self, caller: Option<&Principal>, user_id: UserId, basket_id: BasketId, price: f64, quantity: Option<f64>, excluding: Option<BidId>) -> Result<(), ApiError> {
+        match &self.risk {
+            Some(risk) if !risk.may_override(caller) => risk.check(self.basket(basket_id)?, price, quantity, &self.exposure(user_id, excluding)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Refuses a bid, new or amended, whose escrow the user's available balance cannot
+    /// cover on top of what their bids on the basket already lock. With partial fills an
+    /// XOR bid may be filled as far as its funds go, so any funds at all will do.
+    fn check_escrow(&self, bid: &BidRecord) -> Result<(), ApiError> {
+        let funds = self.available_balance(bid.user_id)? + self.escrow.locked(bid.user_id, bid.basket_id);
+        let funded = match bid.bid_type {
+            BidType::XOR if self.auction.partial_fills => funds > 0.0,
+            _ => funds >= self.escrow_required(bid.user_id, bid.basket_id, Some(bid)),
+        };
+        if !funded {
+            return Err(ApiError::Rejected(ClearingError::InsufficientFunds { user_id: bid.user_id, what: "the escrow for its bid" }));
+        }
+        Ok(())
+    }
+
+    /// Why a bid at `price` for `quantity` of the basket may not rest, if it may not.
+    fn validate_bid(&self, basket_id: BasketId, price: f64, quantity: Option<f64>) -> Result<(), ApiError> {
+        if self.is_auctioned(basket_id) {
+            return Err(ApiError::Conflict("basket has already been auctioned"));
+        }
+        if self.halted.contains(&basket_id) {
+            return Err(ApiError::Conflict("auction of this basket is halted"));
+        }
+        if self.closes_at(basket_id).is_some_and(|closes_at| self.clock.now_millis() >= closes_at) {
+            return Err(ApiError::Conflict("bidding on this basket has closed"));
+        }
+        if safe_math::positive("price", price).is_err() {
+            return Err(ApiError::BadRequest("price must be positive".to_string()));
+        }
+        if quantity.is_some_and(|quantity| !(quantity > 0.0 && quantity <= 1.0)) {
+            return Err(ApiError::BadRequest("quantity is a fraction of the basket in (0, 1]".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Rests a bid. A bid from a known user for a known basket that is refused, including
+    /// by the risk checks, is still given an id and tracked as a rejected order, unless it
+    /// was rate limited.
+    pub fn submit_bid(&mut self, user_id: UserId, basket_id: BasketId, bid_type: BidType, price: f64, quantity: Option<f64>) -> Result<BidRecord, ApiError> {
+        self.submit_bid_as(None, user_id, basket_id, bid_type, price, quantity)
+    }
+
+    /// [`Exchange::submit_bid`] on behalf of `caller`, whose scope may lift the risk checks.
+    pub fn submit_bid_as(&mut self, caller: Option<&Principal>, user_id: UserId, basket_id: BasketId, bid_type: BidType, price: f64, quantity: Option<f64>) -> Result<BidRecord, ApiError> {
+        self.user(user_id)?;
+        self.basket(basket_id)?;
+        self.check_rate(user_id, basket_id)?;
+        let bid = BidRecord { id: BidId(self.next_id()), user_id, basket_id, bid_type, price, quantity };
+        let checked = self.validate_bid(basket_id, price, quantity)
+            .and_then(|_| self.check_risk(caller, user_id, basket_id, price, quantity, None))
+            .and_then(|_| self.check_escrow(&bid));
+        if let Err(error) = checked {
+            self.commit(vec![ExchangeEvent::BidRejected { bid_id: bid.id, user_id, basket_id, reason: error.to_string() }])?;
+            return Err(error);
+        }
+        let extended = self.soft_close(&bid);
+        let controls = extended.as_ref().and_then(|event| self.controls_after(event));
+        self.persist(|repository| {
+            repository.save_bid(&bid)?;
+            controls.map_or(Ok(()), |controls| repository.save_controls(&controls))
+        })?;
+        self.commit(std::iter::once(ExchangeEvent::BidSubmitted { bid: bid.clone() }).chain(extended).collect())?;
+        metrics::counter!(BIDS_SUBMITTED).increment(1);
+        Ok(bid)
+    }
+
+    /// The move of the basket's close that `bid`, new or amended, earns under the
+    /// soft-close rule: it must arrive in the final window, beat every other resting bid's
+    /// price per unit of the basket, and find extensions left.
+    fn soft_close(&self, bid: &BidRecord) -> Option<ExchangeEvent> {
+        let soft_close = &self.auction.soft_close;
+        let closes_at = self.closes_at(bid.basket_id)?;
+        let extension = self.extensions.get(&bid.basket_id).copied().unwrap_or(0);
+        if !soft_close.enabled || extension >= soft_close.max_extensions {
+            return None;
+        }
+        if self.clock.now_millis() + soft_close.window_secs * 1000 < closes_at {
+            return None;
+        }
+        let unit_price = |bid: &BidRecord| bid.price / bid.quantity.unwrap_or(1.0);
+        if self.bids_for(bid.basket_id).into_iter().any(|resting| resting.id != bid.id && unit_price(resting) >= unit_price(bid)) {
+            return None;
+        }
+        let closes_at = closes_at + soft_close.extension_secs * 1000;
+        tracing::info!(basket_id = bid.basket_id.get(), bid_id = bid.id.get(), closes_at, extension = extension + 1, "soft close extended bidding");
+        Some(ExchangeEvent::BiddingExtended { basket_id: bid.basket_id, closes_at, extension: extension + 1 })
+    }
+
+    /// Changes a resting bid's price and quantity, keeping its id. Bids in a CCA's clock
+    /// rounds are fixed until it settles.
+    pub fn amend_bid(&mut self, bid_id: BidId, price: f64, quantity: Option<f64>) -> Result<BidRecord, ApiError> {
+        self.amend_bid_as(None, bid_id, price, quantity)
+    }
+
+    /// [`Exchange::amend_bid`] on behalf of `caller`, whose scope may lift the risk checks.
+    pub fn amend_bid_as(&mut self, caller: Option<&Principal>, bid_id: BidId, price: f64, quantity: Option<f64>) -> Result<BidRecord, ApiError> {
+        let bid = self.bid(bid_id)?.clone();
+        if self.final_rounds.contains(&bid.basket_id) {
+            return Err(ApiError::Conflict("bids cannot be amended while their auction runs"));
+        }
+        self.check_rate(bid.user_id, bid.basket_id)?;
+        self.validate_bid(bid.basket_id, price, quantity)?;
+        self.check_risk(caller, bid.user_id, bid.basket_id, price, quantity, Some(bid_id))?;
+        let amended = BidRecord { price, quantity, ..bid };
+        self.check_escrow(&amended)?;
+        let extended = self.soft_close(&amended);
+        let controls = extended.as_ref().and_then(|event| self.controls_after(event));
+        self.persist(|repository| {
+            repository.save_bid(&amended)?;
+            controls.map_or(Ok(()), |controls| repository.save_controls(&controls))
+        })?;
+        self.commit(std::iter::once(ExchangeEvent::BidAmended { bid: amended.clone() }).chain(extended).collect())?;
+        Ok(amended)
+    }
+
+    pub fn cancel_bid(&mut self, bid_id: BidId) -> Result<BidRecord, ApiError> {
+        let bid = self.bids.get(&bid_id).cloned().ok_or(ApiError::NotFound("bid"))?;
+        self.check_rate(bid.user_id, bid.basket_id)?;
+        self.persist(|repository| repository.delete_bid(bid_id))?;
+        self.commit(vec![ExchangeEvent::BidCancelled { bid_id }])?;
+        Ok(bid)
+    }
+
+    pub fn bid(&self, bid_id: BidId) -> Result<&BidRecord, ApiError> {
+        self.bids.get(&bid_id).ok_or(ApiError::NotFound("bid"))
+    }
+
+    pub fn bids_for(&self, basket_id: BasketId) -> Vec<&BidRecord> {
+        self.bids.values().filter(|bid| bid.basket_id == basket_id).collect()
+    }
+
+    /// Every cash movement clearing has booked.
+    pub fn ledger(&self) -> &Ledger {
+        &self.clearing.ledger
+    }
+
+    pub fn is_halted(&self, basket_id: BasketId) -> bool {
+        self.halted.contains(&basket_id)
+    }
+
+    /// When bidding on the basket closes, if an operator set a deadline.
+    pub fn closes_at(&self, basket_id: BasketId) -> Option<u64> {
+        self.deadlines.get(&basket_id).copied()
+    }
+
+    /// The time on the exchange's clock, in Unix milliseconds.
+    pub fn now_millis(&self) -> u64 {
+        self.clock.now_millis()
+    }
+
+    fn intervene(&mut self, action: AdminAction, operator: Option<UserId>) -> Result<AdminAction, ApiError> {
+        tracing::warn!(?action, operator = operator.map(UserId::get), "operator intervention");
+        let event = ExchangeEvent::Admin { action: action.clone(), operator };
+        if let Some(controls) = self.controls_after(&event) {
+            self.persist(|repository| repository.save_controls(&controls))?;
+        }
+        match action {
+            AdminAction::Halt { basket_id } => self.persist(|repository| repository.save_halted(basket_id, true))?,
+            AdminAction::Resume { basket_id } => self.persist(|repository| repository.save_halted(basket_id, false))?,
+            _ => {}
+        }
+        self.commit(vec![event])?;
+        Ok(action)
+    }
+
+    /// Stops bidding on and auctioning the basket until it is resumed. An auction of it in
+    /// flight is refused at settlement and its bids stay resting.
+    pub fn halt_auction(&mut self, basket_id: BasketId, operator: Option<UserId>) -> Result<AdminAction, ApiError> {
+        self.basket(basket_id)?;
+        if self.is_auctioned(basket_id) {
+            return Err(ApiError::Conflict("basket has already been auctioned"));
+        }
+        if self.halted.contains(&basket_id) {
+            return Err(ApiError::Conflict("auction of this basket is already halted"));
+        }
+        self.intervene(AdminAction::Halt { basket_id }, operator)
+    }
+
+    pub fn resume_auction(&mut self, basket_id: BasketId, operator: Option<UserId>) -> Result<AdminAction, ApiError> {
+        self.basket(basket_id)?;
+        if !self.halted.contains(&basket_id) {
+            return Err(ApiError::Conflict("auction of this basket is not halted"));
+        }
+        self.intervene(AdminAction::Resume { basket_id }, operator)
+    }
+
+    /// Moves the close of bidding on the basket `by` later than its current deadline, or
+    /// than now if it has none or it has passed. Returns the new deadline.
+    pub fn extend_bidding(&mut self, basket_id: BasketId, by: Duration, operator: Option<UserId>) -> Result<u64, ApiError> {
+        self.basket(basket_id)?;
+        if self.is_auctioned(basket_id) {
+            return Err(ApiError::Conflict("basket has already been auctioned"));
+        }
+        let from = self.closes_at(basket_id).unwrap_or(0).max(self.clock.now_millis());
+        let closes_at = from + by.as_millis() as u64;
+        self.intervene(AdminAction::Extend { basket_id, closes_at }, operator)?;
+        Ok(closes_at)
+    }
+
+    /// Cancels every resting bid on the basket, releasing their escrow, and an auction of
+    /// it in flight is refused at settlement. The basket can be bid on and auctioned again.
+    pub fn cancel_auction(&mut self, basket_id: BasketId, operator: Option<UserId>) -> Result<Vec<BidRecord>, ApiError> {
+        self.basket(basket_id)?;
+        if self.is_auctioned(basket_id) {
+            return Err(ApiError::Conflict("basket has already been auctioned"));
+        }
+        let cancelled: Vec<BidRecord> = self.bids_for(basket_id).into_iter().cloned().collect();
+        let cancelled_bids: Vec<BidId> = cancelled.iter().map(|bid| bid.id).collect();
+        self.persist(|repository| cancelled_bids.iter().try_for_each(|bid_id| repository.delete_bid(*bid_id)))?;
+        self.intervene(AdminAction::Cancel { basket_id, cancelled_bids }, operator)?;
+        Ok(cancelled)
+    }
+
+    /// Expires every resting bid submitted or amended more than `max_age` ago.
+    pub fn expire_stale_bids(&mut self, max_age: Duration, operator: Option<UserId>) -> Result<Vec<BidRecord>, ApiError> {
+        let before = self.clock.now_millis().saturating_sub(max_age.as_millis() as u64);
+        let expired: Vec<BidRecord> = self.bids.values()
+            .filter(|bid| self.orders.last_change(bid.id).is_none_or(|at| at < before))
+            .cloned()
+            .collect();
+        if expired.is_empty() {
+            return Ok(expired);
+        }
+        let expired_bids: Vec<BidId> = expired.iter().map(|bid| bid.id).collect();
+        self.persist(|repository| expired_bids.iter().try_for_each(|bid_id| repository.delete_bid(*bid_id)))?;
+        self.intervene(AdminAction::ExpireStaleBids { before, expired_bids }, operator)?;
+        Ok(expired)
+    }
+
+    /// Every bid's lifecycle, including bids no longer resting.
+    pub fn orders(&self) -> &OrderManager {
+        &self.orders
+    }
+
+    pub fn outcome(&self, auction_id: AuctionId) -> Result<&AuctionOutcome, ApiError> {
+        self.outcomes.get(&auction_id).ok_or(ApiError::NotFound("auction"))
+    }
+
+    /// `record` as a model bid, holding its user as currently funded.
+    fn model_bid(&self, record: &BidRecord) -> Option<Bid> {
+        let user = Arc::new(self.users.get(&record.user_id)?.clone());
+        Some(Bid::new(user, record.basket_id, record.bid_type.clone(), record.price, record.quantity))
+    }
+
+    /// Model bids for an auction of `basket_id`, each holding its user funded with the
+    /// escrow their bids on the basket lock, so no auction can spend what another reserved.
+    fn model_bids(&self, basket_id: BasketId) -> Vec<(BidId, Bid)> {
+        self.bids_for(basket_id).into_iter()
+            .filter_map(|record| {
+                let mut bid = self.model_bid(record)?;
+                Arc::make_mut(&mut bid.user).balance = self.escrow.locked(record.user_id, basket_id);
+                Some((record.id, bid))
+            })
+            .collect()
+    }
+
+    /// Auctions a basket among its resting bids and settles the winners. The bids are
+    /// consumed; if clearing refuses the outcome nothing changes.
+    pub fn run_auction(&mut self, request: &AuctionRequest) -> Result<AuctionOutcome, ApiError> {
+        let _span = tracing::info_span!("auction", basket_id = request.basket_id.get(), mechanism = request.mechanism.name()).entered();
+        let pending = self.open_auction(request)?;
+        self.settle_auction(pending.solve())
+    }
+
+    /// Takes the basket's resting bids for an auction that is solved apart from the
+    /// exchange, so a long winner determination does not hold it up; see
+    /// [`Exchange::settle_auction`].
+    pub fn open_auction(&mut self, request: &AuctionRequest) -> Result<PendingAuction, ApiError> {
+        let basket = self.basket(request.basket_id)?.clone();
+        if self.is_auctioned(basket.id) {
+            return Err(ApiError::Conflict("basket has already been auctioned"));
+        }
+        if self.halted.contains(&basket.id) {
+            return Err(ApiError::Conflict("auction of this basket is halted"));
+        }
+        let entries = self.model_bids(basket.id);
+        let bid_ids: Vec<BidId> = entries.iter().map(|(id, _)| *id).collect();
+        let bids: Vec<Bid> = entries.into_iter().map(|(_, bid)| bid).collect();
+        metrics::histogram!(AUCTION_BIDS, "mechanism" => request.mechanism.name()).record(bids.len() as f64);
+        let recorder = Arc::new(AuctionRecorder::default());
+        let mut observers = self.observers.clone();
+        observers.add(recorder.clone());
+        let config = AuctionConfig {
+            price_increment: request.price_increment.unwrap_or(self.auction.price_increment),
+            max_rounds: request.max_rounds.unwrap_or(self.auction.max_rounds),
+            ..self.auction.clone()
+        };
+        if request.mechanism == Mechanism::Cca {
+            self.final_rounds.insert(basket.id);
+        }
+        Ok(PendingAuction {
+            mechanism: request.mechanism,
+            settlement: request.settlement.unwrap_or(self.settlement.mode),
+            scoring: self.scoring.clone().filter(|_| request.mechanism == Mechanism::Xor).map(|rule| (rule, self.profiles.clone())),
+            fee_rate: self.fees.rate_for(request.mechanism.name()),
+            interruptions: self.interruptions.get(&basket.id).copied().unwrap_or(0),
+            basket,
+            bid_ids,
+            bids,
+            config,
+            observers,
+            recorder,
+            rng: self.rng.as_mut().map(SimRng::fork),
+            _open: self.open_auctions.clone(),
+        })
+    }
+
+    /// Settles a solved auction. Winners pay from the escrow their bids locked, which no
+    /// other bid could spend, and the losers' escrow is released; the auction is refused if
+    /// a winning bid was cancelled or rests unfunded, the basket auctioned, or the auction
+    /// halted or cancelled by an operator in the meantime.
+    pub fn settle_auction(&mut self, solved: SolvedAuction) -> Result<AuctionOutcome, ApiError> {
+        let SolvedAuction { pending, winners, allocation, charged, scores, fills } = solved;
+        let PendingAuction { mechanism, settlement, basket, bid_ids, bids, recorder, interruptions, .. } = pending;
+        self.final_rounds.remove(&basket.id);
+        if self.is_auctioned(basket.id) {
+            return Err(ApiError::Conflict("basket has already been auctioned"));
+        }
+        if self.interruptions.get(&basket.id).copied().unwrap_or(0) != interruptions {
+            return Err(ApiError::Conflict("auction was halted or cancelled by an operator"));
+        }
+        if winners.iter().any(|index| !self.bids.contains_key(&bid_ids[*index])) {
+            return Err(ApiError::Conflict("a winning bid was cancelled during the auction"));
+        }
+
+        let winning_bids: Vec<Bid> = winners.iter().zip(&charged)
+            .map(|(index, price)| {
+                let user = self.users.get(&bids[*index].user.id).cloned().map(Arc::new).unwrap_or_else(|| bids[*index].user.clone());
+                Bid { price: *price, user, ..bids[*index].clone() }
+            })
+            .collect();
+        if let Some(bid) = winning_bids.iter().find(|bid| self.escrow.locked(bid.user.id, bid.basket_id) <= 0.0) {
+            return Err(ApiError::Rejected(ClearingError::NotEscrowed { user_id: bid.user.id, basket_id: bid.basket_id }));
+        }
+        let metadata = AuctionMetadata::at(basket.id, mechanism.name(), self.clock.as_ref());
+        let fee_rate = self.fees.rate_for(mechanism.name());
+        let posted = self.clearing.ledger.entries().len();
+        let settlement = match settlement {
+            SettlementMode::Physical => self.clearing.clear_with_report(metadata, winning_bids, allocation.clone(), fee_rate)?,
+            SettlementMode::Cash => {
+                let marks = self.fixing_marks(&basket);
+                self.clearing.clear_cash_settled(metadata, winning_bids, allocation.clone(), &marks, fee_rate)?
+            }
+        };
+
+        let mut payments = HashMap::new();
+        for (index, price) in winners.iter().zip(&charged) {
+            *payments.entry(bids[*index].user.id).or_insert(0.0) += price;
+        }
+        let outcome = AuctionOutcome {
+            auction_id: settlement.report.metadata.auction_id,
+            basket_id: basket.id,
+            mechanism,
+            winning_bids: winners.iter().map(|index| bid_ids[*index]).collect(),
+            allocation,
+            payments,
+            report: settlement.report,
+            fills,
+        };
+        let settled_users: Vec<User> = settlement.users.values()
+            .filter_map(|user| Some(User { balance: user.balance, ..self.users.get(&user.id)?.clone() }))
+            .collect();
+        // Bids that arrived while the auction was solved are consumed along with the rest
+        let consumed: Vec<BidId> = self.bids_for(basket.id).iter().map(|bid| bid.id).collect();
+        let entries = &self.clearing.ledger.entries()[posted..];
+        self.persist(|repository| repository.save_auction(&outcome, &settled_users, &consumed, entries))?;
+
+        let mut events = recorder.take();
+        if let Some((rule, scores)) = scores {
+            events.push(ExchangeEvent::BidsScored { basket_id: basket.id, auction_id: outcome.auction_id, rule, scores });
+        }
+        events.push(ExchangeEvent::WinnersSelected {
+            basket_id: basket.id,
+            auction_id: outcome.auction_id,
+            winning_bids: outcome.winning_bids.clone(),
+            payments: outcome.payments.clone(),
+        });
+        events.push(ExchangeEvent::Settled { outcome: outcome.clone(), users: settled_users });
+        self.commit(events)?;
+        let welfare: f64 = outcome.payments.values().sum();
+        tracing::info!(auction_id = outcome.auction_id.get(), winners = outcome.winning_bids.len(), welfare, "auction settled");
+        self.observers.on_event(&AuctionEvent::Winners { basket_id: basket.id, auction_id: outcome.auction_id, payments: outcome.payments.clone() });
+        self.observers.on_event(&AuctionEvent::Settled { report: outcome.report.clone() });
+        Ok(outcome)
+    }
+}
+
+
+/// An auction opened by [`Exchange::open_auction`]: its bids as they stood then, with
+/// the users funded by their escrow on the basket.
+pub struct PendingAuction {
+    mechanism: Mechanism,
+    settlement: SettlementMode,
+    scoring: Option<(Arc<dyn ScoringRule>, HashMap<UserId, BidderProfile>)>,
+    /// The fee on the mechanism, which partial fills leave room for in a winner's budget.
+    fee_rate: f64,
+    /// The basket's interruption count when the auction opened.
+    interruptions: u64,
+    basket: Basket,
+    bid_ids: Vec<BidId>,
+    bids: Vec<Bid>,
+    config: AuctionConfig,
+    observers: AuctionObservers,
+    recorder: Arc<AuctionRecorder>,
+    rng: Option<SimRng>,
+    /// Refuses the exchange's snapshots until the auction is settled or dropped.
+    _open: Arc<()>,
+}
+
+impl fmt::Debug for PendingAuction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PendingAuction")
+            .field("basket_id", &self.basket.id)
+            .field("mechanism", &self.mechanism)
+            .field("bids", &self.bid_ids)
+            .finish()
+    }
+}
+
+/// Winners of a [`PendingAuction`], waiting for [`Exchange::settle_auction`].
+#[derive(Debug)]
+pub struct SolvedAuction {
+    pending: PendingAuction,
+    /// Index of each winning bid
+    winners: Vec<usize>,
+    allocation: HashMap<UserId, Vec<AssetInfo>>,
+    /// Price charged per winning bid
+    charged: Vec<f64>,
+    /// The scoring rule's name and every bid's score, when one ranked the bids.
+    scores: Option<(String, Vec<ScoredBid>)>,
+    fills: Vec<FilledBid>,
+}
+
+impl PendingAuction {
+    pub fn basket_id(&self) -> BasketId {
+        self.basket.id
+    }
+
+    pub fn mechanism(&self) -> Mechanism {
+        self.mechanism
+    }
+
+    /// Opens the clock at `marks`, the latest price of each asset by base, instead of the
+    /// prices the basket was listed at. Assets without a mark keep their listed price.
+    pub fn reprice(&mut self, marks: &HashMap<String, f64>) {
+        for info in &mut self.basket.assets {
+            if let Some(mark) = marks.get(&info.asset.base) {
+                info.price = *mark;
+            }
+        }
+    }
+
+    /// Winner determination, which needs nothing from the exchange.
+    pub fn solve(mut self) -> SolvedAuction {
+        let (bids, basket) = (&self.bids, &self.basket);
+        let mut scores = None;
+        let mut fills = Vec::new();
+        let (winners, allocation, charged): (Vec<usize>, HashMap<UserId, Vec<AssetInfo>>, Vec<f64>) = match self.mechanism {
+            Mechanism::Xor if self.config.partial_fills && self.scoring.is_none() => {
+                let (cascade, allocation) = XorAuction::evaluate_cascade(bids, basket, self.fee_rate);
+                let winners: Vec<usize> = cascade.iter().map(|(bid, _)| position(bids, bid)).collect();
+                let charged = cascade.iter().map(|(_, fill)| fill.payment).collect();
+                fills = winners.iter().zip(cascade)
+                    .map(|(index, (_, fill))| FilledBid { bid_id: self.bid_ids[*index], fill })
+                    .collect();
+                (winners, allocation, charged)
+            }
+            Mechanism::Xor => {
+                let highest = match (&self.scoring, self.rng.as_mut()) {
+                    (Some((rule, profiles)), _) => {
+                        let scored = scoring::score_bids(bids, rule.as_ref(), profiles).into_iter()
+                            .zip(&self.bid_ids)
+                            .map(|(score, bid_id)| ScoredBid { bid_id: *bid_id, score })
+                            .collect();
+                        scores = Some((rule.name().to_string(), scored));
+                        XorAuction::evaluate_scored_bids(bids, basket, rule.as_ref(), profiles).map(|(winner, allocation, _)| (winner, allocation))
+                    }
+                    (None, Some(rng)) => XorAuction::evaluate_partial_bids_seeded(bids, basket, rng),
+                    (None, None) => XorAuction::evaluate_partial_bids(bids, basket),
+                };
+                match highest {
+                    Some((winner, allocation)) => {
+                        let index = position(bids, winner);
+                        (vec![index], allocation, vec![winner.price])
+                    }
+                    None => (Vec::new(), HashMap::new(), Vec::new()),
+                }
+            }
+            Mechanism::Or => {
+                let (winners, allocation) = OrAuction::evaluate_bids(bids, basket);
+                (winners.iter().map(|bid| position(bids, bid)).collect(), allocation, winners.iter().map(|bid| bid.price).collect())
+            }
+            Mechanism::Vcg => {
+                let (winners, allocation, payments) = VCGAuction::outcome(bids, basket);
+                let charged = winners.iter().map(|bid| payments.get(&bid.user.id).copied().unwrap_or(bid.price)).collect();
+                (winners.iter().map(|bid| position(bids, bid)).collect(), allocation, charged)
+            }
+            Mechanism::Cca => {
+                let initial_prices = basket.assets.iter().map(|info| (info.asset.base.as_str(), info.price)).collect();
+                let (standing, allocation) = CombiClockAuction::configured_outcome(bids, basket, initial_prices, &self.config, &self.observers);
+                let winners: Vec<usize> = standing.iter()
+                    .filter(|bid| allocation.contains_key(&bid.user.id))
+                    .filter_map(|bid| bids.iter().position(|candidate| candidate.user.id == bid.user.id && candidate.price == bid.price))
+                    .collect();
+                let charged = winners.iter().map(|index| bids[*index].price).collect();
+                (winners, allocation, charged)
+            }
+        };
+        SolvedAuction { pending: self, winners, allocation, charged, scores, fills }
+    }
+}
+
+/// Escrow that locks a bid's price plus the highest fee any mechanism may charge on it.
+fn escrow_for(fees: &FeeSchedule) -> Escrow {
+    let fee = fees.mechanisms.values().copied().fold(fees.rate, f64::max);
+    Escrow::with_engine(Arc::new(RateMargin { rate: 1.0 + fee }))
+}
+
+/// Index of `bid` in `bids`; the auctions return references into the slice they are given.
+fn position(bids: &[Bid], bid: &Bid) -> usize {
+    bids.iter().position(|candidate| std::ptr::eq(candidate, bid)).expect("winning bids come from the bid list")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use auction::error::ClearingError;
+    use auction::corporate_actions::{CorporateActionKind, EntitledParty};
+    use auction::sim::SimClock;
+    use model::model::Asset;
+
+    fn exchange() -> (Exchange, UserId, UserId, BasketId) {
+        let mut exchange = Exchange::new();
+        let alice = exchange.register_user("Alice", 100_000.0).unwrap().id;
+        let bob = exchange.register_user("Bob", 100_000.0).unwrap().id;
+        let basket = exchange.create_basket(vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60_000.0)]).unwrap().id;
+        (exchange, alice, bob, basket)
+    }
+
+    #[test]
+    fn test_xor_auction_settles_the_highest_bid() {
+        let (mut exchange, alice, bob, basket) = exchange();
+        exchange.submit_bid(alice, basket, BidType::XOR, 61_000.0, None).unwrap();
+        let losing = exchange.submit_bid(bob, basket, BidType::XOR, 59_000.0, None).unwrap();
+        let cancelled = exchange.submit_bid(bob, basket, BidType::XOR, 70_000.0, None).unwrap();
+        exchange.cancel_bid(cancelled.id).unwrap();
+        assert_eq!(exchange.cancel_bid(cancelled.id), Err(ApiError::NotFound("bid")));
+
+        let outcome = exchange.run_auction(&AuctionRequest { basket_id: basket, mechanism: Mechanism::Xor, price_increment: Some(0.05), max_rounds: Some(20), settlement: None }).unwrap();
+        assert_eq!(outcome.payments, HashMap::from([(alice, 61_000.0)]));
+        assert!(!outcome.winning_bids.contains(&losing.id));
+        assert_eq!(exchange.user(alice).unwrap().balance, 39_000.0);
+        assert_eq!(exchange.user(bob).unwrap().balance, 100_000.0);
+        assert_eq!(exchange.outcome(outcome.auction_id).unwrap().winning_bids, outcome.winning_bids);
+        assert!(exchange.bids_for(basket).is_empty());
+
+        let again = AuctionRequest { basket_id: basket, mechanism: Mechanism::Or, price_increment: Some(0.05), max_rounds: Some(20), settlement: None };
+        assert!(matches!(exchange.run_auction(&again), Err(ApiError::Conflict(_))));
+    }
+
+    #[test]
+    fn test_config_sets_fees_and_clock_defaults() {
+        let config = Config::from_toml("[auction]\nmax_rounds = 1\n\n[fees]\nrate = 0.01\nmechanisms = { or = 0.0 }").unwrap();
+        let (exchange, alice, bob, basket) = exchange();
+        let mut exchange = exchange.with_config(&config);
+        exchange.submit_bid(alice, basket, BidType::XOR, 61_000.0, None).unwrap();
+        exchange.run_auction(&AuctionRequest::new(basket, Mechanism::Xor)).unwrap();
+        assert_eq!(exchange.user(alice).unwrap().balance, 100_000.0 - 61_000.0 * 1.01);
+
+        let other = exchange.create_basket(vec![AssetInfo::new(Asset::new("ETH", "USD"), 1.0, 3_000.0)]).unwrap().id;
+        exchange.submit_bid(bob, other, BidType::OR, 3_000.0, Some(1.0)).unwrap();
+        let outcome = exchange.run_auction(&AuctionRequest::new(other, Mechanism::Or)).unwrap();
+        assert_eq!(outcome.report.settlements[0].fee, 0.0);
+
+        let config = Config::from_toml("[settlement.accruals]\ncoupon = \"seller\"").unwrap();
+        let exchange = Exchange::new().with_config(&config);
+        assert_eq!(exchange.clearing.accruals.entitled(CorporateActionKind::Coupon), EntitledParty::Seller);
+    }
+
+    #[test]
+    fn test_seeded_runs_replay_exactly() {
+        let run = |seed| {
+            let clock = SimClock::new(1_700_000_000_000);
+            let (exchange, _, _, basket) = exchange();
+            let mut exchange = exchange.with_clock(Arc::new(clock.clone())).with_seed(seed);
+            for bidder in 0..5 {
+                let user = exchange.register_user(&format!("Bidder {}", bidder), 100_000.0).unwrap().id;
+                exchange.submit_bid(user, basket, BidType::XOR, 61_000.0, None).unwrap();
+            }
+            clock.advance(std::time::Duration::from_secs(60));
+            let outcome = exchange.run_auction(&AuctionRequest::new(basket, Mechanism::Xor)).unwrap();
+            (outcome.winning_bids, outcome.report.metadata.timestamp)
+        };
+        let (winners, timestamp) = run(11);
+        assert_eq!(timestamp, 1_700_000_060);
+        assert_eq!(run(11).0, winners);
+        assert!((0..16).any(|seed| run(seed).0 != winners));
+    }
+
+    #[test]
+    fn test_cash_settlement_pays_the_difference_to_the_fixing() {
+        let config = Config::from_toml("[settlement]\nfixing_time = \"16:00\"").unwrap();
+        // 13:53 UTC
+        let clock = SimClock::new(1_699_970_000_000);
+        let (exchange, alice, bob, basket) = exchange();
+        let mut exchange = exchange.with_clock(Arc::new(clock.clone())).with_config(&config);
+        exchange.update_mark("BTC", 62_000.0);
+        clock.advance(Duration::from_secs(3 * 3600));
+        exchange.update_mark("BTC", 64_000.0);
+        assert_eq!(exchange.fixing_marks(exchange.basket(basket).unwrap()), HashMap::from([("BTC".to_string(), 62_000.0)]));
+
+        exchange.submit_bid(alice, basket, BidType::XOR, 61_000.0, None).unwrap();
+        exchange.submit_bid(bob, basket, BidType::XOR, 59_000.0, None).unwrap();
+        let request = AuctionRequest { settlement: Some(SettlementMode::Cash), ..AuctionRequest::new(basket, Mechanism::Xor) };
+        let outcome = exchange.run_auction(&request).unwrap();
+        assert_eq!(outcome.report.mode, SettlementMode::Cash);
+        assert_eq!(outcome.report.fixing_marks, BTreeMap::from([("BTC".to_string(), 62_000.0)]));
+        assert_eq!(exchange.user(alice).unwrap().balance, 101_000.0);
+        assert!(exchange.exposure(alice, None).positions.is_empty());
+
+        let other = exchange.create_basket(vec![AssetInfo::new(Asset::new("ETH", "USD"), 1.0, 3_000.0)]).unwrap().id;
+        exchange.submit_bid(bob, other, BidType::XOR, 3_000.0, None).unwrap();
+        let unmarked = exchange.run_auction(&AuctionRequest { settlement: Some(SettlementMode::Cash), ..AuctionRequest::new(other, Mechanism::Xor) });
+        assert!(matches!(unmarked, Err(ApiError::Rejected(ClearingError::NoMark(_)))));
+    }
+
+    #[test]
+    fn test_scoring_rule_picks_and_journals_xor_winners() {
+        let journal = Arc::new(crate::event_store::MemoryEventStore::new());
+        let (exchange, alice, bob, basket) = exchange();
+        let mut exchange = exchange.with_scoring(Arc::new(scoring::RiskAdjustedScore::default())).with_journal(journal.clone());
+        assert!(exchange.set_bidder_profile(alice, BidderProfile { credit_score: 1.5, settlement_days: 0.0 }).is_err());
+        exchange.set_bidder_profile(alice, BidderProfile { credit_score: 0.9, settlement_days: 2.0 }).unwrap();
+
+        exchange.submit_bid(alice, basket, BidType::XOR, 61_000.0, None).unwrap();
+        let bob_bid = exchange.submit_bid(bob, basket, BidType::XOR, 59_000.0, None).unwrap();
+        let outcome = exchange.run_auction(&AuctionRequest::new(basket, Mechanism::Xor)).unwrap();
+        assert_eq!(outcome.winning_bids, vec![bob_bid.id]);
+
+        let scored = journal.read_from(1).unwrap().into_iter()
+            .find_map(|stored| match stored.event {
+                ExchangeEvent::BidsScored { rule, scores, .. } => Some((rule, scores)),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(scored.0, "risk_adjusted");
+        let alice_score = scored.1.iter().find(|scored| scored.score.user_id == alice).unwrap();
+        assert_eq!((alice_score.score.credit_score, alice_score.score.settlement_days), (0.9, 2.0));
+        assert!(alice_score.score.score < 59_000.0);
+    }
+
+    #[test]
+    fn test_partial_fills_cascade_past_a_budget_bound_xor_winner() {
+        let config = Config::from_toml("[auction]\npartial_fills = true").unwrap();
+        let (exchange, _, bob, basket) = exchange();
+        let mut exchange = exchange.with_config(&config);
+        let carol = exchange.register_user("Carol", 30_500.0).unwrap().id;
+        // With partial fills a bid rests on whatever its bidder has available
+        let carol_bid = exchange.submit_bid(carol, basket, BidType::XOR, 61_000.0, None).unwrap();
+        assert_eq!(exchange.available_balance(carol).unwrap(), 0.0);
+        let other = exchange.create_basket(vec![AssetInfo::new(Asset::new("ETH", "USD"), 1.0, 3_000.0)]).unwrap().id;
+        assert!(matches!(exchange.submit_bid(carol, other, BidType::XOR, 3_000.0, None), Err(ApiError::Rejected(_))));
+        let bob_bid = exchange.submit_bid(bob, basket, BidType::XOR, 59_000.0, None).unwrap();
+
+        let outcome = exchange.run_auction(&AuctionRequest::new(basket, Mechanism::Xor)).unwrap();
+        assert_eq!(outcome.winning_bids, vec![carol_bid.id, bob_bid.id]);
+        let [carol_fill, bob_fill] = [&outcome.fills[0], &outcome.fills[1]];
+        assert_eq!((carol_fill.bid_id, carol_fill.fill.budget_bound, bob_fill.fill.budget_bound), (carol_bid.id, true, false));
+        assert!(carol_fill.fill.share < 0.5 + 1e-12 && (carol_fill.fill.share + bob_fill.fill.share - 1.0).abs() < 1e-12);
+        assert!((outcome.payments[&bob] - 59_000.0 * bob_fill.fill.share).abs() < 1e-6);
+        assert!((outcome.allocation[&carol][0].quantity - carol_fill.fill.share).abs() < 1e-12);
+        assert!(exchange.user(carol).unwrap().balance >= 0.0);
+
+        // The fills survive the binary codec
+        let decoded = codec::decode_snapshot(&exchange.snapshot_as(Encoding::Protobuf).unwrap()).unwrap();
+        assert_eq!(decoded.outcomes[0].fills, outcome.fills);
+    }
+
+    #[test]
+    fn test_rate_limits_tighten_in_final_rounds() {
+        let config = Config::from_toml("[rate_limits]\nenabled = true\nper_user = { per_second = 1.0, burst = 3 }\nfinal_rounds = { per_second = 0.5, burst = 1 }").unwrap();
+        let clock = SimClock::new(1_700_000_000_000);
+        let (exchange, alice, bob, basket) = exchange();
+        let mut exchange = exchange.with_clock(Arc::new(clock.clone())).with_config(&config);
+        for _ in 0..3 {
+            exchange.submit_bid(alice, basket, BidType::OR, 10_000.0, Some(0.1)).unwrap();
+        }
+        let limited = exchange.submit_bid(alice, basket, BidType::OR, 10_000.0, Some(0.1));
+        assert_eq!(limited, Err(ApiError::TooManyRequests("too many bids from this user, slow down")));
+        exchange.submit_bid(bob, basket, BidType::OR, 10_000.0, Some(0.1)).unwrap();
+        clock.advance(std::time::Duration::from_secs(1));
+        exchange.submit_bid(alice, basket, BidType::OR, 10_000.0, Some(0.1)).unwrap();
+
+        let pending = exchange.open_auction(&AuctionRequest::new(basket, Mechanism::Cca)).unwrap();
+        assert_eq!(exchange.phase(basket), AuctionPhase::FinalRounds);
+        let late = exchange.submit_bid(bob, basket, BidType::OR, 10_000.0, Some(0.1)).unwrap();
+        assert!(matches!(exchange.cancel_bid(late.id), Err(ApiError::TooManyRequests(_))));
+        exchange.settle_auction(pending.solve()).unwrap();
+        assert_eq!(exchange.phase(basket), AuctionPhase::Open);
+    }
+
+    #[test]
+    fn test_risk_checks_count_pending_allocations_and_yield_to_admins() {
+        let config = Config::from_toml("[risk]\nenabled = true\nposition_limits = { BTC = 1.0 }\nprice_collar = 0.2").unwrap();
+        let (exchange, alice, _, basket) = exchange();
+        let mut exchange = exchange.with_config(&config);
+        exchange.submit_bid(alice, basket, BidType::OR, 27_000.0, Some(0.5)).unwrap();
+        exchange.update_mark("BTC", 50_000.0);
+        let collared = exchange.submit_bid(alice, basket, BidType::OR, 31_000.0, Some(0.5));
+        assert!(matches!(collared, Err(ApiError::RiskLimit(_))));
+        exchange.submit_bid(alice, basket, BidType::OR, 26_000.0, Some(0.5)).unwrap();
+        // Two resting halves already make the whole position
+        assert!(exchange.submit_bid(alice, basket, BidType::OR, 5_000.0, Some(0.1)).is_err());
+        let admin = Principal::admin();
+        exchange.submit_bid_as(Some(&admin), alice, basket, BidType::OR, 5_000.0, Some(0.1)).unwrap();
+        let rejected = exchange.orders().for_user(alice).into_iter().filter(|order| order.state == auction::observer::OrderState::Rejected).count();
+        assert_eq!(rejected, 2);
+
+        // Once the auction settles its allocation is delivered and no longer at stake
+        let pending = exchange.open_auction(&AuctionRequest::new(basket, Mechanism::Or)).unwrap();
+        assert!(exchange.exposure(alice, None).positions["BTC"] > 1.0);
+        exchange.settle_auction(pending.solve()).unwrap();
+        assert!(exchange.exposure(alice, None).positions.is_empty());
+        let other = exchange.create_basket(vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 50_000.0)]).unwrap().id;
+        exchange.submit_bid(alice, other, BidType::OR, 41_000.0, None).unwrap();
+    }
+
+    #[test]
+    fn test_operators_halt_extend_cancel_and_expire() {
+        let clock = SimClock::new(1_700_000_000_000);
+        let journal = Arc::new(crate::event_store::MemoryEventStore::new());
+        let (exchange, alice, bob, basket) = exchange();
+        let mut exchange = exchange.with_clock(Arc::new(clock.clone())).with_journal(journal.clone());
+        exchange.submit_bid(alice, basket, BidType::XOR, 61_000.0, None).unwrap();
+
+        // A halted auction in flight is not settled and its bids keep resting
+        let pending = exchange.open_auction(&AuctionRequest::new(basket, Mechanism::Cca)).unwrap();
+        exchange.halt_auction(basket, Some(UserId(99))).unwrap();
+        assert!(matches!(exchange.submit_bid(bob, basket, BidType::XOR, 62_000.0, None), Err(ApiError::Conflict(_))));
+        assert!(exchange.settle_auction(pending.solve()).is_err());
+        assert!(exchange.open_auction(&AuctionRequest::new(basket, Mechanism::Xor)).is_err());
+        exchange.resume_auction(basket, Some(UserId(99))).unwrap();
+        assert_eq!(exchange.bids_for(basket).len(), 1);
+
+        let closes_at = exchange.extend_bidding(basket, Duration::from_secs(60), None).unwrap();
+        assert_eq!(exchange.extend_bidding(basket, Duration::from_secs(30), None).unwrap(), closes_at + 30_000);
+        clock.advance(Duration::from_secs(60));
+        let fresh = exchange.submit_bid(bob, basket, BidType::XOR, 62_000.0, None).unwrap();
+        assert_eq!(exchange.expire_stale_bids(Duration::from_secs(30), None).unwrap().len(), 1);
+        assert_eq!(exchange.bids_for(basket), vec![&fresh]);
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(exchange.submit_bid(bob, basket, BidType::XOR, 1.0, None), Err(ApiError::Conflict("bidding on this basket has closed")));
+
+        let pending = exchange.open_auction(&AuctionRequest::new(basket, Mechanism::Xor)).unwrap();
+        assert_eq!(exchange.cancel_auction(basket, None).unwrap(), vec![fresh.clone()]);
+        assert!(exchange.settle_auction(pending.solve()).is_err());
+        assert_eq!(exchange.orders().order(fresh.id).unwrap().state, auction::observer::OrderState::Cancelled);
+
+        let actions: Vec<AdminAction> = journal.read_from(1).unwrap().into_iter()
+            .filter_map(|stored| match stored.event {
+                ExchangeEvent::Admin { action, .. } => Some(action),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(actions.len(), 6);
+        assert_eq!(actions[0], AdminAction::Halt { basket_id: basket });
+        assert_eq!(actions[5], AdminAction::Cancel { basket_id: basket, cancelled_bids: vec![fresh.id] });
+    }
+
+    #[test]
+    fn test_soft_close_extends_for_late_leading_bids() {
+        let clock = SimClock::new(1_700_000_000_000);
+        let journal = Arc::new(crate::event_store::MemoryEventStore::new());
+        let config = Config::from_toml("[auction.soft_close]\nenabled = true\nwindow_secs = 60\nextension_secs = 30\nmax_extensions = 2").unwrap();
+        let (exchange, alice, bob, basket) = exchange();
+        let mut exchange = exchange.with_clock(Arc::new(clock.clone())).with_config(&config).with_journal(journal.clone());
+        let closes_at = exchange.extend_bidding(basket, Duration::from_secs(300), None).unwrap();
+        let early = exchange.submit_bid(alice, basket, BidType::XOR, 61_000.0, None).unwrap();
+        assert_eq!(exchange.closes_at(basket), Some(closes_at));
+
+        // Only a bid that takes the lead in the last minute moves the close
+        clock.advance(Duration::from_secs(250));
+        exchange.submit_bid(bob, basket, BidType::XOR, 62_000.0, None).unwrap();
+        assert_eq!(exchange.closes_at(basket), Some(closes_at + 30_000));
+        exchange.submit_bid(alice, basket, BidType::XOR, 30_000.0, Some(0.5)).unwrap();
+        assert_eq!(exchange.closes_at(basket), Some(closes_at + 30_000));
+        clock.advance(Duration::from_secs(60));
+        exchange.amend_bid(early.id, 63_000.0, None).unwrap();
+        assert_eq!(exchange.closes_at(basket), Some(closes_at + 60_000));
+
+        // Extensions run out
+        clock.advance(Duration::from_secs(20));
+        exchange.submit_bid(bob, basket, BidType::XOR, 64_000.0, None).unwrap();
+        assert_eq!(exchange.closes_at(basket), Some(closes_at + 60_000));
+        let replayed = crate::event_store::replay(&journal.read_from(1).unwrap(), None);
+        assert_eq!(replayed.closes_at(basket), Some(closes_at + 60_000));
+    }
+
+    #[test]
+    fn test_resting_bids_lock_escrow_until_they_leave_the_book() {
+        let clock = SimClock::new(1_700_000_000_000);
+        let (exchange, alice, bob, basket) = exchange();
+        let mut exchange = exchange.with_clock(Arc::new(clock.clone()));
+        let other = exchange.create_basket(vec![AssetInfo::new(Asset::new("ETH", "USD"), 1.0, 3_000.0)]).unwrap().id;
+        // At most one of a user's XOR bids on a basket wins, so only the dearest is locked
+        exchange.submit_bid(alice, basket, BidType::XOR, 61_000.0, None).unwrap();
+        exchange.submit_bid(alice, basket, BidType::XOR, 30_000.0, Some(0.5)).unwrap();
+        assert_eq!(exchange.available_balance(alice).unwrap(), 39_000.0);
+        assert!(matches!(exchange.submit_bid(alice, other, BidType::XOR, 40_000.0, None), Err(ApiError::Rejected(ClearingError::InsufficientFunds { .. }))));
+
+        exchange.cancel_auction(basket, None).unwrap();
+        assert_eq!(exchange.available_balance(alice).unwrap(), 100_000.0);
+        exchange.submit_bid(alice, other, BidType::XOR, 40_000.0, None).unwrap();
+        clock.advance(Duration::from_secs(3_600));
+        exchange.expire_stale_bids(Duration::from_secs(60), None).unwrap();
+        assert_eq!(exchange.available_balance(alice).unwrap(), 100_000.0);
+
+        // The winner pays from its lock and the loser's is released
+        exchange.submit_bid(alice, basket, BidType::XOR, 61_000.0, None).unwrap();
+        exchange.submit_bid(bob, basket, BidType::XOR, 59_000.0, None).unwrap();
+        assert_eq!(exchange.available_balance(bob).unwrap(), 41_000.0);
+        exchange.run_auction(&AuctionRequest::new(basket, Mechanism::Xor)).unwrap();
+        assert_eq!(exchange.available_balance(alice).unwrap(), 39_000.0);
+        assert_eq!(exchange.available_balance(bob).unwrap(), 100_000.0);
+    }
+
+    #[test]
+    fn test_invalid_requests_are_refused() {
+        let (mut exchange, alice, bob, basket) = exchange();
+        assert_eq!(exchange.submit_bid(UserId(99), basket, BidType::XOR, 1.0, None), Err(ApiError::NotFound("user")));
+        assert!(matches!(exchange.submit_bid(alice, basket, BidType::OR, 1.0, Some(1.5)), Err(ApiError::BadRequest(_))));
+        assert!(matches!(exchange.create_basket(Vec::new()), Err(ApiError::BadRequest(_))));
+        // Non-finite numbers are refused at the door
+        assert_eq!(exchange.register_user("Mallory", f64::INFINITY), Err(ApiError::BadRequest("balance must not be negative".to_string())));
+        assert!(matches!(exchange.submit_bid(alice, basket, BidType::XOR, f64::INFINITY, None), Err(ApiError::BadRequest(_))));
+        assert!(matches!(exchange.create_basket(vec![AssetInfo::new(Asset::new("BTC", "USD"), 1e200, 1e200)]), Err(ApiError::BadRequest(_))));
+
+        // Bids the bidder cannot fund are refused before they rest, amendments included
+        let unfunded = exchange.submit_bid(alice, basket, BidType::OR, 150_000.0, Some(0.5));
+        assert_eq!(unfunded, Err(ApiError::Rejected(ClearingError::InsufficientFunds { user_id: alice, what: "the escrow for its bid" })));
+        let resting = exchange.submit_bid(alice, basket, BidType::OR, 60_000.0, Some(0.5)).unwrap();
+        assert!(matches!(exchange.amend_bid(resting.id, 110_000.0, Some(0.5)), Err(ApiError::Rejected(_))));
+        exchange.amend_bid(resting.id, 100_000.0, Some(0.5)).unwrap();
+        exchange.cancel_bid(resting.id).unwrap();
+        exchange.submit_bid(bob, basket, BidType::OR, 30_000.0, Some(0.5)).unwrap();
+        let outcome = exchange.run_auction(&AuctionRequest { basket_id: basket, mechanism: Mechanism::Or, price_increment: Some(0.05), max_rounds: Some(20), settlement: None }).unwrap();
+        assert_eq!(outcome.payments, HashMap::from([(bob, 30_000.0)]));
+        assert_eq!(exchange.user(alice).unwrap().balance, 100_000.0);
+    }
+
+    #[test]
+    fn test_snapshot_restores_an_identical_exchange() {
+        let (mut exchange, alice, bob, basket) = exchange();
+        exchange.submit_bid(alice, basket, BidType::XOR, 61_000.0, None).unwrap();
+        let outcome = exchange.run_auction(&AuctionRequest::new(basket, Mechanism::Xor)).unwrap();
+        let other = exchange.create_basket(vec![AssetInfo::new(Asset::new("ETH", "USD"), 2.0, 3_000.0)]).unwrap().id;
+        let resting = exchange.submit_bid(bob, other, BidType::XOR, 6_500.0, None).unwrap();
+
+        let bytes = exchange.snapshot().unwrap();
+        let mut restored = Exchange::restore(&bytes).unwrap();
+        // Outcomes hold hash maps, so compare parsed JSON rather than bytes
+        let parse = |bytes: &[u8]| serde_json::from_slice::<serde_json::Value>(bytes).unwrap();
+        assert_eq!(parse(&restored.snapshot().unwrap()), parse(&bytes));
+        assert_eq!(restored.user(alice).unwrap().balance, 39_000.0);
+        assert_eq!(restored.clearing.ledger.cash_balance(alice), exchange.clearing.ledger.cash_balance(alice));
+        assert_eq!(restored.bids_for(other), vec![&resting]);
+        assert_eq!(restored.outcome(outcome.auction_id).unwrap().payments, outcome.payments);
+        // Clearing the restored auction's settlement again charges nobody twice
+        assert!(restored.clearing.is_processed(outcome.report.metadata.settlement_id));
+
+        // The restored exchange keeps going where the original stopped
+        let next = restored.submit_bid(bob, other, BidType::XOR, 6_600.0, None).unwrap();
+        assert!(next.id > resting.id);
+        let later = restored.run_auction(&AuctionRequest::new(other, Mechanism::Xor)).unwrap();
+        assert!(later.auction_id > outcome.auction_id);
+        assert!(later.report.metadata.settlement_id > outcome.report.metadata.settlement_id);
+
+        let binary = exchange.snapshot_as(Encoding::Protobuf).unwrap();
+        assert!(binary.len() < bytes.len());
+        assert_eq!(parse(&Exchange::restore(&binary).unwrap().snapshot().unwrap()), parse(&bytes));
+    }
+
+    #[test]
+    fn test_halted_auctions_stay_halted_across_a_snapshot() {
+        let (mut exchange, alice, _, basket) = exchange();
+        exchange.submit_bid(alice, basket, BidType::XOR, 61_000.0, None).unwrap();
+        exchange.halt_auction(basket, None).unwrap();
+
+        for encoding in [Encoding::Json, Encoding::Protobuf] {
+            let mut restored = Exchange::restore(&exchange.snapshot_as(encoding).unwrap()).unwrap();
+            assert!(restored.is_halted(basket));
+            assert!(matches!(restored.open_auction(&AuctionRequest::new(basket, Mechanism::Xor)), Err(ApiError::Conflict("auction of this basket is halted"))));
+            restored.resume_auction(basket, None).unwrap();
+            assert_eq!(restored.run_auction(&AuctionRequest::new(basket, Mechanism::Xor)).unwrap().payments[&alice], 61_000.0);
+        }
+
+        // Version 1 snapshots predate halting and restore with nothing halted
+        let mut snapshot: serde_json::Value = serde_json::from_slice(&exchange.snapshot().unwrap()).unwrap();
+        snapshot["version"] = serde_json::json!(1);
+        snapshot.as_object_mut().unwrap().remove("halted");
+        let restored = Exchange::restore(&serde_json::to_vec(&snapshot).unwrap()).unwrap();
+        assert!(!restored.is_halted(basket));
+    }
+
+    #[test]
+    fn test_snapshot_carries_marks_navs_profiles_and_escrow() {
+        let clock = SimClock::new(1_700_000_000_000);
+        let (exchange, alice, bob, basket) = exchange();
+        let mut exchange = exchange.with_clock(Arc::new(clock.clone())).with_nav_interval(Duration::from_secs(60));
+        exchange.update_mark("BTC", 62_000.0);
+        clock.advance(Duration::from_secs(90));
+        exchange.update_mark("BTC", 63_000.0);
+        exchange.set_bidder_profile(alice, BidderProfile { credit_score: 0.9, settlement_days: 2.0 }).unwrap();
+        exchange.submit_bid(alice, basket, BidType::XOR, 61_000.0, None).unwrap();
+        exchange.submit_bid(bob, basket, BidType::OR, 30_000.0, Some(0.5)).unwrap();
+
+        // An open auction could not settle into the copy, so no snapshot is taken meanwhile
+        let pending = exchange.open_auction(&AuctionRequest::new(basket, Mechanism::Xor)).unwrap();
+        assert!(matches!(exchange.snapshot(), Err(ApiError::Conflict(_))));
+        drop(pending);
+
+        for encoding in [Encoding::Json, Encoding::Protobuf] {
+            let restored = Exchange::restore(&exchange.snapshot_as(encoding).unwrap()).unwrap().with_clock(Arc::new(clock.clone()));
+            assert_eq!(restored.mark_history, exchange.mark_history);
+            assert_eq!(restored.fixing_marks(exchange.basket(basket).unwrap()), HashMap::from([("BTC".to_string(), 63_000.0)]));
+            assert_eq!(restored.nav_history(basket, None, None).unwrap(), exchange.nav_history(basket, None, None).unwrap());
+            assert_eq!(restored.nav_history(basket, None, None).unwrap().len(), 2);
+            assert_eq!(restored.last_nav_at, exchange.last_nav_at);
+            assert_eq!(restored.profiles, exchange.profiles);
+            for user_id in [alice, bob] {
+                assert_eq!(restored.escrow.locked(user_id, basket), exchange.escrow.locked(user_id, basket));
+                assert_eq!(restored.available_balance(user_id), exchange.available_balance(user_id));
+            }
+        }
+    }
+
+    #[test]
+    fn test_restore_refuses_foreign_bytes() {
+        assert!(matches!(Exchange::restore(b"not a snapshot"), Err(StorageError::Corrupt(_))));
+        let mut snapshot: serde_json::Value = serde_json::from_slice(&Exchange::new().snapshot().unwrap()).unwrap();
+        snapshot["version"] = serde_json::json!(SNAPSHOT_VERSION + 1);
+        let bytes = serde_json::to_vec(&snapshot).unwrap();
+        assert!(matches!(Exchange::restore(&bytes), Err(StorageError::Corrupt(_))));
+    }
+}
@@ -0,0 +1,666 @@
+//! FIX 4.4 gateway for institutional bidders. NewOrderSingle submits a bid and
+//! OrderCancelRequest cancels one; each is answered with an ExecutionReport, and the
+//! session's orders get further ExecutionReports when their basket is auctioned: a trade
+//! or an expiry when winners are chosen, and an order status carrying the fee and net
+//! money when the auction settles.
+//!
+//! An order's `Account` (1) is the bidding user and its `Symbol` (55) the basket id.
+//! `Price` (44) is the bid, `OrderQty` (38) the fraction of the basket, left out for all
+//! of it, and the user-defined tag 5001 `BidType` is `XOR` (the default) or `OR`. Only
+//! buy orders (`Side` 1) exist. With authentication on, the Logon's `Password` (554) is
+//! an API key. Incoming `MsgSeqNum` (34) must count up from 1 without gaps; resends are
+//! not supported, so a gap or replay ends the session.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use auction::observer::AuctionEvent;
+use auction::sim::{Clock, SystemClock};
+use model::ids::{BasketId, BidId, UserId};
+use model::model::BidType;
+use crate::auth::{self, Authenticator, Principal, Scope};
+use crate::error::ApiError;
+use crate::events::EventBroadcaster;
+use crate::routes::SharedExchange;
+
+pub const SOH: u8 = 0x01;
+pub const BEGIN_STRING: &str = "FIX.4.4";
+/// Largest `BodyLength` accepted; no message the gateway speaks comes near it.
+pub const MAX_BODY_LENGTH: usize = 16 * 1024;
+/// Most bytes a session holds while a message arrives: the largest message and its framing.
+const MAX_BUFFERED: usize = MAX_BODY_LENGTH + 64;
+
+/// Tags the gateway reads or writes.
+pub mod tag {
+    pub const ACCOUNT: u32 = 1;
+    pub const AVG_PX: u32 = 6;
+    pub const BEGIN_STRING: u32 = 8;
+    pub const BODY_LENGTH: u32 = 9;
+    pub const CHECKSUM: u32 = 10;
+    pub const CL_ORD_ID: u32 = 11;
+    pub const COMMISSION: u32 = 12;
+    pub const COMM_TYPE: u32 = 13;
+    pub const CUM_QTY: u32 = 14;
+    pub const EXEC_ID: u32 = 17;
+    pub const LAST_PX: u32 = 31;
+    pub const LAST_QTY: u32 = 32;
+    pub const MSG_SEQ_NUM: u32 = 34;
+    pub const MSG_TYPE: u32 = 35;
+    pub const ORDER_ID: u32 = 37;
+    pub const ORDER_QTY: u32 = 38;
+    pub const POSS_DUP_FLAG: u32 = 43;
+    pub const ORD_STATUS: u32 = 39;
+    pub const ORD_TYPE: u32 = 40;
+    pub const ORIG_CL_ORD_ID: u32 = 41;
+    pub const PRICE: u32 = 44;
+    pub const REF_SEQ_NUM: u32 = 45;
+    pub const SENDER_COMP_ID: u32 = 49;
+    pub const SENDING_TIME: u32 = 52;
+    pub const SIDE: u32 = 54;
+    pub const SYMBOL: u32 = 55;
+    pub const TARGET_COMP_ID: u32 = 56;
+    pub const TEXT: u32 = 58;
+    pub const ENCRYPT_METHOD: u32 = 98;
+    pub const CXL_REJ_REASON: u32 = 102;
+    pub const HEART_BT_INT: u32 = 108;
+    pub const TEST_REQ_ID: u32 = 112;
+    pub const NET_MONEY: u32 = 118;
+    pub const EXEC_TYPE: u32 = 150;
+    pub const LEAVES_QTY: u32 = 151;
+    pub const REF_MSG_TYPE: u32 = 372;
+    pub const CXL_REJ_RESPONSE_TO: u32 = 434;
+    pub const PASSWORD: u32 = 554;
+    pub const BID_TYPE: u32 = 5001;
+}
+
+/// Message types the gateway speaks.
+pub mod msg_type {
+    pub const HEARTBEAT: &str = "0";
+    pub const TEST_REQUEST: &str = "1";
+    pub const REJECT: &str = "3";
+    pub const LOGOUT: &str = "5";
+    pub const EXECUTION_REPORT: &str = "8";
+    pub const ORDER_CANCEL_REJECT: &str = "9";
+    pub const LOGON: &str = "A";
+    pub const NEW_ORDER_SINGLE: &str = "D";
+    pub const ORDER_CANCEL_REQUEST: &str = "F";
+}
+
+
+/// Why a message could not be read or acted on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FixError {
+    /// The bytes are not a FIX 4.4 message; the session cannot continue.
+    Garbled(&'static str),
+    BadChecksum { expected: u8, found: u8 },
+    MissingField(u32),
+    InvalidField(u32),
+}
+
+impl fmt::Display for FixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FixError::Garbled(reason) => write!(f, "garbled message: {}", reason),
+            FixError::BadChecksum { expected, found } => write!(f, "checksum {:03} does not match {:03}", found, expected),
+            FixError::MissingField(tag) => write!(f, "required tag {} missing", tag),
+            FixError::InvalidField(tag) => write!(f, "value of tag {} is invalid", tag),
+        }
+    }
+}
+
+impl std::error::Error for FixError {}
+
+
+/// A FIX message as its fields in order, `MsgType` first and without the `BeginString`,
+/// `BodyLength` and `CheckSum` framing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixMessage {
+    fields: Vec<(u32, String)>,
+}
+
+impl FixMessage {
+    pub fn new(msg_type: &str) -> Self {
+        FixMessage { fields: vec![(tag::MSG_TYPE, msg_type.to_string())] }
+    }
+
+    pub fn with(mut self, tag: u32, value: impl fmt::Display) -> Self {
+        self.fields.push((tag, value.to_string()));
+        self
+    }
+
+    pub fn msg_type(&self) -> &str {
+        self.get(tag::MSG_TYPE).unwrap_or_default()
+    }
+
+    pub fn get(&self, tag: u32) -> Option<&str> {
+        self.fields.iter().find(|(t, _)| *t == tag).map(|(_, value)| value.as_str())
+    }
+
+    pub fn required(&self, tag: u32) -> Result<&str, FixError> {
+        self.get(tag).ok_or(FixError::MissingField(tag))
+    }
+
+    pub fn parse<T: FromStr>(&self, tag: u32) -> Result<T, FixError> {
+        self.required(tag)?.parse().map_err(|_| FixError::InvalidField(tag))
+    }
+
+    /// The wire form, with `BodyLength` and `CheckSum` computed.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (tag, value) in &self.fields {
+            body.extend_from_slice(format!("{}={}", tag, value).as_bytes());
+            body.push(SOH);
+        }
+        let mut message = format!("8={}\x019={}\x01", BEGIN_STRING, body.len()).into_bytes();
+        message.extend_from_slice(&body);
+        let checksum = checksum(&message);
+        message.extend_from_slice(format!("10={:03}\x01", checksum).as_bytes());
+        message
+    }
+
+    /// The first message in `buffer` and the bytes it took, or `None` if it has not
+    /// arrived in full yet.
+    pub fn decode(buffer: &[u8]) -> Result<Option<(FixMessage, usize)>, FixError> {
+        let begin = format!("8={}\x019=", BEGIN_STRING).into_bytes();
+        if buffer.len() < begin.len() {
+            return if begin.starts_with(buffer) { Ok(None) } else { Err(FixError::Garbled("must begin with 8=FIX.4.4")) };
+        }
+        if !buffer.starts_with(&begin) {
+            return Err(FixError::Garbled("must begin with 8=FIX.4.4"));
+        }
+        let Some(length_end) = buffer[begin.len()..].iter().position(|byte| *byte == SOH).map(|at| begin.len() + at) else {
+            // No BodyLength within bounds takes more than a few digits
+            return if buffer.len() - begin.len() > 20 { Err(FixError::Garbled("body length is not a number")) } else { Ok(None) };
+        };
+        let body_length: usize = std::str::from_utf8(&buffer[begin.len()..length_end]).ok()
+            .and_then(|length| length.parse().ok())
+            .ok_or(FixError::Garbled("body length is not a number"))?;
+        if body_length > MAX_BODY_LENGTH {
+            return Err(FixError::Garbled("body length exceeds the maximum"));
+        }
+        // 10=nnn and its delimiter
+        let Some((body_end, end)) = length_end.checked_add(1)
+            .and_then(|start| start.checked_add(body_length))
+            .and_then(|body_end| Some((body_end, body_end.checked_add(7)?))) else {
+            return Err(FixError::Garbled("body length exceeds the maximum"));
+        };
+        if buffer.len() < end {
+            return Ok(None);
+        }
+        let trailer = &buffer[body_end..end];
+        if !trailer.starts_with(b"10=") || trailer[6] != SOH {
+            return Err(FixError::Garbled("body length does not match the checksum's position"));
+        }
+        let found = std::str::from_utf8(&trailer[3..6]).ok().and_then(|sum| sum.parse().ok()).ok_or(FixError::Garbled("checksum is not a number"))?;
+        let expected = checksum(&buffer[..body_end]);
+        if found != expected {
+            return Err(FixError::BadChecksum { expected, found });
+        }
+
+        let body = std::str::from_utf8(&buffer[length_end + 1..body_end]).map_err(|_| FixError::Garbled("body is not UTF-8"))?;
+        let mut fields = Vec::new();
+        for field in body.split('\x01').filter(|field| !field.is_empty()) {
+            let (tag, value) = field.split_once('=').ok_or(FixError::Garbled("field without ="))?;
+            fields.push((tag.parse().map_err(|_| FixError::Garbled("tag is not a number"))?, value.to_string()));
+        }
+        if fields.first().map(|(tag, _)| *tag) != Some(tag::MSG_TYPE) {
+            return Err(FixError::Garbled("MsgType must be the first field of the body"));
+        }
+        Ok(Some((FixMessage { fields }, end)))
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// `SendingTime`: UTC as YYYYMMDD-HH:MM:SS.sss.
+fn utc_timestamp(millis: u64) -> String {
+    let (days, millis_of_day) = ((millis / 86_400_000) as i64, millis % 86_400_000);
+    // Civil date from days since 1970-01-01, after Howard Hinnant's days_from_civil inverse
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    let (seconds, millis) = (millis_of_day / 1000, millis_of_day % 1000);
+    format!("{:04}{:02}{:02}-{:02}:{:02}:{:02}.{:03}", year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60, millis)
+}
+
+
+/// An order entered on a session, by its bid id.
+#[derive(Debug, Clone)]
+struct Order {
+    cl_ord_id: String,
+    user_id: UserId,
+    basket_id: BasketId,
+    price: f64,
+    quantity: f64,
+}
+
+/// One counterparty's connection.
+#[derive(Debug)]
+struct Session {
+    counterparty: Option<String>,
+    principal: Option<Principal>,
+    /// The last `MsgSeqNum` received.
+    in_seq: u64,
+    out_seq: u64,
+    exec_id: u64,
+    orders: HashMap<BidId, Order>,
+    /// Orders that won, awaiting their auction's settlement.
+    filled: HashMap<BidId, Order>,
+    closed: bool,
+}
+
+impl Session {
+    fn next_exec_id(&mut self) -> u64 {
+        self.exec_id += 1;
+        self.exec_id
+    }
+
+    fn execution_report(&mut self, bid_id: BidId, order: &Order, exec_type: char, ord_status: char) -> FixMessage {
+        FixMessage::new(msg_type::EXECUTION_REPORT)
+            .with(tag::ORDER_ID, bid_id)
+            .with(tag::CL_ORD_ID, &order.cl_ord_id)
+            .with(tag::EXEC_ID, self.next_exec_id())
+            .with(tag::EXEC_TYPE, exec_type)
+            .with(tag::ORD_STATUS, ord_status)
+            .with(tag::ACCOUNT, order.user_id)
+            .with(tag::SYMBOL, order.basket_id)
+            .with(tag::SIDE, 1)
+            .with(tag::PRICE, order.price)
+            .with(tag::ORDER_QTY, order.quantity)
+    }
+}
+
+
+/// Accepts FIX sessions and maps them onto the exchange. Its reports come from the
+/// exchange's auction events, so `events` must be the broadcaster the exchange was
+/// [`crate::share`]d with.
+#[derive(Debug, Clone)]
+pub struct FixGateway {
+    exchange: SharedExchange,
+    events: EventBroadcaster,
+    comp_id: String,
+    authenticator: Option<Arc<Authenticator>>,
+}
+
+impl FixGateway {
+    pub fn new(exchange: SharedExchange, events: EventBroadcaster) -> Self {
+        FixGateway { exchange, events, comp_id: "COMBIDEX".to_string(), authenticator: None }
+    }
+
+    /// Our `SenderCompID`; COMBIDEX by default.
+    pub fn with_comp_id(mut self, comp_id: &str) -> Self {
+        self.comp_id = comp_id.to_string();
+        self
+    }
+
+    /// Requires an API key in the Logon's `Password`, with the same scopes as REST.
+    pub fn with_auth(mut self, authenticator: Arc<Authenticator>) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    pub async fn serve(self, listener: TcpListener) -> io::Result<()> {
+        let gateway = Arc::new(self);
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let gateway = gateway.clone();
+            tokio::spawn(async move {
+                if let Err(error) = gateway.run_session(stream).await {
+                    tracing::warn!(%peer, %error, "FIX session ended");
+                }
+            });
+        }
+    }
+
+    async fn run_session(&self, mut stream: TcpStream) -> io::Result<()> {
+        let mut events = self.events.subscribe();
+        let mut session = Session { counterparty: None, principal: None, in_seq: 0, out_seq: 0, exec_id: 0, orders: HashMap::new(), filled: HashMap::new(), closed: false };
+        let (mut buffer, mut chunk) = (Vec::new(), [0u8; 4096]);
+        while !session.closed {
+            let replies = tokio::select! {
+                read = stream.read(&mut chunk) => {
+                    let read = read?;
+                    if read == 0 {
+                        return Ok(());
+                    }
+                    buffer.extend_from_slice(&chunk[..read]);
+                    let mut replies = Vec::new();
+                    while let Some((message, used)) = FixMessage::decode(&buffer).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))? {
+                        buffer.drain(..used);
+                        replies.extend(self.handle(&mut session, &message));
+                    }
+                    if buffer.len() > MAX_BUFFERED {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "FIX message exceeds the maximum size"));
+                    }
+                    replies
+                }
+                event = events.recv() => match event {
+                    Ok(event) if session.counterparty.is_some() => self.report(&mut session, &event),
+                    Ok(_) => Vec::new(),
+                    Err(RecvError::Lagged(missed)) => {
+                        tracing::warn!(missed, "FIX session fell behind the auction events");
+                        Vec::new()
+                    }
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+            };
+            for reply in replies {
+                stream.write_all(&self.stamp(&mut session, reply).encode()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the standard header: our and the counterparty's ids, sequence number and time.
+    fn stamp(&self, session: &mut Session, message: FixMessage) -> FixMessage {
+        session.out_seq += 1;
+        let FixMessage { fields } = message;
+        let (msg_type, rest) = fields.split_first().expect("messages start with MsgType");
+        let mut stamped = FixMessage { fields: vec![msg_type.clone()] }
+            .with(tag::SENDER_COMP_ID, &self.comp_id)
+            .with(tag::TARGET_COMP_ID, session.counterparty.as_deref().unwrap_or("UNKNOWN"))
+            .with(tag::MSG_SEQ_NUM, session.out_seq)
+            .with(tag::SENDING_TIME, utc_timestamp(SystemClock.now_millis()));
+        stamped.fields.extend_from_slice(rest);
+        stamped
+    }
+
+    fn handle(&self, session: &mut Session, message: &FixMessage) -> Vec<FixMessage> {
+        let logout = |session: &mut Session, text: &str| {
+            session.closed = true;
+            vec![FixMessage::new(msg_type::LOGOUT).with(tag::TEXT, text)]
+        };
+        // Resends are not supported, so a gap ends the session like a sequence reset would
+        let expected = session.in_seq + 1;
+        match message.get(tag::MSG_SEQ_NUM).map(str::parse::<u64>) {
+            Some(Ok(seq)) if seq == expected => session.in_seq = seq,
+            Some(Ok(seq)) if seq < expected && message.get(tag::POSS_DUP_FLAG) == Some("Y") => return Vec::new(),
+            Some(Ok(seq)) if seq < expected => return logout(session, &format!("MsgSeqNum too low, expecting {} but received {}", expected, seq)),
+            Some(Ok(seq)) => return logout(session, &format!("MsgSeqNum gap, expecting {} but received {}", expected, seq)),
+            Some(Err(_)) | None => return logout(session, "MsgSeqNum missing or invalid"),
+        }
+        match (message.msg_type(), &session.counterparty) {
+            (msg_type::LOGON, None) => {
+                let Some(counterparty) = message.get(tag::SENDER_COMP_ID) else {
+                    return logout(session, "Logon must carry SenderCompID");
+                };
+                session.counterparty = Some(counterparty.to_string());
+                if let Some(authenticator) = &self.authenticator {
+                    match authenticator.authenticate(None, Some(message.get(tag::PASSWORD).unwrap_or_default())) {
+                        Ok(principal) => session.principal = Some(principal),
+                        Err(error) => return logout(session, &error.to_string()),
+                    }
+                }
+                vec![FixMessage::new(msg_type::LOGON).with(tag::ENCRYPT_METHOD, 0).with(tag::HEART_BT_INT, message.get(tag::HEART_BT_INT).unwrap_or("30"))]
+            }
+            (_, None) => logout(session, "first message must be a Logon"),
+            (msg_type::LOGOUT, Some(_)) => logout(session, "goodbye"),
+            (msg_type::HEARTBEAT, Some(_)) => Vec::new(),
+            (msg_type::TEST_REQUEST, Some(_)) => {
+                vec![FixMessage::new(msg_type::HEARTBEAT).with(tag::TEST_REQ_ID, message.get(tag::TEST_REQ_ID).unwrap_or_default())]
+            }
+            (msg_type::NEW_ORDER_SINGLE, Some(_)) => vec![self.new_order(session, message)],
+            (msg_type::ORDER_CANCEL_REQUEST, Some(_)) => vec![self.cancel_order(session, message)],
+            (other, Some(_)) => vec![FixMessage::new(msg_type::REJECT)
+                .with(tag::REF_SEQ_NUM, message.get(tag::MSG_SEQ_NUM).unwrap_or("0"))
+                .with(tag::REF_MSG_TYPE, other)
+                .with(tag::TEXT, "unsupported message type")],
+        }
+    }
+
+    fn new_order(&self, session: &mut Session, message: &FixMessage) -> FixMessage {
+        let cl_ord_id = message.get(tag::CL_ORD_ID).unwrap_or_default().to_string();
+        let submitted = (|| {
+            let order = Order {
+                cl_ord_id: message.required(tag::CL_ORD_ID)?.to_string(),
+                user_id: message.parse(tag::ACCOUNT)?,
+                basket_id: message.parse(tag::SYMBOL)?,
+                price: message.parse(tag::PRICE)?,
+                quantity: message.get(tag::ORDER_QTY).map(|_| message.parse(tag::ORDER_QTY)).transpose()?.unwrap_or(1.0),
+            };
+            if message.get(tag::SIDE) != Some("1") {
+                return Err(ApiError::BadRequest("only buy orders (Side 1) can be placed".to_string()));
+            }
+            let bid_type = match message.get(tag::BID_TYPE).unwrap_or("XOR") {
+                "XOR" => BidType::XOR,
+                "OR" => BidType::OR,
+                _ => return Err(FixError::InvalidField(tag::BID_TYPE).into()),
+            };
+            auth::require(session.principal.as_ref(), Scope::Trade)?;
+            auth::require_owner(session.principal.as_ref(), order.user_id)?;
+            let quantity = message.get(tag::ORDER_QTY).map(|_| order.quantity);
+            let bid = self.lock()?.submit_bid_as(session.principal.as_ref(), order.user_id, order.basket_id, bid_type, order.price, quantity)?;
+            Ok::<_, ApiError>((bid.id, order))
+        })();
+        match submitted {
+            Ok((bid_id, order)) => {
+                let report = session.execution_report(bid_id, &order, '0', '0')
+                    .with(tag::LEAVES_QTY, order.quantity)
+                    .with(tag::CUM_QTY, 0)
+                    .with(tag::AVG_PX, 0);
+                session.orders.insert(bid_id, order);
+                report
+            }
+            Err(error) => FixMessage::new(msg_type::EXECUTION_REPORT)
+                .with(tag::ORDER_ID, "NONE")
+                .with(tag::CL_ORD_ID, cl_ord_id)
+                .with(tag::EXEC_ID, session.next_exec_id())
+                .with(tag::EXEC_TYPE, '8')
+                .with(tag::ORD_STATUS, '8')
+                .with(tag::SYMBOL, message.get(tag::SYMBOL).unwrap_or_default())
+                .with(tag::SIDE, message.get(tag::SIDE).unwrap_or("1"))
+                .with(tag::LEAVES_QTY, 0)
+                .with(tag::CUM_QTY, 0)
+                .with(tag::AVG_PX, 0)
+                .with(tag::TEXT, error),
+        }
+    }
+
+    fn cancel_order(&self, session: &mut Session, message: &FixMessage) -> FixMessage {
+        let cl_ord_id = message.get(tag::CL_ORD_ID).unwrap_or_default().to_string();
+        let orig_cl_ord_id = message.get(tag::ORIG_CL_ORD_ID).unwrap_or_default().to_string();
+        let found = session.orders.iter()
+            .find(|(bid_id, order)| order.cl_ord_id == orig_cl_ord_id || message.get(tag::ORDER_ID) == Some(&bid_id.to_string()))
+            .map(|(bid_id, order)| (*bid_id, order.clone()));
+        let cancelled = found.clone().ok_or(ApiError::NotFound("order")).and_then(|(bid_id, order)| {
+            auth::require(session.principal.as_ref(), Scope::Trade)?;
+            auth::require_owner(session.principal.as_ref(), order.user_id)?;
+            self.lock()?.cancel_bid(bid_id)?;
+            Ok((bid_id, order))
+        });
+        match cancelled {
+            Ok((bid_id, order)) => {
+                session.orders.remove(&bid_id);
+                let order = Order { cl_ord_id, ..order };
+                session.execution_report(bid_id, &order, '4', '4')
+                    .with(tag::ORIG_CL_ORD_ID, orig_cl_ord_id)
+                    .with(tag::LEAVES_QTY, 0)
+                    .with(tag::CUM_QTY, 0)
+                    .with(tag::AVG_PX, 0)
+            }
+            Err(error) => FixMessage::new(msg_type::ORDER_CANCEL_REJECT)
+                .with(tag::ORDER_ID, found.as_ref().map_or("NONE".to_string(), |(bid_id, _)| bid_id.to_string()))
+                .with(tag::CL_ORD_ID, cl_ord_id)
+                .with(tag::ORIG_CL_ORD_ID, orig_cl_ord_id)
+                .with(tag::ORD_STATUS, '8')
+                .with(tag::CXL_REJ_RESPONSE_TO, 1)
+                // 1 is unknown order; 99 other
+                .with(tag::CXL_REJ_REASON, if found.is_none() { 1 } else { 99 })
+                .with(tag::TEXT, error),
+        }
+    }
+
+    /// Reports on the session's orders in the auction `event` is about. A winning order
+    /// trades at its user's payment spread evenly over their winning orders.
+    fn report(&self, session: &mut Session, event: &AuctionEvent) -> Vec<FixMessage> {
+        match event {
+            AuctionEvent::Winners { basket_id, auction_id, payments } => {
+                let ours: Vec<BidId> = session.orders.iter().filter(|(_, order)| order.basket_id == *basket_id).map(|(bid_id, _)| *bid_id).collect();
+                if ours.is_empty() {
+                    return Vec::new();
+                }
+                let Ok(winning) = self.lock().and_then(|exchange| Ok(exchange.outcome(*auction_id)?.winning_bids.clone())) else {
+                    return Vec::new();
+                };
+                let mut wins: HashMap<UserId, usize> = HashMap::new();
+                for order in winning.iter().filter_map(|bid_id| session.orders.get(bid_id)) {
+                    *wins.entry(order.user_id).or_insert(0) += 1;
+                }
+                let mut reports = Vec::new();
+                for bid_id in ours {
+                    let Some(order) = session.orders.remove(&bid_id) else { continue };
+                    if winning.contains(&bid_id) {
+                        let price = payments.get(&order.user_id).copied().unwrap_or(order.price) / wins[&order.user_id] as f64;
+                        reports.push(session.execution_report(bid_id, &order, 'F', '2')
+                            .with(tag::LAST_PX, price)
+                            .with(tag::LAST_QTY, order.quantity)
+                            .with(tag::LEAVES_QTY, 0)
+                            .with(tag::CUM_QTY, order.quantity)
+                            .with(tag::AVG_PX, price));
+                        session.filled.insert(bid_id, order);
+                    } else {
+                        reports.push(session.execution_report(bid_id, &order, 'C', 'C')
+                            .with(tag::LEAVES_QTY, 0)
+                            .with(tag::CUM_QTY, 0)
+                            .with(tag::AVG_PX, 0)
+                            .with(tag::TEXT, "lost the auction"));
+                    }
+                }
+                reports
+            }
+            AuctionEvent::Settled { report } => {
+                let settled: Vec<(BidId, Order)> = session.filled.iter()
+                    .filter(|(_, order)| order.basket_id == report.metadata.basket_id)
+                    .map(|(bid_id, order)| (*bid_id, order.clone()))
+                    .collect();
+                let mut reports = Vec::new();
+                for (bid_id, order) in settled {
+                    session.filled.remove(&bid_id);
+                    let Some(settlement) = report.settlements.iter().find(|settlement| settlement.user_id == order.user_id) else { continue };
+                    reports.push(session.execution_report(bid_id, &order, 'I', '2')
+                        .with(tag::LEAVES_QTY, 0)
+                        .with(tag::CUM_QTY, order.quantity)
+                        .with(tag::AVG_PX, settlement.payment)
+                        .with(tag::COMMISSION, settlement.fee)
+                        // Absolute
+                        .with(tag::COMM_TYPE, 3)
+                        .with(tag::NET_MONEY, settlement.total_debit())
+                        .with(tag::TEXT, format!("settled in auction {}", report.metadata.auction_id)));
+                }
+                reports
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, crate::exchange::Exchange>, ApiError> {
+        self.exchange.lock().map_err(|_| ApiError::Internal("exchange lock poisoned".to_string()))
+    }
+}
+
+impl From<FixError> for ApiError {
+    fn from(error: FixError) -> Self {
+        ApiError::BadRequest(error.to_string())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::model::{Asset, AssetInfo};
+    use crate::exchange::{AuctionRequest, Exchange, Mechanism};
+
+    #[test]
+    fn test_messages_round_trip_with_checksum() {
+        let message = FixMessage::new(msg_type::NEW_ORDER_SINGLE).with(tag::CL_ORD_ID, "o-1").with(tag::PRICE, 61000.5);
+        let mut bytes = message.encode();
+        assert!(bytes.starts_with(b"8=FIX.4.4\x019=23\x0135=D\x01"));
+        assert_eq!(FixMessage::decode(&bytes[..10]), Ok(None));
+        bytes.extend_from_slice(b"8=FIX");
+        let (decoded, used) = FixMessage::decode(&bytes).unwrap().unwrap();
+        assert_eq!((decoded, used), (message, bytes.len() - 5));
+
+        let mut corrupt = FixMessage::new(msg_type::HEARTBEAT).encode();
+        corrupt[14] = b'1';
+        assert!(matches!(FixMessage::decode(&corrupt), Err(FixError::BadChecksum { .. })));
+        assert_eq!(FixMessage::decode(b"GET / HTTP/1.1"), Err(FixError::Garbled("must begin with 8=FIX.4.4")));
+
+        // An untrusted BodyLength can neither overflow nor hold the buffer open forever
+        let huge = format!("8=FIX.4.4\x019={}\x0135=0\x01", usize::MAX);
+        assert_eq!(FixMessage::decode(huge.as_bytes()), Err(FixError::Garbled("body length exceeds the maximum")));
+        assert_eq!(FixMessage::decode(format!("8=FIX.4.4\x019={}\x01", MAX_BODY_LENGTH + 1).as_bytes()), Err(FixError::Garbled("body length exceeds the maximum")));
+        assert_eq!(FixMessage::decode(b"8=FIX.4.4\x019=1234567890123456789012345"), Err(FixError::Garbled("body length is not a number")));
+        assert_eq!(utc_timestamp(1_700_000_000_123), "20231114-22:13:20.123");
+    }
+
+    async fn receive(stream: &mut TcpStream, buffer: &mut Vec<u8>) -> FixMessage {
+        loop {
+            if let Some((message, used)) = FixMessage::decode(buffer).unwrap() {
+                buffer.drain(..used);
+                return message;
+            }
+            let mut chunk = [0u8; 1024];
+            let read = stream.read(&mut chunk).await.unwrap();
+            assert!(read > 0, "gateway closed the session");
+            buffer.extend_from_slice(&chunk[..read]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_orders_cancels_and_fills_over_a_session() {
+        let events = EventBroadcaster::new(64);
+        let mut exchange = Exchange::new();
+        let alice = exchange.register_user("Alice", 100_000.0).unwrap().id;
+        let basket = exchange.create_basket(vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60_000.0)]).unwrap().id;
+        let exchange = crate::share(exchange, events.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(FixGateway::new(exchange.clone(), events).serve(listener));
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut buffer = Vec::new();
+        let order = |seq: u64, cl_ord_id: &str, price: f64| FixMessage::new(msg_type::NEW_ORDER_SINGLE)
+            .with(tag::MSG_SEQ_NUM, seq).with(tag::CL_ORD_ID, cl_ord_id).with(tag::ACCOUNT, alice).with(tag::SYMBOL, basket)
+            .with(tag::SIDE, 1).with(tag::ORD_TYPE, 2).with(tag::PRICE, price);
+        client.write_all(&FixMessage::new(msg_type::LOGON).with(tag::SENDER_COMP_ID, "BANK").with(tag::MSG_SEQ_NUM, 1).encode()).await.unwrap();
+        assert_eq!(receive(&mut client, &mut buffer).await.msg_type(), msg_type::LOGON);
+
+        client.write_all(&order(2, "o-1", 70_000.0).encode()).await.unwrap();
+        let cancelled = FixMessage::new(msg_type::ORDER_CANCEL_REQUEST).with(tag::MSG_SEQ_NUM, 3).with(tag::CL_ORD_ID, "c-1").with(tag::ORIG_CL_ORD_ID, "o-1").with(tag::SIDE, 1);
+        client.write_all(&cancelled.encode()).await.unwrap();
+        client.write_all(&order(4, "o-2", 61_000.0).encode()).await.unwrap();
+        client.write_all(&order(5, "o-3", -1.0).encode()).await.unwrap();
+        let new = receive(&mut client, &mut buffer).await;
+        assert_eq!((new.get(tag::EXEC_TYPE), new.get(tag::TARGET_COMP_ID)), (Some("0"), Some("BANK")));
+        assert_eq!(receive(&mut client, &mut buffer).await.get(tag::EXEC_TYPE), Some("4"));
+        let accepted = receive(&mut client, &mut buffer).await;
+        assert_eq!(receive(&mut client, &mut buffer).await.get(tag::TEXT), Some("price must be positive"));
+
+        exchange.lock().unwrap().run_auction(&AuctionRequest::new(basket, Mechanism::Xor)).unwrap();
+        let trade = receive(&mut client, &mut buffer).await;
+        assert_eq!((trade.get(tag::EXEC_TYPE), trade.get(tag::ORDER_ID), trade.get(tag::LAST_PX)), (Some("F"), accepted.get(tag::ORDER_ID), Some("61000")));
+        let settled = receive(&mut client, &mut buffer).await;
+        assert_eq!((settled.get(tag::EXEC_TYPE), settled.get(tag::NET_MONEY)), (Some("I"), Some("61000")));
+
+        // A possible duplicate is ignored, a replayed sequence number ends the session
+        let heartbeat = FixMessage::new(msg_type::HEARTBEAT).with(tag::MSG_SEQ_NUM, 3);
+        client.write_all(&heartbeat.clone().with(tag::POSS_DUP_FLAG, "Y").encode()).await.unwrap();
+        client.write_all(&heartbeat.encode()).await.unwrap();
+        let logout = receive(&mut client, &mut buffer).await;
+        assert_eq!((logout.msg_type(), logout.get(tag::TEXT)), (msg_type::LOGOUT, Some("MsgSeqNum too low, expecting 6 but received 3")));
+    }
+}
@@ -0,0 +1,361 @@
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+use auction::report::SettlementMode;
+use model::model::{Asset, AssetInfo, Basket, BidType, User};
+use crate::auth::{self, Authenticator, Principal, Scope, API_KEY_HEADER};
+use crate::error::ApiError;
+use crate::exchange::{AuctionOutcome, AuctionRequest, BidRecord, Exchange, Mechanism};
+use crate::rate_limit::IpRateLimits;
+use crate::routes::SharedExchange;
+
+/// Messages and service stubs generated from `proto/exchange.proto`.
+pub mod proto {
+    tonic::include_proto!("combidex.exchange.v1");
+}
+
+use proto::exchange_server::ExchangeServer;
+
+
+impl From<ApiError> for Status {
+    fn from(error: ApiError) -> Self {
+        let message = error.to_string();
+        match error {
+            ApiError::NotFound(_) => Status::not_found(message),
+            ApiError::BadRequest(_) => Status::invalid_argument(message),
+            ApiError::Conflict(_) => Status::already_exists(message),
+            ApiError::Rejected(_) | ApiError::RiskLimit(_) => Status::failed_precondition(message),
+            ApiError::Storage(_) | ApiError::Internal(_) => Status::internal(message),
+            ApiError::Unauthorized(_) => Status::unauthenticated(message),
+            ApiError::Forbidden(_) => Status::permission_denied(message),
+            ApiError::TooManyRequests(_) => Status::resource_exhausted(message),
+        }
+    }
+}
+
+impl From<&User> for proto::User {
+    fn from(user: &User) -> Self {
+        proto::User { id: user.id.into(), name: user.name.clone(), balance: user.balance }
+    }
+}
+
+impl From<&AssetInfo> for proto::AssetInfo {
+    fn from(info: &AssetInfo) -> Self {
+        let asset = proto::Asset { base: info.asset.base.clone(), quote: info.asset.quote.clone() };
+        proto::AssetInfo { asset: Some(asset), quantity: info.quantity, price: info.price }
+    }
+}
+
+impl TryFrom<proto::AssetInfo> for AssetInfo {
+    type Error = ApiError;
+
+    fn try_from(info: proto::AssetInfo) -> Result<Self, ApiError> {
+        let asset = info.asset.ok_or_else(|| ApiError::BadRequest("asset is required".to_string()))?;
+        Ok(AssetInfo::new(Asset::new(&asset.base, &asset.quote), info.quantity, info.price))
+    }
+}
+
+impl From<&Basket> for proto::Basket {
+    fn from(basket: &Basket) -> Self {
+        proto::Basket { id: basket.id.into(), assets: basket.assets.iter().map(Into::into).collect() }
+    }
+}
+
+impl From<&BidType> for proto::BidType {
+    fn from(bid_type: &BidType) -> Self {
+        match bid_type {
+            BidType::XOR => proto::BidType::Xor,
+            BidType::OR => proto::BidType::Or,
+        }
+    }
+}
+
+impl From<proto::BidType> for BidType {
+    fn from(bid_type: proto::BidType) -> Self {
+        match bid_type {
+            proto::BidType::Xor => BidType::XOR,
+            proto::BidType::Or => BidType::OR,
+        }
+    }
+}
+
+impl From<&BidRecord> for proto::Bid {
+    fn from(bid: &BidRecord) -> Self {
+        proto::Bid {
+            id: bid.id.into(),
+            user_id: bid.user_id.into(),
+            basket_id: bid.basket_id.into(),
+            bid_type: proto::BidType::from(&bid.bid_type).into(),
+            price: bid.price,
+            quantity: bid.quantity,
+        }
+    }
+}
+
+impl From<Mechanism> for proto::Mechanism {
+    fn from(mechanism: Mechanism) -> Self {
+        match mechanism {
+            Mechanism::Xor => proto::Mechanism::Xor,
+            Mechanism::Or => proto::Mechanism::Or,
+            Mechanism::Vcg => proto::Mechanism::Vcg,
+            Mechanism::Cca => proto::Mechanism::Cca,
+        }
+    }
+}
+
+impl From<proto::Mechanism> for Mechanism {
+    fn from(mechanism: proto::Mechanism) -> Self {
+        match mechanism {
+            proto::Mechanism::Xor => Mechanism::Xor,
+            proto::Mechanism::Or => Mechanism::Or,
+            proto::Mechanism::Vcg => Mechanism::Vcg,
+            proto::Mechanism::Cca => Mechanism::Cca,
+        }
+    }
+}
+
+impl From<&AuctionOutcome> for proto::AuctionOutcome {
+    fn from(outcome: &AuctionOutcome) -> Self {
+        let mut allocations: Vec<proto::Allocation> = outcome.allocation.iter()
+            .map(|(user_id, assets)| proto::Allocation { user_id: user_id.get(), assets: assets.iter().map(Into::into).collect() })
+            .collect();
+        allocations.sort_by_key(|allocation| allocation.user_id);
+        let mut payments: Vec<proto::Payment> = outcome.payments.iter()
+            .map(|(user_id, amount)| proto::Payment { user_id: user_id.get(), amount: *amount })
+            .collect();
+        payments.sort_by_key(|payment| payment.user_id);
+        proto::AuctionOutcome {
+            auction_id: outcome.auction_id.into(),
+            settlement_id: outcome.report.metadata.settlement_id,
+            basket_id: outcome.basket_id.into(),
+            mechanism: proto::Mechanism::from(outcome.mechanism).into(),
+            winning_bids: outcome.winning_bids.iter().map(|id| id.get()).collect(),
+            allocations,
+            payments,
+            timestamp: outcome.report.metadata.timestamp,
+            report: Some((&outcome.report).into()),
+            fills: outcome.fills.iter()
+                .map(|filled| proto::PartialFill {
+                    bid_id: filled.bid_id.get(),
+                    user_id: filled.fill.user_id.get(),
+                    share: filled.fill.share,
+                    payment: filled.fill.payment,
+                    budget_bound: filled.fill.budget_bound,
+                })
+                .collect(),
+        }
+    }
+}
+
+fn bid_type(value: i32) -> Result<BidType, ApiError> {
+    proto::BidType::try_from(value).map(Into::into).map_err(|_| ApiError::BadRequest(format!("unknown bid type {}", value)))
+}
+
+fn mechanism(value: i32) -> Result<Mechanism, ApiError> {
+    proto::Mechanism::try_from(value).map(Into::into).map_err(|_| ApiError::BadRequest(format!("unknown mechanism {}", value)))
+}
+
+fn settlement_mode(value: i32) -> Result<SettlementMode, ApiError> {
+    proto::SettlementMode::try_from(value).map(Into::into).map_err(|_| ApiError::BadRequest(format!("unknown settlement mode {}", value)))
+}
+
+
+/// The gRPC face of the exchange, sharing state with the REST routes.
+#[derive(Debug, Clone)]
+pub struct ExchangeService {
+    exchange: SharedExchange,
+    authenticator: Option<Arc<Authenticator>>,
+    rate_limits: Option<Arc<IpRateLimits>>,
+}
+
+impl ExchangeService {
+    pub fn new(exchange: SharedExchange) -> Self {
+        ExchangeService { exchange, authenticator: None, rate_limits: None }
+    }
+
+    /// Requires callers to authenticate, with the same scopes as [`crate::routes::secured`].
+    /// Credentials are read from the `authorization` and `x-api-key` metadata.
+    pub fn with_auth(mut self, authenticator: Arc<Authenticator>) -> Self {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    /// Holds bid submissions and cancellations to a per-address limit.
+    pub fn with_rate_limits(mut self, rate_limits: Arc<IpRateLimits>) -> Self {
+        self.rate_limits = Some(rate_limits);
+        self
+    }
+
+    pub fn into_server(self) -> ExchangeServer<Self> {
+        ExchangeServer::new(self)
+    }
+
+    fn with<T>(&self, f: impl FnOnce(&mut Exchange) -> Result<T, ApiError>) -> Result<Response<T>, Status> {
+        let mut exchange = self.exchange.lock().unwrap();
+        Ok(Response::new(f(&mut exchange)?))
+    }
+
+    /// The caller of `request`, or `None` when authentication is off.
+    fn caller<T>(&self, request: &Request<T>) -> Result<Option<Principal>, Status> {
+        let Some(authenticator) = &self.authenticator else {
+            return Ok(None);
+        };
+        let metadata = |name: &str| request.metadata().get(name).and_then(|value| value.to_str().ok());
+        Ok(Some(authenticator.authenticate(metadata("authorization"), metadata(API_KEY_HEADER))?))
+    }
+
+    fn check_rate<T>(&self, request: &Request<T>) -> Result<(), Status> {
+        match (&self.rate_limits, request.remote_addr()) {
+            (Some(limits), Some(addr)) => Ok(limits.check(addr.ip())?),
+            _ => Ok(()),
+        }
+    }
+
+    fn require<T>(&self, request: &Request<T>, scope: Scope) -> Result<Option<Principal>, Status> {
+        let caller = self.caller(request)?;
+        auth::require(caller.as_ref(), scope)?;
+        Ok(caller)
+    }
+}
+
+#[tonic::async_trait]
+impl proto::exchange_server::Exchange for ExchangeService {
+    async fn register_user(&self, request: Request<proto::RegisterUserRequest>) -> Result<Response<proto::User>, Status> {
+        self.require(&request, Scope::Admin)?;
+        let request = request.into_inner();
+        self.with(|exchange| exchange.register_user(&request.name, request.balance).map(|user| (&user).into()))
+    }
+
+    async fn get_user(&self, request: Request<proto::GetUserRequest>) -> Result<Response<proto::User>, Status> {
+        let caller = self.require(&request, Scope::ReadOnly)?;
+        auth::require_owner(caller.as_ref(), request.get_ref().id.into())?;
+        self.with(|exchange| exchange.user(request.get_ref().id.into()).map(Into::into))
+    }
+
+    async fn create_basket(&self, request: Request<proto::CreateBasketRequest>) -> Result<Response<proto::Basket>, Status> {
+        self.require(&request, Scope::Admin)?;
+        let assets = request.into_inner().assets.into_iter().map(TryInto::try_into).collect::<Result<Vec<_>, _>>()?;
+        self.with(|exchange| exchange.create_basket(assets).map(|basket| (&basket).into()))
+    }
+
+    async fn get_basket(&self, request: Request<proto::GetBasketRequest>) -> Result<Response<proto::Basket>, Status> {
+        self.require(&request, Scope::ReadOnly)?;
+        self.with(|exchange| exchange.basket(request.get_ref().id.into()).map(Into::into))
+    }
+
+    async fn list_baskets(&self, request: Request<proto::ListBasketsRequest>) -> Result<Response<proto::ListBasketsResponse>, Status> {
+        self.require(&request, Scope::ReadOnly)?;
+        self.with(|exchange| Ok(proto::ListBasketsResponse { baskets: exchange.baskets().into_iter().map(Into::into).collect() }))
+    }
+
+    async fn list_bids(&self, request: Request<proto::ListBidsRequest>) -> Result<Response<proto::ListBidsResponse>, Status> {
+        self.require(&request, Scope::ReadOnly)?;
+        let basket_id = request.get_ref().basket_id;
+        self.with(|exchange| {
+            exchange.basket(basket_id.into())?;
+            Ok(proto::ListBidsResponse { bids: exchange.bids_for(basket_id.into()).into_iter().map(Into::into).collect() })
+        })
+    }
+
+    async fn submit_bid(&self, request: Request<proto::SubmitBidRequest>) -> Result<Response<proto::Bid>, Status> {
+        let caller = self.require(&request, Scope::Trade)?;
+        self.check_rate(&request)?;
+        let request = request.into_inner();
+        auth::require_owner(caller.as_ref(), request.user_id.into())?;
+        let bid_type = bid_type(request.bid_type)?;
+        self.with(|exchange| {
+            exchange.submit_bid_as(caller.as_ref(), request.user_id.into(), request.basket_id.into(), bid_type, request.price, request.quantity).map(|bid| (&bid).into())
+        })
+    }
+
+    async fn cancel_bid(&self, request: Request<proto::CancelBidRequest>) -> Result<Response<proto::Bid>, Status> {
+        let caller = self.require(&request, Scope::Trade)?;
+        self.check_rate(&request)?;
+        self.with(|exchange| {
+            auth::require_owner(caller.as_ref(), exchange.bid(request.get_ref().id.into())?.user_id)?;
+            exchange.cancel_bid(request.get_ref().id.into()).map(|bid| (&bid).into())
+        })
+    }
+
+    async fn start_auction(&self, request: Request<proto::StartAuctionRequest>) -> Result<Response<proto::AuctionOutcome>, Status> {
+        self.require(&request, Scope::Admin)?;
+        let request = request.into_inner();
+        let auction = AuctionRequest {
+            basket_id: request.basket_id.into(),
+            mechanism: mechanism(request.mechanism)?,
+            price_increment: request.price_increment,
+            max_rounds: request.max_rounds.map(|rounds| rounds as usize),
+            settlement: request.settlement.map(settlement_mode).transpose()?,
+        };
+        self.with(|exchange| exchange.run_auction(&auction).map(|outcome| (&outcome).into()))
+    }
+
+    async fn get_auction(&self, request: Request<proto::GetAuctionRequest>) -> Result<Response<proto::AuctionOutcome>, Status> {
+        self.require(&request, Scope::ReadOnly)?;
+        self.with(|exchange| exchange.outcome(request.get_ref().id.into()).map(Into::into))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tonic::Code;
+    use proto::exchange_server::Exchange as _;
+    use model::ids::UserId;
+
+    fn service() -> ExchangeService {
+        ExchangeService::new(Arc::new(Mutex::new(Exchange::new())))
+    }
+
+    fn btc(quantity: f64, price: f64) -> proto::AssetInfo {
+        proto::AssetInfo { asset: Some(proto::Asset { base: "BTC".to_string(), quote: "USD".to_string() }), quantity, price }
+    }
+
+    #[tokio::test]
+    async fn test_or_auction_over_grpc() {
+        let service = service();
+        let mut users = Vec::new();
+        for name in ["Alice", "Bob"] {
+            let user = service.register_user(Request::new(proto::RegisterUserRequest { name: name.to_string(), balance: 50_000.0 })).await.unwrap();
+            users.push(user.into_inner().id);
+        }
+        let basket = service.create_basket(Request::new(proto::CreateBasketRequest { assets: vec![btc(1.0, 60_000.0)] })).await.unwrap().into_inner();
+        assert_eq!(basket.assets[0].quantity, 1.0);
+
+        for user_id in &users {
+            let bid = proto::SubmitBidRequest { user_id: *user_id, basket_id: basket.id, bid_type: proto::BidType::Or.into(), price: 31_000.0, quantity: Some(0.5) };
+            service.submit_bid(Request::new(bid)).await.unwrap();
+        }
+        let bids = service.list_bids(Request::new(proto::ListBidsRequest { basket_id: basket.id })).await.unwrap().into_inner().bids;
+        assert_eq!(bids.len(), 2);
+        assert_eq!(bids[0].bid_type(), proto::BidType::Or);
+
+        let request = proto::StartAuctionRequest { basket_id: basket.id, mechanism: proto::Mechanism::Or.into(), price_increment: None, max_rounds: None, settlement: None };
+        let outcome = service.start_auction(Request::new(request)).await.unwrap().into_inner();
+        assert_eq!(outcome.mechanism(), proto::Mechanism::Or);
+        assert_eq!(outcome.payments.iter().map(|p| p.user_id).collect::<Vec<_>>(), users);
+        let fetched = service.get_auction(Request::new(proto::GetAuctionRequest { id: outcome.auction_id })).await.unwrap().into_inner();
+        assert_eq!(fetched, outcome);
+        let alice = service.get_user(Request::new(proto::GetUserRequest { id: users[0] })).await.unwrap().into_inner();
+        assert_eq!(alice.balance, 19_000.0);
+    }
+
+    #[tokio::test]
+    async fn test_errors_map_to_status_codes() {
+        let service = service();
+        let missing = service.get_basket(Request::new(proto::GetBasketRequest { id: 9 })).await.unwrap_err();
+        assert_eq!((missing.code(), missing.message()), (Code::NotFound, "basket not found"));
+        let request = proto::CreateBasketRequest { assets: vec![proto::AssetInfo { asset: None, quantity: 1.0, price: 1.0 }] };
+        assert_eq!(service.create_basket(Request::new(request)).await.unwrap_err().code(), Code::InvalidArgument);
+        let request = proto::StartAuctionRequest { basket_id: 1, mechanism: 7, price_increment: None, max_rounds: None, settlement: None };
+        assert_eq!(service.start_auction(Request::new(request)).await.unwrap_err().code(), Code::InvalidArgument);
+
+        let service = service.with_auth(Arc::new(Authenticator::new().with_api_key("k-bob", Principal::user(UserId(2), Scope::Trade))));
+        let unauthenticated = service.list_baskets(Request::new(proto::ListBasketsRequest {})).await.unwrap_err();
+        assert_eq!(unauthenticated.code(), Code::Unauthenticated);
+        let mut request = Request::new(proto::CreateBasketRequest { assets: vec![btc(1.0, 1.0)] });
+        request.metadata_mut().insert(API_KEY_HEADER, "k-bob".parse().unwrap());
+        assert_eq!(service.create_basket(request).await.unwrap_err().code(), Code::PermissionDenied);
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use quanto_pricer::provider::MarketDataProvider;
+use model::model::{Asset, AssetInfo, Basket};
+use model::ids::BasketId;
+use model::safe_math;
+use crate::error::ApiError;
+use crate::routes::SharedExchange;
+use crate::scheduler::Recurrence;
+
+
+/// An asset the index may hold, ranked by its market cap, price times `supply`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexCandidate {
+    pub base: String,
+    /// Circulating supply in units of the base.
+    pub supply: f64,
+}
+
+/// How the constituents share the index's value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndexWeighting {
+    #[default]
+    MarketCap,
+    Equal,
+}
+
+/// An index of the `top` candidates by market cap, priced in `quote`, e.g. the five
+/// largest coins weighted by market cap with none above 40%.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexDefinition {
+    pub name: String,
+    pub quote: String,
+    pub candidates: Vec<IndexCandidate>,
+    pub top: usize,
+    #[serde(default)]
+    pub weighting: IndexWeighting,
+    /// No constituent weighs more than this; the excess goes to the others by market cap.
+    #[serde(default)]
+    pub max_weight: Option<f64>,
+    /// Value of the index when first materialized, in `quote`.
+    pub notional: f64,
+    /// Rebalance trades worth less than this are left undone.
+    #[serde(default)]
+    pub min_trade_value: f64,
+}
+
+/// A constituent's share of the index at the prices it was composed at.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexWeight {
+    pub asset: Asset,
+    pub price: f64,
+    pub market_cap: f64,
+    pub weight: f64,
+}
+
+impl IndexDefinition {
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.top == 0 || self.candidates.is_empty() {
+            return Err(ApiError::BadRequest(format!("index '{}' needs at least one constituent", self.name)));
+        }
+        if self.candidates.iter().any(|candidate| safe_math::positive("supply", candidate.supply).is_err()) {
+            return Err(ApiError::BadRequest("candidate supplies must be positive".to_string()));
+        }
+        if safe_math::positive("notional", self.notional).is_err() || safe_math::non_negative("minimum trade value", self.min_trade_value).is_err() {
+            return Err(ApiError::BadRequest("the notional must be positive and the minimum trade value not negative".to_string()));
+        }
+        if let Some(max_weight) = self.max_weight {
+            let constituents = self.top.min(self.candidates.len()) as f64;
+            if !(max_weight > 0.0 && max_weight <= 1.0) || max_weight * constituents < 1.0 {
+                return Err(ApiError::BadRequest(format!("a max weight of {} cannot be met by {} constituents", max_weight, constituents)));
+            }
+        }
+        Ok(())
+    }
+
+    /// The constituents and their weights at `prices`, by base, largest first. Candidates
+    /// without a price are not ranked.
+    pub fn compose(&self, prices: &HashMap<String, f64>) -> Result<Vec<IndexWeight>, ApiError> {
+        self.validate()?;
+        let mut ranked: Vec<IndexWeight> = self.candidates.iter()
+            .filter_map(|candidate| {
+                let price = prices.get(&candidate.base).copied().filter(|price| price.is_finite() && *price > 0.0)?;
+                Some(IndexWeight { asset: Asset::new(&candidate.base, &self.quote), price, market_cap: price * candidate.supply, weight: 0.0 })
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.market_cap.total_cmp(&a.market_cap));
+        ranked.truncate(self.top);
+        if ranked.is_empty() {
+            return Err(ApiError::Internal(format!("no candidate of index '{}' has a price", self.name)));
+        }
+
+        let basis: Vec<f64> = match self.weighting {
+            IndexWeighting::MarketCap => ranked.iter().map(|weight| weight.market_cap).collect(),
+            IndexWeighting::Equal => vec![1.0; ranked.len()],
+        };
+        let weights = capped_weights(&basis, self.max_weight.unwrap_or(1.0));
+        for (constituent, weight) in ranked.iter_mut().zip(weights) {
+            constituent.weight = weight;
+        }
+        Ok(ranked)
+    }
+}
+
+/// `basis` normalized to weights none of which exceeds `cap`, the excess of those that
+/// would goes to the rest in proportion to their basis.
+fn capped_weights(basis: &[f64], cap: f64) -> Vec<f64> {
+    let mut weights = vec![0.0; basis.len()];
+    let mut capped = vec![false; basis.len()];
+    loop {
+        let left = 1.0 - cap * capped.iter().filter(|capped| **capped).count() as f64;
+        let uncapped: f64 = basis.iter().zip(&capped).filter(|(_, capped)| !**capped).map(|(basis, _)| basis).sum();
+        let mut changed = false;
+        for i in 0..basis.len() {
+            weights[i] = if capped[i] { cap } else { left * basis[i] / uncapped };
+            if !capped[i] && weights[i] > cap + 1e-12 {
+                capped[i] = true;
+                changed = true;
+            }
+        }
+        if !changed {
+            return weights;
+        }
+    }
+}
+
+/// What one rebalance changed. Holdings the index no longer wants are sold by auctioning
+/// them as basket `basket_id`; the `buys` are left to the operator.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rebalance {
+    pub index: String,
+    pub at: u64,
+    pub weights: Vec<IndexWeight>,
+    pub basket_id: Option<BasketId>,
+    pub sells: Vec<AssetInfo>,
+    pub buys: Vec<AssetInfo>,
+}
+
+
+/// Keeps an index on an exchange: materializes it into a basket at live prices and
+/// rebalances it on `recurrence`, creating a basket of what it sells each time.
+pub struct IndexService {
+    definition: IndexDefinition,
+    provider: Box<dyn MarketDataProvider>,
+    exchange: SharedExchange,
+    recurrence: Recurrence,
+    holdings: Vec<AssetInfo>,
+    next_rebalance: Option<u64>,
+}
+
+impl IndexService {
+    pub fn new(definition: IndexDefinition, provider: Box<dyn MarketDataProvider>, exchange: SharedExchange, recurrence: Recurrence) -> Result<Self, ApiError> {
+        definition.validate()?;
+        Ok(IndexService { definition, provider, exchange, recurrence, holdings: Vec::new(), next_rebalance: None })
+    }
+
+    pub fn definition(&self) -> &IndexDefinition {
+        &self.definition
+    }
+
+    /// What the index holds, priced at its last rebalance.
+    pub fn holdings(&self) -> &[AssetInfo] {
+        &self.holdings
+    }
+
+    /// Spot prices of the candidates by base. Those the venue cannot price are left out.
+    pub async fn prices(&self) -> HashMap<String, f64> {
+        let mut prices = HashMap::new();
+        for candidate in &self.definition.candidates {
+            match self.provider.spot_price(&Asset::new(&candidate.base, &self.definition.quote)).await {
+                Ok(price) => {
+                    prices.insert(candidate.base.clone(), price);
+                }
+                Err(error) => tracing::warn!(venue = self.provider.venue(), base = %candidate.base, %error, "no price for an index candidate"),
+            }
+        }
+        prices
+    }
+
+    /// Creates a basket of the index at its notional and live prices, and holds it.
+    pub async fn materialize(&mut self) -> Result<Basket, ApiError> {
+        let prices = self.prices().await;
+        self.materialize_at(&prices)
+    }
+
+    /// [`IndexService::materialize`] at `prices`, by base.
+    pub fn materialize_at(&mut self, prices: &HashMap<String, f64>) -> Result<Basket, ApiError> {
+        let weights = self.definition.compose(prices)?;
+        let assets = target(&weights, self.definition.notional);
+        let mut exchange = self.exchange.lock().map_err(|_| ApiError::Internal("exchange lock poisoned".to_string()))?;
+        let basket = exchange.create_basket(assets.clone())?;
+        self.next_rebalance = Some(self.recurrence.next_after(exchange.now_millis()));
+        drop(exchange);
+        tracing::info!(index = %self.definition.name, basket_id = basket.id.get(), constituents = assets.len(), "index materialized");
+        self.holdings = assets;
+        Ok(basket)
+    }
+
+    /// Rebalances the holdings to the index's weights at `prices`, by base, keeping their
+    /// value. Creates a basket of what is sold unless nothing is.
+    pub fn rebalance_at(&mut self, prices: &HashMap<String, f64>) -> Result<Rebalance, ApiError> {
+        if self.holdings.is_empty() {
+            return Err(ApiError::Conflict("the index has not been materialized"));
+        }
+        let mut value = 0.0;
+        for held in &self.holdings {
+            let price = prices.get(&held.asset.base).ok_or_else(|| ApiError::Internal(format!("no price for held {}", held.asset.base)))?;
+            value += held.quantity * price;
+        }
+        let weights = self.definition.compose(prices)?;
+        let wanted = target(&weights, value);
+
+        let mut sells = Vec::new();
+        let mut buys = Vec::new();
+        let held = |base: &str| self.holdings.iter().find(|held| held.asset.base == base).map_or(0.0, |held| held.quantity);
+        for info in &self.holdings {
+            let quantity = info.quantity - wanted.iter().find(|want| want.asset == info.asset).map_or(0.0, |want| want.quantity);
+            let price = prices[&info.asset.base];
+            if quantity > 0.0 && quantity * price >= self.definition.min_trade_value {
+                sells.push(AssetInfo::new(info.asset.clone(), quantity, price));
+            }
+        }
+        for want in &wanted {
+            let quantity = want.quantity - held(&want.asset.base);
+            if quantity > 0.0 && quantity * want.price >= self.definition.min_trade_value {
+                buys.push(AssetInfo::new(want.asset.clone(), quantity, want.price));
+            }
+        }
+
+        let mut exchange = self.exchange.lock().map_err(|_| ApiError::Internal("exchange lock poisoned".to_string()))?;
+        let basket_id = if sells.is_empty() { None } else { Some(exchange.create_basket(sells.clone())?.id) };
+        let at = exchange.now_millis();
+        self.next_rebalance = Some(self.recurrence.next_after(at));
+        drop(exchange);
+        tracing::info!(index = %self.definition.name, sells = sells.len(), buys = buys.len(), basket_id = basket_id.map(|id| id.get()), "index rebalanced");
+        self.holdings = wanted;
+        Ok(Rebalance { index: self.definition.name.clone(), at, weights, basket_id, sells, buys })
+    }
+
+    /// Rebalances at live prices if a rebalance is due by the exchange's clock.
+    pub async fn tick(&mut self) -> Result<Option<Rebalance>, ApiError> {
+        let due = self.next_rebalance.is_some_and(|next| self.exchange.lock().is_ok_and(|exchange| exchange.now_millis() >= next));
+        if !due {
+            return Ok(None);
+        }
+        let prices = self.prices().await;
+        self.rebalance_at(&prices).map(Some)
+    }
+
+    /// Ticks every `every` until the task is dropped.
+    pub async fn run(mut self, every: Duration) {
+        let mut interval = tokio::time::interval(every);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            if let Err(error) = self.tick().await {
+                tracing::warn!(index = %self.definition.name, %error, "index rebalance failed");
+            }
+        }
+    }
+}
+
+/// The quantities worth `value` in total split by `weights`, at their prices.
+fn target(weights: &[IndexWeight], value: f64) -> Vec<AssetInfo> {
+    weights.iter()
+        .map(|weight| AssetInfo::new(weight.asset.clone(), value * weight.weight / weight.price, weight.price))
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use auction::sim::SimClock;
+    use quanto_pricer::replay::{Fixtures, MockProvider};
+    use crate::exchange::Exchange;
+
+    // Monday 2024-01-01 00:00 UTC
+    const MONDAY: u64 = 1_704_067_200_000;
+
+    fn definition() -> IndexDefinition {
+        IndexDefinition {
+            name: "top3".to_string(),
+            quote: "USD".to_string(),
+            candidates: vec![
+                IndexCandidate { base: "BTC".to_string(), supply: 20.0 },
+                IndexCandidate { base: "ETH".to_string(), supply: 100.0 },
+                IndexCandidate { base: "SOL".to_string(), supply: 1_000.0 },
+                IndexCandidate { base: "DOGE".to_string(), supply: 1_000_000.0 },
+            ],
+            top: 3,
+            weighting: IndexWeighting::MarketCap,
+            max_weight: Some(0.5),
+            notional: 100_000.0,
+            min_trade_value: 1.0,
+        }
+    }
+
+    fn prices(sol: f64) -> HashMap<String, f64> {
+        HashMap::from([("BTC".to_string(), 60_000.0), ("ETH".to_string(), 3_000.0), ("SOL".to_string(), sol), ("DOGE".to_string(), 0.1)])
+    }
+
+    #[test]
+    fn test_top_constituents_are_weighted_by_capped_market_cap() {
+        // Market caps 1.2m, 300k, 100k and 100k
+        let weights = definition().compose(&prices(100.0)).unwrap();
+        let ranked: Vec<(&str, f64)> = weights.iter().map(|weight| (weight.asset.base.as_str(), weight.weight)).collect();
+        assert_eq!(ranked.len(), 3);
+        assert_eq!(ranked[0], ("BTC", 0.5));
+        assert!((ranked[1].1 - 0.375).abs() < 1e-12 && (ranked[2].1 - 0.125).abs() < 1e-12);
+
+        let equal = IndexDefinition { weighting: IndexWeighting::Equal, max_weight: None, ..definition() };
+        assert!(equal.compose(&prices(100.0)).unwrap().iter().all(|weight| (weight.weight - 1.0 / 3.0).abs() < 1e-12));
+        assert!(matches!(IndexDefinition { max_weight: Some(0.3), ..definition() }.validate(), Err(ApiError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn test_rebalancing_auctions_what_the_index_sells() {
+        let clock = SimClock::new(MONDAY);
+        let exchange = Arc::new(Mutex::new(Exchange::new().with_clock(Arc::new(clock.clone()))));
+        let spot_prices = prices(100.0).into_iter().map(|(base, price)| (format!("{}/USD", base), price)).collect();
+        let provider = MockProvider::new(Fixtures { venue: "deribit".to_string(), spot_prices, ..Fixtures::default() });
+        let mut service = IndexService::new(definition(), Box::new(provider), exchange.clone(), "daily 16:00".parse().unwrap()).unwrap();
+        assert!(service.tick().await.unwrap().is_none());
+
+        let basket = service.materialize().await.unwrap();
+        assert_eq!((basket.assets[0].asset.base.as_str(), basket.assets[0].quantity), ("BTC", 50_000.0 / 60_000.0));
+        assert!((basket.assets.iter().map(AssetInfo::total_value).sum::<f64>() - 100_000.0).abs() < 1e-6);
+        assert!(service.tick().await.unwrap().is_none());
+        clock.advance(Duration::from_secs(16 * 3600));
+        assert!(service.tick().await.unwrap().is_some());
+
+        // DOGE overtakes ETH and pushes SOL out of the top three
+        let mut moved = prices(100.0);
+        moved.insert("DOGE".to_string(), 0.5);
+        let rebalance = service.rebalance_at(&moved).unwrap();
+        let exchange = exchange.lock().unwrap();
+        let sold = exchange.basket(rebalance.basket_id.unwrap()).unwrap();
+        assert_eq!(sold.assets.len(), rebalance.sells.len());
+        let constituents: Vec<&str> = rebalance.weights.iter().map(|weight| weight.asset.base.as_str()).collect();
+        assert_eq!(constituents, ["BTC", "DOGE", "ETH"]);
+        assert_eq!(rebalance.sells.iter().map(|info| info.asset.base.as_str()).collect::<Vec<_>>(), ["ETH", "SOL"]);
+        assert_eq!(rebalance.buys.iter().map(|info| (info.asset.base.as_str(), info.quantity)).collect::<Vec<_>>(), [("DOGE", 62_500.0)]);
+        let sold: f64 = rebalance.sells.iter().map(AssetInfo::total_value).sum();
+        let bought: f64 = rebalance.buys.iter().map(AssetInfo::total_value).sum();
+        assert!((sold - bought).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use rskafka::chrono::DateTime;
+use rskafka::client::partition::{Compression, PartitionClient, UnknownTopicHandling};
+use rskafka::client::{Client, ClientBuilder};
+use rskafka::record::Record;
+use tokio::sync::Mutex;
+use crate::publish::{EventPublisher, Message, PublishError, PublishFuture};
+
+impl From<rskafka::client::error::Error> for PublishError {
+    fn from(error: rskafka::client::error::Error) -> Self {
+        PublishError::Unavailable(error.to_string())
+    }
+}
+
+
+/// Publishes to Kafka. A message goes to the partition its key hashes to, so Kafka keeps
+/// an auction's messages on each topic in order; the sequence number travels as the
+/// `sequence` header.
+pub struct KafkaPublisher {
+    client: Client,
+    /// Partition clients by topic, in partition order, made on first use.
+    partitions: Mutex<HashMap<String, Vec<Arc<PartitionClient>>>>,
+}
+
+impl KafkaPublisher {
+    pub async fn connect(brokers: &[&str]) -> Result<Self, PublishError> {
+        let client = ClientBuilder::new(brokers.iter().map(|broker| broker.to_string()).collect()).build().await?;
+        Ok(KafkaPublisher { client, partitions: Mutex::new(HashMap::new()) })
+    }
+
+    async fn partition(&self, topic: &str, key: u64) -> Result<Arc<PartitionClient>, PublishError> {
+        let mut partitions = self.partitions.lock().await;
+        if !partitions.contains_key(topic) {
+            let ids = self.client.list_topics().await?.into_iter()
+                .find(|candidate| candidate.name == topic)
+                .map(|found| found.partitions)
+                .ok_or_else(|| PublishError::Unavailable(format!("topic {} does not exist", topic)))?;
+            let mut clients = Vec::new();
+            for id in ids {
+                clients.push(Arc::new(self.client.partition_client(topic, id, UnknownTopicHandling::Retry).await?));
+            }
+            partitions.insert(topic.to_string(), clients);
+        }
+        let clients = &partitions[topic];
+        if clients.is_empty() {
+            return Err(PublishError::Unavailable(format!("topic {} has no partitions", topic)));
+        }
+        Ok(clients[key as usize % clients.len()].clone())
+    }
+}
+
+impl EventPublisher for KafkaPublisher {
+    fn publish<'a>(&'a self, message: &'a Message) -> PublishFuture<'a> {
+        Box::pin(async move {
+            let partition = self.partition(&message.topic, message.key).await?;
+            let now_millis = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0);
+            let record = Record {
+                key: Some(message.key.to_string().into_bytes()),
+                value: Some(message.payload.clone()),
+                headers: BTreeMap::from([("sequence".to_string(), message.sequence.to_string().into_bytes())]),
+                timestamp: DateTime::from_timestamp_millis(now_millis).unwrap_or_default(),
+            };
+            partition.produce(vec![record], Compression::NoCompression).await?;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,51 @@
+pub mod actors;
+pub mod admin;
+pub mod auth;
+pub mod backtest;
+pub mod codec;
+pub mod discovery;
+pub mod engine;
+pub mod error;
+pub mod event_store;
+pub mod events;
+pub mod exchange;
+pub mod fix;
+pub mod grpc;
+pub mod index;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "nats")]
+pub mod nats;
+pub mod nav;
+pub mod oms;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod prometheus;
+pub mod publish;
+pub mod rate_limit;
+pub mod risk;
+pub mod routes;
+pub mod scenario;
+pub mod scheduler;
+pub mod storage;
+pub mod surveillance;
+#[cfg(feature = "telemetry")]
+pub mod telemetry;
+
+use std::sync::{Arc, Mutex};
+use axum::Router;
+use crate::events::EventBroadcaster;
+use crate::exchange::Exchange;
+use crate::routes::SharedExchange;
+
+
+/// Shares `exchange` between the REST and gRPC services, reporting its auctions to `events`.
+pub fn share(mut exchange: Exchange, events: EventBroadcaster) -> SharedExchange {
+    exchange.add_observer(Arc::new(events));
+    Arc::new(Mutex::new(exchange))
+}
+
+/// The HTTP application over `exchange`.
+pub fn app(exchange: SharedExchange) -> Router {
+    routes::router(exchange)
+}
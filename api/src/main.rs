@@ -0,0 +1,101 @@
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use api::auth::Authenticator;
+use api::codec::Encoding;
+use api::events::{EventBroadcaster, serve_authenticated_events, serve_events};
+use api::event_store::FileEventStore;
+use api::exchange::Exchange;
+use api::fix::FixGateway;
+use auction::config::Config;
+use api::grpc::ExchangeService;
+use api::publish::EventStream;
+use api::rate_limit::{limit_bids, IpRateLimits};
+use api::storage::SledRepository;
+use quanto_pricer::price_feed::PriceFeedService;
+use quanto_pricer::stream::StreamSettings;
+
+fn setting(var: &str, default: &str) -> String {
+    env::var(var).unwrap_or_else(|_| default.to_string())
+}
+
+/// Serves the exchange over REST on `API_ADDR` (127.0.0.1:8080) and gRPC on
+/// `API_GRPC_ADDR` (127.0.0.1:50051), and pushes auction events to WebSocket clients on
+/// `API_EVENTS_ADDR` (127.0.0.1:8081). FIX 4.4 sessions are accepted on `API_FIX_ADDR`
+/// (127.0.0.1:9878). State is kept in the sled database at `API_DB`
+/// (./combidex-db) and recovered from it on startup. With `API_JOURNAL` set, every change
+/// is also appended to the event log at that path, written as `API_JOURNAL_ENCODING`
+/// (json, or protobuf for a compact binary log) when it is created. Auction defaults and fees come from
+/// the TOML file at `API_CONFIG`, if set, and `COMBIDEX_` overrides. Logs are JSON lines
+/// on stdout, filtered by `RUST_LOG` (info by default), and metrics are served in the
+/// Prometheus format at `/metrics`. Setting `API_KEYS` or `API_JWT_SECRET` requires
+/// every client to authenticate; see [`Authenticator::from_env`]. Bid rate limits, per
+/// user and per client address, apply when `rate_limits.enabled` is set, and pre-trade
+/// risk checks when `risk.enabled` is. With `market_data.enabled`, marks stream from the
+/// Deribit price feed and drive NAV snapshots every `market_data.nav_interval_secs`.
+/// With `publish.enabled`, auction events, fills and settlement reports are also
+/// streamed to Kafka or NATS, if built with the matching feature.
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "telemetry")]
+    api::telemetry::init()?;
+    let metrics = api::prometheus::install()?;
+    let (rest_addr, grpc_addr, events_addr) = (setting("API_ADDR", "127.0.0.1:8080"), setting("API_GRPC_ADDR", "127.0.0.1:50051"), setting("API_EVENTS_ADDR", "127.0.0.1:8081"));
+    let fix_addr = setting("API_FIX_ADDR", "127.0.0.1:9878");
+    let events = EventBroadcaster::new(1024);
+    let repository = SledRepository::open(setting("API_DB", "combidex-db"))?;
+    let config = Config::load(env::var("API_CONFIG").ok().as_deref().map(std::path::Path::new))?;
+    let mut exchange = Exchange::recover(Arc::new(repository))?
+        .with_config(&config)
+        .with_nav_interval(Duration::from_secs(config.market_data.nav_interval_secs));
+    if let Ok(path) = env::var("API_JOURNAL") {
+        let encoding = setting("API_JOURNAL_ENCODING", "json").parse::<Encoding>()?;
+        exchange = exchange.with_journal(Arc::new(FileEventStore::open_as(path, encoding)?));
+    }
+    if config.publish.enabled {
+        let publisher = api::publish::connect(&config.publish).await?;
+        exchange.add_observer(Arc::new(EventStream::spawn(publisher, &config.publish, 8)));
+    }
+    let exchange = api::share(exchange, events.clone());
+    if config.market_data.enabled {
+        let feed = Arc::new(PriceFeedService::new(1024));
+        feed.start(StreamSettings::default());
+        tokio::spawn(api::nav::mark_from_feed(feed, exchange.clone(), Duration::from_secs(5)));
+    }
+    let authenticator = Authenticator::from_env()?.map(Arc::new);
+
+    let events_listener = tokio::net::TcpListener::bind(&events_addr).await?;
+    let mut fix = FixGateway::new(exchange.clone(), events.clone());
+    if let Some(authenticator) = &authenticator {
+        fix = fix.with_auth(authenticator.clone());
+    }
+    tokio::spawn(fix.serve(tokio::net::TcpListener::bind(&fix_addr).await?));
+    let mut service = ExchangeService::new(exchange.clone());
+    let rate_limits = config.rate_limits.enabled.then(|| Arc::new(IpRateLimits::new(config.rate_limits.per_ip)));
+    if let Some(rate_limits) = &rate_limits {
+        service = service.with_rate_limits(rate_limits.clone());
+    }
+    let mut app = match &authenticator {
+        Some(authenticator) => {
+            tokio::spawn(serve_authenticated_events(events_listener, events, authenticator.clone()));
+            service = service.with_auth(authenticator.clone());
+            api::routes::secured(exchange, authenticator.clone())
+        }
+        None => {
+            tokio::spawn(serve_events(events_listener, events));
+            api::app(exchange)
+        }
+    };
+    if let Some(rate_limits) = rate_limits {
+        app = app.layer(axum::middleware::from_fn_with_state(rate_limits, limit_bids));
+    }
+    let grpc = tonic::transport::Server::builder()
+        .add_service(service.into_server())
+        .serve(grpc_addr.parse()?);
+    tokio::spawn(grpc);
+    let listener = tokio::net::TcpListener::bind(&rest_addr).await?;
+    tracing::info!(rest = %rest_addr, grpc = %grpc_addr, events = %events_addr, fix = %fix_addr, authenticated = authenticator.is_some(), "listening");
+    let app = app.merge(api::prometheus::router(metrics));
+    axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>()).await?;
+    Ok(())
+}
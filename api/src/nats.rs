@@ -0,0 +1,31 @@
+use async_nats::{Client, HeaderMap};
+use crate::publish::{EventPublisher, Message, PublishError, PublishFuture};
+
+
+/// Publishes to NATS on the subject `<topic>.<key>`, so subscribers can follow one
+/// auction or, with a wildcard, all of them. Each message is flushed before the next is
+/// sent, which keeps an auction's messages in order; the sequence number travels as the
+/// `Combidex-Sequence` header.
+pub struct NatsPublisher {
+    client: Client,
+}
+
+impl NatsPublisher {
+    pub async fn connect(servers: &[&str]) -> Result<Self, PublishError> {
+        let client = async_nats::connect(servers.to_vec()).await.map_err(|error| PublishError::Unavailable(error.to_string()))?;
+        Ok(NatsPublisher { client })
+    }
+}
+
+impl EventPublisher for NatsPublisher {
+    fn publish<'a>(&'a self, message: &'a Message) -> PublishFuture<'a> {
+        Box::pin(async move {
+            let mut headers = HeaderMap::new();
+            headers.insert("Combidex-Sequence", message.sequence.to_string().as_str());
+            let subject = format!("{}.{}", message.topic, message.key);
+            self.client.publish_with_headers(subject, headers, message.payload.clone().into()).await
+                .map_err(|error| PublishError::Unavailable(error.to_string()))?;
+            self.client.flush().await.map_err(|error| PublishError::Unavailable(error.to_string()))
+        })
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use model::ids::{AuctionId, BasketId};
+use model::model::Basket;
+use quanto_pricer::price_feed::PriceFeedService;
+use crate::exchange::AuctionOutcome;
+use crate::routes::SharedExchange;
+
+
+/// A basket's net asset value at `at`, in Unix milliseconds: every asset's quantity at
+/// its latest mark.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NavSnapshot {
+    pub basket_id: BasketId,
+    pub at: u64,
+    pub nav: f64,
+}
+
+impl NavSnapshot {
+    /// The NAV of `basket` at `marks`, by base, if every one of its assets is marked.
+    pub fn of(basket: &Basket, marks: &HashMap<String, f64>, at: u64) -> Option<Self> {
+        let nav = basket.assets.iter()
+            .map(|info| marks.get(&info.asset.base).map(|mark| info.quantity * mark))
+            .sum::<Option<f64>>()?;
+        Some(NavSnapshot { basket_id: basket.id, at, nav })
+    }
+}
+
+/// How an auction priced its basket against the basket's NAV. `premium` is what the
+/// winners paid over the NAV when it cleared, and `nav_return` how the NAV has moved
+/// since, i.e. what winning has been worth so far.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuctionPerformance {
+    pub auction_id: AuctionId,
+    pub basket_id: BasketId,
+    /// Unix milliseconds.
+    pub cleared_at: u64,
+    pub payments: f64,
+    pub nav_at_clearing: Option<f64>,
+    pub premium: Option<f64>,
+    pub latest_nav: Option<f64>,
+    pub nav_return: Option<f64>,
+}
+
+impl AuctionPerformance {
+    /// The performance of `outcome` against `history`, its basket's snapshots in time order.
+    /// The NAV at clearing is the last snapshot at or before it, to the second.
+    pub fn of(outcome: &AuctionOutcome, history: &[NavSnapshot]) -> Self {
+        let cleared_at = outcome.report.metadata.timestamp * 1_000;
+        let payments = outcome.report.total_payments();
+        let nav_at_clearing = history.iter().rev().find(|snapshot| snapshot.at <= cleared_at).map(|snapshot| snapshot.nav);
+        let latest_nav = history.last().filter(|snapshot| snapshot.at >= cleared_at).map(|snapshot| snapshot.nav);
+        let ratio = |numerator: f64, nav: f64| (nav > 0.0).then(|| numerator / nav - 1.0);
+        AuctionPerformance {
+            auction_id: outcome.auction_id,
+            basket_id: outcome.basket_id,
+            cleared_at,
+            payments,
+            nav_at_clearing,
+            premium: nav_at_clearing.and_then(|nav| ratio(payments, nav)),
+            latest_nav,
+            nav_return: nav_at_clearing.zip(latest_nav).and_then(|(then, now)| ratio(now, then)),
+        }
+    }
+}
+
+/// Marks `exchange` at every price `feed` streams, which also drives its NAV snapshots.
+/// The feed tracks every basket on the exchange, checked for new ones every `rescan`.
+pub async fn mark_from_feed(feed: Arc<PriceFeedService>, exchange: SharedExchange, rescan: Duration) {
+    let mut revaluations = feed.subscribe();
+    let mut rescans = tokio::time::interval(rescan);
+    loop {
+        tokio::select! {
+            revaluation = revaluations.recv() => match revaluation {
+                Ok(revaluation) => exchange.lock().unwrap().update_mark(&revaluation.asset.base, revaluation.price),
+                // The marks skipped are superseded by the ones still to come
+                Err(RecvError::Lagged(skipped)) => tracing::warn!(skipped, "price feed lagged"),
+                Err(RecvError::Closed) => return,
+            },
+            _ = rescans.tick() => {
+                let baskets: Vec<Basket> = exchange.lock().unwrap().baskets().into_iter().cloned().collect();
+                for basket in baskets.into_iter().filter(|basket| feed.basket(basket.id).is_none()) {
+                    feed.add_basket(basket);
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use auction::sim::SimClock;
+    use quanto_pricer::stream::TickerUpdate;
+    use model::model::{Asset, AssetInfo, BidType};
+    use crate::exchange::{AuctionRequest, Exchange, Mechanism};
+    use crate::storage::{MemoryRepository, Repository, SledRepository};
+
+    #[test]
+    fn test_marks_drive_nav_snapshots_and_auction_performance() {
+        let clock = SimClock::new(1_700_000_000_000);
+        let repository = Arc::new(SledRepository::temporary().unwrap());
+        let mut exchange = Exchange::recover(repository.clone()).unwrap()
+            .with_clock(Arc::new(clock.clone()))
+            .with_nav_interval(Duration::from_secs(60));
+        let alice = exchange.register_user("Alice", 100_000.0).unwrap().id;
+        let basket = exchange.create_basket(vec![
+            AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60_000.0),
+            AssetInfo::new(Asset::new("ETH", "USD"), 2.0, 3_000.0),
+        ]).unwrap().id;
+
+        // No snapshot until every asset is marked, and at most one a minute
+        exchange.update_mark("BTC", 60_000.0);
+        exchange.update_mark("ETH", 3_000.0);
+        assert!(exchange.nav_history(basket, None, None).unwrap().is_empty());
+        clock.advance(Duration::from_secs(60));
+        exchange.update_mark("BTC", 61_000.0);
+        clock.advance(Duration::from_secs(30));
+        exchange.update_mark("BTC", 62_000.0);
+        assert_eq!(exchange.nav_history(basket, None, None).unwrap(), vec![NavSnapshot { basket_id: basket, at: 1_700_000_060_000, nav: 67_000.0 }]);
+
+        exchange.submit_bid(alice, basket, BidType::XOR, 70_350.0, None).unwrap();
+        let outcome = exchange.run_auction(&AuctionRequest::new(basket, Mechanism::Xor)).unwrap();
+        clock.advance(Duration::from_secs(30));
+        exchange.update_mark("ETH", 3_500.0);
+        let performance = exchange.auction_performance(outcome.auction_id).unwrap();
+        assert_eq!((performance.nav_at_clearing, performance.latest_nav), (Some(67_000.0), Some(69_000.0)));
+        assert!((performance.premium.unwrap() - 0.05).abs() < 1e-12);
+        assert!((performance.nav_return.unwrap() - 2_000.0 / 67_000.0).abs() < 1e-12);
+        assert_eq!(exchange.nav_history(basket, Some(1_700_000_061_000), None).unwrap().len(), 1);
+        assert!(exchange.nav_history(BasketId(99), None, None).is_err());
+
+        // Snapshots are stored with the rest of the exchange
+        let recovered = Exchange::recover(repository.clone()).unwrap();
+        assert_eq!(recovered.nav_history(basket, None, None).unwrap().len(), 2);
+        let memory = MemoryRepository::new();
+        memory.save_navs(&recovered.nav_history(basket, None, None).unwrap()).unwrap();
+        assert_eq!(memory.load().unwrap().navs, repository.load().unwrap().navs);
+    }
+
+    #[tokio::test]
+    async fn test_feed_marks_the_exchange() {
+        let mut exchange = Exchange::new().with_nav_interval(Duration::from_secs(60));
+        let basket = exchange.create_basket(vec![AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 60_000.0)]).unwrap().id;
+        let exchange = Arc::new(std::sync::Mutex::new(exchange));
+        let feed = Arc::new(PriceFeedService::new(16));
+        let marking = tokio::spawn(mark_from_feed(feed.clone(), exchange.clone(), Duration::from_millis(10)));
+        while feed.basket(basket).is_none() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let ticker = TickerUpdate {
+            instrument_name: "BTC-PERPETUAL".to_string(),
+            timestamp: 1_700_000_000_000,
+            mark_price: 61_000.0,
+            mark_iv: None,
+            index_price: Some(61_000.0),
+            underlying_price: None,
+            best_bid_price: None,
+            best_ask_price: None,
+            greeks: None,
+            received_at: None,
+        };
+        feed.apply(&ticker);
+        while exchange.lock().unwrap().nav_history(basket, None, None).unwrap().is_empty() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(exchange.lock().unwrap().nav_history(basket, None, None).unwrap()[0].nav, 122_000.0);
+        marking.abort();
+    }
+}
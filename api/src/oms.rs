@@ -0,0 +1,197 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use model::ids::{AuctionId, BasketId, BidId, UserId};
+use auction::observer::{AuctionEvent, OrderState};
+use crate::admin::AdminAction;
+use crate::event_store::ExchangeEvent;
+use crate::exchange::BidRecord;
+
+
+/// One step of an order's lifecycle.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderTransition {
+    pub state: OrderState,
+    /// Unix milliseconds.
+    pub at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// A bid and everything that happened to it, by bid id.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Order {
+    pub bid_id: BidId,
+    pub user_id: UserId,
+    pub basket_id: BasketId,
+    /// The bid as last submitted or amended; none if it was rejected.
+    pub bid: Option<BidRecord>,
+    /// The auction that filled or expired it.
+    pub auction_id: Option<AuctionId>,
+    pub state: OrderState,
+    pub history: Vec<OrderTransition>,
+}
+
+
+/// Tracks every bid from submission to the end of its auction. It is driven by the
+/// exchange's events, so replaying the journal rebuilds it along with the rest.
+#[derive(Debug, Clone, Default)]
+pub struct OrderManager {
+    orders: BTreeMap<BidId, Order>,
+}
+
+impl OrderManager {
+    pub fn new() -> Self {
+        OrderManager::default()
+    }
+
+    /// Orders for bids recovered without their history, as active since `at`.
+    pub(crate) fn resting<'a>(bids: impl IntoIterator<Item = &'a BidRecord>, at: u64) -> Self {
+        let mut manager = OrderManager::new();
+        for bid in bids {
+            manager.open(bid.id, bid.user_id, bid.basket_id, Some(bid.clone()), at);
+            manager.transition(bid.id, OrderState::Active, at, None);
+        }
+        manager
+    }
+
+    pub fn order(&self, bid_id: BidId) -> Option<&Order> {
+        self.orders.get(&bid_id)
+    }
+
+    pub fn for_user(&self, user_id: UserId) -> Vec<&Order> {
+        self.orders.values().filter(|order| order.user_id == user_id).collect()
+    }
+
+    /// When the order last changed, in Unix milliseconds.
+    pub fn last_change(&self, bid_id: BidId) -> Option<u64> {
+        self.orders.get(&bid_id)?.history.last().map(|transition| transition.at)
+    }
+
+    pub fn for_auction(&self, auction_id: AuctionId) -> Vec<&Order> {
+        self.orders.values().filter(|order| order.auction_id == Some(auction_id)).collect()
+    }
+
+    fn open(&mut self, bid_id: BidId, user_id: UserId, basket_id: BasketId, bid: Option<BidRecord>, at: u64) {
+        let history = vec![OrderTransition { state: OrderState::PendingNew, at, reason: None }];
+        self.orders.insert(bid_id, Order { bid_id, user_id, basket_id, bid, auction_id: None, state: OrderState::PendingNew, history });
+    }
+
+    /// Moves an order to `state`; orders already finished, and unknown ones, are left
+    /// alone. Returns the event announcing the move.
+    fn transition(&mut self, bid_id: BidId, state: OrderState, at: u64, reason: Option<String>) -> Option<AuctionEvent> {
+        let order = self.orders.get_mut(&bid_id).filter(|order| !order.state.is_terminal())?;
+        order.state = state;
+        order.history.push(OrderTransition { state, at, reason });
+        Some(AuctionEvent::OrderUpdated { basket_id: order.basket_id, bid_id, user_id: order.user_id, state })
+    }
+
+    /// Follows `event` through the lifecycle of the orders it touches, returning the
+    /// updates to announce.
+    pub(crate) fn apply(&mut self, event: &ExchangeEvent, at: u64) -> Vec<AuctionEvent> {
+        match event {
+            ExchangeEvent::BidSubmitted { bid } => {
+                self.open(bid.id, bid.user_id, bid.basket_id, Some(bid.clone()), at);
+                self.transition(bid.id, OrderState::Active, at, None).into_iter().collect()
+            }
+            ExchangeEvent::BidRejected { bid_id, user_id, basket_id, reason } => {
+                self.open(*bid_id, *user_id, *basket_id, None, at);
+                self.transition(*bid_id, OrderState::Rejected, at, Some(reason.clone())).into_iter().collect()
+            }
+            ExchangeEvent::BidAmended { bid } => {
+                if let Some(order) = self.orders.get_mut(&bid.id) {
+                    order.bid = Some(bid.clone());
+                }
+                self.transition(bid.id, OrderState::Amended, at, None).into_iter().collect()
+            }
+            ExchangeEvent::BidCancelled { bid_id } => self.transition(*bid_id, OrderState::Cancelled, at, None).into_iter().collect(),
+            ExchangeEvent::WinnersSelected { auction_id, winning_bids, .. } => winning_bids.iter()
+                .filter_map(|bid_id| {
+                    let update = self.transition(*bid_id, OrderState::ProvisionalWinner, at, None)?;
+                    self.orders.get_mut(bid_id)?.auction_id = Some(*auction_id);
+                    Some(update)
+                })
+                .collect(),
+            ExchangeEvent::Settled { outcome, .. } => {
+                let consumed: Vec<BidId> = self.orders.values()
+                    .filter(|order| order.basket_id == outcome.basket_id && !order.state.is_terminal())
+                    .map(|order| order.bid_id)
+                    .collect();
+                let mut updates = Vec::new();
+                for bid_id in consumed {
+                    let update = if outcome.winning_bids.contains(&bid_id) {
+                        self.transition(bid_id, OrderState::Filled, at, None)
+                    } else {
+                        self.transition(bid_id, OrderState::Expired, at, Some("lost the auction".to_string()))
+                    };
+                    if let Some(order) = self.orders.get_mut(&bid_id) {
+                        order.auction_id = Some(outcome.auction_id);
+                    }
+                    updates.extend(update);
+                }
+                updates
+            }
+            ExchangeEvent::Admin { action: AdminAction::Cancel { cancelled_bids, .. }, .. } => cancelled_bids.iter()
+                .filter_map(|bid_id| self.transition(*bid_id, OrderState::Cancelled, at, Some("auction cancelled by an operator".to_string())))
+                .collect(),
+            ExchangeEvent::Admin { action: AdminAction::ExpireStaleBids { expired_bids, .. }, .. } => expired_bids.iter()
+                .filter_map(|bid_id| self.transition(*bid_id, OrderState::Expired, at, Some("expired by an operator".to_string())))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use auction::observer::AuctionObserver;
+    use model::model::{Asset, AssetInfo, BidType};
+    use crate::exchange::{AuctionRequest, Exchange, Mechanism};
+    use super::*;
+
+    #[derive(Default)]
+    struct Updates(Mutex<Vec<(BidId, OrderState)>>);
+
+    impl AuctionObserver for Updates {
+        fn on_event(&self, event: &AuctionEvent) {
+            if let AuctionEvent::OrderUpdated { bid_id, state, .. } = event {
+                self.0.lock().unwrap().push((*bid_id, *state));
+            }
+        }
+    }
+
+    fn states(order: &Order) -> Vec<OrderState> {
+        order.history.iter().map(|transition| transition.state).collect()
+    }
+
+    #[test]
+    fn test_orders_are_tracked_from_submission_to_settlement() {
+        let updates = Arc::new(Updates::default());
+        let mut exchange = Exchange::new();
+        exchange.add_observer(updates.clone());
+        let alice = exchange.register_user("Alice", 100_000.0).unwrap().id;
+        let bob = exchange.register_user("Bob", 100_000.0).unwrap().id;
+        let basket = exchange.create_basket(vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60_000.0)]).unwrap().id;
+        let winner = exchange.submit_bid(alice, basket, BidType::XOR, 61_000.0, None).unwrap().id;
+        exchange.amend_bid(winner, 63_000.0, None).unwrap();
+        let loser = exchange.submit_bid(bob, basket, BidType::XOR, 62_000.0, None).unwrap().id;
+        let cancelled = exchange.submit_bid(bob, basket, BidType::XOR, 1_000.0, None).unwrap().id;
+        exchange.cancel_bid(cancelled).unwrap();
+        assert!(exchange.submit_bid(bob, basket, BidType::XOR, -5.0, None).is_err());
+        let outcome = exchange.run_auction(&AuctionRequest::new(basket, Mechanism::Xor)).unwrap();
+
+        let orders = exchange.orders();
+        use OrderState::*;
+        assert_eq!(states(orders.order(winner).unwrap()), [PendingNew, Active, Amended, ProvisionalWinner, Filled]);
+        assert_eq!(orders.order(winner).unwrap().bid.as_ref().map(|bid| bid.price), Some(63_000.0));
+        assert_eq!(states(orders.order(loser).unwrap()), [PendingNew, Active, Expired]);
+        assert_eq!(states(orders.order(cancelled).unwrap()), [PendingNew, Active, Cancelled]);
+        let rejected = orders.for_user(bob).into_iter().find(|order| order.state == Rejected).unwrap();
+        assert_eq!(rejected.history[1].reason.as_deref(), Some("price must be positive"));
+        let auctioned: Vec<BidId> = orders.for_auction(outcome.auction_id).iter().map(|order| order.bid_id).collect();
+        assert_eq!(auctioned, [winner, loser]);
+        assert_eq!(updates.0.lock().unwrap().last(), Some(&(loser, Expired)));
+        assert!(updates.0.lock().unwrap().contains(&(winner, Filled)));
+    }
+}
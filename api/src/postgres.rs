@@ -0,0 +1,327 @@
+use sqlx::migrate::Migrator;
+use sqlx::postgres::{PgPool, PgPoolOptions, PgRow};
+use sqlx::types::Json;
+use sqlx::Row;
+use auction::ledger::JournalEntry;
+use model::ids::{BasketId, BidId, UserId};
+use model::model::{AssetInfo, Basket, BidType, User};
+use crate::exchange::{AuctionOutcome, BasketControls, BidRecord};
+use crate::nav::NavSnapshot;
+use crate::storage::{AsyncRepository, StorageError, StorageFuture, StoredExchange};
+
+/// The schema, applied in order by [`PostgresRepository::migrate`].
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+
+impl From<sqlx::Error> for StorageError {
+    fn from(error: sqlx::Error) -> Self {
+        match error {
+            sqlx::Error::ColumnDecode { .. } | sqlx::Error::Decode(_) => StorageError::Corrupt(error.to_string()),
+            other => StorageError::Backend(other.to_string()),
+        }
+    }
+}
+
+/// Postgres has no unsigned integers; ids are stored as BIGINT.
+fn to_sql(id: impl Into<u64>) -> i64 {
+    id.into() as i64
+}
+
+fn from_sql<T: From<u64>>(row: &PgRow, column: &str) -> Result<T, StorageError> {
+    Ok(T::from(row.try_get::<i64, _>(column)? as u64))
+}
+
+fn bid_type_name(bid_type: &BidType) -> &'static str {
+    match bid_type {
+        BidType::XOR => "XOR",
+        BidType::OR => "OR",
+    }
+}
+
+fn parse_bid_type(name: &str) -> Result<BidType, StorageError> {
+    match name {
+        "XOR" => Ok(BidType::XOR),
+        "OR" => Ok(BidType::OR),
+        other => Err(StorageError::Corrupt(format!("unknown bid type {}", other))),
+    }
+}
+
+/// Fails with a conflict when `rows` is zero, i.e. an optimistic check did not hold.
+fn expect_row(rows: u64, conflict: impl FnOnce() -> String) -> Result<(), StorageError> {
+    if rows == 0 {
+        return Err(StorageError::Conflict(conflict()));
+    }
+    Ok(())
+}
+
+
+/// Exchange state in Postgres, for deployments that run several exchange processes over
+/// one database. Baskets carry a version that every write bumps, and clearing an auction
+/// claims its basket in the same transaction that records the outcome, so two processes
+/// can never settle the same basket.
+#[derive(Debug, Clone)]
+pub struct PostgresRepository {
+    pool: PgPool,
+}
+
+impl PostgresRepository {
+    pub async fn connect(url: &str) -> Result<Self, StorageError> {
+        let pool = PgPoolOptions::new().max_connections(8).connect(url).await?;
+        Ok(PostgresRepository { pool })
+    }
+
+    pub fn from_pool(pool: PgPool) -> Self {
+        PostgresRepository { pool }
+    }
+
+    /// Brings the schema up to date.
+    pub async fn migrate(&self) -> Result<(), StorageError> {
+        MIGRATOR.run(&self.pool).await.map_err(|error| StorageError::Backend(error.to_string()))
+    }
+
+    pub async fn basket_version(&self, basket_id: BasketId) -> Result<Option<u64>, StorageError> {
+        let row = sqlx::query("SELECT version FROM baskets WHERE id = $1")
+            .bind(to_sql(basket_id))
+            .fetch_optional(&self.pool)
+            .await?;
+        row.map(|row| from_sql(&row, "version")).transpose()
+    }
+
+    /// Replaces the assets of a basket last seen at `expected_version` and returns its new
+    /// version. Fails with a conflict if it has changed since or has been auctioned.
+    pub async fn update_basket(&self, basket: &Basket, expected_version: u64) -> Result<u64, StorageError> {
+        let row = sqlx::query("UPDATE baskets SET assets = $2, version = version + 1 WHERE id = $1 AND version = $3 AND NOT auctioned RETURNING version")
+            .bind(to_sql(basket.id))
+            .bind(Json(&basket.assets))
+            .bind(to_sql(expected_version))
+            .fetch_optional(&self.pool)
+            .await?;
+        match row {
+            Some(row) => from_sql(&row, "version"),
+            None => Err(StorageError::Conflict(format!("basket {} is no longer at version {}", basket.id, expected_version))),
+        }
+    }
+}
+
+impl AsyncRepository for PostgresRepository {
+    fn save_user<'a>(&'a self, user: &'a User) -> StorageFuture<'a, ()> {
+        Box::pin(async move {
+            sqlx::query("INSERT INTO users (id, name, balance) VALUES ($1, $2, $3) ON CONFLICT (id) DO UPDATE SET name = EXCLUDED.name, balance = EXCLUDED.balance")
+                .bind(to_sql(user.id))
+                .bind(&user.name)
+                .bind(user.balance)
+                .execute(&self.pool)
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn save_basket<'a>(&'a self, basket: &'a Basket) -> StorageFuture<'a, ()> {
+        Box::pin(async move {
+            let result = sqlx::query("INSERT INTO baskets (id, assets) VALUES ($1, $2) ON CONFLICT (id) DO UPDATE SET assets = EXCLUDED.assets, version = baskets.version + 1 WHERE NOT baskets.auctioned")
+                .bind(to_sql(basket.id))
+                .bind(Json(&basket.assets))
+                .execute(&self.pool)
+                .await?;
+            expect_row(result.rows_affected(), || format!("basket {} has been auctioned", basket.id))
+        })
+    }
+
+    fn save_bid<'a>(&'a self, bid: &'a BidRecord) -> StorageFuture<'a, ()> {
+        Box::pin(async move {
+            // Only rest bids on baskets no other process has auctioned
+            let result = sqlx::query(
+                "INSERT INTO bids (id, user_id, basket_id, bid_type, price, quantity) \
+                 SELECT $1, $2, $3, $4, $5, $6 WHERE EXISTS (SELECT 1 FROM baskets WHERE id = $3 AND NOT auctioned) \
+                 ON CONFLICT (id) DO UPDATE SET price = EXCLUDED.price, quantity = EXCLUDED.quantity")
+                .bind(to_sql(bid.id))
+                .bind(to_sql(bid.user_id))
+                .bind(to_sql(bid.basket_id))
+                .bind(bid_type_name(&bid.bid_type))
+                .bind(bid.price)
+                .bind(bid.quantity)
+                .execute(&self.pool)
+                .await?;
+            expect_row(result.rows_affected(), || format!("basket {} has been auctioned", bid.basket_id))
+        })
+    }
+
+    fn delete_bid(&self, bid_id: BidId) -> StorageFuture<'_, ()> {
+        Box::pin(async move {
+            sqlx::query("DELETE FROM bids WHERE id = $1").bind(to_sql(bid_id)).execute(&self.pool).await?;
+            Ok(())
+        })
+    }
+
+    fn save_auction<'a>(&'a self, outcome: &'a AuctionOutcome, users: &'a [User], consumed_bids: &'a [BidId], entries: &'a [JournalEntry]) -> StorageFuture<'a, ()> {
+        Box::pin(async move {
+            // Dropping the transaction on any error rolls it back
+            let mut transaction = self.pool.begin().await?;
+            let claimed = sqlx::query("UPDATE baskets SET auctioned = TRUE, version = version + 1 WHERE id = $1 AND NOT auctioned")
+                .bind(to_sql(outcome.basket_id))
+                .execute(&mut *transaction)
+                .await?;
+            expect_row(claimed.rows_affected(), || format!("basket {} has already been auctioned", outcome.basket_id))?;
+
+            sqlx::query("INSERT INTO auction_outcomes (auction_id, settlement_id, basket_id, mechanism, outcome) VALUES ($1, $2, $3, $4, $5)")
+                .bind(to_sql(outcome.auction_id))
+                .bind(to_sql(outcome.report.metadata.settlement_id))
+                .bind(to_sql(outcome.basket_id))
+                .bind(outcome.mechanism.name())
+                .bind(Json(outcome))
+                .execute(&mut *transaction)
+                .await?;
+            for user in users {
+                sqlx::query("UPDATE users SET balance = $2 WHERE id = $1")
+                    .bind(to_sql(user.id))
+                    .bind(user.balance)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+            for entry in entries {
+                sqlx::query("INSERT INTO ledger_entries (id, entry) VALUES ($1, $2)")
+                    .bind(to_sql(entry.id))
+                    .bind(Json(entry))
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+            let consumed: Vec<i64> = consumed_bids.iter().map(|id| to_sql(*id)).collect();
+            sqlx::query("DELETE FROM bids WHERE id = ANY($1)").bind(&consumed).execute(&mut *transaction).await?;
+            transaction.commit().await?;
+            Ok(())
+        })
+    }
+
+    fn save_navs<'a>(&'a self, snapshots: &'a [NavSnapshot]) -> StorageFuture<'a, ()> {
+        Box::pin(async move {
+            let basket_ids: Vec<i64> = snapshots.iter().map(|snapshot| to_sql(snapshot.basket_id)).collect();
+            let times: Vec<i64> = snapshots.iter().map(|snapshot| to_sql(snapshot.at)).collect();
+            let navs: Vec<f64> = snapshots.iter().map(|snapshot| snapshot.nav).collect();
+            sqlx::query("INSERT INTO nav_snapshots (basket_id, at, nav) SELECT * FROM UNNEST($1::BIGINT[], $2::BIGINT[], $3::DOUBLE PRECISION[]) ON CONFLICT (basket_id, at) DO UPDATE SET nav = EXCLUDED.nav")
+                .bind(&basket_ids)
+                .bind(&times)
+                .bind(&navs)
+                .execute(&self.pool)
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn save_controls<'a>(&'a self, controls: &'a BasketControls) -> StorageFuture<'a, ()> {
+        Box::pin(async move {
+            sqlx::query("INSERT INTO basket_controls (basket_id, closes_at, extensions) VALUES ($1, $2, $3) ON CONFLICT (basket_id) DO UPDATE SET closes_at = EXCLUDED.closes_at, extensions = EXCLUDED.extensions")
+                .bind(to_sql(controls.basket_id))
+                .bind(controls.closes_at.map(to_sql))
+                .bind(controls.extensions as i32)
+                .execute(&self.pool)
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn save_halted(&self, basket_id: BasketId, halted: bool) -> StorageFuture<'_, ()> {
+        Box::pin(async move {
+            let query = if halted {
+                "INSERT INTO halted_baskets (basket_id) VALUES ($1) ON CONFLICT (basket_id) DO NOTHING"
+            } else {
+                "DELETE FROM halted_baskets WHERE basket_id = $1"
+            };
+            sqlx::query(query).bind(to_sql(basket_id)).execute(&self.pool).await?;
+            Ok(())
+        })
+    }
+
+    fn load(&self) -> StorageFuture<'_, StoredExchange> {
+        Box::pin(async move {
+            let users = sqlx::query("SELECT id, name, balance FROM users ORDER BY id").fetch_all(&self.pool).await?
+                .iter()
+                .map(|row| Ok(User::new(from_sql::<UserId>(row, "id")?, row.try_get("name")?, row.try_get("balance")?)))
+                .collect::<Result<_, StorageError>>()?;
+            let baskets = sqlx::query("SELECT id, assets FROM baskets ORDER BY id").fetch_all(&self.pool).await?
+                .iter()
+                .map(|row| {
+                    let Json(assets): Json<Vec<AssetInfo>> = row.try_get("assets")?;
+                    Ok(Basket { id: from_sql(row, "id")?, assets })
+                })
+                .collect::<Result<_, StorageError>>()?;
+            let bids = sqlx::query("SELECT id, user_id, basket_id, bid_type, price, quantity FROM bids ORDER BY id").fetch_all(&self.pool).await?
+                .iter()
+                .map(|row| Ok(BidRecord {
+                    id: from_sql(row, "id")?,
+                    user_id: from_sql(row, "user_id")?,
+                    basket_id: from_sql(row, "basket_id")?,
+                    bid_type: parse_bid_type(row.try_get("bid_type")?)?,
+                    price: row.try_get("price")?,
+                    quantity: row.try_get("quantity")?,
+                }))
+                .collect::<Result<_, StorageError>>()?;
+            let outcomes = sqlx::query("SELECT outcome FROM auction_outcomes ORDER BY auction_id").fetch_all(&self.pool).await?
+                .iter()
+                .map(|row| Ok(row.try_get::<Json<AuctionOutcome>, _>("outcome")?.0))
+                .collect::<Result<_, StorageError>>()?;
+            let navs = sqlx::query("SELECT basket_id, at, nav FROM nav_snapshots ORDER BY basket_id, at").fetch_all(&self.pool).await?
+                .iter()
+                .map(|row| Ok(NavSnapshot { basket_id: from_sql(row, "basket_id")?, at: from_sql(row, "at")?, nav: row.try_get("nav")? }))
+                .collect::<Result<_, StorageError>>()?;
+            let controls = sqlx::query("SELECT basket_id, closes_at, extensions FROM basket_controls ORDER BY basket_id").fetch_all(&self.pool).await?
+                .iter()
+                .map(|row| Ok(BasketControls {
+                    basket_id: from_sql(row, "basket_id")?,
+                    closes_at: row.try_get::<Option<i64>, _>("closes_at")?.map(|at| at as u64),
+                    extensions: row.try_get::<i32, _>("extensions")? as u32,
+                }))
+                .collect::<Result<_, StorageError>>()?;
+            let ledger = sqlx::query("SELECT entry FROM ledger_entries ORDER BY id").fetch_all(&self.pool).await?
+                .iter()
+                .map(|row| Ok(row.try_get::<Json<JournalEntry>, _>("entry")?.0))
+                .collect::<Result<_, StorageError>>()?;
+            let halted = sqlx::query("SELECT basket_id FROM halted_baskets ORDER BY basket_id").fetch_all(&self.pool).await?
+                .iter()
+                .map(|row| from_sql(row, "basket_id"))
+                .collect::<Result<_, StorageError>>()?;
+            Ok(StoredExchange { users, baskets, bids, outcomes, navs, controls, ledger, halted })
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use model::model::Asset;
+    use crate::exchange::{AuctionRequest, Exchange, Mechanism};
+    use crate::storage::BlockingRepository;
+
+    #[test]
+    fn test_bid_types_round_trip() {
+        for bid_type in [BidType::XOR, BidType::OR] {
+            assert_eq!(parse_bid_type(bid_type_name(&bid_type)).unwrap(), bid_type);
+        }
+        assert!(matches!(parse_bid_type("AND"), Err(StorageError::Corrupt(_))));
+        assert!(matches!(expect_row(0, || "taken".to_string()), Err(StorageError::Conflict(_))));
+    }
+
+    /// Needs a scratch database in `DATABASE_URL`.
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore]
+    async fn test_second_process_cannot_clear_the_same_basket() {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL");
+        let repository = PostgresRepository::connect(&url).await.unwrap();
+        repository.migrate().await.unwrap();
+        let handle = tokio::runtime::Handle::current();
+
+        let mut first = Exchange::recover(Arc::new(BlockingRepository::new(repository.clone(), handle.clone()))).unwrap();
+        let alice = first.register_user("Alice", 100_000.0).unwrap().id;
+        let basket = first.create_basket(vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60_000.0)]).unwrap();
+        first.submit_bid(alice, basket.id, BidType::XOR, 61_000.0, None).unwrap();
+        assert_eq!(repository.basket_version(basket.id).await.unwrap(), Some(1));
+
+        // A second process recovered before the first one clears
+        let mut second = Exchange::recover(Arc::new(BlockingRepository::new(repository.clone(), handle))).unwrap();
+        first.run_auction(&AuctionRequest::new(basket.id, Mechanism::Xor)).unwrap();
+        let error = second.run_auction(&AuctionRequest::new(basket.id, Mechanism::Xor)).unwrap_err();
+        assert_eq!(error, crate::error::ApiError::Conflict("stored state was changed by another writer"));
+        assert!(matches!(repository.update_basket(&basket, 1).await, Err(StorageError::Conflict(_))));
+    }
+}
@@ -0,0 +1,48 @@
+use axum::routing::get;
+use axum::Router;
+use metrics_exporter_prometheus::{BuildError, PrometheusBuilder, PrometheusHandle};
+
+
+/// Installs a Prometheus recorder for the whole process, so the metrics named in
+/// [`auction::stats`] are collected from then on. Fails if a recorder is already installed.
+pub fn install() -> Result<PrometheusHandle, BuildError> {
+    PrometheusBuilder::new().install_recorder()
+}
+
+/// `GET /metrics` in the Prometheus text format.
+pub fn router(handle: PrometheusHandle) -> Router {
+    Router::new().route("/metrics", get(move || async move { handle.render() }))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+    use model::model::{Asset, AssetInfo, BidType};
+    use auction::stats::{AUCTION_BIDS, BIDS_SUBMITTED, WDP_SOLVE_SECONDS};
+    use crate::exchange::{AuctionRequest, Exchange, Mechanism};
+
+    #[tokio::test]
+    async fn test_metrics_endpoint_reports_auctions() {
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        metrics::with_local_recorder(&recorder, || {
+            let mut exchange = Exchange::new();
+            let alice = exchange.register_user("Alice", 100_000.0).unwrap().id;
+            let basket = exchange.create_basket(vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60_000.0)]).unwrap().id;
+            exchange.submit_bid(alice, basket, BidType::OR, 61_000.0, Some(1.0)).unwrap();
+            exchange.run_auction(&AuctionRequest::new(basket, Mechanism::Vcg)).unwrap();
+        });
+
+        let response = router(handle).oneshot(Request::get("/metrics").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = String::from_utf8(response.into_body().collect().await.unwrap().to_bytes().to_vec()).unwrap();
+        assert!(body.contains(&format!("{} 1", BIDS_SUBMITTED)), "{}", body);
+        assert!(body.contains(&format!("{}_count{{mechanism=\"VCG\"}} 1", AUCTION_BIDS)), "{}", body);
+        assert!(body.contains(WDP_SOLVE_SECONDS), "{}", body);
+    }
+}
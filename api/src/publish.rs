@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use auction::config::PublishConfig;
+use auction::observer::{AuctionEvent, AuctionObserver};
+use auction::stats::{EVENTS_PUBLISHED, EVENTS_PUBLISH_FAILED, EVENTS_PUBLISH_RETRIES};
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PublishError {
+    /// The broker could not be reached or refused the message; worth retrying.
+    Unavailable(String),
+    /// The publisher is misconfigured, e.g. built without support for its backend.
+    Config(String),
+}
+
+impl fmt::Display for PublishError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PublishError::Unavailable(message) => write!(f, "broker unavailable: {}", message),
+            PublishError::Config(message) => write!(f, "publisher misconfigured: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for PublishError {}
+
+
+/// One event on its way to a broker, as JSON.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub topic: String,
+    /// The basket auctioned, which names the auction since a basket is auctioned once.
+    /// Messages with one key are delivered in order.
+    pub key: u64,
+    /// Position among the messages with this key, from 1, whatever their topic; a
+    /// consumer seeing a gap knows a message was given up on.
+    pub sequence: u64,
+    pub payload: Vec<u8>,
+}
+
+pub type PublishFuture<'a> = Pin<Box<dyn Future<Output = Result<(), PublishError>> + Send + 'a>>;
+
+/// Extension point for shipping events to systems outside the exchange, such as risk,
+/// accounting and analytics. A publisher delivers one message at a time and reports
+/// whether the broker took it; retries and ordering are left to [`EventStream`].
+pub trait EventPublisher: Send + Sync {
+    fn publish<'a>(&'a self, message: &'a Message) -> PublishFuture<'a>;
+}
+
+
+/// Topics under a prefix: clock rounds, eliminations, provisional allocations and
+/// winners go to `<prefix>.auctions`, order updates including fills to
+/// `<prefix>.orders` and settlement reports to `<prefix>.settlements`.
+pub fn topic(prefix: &str, event: &AuctionEvent) -> String {
+    let name = match event {
+        AuctionEvent::OrderUpdated { .. } => "orders",
+        AuctionEvent::Settled { .. } => "settlements",
+        _ => "auctions",
+    };
+    format!("{}.{}", prefix, name)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Retry {
+    max_attempts: u32,
+    backoff: Duration,
+}
+
+/// Tries `message` until the publisher takes it or the attempts run out.
+async fn deliver(publisher: &dyn EventPublisher, message: &Message, retry: Retry) {
+    let mut backoff = retry.backoff;
+    for attempt in 1..=retry.max_attempts {
+        match publisher.publish(message).await {
+            Ok(()) => {
+                metrics::counter!(EVENTS_PUBLISHED, "topic" => message.topic.clone()).increment(1);
+                return;
+            }
+            Err(error) if attempt < retry.max_attempts && !matches!(error, PublishError::Config(_)) => {
+                tracing::warn!(topic = %message.topic, key = message.key, sequence = message.sequence, attempt, %error, "retrying event delivery");
+                metrics::counter!(EVENTS_PUBLISH_RETRIES).increment(1);
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(error) => {
+                tracing::error!(topic = %message.topic, key = message.key, sequence = message.sequence, attempt, %error, "giving up on event");
+                break;
+            }
+        }
+    }
+    metrics::counter!(EVENTS_PUBLISH_FAILED, "topic" => message.topic.clone()).increment(1);
+}
+
+
+/// Observes the exchange and streams its events to a publisher in the background, so a
+/// slow broker never holds up an auction. Events are spread over workers by key, each
+/// delivering its messages one after another, so an auction's events arrive in the order
+/// they happened while other auctions' go on in parallel.
+pub struct EventStream {
+    prefix: String,
+    workers: Vec<mpsc::UnboundedSender<Message>>,
+    sequences: Mutex<HashMap<u64, u64>>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl EventStream {
+    /// Starts `workers` delivery tasks on the current runtime.
+    pub fn spawn(publisher: Arc<dyn EventPublisher>, config: &PublishConfig, workers: usize) -> Self {
+        let retry = Retry { max_attempts: config.max_attempts, backoff: Duration::from_millis(config.retry_backoff_ms) };
+        let (senders, handles) = (0..workers.max(1))
+            .map(|_| {
+                let (sender, mut receiver) = mpsc::unbounded_channel::<Message>();
+                let publisher = publisher.clone();
+                let handle = tokio::spawn(async move {
+                    while let Some(message) = receiver.recv().await {
+                        deliver(publisher.as_ref(), &message, retry).await;
+                    }
+                });
+                (sender, handle)
+            })
+            .unzip();
+        EventStream { prefix: config.topic_prefix.clone(), workers: senders, sequences: Mutex::new(HashMap::new()), handles }
+    }
+
+    /// Stops taking events and waits for those already taken to be delivered or given up on.
+    pub async fn shutdown(self) {
+        drop(self.workers);
+        for handle in self.handles {
+            let _ = handle.await;
+        }
+    }
+}
+
+impl AuctionObserver for EventStream {
+    fn on_event(&self, event: &AuctionEvent) {
+        let key = event.basket_id().get();
+        let payload = match serde_json::to_vec(event) {
+            Ok(payload) => payload,
+            Err(error) => {
+                tracing::error!(%error, "cannot encode event");
+                return;
+            }
+        };
+        // Numbering and queueing under one lock keeps the queue in sequence order
+        let mut sequences = self.sequences.lock().unwrap();
+        let sequence = sequences.entry(key).or_insert(0);
+        *sequence += 1;
+        let message = Message { topic: topic(&self.prefix, event), key, sequence: *sequence, payload };
+        // Workers only stop once the stream is shut down
+        let _ = self.workers[key as usize % self.workers.len()].send(message);
+    }
+}
+
+
+/// The publisher `config` names, connected. Backends the crate was built without are a
+/// configuration error.
+pub async fn connect(config: &PublishConfig) -> Result<Arc<dyn EventPublisher>, PublishError> {
+    match config.backend.as_str() {
+        #[cfg(feature = "kafka")]
+        "kafka" => Ok(Arc::new(crate::kafka::KafkaPublisher::connect(&config.servers()).await?)),
+        #[cfg(feature = "nats")]
+        "nats" => Ok(Arc::new(crate::nats::NatsPublisher::connect(&config.servers()).await?)),
+        other => Err(PublishError::Config(format!("built without support for {}", other))),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use model::model::{Asset, AssetInfo, BidType};
+    use crate::exchange::{AuctionRequest, Exchange, Mechanism};
+    use super::*;
+
+    /// Refuses every message the first time it is offered.
+    #[derive(Default)]
+    struct Flaky {
+        offered: Mutex<Vec<(u64, u64)>>,
+        delivered: Mutex<Vec<Message>>,
+    }
+
+    impl EventPublisher for Flaky {
+        fn publish<'a>(&'a self, message: &'a Message) -> PublishFuture<'a> {
+            Box::pin(async move {
+                let mut offered = self.offered.lock().unwrap();
+                if !offered.contains(&(message.key, message.sequence)) {
+                    offered.push((message.key, message.sequence));
+                    return Err(PublishError::Unavailable("connection reset".to_string()));
+                }
+                self.delivered.lock().unwrap().push(message.clone());
+                Ok(())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_events_are_retried_and_stay_in_order_per_auction() {
+        let publisher = Arc::new(Flaky::default());
+        let config = PublishConfig { retry_backoff_ms: 1, ..PublishConfig::default() };
+        let stream = Arc::new(EventStream::spawn(publisher.clone(), &config, 4));
+        let mut exchange = Exchange::new();
+        exchange.add_observer(stream.clone());
+        let alice = exchange.register_user("Alice", 1_000_000.0).unwrap().id;
+        let bob = exchange.register_user("Bob", 1_000_000.0).unwrap().id;
+        let mut baskets = Vec::new();
+        for _ in 0..3 {
+            let basket = exchange.create_basket(vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60_000.0)]).unwrap().id;
+            exchange.submit_bid(alice, basket, BidType::XOR, 61_000.0, None).unwrap();
+            exchange.submit_bid(bob, basket, BidType::XOR, 62_000.0, None).unwrap();
+            baskets.push(basket);
+        }
+        for basket in &baskets {
+            exchange.run_auction(&AuctionRequest::new(*basket, Mechanism::Cca)).unwrap();
+        }
+        drop(exchange);
+        Arc::into_inner(stream).unwrap().shutdown().await;
+
+        let delivered = publisher.delivered.lock().unwrap();
+        for basket in baskets {
+            let messages: Vec<&Message> = delivered.iter().filter(|message| message.key == basket.get()).collect();
+            let sequences: Vec<u64> = messages.iter().map(|message| message.sequence).collect();
+            assert_eq!(sequences, (1..=messages.len() as u64).collect::<Vec<_>>());
+            assert_eq!(messages.last().unwrap().topic, "combidex.settlements");
+            assert!(messages.iter().any(|message| message.topic == "combidex.orders"));
+            let last: serde_json::Value = serde_json::from_slice(&messages.last().unwrap().payload).unwrap();
+            assert_eq!(last["event"], "settled");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_backends_are_a_configuration_error() {
+        let config = PublishConfig { backend: "carrier_pigeon".to_string(), ..PublishConfig::default() };
+        assert!(matches!(connect(&config).await, Err(PublishError::Config(_))));
+    }
+}
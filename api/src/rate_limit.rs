@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::Method;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use model::ids::UserId;
+use auction::config::{BidLimit, RateLimitConfig};
+use auction::sim::{Clock, SystemClock};
+use auction::stats::BIDS_RATE_LIMITED;
+use crate::error::ApiError;
+
+
+/// Where a basket's auction stands, as far as bidding on it is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AuctionPhase {
+    /// Bids rest until the auction is started.
+    Open,
+    /// The basket's CCA is running its clock rounds.
+    FinalRounds,
+}
+
+impl AuctionPhase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AuctionPhase::Open => "open",
+            AuctionPhase::FinalRounds => "final_rounds",
+        }
+    }
+}
+
+/// Buckets idle long enough to have refilled are dropped at most this often, in
+/// milliseconds.
+const SWEEP_INTERVAL_MILLIS: u64 = 60_000;
+
+/// Tokens left and when they were last counted, and when the bucket is full again, in
+/// Unix milliseconds.
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    counted_at: u64,
+    full_at: u64,
+}
+
+#[derive(Debug)]
+struct Buckets<K> {
+    by_key: HashMap<K, Bucket>,
+    swept_at: u64,
+}
+
+/// Token buckets by key, refilled lazily when taken from. A full bucket is the same as
+/// none, so buckets that have refilled are swept out as keys are taken from.
+#[derive(Debug)]
+pub struct RateLimiter<K> {
+    buckets: Mutex<Buckets<K>>,
+}
+
+impl<K> Default for RateLimiter<K> {
+    fn default() -> Self {
+        RateLimiter { buckets: Mutex::new(Buckets { by_key: HashMap::new(), swept_at: 0 }) }
+    }
+}
+
+impl<K: Eq + Hash> RateLimiter<K> {
+    /// Takes a token from `key`'s bucket under `limit`, if one is left at `now_millis`.
+    pub fn try_acquire(&self, key: K, limit: &BidLimit, now_millis: u64) -> bool {
+        let burst = limit.burst.max(1) as f64;
+        let mut buckets = self.buckets.lock().unwrap();
+        if now_millis >= buckets.swept_at.saturating_add(SWEEP_INTERVAL_MILLIS) {
+            buckets.by_key.retain(|_, bucket| bucket.full_at > now_millis);
+            buckets.swept_at = now_millis;
+        }
+        let bucket = buckets.by_key.entry(key).or_insert(Bucket { tokens: burst, counted_at: now_millis, full_at: now_millis });
+        let elapsed = now_millis.saturating_sub(bucket.counted_at) as f64 / 1000.0;
+        bucket.tokens = (bucket.tokens + elapsed * limit.per_second).min(burst);
+        bucket.counted_at = now_millis.max(bucket.counted_at);
+        let acquired = bucket.tokens >= 1.0;
+        if acquired {
+            bucket.tokens -= 1.0;
+        }
+        let refill_millis = (burst - bucket.tokens) / limit.per_second * 1000.0;
+        bucket.full_at = bucket.counted_at.saturating_add(refill_millis.ceil() as u64);
+        acquired
+    }
+}
+
+
+/// Per-user limits of the bid book, one bucket per user and phase.
+#[derive(Debug, Default)]
+pub struct UserRateLimits {
+    config: RateLimitConfig,
+    buckets: RateLimiter<(UserId, AuctionPhase)>,
+}
+
+impl UserRateLimits {
+    pub fn new(config: RateLimitConfig) -> Self {
+        UserRateLimits { config, buckets: RateLimiter::default() }
+    }
+
+    pub fn check(&self, user_id: UserId, phase: AuctionPhase, now_millis: u64) -> Result<(), ApiError> {
+        let limit = match phase {
+            AuctionPhase::Open => &self.config.per_user,
+            AuctionPhase::FinalRounds => &self.config.final_rounds,
+        };
+        if self.buckets.try_acquire((user_id, phase), limit, now_millis) {
+            Ok(())
+        } else {
+            metrics::counter!(BIDS_RATE_LIMITED, "limit" => "user", "phase" => phase.name()).increment(1);
+            Err(ApiError::TooManyRequests("too many bids from this user, slow down"))
+        }
+    }
+}
+
+
+/// Per-address limits of the API layer, for bid submissions, amendments and cancellations.
+pub struct IpRateLimits {
+    limit: BidLimit,
+    buckets: RateLimiter<IpAddr>,
+    clock: Arc<dyn Clock>,
+}
+
+impl fmt::Debug for IpRateLimits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IpRateLimits").field("limit", &self.limit).finish()
+    }
+}
+
+impl IpRateLimits {
+    pub fn new(limit: BidLimit) -> Self {
+        IpRateLimits { limit, buckets: RateLimiter::default(), clock: Arc::new(SystemClock) }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn check(&self, ip: IpAddr) -> Result<(), ApiError> {
+        if self.buckets.try_acquire(ip, &self.limit, self.clock.now_millis()) {
+            Ok(())
+        } else {
+            metrics::counter!(BIDS_RATE_LIMITED, "limit" => "ip", "phase" => "any").increment(1);
+            Err(ApiError::TooManyRequests("too many bids from this address, slow down"))
+        }
+    }
+}
+
+/// REST middleware: holds bid submissions, amendments and cancellations to the
+/// per-address limit.
+/// The address comes from [`ConnectInfo`], so serve with
+/// `into_make_service_with_connect_info::<SocketAddr>()`; without it nothing is limited.
+pub async fn limit_bids(State(limits): State<Arc<IpRateLimits>>, request: Request, next: Next) -> Response {
+    let is_bid = request.uri().path().starts_with("/bids") && matches!(*request.method(), Method::POST | Method::PATCH | Method::DELETE);
+    let peer = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip());
+    if let (true, Some(ip)) = (is_bid, peer) {
+        if let Err(error) = limits.check(ip) {
+            return error.into_response();
+        }
+    }
+    next.run(request).await
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use axum::routing::{get, patch};
+    use axum::Router;
+    use auction::sim::SimClock;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_buckets_refill_over_time() {
+        let limiter = RateLimiter::default();
+        let limit = BidLimit::new(2.0, 3);
+        assert!((0..3).all(|_| limiter.try_acquire(7, &limit, 1_000)));
+        assert!(!limiter.try_acquire(7, &limit, 1_000));
+        assert!(limiter.try_acquire(8, &limit, 1_000));
+        // Half a second refills one token
+        assert!(limiter.try_acquire(7, &limit, 1_500));
+        assert!(!limiter.try_acquire(7, &limit, 1_500));
+        assert!((0..3).all(|_| limiter.try_acquire(7, &limit, 60_000)));
+        assert!(!limiter.try_acquire(7, &limit, 60_000));
+    }
+
+    #[test]
+    fn test_refilled_buckets_are_swept() {
+        let limiter = RateLimiter::default();
+        let limit = BidLimit::new(2.0, 3);
+        let kept = |limiter: &RateLimiter<u32>| limiter.buckets.lock().unwrap().by_key.len();
+        for key in 0..100 {
+            assert!(limiter.try_acquire(key, &limit, 1_000));
+        }
+        assert_eq!(kept(&limiter), 100);
+        // A minute on, every bucket has refilled and only the one just taken from is kept
+        assert!(limiter.try_acquire(0, &limit, 61_000));
+        assert_eq!(kept(&limiter), 1);
+        // Until the next sweep a drained bucket keeps its count
+        assert!((0..2).all(|_| limiter.try_acquire(0, &limit, 61_000)));
+        assert!(!limiter.try_acquire(0, &limit, 61_000));
+    }
+
+    #[tokio::test]
+    async fn test_amendments_count_against_the_address_limit() {
+        let limits = Arc::new(IpRateLimits::new(BidLimit::new(1.0, 2)).with_clock(Arc::new(SimClock::new(1_000))));
+        let app = Router::new()
+            .route("/bids/{id}", patch(|| async { StatusCode::OK }))
+            .route("/baskets", get(|| async { StatusCode::OK }))
+            .layer(axum::middleware::from_fn_with_state(limits, limit_bids));
+        let call = |method: Method, uri: &str| {
+            let mut request = Request::builder().method(method).uri(uri).body(Body::empty()).unwrap();
+            request.extensions_mut().insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 4000))));
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+        assert_eq!(call(Method::PATCH, "/bids/1").await, StatusCode::OK);
+        assert_eq!(call(Method::PATCH, "/bids/1").await, StatusCode::OK);
+        assert_eq!(call(Method::PATCH, "/bids/2").await, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(call(Method::GET, "/baskets").await, StatusCode::OK);
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use auction::config::RiskConfig;
+use auction::stats::BIDS_RISK_REJECTED;
+use model::model::Basket;
+use crate::auth::{Principal, Scope};
+use crate::error::ApiError;
+
+
+/// What a user has at stake before a new bid: the prices of their resting bids and, by
+/// asset base, the quantities they won or stand to win.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Exposure {
+    pub notional: f64,
+    pub positions: HashMap<String, f64>,
+}
+
+impl Exposure {
+    /// Adds a bid of `price` for `fraction` of `basket`.
+    pub fn add_bid(&mut self, basket: &Basket, price: f64, fraction: f64) {
+        self.notional += price;
+        for info in &basket.assets {
+            *self.positions.entry(info.asset.base.clone()).or_insert(0.0) += info.quantity * fraction;
+        }
+    }
+}
+
+
+/// Pre-trade checks run on every bid before it rests: the user's notional, their
+/// positions counting what resting bids may win, and a collar around the basket's value
+/// at the latest marks.
+#[derive(Debug, Clone)]
+pub struct RiskEngine {
+    config: RiskConfig,
+    override_scope: Option<Scope>,
+    marks: HashMap<String, f64>,
+}
+
+impl RiskEngine {
+    pub fn new(config: RiskConfig) -> Self {
+        let override_scope = config.override_scope.parse().ok();
+        RiskEngine { config, override_scope, marks: HashMap::new() }
+    }
+
+    /// Records the latest price of the asset with base `base`.
+    pub fn update_mark(&mut self, base: &str, price: f64) {
+        if price.is_finite() && price > 0.0 {
+            self.marks.insert(base.to_string(), price);
+        }
+    }
+
+    /// Whether `caller`'s bids skip the checks.
+    pub fn may_override(&self, caller: Option<&Principal>) -> bool {
+        matches!((caller, self.override_scope), (Some(caller), Some(scope)) if caller.scope >= scope)
+    }
+
+    /// `fraction` of the basket valued at the latest marks, or its listed prices for
+    /// assets without one.
+    pub fn fair_value(&self, basket: &Basket, fraction: f64) -> f64 {
+        let value: f64 = basket.assets.iter()
+            .map(|info| info.quantity * self.marks.get(&info.asset.base).copied().unwrap_or(info.price))
+            .sum();
+        value * fraction
+    }
+
+    /// Refuses a bid of `price` for `quantity` of `basket` from a user with `exposure`.
+    pub fn check(&self, basket: &Basket, price: f64, quantity: Option<f64>, exposure: &Exposure) -> Result<(), ApiError> {
+        let fraction = quantity.unwrap_or(1.0);
+        let reject = |check: &'static str, message: String| {
+            metrics::counter!(BIDS_RISK_REJECTED, "check" => check).increment(1);
+            Err(ApiError::RiskLimit(message))
+        };
+        let fair_value = self.fair_value(basket, fraction);
+        if fair_value > 0.0 && (price - fair_value).abs() > self.config.price_collar * fair_value {
+            return reject("price_collar", format!("price {} is more than {}% away from the basket's value of {:.2}", price, self.config.price_collar * 100.0, fair_value));
+        }
+        let mut after = exposure.clone();
+        after.add_bid(basket, price, fraction);
+        if after.notional > self.config.max_notional {
+            return reject("notional", format!("resting bids of {:.2} would exceed the notional limit of {}", after.notional, self.config.max_notional));
+        }
+        for info in &basket.assets {
+            let Some(limit) = self.config.position_limit(&info.asset.base) else { continue };
+            let position = after.positions.get(&info.asset.base).copied().unwrap_or(0.0);
+            if position > limit {
+                return reject("position", format!("a position of {} {} would exceed its limit of {}", position, info.asset.base, limit));
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use model::ids::{BasketId, UserId};
+    use model::model::{Asset, AssetInfo};
+
+    #[test]
+    fn test_collar_notional_and_position_limits() {
+        let config = RiskConfig {
+            enabled: true,
+            max_notional: 100_000.0,
+            position_limits: BTreeMap::from([("BTC".to_string(), 1.5)]),
+            price_collar: 0.1,
+            override_scope: "admin".to_string(),
+        };
+        let mut engine = RiskEngine::new(config);
+        let basket = Basket { id: BasketId(1), assets: vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60_000.0)] };
+        assert!(engine.check(&basket, 62_000.0, None, &Exposure::default()).is_ok());
+        assert!(matches!(engine.check(&basket, 600_000.0, None, &Exposure::default()), Err(ApiError::RiskLimit(_))));
+        // The collar follows the marks rather than the listed price
+        engine.update_mark("BTC", 50_000.0);
+        assert!(engine.check(&basket, 62_000.0, None, &Exposure::default()).is_err());
+        assert!(engine.check(&basket, 26_000.0, Some(0.5), &Exposure::default()).is_ok());
+
+        let mut exposure = Exposure::default();
+        exposure.add_bid(&basket, 50_000.0, 1.0);
+        assert!(engine.check(&basket, 25_000.0, Some(0.5), &exposure).is_ok());
+        assert!(engine.check(&basket, 50_000.0, None, &exposure).unwrap_err().to_string().contains("position of 2 BTC"));
+        exposure.notional = 90_000.0;
+        assert!(engine.check(&basket, 25_000.0, Some(0.5), &exposure).unwrap_err().to_string().contains("notional"));
+
+        assert!(engine.may_override(Some(&Principal::admin())));
+        assert!(!engine.may_override(Some(&Principal::user(UserId(1), Scope::Trade))));
+        assert!(!engine.may_override(None));
+    }
+}
@@ -0,0 +1,278 @@
+use std::sync::{Arc, Mutex};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::middleware;
+use axum::routing::{delete, get, post};
+use axum::{Extension, Json, Router};
+use serde::Deserialize;
+use auction::analysis::MechanismComparison;
+use model::ids::{AuctionId, BasketId, BidId, UserId};
+use model::model::{AssetInfo, Basket, BidType, User};
+use crate::auth::{self, Authenticator, Principal, Scope};
+use crate::error::ApiError;
+use crate::exchange::{AuctionOutcome, AuctionRequest, BidRecord, Exchange};
+use crate::nav::{AuctionPerformance, NavSnapshot};
+use crate::oms::Order;
+
+pub type SharedExchange = Arc<Mutex<Exchange>>;
+/// The authenticated caller; absent when the router is not [`secured`].
+type Caller = Option<Extension<Principal>>;
+
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RegisterUser {
+    pub name: String,
+    pub balance: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateBasket {
+    pub assets: Vec<AssetInfo>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubmitBid {
+    pub user_id: UserId,
+    pub basket_id: BasketId,
+    pub bid_type: BidType,
+    pub price: f64,
+    #[serde(default)]
+    pub quantity: Option<f64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AmendBid {
+    pub price: f64,
+    #[serde(default)]
+    pub quantity: Option<f64>,
+}
+
+/// Bounds of a time series, both inclusive and in Unix milliseconds.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct TimeRange {
+    #[serde(default)]
+    pub from: Option<u64>,
+    #[serde(default)]
+    pub to: Option<u64>,
+}
+
+
+/// Every endpoint, over one shared exchange, open to anyone.
+pub fn router(exchange: SharedExchange) -> Router {
+    Router::new()
+        .route("/users", post(register_user))
+        .route("/users/{id}", get(get_user))
+        .route("/users/{id}/orders", get(list_user_orders))
+        .route("/baskets", post(create_basket).get(list_baskets))
+        .route("/baskets/{id}", get(get_basket))
+        .route("/baskets/{id}/bids", get(list_bids))
+        .route("/baskets/{id}/nav", get(get_nav_history))
+        .route("/baskets/{id}/analysis", get(get_mechanism_comparison))
+        .route("/bids", post(submit_bid))
+        .route("/bids/{id}", delete(cancel_bid).patch(amend_bid))
+        .route("/orders/{id}", get(get_order))
+        .route("/auctions", post(start_auction))
+        .route("/auctions/{id}", get(get_auction))
+        .route("/auctions/{id}/orders", get(list_auction_orders))
+        .route("/auctions/{id}/performance", get(get_auction_performance))
+        .merge(crate::admin::router())
+        .with_state(exchange)
+}
+
+/// [`router`] for authenticated callers only. Reading takes the read-only scope, trading
+/// the trade scope and only for the caller's own user, and managing users, baskets and
+/// auctions, including the [`crate::admin`] endpoints, the admin scope.
+pub fn secured(exchange: SharedExchange, authenticator: Arc<Authenticator>) -> Router {
+    router(exchange).layer(middleware::from_fn_with_state(authenticator, auth::authenticate))
+}
+
+fn principal(caller: &Caller) -> Option<&Principal> {
+    caller.as_ref().map(|Extension(principal)| principal)
+}
+
+async fn register_user(State(exchange): State<SharedExchange>, caller: Caller, Json(request): Json<RegisterUser>) -> Result<(StatusCode, Json<User>), ApiError> {
+    auth::require(principal(&caller), Scope::Admin)?;
+    let user = exchange.lock().unwrap().register_user(&request.name, request.balance)?;
+    Ok((StatusCode::CREATED, Json(user)))
+}
+
+async fn get_user(State(exchange): State<SharedExchange>, caller: Caller, Path(id): Path<UserId>) -> Result<Json<User>, ApiError> {
+    auth::require_owner(principal(&caller), id)?;
+    Ok(Json(exchange.lock().unwrap().user(id)?.clone()))
+}
+
+async fn create_basket(State(exchange): State<SharedExchange>, caller: Caller, Json(request): Json<CreateBasket>) -> Result<(StatusCode, Json<Basket>), ApiError> {
+    auth::require(principal(&caller), Scope::Admin)?;
+    let basket = exchange.lock().unwrap().create_basket(request.assets)?;
+    Ok((StatusCode::CREATED, Json(basket)))
+}
+
+async fn list_baskets(State(exchange): State<SharedExchange>) -> Json<Vec<Basket>> {
+    Json(exchange.lock().unwrap().baskets().into_iter().cloned().collect())
+}
+
+async fn get_basket(State(exchange): State<SharedExchange>, Path(id): Path<BasketId>) -> Result<Json<Basket>, ApiError> {
+    Ok(Json(exchange.lock().unwrap().basket(id)?.clone()))
+}
+
+async fn list_bids(State(exchange): State<SharedExchange>, Path(id): Path<BasketId>) -> Result<Json<Vec<BidRecord>>, ApiError> {
+    let exchange = exchange.lock().unwrap();
+    exchange.basket(id)?;
+    Ok(Json(exchange.bids_for(id).into_iter().cloned().collect()))
+}
+
+async fn get_nav_history(State(exchange): State<SharedExchange>, Path(id): Path<BasketId>, Query(range): Query<TimeRange>) -> Result<Json<Vec<NavSnapshot>>, ApiError> {
+    Ok(Json(exchange.lock().unwrap().nav_history(id, range.from, range.to)?))
+}
+
+async fn submit_bid(State(exchange): State<SharedExchange>, caller: Caller, Json(request): Json<SubmitBid>) -> Result<(StatusCode, Json<BidRecord>), ApiError> {
+    auth::require(principal(&caller), Scope::Trade)?;
+    auth::require_owner(principal(&caller), request.user_id)?;
+    let bid = exchange.lock().unwrap().submit_bid_as(principal(&caller), request.user_id, request.basket_id, request.bid_type, request.price, request.quantity)?;
+    Ok((StatusCode::CREATED, Json(bid)))
+}
+
+async fn cancel_bid(State(exchange): State<SharedExchange>, caller: Caller, Path(id): Path<BidId>) -> Result<Json<BidRecord>, ApiError> {
+    auth::require(principal(&caller), Scope::Trade)?;
+    let mut exchange = exchange.lock().unwrap();
+    auth::require_owner(principal(&caller), exchange.bid(id)?.user_id)?;
+    Ok(Json(exchange.cancel_bid(id)?))
+}
+
+async fn amend_bid(State(exchange): State<SharedExchange>, caller: Caller, Path(id): Path<BidId>, Json(request): Json<AmendBid>) -> Result<Json<BidRecord>, ApiError> {
+    auth::require(principal(&caller), Scope::Trade)?;
+    let mut exchange = exchange.lock().unwrap();
+    auth::require_owner(principal(&caller), exchange.bid(id)?.user_id)?;
+    Ok(Json(exchange.amend_bid_as(principal(&caller), id, request.price, request.quantity)?))
+}
+
+async fn get_order(State(exchange): State<SharedExchange>, caller: Caller, Path(id): Path<BidId>) -> Result<Json<Order>, ApiError> {
+    let exchange = exchange.lock().unwrap();
+    let order = exchange.orders().order(id).ok_or(ApiError::NotFound("order"))?;
+    auth::require_owner(principal(&caller), order.user_id)?;
+    Ok(Json(order.clone()))
+}
+
+async fn list_user_orders(State(exchange): State<SharedExchange>, caller: Caller, Path(id): Path<UserId>) -> Result<Json<Vec<Order>>, ApiError> {
+    auth::require_owner(principal(&caller), id)?;
+    let exchange = exchange.lock().unwrap();
+    exchange.user(id)?;
+    Ok(Json(exchange.orders().for_user(id).into_iter().cloned().collect()))
+}
+
+async fn start_auction(State(exchange): State<SharedExchange>, caller: Caller, Json(request): Json<AuctionRequest>) -> Result<(StatusCode, Json<AuctionOutcome>), ApiError> {
+    auth::require(principal(&caller), Scope::Admin)?;
+    let outcome = exchange.lock().unwrap().run_auction(&request)?;
+    Ok((StatusCode::CREATED, Json(outcome)))
+}
+
+async fn get_auction(State(exchange): State<SharedExchange>, Path(id): Path<AuctionId>) -> Result<Json<AuctionOutcome>, ApiError> {
+    Ok(Json(exchange.lock().unwrap().outcome(id)?.clone()))
+}
+
+async fn list_auction_orders(State(exchange): State<SharedExchange>, Path(id): Path<AuctionId>) -> Result<Json<Vec<Order>>, ApiError> {
+    let exchange = exchange.lock().unwrap();
+    exchange.outcome(id)?;
+    Ok(Json(exchange.orders().for_auction(id).into_iter().cloned().collect()))
+}
+
+async fn get_auction_performance(State(exchange): State<SharedExchange>, Path(id): Path<AuctionId>) -> Result<Json<AuctionPerformance>, ApiError> {
+    Ok(Json(exchange.lock().unwrap().auction_performance(id)?))
+}
+
+async fn get_mechanism_comparison(State(exchange): State<SharedExchange>, Path(id): Path<BasketId>) -> Result<Json<MechanismComparison>, ApiError> {
+    Ok(Json(exchange.lock().unwrap().compare_mechanisms(id)?))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use http_body_util::BodyExt;
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    async fn call(app: &Router, method: &str, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let request = Request::builder().method(method).uri(uri).header("content-type", "application/json")
+            .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&bytes).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_vcg_auction_over_http() {
+        let app = router(Arc::new(Mutex::new(Exchange::new())));
+        let (status, alice) = call(&app, "POST", "/users", Some(json!({"name": "Alice", "balance": 100000.0}))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (_, bob) = call(&app, "POST", "/users", Some(json!({"name": "Bob", "balance": 100000.0}))).await;
+        let assets = json!([{"asset": {"base": "BTC", "quote": "USD"}, "quantity": 1.0, "price": 60000.0}]);
+        let (_, basket) = call(&app, "POST", "/baskets", Some(json!({"assets": assets}))).await;
+        let basket_id = basket["id"].as_u64().unwrap();
+
+        for (user, price) in [(&alice, 62000.0), (&bob, 61000.0)] {
+            let bid = json!({"user_id": user["id"], "basket_id": basket_id, "bid_type": "XOR", "price": price});
+            assert_eq!(call(&app, "POST", "/bids", Some(bid)).await.0, StatusCode::CREATED);
+        }
+        let (_, bids) = call(&app, "GET", &format!("/baskets/{}/bids", basket_id), None).await;
+        assert_eq!(bids.as_array().unwrap().len(), 2);
+        let (status, analysis) = call(&app, "GET", &format!("/baskets/{}/analysis", basket_id), None).await;
+        assert_eq!((status, analysis["results"].as_array().unwrap().len()), (StatusCode::OK, 5));
+        assert_eq!(analysis["results"][0]["mechanism"], "xor");
+
+        let (status, outcome) = call(&app, "POST", "/auctions", Some(json!({"basket_id": basket_id, "mechanism": "vcg"}))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(outcome["mechanism"], "vcg");
+        assert_eq!(outcome["report"]["metadata"]["mechanism"], "VCG");
+        let (status, fetched) = call(&app, "GET", &format!("/auctions/{}", outcome["auction_id"]), None).await;
+        assert_eq!((status, &fetched), (StatusCode::OK, &outcome));
+        let (_, winner) = call(&app, "GET", &format!("/users/{}", alice["id"]), None).await;
+        let payment = outcome["payments"][alice["id"].to_string()].as_f64().unwrap();
+        assert_eq!(winner["balance"].as_f64().unwrap(), 100000.0 - payment);
+
+        let (status, navs) = call(&app, "GET", &format!("/baskets/{}/nav?from=0", basket_id), None).await;
+        assert_eq!((status, navs), (StatusCode::OK, json!([])));
+        let (_, performance) = call(&app, "GET", &format!("/auctions/{}/performance", outcome["auction_id"]), None).await;
+        assert_eq!((performance["payments"].as_f64(), &performance["premium"]), (Some(payment), &Value::Null));
+    }
+
+    #[tokio::test]
+    async fn test_errors_map_to_statuses() {
+        let app = router(Arc::new(Mutex::new(Exchange::new())));
+        let (status, body) = call(&app, "GET", "/baskets/7", None).await;
+        assert_eq!((status, body), (StatusCode::NOT_FOUND, json!({"error": "basket not found"})));
+        assert_eq!(call(&app, "DELETE", "/bids/7", None).await.0, StatusCode::NOT_FOUND);
+        assert_eq!(call(&app, "POST", "/users", Some(json!({"name": "", "balance": 1.0}))).await.0, StatusCode::BAD_REQUEST);
+        let (status, _) = call(&app, "POST", "/auctions", Some(json!({"basket_id": 1, "mechanism": "dutch"}))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn test_secured_routes_enforce_scopes_and_ownership() {
+        let mut exchange = Exchange::new();
+        let alice = exchange.register_user("Alice", 100_000.0).unwrap().id;
+        let bob = exchange.register_user("Bob", 100_000.0).unwrap().id;
+        let basket = exchange.create_basket(vec![model::model::AssetInfo::new(model::model::Asset::new("BTC", "USD"), 1.0, 60_000.0)]).unwrap().id;
+        let alices_bid = exchange.submit_bid(alice, basket, model::model::BidType::XOR, 61_000.0, None).unwrap().id;
+        let authenticator = Authenticator::new()
+            .with_api_key("k-alice", Principal::user(alice, Scope::Trade))
+            .with_api_key("k-bob", Principal::user(bob, Scope::Trade));
+        let app = secured(Arc::new(Mutex::new(exchange)), Arc::new(authenticator));
+        let call_as = |key: &'static str, method: &'static str, uri: String| {
+            let request = Request::builder().method(method).uri(uri).header(auth::API_KEY_HEADER, key).body(Body::empty()).unwrap();
+            let app = app.clone();
+            async move { app.oneshot(request).await.unwrap().status() }
+        };
+
+        assert_eq!(call(&app, "GET", "/baskets", None).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(call_as("k-bob", "GET", "/baskets".to_string()).await, StatusCode::OK);
+        assert_eq!(call_as("k-bob", "GET", format!("/users/{}", alice)).await, StatusCode::FORBIDDEN);
+        assert_eq!(call_as("k-bob", "DELETE", format!("/bids/{}", alices_bid)).await, StatusCode::FORBIDDEN);
+        assert_eq!(call_as("k-alice", "DELETE", format!("/bids/{}", alices_bid)).await, StatusCode::OK);
+        assert_eq!(call_as("k-alice", "POST", format!("/admin/baskets/{}/halt", basket)).await, StatusCode::FORBIDDEN);
+    }
+}
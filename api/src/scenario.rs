@@ -0,0 +1,235 @@
+//! Declarative end-to-end scenarios. A scenario file names users, baskets and a bid
+//! sequence, then lists runs: each auctions one basket with one mechanism on a fresh
+//! exchange holding all of them, and states the winners, payments and balances it must
+//! end with. Regression cases are added as files under `scenarios/` rather than as code.
+//!
+//! ```json
+//! {
+//!   "name": "two bidders, one basket",
+//!   "users": [{"name": "alice", "balance": 100000.0}, {"name": "bob", "balance": 100000.0}],
+//!   "baskets": [{"name": "btc", "assets": [{"asset": {"base": "BTC", "quote": "USD"}, "quantity": 1.0, "price": 60000.0}]}],
+//!   "bids": [
+//!     {"user": "alice", "basket": "btc", "bid_type": "XOR", "price": 62000.0},
+//!     {"user": "bob", "basket": "btc", "bid_type": "XOR", "price": -1.0, "rejected": "price must be positive"}
+//!   ],
+//!   "runs": [{"basket": "btc", "mechanism": "xor", "expect": {"winners": ["alice"], "balances": {"alice": 38000.0}}}]
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use model::ids::UserId;
+use model::model::{AssetInfo, BidType};
+use crate::exchange::{AuctionRequest, Exchange, Mechanism};
+
+/// Payments and balances match when this close.
+pub const TOLERANCE: f64 = 1e-6;
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScenarioUser {
+    pub name: String,
+    pub balance: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScenarioBasket {
+    pub name: String,
+    pub assets: Vec<AssetInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScenarioBid {
+    pub user: String,
+    pub basket: String,
+    pub bid_type: BidType,
+    pub price: f64,
+    #[serde(default)]
+    pub quantity: Option<f64>,
+    /// The bid must be refused with an error containing this.
+    #[serde(default)]
+    pub rejected: Option<String>,
+}
+
+/// What a run must end with. Users left out of `payments` and `balances` are not checked.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Expectation {
+    /// Names of the winning users, in any order.
+    #[serde(default)]
+    pub winners: Option<Vec<String>>,
+    #[serde(default)]
+    pub payments: BTreeMap<String, f64>,
+    #[serde(default)]
+    pub balances: BTreeMap<String, f64>,
+    /// The auction must fail with an error containing this.
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScenarioRun {
+    pub basket: String,
+    pub mechanism: Mechanism,
+    #[serde(default)]
+    pub price_increment: Option<f64>,
+    #[serde(default)]
+    pub max_rounds: Option<usize>,
+    pub expect: Expectation,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub users: Vec<ScenarioUser>,
+    pub baskets: Vec<ScenarioBasket>,
+    pub bids: Vec<ScenarioBid>,
+    pub runs: Vec<ScenarioRun>,
+}
+
+
+/// Why a scenario could not be read, or how one of its runs went wrong.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScenarioError {
+    Io(String),
+    Parse(String),
+    /// A bid or run names a user or basket the scenario does not define.
+    Unknown { kind: &'static str, name: String },
+    /// A run did not end as expected.
+    Mismatch { scenario: String, run: String, message: String },
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScenarioError::Io(message) | ScenarioError::Parse(message) => write!(f, "{}", message),
+            ScenarioError::Unknown { kind, name } => write!(f, "unknown {} '{}'", kind, name),
+            ScenarioError::Mismatch { scenario, run, message } => write!(f, "{} [{}]: {}", scenario, run, message),
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {}
+
+
+impl Scenario {
+    pub fn from_json(text: &str) -> Result<Self, ScenarioError> {
+        serde_json::from_str(text).map_err(|error| ScenarioError::Parse(error.to_string()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ScenarioError> {
+        let text = fs::read_to_string(path).map_err(|error| ScenarioError::Io(format!("cannot read {}: {}", path.display(), error)))?;
+        Scenario::from_json(&text).map_err(|error| ScenarioError::Parse(format!("{}: {}", path.display(), error)))
+    }
+
+    /// Every `.json` scenario in `dir`, by file name.
+    pub fn load_dir(dir: &Path) -> Result<Vec<Scenario>, ScenarioError> {
+        let entries = fs::read_dir(dir).map_err(|error| ScenarioError::Io(format!("cannot read {}: {}", dir.display(), error)))?;
+        let mut paths: Vec<_> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+            .collect();
+        paths.sort();
+        paths.iter().map(|path| Scenario::load(path)).collect()
+    }
+
+    /// Runs each run on its own exchange and returns every way they went wrong; empty
+    /// when the scenario passes.
+    pub fn run(&self) -> Vec<ScenarioError> {
+        self.runs.iter().filter_map(|run| self.execute(run).err()).collect()
+    }
+
+    fn execute(&self, run: &ScenarioRun) -> Result<(), ScenarioError> {
+        let label = format!("{} of {}", run.mechanism.name(), run.basket);
+        let mismatch = |message: String| ScenarioError::Mismatch { scenario: self.name.clone(), run: label.clone(), message };
+        let setup = |error: crate::error::ApiError| mismatch(format!("setting up: {}", error));
+
+        let mut exchange = Exchange::new();
+        let mut users = BTreeMap::new();
+        for user in &self.users {
+            users.insert(user.name.as_str(), exchange.register_user(&user.name, user.balance).map_err(setup)?.id);
+        }
+        let mut baskets = BTreeMap::new();
+        for basket in &self.baskets {
+            baskets.insert(basket.name.as_str(), exchange.create_basket(basket.assets.clone()).map_err(setup)?.id);
+        }
+        let user_id = |name: &str| users.get(name).copied().ok_or_else(|| ScenarioError::Unknown { kind: "user", name: name.to_string() });
+        let basket_id = |name: &str| baskets.get(name).copied().ok_or_else(|| ScenarioError::Unknown { kind: "basket", name: name.to_string() });
+
+        for (index, bid) in self.bids.iter().enumerate() {
+            let submitted = exchange.submit_bid(user_id(&bid.user)?, basket_id(&bid.basket)?, bid.bid_type.clone(), bid.price, bid.quantity);
+            match (&submitted, &bid.rejected) {
+                (Ok(_), None) => {}
+                (Err(error), Some(expected)) if error.to_string().contains(expected.as_str()) => {}
+                (Ok(_), Some(expected)) => return Err(mismatch(format!("bid {} was accepted, expected '{}'", index, expected))),
+                (Err(error), _) => return Err(mismatch(format!("bid {} was refused: {}", index, error))),
+            }
+        }
+
+        let request = AuctionRequest { basket_id: basket_id(&run.basket)?, mechanism: run.mechanism, price_increment: run.price_increment, max_rounds: run.max_rounds, settlement: None };
+        let outcome = match (exchange.run_auction(&request), &run.expect.error) {
+            (Ok(outcome), None) => outcome,
+            (Err(error), Some(expected)) if error.to_string().contains(expected.as_str()) => return Ok(()),
+            (Ok(_), Some(expected)) => return Err(mismatch(format!("auction settled, expected '{}'", expected))),
+            (Err(error), _) => return Err(mismatch(format!("auction failed: {}", error))),
+        };
+
+        let name_of = |id: UserId| users.iter().find(|(_, user_id)| **user_id == id).map_or(id.to_string(), |(name, _)| name.to_string());
+        if let Some(expected) = &run.expect.winners {
+            let mut winners: Vec<String> = outcome.winning_bids.iter().filter_map(|bid_id| exchange.orders().order(*bid_id)).map(|order| name_of(order.user_id)).collect();
+            let mut expected = expected.clone();
+            winners.sort();
+            winners.dedup();
+            expected.sort();
+            if winners != expected {
+                return Err(mismatch(format!("winners were {:?}, expected {:?}", winners, expected)));
+            }
+        }
+        for (name, expected) in &run.expect.payments {
+            let paid = outcome.payments.get(&user_id(name)?).copied().unwrap_or(0.0);
+            if (paid - expected).abs() > TOLERANCE {
+                return Err(mismatch(format!("{} paid {}, expected {}", name, paid, expected)));
+            }
+        }
+        for (name, expected) in &run.expect.balances {
+            let balance = exchange.user(user_id(name)?).map_err(setup)?.balance;
+            if (balance - expected).abs() > TOLERANCE {
+                return Err(mismatch(format!("{} has {}, expected {}", name, balance, expected)));
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_scenario_passes() {
+        let scenarios = Scenario::load_dir(&Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios")).unwrap();
+        assert!(!scenarios.is_empty());
+        let failures: Vec<String> = scenarios.iter().flat_map(Scenario::run).map(|failure| failure.to_string()).collect();
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn test_mismatches_name_the_scenario_and_run() {
+        let mut scenario = Scenario::from_json(include_str!("../scenarios/xor_highest_bid.json")).unwrap();
+        scenario.runs[0].expect.winners = Some(vec!["bob".to_string()]);
+        let failures = scenario.run();
+        assert_eq!(failures.len(), 1);
+        assert!(matches!(&failures[0], ScenarioError::Mismatch { run, message, .. } if run == "XOR of btc" && message.contains("winners")));
+        assert!(Scenario::from_json("{\"name\": \"x\"}").is_err());
+    }
+}
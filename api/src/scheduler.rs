@@ -0,0 +1,391 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use auction::deferred::SettlementCalendar;
+use model::ids::BasketId;
+use crate::engine::ExchangeEngine;
+use crate::error::ApiError;
+use crate::exchange::{AuctionOutcome, AuctionRequest, Mechanism};
+
+const MILLIS_PER_MINUTE: u64 = 60_000;
+const MILLIS_PER_DAY: u64 = 86_400_000;
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+
+/// When a recurring auction runs, in UTC. Written as `daily 14:00`, `fri 16:30` for once
+/// a week, or `every 15m` / `every 4h` for a fixed period counted from the epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Recurrence {
+    Daily { minute: u32 },
+    /// `weekday` 0 is Monday.
+    Weekly { weekday: u32, minute: u32 },
+    Every { minutes: u32 },
+}
+
+impl Recurrence {
+    /// Longest gap between two runs, which a bidding window must stay under.
+    pub fn period(&self) -> Duration {
+        match self {
+            Recurrence::Daily { .. } => Duration::from_millis(MILLIS_PER_DAY),
+            Recurrence::Weekly { .. } => Duration::from_millis(7 * MILLIS_PER_DAY),
+            Recurrence::Every { minutes } => Duration::from_millis(*minutes as u64 * MILLIS_PER_MINUTE),
+        }
+    }
+
+    /// The first run strictly after `after`, both in Unix milliseconds.
+    pub fn next_after(&self, after: u64) -> u64 {
+        let day = after / MILLIS_PER_DAY;
+        let at = |day: u64, minute: u32| day * MILLIS_PER_DAY + minute as u64 * MILLIS_PER_MINUTE;
+        match *self {
+            Recurrence::Daily { minute } => {
+                let today = at(day, minute);
+                if today > after { today } else { at(day + 1, minute) }
+            }
+            Recurrence::Weekly { weekday, minute } => {
+                // 1970-01-01 was a Thursday
+                let ahead = (weekday as u64 + 7 - (day + 3) % 7) % 7;
+                let candidate = at(day + ahead, minute);
+                if candidate > after { candidate } else { at(day + ahead + 7, minute) }
+            }
+            Recurrence::Every { minutes } => {
+                let period = minutes as u64 * MILLIS_PER_MINUTE;
+                (after / period + 1) * period
+            }
+        }
+    }
+}
+
+fn parse_time(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.split_once(':')?;
+    let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+impl FromStr for Recurrence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid recurrence '{}', expected e.g. 'daily 14:00', 'fri 16:30' or 'every 15m'", s);
+        let (kind, rest) = s.trim().split_once(' ').ok_or_else(invalid)?;
+        let kind = kind.to_ascii_lowercase();
+        let rest = rest.trim();
+        if kind == "every" {
+            let minutes = match rest.strip_suffix('h') {
+                Some(hours) => hours.parse::<u32>().ok().and_then(|hours| hours.checked_mul(60)),
+                None => rest.strip_suffix('m').and_then(|minutes| minutes.parse().ok()),
+            };
+            return minutes.filter(|minutes| *minutes > 0).map(|minutes| Recurrence::Every { minutes }).ok_or_else(invalid);
+        }
+        let minute = parse_time(rest).ok_or_else(invalid)?;
+        if kind == "daily" {
+            return Ok(Recurrence::Daily { minute });
+        }
+        let weekday = WEEKDAYS.iter().position(|name| *name == kind).ok_or_else(invalid)?;
+        Ok(Recurrence::Weekly { weekday: weekday as u32, minute })
+    }
+}
+
+impl TryFrom<String> for Recurrence {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, String> {
+        value.parse()
+    }
+}
+
+impl From<Recurrence> for String {
+    fn from(recurrence: Recurrence) -> Self {
+        recurrence.to_string()
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Recurrence::Daily { minute } => write!(f, "daily {:02}:{:02}", minute / 60, minute % 60),
+            Recurrence::Weekly { weekday, minute } => write!(f, "{} {:02}:{:02}", WEEKDAYS[*weekday as usize % 7], minute / 60, minute % 60),
+            Recurrence::Every { minutes } if minutes % 60 == 0 => write!(f, "every {}h", minutes / 60),
+            Recurrence::Every { minutes } => write!(f, "every {}m", minutes),
+        }
+    }
+}
+
+
+/// A recurring auction. A basket is only ever auctioned once, so each run auctions a
+/// fresh basket with the assets of `template`, open for bids for `window` before it runs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub name: String,
+    pub template: BasketId,
+    pub mechanism: Mechanism,
+    pub recurrence: Recurrence,
+    #[serde(with = "window_secs")]
+    pub window: Duration,
+    #[serde(default)]
+    pub price_increment: Option<f64>,
+    #[serde(default)]
+    pub max_rounds: Option<usize>,
+}
+
+mod window_secs {
+    use std::time::Duration;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(window: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(window.as_secs())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_secs)
+    }
+}
+
+/// Why a run did not happen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// The run falls on a day the calendar closes.
+    Holiday,
+    /// The scheduler was not ticking when the window should have opened.
+    Missed,
+    /// The previous run of the schedule has not finished.
+    Overlap,
+}
+
+/// What a tick of the scheduler did.
+#[derive(Debug, Clone)]
+pub enum ScheduleEvent {
+    WindowOpened { schedule: String, basket_id: BasketId, closes_at: u64 },
+    Settled { schedule: String, outcome: Box<AuctionOutcome> },
+    Failed { schedule: String, error: ApiError },
+    Skipped { schedule: String, run_at: u64, reason: SkipReason },
+}
+
+/// A bidding window that is open, and when its auction runs.
+#[derive(Debug, Clone, Copy)]
+struct OpenWindow {
+    basket_id: BasketId,
+    run_at: u64,
+}
+
+#[derive(Debug)]
+struct Scheduled {
+    schedule: Schedule,
+    next_run: u64,
+    open: Option<OpenWindow>,
+}
+
+
+/// Runs recurring auctions on an engine: opens each run's bidding window, runs the
+/// mechanism when the window closes and settles it. Outcomes reach subscribers and
+/// publishers through the exchange's observers like any other auction. Runs on days the
+/// calendar closes are skipped, and a run whose predecessor has not finished is skipped
+/// rather than stacked.
+#[derive(Debug)]
+pub struct AuctionScheduler {
+    engine: ExchangeEngine,
+    calendar: SettlementCalendar,
+    schedules: Vec<Scheduled>,
+}
+
+impl AuctionScheduler {
+    pub fn new(engine: ExchangeEngine) -> Self {
+        AuctionScheduler { engine, calendar: SettlementCalendar::continuous(), schedules: Vec::new() }
+    }
+
+    /// Skips runs on the calendar's holidays, and on weekends unless it settles on them.
+    pub fn with_calendar(mut self, calendar: SettlementCalendar) -> Self {
+        self.calendar = calendar;
+        self
+    }
+
+    pub fn schedules(&self) -> impl Iterator<Item = &Schedule> {
+        self.schedules.iter().map(|scheduled| &scheduled.schedule)
+    }
+
+    /// Adds a schedule whose first run is the next one after now. Refused if the name is
+    /// taken or the window is so long that one run's bidding would overlap the next's.
+    pub fn add(&mut self, schedule: Schedule) -> Result<(), ApiError> {
+        if self.schedules.iter().any(|scheduled| scheduled.schedule.name == schedule.name) {
+            return Err(ApiError::Conflict("a schedule with this name exists"));
+        }
+        if schedule.window.is_zero() || schedule.window >= schedule.recurrence.period() {
+            return Err(ApiError::BadRequest(format!("the bidding window must be shorter than the period of '{}'", schedule.recurrence)));
+        }
+        let exchange = self.engine.exchange().lock().unwrap();
+        exchange.basket(schedule.template)?;
+        let next_run = schedule.recurrence.next_after(exchange.now_millis());
+        drop(exchange);
+        self.schedules.push(Scheduled { schedule, next_run, open: None });
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Schedule> {
+        let index = self.schedules.iter().position(|scheduled| scheduled.schedule.name == name)?;
+        Some(self.schedules.remove(index).schedule)
+    }
+
+    /// Opens the windows and runs the auctions that are due by the exchange's clock,
+    /// waiting for the auctions started to settle.
+    pub async fn tick(&mut self) -> Vec<ScheduleEvent> {
+        let now = self.engine.exchange().lock().unwrap().now_millis();
+        let mut events = Vec::new();
+        let mut started = Vec::new();
+        for scheduled in &mut self.schedules {
+            let name = scheduled.schedule.name.clone();
+            if let Some(open) = scheduled.open.filter(|open| now >= open.run_at) {
+                if self.engine.running().contains(&open.basket_id) {
+                    continue;
+                }
+                scheduled.open = None;
+                let request = AuctionRequest {
+                    basket_id: open.basket_id,
+                    mechanism: scheduled.schedule.mechanism,
+                    price_increment: scheduled.schedule.price_increment,
+                    max_rounds: scheduled.schedule.max_rounds,
+                    settlement: None,
+                };
+                match self.engine.start(request) {
+                    Ok(handle) => started.push((name.clone(), handle)),
+                    Err(error) => events.push(ScheduleEvent::Failed { schedule: name.clone(), error }),
+                }
+            }
+
+            let opens_at = scheduled.next_run.saturating_sub(scheduled.schedule.window.as_millis() as u64);
+            if now < opens_at {
+                continue;
+            }
+            let run_at = scheduled.next_run;
+            scheduled.next_run = scheduled.schedule.recurrence.next_after(run_at.max(now));
+            let reason = if !self.calendar.is_business_day(run_at / MILLIS_PER_DAY) {
+                Some(SkipReason::Holiday)
+            } else if now >= run_at {
+                Some(SkipReason::Missed)
+            } else if scheduled.open.is_some() {
+                Some(SkipReason::Overlap)
+            } else {
+                None
+            };
+            if let Some(reason) = reason {
+                tracing::warn!(schedule = %name, run_at, ?reason, "scheduled auction skipped");
+                events.push(ScheduleEvent::Skipped { schedule: name, run_at, reason });
+                continue;
+            }
+            match open_window(&self.engine, &scheduled.schedule, run_at - now) {
+                Ok(basket_id) => {
+                    tracing::info!(schedule = %name, basket_id = basket_id.get(), closes_at = run_at, "bidding window opened");
+                    scheduled.open = Some(OpenWindow { basket_id, run_at });
+                    events.push(ScheduleEvent::WindowOpened { schedule: name, basket_id, closes_at: run_at });
+                }
+                Err(error) => events.push(ScheduleEvent::Failed { schedule: name, error }),
+            }
+        }
+
+        let (names, handles): (Vec<String>, Vec<_>) = started.into_iter().unzip();
+        let outcomes = join_all(handles.into_iter().map(|handle| handle.outcome())).await;
+        for (schedule, outcome) in names.into_iter().zip(outcomes) {
+            events.push(match outcome {
+                Ok(outcome) => ScheduleEvent::Settled { schedule, outcome: Box::new(outcome) },
+                Err(error) => {
+                    tracing::warn!(%schedule, %error, "scheduled auction failed");
+                    ScheduleEvent::Failed { schedule, error }
+                }
+            });
+        }
+        events
+    }
+
+    /// Ticks every `every` until the task is dropped.
+    pub async fn run(mut self, every: Duration) {
+        let mut interval = tokio::time::interval(every);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            self.tick().await;
+        }
+    }
+}
+
+/// Creates the run's basket from the template and closes bidding on it `closes_in` from now.
+fn open_window(engine: &ExchangeEngine, schedule: &Schedule, closes_in: u64) -> Result<BasketId, ApiError> {
+    let mut exchange = engine.exchange().lock().map_err(|_| ApiError::Internal("exchange lock poisoned".to_string()))?;
+    let assets = exchange.basket(schedule.template)?.assets.clone();
+    let basket = exchange.create_basket(assets)?;
+    exchange.extend_bidding(basket.id, Duration::from_millis(closes_in), None)?;
+    Ok(basket.id)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use auction::sim::SimClock;
+    use model::model::{Asset, AssetInfo, BidType};
+    use crate::exchange::Exchange;
+
+    // Monday 2024-01-01 00:00 UTC
+    const MONDAY: u64 = 1_704_067_200_000;
+
+    #[test]
+    fn test_recurrences_parse_and_find_the_next_run() {
+        let daily: Recurrence = "daily 14:00".parse().unwrap();
+        assert_eq!(daily.next_after(MONDAY), MONDAY + 14 * 3_600_000);
+        assert_eq!(daily.next_after(MONDAY + 14 * 3_600_000), MONDAY + MILLIS_PER_DAY + 14 * 3_600_000);
+        let friday: Recurrence = "Fri 16:30".parse().unwrap();
+        assert_eq!(friday.next_after(MONDAY), MONDAY + 4 * MILLIS_PER_DAY + 990 * MILLIS_PER_MINUTE);
+        assert_eq!("every 4h".parse::<Recurrence>().unwrap().next_after(MONDAY + 1), MONDAY + 4 * 3_600_000);
+        assert_eq!(friday.to_string(), "fri 16:30");
+        assert_eq!(serde_json::to_string(&Recurrence::Every { minutes: 90 }).unwrap(), "\"every 90m\"");
+        assert!("daily 25:00".parse::<Recurrence>().is_err());
+        assert!("every 0m".parse::<Recurrence>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_daily_auction_opens_runs_and_skips_holidays() {
+        let clock = SimClock::new(MONDAY + 13 * 3_600_000);
+        let mut exchange = Exchange::new().with_clock(Arc::new(clock.clone()));
+        let alice = exchange.register_user("Alice", 100_000.0).unwrap().id;
+        let template = exchange.create_basket(vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60_000.0)]).unwrap().id;
+        let engine = ExchangeEngine::new(Arc::new(Mutex::new(exchange)));
+        let mut calendar = SettlementCalendar::continuous();
+        calendar.add_holiday(MONDAY / MILLIS_PER_DAY + 1);
+        let mut scheduler = AuctionScheduler::new(engine.clone()).with_calendar(calendar);
+        let schedule = Schedule {
+            name: "btc-daily".to_string(),
+            template,
+            mechanism: Mechanism::Xor,
+            recurrence: "daily 14:00".parse().unwrap(),
+            window: Duration::from_secs(1800),
+            price_increment: None,
+            max_rounds: None,
+        };
+        assert!(matches!(scheduler.add(Schedule { window: Duration::from_secs(86_400), ..schedule.clone() }), Err(ApiError::BadRequest(_))));
+        scheduler.add(schedule.clone()).unwrap();
+        assert_eq!(scheduler.add(schedule), Err(ApiError::Conflict("a schedule with this name exists")));
+
+        assert!(scheduler.tick().await.is_empty());
+        clock.advance(Duration::from_secs(1800));
+        let basket = match scheduler.tick().await.as_slice() {
+            [ScheduleEvent::WindowOpened { basket_id, closes_at, .. }] if *closes_at == MONDAY + 14 * 3_600_000 => *basket_id,
+            events => panic!("unexpected events {:?}", events),
+        };
+        engine.exchange().lock().unwrap().submit_bid(alice, basket, BidType::XOR, 61_000.0, None).unwrap();
+
+        clock.advance(Duration::from_secs(1800));
+        assert!(matches!(engine.exchange().lock().unwrap().submit_bid(alice, basket, BidType::XOR, 62_000.0, None), Err(ApiError::Conflict(_))));
+        match scheduler.tick().await.as_slice() {
+            [ScheduleEvent::Settled { outcome, .. }] => assert_eq!(outcome.payments[&alice], 61_000.0),
+            events => panic!("unexpected events {:?}", events),
+        }
+
+        // Tuesday is a holiday, so the next window to open is Wednesday's
+        clock.advance(Duration::from_millis(MILLIS_PER_DAY));
+        assert!(matches!(scheduler.tick().await.as_slice(), [ScheduleEvent::Skipped { reason: SkipReason::Holiday, .. }]));
+        clock.advance(Duration::from_millis(MILLIS_PER_DAY - 1_800_000));
+        assert!(matches!(scheduler.tick().await.as_slice(), [ScheduleEvent::WindowOpened { .. }]));
+    }
+}
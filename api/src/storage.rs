@@ -0,0 +1,513 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Mutex;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use sled::Transactional;
+use sled::transaction::{ConflictableTransactionError, TransactionError};
+use auction::ledger::JournalEntry;
+use model::ids::{AuctionId, BasketId, BidId, UserId};
+use model::model::{Basket, User};
+use crate::exchange::{AuctionOutcome, BasketControls, BidRecord};
+use crate::nav::NavSnapshot;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StorageError {
+    /// The store could not be read or written.
+    Backend(String),
+    /// A stored record could not be decoded.
+    Corrupt(String),
+    /// Another writer changed the record first, e.g. cleared the same basket.
+    Conflict(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Backend(message) => write!(f, "storage error: {}", message),
+            StorageError::Corrupt(message) => write!(f, "corrupt record: {}", message),
+            StorageError::Conflict(message) => write!(f, "write conflict: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<sled::Error> for StorageError {
+    fn from(error: sled::Error) -> Self {
+        StorageError::Backend(error.to_string())
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(error: serde_json::Error) -> Self {
+        StorageError::Corrupt(error.to_string())
+    }
+}
+
+
+/// Everything an exchange needs to resume after a restart, ordered by id.
+#[derive(Debug, Clone, Default)]
+pub struct StoredExchange {
+    pub users: Vec<User>,
+    pub baskets: Vec<Basket>,
+    pub bids: Vec<BidRecord>,
+    pub outcomes: Vec<AuctionOutcome>,
+    /// Ordered by basket, then time.
+    pub navs: Vec<NavSnapshot>,
+    pub controls: Vec<BasketControls>,
+    /// The clearing ledger, ordered by entry id.
+    pub ledger: Vec<JournalEntry>,
+    pub halted: Vec<BasketId>,
+}
+
+
+/// Durable home of the exchange's state. The exchange writes through it before changing
+/// its own copy, so a failed write leaves both unchanged.
+pub trait Repository: Send + Sync {
+    fn save_user(&self, user: &User) -> Result<(), StorageError>;
+
+    fn save_basket(&self, basket: &Basket) -> Result<(), StorageError>;
+
+    fn save_bid(&self, bid: &BidRecord) -> Result<(), StorageError>;
+
+    fn delete_bid(&self, bid_id: BidId) -> Result<(), StorageError>;
+
+    /// Records a cleared auction in one step: its outcome and settlement report, the
+    /// winners' new balances, the ledger entries its clearing posted and the removal of
+    /// the bids it consumed.
+    fn save_auction(&self, outcome: &AuctionOutcome, users: &[User], consumed_bids: &[BidId], entries: &[JournalEntry]) -> Result<(), StorageError>;
+
+    fn save_navs(&self, snapshots: &[NavSnapshot]) -> Result<(), StorageError>;
+
+    /// Replaces the basket's bidding deadline and soft-close extensions.
+    fn save_controls(&self, controls: &BasketControls) -> Result<(), StorageError>;
+
+    /// Marks the basket's auction halted by an operator, or resumed.
+    fn save_halted(&self, basket_id: BasketId, halted: bool) -> Result<(), StorageError>;
+
+    fn load(&self) -> Result<StoredExchange, StorageError>;
+}
+
+
+pub type StorageFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, StorageError>> + Send + 'a>>;
+
+/// Asynchronous variant of `Repository`, for network databases. Hand it to the exchange
+/// through `BlockingRepository`.
+pub trait AsyncRepository: Send + Sync {
+    fn save_user<'a>(&'a self, user: &'a User) -> StorageFuture<'a, ()>;
+
+    fn save_basket<'a>(&'a self, basket: &'a Basket) -> StorageFuture<'a, ()>;
+
+    fn save_bid<'a>(&'a self, bid: &'a BidRecord) -> StorageFuture<'a, ()>;
+
+    fn delete_bid(&self, bid_id: BidId) -> StorageFuture<'_, ()>;
+
+    fn save_auction<'a>(&'a self, outcome: &'a AuctionOutcome, users: &'a [User], consumed_bids: &'a [BidId], entries: &'a [JournalEntry]) -> StorageFuture<'a, ()>;
+
+    fn save_navs<'a>(&'a self, snapshots: &'a [NavSnapshot]) -> StorageFuture<'a, ()>;
+
+    fn save_controls<'a>(&'a self, controls: &'a BasketControls) -> StorageFuture<'a, ()>;
+
+    fn save_halted(&self, basket_id: BasketId, halted: bool) -> StorageFuture<'_, ()>;
+
+    fn load(&self) -> StorageFuture<'_, StoredExchange>;
+}
+
+
+/// Runs an `AsyncRepository` to completion on the calling thread. That thread must be a
+/// worker of a multi-threaded tokio runtime, or outside any runtime with `handle` given.
+pub struct BlockingRepository<R: AsyncRepository> {
+    pub repository: R,
+    handle: tokio::runtime::Handle,
+}
+
+impl<R: AsyncRepository> BlockingRepository<R> {
+    pub fn new(repository: R, handle: tokio::runtime::Handle) -> Self {
+        BlockingRepository { repository, handle }
+    }
+
+    fn block_on<T>(&self, future: StorageFuture<'_, T>) -> Result<T, StorageError> {
+        match tokio::runtime::Handle::try_current() {
+            Ok(_) => tokio::task::block_in_place(|| self.handle.block_on(future)),
+            Err(_) => self.handle.block_on(future),
+        }
+    }
+}
+
+impl<R: AsyncRepository> Repository for BlockingRepository<R> {
+    fn save_user(&self, user: &User) -> Result<(), StorageError> {
+        self.block_on(self.repository.save_user(user))
+    }
+
+    fn save_basket(&self, basket: &Basket) -> Result<(), StorageError> {
+        self.block_on(self.repository.save_basket(basket))
+    }
+
+    fn save_bid(&self, bid: &BidRecord) -> Result<(), StorageError> {
+        self.block_on(self.repository.save_bid(bid))
+    }
+
+    fn delete_bid(&self, bid_id: BidId) -> Result<(), StorageError> {
+        self.block_on(self.repository.delete_bid(bid_id))
+    }
+
+    fn save_auction(&self, outcome: &AuctionOutcome, users: &[User], consumed_bids: &[BidId], entries: &[JournalEntry]) -> Result<(), StorageError> {
+        self.block_on(self.repository.save_auction(outcome, users, consumed_bids, entries))
+    }
+
+    fn save_navs(&self, snapshots: &[NavSnapshot]) -> Result<(), StorageError> {
+        self.block_on(self.repository.save_navs(snapshots))
+    }
+
+    fn save_controls(&self, controls: &BasketControls) -> Result<(), StorageError> {
+        self.block_on(self.repository.save_controls(controls))
+    }
+
+    fn save_halted(&self, basket_id: BasketId, halted: bool) -> Result<(), StorageError> {
+        self.block_on(self.repository.save_halted(basket_id, halted))
+    }
+
+    fn load(&self) -> Result<StoredExchange, StorageError> {
+        self.block_on(self.repository.load())
+    }
+}
+
+
+/// A repository that forgets everything with the process, for tests and throwaway runs.
+#[derive(Debug, Default)]
+pub struct MemoryRepository {
+    users: Mutex<BTreeMap<UserId, User>>,
+    baskets: Mutex<BTreeMap<BasketId, Basket>>,
+    bids: Mutex<BTreeMap<BidId, BidRecord>>,
+    outcomes: Mutex<BTreeMap<AuctionId, AuctionOutcome>>,
+    navs: Mutex<BTreeMap<(BasketId, u64), NavSnapshot>>,
+    controls: Mutex<BTreeMap<BasketId, BasketControls>>,
+    ledger: Mutex<BTreeMap<u64, JournalEntry>>,
+    halted: Mutex<BTreeSet<BasketId>>,
+}
+
+impl MemoryRepository {
+    pub fn new() -> Self {
+        MemoryRepository::default()
+    }
+}
+
+impl Repository for MemoryRepository {
+    fn save_user(&self, user: &User) -> Result<(), StorageError> {
+        self.users.lock().unwrap().insert(user.id, user.clone());
+        Ok(())
+    }
+
+    fn save_basket(&self, basket: &Basket) -> Result<(), StorageError> {
+        self.baskets.lock().unwrap().insert(basket.id, basket.clone());
+        Ok(())
+    }
+
+    fn save_bid(&self, bid: &BidRecord) -> Result<(), StorageError> {
+        self.bids.lock().unwrap().insert(bid.id, bid.clone());
+        Ok(())
+    }
+
+    fn delete_bid(&self, bid_id: BidId) -> Result<(), StorageError> {
+        self.bids.lock().unwrap().remove(&bid_id);
+        Ok(())
+    }
+
+    fn save_auction(&self, outcome: &AuctionOutcome, users: &[User], consumed_bids: &[BidId], entries: &[JournalEntry]) -> Result<(), StorageError> {
+        let mut stored_users = self.users.lock().unwrap();
+        let mut bids = self.bids.lock().unwrap();
+        let mut ledger = self.ledger.lock().unwrap();
+        self.outcomes.lock().unwrap().insert(outcome.auction_id, outcome.clone());
+        for user in users {
+            stored_users.insert(user.id, user.clone());
+        }
+        for entry in entries {
+            ledger.insert(entry.id, entry.clone());
+        }
+        for bid_id in consumed_bids {
+            bids.remove(bid_id);
+        }
+        Ok(())
+    }
+
+    fn save_navs(&self, snapshots: &[NavSnapshot]) -> Result<(), StorageError> {
+        let mut navs = self.navs.lock().unwrap();
+        for snapshot in snapshots {
+            navs.insert((snapshot.basket_id, snapshot.at), *snapshot);
+        }
+        Ok(())
+    }
+
+    fn save_controls(&self, controls: &BasketControls) -> Result<(), StorageError> {
+        self.controls.lock().unwrap().insert(controls.basket_id, *controls);
+        Ok(())
+    }
+
+    fn save_halted(&self, basket_id: BasketId, halted: bool) -> Result<(), StorageError> {
+        let mut stored = self.halted.lock().unwrap();
+        if halted {
+            stored.insert(basket_id);
+        } else {
+            stored.remove(&basket_id);
+        }
+        Ok(())
+    }
+
+    fn load(&self) -> Result<StoredExchange, StorageError> {
+        Ok(StoredExchange {
+            users: self.users.lock().unwrap().values().cloned().collect(),
+            baskets: self.baskets.lock().unwrap().values().cloned().collect(),
+            bids: self.bids.lock().unwrap().values().cloned().collect(),
+            outcomes: self.outcomes.lock().unwrap().values().cloned().collect(),
+            navs: self.navs.lock().unwrap().values().copied().collect(),
+            controls: self.controls.lock().unwrap().values().copied().collect(),
+            ledger: self.ledger.lock().unwrap().values().cloned().collect(),
+            halted: self.halted.lock().unwrap().iter().copied().collect(),
+        })
+    }
+}
+
+
+/// Big-endian ids, so each tree iterates in id order.
+fn key(id: u64) -> [u8; 8] {
+    id.to_be_bytes()
+}
+
+fn values<T: DeserializeOwned>(tree: &sled::Tree) -> Result<Vec<T>, StorageError> {
+    tree.iter().values().map(|value| Ok(serde_json::from_slice(&value?)?)).collect()
+}
+
+fn insert<T: Serialize>(tree: &sled::Tree, id: u64, value: &T) -> Result<(), StorageError> {
+    tree.insert(key(id), serde_json::to_vec(value)?)?;
+    Ok(())
+}
+
+
+/// An embedded sled database holding one JSON-encoded tree per kind of record.
+#[derive(Debug, Clone)]
+pub struct SledRepository {
+    db: sled::Db,
+    users: sled::Tree,
+    baskets: sled::Tree,
+    bids: sled::Tree,
+    outcomes: sled::Tree,
+    /// Keyed by basket id then time, so each basket's history iterates in time order.
+    navs: sled::Tree,
+    controls: sled::Tree,
+    ledger: sled::Tree,
+    halted: sled::Tree,
+}
+
+impl SledRepository {
+    /// Opens the database at `path`, creating it if needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        SledRepository::from_db(sled::open(path)?)
+    }
+
+    /// A database that lives only as long as the repository, for tests.
+    pub fn temporary() -> Result<Self, StorageError> {
+        SledRepository::from_db(sled::Config::new().temporary(true).open()?)
+    }
+
+    fn from_db(db: sled::Db) -> Result<Self, StorageError> {
+        Ok(SledRepository {
+            users: db.open_tree("users")?,
+            baskets: db.open_tree("baskets")?,
+            bids: db.open_tree("bids")?,
+            outcomes: db.open_tree("outcomes")?,
+            navs: db.open_tree("navs")?,
+            controls: db.open_tree("controls")?,
+            ledger: db.open_tree("ledger")?,
+            halted: db.open_tree("halted")?,
+            db,
+        })
+    }
+
+    /// Waits until every write so far is on disk.
+    pub fn flush(&self) -> Result<(), StorageError> {
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+impl Repository for SledRepository {
+    fn save_user(&self, user: &User) -> Result<(), StorageError> {
+        insert(&self.users, user.id.get(), user)
+    }
+
+    fn save_basket(&self, basket: &Basket) -> Result<(), StorageError> {
+        insert(&self.baskets, basket.id.get(), basket)
+    }
+
+    fn save_bid(&self, bid: &BidRecord) -> Result<(), StorageError> {
+        insert(&self.bids, bid.id.get(), bid)
+    }
+
+    fn delete_bid(&self, bid_id: BidId) -> Result<(), StorageError> {
+        self.bids.remove(key(bid_id.get()))?;
+        Ok(())
+    }
+
+    fn save_auction(&self, outcome: &AuctionOutcome, users: &[User], consumed_bids: &[BidId], entries: &[JournalEntry]) -> Result<(), StorageError> {
+        let encoded_outcome = serde_json::to_vec(outcome)?;
+        let encoded_users = users.iter()
+            .map(|user| Ok((key(user.id.get()), serde_json::to_vec(user)?)))
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        let encoded_entries = entries.iter()
+            .map(|entry| Ok((key(entry.id), serde_json::to_vec(entry)?)))
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        (&self.users, &self.bids, &self.outcomes, &self.ledger)
+            .transaction(|(users, bids, outcomes, ledger)| {
+                outcomes.insert(&key(outcome.auction_id.get()), encoded_outcome.as_slice())?;
+                for (id, user) in &encoded_users {
+                    users.insert(id, user.as_slice())?;
+                }
+                for (id, entry) in &encoded_entries {
+                    ledger.insert(id, entry.as_slice())?;
+                }
+                for bid_id in consumed_bids {
+                    bids.remove(&key(bid_id.get()))?;
+                }
+                Ok::<(), ConflictableTransactionError<()>>(())
+            })
+            .map_err(|error: TransactionError<()>| match error {
+                TransactionError::Storage(error) => StorageError::from(error),
+                TransactionError::Abort(()) => StorageError::Backend("auction write aborted".to_string()),
+            })
+    }
+
+    fn save_navs(&self, snapshots: &[NavSnapshot]) -> Result<(), StorageError> {
+        let mut batch = sled::Batch::default();
+        for snapshot in snapshots {
+            let mut id = key(snapshot.basket_id.get()).to_vec();
+            id.extend_from_slice(&key(snapshot.at));
+            batch.insert(id, serde_json::to_vec(snapshot)?);
+        }
+        self.navs.apply_batch(batch)?;
+        Ok(())
+    }
+
+    fn save_controls(&self, controls: &BasketControls) -> Result<(), StorageError> {
+        insert(&self.controls, controls.basket_id.get(), controls)
+    }
+
+    fn save_halted(&self, basket_id: BasketId, halted: bool) -> Result<(), StorageError> {
+        if halted {
+            insert(&self.halted, basket_id.get(), &basket_id)
+        } else {
+            self.halted.remove(key(basket_id.get()))?;
+            Ok(())
+        }
+    }
+
+    fn load(&self) -> Result<StoredExchange, StorageError> {
+        Ok(StoredExchange {
+            users: values(&self.users)?,
+            baskets: values(&self.baskets)?,
+            bids: values(&self.bids)?,
+            outcomes: values(&self.outcomes)?,
+            navs: values(&self.navs)?,
+            controls: values(&self.controls)?,
+            ledger: values(&self.ledger)?,
+            halted: values(&self.halted)?,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use auction::config::Config;
+    use auction::sim::SimClock;
+    use model::model::{Asset, AssetInfo, BidType};
+    use crate::exchange::{AuctionRequest, Exchange, Mechanism};
+
+    #[test]
+    fn test_exchange_recovers_from_sled() {
+        let dir = std::env::temp_dir().join(format!("combidex-storage-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let repository = Arc::new(SledRepository::open(&dir).unwrap());
+        let (alice, basket, resting, auction_id, entries, cash) = {
+            let mut exchange = Exchange::recover(repository.clone()).unwrap();
+            let alice = exchange.register_user("Alice", 100_000.0).unwrap().id;
+            let bob = exchange.register_user("Bob", 100_000.0).unwrap().id;
+            let sold = exchange.create_basket(vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60_000.0)]).unwrap().id;
+            let basket = exchange.create_basket(vec![AssetInfo::new(Asset::new("ETH", "USD"), 10.0, 2_000.0)]).unwrap().id;
+            exchange.submit_bid(alice, sold, BidType::XOR, 61_000.0, None).unwrap();
+            exchange.submit_bid(bob, sold, BidType::XOR, 60_500.0, None).unwrap();
+            let cancelled = exchange.submit_bid(bob, basket, BidType::XOR, 19_000.0, None).unwrap();
+            exchange.cancel_bid(cancelled.id).unwrap();
+            let resting = exchange.submit_bid(bob, basket, BidType::XOR, 20_000.0, None).unwrap();
+            let outcome = exchange.run_auction(&AuctionRequest::new(sold, Mechanism::Xor)).unwrap();
+            exchange.halt_auction(basket, None).unwrap();
+            repository.flush().unwrap();
+            (alice, basket, resting, outcome.auction_id, exchange.ledger().entries().to_vec(), exchange.ledger().cash_balance(alice))
+        };
+
+        // sled keeps its file lock briefly after close, so recover from the open database
+        let mut recovered = Exchange::recover(repository).unwrap();
+        assert_eq!(recovered.user(alice).unwrap().balance, 39_000.0);
+        assert_eq!(recovered.baskets().len(), 2);
+        assert_eq!(recovered.bids_for(basket), vec![&resting]);
+        assert_eq!(recovered.outcome(auction_id).unwrap().report.settlements[0].payment, 61_000.0);
+        // The clearing ledger and the operator's halt come back too
+        assert!(!entries.is_empty());
+        assert_eq!(format!("{:?}", recovered.ledger().entries()), format!("{:?}", entries));
+        assert_eq!(recovered.ledger().cash_balance(alice), cash);
+        assert!(recovered.is_halted(basket));
+        recovered.resume_auction(basket, None).unwrap();
+        // New ids continue after the recovered ones
+        let carol = recovered.register_user("Carol", 1.0).unwrap();
+        assert!(carol.id.get() > resting.id.get());
+        let outcome = recovered.run_auction(&AuctionRequest::new(basket, Mechanism::Xor)).unwrap();
+        assert!(outcome.auction_id > auction_id);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_bidding_deadlines_survive_recovery_and_snapshots() {
+        let clock = SimClock::new(1_700_000_000_000);
+        let config = Config::from_toml("[auction.soft_close]\nenabled = true\nwindow_secs = 60\nextension_secs = 30\nmax_extensions = 1").unwrap();
+        let repository = Arc::new(SledRepository::temporary().unwrap());
+        let mut exchange = Exchange::recover(repository.clone()).unwrap().with_clock(Arc::new(clock.clone())).with_config(&config);
+        let alice = exchange.register_user("Alice", 100_000.0).unwrap().id;
+        let bob = exchange.register_user("Bob", 100_000.0).unwrap().id;
+        let basket = exchange.create_basket(vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60_000.0)]).unwrap().id;
+        let closes_at = exchange.extend_bidding(basket, Duration::from_secs(300), None).unwrap();
+        clock.advance(Duration::from_secs(250));
+        exchange.submit_bid(alice, basket, BidType::XOR, 61_000.0, None).unwrap();
+        assert_eq!(exchange.closes_at(basket), Some(closes_at + 30_000));
+
+        let restored = Exchange::restore(&exchange.snapshot().unwrap()).unwrap();
+        let recovered = Exchange::recover(repository).unwrap();
+        for resumed in [restored, recovered] {
+            let mut resumed = resumed.with_clock(Arc::new(clock.clone())).with_config(&config);
+            assert_eq!(resumed.closes_at(basket), Some(closes_at + 30_000));
+            // The one extension allowed is spent, so a new leading bid leaves the close alone
+            resumed.submit_bid(bob, basket, BidType::XOR, 62_000.0, None).unwrap();
+            assert_eq!(resumed.closes_at(basket), Some(closes_at + 30_000));
+        }
+    }
+
+    #[test]
+    fn test_repositories_agree() {
+        let repositories: Vec<Box<dyn Repository>> = vec![Box::new(MemoryRepository::new()), Box::new(SledRepository::temporary().unwrap())];
+        for repository in repositories {
+            repository.save_user(&User::new(2, "Bob", 5.0)).unwrap();
+            repository.save_user(&User::new(1, "Alice", 5.0)).unwrap();
+            repository.save_bid(&BidRecord { id: BidId(3), user_id: UserId(1), basket_id: BasketId(9), bid_type: BidType::OR, price: 1.0, quantity: Some(0.5) }).unwrap();
+            repository.delete_bid(BidId(3)).unwrap();
+            let stored = repository.load().unwrap();
+            assert_eq!(stored.users.iter().map(|user| user.id).collect::<Vec<_>>(), vec![UserId(1), UserId(2)]);
+            assert!(stored.bids.is_empty());
+        }
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use serde::{Deserialize, Serialize};
+use model::ids::{AuctionId, BasketId, BidId, UserId};
+use crate::admin::AdminAction;
+use crate::event_store::{ExchangeEvent, StoredEvent};
+use crate::exchange::BidRecord;
+
+
+/// Accounts known to share an owner, e.g. from onboarding or a common funding source.
+/// Linking is transitive: linking a to b and b to c puts all three in one group.
+#[derive(Debug, Clone, Default)]
+pub struct LinkedAccounts {
+    groups: Vec<BTreeSet<UserId>>,
+}
+
+impl LinkedAccounts {
+    pub fn new() -> Self {
+        LinkedAccounts::default()
+    }
+
+    pub fn link(&mut self, a: UserId, b: UserId) {
+        let mut merged: BTreeSet<UserId> = BTreeSet::from([a, b]);
+        self.groups.retain(|group| {
+            let touches = group.contains(&a) || group.contains(&b);
+            if touches {
+                merged.extend(group);
+            }
+            !touches
+        });
+        self.groups.push(merged);
+    }
+
+    pub fn are_linked(&self, a: UserId, b: UserId) -> bool {
+        a == b || self.groups.iter().any(|group| group.contains(&a) && group.contains(&b))
+    }
+
+    fn group_of(&self, user_id: UserId) -> Option<&BTreeSet<UserId>> {
+        self.groups.iter().find(|group| group.contains(&user_id))
+    }
+}
+
+/// Thresholds of the checks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SurveillanceConfig {
+    /// How close to the close of bidding a withdrawal counts as last-second.
+    pub late_window_ms: u64,
+    /// Withdrawals by at least this many bidders in the late window count as coordinated.
+    pub coordinated_withdrawals: usize,
+    /// Auctions the same bidders must meet in before rotating winners is suspicious.
+    pub rotation_auctions: usize,
+}
+
+impl Default for SurveillanceConfig {
+    fn default() -> Self {
+        SurveillanceConfig { late_window_ms: 60_000, coordinated_withdrawals: 2, rotation_auctions: 3 }
+    }
+}
+
+/// A suspicious pattern in one auction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Flag {
+    /// Linked accounts bid against each other, so one may be bidding the price up for
+    /// the other; `won` if one of them won.
+    LinkedBidders { users: Vec<UserId>, won: bool },
+    /// The same bidders keep meeting and take turns winning.
+    BidRotation { bidders: Vec<UserId>, auctions: usize },
+    /// Several bidders withdrew bids just before bidding closed.
+    LateWithdrawals { users: Vec<UserId> },
+    /// Different bidders placed exactly the same bid.
+    IdenticalBids { users: Vec<UserId>, price: f64 },
+}
+
+impl Flag {
+    /// How strongly the pattern alone suggests manipulation, in (0, 1).
+    pub fn weight(&self) -> f64 {
+        match self {
+            Flag::LinkedBidders { won: true, .. } => 0.6,
+            Flag::LinkedBidders { won: false, .. } => 0.3,
+            Flag::BidRotation { .. } => 0.4,
+            Flag::LateWithdrawals { .. } => 0.3,
+            Flag::IdenticalBids { .. } => 0.2,
+        }
+    }
+}
+
+/// The flags raised on one settled auction. `score` combines their weights as
+/// independent evidence, `1 - Π(1 - weight)`, so it is 0 for a clean auction and grows
+/// towards 1 as patterns pile up.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuctionReport {
+    pub auction_id: AuctionId,
+    pub basket_id: BasketId,
+    pub bidders: Vec<UserId>,
+    pub winners: Vec<UserId>,
+    pub flags: Vec<Flag>,
+    pub score: f64,
+}
+
+impl AuctionReport {
+    pub fn is_clean(&self) -> bool {
+        self.flags.is_empty()
+    }
+}
+
+/// What the trail says about one basket up to its settlement.
+#[derive(Debug, Default)]
+struct BasketTrail {
+    bids: Vec<BidRecord>,
+    /// Bidder and journal time of each bid withdrawn by its bidder.
+    withdrawals: Vec<(UserId, u64)>,
+    closes_at: Option<u64>,
+}
+
+
+/// Screens every auction settled in `events`, in the order they settled.
+pub fn surveil(events: &[StoredEvent], links: &LinkedAccounts, config: &SurveillanceConfig) -> Vec<AuctionReport> {
+    let mut trails: HashMap<BasketId, BasketTrail> = HashMap::new();
+    let mut bid_baskets: HashMap<BidId, (BasketId, UserId)> = HashMap::new();
+    let mut reports = Vec::new();
+    for stored in events {
+        match &stored.event {
+            ExchangeEvent::BidSubmitted { bid } => {
+                bid_baskets.insert(bid.id, (bid.basket_id, bid.user_id));
+                trails.entry(bid.basket_id).or_default().bids.push(bid.clone());
+            }
+            ExchangeEvent::BidAmended { bid } => trails.entry(bid.basket_id).or_default().bids.push(bid.clone()),
+            ExchangeEvent::BidCancelled { bid_id } => {
+                if let Some((basket_id, user_id)) = bid_baskets.get(bid_id) {
+                    trails.entry(*basket_id).or_default().withdrawals.push((*user_id, stored.timestamp));
+                }
+            }
+            ExchangeEvent::BiddingExtended { basket_id, closes_at, .. } => trails.entry(*basket_id).or_default().closes_at = Some(*closes_at),
+            ExchangeEvent::Admin { action: AdminAction::Extend { basket_id, closes_at }, .. } => {
+                trails.entry(*basket_id).or_default().closes_at = Some(*closes_at);
+            }
+            ExchangeEvent::Settled { outcome, .. } => {
+                let trail = trails.remove(&outcome.basket_id).unwrap_or_default();
+                let winners: BTreeSet<UserId> = outcome.payments.keys().copied().collect();
+                let closes_at = trail.closes_at.unwrap_or(stored.timestamp);
+                let mut flags = Vec::new();
+                flags.extend(linked_bidders(&trail, &winners, links));
+                flags.extend(late_withdrawals(&trail, closes_at, config));
+                flags.extend(identical_bids(&trail, links));
+                let bidders: BTreeSet<UserId> = trail.bids.iter().map(|bid| bid.user_id).collect();
+                reports.push(AuctionReport {
+                    auction_id: outcome.auction_id,
+                    basket_id: outcome.basket_id,
+                    bidders: bidders.into_iter().collect(),
+                    winners: winners.into_iter().collect(),
+                    flags,
+                    score: 0.0,
+                });
+            }
+            _ => {}
+        }
+    }
+    flag_rotations(&mut reports, config);
+    for report in &mut reports {
+        report.score = 1.0 - report.flags.iter().map(|flag| 1.0 - flag.weight()).product::<f64>();
+        if !report.is_clean() {
+            tracing::warn!(auction_id = report.auction_id.get(), basket_id = report.basket_id.get(), score = report.score, flags = report.flags.len(), "suspicious auction");
+        }
+    }
+    reports
+}
+
+fn linked_bidders(trail: &BasketTrail, winners: &BTreeSet<UserId>, links: &LinkedAccounts) -> Vec<Flag> {
+    let bidders: BTreeSet<UserId> = trail.bids.iter().map(|bid| bid.user_id).collect();
+    let mut seen = BTreeSet::new();
+    let mut flags = Vec::new();
+    for bidder in &bidders {
+        let Some(group) = links.group_of(*bidder) else { continue };
+        if !seen.insert(group.clone()) {
+            continue;
+        }
+        let users: Vec<UserId> = bidders.intersection(group).copied().collect();
+        if users.len() > 1 {
+            let won = users.iter().any(|user_id| winners.contains(user_id));
+            flags.push(Flag::LinkedBidders { users, won });
+        }
+    }
+    flags
+}
+
+fn late_withdrawals(trail: &BasketTrail, closes_at: u64, config: &SurveillanceConfig) -> Option<Flag> {
+    let from = closes_at.saturating_sub(config.late_window_ms);
+    let users: BTreeSet<UserId> = trail.withdrawals.iter()
+        .filter(|(_, at)| (from..=closes_at).contains(at))
+        .map(|(user_id, _)| *user_id)
+        .collect();
+    (users.len() >= config.coordinated_withdrawals.max(1)).then(|| Flag::LateWithdrawals { users: users.into_iter().collect() })
+}
+
+/// Bids by unlinked bidders that match in type, price and quantity. Linked bidders are
+/// already flagged as such.
+fn identical_bids(trail: &BasketTrail, links: &LinkedAccounts) -> Vec<Flag> {
+    let mut fingerprints: BTreeMap<(String, u64, Option<u64>), BTreeSet<UserId>> = BTreeMap::new();
+    for bid in &trail.bids {
+        let fingerprint = (format!("{:?}", bid.bid_type), bid.price.to_bits(), bid.quantity.map(f64::to_bits));
+        fingerprints.entry(fingerprint).or_default().insert(bid.user_id);
+    }
+    fingerprints.into_iter()
+        .filter(|(_, users)| users.iter().any(|a| users.iter().any(|b| !links.are_linked(*a, *b))))
+        .map(|((_, price, _), users)| Flag::IdenticalBids { users: users.into_iter().collect(), price: f64::from_bits(price) })
+        .collect()
+}
+
+/// Flags every auction among a recurring group of bidders when each of them has won and
+/// no one won twice running, once the group has met `rotation_auctions` times.
+fn flag_rotations(reports: &mut [AuctionReport], config: &SurveillanceConfig) {
+    let mut groups: BTreeMap<Vec<UserId>, Vec<usize>> = BTreeMap::new();
+    for (index, report) in reports.iter().enumerate() {
+        if report.bidders.len() > 1 && !report.winners.is_empty() {
+            groups.entry(report.bidders.clone()).or_default().push(index);
+        }
+    }
+    for (bidders, indices) in groups {
+        if indices.len() < config.rotation_auctions.max(2) {
+            continue;
+        }
+        let everyone_won = bidders.iter().all(|bidder| indices.iter().any(|index| reports[*index].winners.contains(bidder)));
+        let alternates = indices.windows(2).all(|pair| reports[pair[0]].winners.iter().all(|winner| !reports[pair[1]].winners.contains(winner)));
+        if everyone_won && alternates {
+            for index in &indices {
+                reports[*index].flags.push(Flag::BidRotation { bidders: bidders.clone(), auctions: indices.len() });
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use auction::sim::SimClock;
+    use model::model::{Asset, AssetInfo, BidType};
+    use crate::event_store::{EventStore, MemoryEventStore};
+    use crate::exchange::{AuctionRequest, Exchange, Mechanism};
+
+    #[test]
+    fn test_linked_late_identical_and_rotating_bidders_are_flagged() {
+        let clock = SimClock::new(1_700_000_000_000);
+        let journal = Arc::new(MemoryEventStore::with_clock(Arc::new(clock.clone())));
+        let mut exchange = Exchange::new().with_clock(Arc::new(clock.clone())).with_journal(journal.clone());
+        let [alice, bob, carol, dave] = ["Alice", "Bob", "Carol", "Dave"].map(|name| exchange.register_user(name, 1_000_000.0).unwrap().id);
+        let mut basket = || exchange.create_basket(vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60_000.0)]).unwrap().id;
+        let baskets: Vec<BasketId> = (0..5).map(|_| basket()).collect();
+
+        // Bob bids up his own other account, Carol and Dave bid alike and both pull out
+        // seconds before the close
+        exchange.extend_bidding(baskets[0], Duration::from_secs(300), None).unwrap();
+        for (user_id, price) in [(alice, 61_000.0), (bob, 60_000.0), (carol, 59_512.5), (dave, 59_512.5)] {
+            exchange.submit_bid(user_id, baskets[0], BidType::XOR, price, None).unwrap();
+        }
+        let pulled: Vec<BidId> = [(carol, 58_000.0), (dave, 57_000.0)].map(|(user_id, price)| exchange.submit_bid(user_id, baskets[0], BidType::XOR, price, None).unwrap().id).to_vec();
+        clock.advance(Duration::from_secs(290));
+        for bid_id in pulled {
+            exchange.cancel_bid(bid_id).unwrap();
+        }
+        clock.advance(Duration::from_secs(10));
+        exchange.run_auction(&AuctionRequest::new(baskets[0], Mechanism::Xor)).unwrap();
+
+        // Carol and Dave take turns
+        for (basket_id, (carol_price, dave_price)) in baskets[1..4].iter().zip([(61_000.0, 60_000.0), (60_100.0, 61_100.0), (61_200.0, 60_200.0)]) {
+            exchange.submit_bid(carol, *basket_id, BidType::XOR, carol_price, None).unwrap();
+            exchange.submit_bid(dave, *basket_id, BidType::XOR, dave_price, None).unwrap();
+            exchange.run_auction(&AuctionRequest::new(*basket_id, Mechanism::Xor)).unwrap();
+        }
+        exchange.submit_bid(alice, baskets[4], BidType::XOR, 61_000.0, None).unwrap();
+        exchange.submit_bid(carol, baskets[4], BidType::XOR, 60_000.0, None).unwrap();
+        exchange.run_auction(&AuctionRequest::new(baskets[4], Mechanism::Xor)).unwrap();
+
+        let mut links = LinkedAccounts::new();
+        links.link(alice, bob);
+        let reports = surveil(&journal.read_from(1).unwrap(), &links, &SurveillanceConfig::default());
+        assert_eq!(reports.len(), 5);
+        assert_eq!(reports[0].winners, vec![alice]);
+        assert_eq!(reports[0].flags, vec![
+            Flag::LinkedBidders { users: vec![alice, bob], won: true },
+            Flag::LateWithdrawals { users: vec![carol, dave] },
+            Flag::IdenticalBids { users: vec![carol, dave], price: 59_512.5 },
+        ]);
+        assert!((reports[0].score - (1.0 - 0.4 * 0.7 * 0.8)).abs() < 1e-12);
+        for report in &reports[1..4] {
+            assert_eq!(report.flags, vec![Flag::BidRotation { bidders: vec![carol, dave], auctions: 3 }]);
+        }
+        assert!(reports[4].is_clean());
+        assert_eq!(reports[4].score, 0.0);
+    }
+
+    #[test]
+    fn test_links_are_transitive() {
+        let mut links = LinkedAccounts::new();
+        links.link(UserId(1), UserId(2));
+        links.link(UserId(3), UserId(4));
+        assert!(!links.are_linked(UserId(1), UserId(3)));
+        links.link(UserId(2), UserId(3));
+        assert!(links.are_linked(UserId(1), UserId(4)));
+        assert_eq!(links.groups.len(), 1);
+    }
+}
@@ -0,0 +1,83 @@
+use tracing::Subscriber;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::MakeWriter;
+
+
+/// Level filter from `RUST_LOG`, or `info` if it is unset or invalid.
+fn filter() -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"))
+}
+
+/// A subscriber writing each event to `writer` as one JSON object, with the fields of
+/// its spans, e.g. the `auction_id` and `round` of the auction it happened in.
+pub fn json_subscriber<W>(writer: W) -> impl Subscriber + Send + Sync
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    tracing_subscriber::fmt()
+        .json()
+        .with_env_filter(filter())
+        .with_current_span(true)
+        .with_span_list(true)
+        .with_writer(writer)
+        .finish()
+}
+
+/// Installs [`json_subscriber`] on stdout for the whole process. Fails if a subscriber is
+/// already installed.
+pub fn init() -> Result<(), tracing::subscriber::SetGlobalDefaultError> {
+    tracing::subscriber::set_global_default(json_subscriber(std::io::stdout))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use model::model::{Asset, AssetInfo, BidType};
+    use crate::exchange::{AuctionRequest, Exchange, Mechanism};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Buffer;
+
+        fn make_writer(&'a self) -> Buffer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn test_auctions_log_json_with_their_context() {
+        let buffer = Buffer::default();
+        tracing::subscriber::with_default(json_subscriber(buffer.clone()), || {
+            let mut exchange = Exchange::new();
+            let alice = exchange.register_user("Alice", 100_000.0).unwrap().id;
+            let basket = exchange.create_basket(vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60_000.0)]).unwrap().id;
+            exchange.submit_bid(alice, basket, BidType::XOR, 61_000.0, None).unwrap();
+            exchange.run_auction(&AuctionRequest::new(basket, Mechanism::Xor)).unwrap();
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<serde_json::Value> = output.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        let cleared = lines.iter().find(|line| line["fields"]["message"] == "settlement cleared").unwrap();
+        assert_eq!(cleared["fields"]["welfare"], 61_000.0);
+        assert_eq!(cleared["span"]["name"], "clearing");
+        assert_eq!(cleared["spans"][0]["mechanism"], "XOR");
+        let settled = lines.iter().find(|line| line["fields"]["message"] == "auction settled").unwrap();
+        assert_eq!(settled["fields"]["auction_id"], cleared["span"]["auction_id"]);
+    }
+}
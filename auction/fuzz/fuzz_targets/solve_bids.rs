@@ -0,0 +1,36 @@
+//! Parses a basket and bids from JSON and runs every solver on them. Run with
+//! `cargo fuzz run solve_bids fuzz/seeds/solve_bids` from the auction crate.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use auction::wdp::WDPSolver;
+use model::helpers::can_fulfill;
+use model::model::{Basket, Bid};
+
+/// Branch and bound is exponential in the number of bids.
+const MAX_BIDS: usize = 12;
+
+/// Amounts the solvers can add and compare without overflowing or losing all precision.
+fn is_reasonable(amount: f64) -> bool {
+    amount.is_finite() && amount.abs() < 1e12
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Ok((basket, bids)) = serde_json::from_slice::<(Basket, Vec<Bid>)>(data) else { return };
+    let amounts = basket.assets.iter().flat_map(|asset| [asset.quantity, asset.price])
+        .chain(bids.iter().flat_map(|bid| [bid.price, bid.quantity.unwrap_or(1.0), bid.user.balance]));
+    if bids.len() > MAX_BIDS || !amounts.into_iter().all(is_reasonable) {
+        return;
+    }
+
+    let _ = WDPSolver::solve_xor(&bids, &basket);
+    let _ = WDPSolver::solve_or(&bids, &basket);
+    let _ = WDPSolver::maximize_welfare_vcg(&bids, &basket);
+    let _ = WDPSolver::dynamic_programming(&bids, &basket);
+
+    let (greedy, greedy_welfare) = WDPSolver::maximize_welfare_cca(&bids, &basket);
+    let (exact, exact_welfare) = WDPSolver::branch_and_bound(&bids, &basket);
+    assert!(can_fulfill(&greedy, &basket));
+    assert!(can_fulfill(&exact, &basket));
+    assert!(exact_welfare >= greedy_welfare - 1e-6 * greedy_welfare.abs().max(1.0));
+});
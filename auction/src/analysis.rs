@@ -0,0 +1,206 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use serde::{Deserialize, Serialize};
+use crate::cca_auction::CombiClockAuction;
+use crate::config::AuctionConfig;
+use crate::core_pricing::CorePricing;
+use crate::observer::AuctionObservers;
+use crate::simple_auction::{OrAuction, XorAuction};
+use crate::vcg_auction::VCGAuction;
+use crate::wdp::WDPSolver;
+use model::model::{Bid, Basket, AssetInfo};
+use model::ids::{BasketId, UserId};
+
+/// Winning bids, their allocation and payments by user.
+type Cleared = (Vec<Bid>, HashMap<UserId, Vec<AssetInfo>>, HashMap<UserId, f64>);
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mechanism {
+    Xor,
+    Or,
+    Vcg,
+    Core,
+    Cca,
+}
+
+impl Mechanism {
+    pub const ALL: [Mechanism; 5] = [Mechanism::Xor, Mechanism::Or, Mechanism::Vcg, Mechanism::Core, Mechanism::Cca];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mechanism::Xor => "XOR",
+            Mechanism::Or => "OR",
+            Mechanism::Vcg => "VCG",
+            Mechanism::Core => "Core",
+            Mechanism::Cca => "CCA",
+        }
+    }
+}
+
+/// How one mechanism would have cleared the bids. `welfare` is the winners' bids, the
+/// value they declared for what they won, and `bidder_surplus` what they keep of it
+/// after paying `revenue`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MechanismResult {
+    pub mechanism: Mechanism,
+    pub winners: Vec<UserId>,
+    pub payments: BTreeMap<UserId, f64>,
+    pub welfare: f64,
+    pub revenue: f64,
+    pub bidder_surplus: f64,
+    /// Welfare over the most any allocation the basket can supply achieves, when that is
+    /// above zero.
+    pub efficiency: Option<f64>,
+    /// Whether the basket can supply every winner at once.
+    pub feasible: bool,
+}
+
+/// One bidder's share of the basket under each mechanism, in the order of
+/// [`MechanismComparison::results`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BidderShares {
+    pub user_id: UserId,
+    pub shares: Vec<f64>,
+}
+
+/// The same bids run through every mechanism, side by side.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MechanismComparison {
+    pub basket_id: BasketId,
+    pub bids: usize,
+    /// The welfare of the best allocation the basket can supply.
+    pub optimal_welfare: f64,
+    pub results: Vec<MechanismResult>,
+    /// Bidders who win under any mechanism, by user.
+    pub shares: Vec<BidderShares>,
+}
+
+impl MechanismComparison {
+    pub fn result(&self, mechanism: Mechanism) -> Option<&MechanismResult> {
+        self.results.iter().find(|result| result.mechanism == mechanism)
+    }
+}
+
+/// Runs `bids` through every mechanism without settling any of them: XOR, OR and CCA
+/// charge the winning bids, VCG and core pricing their own payments. The CCA clock starts
+/// at the basket's listed prices with `config`'s increment and rounds.
+pub fn compare(bids: &[Bid], basket: &Basket, config: &AuctionConfig) -> MechanismComparison {
+    let (_, optimal_welfare) = WDPSolver::branch_and_bound(bids, basket);
+    let mut allocations = Vec::new();
+    let results = Mechanism::ALL.iter()
+        .map(|mechanism| {
+            let (winners, allocation, payments) = clear(*mechanism, bids, basket, config);
+            let winning: Vec<&Bid> = winners.iter().collect();
+            let welfare: f64 = winners.iter().map(|bid| bid.price).sum();
+            let revenue: f64 = payments.values().sum();
+            let mut users: Vec<UserId> = winners.iter().map(|bid| bid.user.id).collect();
+            users.sort();
+            users.dedup();
+            allocations.push(allocation);
+            MechanismResult {
+                mechanism: *mechanism,
+                winners: users,
+                payments: payments.into_iter().collect(),
+                welfare,
+                revenue,
+                bidder_surplus: welfare - revenue,
+                efficiency: (optimal_welfare > 0.0).then(|| welfare / optimal_welfare),
+                feasible: model::helpers::can_fulfill(&winning, basket),
+            }
+        })
+        .collect();
+
+    let users: BTreeSet<UserId> = allocations.iter().flat_map(|allocation| allocation.keys().copied()).collect();
+    let shares = users.into_iter()
+        .map(|user_id| BidderShares {
+            user_id,
+            shares: allocations.iter().map(|allocation| share(allocation.get(&user_id), basket)).collect(),
+        })
+        .collect();
+    MechanismComparison { basket_id: basket.id, bids: bids.len(), optimal_welfare, results, shares }
+}
+
+/// The winning bids, their allocation and the payments by user under `mechanism`.
+fn clear(mechanism: Mechanism, bids: &[Bid], basket: &Basket, config: &AuctionConfig) -> Cleared {
+    let (winners, allocation, payments) = match mechanism {
+        Mechanism::Xor => match XorAuction::evaluate_partial_bids(bids, basket) {
+            Some((winner, allocation)) => (vec![winner.clone()], allocation, None),
+            None => (Vec::new(), HashMap::new(), None),
+        },
+        Mechanism::Or => {
+            let (winners, allocation) = OrAuction::evaluate_bids(bids, basket);
+            (winners.into_iter().cloned().collect(), allocation, None)
+        }
+        Mechanism::Vcg => {
+            let (winners, allocation, payments) = VCGAuction::outcome(bids, basket);
+            (winners.into_iter().cloned().collect(), allocation, Some(payments))
+        }
+        Mechanism::Core => {
+            let (winners, allocation, payments) = CorePricing::outcome(bids, basket);
+            (winners.into_iter().cloned().collect(), allocation, Some(payments))
+        }
+        Mechanism::Cca => {
+            let initial_prices = basket.assets.iter().map(|info| (info.asset.base.as_str(), info.price)).collect();
+            let (standing, allocation) = CombiClockAuction::configured_outcome(bids, basket, initial_prices, config, &AuctionObservers::default());
+            let winners = standing.into_iter().filter(|bid| allocation.contains_key(&bid.user.id)).collect();
+            (winners, allocation, None)
+        }
+    };
+    let payments = payments.unwrap_or_else(|| {
+        let mut charged = HashMap::new();
+        for bid in &winners {
+            *charged.entry(bid.user.id).or_insert(0.0) += bid.price;
+        }
+        charged
+    });
+    (winners, allocation, payments)
+}
+
+/// The fraction of the basket in `assets`, by its first asset.
+fn share(assets: Option<&Vec<AssetInfo>>, basket: &Basket) -> f64 {
+    let (Some(assets), Some(first)) = (assets, basket.assets.first()) else {
+        return 0.0;
+    };
+    assets.iter()
+        .find(|info| info.asset == first.asset)
+        .map_or(0.0, |info| if first.quantity > 0.0 { info.quantity / first.quantity } else { 0.0 })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::model::{User, Asset, BidType};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_compare_reports_each_mechanism_on_the_same_bids() {
+        let basket = Basket { id: BasketId(1), assets: vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60000.0)] };
+        let bid = |id, price, quantity| Bid::new(Arc::new(User::new(id, "Bidder", 1000000.0)), 1, BidType::XOR, price, Some(quantity));
+        let bids = vec![bid(1, 40000.0, 0.5), bid(2, 40000.0, 0.5), bid(3, 70000.0, 1.0)];
+
+        let comparison = compare(&bids, &basket, &AuctionConfig::default());
+        assert_eq!(comparison.optimal_welfare, 80000.0);
+        let names: Vec<&str> = comparison.results.iter().map(|result| result.mechanism.name()).collect();
+        assert_eq!(names, vec!["XOR", "OR", "VCG", "Core", "CCA"]);
+
+        // XOR sells the whole basket to its highest bid, short of the two halves together
+        let xor = comparison.result(Mechanism::Xor).unwrap();
+        assert_eq!((xor.winners.clone(), xor.welfare, xor.bidder_surplus), (vec![UserId(3)], 70000.0, 0.0));
+        assert_eq!(xor.efficiency, Some(70000.0 / 80000.0));
+
+        // Core pricing is efficient, and takes what the losing bid offered
+        let core = comparison.result(Mechanism::Core).unwrap();
+        assert!(core.feasible && core.efficiency == Some(1.0));
+        assert!((core.revenue - 70000.0).abs() < 1e-6 && (core.bidder_surplus - 10000.0).abs() < 1e-6);
+
+        for result in &comparison.results {
+            assert!((result.welfare - result.revenue - result.bidder_surplus).abs() < 1e-9);
+        }
+        let carol = comparison.shares.iter().find(|shares| shares.user_id == UserId(3)).unwrap();
+        assert_eq!((carol.shares[0], carol.shares[3]), (1.0, 0.0));
+        let alice = comparison.shares.iter().find(|shares| shares.user_id == UserId(1)).unwrap();
+        assert_eq!((alice.shares[0], alice.shares[3]), (0.0, 0.5));
+    }
+}
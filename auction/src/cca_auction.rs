@@ -2,36 +2,88 @@ use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use crate::wdp::WDPSolver;
 use model::model::{Bid, Basket, AssetInfo, User};
+use model::ids::UserId;
+use model::safe_math;
 use crate::clearing::Clearing;
+use crate::error::AuctionError;
+use crate::config::AuctionConfig;
+use crate::stats::CCA_ROUNDS;
+use crate::report::AuctionMetadata;
+use crate::observer::{AuctionEvent, AuctionObserver, AuctionObservers};
+
+/// Standing bids and their allocation, then the last price-raising round's bids and allocation.
+type ClockResult = (Vec<Bid>, HashMap<UserId, Vec<AssetInfo>>, Vec<Bid>, HashMap<UserId, Vec<AssetInfo>>);
+/// Standing bids and their allocation, then the users as settled by clearing.
+type ClearedAuction = (Vec<Bid>, HashMap<UserId, Vec<AssetInfo>>, HashMap<UserId, Arc<User>>);
+/// The bids admitted to a round, the excess demand for each asset, and the eligibility
+/// points each bidder demanded.
+type EvaluatedRound<'a> = (Vec<&'a Bid>, HashMap<&'a str, f64>, HashMap<UserId, f64>);
+
+/// Eligibility points of the basket's whole supply; demanding the whole of an asset is
+/// worth its share of the basket's value at the starting prices.
+pub const BASKET_POINTS: f64 = 100.0;
+
+/// Slack for rounding when comparing demanded points to eligibility.
+const POINTS_EPSILON: f64 = 1e-9;
 
 pub struct CombiClockAuction;
 
 impl CombiClockAuction {
 
+    /// Points per fraction of the basket demanded of each asset, from the value of its
+    /// whole supply at `prices` within the basket's, so that demanding all of every asset
+    /// is worth [`BASKET_POINTS`].
+    fn eligibility_points<'a>(basket: &'a Basket, prices: &HashMap<&'a str, f64>) -> HashMap<&'a str, f64> {
+        let price = |asset_info: &'a AssetInfo| prices.get(asset_info.asset.base.as_str()).copied().unwrap_or(asset_info.price);
+        let value: f64 = basket.assets.iter().map(|asset_info| asset_info.quantity * price(asset_info)).sum();
+        if !value.is_finite() || value <= 0.0 {
+            return HashMap::new();
+        }
+        basket.assets.iter().map(|asset_info| (asset_info.asset.base.as_str(), BASKET_POINTS * asset_info.quantity * price(asset_info) / value)).collect()
+    }
+
+    /// Evaluates the round's bids. With `eligibility`, a bidder's bids are admitted in
+    /// order only while the points they demand stay within the bidder's eligibility, so
+    /// no one can demand more than they did in earlier rounds.
     fn evaluate_bids_in_round<'a>(
         bids: &'a [Bid],
         basket: &'a Basket,
         prices: &HashMap<&'a str, f64>,
-        active_bidders: &HashSet<u64>
-    ) -> (Vec<&'a Bid>, HashMap<&'a str, f64>) {
+        active_bidders: &HashSet<UserId>,
+        points: &HashMap<&'a str, f64>,
+        eligibility: Option<&HashMap<UserId, f64>>,
+    ) -> EvaluatedRound<'a> {
         let mut valid_bids = Vec::new();
         let mut total_demand: HashMap<&'a str, f64> = HashMap::new();
         let mut excess_demand: HashMap<&'a str, f64> = HashMap::new();
+        let mut demanded_points: HashMap<UserId, f64> = HashMap::new();
 
         for bid in bids.iter() {
             if !active_bidders.contains(&bid.user.id) {
                 continue;
             }
             if bid.is_valid() {
+                let demands: Vec<(&'a str, f64)> = basket.assets.iter()
+                    .map(|asset_info| {
+                        let current_price = *prices.get(asset_info.asset.base.as_str()).unwrap_or(&asset_info.price);
+                        let max_affordable_quantity = bid.price / current_price;
+
+                        let requested_quantity = bid.quantity.unwrap_or(1.0);
+                        (asset_info.asset.base.as_str(), requested_quantity.min(max_affordable_quantity))
+                    })
+                    .collect();
+                let bid_points: f64 = demands.iter().map(|(asset, demand)| demand * points.get(asset).unwrap_or(&0.0)).sum();
+                let demanded = demanded_points.entry(bid.user.id).or_insert(0.0);
+                if let Some(limit) = eligibility.and_then(|eligibility| eligibility.get(&bid.user.id)) {
+                    if *demanded + bid_points > limit + POINTS_EPSILON {
+                        tracing::debug!(user_id = bid.user.id.get(), bid_points, eligibility = limit, "bid exceeds eligibility");
+                        continue;
+                    }
+                }
+                *demanded += bid_points;
                 valid_bids.push(bid);
-                for asset_info in &basket.assets {
-                    let current_price = *prices.get(asset_info.asset.base.as_str()).unwrap_or(&asset_info.price);
-                    let max_affordable_quantity = bid.price / current_price;
-
-                    let requested_quantity = bid.quantity.unwrap_or(1.0);
-                    let actual_demand = requested_quantity.min(max_affordable_quantity);
-
-                    let demand = total_demand.entry(asset_info.asset.base.as_str()).or_insert(0.0);
+                for (asset, actual_demand) in demands {
+                    let demand = total_demand.entry(asset).or_insert(0.0);
                     *demand += actual_demand;
                 }
             }
@@ -45,7 +97,17 @@ impl CombiClockAuction {
             }
         }
 
-        (valid_bids, excess_demand)
+        (valid_bids, excess_demand, demanded_points)
+    }
+
+    /// Eligibility after a round: what each active bidder demanded in it, never more than
+    /// they were eligible for before. The first round sets it.
+    fn update_eligibility(eligibility: &mut HashMap<UserId, f64>, active_bidders: &HashSet<UserId>, demanded_points: &HashMap<UserId, f64>) {
+        for user_id in active_bidders {
+            let demanded = demanded_points.get(user_id).copied().unwrap_or(0.0);
+            let points = eligibility.get(user_id).map_or(demanded, |current| current.min(demanded));
+            eligibility.insert(*user_id, points);
+        }
     }
 
     fn update_prices<'a>(
@@ -56,18 +118,21 @@ impl CombiClockAuction {
         let mut new_prices = current_prices.clone();
 
         for (asset, excess) in excess_demand {
+            let Some(&current_price) = current_prices.get(asset) else { continue };
             if *excess > 0.0 {
-                let current_price = *current_prices.get(asset).unwrap();
                 let dynamic_increment = base_price_increment * (1.0 + (excess / current_price) * 10.0);
-                new_prices.insert(asset, current_price * (1.0 + dynamic_increment));
+                match safe_math::checked_mul("clock price", current_price, 1.0 + dynamic_increment) {
+                    Ok(price) => { new_prices.insert(asset, price); }
+                    Err(error) => tracing::warn!(asset, %error, "price left unchanged"),
+                }
             }
         }
 
         new_prices
     }
 
-    fn apply_activity_rule(active_bidders: &mut HashSet<u64>, valid_bids: Vec<&Bid>) {
-        let bidders_in_round: HashSet<u64> = valid_bids.iter().map(|bid| bid.user.id).collect();
+    fn apply_activity_rule(active_bidders: &mut HashSet<UserId>, valid_bids: Vec<&Bid>) {
+        let bidders_in_round: HashSet<UserId> = valid_bids.iter().map(|bid| bid.user.id).collect();
         *active_bidders = active_bidders.intersection(&bidders_in_round).copied().collect();
     }
 
@@ -76,8 +141,8 @@ impl CombiClockAuction {
         valid_bids: Vec<&Bid>,
         basket: &'a Basket,
         final_prices: &HashMap<&'a str, f64>
-    ) -> HashMap<u64, Vec<AssetInfo>> {
-        let mut allocation: HashMap<u64, Vec<AssetInfo>> = HashMap::new();
+    ) -> HashMap<UserId, Vec<AssetInfo>> {
+        let mut allocation: HashMap<UserId, Vec<AssetInfo>> = HashMap::new();
 
         for bid in valid_bids {
             let mut allocated_assets = Vec::new();
@@ -100,50 +165,153 @@ impl CombiClockAuction {
         allocation
     }
 
-    pub fn run_auction<'a>(
+    /// Runs the clock rounds. Returns the bids standing when demand cleared or the rounds
+    /// ran out with their allocation, and the bids and allocation of the last round that
+    /// raised prices, which is what `run_auction` settles. `observer` hears every round
+    /// and the final allocation.
+    fn run_clock<'a>(
         bids: &'a [Bid],
         basket: &'a Basket,
         initial_prices: HashMap<&'a str, f64>,
-        price_increment: f64,
-        max_rounds: usize,
-    ) -> (Vec<Bid>, HashMap<u64, Vec<AssetInfo>>, HashMap<u64, Arc<User>>) {
-        let mut prices = initial_prices.clone();
-        let mut active_bidders: HashSet<u64> = bids.iter().map(|bid| bid.user.id).collect();
+        config: &AuctionConfig,
+        observer: &dyn AuctionObserver,
+    ) -> ClockResult {
+        let (price_increment, max_rounds) = (config.price_increment, config.max_rounds);
+        let _span = tracing::info_span!("cca_clock", basket_id = basket.id.get(), max_rounds).entered();
+        // Starting prices must be positive and finite; a corrupt one is dropped so every
+        // price the clock divides by and raises is sound
+        let mut prices: HashMap<&'a str, f64> = initial_prices.into_iter()
+            .filter(|(asset, price)| match safe_math::positive("starting price", *price) {
+                Ok(_) => true,
+                Err(error) => {
+                    tracing::warn!(asset, %error, "starting price ignored");
+                    false
+                }
+            })
+            .collect();
+        // Assets without a starting price start the clock at their basket price
+        for asset_info in &basket.assets {
+            if safe_math::positive("basket price", asset_info.price).is_ok() {
+                prices.entry(asset_info.asset.base.as_str()).or_insert(asset_info.price);
+            }
+        }
+        let points = CombiClockAuction::eligibility_points(basket, &prices);
+        let mut active_bidders: HashSet<UserId> = bids.iter().map(|bid| bid.user.id).collect();
+        let mut eligibility: HashMap<UserId, f64> = HashMap::new();
         let mut best_allocation = HashMap::new();
         let mut best_bids = Vec::new();
 
         for round in 0..max_rounds {
-            let (valid_bids, excess_demand) = CombiClockAuction::evaluate_bids_in_round(bids, basket, &prices, &active_bidders);
-            println!("Excess demand: {:?}", excess_demand);
+            // Eligibility caps demand under the activity rule from the second round on
+            let limits = (config.activity_rule && round > 0).then_some(&eligibility);
+            let (valid_bids, excess_demand, demanded_points) = CombiClockAuction::evaluate_bids_in_round(bids, basket, &prices, &active_bidders, &points, limits);
+            CombiClockAuction::update_eligibility(&mut eligibility, &active_bidders, &demanded_points);
+            tracing::debug!(round, ?excess_demand, active_bidders = active_bidders.len(), "round evaluated");
+            observer.on_event(&AuctionEvent::Round {
+                basket_id: basket.id,
+                round,
+                prices: prices.iter().map(|(asset, price)| (asset.to_string(), *price)).collect(),
+                excess_demand: excess_demand.iter().map(|(asset, excess)| (asset.to_string(), *excess)).collect(),
+                active_bidders: active_bidders.len(),
+                eligibility: eligibility.clone(),
+            });
             if excess_demand.is_empty() {
                 let owned_valid_bids: Vec<Bid> = valid_bids.into_iter().cloned().collect();
-                let (winning_bids, _) = WDPSolver::maximize_welfare_cca(&owned_valid_bids, basket);
+                let (winning_bids, welfare) = WDPSolver::maximize_welfare_cca(&owned_valid_bids, basket);
+                tracing::info!(round, welfare, winners = winning_bids.len(), "demand cleared");
+                metrics::histogram!(CCA_ROUNDS).record((round + 1) as f64);
                 let allocation = CombiClockAuction::allocate_assets(winning_bids, basket, &prices);
-                let result = Clearing::clear_winning_bids(best_bids.clone(), best_allocation.clone()).unwrap();
-                return (owned_valid_bids, allocation, result);
+                observer.on_event(&AuctionEvent::ProvisionalAllocation { basket_id: basket.id, allocation: allocation.clone() });
+                return (owned_valid_bids, allocation, best_bids, best_allocation);
             }
 
             if round == max_rounds - 1 {
-                println!("Reached maximum number of rounds with remaining excess demand.");
                 let owned_valid_bids: Vec<Bid> = valid_bids.into_iter().cloned().collect();
-                let (winning_bids, _) = WDPSolver::maximize_welfare_cca(&owned_valid_bids, basket);
+                let (winning_bids, welfare) = WDPSolver::maximize_welfare_cca(&owned_valid_bids, basket);
+                tracing::warn!(round, welfare, winners = winning_bids.len(), ?excess_demand, "rounds ran out with excess demand");
+                metrics::histogram!(CCA_ROUNDS).record((round + 1) as f64);
                 let allocation = CombiClockAuction::allocate_assets(winning_bids, basket, &prices);
-                let result = Clearing::clear_winning_bids(best_bids.clone(), best_allocation.clone()).unwrap();
-                return (owned_valid_bids, allocation, result);
+                observer.on_event(&AuctionEvent::ProvisionalAllocation { basket_id: basket.id, allocation: allocation.clone() });
+                return (owned_valid_bids, allocation, best_bids, best_allocation);
             }
 
             prices = CombiClockAuction::update_prices(&prices, &excess_demand, price_increment);
-            println!("Round {}: Updated prices: {:?}", round, prices);
-            CombiClockAuction::apply_activity_rule(&mut active_bidders, valid_bids.clone());
+            tracing::debug!(round, ?prices, "prices raised");
+            let before = active_bidders.clone();
+            if config.activity_rule {
+                CombiClockAuction::apply_activity_rule(&mut active_bidders, valid_bids.clone());
+            }
+            let mut eliminated: Vec<UserId> = before.difference(&active_bidders).copied().collect();
+            eliminated.sort_unstable();
+            for user_id in eliminated {
+                tracing::debug!(round, user_id = user_id.get(), "bidder eliminated by the activity rule");
+                observer.on_event(&AuctionEvent::BidderEliminated { basket_id: basket.id, round, user_id });
+            }
 
             // Track best bids and allocation so far
             best_bids = valid_bids.into_iter().cloned().collect();
             let references_to_best_bids: Vec<&Bid> = best_bids.iter().collect();
             best_allocation = CombiClockAuction::allocate_assets(references_to_best_bids, basket, &prices);
         }
-        println!("Returning best allocation after {} rounds.", max_rounds);
-        let result = Clearing::clear_winning_bids(best_bids.clone(), best_allocation.clone()).unwrap();
-        (best_bids, best_allocation, result)
+        tracing::info!(rounds = max_rounds, "no rounds left; returning the best allocation");
+        metrics::histogram!(CCA_ROUNDS).record(max_rounds as f64);
+        (best_bids.clone(), best_allocation.clone(), best_bids, best_allocation)
+    }
+
+    /// The clock rounds without settlement: the standing bids and their allocation at the
+    /// final prices, for callers that clear the result themselves.
+    pub fn outcome<'a>(
+        bids: &'a [Bid],
+        basket: &'a Basket,
+        initial_prices: HashMap<&'a str, f64>,
+        price_increment: f64,
+        max_rounds: usize,
+    ) -> (Vec<Bid>, HashMap<UserId, Vec<AssetInfo>>) {
+        CombiClockAuction::observed_outcome(bids, basket, initial_prices, price_increment, max_rounds, &AuctionObservers::default())
+    }
+
+    /// As [`CombiClockAuction::outcome`], reporting each round and the final allocation to
+    /// `observer` as they happen.
+    pub fn observed_outcome<'a>(
+        bids: &'a [Bid],
+        basket: &'a Basket,
+        initial_prices: HashMap<&'a str, f64>,
+        price_increment: f64,
+        max_rounds: usize,
+        observer: &dyn AuctionObserver,
+    ) -> (Vec<Bid>, HashMap<UserId, Vec<AssetInfo>>) {
+        let config = AuctionConfig { price_increment, max_rounds, ..AuctionConfig::default() };
+        CombiClockAuction::configured_outcome(bids, basket, initial_prices, &config, observer)
+    }
+
+    /// As [`CombiClockAuction::observed_outcome`] with the clock settings, including
+    /// whether the activity rule applies, taken from `config`.
+    pub fn configured_outcome<'a>(
+        bids: &'a [Bid],
+        basket: &'a Basket,
+        initial_prices: HashMap<&'a str, f64>,
+        config: &AuctionConfig,
+        observer: &dyn AuctionObserver,
+    ) -> (Vec<Bid>, HashMap<UserId, Vec<AssetInfo>>) {
+        let (standing_bids, allocation, _, _) = CombiClockAuction::run_clock(bids, basket, initial_prices, config, observer);
+        (standing_bids, allocation)
+    }
+
+    /// Runs the clock and clears its last price-raising round under `metadata`. The
+    /// caller keeps the metadata for retries: clearing it again returns the users as
+    /// first settled instead of charging them twice.
+    pub fn run_auction<'a>(
+        bids: &'a [Bid],
+        basket: &'a Basket,
+        initial_prices: HashMap<&'a str, f64>,
+        price_increment: f64,
+        max_rounds: usize,
+        metadata: AuctionMetadata,
+        clearing: &mut Clearing,
+    ) -> Result<ClearedAuction, AuctionError> {
+        let (standing_bids, allocation, best_bids, best_allocation) = CombiClockAuction::run_clock(bids, basket, initial_prices, &AuctionConfig { price_increment, max_rounds, ..AuctionConfig::default() }, &AuctionObservers::default());
+        let result = clearing.clear_winning_bids(metadata, best_bids, best_allocation)?.users;
+        Ok((standing_bids, allocation, result))
     }
 }
 
@@ -151,6 +319,7 @@ impl CombiClockAuction {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use model::ids::BasketId;
     use model::model::{Bid, User, Basket, AssetInfo, Asset, BidType};
     use std::sync::Arc;
     use std::collections::HashMap;
@@ -161,7 +330,7 @@ mod tests {
         let user2 = Arc::new(User::new(2, "Bob", 2000000.0));
 
         let basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![
                 AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0), // 2 BTC
                 AssetInfo::new(Asset::new("ETH", "USD"), 5.0, 2000.0),  // 5 ETH
@@ -176,7 +345,7 @@ mod tests {
         let bid3 = Bid::new(user1.clone(), 1, BidType::XOR, 80000.0, Some(0.5));  // Wants 50% of basket
 
         let bids = vec![bid1, bid2, bid3];
-        let (winning_bids, allocation, result) = CombiClockAuction::run_auction(&bids, &basket, initial_prices, price_increment, 10);
+        let (winning_bids, allocation, result) = CombiClockAuction::run_auction(&bids, &basket, initial_prices, price_increment, 10, AuctionMetadata::new(basket.id, "CCA"), &mut Clearing::new()).unwrap();
 
         assert_eq!(winning_bids.len(), 2);
         println!("{:?}", allocation);
@@ -189,7 +358,7 @@ mod tests {
         let user3 = Arc::new(User::new(3, "Charlie", 3000000.0));
 
         let basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![
                 AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0), // 2 BTC
                 AssetInfo::new(Asset::new("ETH", "USD"), 5.0, 2000.0),  // 5 ETH
@@ -204,7 +373,7 @@ mod tests {
         let bid3 = Bid::new(user3.clone(), 1, BidType::XOR, 80000.0, Some(0.5));  // Wants 50% of basket
 
         let bids = vec![bid1, bid2, bid3];
-        let (winning_bids, allocation, result) = CombiClockAuction::run_auction(&bids, &basket, initial_prices, price_increment, 20);
+        let (winning_bids, allocation, result) = CombiClockAuction::run_auction(&bids, &basket, initial_prices, price_increment, 20, AuctionMetadata::new(basket.id, "CCA"), &mut Clearing::new()).unwrap();
 
         assert_eq!(winning_bids.len(), 2);
         println!("{:?}", allocation);
@@ -217,7 +386,7 @@ mod tests {
         let user3 = Arc::new(User::new(3, "Charlie", 3000000.0));
 
         let basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![
                 AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0),
                 AssetInfo::new(Asset::new("ETH", "USD"), 5.0, 2000.0),
@@ -232,14 +401,123 @@ mod tests {
         let bid3 = Bid::new(user3.clone(), 1, BidType::XOR, 80000.0, Some(0.5));
 
         let bids = vec![bid1, bid2, bid3];
-        let (winning_bids, allocation, result) = CombiClockAuction::run_auction(&bids, &basket, initial_prices, price_increment, 10);
+        let (winning_bids, allocation, result) = CombiClockAuction::run_auction(&bids, &basket, initial_prices, price_increment, 10, AuctionMetadata::new(basket.id, "CCA"), &mut Clearing::new()).unwrap();
 
         // Check that the auction completed and cleared
         assert_eq!(winning_bids.len(), 2);  // Only 2 bids should win (depending on availability)
         println!("{:?}", allocation);
 
         // Check user balances after clearing
-        assert_eq!(result.get(&1).unwrap().balance, 940000.0); // Alice pays 60000
-        assert_eq!(result.get(&2).unwrap().balance, 1930000.0); // Bob pays 70000
+        assert_eq!(result.get(&UserId(1)).unwrap().balance, 940000.0); // Alice pays 60000
+        assert_eq!(result.get(&UserId(2)).unwrap().balance, 1930000.0); // Bob pays 70000
+    }
+
+    #[test]
+    fn test_observer_hears_each_round() {
+        use std::sync::Mutex;
+        use crate::observer::{AuctionEvent, AuctionObserver};
+
+        #[derive(Default)]
+        struct Rounds(Mutex<Vec<AuctionEvent>>);
+        impl AuctionObserver for Rounds {
+            fn on_event(&self, event: &AuctionEvent) {
+                self.0.lock().unwrap().push(event.clone());
+            }
+        }
+
+        let basket = Basket { id: BasketId(7), assets: vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 30000.0)] };
+        let bids = vec![
+            Bid::new(Arc::new(User::new(1, "Alice", 1000000.0)), 7, BidType::XOR, 60000.0, Some(1.0)),
+            Bid::new(Arc::new(User::new(2, "Bob", 1000000.0)), 7, BidType::XOR, 40000.0, Some(1.0)),
+            Bid::new(Arc::new(User::new(3, "Carol", 100.0)), 7, BidType::XOR, 50000.0, Some(1.0)),
+        ];
+        let rounds = Rounds::default();
+        CombiClockAuction::observed_outcome(&bids, &basket, HashMap::from([("BTC", 30000.0)]), 0.10, 3, &rounds);
+
+        let events = rounds.0.into_inner().unwrap();
+        assert_eq!(events.len(), 5);
+        // Carol cannot fund her bid and drops out after the first round
+        assert!(matches!(events[1], AuctionEvent::BidderEliminated { basket_id: BasketId(7), round: 0, user_id: UserId(3) }));
+        match (&events[0], &events[2]) {
+            (AuctionEvent::Round { round: 0, prices: first, excess_demand, active_bidders: 3, .. }, AuctionEvent::Round { round: 1, prices: second, .. }) => {
+                assert_eq!(excess_demand.get("BTC"), Some(&1.0));
+                assert!(second["BTC"] > first["BTC"]);
+            }
+            other => panic!("unexpected events {:?}", other),
+        }
+        assert!(matches!(events[4], AuctionEvent::ProvisionalAllocation { basket_id: BasketId(7), .. }));
+        // Alice and Bob keep demanding the whole basket; Carol never had a valid bid
+        match (&events[0], &events[3]) {
+            (AuctionEvent::Round { eligibility: first, .. }, AuctionEvent::Round { eligibility: last, .. }) => {
+                assert_eq!(*first, HashMap::from([(UserId(1), BASKET_POINTS), (UserId(2), BASKET_POINTS), (UserId(3), 0.0)]));
+                assert_eq!(last, first);
+            }
+            other => panic!("unexpected events {:?}", other),
+        }
+
+        // Without the activity rule nobody is dropped between rounds
+        let rounds = Rounds::default();
+        let config = AuctionConfig { price_increment: 0.10, max_rounds: 3, activity_rule: false, ..AuctionConfig::default() };
+        CombiClockAuction::configured_outcome(&bids, &basket, HashMap::from([("BTC", 30000.0)]), &config, &rounds);
+        let events = rounds.0.into_inner().unwrap();
+        assert!(!events.iter().any(|event| matches!(event, AuctionEvent::BidderEliminated { .. })));
+        assert!(matches!(events[1], AuctionEvent::Round { round: 1, active_bidders: 3, .. }));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_demand_cannot_exceed_eligibility() {
+        let basket = Basket { id: BasketId(4), assets: vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 30000.0), AssetInfo::new(Asset::new("ETH", "USD"), 10.0, 3000.0)] };
+        let prices = HashMap::from([("BTC", 30000.0), ("ETH", 3000.0)]);
+        let points = CombiClockAuction::eligibility_points(&basket, &prices);
+        assert_eq!((points["BTC"], points["ETH"]), (50.0, 50.0));
+
+        let alice = Arc::new(User::new(1, "Alice", 1000000.0));
+        let bids = vec![
+            Bid::new(alice.clone(), 4, BidType::XOR, 60000.0, Some(0.5)),
+            Bid::new(alice, 4, BidType::XOR, 60000.0, Some(0.25)),
+            Bid::new(Arc::new(User::new(2, "Bob", 1000000.0)), 4, BidType::XOR, 60000.0, Some(1.0)),
+        ];
+        let active: HashSet<UserId> = HashSet::from([UserId(1), UserId(2)]);
+        let (admitted, _, demanded) = CombiClockAuction::evaluate_bids_in_round(&bids, &basket, &prices, &active, &points, None);
+        assert_eq!(admitted.len(), 3);
+        // Half the basket, then a quarter of it; the whole basket is all its points
+        assert_eq!(demanded[&UserId(1)], 75.0);
+        assert_eq!(demanded[&UserId(2)], BASKET_POINTS);
+
+        // Alice is only eligible for 60 points, so her second bid is turned away
+        let eligibility = HashMap::from([(UserId(1), 60.0), (UserId(2), 100.0)]);
+        let (admitted, _, demanded) = CombiClockAuction::evaluate_bids_in_round(&bids, &basket, &prices, &active, &points, Some(&eligibility));
+        assert_eq!(admitted.iter().map(|bid| bid.quantity).collect::<Vec<_>>(), vec![Some(0.5), Some(1.0)]);
+        assert_eq!(demanded[&UserId(1)], 50.0);
+        let mut next = eligibility.clone();
+        CombiClockAuction::update_eligibility(&mut next, &active, &HashMap::from([(UserId(1), 70.0), (UserId(2), 40.0)]));
+        assert_eq!(next, HashMap::from([(UserId(1), 60.0), (UserId(2), 40.0)]));
+    }
+
+    #[test]
+    fn test_malformed_bids_and_unpriced_assets_do_not_panic() {
+        let basket = Basket { id: BasketId(3), assets: vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 30000.0), AssetInfo::new(Asset::new("ETH", "USD"), 1.0, 2000.0)] };
+        let bids = vec![
+            Bid::new(Arc::new(User::new(1, "Alice", 1000000.0)), 3, BidType::XOR, f64::NAN, None),
+            Bid::new(Arc::new(User::new(2, "Bob", 1000000.0)), 3, BidType::XOR, 40000.0, Some(f64::NAN)),
+            Bid::new(Arc::new(User::new(3, "Carol", 1000000.0)), 3, BidType::XOR, 50000.0, None),
+            Bid::new(Arc::new(User::new(4, "Dave", 1000000.0)), 3, BidType::XOR, 45000.0, None),
+        ];
+        // ETH has no starting price, so its clock starts at the basket price
+        let (_, allocation, _) = CombiClockAuction::run_auction(&bids, &basket, HashMap::from([("BTC", 30000.0)]), 0.1, 5, AuctionMetadata::new(basket.id, "CCA"), &mut Clearing::new()).unwrap();
+        assert!(!allocation.contains_key(&UserId(1)) && !allocation.contains_key(&UserId(2)));
+        assert_eq!(WDPSolver::solve_xor(&bids, &basket).map(|bid| bid.user.id), Some(UserId(3)));
+
+        // Corrupt starting prices are dropped, so the clock starts at the basket prices
+        let priced = |initial_prices| {
+            let (_, allocation) = CombiClockAuction::outcome(&bids, &basket, initial_prices, 0.1, 5);
+            let mut prices: Vec<(UserId, f64)> = allocation.iter().flat_map(|(user_id, assets)| assets.iter().map(|asset| (*user_id, asset.price))).collect();
+            prices.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)));
+            prices
+        };
+        assert_eq!(priced(HashMap::from([("BTC", f64::NAN), ("ETH", -1.0)])), priced(HashMap::new()));
+        // A raise that would overflow leaves the price where it was
+        let raised = CombiClockAuction::update_prices(&HashMap::from([("BTC", f64::MAX)]), &HashMap::from([("BTC", 1.0)]), 0.1);
+        assert_eq!(raised["BTC"], f64::MAX);
+    }
+}
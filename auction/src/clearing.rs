@@ -1,48 +1,807 @@
-use std::collections::HashMap;
-use model::model::{User, Bid, AssetInfo, Basket};
+use std::collections::{HashMap, HashSet};
+use std::cmp::Ordering;
+use std::collections::hash_map::Entry;
+use model::model::{User, Bid, Asset, AssetInfo};
+use model::ids::{AuctionId, BasketId, UserId};
+use model::money::{Code, Money};
 use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use crate::escrow::Escrow;
+use crate::ledger::{Ledger, Posting, LedgerAccount, HOUSE_ACCOUNT, DEFAULT_FUND_ACCOUNT, ISSUER_ACCOUNT};
+use crate::corporate_actions::{Accrual, AccrualRules, CorporateAction, Payout};
+use crate::netting::NetPosition;
+use crate::report::{SettlementReport, SettlementMode, AuctionMetadata, AllocatedAsset};
+use crate::deferred::{DueSettlement, PendingObligation, PendingObligations, SettlementCalendar};
+use crate::hooks::{SettlementHook, SettlementHooks, Transfer, HookRejection};
+use crate::risk::{DefaultWaterfall, WaterfallReport, WaterfallStep};
+use crate::fx::{FxConversion, FxSettlement};
+use crate::error::ClearingError;
+use crate::ids::advance_past;
+use crate::stats::CLEARING_FAILURES;
 
 
-pub struct Clearing;
+#[derive(Debug, Clone)]
+pub struct ClearedSettlement {
+    pub users: HashMap<UserId, Arc<User>>,
+    pub report: SettlementReport,
+    /// Conversions charged to users settling outside the base currency.
+    pub conversions: Vec<FxConversion>,
+}
+
+
+/// Audit record of a reversed allocation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClawbackRecord {
+    pub settlement_id: u64,
+    pub auction_id: AuctionId,
+    pub user_id: UserId,
+    pub refund: f64,
+    pub fee_refund: f64,
+    pub assets: Vec<AllocatedAsset>,
+    /// Ledger entry posting the whole reversal, so it is booked entirely or not at all.
+    pub entry_id: u64,
+}
+
+
+/// Settles auction outcomes. Every balance change is posted to the double-entry `ledger`
+/// first and then mirrored onto the returned users. Settlements are recorded by id so a
+/// retried clearing returns the original result instead of charging users twice.
+#[derive(Debug, Clone, Default)]
+pub struct Clearing {
+    pub ledger: Ledger,
+    pub seller_id: UserId,
+    pub hooks: SettlementHooks,
+    /// Per-user settlement currencies. Without it every user settles in the base currency.
+    pub fx: Option<FxSettlement>,
+    /// Who keeps corporate actions on baskets booked for deferred settlement.
+    pub accruals: AccrualRules,
+    processed: HashMap<u64, ClearedSettlement>,
+    last_rejection: Option<HookRejection>,
+    clawbacks: Vec<ClawbackRecord>,
+}
 
 impl Clearing {
+    pub fn new() -> Self {
+        Clearing {
+            ledger: Ledger::new(),
+            seller_id: HOUSE_ACCOUNT,
+            hooks: SettlementHooks::default(),
+            fx: None,
+            accruals: AccrualRules::default(),
+            processed: HashMap::new(),
+            last_rejection: None,
+            clawbacks: Vec::new(),
+        }
+    }
+
+    pub fn add_hook(&mut self, hook: Arc<dyn SettlementHook>) {
+        self.hooks.add(hook);
+    }
+
+    /// Why the most recent clearing was aborted by a pre-settlement hook, if it was.
+    pub fn last_rejection(&self) -> Option<&HookRejection> {
+        self.last_rejection.as_ref()
+    }
+
+    pub fn is_processed(&self, settlement_id: u64) -> bool {
+        self.processed.contains_key(&settlement_id)
+    }
+
+    pub fn processed_settlement(&self, settlement_id: u64) -> Option<&ClearedSettlement> {
+        self.processed.get(&settlement_id)
+    }
+
+    /// Records a settlement cleared before a restart, so that retrying it is still a
+    /// no-op, and makes later auction and settlement ids start above its own.
+    pub fn restore_settlement(&mut self, settlement: ClearedSettlement) {
+        let metadata = &settlement.report.metadata;
+        advance_past(metadata.auction_id, metadata.settlement_id);
+        self.processed.insert(metadata.settlement_id, settlement);
+    }
+
+    /// The ledger account a user's balance is held in: base cash, or its settlement currency.
+    fn cash_account(&self, user_id: UserId) -> LedgerAccount {
+        match self.fx.as_ref().and_then(|fx| fx.foreign_currency(user_id)) {
+            Some(currency) => LedgerAccount::Currency(user_id, currency.to_string()),
+            None => LedgerAccount::Cash(user_id),
+        }
+    }
+
+    /// Clearing paths other than `clear_with_report` move base cash only.
+    fn ensure_base_currency<'a>(&self, mut user_ids: impl Iterator<Item = &'a UserId>) -> Result<(), ClearingError> {
+        if let Some(user_id) = user_ids.find(|user_id| matches!(self.cash_account(**user_id), LedgerAccount::Currency(..))) {
+            return Err(ClearingError::ForeignCurrency(*user_id));
+        }
+        Ok(())
+    }
+
+    pub fn clawbacks(&self) -> &[ClawbackRecord] {
+        &self.clawbacks
+    }
+
+    /// Adds the user to the clearing set, opening its ledger cash account if needed.
+    fn enter_user(&mut self, users: &mut HashMap<UserId, Arc<User>>, user: &Arc<User>) -> Result<(), ClearingError> {
+        if let Entry::Vacant(entry) = users.entry(user.id) {
+            self.open_account(user)?;
+            entry.insert(Arc::clone(user));
+        }
+        Ok(())
+    }
+
+    /// Opens the user's cash account on the ledger at the balance the user carries, unless
+    /// the ledger has seen the account before. From then on the ledger's balance is the
+    /// one that counts, so a stale copy of the user cannot undo an earlier settlement.
+    pub(crate) fn open_account(&mut self, user: &User) -> Result<(), ClearingError> {
+        let account = self.cash_account(user.id);
+        if !self.ledger.has_account(&account) {
+            self.ledger.sync_account(account, user.balance)?;
+        }
+        Ok(())
+    }
+
+    /// `user` with the balance the ledger holds for it, once it has an account there.
+    pub(crate) fn current(&self, user: &Arc<User>) -> Arc<User> {
+        let account = self.cash_account(user.id);
+        let mut current = Arc::clone(user);
+        if self.ledger.has_account(&account) {
+            Arc::make_mut(&mut current).balance = self.ledger.balance(&account);
+        }
+        current
+    }
+
+    /// `bids` with their users funded as the ledger has them.
+    fn current_bids(&self, bids: Vec<Bid>) -> Vec<Bid> {
+        bids.into_iter().map(|bid| Bid { user: self.current(&bid.user), ..bid }).collect()
+    }
+
+    /// Mirrors the ledger's cash balances back onto the users.
+    fn apply_cash(&self, users: &mut HashMap<UserId, Arc<User>>) {
+        for (user_id, user) in users.iter_mut() {
+            Arc::make_mut(user).balance = self.ledger.balance(&self.cash_account(*user_id));
+        }
+    }
+
+    /// Converts each winner's total due into its settlement currency, if it is a foreign one.
+    fn conversions(&self, winning_bids: &[Bid], fee_rate: f64) -> Result<Vec<FxConversion>, ClearingError> {
+        let fx = match &self.fx {
+            Some(fx) => fx,
+            None => return Ok(Vec::new()),
+        };
+        let base = Code::new(&fx.base_currency);
+        let mut totals: Vec<(UserId, Money)> = Vec::new();
+        for bid in winning_bids.iter().filter(|bid| fx.foreign_currency(bid.user.id).is_some()) {
+            let due = bid.price_in(base.clone()).scale(1.0 + fee_rate);
+            match totals.iter_mut().find(|(user_id, _)| *user_id == bid.user.id) {
+                Some((_, total)) => *total = total.clone().checked_add(due)?,
+                None => totals.push((bid.user.id, due)),
+            }
+        }
+        totals.into_iter().map(|(user_id, total)| fx.convert(user_id, total)).collect()
+    }
+
+    fn check_funds(winning_bids: &[Bid], fee_rate: f64, conversions: &[FxConversion]) -> Result<(), ClearingError> {
+        let mut totals: HashMap<UserId, (f64, &Arc<User>)> = HashMap::new();
+        for bid in winning_bids {
+            totals.entry(bid.user.id).or_insert((0.0, &bid.user)).0 += bid.price * (1.0 + fee_rate);
+        }
+        for conversion in conversions {
+            if let Some(total) = totals.get_mut(&conversion.user_id) {
+                total.0 = conversion.converted;
+            }
+        }
+        for (total, user) in totals.values() {
+            if !user.can_afford(*total) {
+                return Err(ClearingError::InsufficientFunds { user_id: user.id, what: "the payment" });
+            }
+        }
+        Ok(())
+    }
+
+    /// Under cash settlement a winner only needs to fund what it owes net of its reference
+    /// value, plus the fee.
+    fn check_cash_funds(winning_bids: &[Bid], report: &SettlementReport) -> Result<(), ClearingError> {
+        for settlement in &report.settlements {
+            let user = winning_bids.iter().find(|bid| bid.user.id == settlement.user_id).map(|bid| &bid.user);
+            if user.is_some_and(|user| !user.can_afford(settlement.total_debit().max(0.0))) {
+                return Err(ClearingError::InsufficientFunds { user_id: settlement.user_id, what: "the cash settlement" });
+            }
+        }
+        Ok(())
+    }
+
+    /// Posts each winner's cash difference, to the seller when it is owed and from the
+    /// seller otherwise.
+    fn post_cash_differences(&mut self, report: &SettlementReport) -> Result<(), ClearingError> {
+        for settlement in &report.settlements {
+            let due = settlement.cash_due();
+            let memo = format!("cash settlement of basket {} for user {}", report.metadata.basket_id, settlement.user_id);
+            if due > 0.0 {
+                self.ledger.transfer_cash(settlement.user_id, self.seller_id, due, &memo)?;
+            } else if due < 0.0 {
+                self.ledger.transfer_cash(self.seller_id, settlement.user_id, -due, &memo)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Posts the buyer's payment and, once per user, the delivery of its allocated assets.
+    fn post_winning_bids(
+        &mut self,
+        winning_bids: &[Bid],
+        allocation: &HashMap<UserId, Vec<AssetInfo>>,
+        users: &mut HashMap<UserId, Arc<User>>,
+    ) -> Result<(), ClearingError> {
+        let mut delivered: HashSet<UserId> = HashSet::new();
+
+        for bid in winning_bids {
+            self.enter_user(users, &bid.user)?;
+
+            let assets: &[AssetInfo] = match allocation.get(&bid.user.id) {
+                Some(assets) if delivered.insert(bid.user.id) => assets,
+                _ => &[],
+            };
+            let memo = format!("basket {} to user {}", bid.basket_id, bid.user.id);
+            self.ledger.post_trade(bid.user.id, self.seller_id, bid.price, assets, &memo)?;
+        }
+        Ok(())
+    }
+
+    /// Clears the winning bids of an auction. Re-invoking with an already processed
+    /// settlement id is a no-op that returns the original settlement.
     pub fn clear_winning_bids(
+        &mut self,
+        metadata: AuctionMetadata,
         winning_bids: Vec<Bid>,
-        allocation: HashMap<u64, Vec<AssetInfo>>,
-    ) -> Result<HashMap<u64, Arc<User>>, &'static str> {
-        let mut users: HashMap<u64, Arc<User>> = HashMap::new();
+        allocation: HashMap<UserId, Vec<AssetInfo>>,
+    ) -> Result<ClearedSettlement, ClearingError> {
+        self.clear_with_report(metadata, winning_bids, allocation, 0.0)
+    }
 
-        for bid in winning_bids {
-            let user_id = bid.user.id;
-            let price = bid.price;
+    /// Clears the winning bids and charges `fee_rate` on each payment, returning the
+    /// settlement report alongside the updated users.
+    pub fn clear_with_report(
+        &mut self,
+        metadata: AuctionMetadata,
+        winning_bids: Vec<Bid>,
+        allocation: HashMap<UserId, Vec<AssetInfo>>,
+        fee_rate: f64,
+    ) -> Result<ClearedSettlement, ClearingError> {
+        let cleared = self.clear_settlement(metadata, winning_bids, allocation, fee_rate, None);
+        if let Err(error) = &cleared {
+            metrics::counter!(CLEARING_FAILURES, "reason" => error.kind()).increment(1);
+        }
+        cleared
+    }
+
+    /// Clears the winning bids in cash against `marks`, the fixing price of each asset by
+    /// base: no assets are delivered, and each winner pays the difference between its
+    /// payment and its allocation's value at the marks, or is paid it by the seller. Fees
+    /// are charged on the payments as under physical delivery. Winners must settle in the
+    /// base currency.
+    pub fn clear_cash_settled(
+        &mut self,
+        metadata: AuctionMetadata,
+        winning_bids: Vec<Bid>,
+        allocation: HashMap<UserId, Vec<AssetInfo>>,
+        marks: &HashMap<String, f64>,
+        fee_rate: f64,
+    ) -> Result<ClearedSettlement, ClearingError> {
+        let cleared = self.clear_settlement(metadata, winning_bids, allocation, fee_rate, Some(marks));
+        if let Err(error) = &cleared {
+            metrics::counter!(CLEARING_FAILURES, "reason" => error.kind()).increment(1);
+        }
+        cleared
+    }
+
+    fn clear_settlement(
+        &mut self,
+        metadata: AuctionMetadata,
+        winning_bids: Vec<Bid>,
+        allocation: HashMap<UserId, Vec<AssetInfo>>,
+        fee_rate: f64,
+        marks: Option<&HashMap<String, f64>>,
+    ) -> Result<ClearedSettlement, ClearingError> {
+        let _span = tracing::info_span!("clearing", auction_id = metadata.auction_id.get(), settlement_id = metadata.settlement_id).entered();
+        if let Some(previous) = self.processed.get(&metadata.settlement_id) {
+            if previous.report.metadata.auction_id != metadata.auction_id {
+                return Err(ClearingError::SettlementReused(metadata.settlement_id));
+            }
+            tracing::debug!("settlement already processed");
+            return Ok(previous.clone());
+        }
+        let winning_bids = self.current_bids(winning_bids);
+
+        let report = SettlementReport::new(metadata, &winning_bids, &allocation, fee_rate);
+        let (report, conversions) = match marks {
+            Some(marks) => {
+                if let Some(fx) = &self.fx {
+                    if let Some(bid) = winning_bids.iter().find(|bid| fx.foreign_currency(bid.user.id).is_some()) {
+                        return Err(ClearingError::ForeignCurrency(bid.user.id));
+                    }
+                }
+                (report.cash_settled(marks)?, Vec::new())
+            }
+            None => (report, self.conversions(&winning_bids, fee_rate)?),
+        };
+        // Ensure every winner can afford all of its payments before posting anything
+        let funded = match marks {
+            Some(_) => Clearing::check_cash_funds(&winning_bids, &report),
+            None => Clearing::check_funds(&winning_bids, fee_rate, &conversions),
+        };
+        if let Err(error) = funded {
+            tracing::warn!(%error, "winners cannot fund their payments");
+            return Err(error);
+        }
+
+        let transfers: Vec<Transfer> = match marks {
+            Some(_) => report.settlements.iter()
+                .map(|settlement| Transfer {
+                    settlement_id: report.metadata.settlement_id,
+                    auction_id: report.metadata.auction_id,
+                    user_id: settlement.user_id,
+                    basket_id: report.metadata.basket_id,
+                    amount: settlement.total_debit(),
+                    assets: Vec::new(),
+                })
+                .collect(),
+            None => winning_bids.iter()
+                .map(|bid| Transfer {
+                    settlement_id: report.metadata.settlement_id,
+                    auction_id: report.metadata.auction_id,
+                    user_id: bid.user.id,
+                    basket_id: bid.basket_id,
+                    amount: bid.price * (1.0 + fee_rate),
+                    assets: allocation.get(&bid.user.id).cloned().unwrap_or_default(),
+                })
+                .collect(),
+        };
+        self.last_rejection = None;
+        if let Err(rejection) = self.hooks.approve(&transfers) {
+            tracing::warn!(hook = %rejection.hook, user_id = rejection.user_id.get(), reason = %rejection.reason, "settlement rejected");
+            self.last_rejection = Some(rejection.clone());
+            return Err(ClearingError::Rejected(rejection));
+        }
+
+        let mut users: HashMap<UserId, Arc<User>> = HashMap::new();
+
+        for bid in &winning_bids {
+            self.enter_user(&mut users, &bid.user)?;
+        }
+        for conversion in &conversions {
+            let memo = format!("{}/{} conversion for user {}", conversion.from, conversion.to, conversion.user_id);
+            self.ledger.post_conversion(self.seller_id, conversion, &memo)?;
+        }
+        match marks {
+            Some(_) => self.post_cash_differences(&report)?,
+            None => self.post_winning_bids(&winning_bids, &allocation, &mut users)?,
+        }
+        for settlement in report.settlements.iter().filter(|s| s.fee > 0.0) {
+            self.ledger.transfer_cash(settlement.user_id, self.seller_id, settlement.fee, "fee")?;
+        }
+        self.apply_cash(&mut users);
+
+        for bid in &winning_bids {
+            tracing::debug!(user_id = bid.user.id.get(), payment = bid.price, "winner charged");
+        }
+        let welfare: f64 = winning_bids.iter().map(|bid| bid.price).sum();
+        tracing::info!(winners = winning_bids.len(), welfare, fee_rate, "settlement cleared");
+        let settlement = ClearedSettlement { users, report, conversions };
+        self.processed.insert(settlement.report.metadata.settlement_id, settlement.clone());
+        self.hooks.notify(&settlement.report);
+        Ok(settlement)
+    }
 
-            // Get the user or initialize with the current bid user (if new)
-            let user = users
-                .entry(user_id)
-                .or_insert_with(|| Arc::clone(&bid.user));
+    /// Reverses one user's part of a processed settlement, e.g. when custody or compliance
+    /// rejects the transfer after clearing: the payment and fee are refunded, any FX
+    /// conversion is unwound at its original rate, and the assets go back to the seller.
+    pub fn clawback(&mut self, settlement_id: u64, user_id: UserId) -> Result<ClawbackRecord, ClearingError> {
+        let mut settlement = self.processed.remove(&settlement_id).ok_or(ClearingError::UnknownSettlement(settlement_id))?;
+        let result = self.reverse_user(&mut settlement, user_id);
+        self.processed.insert(settlement_id, settlement);
 
-            // Ensure the user can afford the bid
-            if !user.can_afford(price) {
-                return Err("User cannot afford the payment");
+        let record = result?;
+        self.clawbacks.push(record.clone());
+        Ok(record)
+    }
+
+    fn reverse_user(&mut self, settlement: &mut ClearedSettlement, user_id: UserId) -> Result<ClawbackRecord, ClearingError> {
+        let index = settlement.report.settlements.iter()
+            .position(|s| s.user_id == user_id)
+            .ok_or(ClearingError::NotAllocated { settlement_id: settlement.report.metadata.settlement_id, user_id })?;
+        let user_settlement = settlement.report.settlements[index].clone();
+        let metadata = settlement.report.metadata.clone();
+        let memo = format!("clawback of settlement {} from user {}", metadata.settlement_id, user_id);
+
+        let mut postings = match settlement.report.mode {
+            // A cash settlement delivered nothing; only the difference goes back
+            SettlementMode::Cash => {
+                let due = user_settlement.cash_due();
+                match due.partial_cmp(&0.0) {
+                    Some(Ordering::Greater) => vec![
+                        Posting::debit(LedgerAccount::Cash(self.seller_id), due),
+                        Posting::credit(LedgerAccount::Cash(user_id), due),
+                    ],
+                    Some(Ordering::Less) => vec![
+                        Posting::debit(LedgerAccount::Cash(user_id), -due),
+                        Posting::credit(LedgerAccount::Cash(self.seller_id), -due),
+                    ],
+                    _ => Vec::new(),
+                }
             }
+            SettlementMode::Physical => {
+                let assets: Vec<AssetInfo> = user_settlement.assets.iter()
+                    .map(|a| AssetInfo::new(Asset::new(&a.base, &a.quote), a.quantity, a.value))
+                    .collect();
+                Ledger::trade_postings(self.seller_id, user_id, user_settlement.payment, &assets)
+            }
+        };
+        if user_settlement.fee > 0.0 {
+            postings.push(Posting::debit(LedgerAccount::Cash(self.seller_id), user_settlement.fee));
+            postings.push(Posting::credit(LedgerAccount::Cash(user_id), user_settlement.fee));
+        }
+        let conversion = settlement.conversions.iter().position(|c| c.user_id == user_id);
+        if let Some(position) = conversion {
+            postings.extend(Ledger::conversion_reversal(self.seller_id, &settlement.conversions[position]));
+        }
+        let entry_id = self.ledger.post(&memo, postings)?;
 
-            // Deduct the price from the user's balance
-            Arc::get_mut(user).unwrap().withdraw(price);
+        if let Some(position) = conversion {
+            settlement.conversions.remove(position);
+        }
+        settlement.report.settlements.remove(index);
+        if let Some(user) = settlement.users.get_mut(&user_id) {
+            Arc::make_mut(user).balance = self.ledger.balance(&self.cash_account(user_id));
+        }
 
-            // Handle asset allocation for the user
-            if let Some(assets) = allocation.get(&user_id) {
-                println!(
-                    "User {} receives the following assets: {:?}",
-                    user_id, assets
-                );
-                // Here you would implement the actual asset transfer logic.
+        Ok(ClawbackRecord {
+            settlement_id: metadata.settlement_id,
+            auction_id: metadata.auction_id,
+            user_id,
+            refund: user_settlement.cash_due(),
+            fee_refund: user_settlement.fee,
+            assets: match settlement.report.mode {
+                SettlementMode::Cash => Vec::new(),
+                SettlementMode::Physical => user_settlement.assets,
+            },
+            entry_id,
+        })
+    }
+
+    /// Clears the winners that can pay and covers every winner that cannot through the
+    /// default waterfall, instead of failing the whole settlement. Defaulters receive no
+    /// allocation; their collateral is taken over by the house. The default fund is drawn
+    /// only as far as [`DEFAULT_FUND_ACCOUNT`] holds cash, and the other winners are
+    /// haircut at most what they gained: their allocation's value less what they paid,
+    /// within the cash they have left.
+    pub fn clear_with_default_management(
+        &mut self,
+        metadata: AuctionMetadata,
+        winning_bids: Vec<Bid>,
+        allocation: HashMap<UserId, Vec<AssetInfo>>,
+        collateral: &HashMap<UserId, Vec<AssetInfo>>,
+        waterfall: &mut DefaultWaterfall,
+    ) -> Result<(ClearedSettlement, Vec<WaterfallReport>), ClearingError> {
+        if let Some(previous) = self.processed.get(&metadata.settlement_id) {
+            if previous.report.metadata.auction_id != metadata.auction_id {
+                return Err(ClearingError::SettlementReused(metadata.settlement_id));
             }
+            return Ok((previous.clone(), Vec::new()));
         }
+        let winning_bids = self.current_bids(winning_bids);
 
+        self.ensure_base_currency(winning_bids.iter().map(|bid| &bid.user.id))?;
+
+        let mut amounts_due: HashMap<UserId, f64> = HashMap::new();
+        for bid in &winning_bids {
+            *amounts_due.entry(bid.user.id).or_insert(0.0) += bid.price;
+        }
+        let (payers, defaulters): (Vec<Bid>, Vec<Bid>) = winning_bids.into_iter()
+            .partition(|bid| bid.user.can_afford(amounts_due[&bid.user.id]));
+
+        let payer_allocation: HashMap<UserId, Vec<AssetInfo>> = allocation.into_iter()
+            .filter(|(user_id, _)| payers.iter().any(|bid| bid.user.id == *user_id))
+            .collect();
+        let mut settlement = self.clear_winning_bids(metadata, payers, payer_allocation)?;
+
+        let mut reports = Vec::new();
+        let mut defaulted: HashSet<UserId> = HashSet::new();
+        let mut haircut: HashMap<UserId, f64> = HashMap::new();
+        for bid in &defaulters {
+            if !defaulted.insert(bid.user.id) {
+                continue;
+            }
+            self.enter_user(&mut settlement.users, &bid.user)?;
+
+            let surplus: HashMap<UserId, f64> = settlement.report.settlements.iter()
+                .map(|winner| {
+                    let value: f64 = winner.assets.iter().map(|asset| asset.value).sum();
+                    let gained = value - winner.payment - winner.fee - haircut.get(&winner.user_id).copied().unwrap_or(0.0);
+                    (winner.user_id, gained.min(self.ledger.cash_balance(winner.user_id)).max(0.0))
+                })
+                .collect();
+            let report = waterfall.run(
+                bid.user.id,
+                amounts_due[&bid.user.id],
+                self.ledger.cash_balance(bid.user.id),
+                collateral.get(&bid.user.id).map(|c| c.as_slice()).unwrap_or(&[]),
+                self.ledger.cash_balance(DEFAULT_FUND_ACCOUNT),
+                &surplus,
+            );
+            self.post_waterfall(&report)?;
+            for entry in report.entries.iter().filter(|entry| entry.step == WaterfallStep::WinnerHaircut) {
+                if let Some(user_id) = entry.user_id {
+                    *haircut.entry(user_id).or_insert(0.0) += entry.amount;
+                }
+            }
+            reports.push(report);
+        }
+        self.apply_cash(&mut settlement.users);
+
+        Ok((settlement, reports))
+    }
+
+    /// Pays `amount` of `from`'s cash into the default fund, which the waterfall draws on
+    /// only as far as it has been funded.
+    pub fn fund_default(&mut self, from: UserId, amount: f64) -> Result<u64, ClearingError> {
+        if !(amount.is_finite() && amount > 0.0) {
+            return Err(ClearingError::NonPositive("default fund contribution"));
+        }
+        self.ledger.transfer_cash(from, DEFAULT_FUND_ACCOUNT, amount, "default fund contribution")
+    }
+
+    fn post_waterfall(&mut self, report: &WaterfallReport) -> Result<(), ClearingError> {
+        let memo = format!("default of user {}", report.defaulter_id);
+
+        for entry in &report.entries {
+            match (entry.step, entry.user_id, &entry.asset) {
+                (WaterfallStep::DefaulterCollateral, Some(user_id), Some(asset)) => {
+                    self.ledger.post(&memo, vec![
+                        Posting::debit(LedgerAccount::Inventory(user_id, asset.clone()), entry.quantity),
+                        Posting::credit(LedgerAccount::Inventory(self.seller_id, asset.clone()), entry.quantity),
+                    ])?;
+                }
+                (WaterfallStep::DefaultFund, _, _) => {
+                    self.ledger.transfer_cash(DEFAULT_FUND_ACCOUNT, self.seller_id, entry.amount, &memo)?;
+                }
+                (_, Some(user_id), _) => {
+                    self.ledger.transfer_cash(user_id, self.seller_id, entry.amount, &memo)?;
+                }
+                _ => return Err(ClearingError::MalformedWaterfall),
+            }
+        }
+        Ok(())
+    }
+
+    /// Settles winning bids from escrowed funds. Each winner's lock is converted into
+    /// payment, and whatever is still locked on the cleared baskets (losing bids) is released.
+    /// Re-invoking with an already processed settlement id leaves the escrow alone and
+    /// returns the users as first settled.
+    pub fn clear_from_escrow(
+        &mut self,
+        metadata: AuctionMetadata,
+        winning_bids: Vec<Bid>,
+        allocation: HashMap<UserId, Vec<AssetInfo>>,
+        escrow: &mut Escrow,
+    ) -> Result<HashMap<UserId, Arc<User>>, ClearingError> {
+        if let Some(previous) = self.processed.get(&metadata.settlement_id) {
+            if previous.report.metadata.auction_id != metadata.auction_id {
+                return Err(ClearingError::SettlementReused(metadata.settlement_id));
+            }
+            tracing::debug!(settlement_id = metadata.settlement_id, "escrow settlement already processed");
+            return Ok(previous.users.clone());
+        }
+        let winning_bids = self.current_bids(winning_bids);
+        let mut users: HashMap<UserId, Arc<User>> = HashMap::new();
+        let mut baskets: Vec<BasketId> = Vec::new();
+        self.ensure_base_currency(winning_bids.iter().map(|bid| &bid.user.id))?;
+
+        for bid in &winning_bids {
+            if escrow.locked(bid.user.id, bid.basket_id) <= 0.0 {
+                return Err(ClearingError::NotEscrowed { user_id: bid.user.id, basket_id: bid.basket_id });
+            }
+            // Only the part of the price not covered by escrow (margin mode) can fail
+            if escrow.locked(bid.user.id, bid.basket_id) < bid.price && !bid.user.can_afford(bid.price) {
+                return Err(ClearingError::InsufficientFunds { user_id: bid.user.id, what: "the unescrowed part of the payment" });
+            }
+        }
+
+        for bid in &winning_bids {
+            escrow.settle(bid)?;
+            if !baskets.contains(&bid.basket_id) {
+                baskets.push(bid.basket_id);
+            }
+        }
+        self.post_winning_bids(&winning_bids, &allocation, &mut users)?;
+        self.apply_cash(&mut users);
+
+        for basket_id in baskets {
+            escrow.release_basket(basket_id);
+        }
+
+        let report = SettlementReport::new(metadata, &winning_bids, &allocation, 0.0);
+        self.processed.insert(report.metadata.settlement_id, ClearedSettlement { users: users.clone(), report, conversions: Vec::new() });
         Ok(users)
     }
-}
 
+    /// Books the winning bids as obligations settling `lag_days` business days after
+    /// `trade_time` (T+N) instead of debiting balances immediately. Returns the obligation ids.
+    pub fn book_deferred(
+        winning_bids: &[Bid],
+        allocation: &HashMap<UserId, Vec<AssetInfo>>,
+        auction_id: AuctionId,
+        trade_time: u64,
+        lag_days: u32,
+        calendar: &SettlementCalendar,
+        pending: &mut PendingObligations,
+    ) -> Vec<u64> {
+        let settles_at = calendar.settlement_time(trade_time, lag_days);
+
+        winning_bids.iter()
+            .map(|bid| pending.book(
+                bid.user.id,
+                auction_id,
+                bid.basket_id,
+                bid.price,
+                settles_at,
+                allocation.get(&bid.user.id).cloned().unwrap_or_default(),
+            ))
+            .collect()
+    }
+
+    /// Accrues `action` on the obligations in `pending` it reaches, under `accruals`. Apply
+    /// each action as it goes ex, so it only reaches baskets auctioned before. The seller
+    /// holds the assets until delivery, so the issuer pays it everything accrued in one
+    /// journal entry; settlement passes on the winner's part. Returns what accrued by
+    /// obligation id.
+    pub fn apply_corporate_action(&mut self, pending: &mut PendingObligations, action: &CorporateAction) -> Result<Vec<(u64, Accrual)>, ClearingError> {
+        let applied = pending.apply_corporate_action(action, &self.accruals)?;
+        let mut postings = Vec::new();
+        for (_, accrual) in &applied {
+            let (issuer, seller, amount) = match &accrual.payout {
+                Payout::Cash(amount) => (LedgerAccount::Cash(ISSUER_ACCOUNT), LedgerAccount::Cash(self.seller_id), *amount),
+                Payout::Units { asset, quantity } => (
+                    LedgerAccount::Inventory(ISSUER_ACCOUNT, asset.clone()),
+                    LedgerAccount::Inventory(self.seller_id, asset.clone()),
+                    *quantity,
+                ),
+            };
+            postings.push(Posting::debit(issuer, amount));
+            postings.push(Posting::credit(seller, amount));
+        }
+        if !postings.is_empty() {
+            self.ledger.post(&format!("corporate action {} on {}", action.id, action.base), postings)?;
+        }
+        Ok(applied)
+    }
+
+    /// Settles every obligation in `pending` that has matured by `now`. Each winner pays
+    /// the seller what is outstanding and receives its assets, and the seller passes on
+    /// the cash and units that accrued to the winner, in one journal entry; the winner
+    /// only needs to fund the net. Obligations whose user cannot pay stay pending and are
+    /// reported as failed.
+    pub fn settle_due(
+        &mut self,
+        pending: &mut PendingObligations,
+        now: u64,
+        users: &mut HashMap<UserId, Arc<User>>,
+    ) -> DueSettlement {
+        let mut result = DueSettlement::default();
+        for obligation in pending.take_due(now) {
+            let mut delivered = obligation.clone();
+            delivered.deliver_accrued_units();
+            match self.post_obligation(&delivered, users) {
+                Ok(_) => {
+                    delivered.settled_amount = delivered.amount;
+                    result.settled.push(delivered);
+                }
+                Err(error) => {
+                    tracing::warn!(obligation_id = obligation.id, %error, "deferred settlement failed");
+                    result.failed.push(obligation.id);
+                    pending.restore(obligation);
+                }
+            }
+        }
+        result
+    }
+
+    /// Pays part of a pending obligation before its settlement date, from its winner to the
+    /// seller. The obligation is removed once nothing is outstanding.
+    pub fn settle_early(
+        &mut self,
+        pending: &mut PendingObligations,
+        id: u64,
+        amount: f64,
+        users: &mut HashMap<UserId, Arc<User>>,
+    ) -> Result<f64, ClearingError> {
+        let obligation = pending.get(id).ok_or(ClearingError::UnknownObligation(id))?;
+        if !amount.is_finite() || amount <= 0.0 {
+            return Err(ClearingError::NonPositive("early settlement amount"));
+        }
+        let (user_id, amount) = (obligation.user_id, amount.min(obligation.outstanding()));
+        self.enter_deferred_user(users, user_id, amount, "the early settlement")?;
+        self.ledger.transfer_cash(user_id, self.seller_id, amount, &format!("early settlement of obligation {}", id))?;
+        self.mirror_cash(users, user_id);
+        pending.record_payment(id, amount);
+        Ok(amount)
+    }
+
+    /// Posts the settlement of one matured obligation whose accrued units have been added
+    /// to its assets.
+    fn post_obligation(&mut self, obligation: &PendingObligation, users: &mut HashMap<UserId, Arc<User>>) -> Result<u64, ClearingError> {
+        let (buyer, seller) = (obligation.user_id, self.seller_id);
+        let (outstanding, accrued) = (obligation.outstanding(), obligation.winner_cash());
+        self.enter_deferred_user(users, buyer, (outstanding - accrued).max(0.0), "the deferred settlement")?;
+
+        let mut postings = vec![
+            Posting::debit(LedgerAccount::Cash(buyer), outstanding),
+            Posting::credit(LedgerAccount::Cash(seller), outstanding),
+        ];
+        if accrued > 0.0 {
+            postings.push(Posting::debit(LedgerAccount::Cash(seller), accrued));
+            postings.push(Posting::credit(LedgerAccount::Cash(buyer), accrued));
+        }
+        for asset_info in &obligation.assets {
+            postings.push(Posting::debit(LedgerAccount::Inventory(seller, asset_info.asset.clone()), asset_info.quantity));
+            postings.push(Posting::credit(LedgerAccount::Inventory(buyer, asset_info.asset.clone()), asset_info.quantity));
+        }
+        let memo = format!("deferred settlement {} of basket {} for user {}", obligation.id, obligation.basket_id, buyer);
+        let entry_id = self.ledger.post(&memo, postings)?;
+        self.mirror_cash(users, buyer);
+        Ok(entry_id)
+    }
+
+    /// Brings a deferred settlement's payer into the ledger and checks it can pay `amount`.
+    fn enter_deferred_user(&mut self, users: &HashMap<UserId, Arc<User>>, user_id: UserId, amount: f64, what: &'static str) -> Result<(), ClearingError> {
+        let user = self.current(users.get(&user_id).ok_or(ClearingError::UnknownUser(user_id))?);
+        self.ensure_base_currency(std::iter::once(&user_id))?;
+        if !user.can_afford(amount) {
+            return Err(ClearingError::InsufficientFunds { user_id, what });
+        }
+        self.open_account(&user)
+    }
+
+    /// Mirrors one user's ledger cash balance back onto it.
+    fn mirror_cash(&self, users: &mut HashMap<UserId, Arc<User>>, user_id: UserId) {
+        if let Some(user) = users.get_mut(&user_id) {
+            Arc::make_mut(user).balance = self.ledger.cash_balance(user_id);
+        }
+    }
+
+    /// Settles one net debit or credit per account instead of every obligation gross.
+    pub fn clear_net_positions(
+        &mut self,
+        positions: &[NetPosition],
+        users: HashMap<UserId, Arc<User>>,
+    ) -> Result<HashMap<UserId, Arc<User>>, ClearingError> {
+        self.ensure_base_currency(users.keys())?;
+        let users: HashMap<UserId, Arc<User>> = users.iter().map(|(user_id, user)| (*user_id, self.current(user))).collect();
+
+        // Check every debit before moving any money so a failure leaves balances untouched
+        for position in positions {
+            let user = users.get(&position.user_id).ok_or(ClearingError::UnknownUser(position.user_id))?;
+            if position.is_debit() && !user.can_afford(-position.net) {
+                return Err(ClearingError::InsufficientFunds { user_id: position.user_id, what: "the net payment" });
+            }
+        }
+
+        let mut cleared: HashMap<UserId, Arc<User>> = HashMap::new();
+        for user in users.values() {
+            self.enter_user(&mut cleared, user)?;
+        }
+
+        for position in positions {
+            let memo = format!("net settlement cycle {}", position.cycle);
+            if position.is_debit() {
+                self.ledger.transfer_cash(position.user_id, self.seller_id, -position.net, &memo)?;
+            } else {
+                self.ledger.transfer_cash(self.seller_id, position.user_id, position.net, &memo)?;
+            }
+        }
+        self.apply_cash(&mut cleared);
+
+        Ok(cleared)
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -57,7 +816,7 @@ mod tests {
         let user2 = Arc::new(User::new(2, "Bob", 200000.0));
 
         let basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![
                 AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0),
                 AssetInfo::new(Asset::new("ETH", "USD"), 5.0, 2000.0),
@@ -73,11 +832,18 @@ mod tests {
         ]);
 
         let bids = vec![bid1, bid2];
-        let cleared_users = Clearing::clear_winning_bids(bids, allocation).unwrap();
+        let mut clearing = Clearing::new();
+        let metadata = AuctionMetadata::new(BasketId(1), "XOR");
+        let cleared_users = clearing.clear_winning_bids(metadata, bids, allocation).unwrap().users;
 
         // Check user balances after clearing
-        assert_eq!(cleared_users.get(&1).unwrap().balance, 40000.0);
-        assert_eq!(cleared_users.get(&2).unwrap().balance, 130000.0);
+        assert_eq!(cleared_users.get(&UserId(1)).unwrap().balance, 40000.0);
+        assert_eq!(cleared_users.get(&UserId(2)).unwrap().balance, 130000.0);
+
+        // Every balance change went through the ledger
+        assert!(clearing.ledger.is_balanced());
+        assert_eq!(clearing.ledger.cash_balance(HOUSE_ACCOUNT), 130000.0);
+        assert_eq!(clearing.ledger.balance(&LedgerAccount::Inventory(UserId(1), Asset::new("BTC", "USD"))), 1.0);
     }
 
     #[test]
@@ -86,7 +852,7 @@ mod tests {
         let user2 = Arc::new(User::new(2, "Bob", 200000.0));
 
         let basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![
                 AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0),
                 AssetInfo::new(Asset::new("ETH", "USD"), 5.0, 2000.0),
@@ -102,9 +868,373 @@ mod tests {
         ]);
 
         let bids = vec![bid1, bid2];
-        let result = Clearing::clear_winning_bids(bids, allocation);
+        let mut clearing = Clearing::new();
+        let result = clearing.clear_winning_bids(AuctionMetadata::new(BasketId(1), "XOR"), bids, allocation);
 
         // Check that the clearing fails due to insufficient funds
         assert!(result.is_err());
+        assert!(clearing.ledger.entries().is_empty());  // Nothing was posted
+    }
+
+    #[test]
+    fn test_clear_from_escrow() {
+        let user1 = Arc::new(User::new(1, "Alice", 100000.0));
+        let user2 = Arc::new(User::new(2, "Bob", 200000.0));
+
+        let bid1 = Bid::new(user1.clone(), 1, BidType::XOR, 60000.0, Some(1.0));
+        let bid2 = Bid::new(user2.clone(), 1, BidType::XOR, 70000.0, Some(1.0));
+
+        let mut escrow = Escrow::new();
+        escrow.lock(&bid1).unwrap();
+        escrow.lock(&bid2).unwrap();
+
+        let allocation = HashMap::from([
+            (user2.id, vec![AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 60000.0)]),
+        ]);
+
+        let mut clearing = Clearing::new();
+        let metadata = AuctionMetadata::new(BasketId(1), "XOR");
+        let cleared_users = clearing.clear_from_escrow(metadata.clone(), vec![bid2.clone()], allocation.clone(), &mut escrow).unwrap();
+
+        assert_eq!(cleared_users.get(&UserId(2)).unwrap().balance, 130000.0);
+        assert!(!cleared_users.contains_key(&UserId(1)));
+        assert_eq!(escrow.total_locked(UserId(1)), 0.0);  // Losing bid released
+        assert_eq!(escrow.total_locked(UserId(2)), 0.0);
+
+        // A retry with the same settlement id charges nobody again, even with the bid re-locked
+        escrow.lock(&bid2).unwrap();
+        let retried = clearing.clear_from_escrow(metadata, vec![bid2], allocation, &mut escrow).unwrap();
+        assert_eq!(retried.get(&UserId(2)).unwrap().balance, 130000.0);
+        assert_eq!(clearing.ledger.cash_balance(HOUSE_ACCOUNT), 70000.0);
+        assert_eq!(escrow.total_locked(UserId(2)), 70000.0);
+    }
+
+    #[test]
+    fn test_clear_from_escrow_requires_lock() {
+        let user1 = Arc::new(User::new(1, "Alice", 100000.0));
+        let bid1 = Bid::new(user1.clone(), 1, BidType::XOR, 60000.0, Some(1.0));
+
+        let mut escrow = Escrow::new();
+        let result = Clearing::new().clear_from_escrow(AuctionMetadata::new(BasketId(1), "XOR"), vec![bid1], HashMap::new(), &mut escrow);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_clear_net_positions() {
+        use crate::netting::NettingEngine;
+
+        let user1 = Arc::new(User::new(1, "Alice", 30000.0));
+        let user2 = Arc::new(User::new(2, "Bob", 100000.0));
+
+        let mut engine = NettingEngine::new(0);
+        engine.add_winning_bids(AuctionId(1), &[Bid::new(user1.clone(), 1, BidType::XOR, 60000.0, None)]).unwrap();
+        engine.add_sale(UserId(1), AuctionId(2), BasketId(2), 40000.0).unwrap();  // Alice can only pay net, not gross
+        engine.add_winning_bids(AuctionId(2), &[Bid::new(user2.clone(), 2, BidType::XOR, 40000.0, None)]).unwrap();
+
+        let users = HashMap::from([(UserId(1), user1), (UserId(2), user2)]);
+        let cleared_users = Clearing::new().clear_net_positions(&engine.close_cycle(), users).unwrap();
+
+        assert_eq!(cleared_users.get(&UserId(1)).unwrap().balance, 10000.0);
+        assert_eq!(cleared_users.get(&UserId(2)).unwrap().balance, 60000.0);
+    }
+
+    #[test]
+    fn test_clear_with_report() {
+        let user1 = Arc::new(User::new(1, "Alice", 100000.0));
+        let bid1 = Bid::new(user1.clone(), 1, BidType::XOR, 60000.0, Some(1.0));
+
+        let allocation = HashMap::from([
+            (user1.id, vec![AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 60000.0)]),
+        ]);
+        let metadata = AuctionMetadata::new(BasketId(1), "XOR");
+
+        let mut clearing = Clearing::new();
+        let cleared = clearing.clear_with_report(metadata, vec![bid1], allocation, 0.01).unwrap();
+        let (cleared_users, report) = (cleared.users, cleared.report);
+
+        assert_eq!(cleared_users.get(&UserId(1)).unwrap().balance, 39400.0);
+        assert_eq!(report.settlements[0].fee, 600.0);
+        assert_eq!(clearing.ledger.cash_balance(HOUSE_ACCOUNT), 60600.0);
+        assert_eq!(report.settlements[0].assets[0].quantity, 2.0);
+    }
+
+    #[test]
+    fn test_book_deferred() {
+        let user1 = Arc::new(User::new(1, "Alice", 100000.0));
+        let bid1 = Bid::new(user1.clone(), 1, BidType::XOR, 60000.0, Some(1.0));
+        let allocation = HashMap::from([
+            (user1.id, vec![AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 60000.0)]),
+        ]);
+
+        let mut pending = PendingObligations::new();
+        let ids = Clearing::book_deferred(&[bid1], &allocation, AuctionId(1), 0, 2, &SettlementCalendar::continuous(), &mut pending);
+
+        let obligation = pending.get(ids[0]).unwrap();
+        assert_eq!(obligation.settles_at, 2 * 86_400);
+        assert_eq!(obligation.assets.len(), 1);
+
+        let mut users = HashMap::from([(UserId(1), user1)]);
+        let mut clearing = Clearing::new();
+        assert!(clearing.settle_due(&mut pending, 86_400, &mut users).settled.is_empty());
+        assert_eq!(clearing.settle_due(&mut pending, 2 * 86_400, &mut users).settled.len(), 1);
+        assert_eq!(users.get(&UserId(1)).unwrap().balance, 40000.0);
+        // The deferred payment and delivery are journaled like an immediate one
+        assert_eq!(clearing.ledger.cash_balance(HOUSE_ACCOUNT), 60000.0);
+        assert_eq!(clearing.ledger.balance(&LedgerAccount::Inventory(UserId(1), Asset::new("BTC", "USD"))), 2.0);
+        assert!(clearing.ledger.is_balanced());
+    }
+
+    #[test]
+    fn test_clearing_is_idempotent() {
+        let user1 = Arc::new(User::new(1, "Alice", 100000.0));
+        let bid1 = Bid::new(user1.clone(), 1, BidType::XOR, 60000.0, Some(1.0));
+        let allocation = HashMap::from([
+            (user1.id, vec![AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 60000.0)]),
+        ]);
+        let metadata = AuctionMetadata::new(BasketId(1), "XOR");
+
+        let mut clearing = Clearing::new();
+        let first = clearing.clear_winning_bids(metadata.clone(), vec![bid1.clone()], allocation.clone()).unwrap();
+        let retry = clearing.clear_winning_bids(metadata.clone(), vec![bid1.clone()], allocation.clone()).unwrap();
+
+        assert!(clearing.is_processed(metadata.settlement_id));
+        assert_eq!(retry.report, first.report);
+        assert_eq!(retry.users.get(&UserId(1)).unwrap().balance, 40000.0);  // Charged once
+        assert_eq!(clearing.ledger.cash_balance(UserId(1)), 40000.0);
+
+        let mut reused = metadata.clone();
+        reused.auction_id = AuctionId(reused.auction_id.get() + 1);
+        assert!(clearing.clear_winning_bids(reused, vec![bid1], allocation).is_err());
+    }
+
+    #[test]
+    fn test_pre_settlement_hook_aborts_clearing() {
+        use crate::hooks::HookDecision;
+
+        struct AmlCheck;
+        impl SettlementHook for AmlCheck {
+            fn name(&self) -> &str {
+                "aml"
+            }
+            fn pre_settlement(&self, transfer: &Transfer) -> HookDecision {
+                if transfer.user_id == UserId(2) {
+                    HookDecision::Reject(String::from("sanctioned"))
+                } else {
+                    HookDecision::Approve
+                }
+            }
+        }
+
+        let user1 = Arc::new(User::new(1, "Alice", 100000.0));
+        let user2 = Arc::new(User::new(2, "Bob", 200000.0));
+        let bids = vec![
+            Bid::new(user1.clone(), 1, BidType::OR, 60000.0, Some(0.5)),
+            Bid::new(user2.clone(), 1, BidType::OR, 70000.0, Some(0.5)),
+        ];
+
+        let mut clearing = Clearing::new();
+        clearing.add_hook(Arc::new(AmlCheck));
+        let result = clearing.clear_winning_bids(AuctionMetadata::new(BasketId(1), "OR"), bids, HashMap::new());
+
+        assert!(result.is_err());
+        assert_eq!(clearing.last_rejection().unwrap().reason, "sanctioned");
+        assert!(clearing.ledger.entries().is_empty());  // Nothing was settled for Alice either
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_clear_with_default_management() {
+        use crate::risk::HaircutSchedule;
+
+        let user1 = Arc::new(User::new(1, "Alice", 10000.0));  // Cannot pay 60000
+        let user2 = Arc::new(User::new(2, "Bob", 200000.0));
+        let bids = vec![
+            Bid::new(user1.clone(), 1, BidType::OR, 60000.0, Some(0.5)),
+            Bid::new(user2.clone(), 1, BidType::OR, 70000.0, Some(0.5)),
+        ];
+        // Bob's half is worth 90,000 to him, 20,000 more than he pays
+        let allocation = HashMap::from([
+            (UserId(1), vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 90000.0)]),
+            (UserId(2), vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 90000.0)]),
+        ]);
+        let collateral = HashMap::from([
+            (UserId(1), vec![AssetInfo::new(Asset::new("ETH", "USD"), 10.0, 2000.0)]),
+        ]);
+        let mut waterfall = DefaultWaterfall::new(HaircutSchedule::new(0.25), 30000.0);
+
+        let mut clearing = Clearing::new();
+        clearing.fund_default(HOUSE_ACCOUNT, 20000.0).unwrap();
+        let metadata = AuctionMetadata::new(BasketId(1), "OR");
+        let (settlement, reports) = clearing.clear_with_default_management(
+            metadata.clone(), bids.clone(), allocation.clone(), &collateral, &mut waterfall
+        ).unwrap();
+
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(report.covered_by(WaterfallStep::DefaulterCash), 10000.0);
+        assert_eq!(report.covered_by(WaterfallStep::DefaulterCollateral), 15000.0);
+        assert_eq!(report.covered_by(WaterfallStep::DefaultFund), 20000.0);
+        assert_eq!(report.covered_by(WaterfallStep::WinnerHaircut), 15000.0);
+        assert!(report.is_fully_covered());
+
+        assert_eq!(settlement.users.get(&UserId(1)).unwrap().balance, 0.0);
+        assert_eq!(settlement.users.get(&UserId(2)).unwrap().balance, 115000.0);
+        assert_eq!(clearing.ledger.cash_balance(HOUSE_ACCOUNT), 95000.0);
+        assert_eq!(clearing.ledger.cash_balance(DEFAULT_FUND_ACCOUNT), 0.0);
+        assert_eq!(waterfall.default_fund, 10000.0);
+        assert_eq!(clearing.ledger.balance(&LedgerAccount::Inventory(HOUSE_ACCOUNT, Asset::new("ETH", "USD"))), 10.0);
+        assert!(clearing.ledger.is_balanced());
+        assert_eq!(settlement.report.settlements.len(), 1);  // Only Bob settled normally
+
+        // A retry returns the settlement; another auction cannot take over its id
+        let (retried, reports) = clearing.clear_with_default_management(
+            metadata.clone(), bids.clone(), allocation.clone(), &collateral, &mut waterfall
+        ).unwrap();
+        assert_eq!((retried.report, reports.len()), (settlement.report, 0));
+        let mut reused = metadata;
+        reused.auction_id = AuctionId(reused.auction_id.get() + 1);
+        let result = clearing.clear_with_default_management(reused, bids, allocation, &collateral, &mut waterfall);
+        assert!(matches!(result, Err(ClearingError::SettlementReused(_))));
+        assert_eq!(clearing.ledger.cash_balance(HOUSE_ACCOUNT), 95000.0);
+    }
+
+    #[test]
+    fn test_clear_in_settlement_currency() {
+        use crate::fx::FxRates;
+
+        let mut rates = FxRates::new();
+        rates.set_rate("USD", "EUR", 0.8);
+        let mut fx = FxSettlement::new("USD", Arc::new(rates));
+        fx.set_settlement_currency(UserId(1), "EUR");
+        fx.set_settlement_currency(UserId(3), "EUR");
+        fx.set_spread("USD", "EUR", 0.01);
+
+        let mut clearing = Clearing::new();
+        clearing.fx = Some(fx);
+
+        let user1 = Arc::new(User::new(1, "Alice", 100000.0));  // EUR
+        let user2 = Arc::new(User::new(2, "Bob", 100000.0));    // USD
+        let bids = vec![
+            Bid::new(user1.clone(), 1, BidType::OR, 60000.0, Some(0.5)),
+            Bid::new(user2.clone(), 1, BidType::OR, 70000.0, Some(0.5)),
+        ];
+        let settlement = clearing.clear_winning_bids(AuctionMetadata::new(BasketId(1), "OR"), bids, HashMap::new()).unwrap();
+
+        assert_eq!(settlement.conversions.len(), 1);
+        assert!((settlement.users.get(&UserId(1)).unwrap().balance - 51520.0).abs() < 1e-6);
+        assert_eq!(settlement.users.get(&UserId(2)).unwrap().balance, 30000.0);
+        assert_eq!(clearing.ledger.cash_balance(HOUSE_ACCOUNT), 70000.0);
+        assert!((clearing.ledger.balance(&LedgerAccount::Currency(HOUSE_ACCOUNT, String::from("EUR"))) - 48480.0).abs() < 1e-6);
+        assert!(clearing.ledger.is_balanced());
+
+        // The conversion makes the EUR user unable to afford a bid it could pay in USD
+        let poor = Arc::new(User::new(3, "Carol", 50000.0));
+        let bids = vec![Bid::new(poor, 2, BidType::OR, 62000.0, Some(0.5))];
+        assert!(clearing.clear_winning_bids(AuctionMetadata::new(BasketId(2), "OR"), bids.clone(), HashMap::new()).is_err());
+        assert!(clearing.clear_from_escrow(AuctionMetadata::new(BasketId(2), "OR"), bids, HashMap::new(), &mut Escrow::new()).is_err());
+
+        // Clawing Alice back refunds her and unwinds her conversion in a single entry
+        let settlement_id = settlement.report.metadata.settlement_id;
+        let entries = clearing.ledger.entries().len();
+        let record = clearing.clawback(settlement_id, UserId(1)).unwrap();
+        assert_eq!((record.entry_id as usize, clearing.ledger.entries().len()), (entries + 1, entries + 1));
+        assert!((clearing.ledger.balance(&LedgerAccount::Currency(UserId(1), String::from("EUR"))) - 100000.0).abs() < 1e-6);
+        assert!(clearing.processed_settlement(settlement_id).unwrap().conversions.is_empty());
+        assert!(clearing.ledger.is_balanced());
+    }
+
+    #[test]
+    fn test_stale_user_copies_do_not_undo_earlier_settlements() {
+        let alice = Arc::new(User::new(1, "Alice", 100000.0));
+        let mut clearing = Clearing::new();
+        let first = vec![Bid::new(alice.clone(), 1, BidType::XOR, 60000.0, Some(1.0))];
+        clearing.clear_winning_bids(AuctionMetadata::new(BasketId(1), "XOR"), first, HashMap::new()).unwrap();
+
+        // The second auction's bid still carries Alice's balance from before the first
+        let second = vec![Bid::new(alice.clone(), 2, BidType::XOR, 30000.0, Some(1.0))];
+        let settlement = clearing.clear_winning_bids(AuctionMetadata::new(BasketId(2), "XOR"), second, HashMap::new()).unwrap();
+        assert_eq!(settlement.users[&UserId(1)].balance, 10000.0);
+        assert_eq!(clearing.ledger.cash_balance(UserId(1)), 10000.0);
+        assert_eq!(clearing.ledger.entries().iter().filter(|entry| entry.memo == "balance sync").count(), 1);
+
+        // Nor can it spend what was paid already
+        let third = vec![Bid::new(alice, 3, BidType::XOR, 50000.0, Some(1.0))];
+        let result = clearing.clear_winning_bids(AuctionMetadata::new(BasketId(3), "XOR"), third, HashMap::new());
+        assert!(matches!(result, Err(ClearingError::InsufficientFunds { user_id: UserId(1), .. })));
+        assert!(clearing.ledger.is_balanced());
+    }
+
+    #[test]
+    fn test_clawback_reverses_one_user() {
+        let user1 = Arc::new(User::new(1, "Alice", 100000.0));
+        let user2 = Arc::new(User::new(2, "Bob", 100000.0));
+        let bids = vec![
+            Bid::new(user1.clone(), 1, BidType::OR, 60000.0, Some(0.5)),
+            Bid::new(user2.clone(), 1, BidType::OR, 70000.0, Some(0.5)),
+        ];
+        let allocation = HashMap::from([
+            (UserId(1), vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 30000.0)]),
+            (UserId(2), vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 30000.0)]),
+        ]);
+
+        let mut clearing = Clearing::new();
+        let metadata = AuctionMetadata::new(BasketId(1), "OR");
+        let settlement_id = metadata.settlement_id;
+        clearing.clear_with_report(metadata, bids, allocation, 0.01).unwrap();
+
+        let record = clearing.clawback(settlement_id, UserId(1)).unwrap();
+        assert_eq!(record.refund, 60000.0);
+        assert_eq!(record.fee_refund, 600.0);
+        // Payment, delivery and fee go back in one entry, the ledger's last
+        let entry = clearing.ledger.entries().last().unwrap();
+        assert_eq!((entry.id, entry.postings.len()), (record.entry_id, 6));
+        assert_eq!(clearing.clawbacks().len(), 1);
+
+        assert_eq!(clearing.ledger.cash_balance(UserId(1)), 100000.0);
+        assert_eq!(clearing.ledger.cash_balance(HOUSE_ACCOUNT), 70700.0);
+        assert_eq!(clearing.ledger.balance(&LedgerAccount::Inventory(UserId(1), Asset::new("BTC", "USD"))), 0.0);
+        assert!(clearing.ledger.is_balanced());
+
+        let settlement = clearing.processed_settlement(settlement_id).unwrap();
+        assert_eq!(settlement.users.get(&UserId(1)).unwrap().balance, 100000.0);
+        assert_eq!(settlement.report.settlements.len(), 1);
+
+        // A second clawback of the same user and unknown settlements are rejected
+        assert!(clearing.clawback(settlement_id, UserId(1)).is_err());
+        assert!(clearing.clawback(settlement_id + 1000, UserId(2)).is_err());
+    }
+
+    #[test]
+    fn test_cash_settlement_moves_only_the_difference() {
+        let user1 = Arc::new(User::new(1, "Alice", 100000.0));
+        let user2 = Arc::new(User::new(2, "Bob", 100000.0));
+        let bids = vec![
+            Bid::new(user1.clone(), 1, BidType::OR, 60000.0, Some(0.5)),
+            Bid::new(user2.clone(), 1, BidType::OR, 70000.0, Some(0.5)),
+        ];
+        let allocation = HashMap::from([
+            (UserId(1), vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 30000.0)]),
+            (UserId(2), vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 30000.0)]),
+        ]);
+        let marks = HashMap::from([("BTC".to_string(), 65000.0)]);
+
+        let mut clearing = Clearing::new();
+        let metadata = AuctionMetadata::new(BasketId(1), "OR");
+        let settlement_id = metadata.settlement_id;
+        let cleared = clearing.clear_cash_settled(metadata, bids.clone(), allocation.clone(), &marks, 0.01).unwrap();
+        assert_eq!(cleared.report.mode, SettlementMode::Cash);
+        assert_eq!(cleared.report.settlements[0].reference_value, Some(65000.0));
+        // Alice bid below the fixing and is paid the difference; Bob pays his
+        assert_eq!(cleared.users.get(&UserId(1)).unwrap().balance, 100000.0 + 5000.0 - 600.0);
+        assert_eq!(cleared.users.get(&UserId(2)).unwrap().balance, 100000.0 - 5000.0 - 700.0);
+        assert_eq!(clearing.ledger.balance(&LedgerAccount::Inventory(UserId(1), Asset::new("BTC", "USD"))), 0.0);
+        assert!(clearing.ledger.is_balanced());
+
+        let record = clearing.clawback(settlement_id, UserId(1)).unwrap();
+        assert_eq!((record.refund, record.assets.len()), (-5000.0, 0));
+        assert_eq!(clearing.ledger.cash_balance(UserId(1)), 100000.0);
+
+        let metadata = AuctionMetadata::new(BasketId(1), "OR");
+        let unmarked = clearing.clear_cash_settled(metadata, bids, allocation, &HashMap::new(), 0.0);
+        assert!(matches!(unmarked, Err(ClearingError::NoMark(base)) if base == "BTC"));
+    }
+}
@@ -0,0 +1,561 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+use model::money::{Currency, Money};
+use crate::corporate_actions::AccrualRules;
+use crate::report::SettlementMode;
+
+/// Environment variables starting with this override the file, e.g.
+/// `COMBIDEX_AUCTION__MAX_ROUNDS=40` sets `auction.max_rounds`.
+pub const ENV_PREFIX: &str = "COMBIDEX_";
+
+
+/// Why a configuration was rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    Io(String),
+    Parse(String),
+    /// An override named a setting that does not exist.
+    UnknownSetting(String),
+    Invalid { field: &'static str, reason: &'static str },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(message) | ConfigError::Parse(message) => write!(f, "{}", message),
+            ConfigError::UnknownSetting(name) => write!(f, "unknown setting {}", name),
+            ConfigError::Invalid { field, reason } => write!(f, "{} {}", field, reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+fn check(field: &'static str, valid: bool, reason: &'static str) -> Result<(), ConfigError> {
+    if valid { Ok(()) } else { Err(ConfigError::Invalid { field, reason }) }
+}
+
+
+/// Defaults for auctions whose request leaves them out.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuctionConfig {
+    /// Relative CCA price rise per round of excess demand.
+    pub price_increment: f64,
+    pub max_rounds: usize,
+    /// Drop CCA bidders with no valid bid in a round from all later rounds.
+    pub activity_rule: bool,
+    pub soft_close: SoftCloseConfig,
+    /// Let an XOR winner whose balance cannot cover its bid take the share it can afford,
+    /// offering the rest to the next-best bids.
+    pub partial_fills: bool,
+}
+
+impl Default for AuctionConfig {
+    fn default() -> Self {
+        AuctionConfig { price_increment: 0.05, max_rounds: 20, activity_rule: true, soft_close: SoftCloseConfig::default(), partial_fills: false }
+    }
+}
+
+/// Anti-sniping for baskets with a bidding deadline, off unless `enabled`. A new leading
+/// bid in the last `window_secs` before the close moves the close `extension_secs` later,
+/// at most `max_extensions` times per basket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SoftCloseConfig {
+    pub enabled: bool,
+    pub window_secs: u64,
+    pub extension_secs: u64,
+    pub max_extensions: u32,
+}
+
+impl Default for SoftCloseConfig {
+    fn default() -> Self {
+        SoftCloseConfig { enabled: false, window_secs: 120, extension_secs: 120, max_extensions: 10 }
+    }
+}
+
+/// Fee charged on top of each winning payment, as a fraction of it.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeeSchedule {
+    pub rate: f64,
+    /// Rates for particular mechanisms, by name, e.g. `vcg = 0.001`.
+    pub mechanisms: BTreeMap<String, f64>,
+}
+
+impl FeeSchedule {
+    pub fn rate_for(&self, mechanism: &str) -> f64 {
+        self.mechanisms.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(mechanism))
+            .map_or(self.rate, |(_, rate)| *rate)
+    }
+
+    /// The fee on `payment`, in the payment's currency.
+    pub fn fee<C: Currency>(&self, mechanism: &str, payment: Money<C>) -> Money<C> {
+        payment.scale(self.rate_for(mechanism))
+    }
+}
+
+/// How auctions settle unless their request says otherwise. Cash settlement fixes each
+/// asset at its last mark at or before the most recent `fixing_time`, `HH:MM` in UTC;
+/// without one it takes the latest marks. `accruals` says who keeps corporate actions on
+/// baskets sold but not yet settled.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SettlementConfig {
+    pub mode: SettlementMode,
+    pub fixing_time: Option<String>,
+    pub accruals: AccrualRules,
+}
+
+impl SettlementConfig {
+    /// The fixing time as minutes after midnight UTC, if one is set and valid.
+    pub fn fixing_minute(&self) -> Option<u32> {
+        let (hours, minutes) = self.fixing_time.as_deref()?.split_once(':')?;
+        let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+        (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+    }
+}
+
+/// Where and how fast to call one market data venue, and the API key for private calls.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProviderConfig {
+    pub base_url: String,
+    #[serde(default = "default_requests_per_second")]
+    pub requests_per_second: f64,
+    #[serde(default = "default_burst")]
+    pub burst: u32,
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub client_secret: Option<String>,
+}
+
+fn default_requests_per_second() -> f64 {
+    10.0
+}
+
+fn default_burst() -> u32 {
+    20
+}
+
+fn default_concurrency() -> usize {
+    8
+}
+
+impl ProviderConfig {
+    pub fn new(base_url: &str) -> Self {
+        ProviderConfig {
+            base_url: base_url.to_string(),
+            requests_per_second: default_requests_per_second(),
+            burst: default_burst(),
+            concurrency: default_concurrency(),
+            client_id: None,
+            client_secret: None,
+        }
+    }
+
+    /// The API key, if one is configured.
+    pub fn credentials(&self) -> Option<(&str, &str)> {
+        Some((self.client_id.as_deref()?, self.client_secret.as_deref()?))
+    }
+}
+
+impl fmt::Debug for ProviderConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProviderConfig")
+            .field("base_url", &self.base_url)
+            .field("requests_per_second", &self.requests_per_second)
+            .field("burst", &self.burst)
+            .field("concurrency", &self.concurrency)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &self.client_secret.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProvidersConfig {
+    pub deribit: ProviderConfig,
+    pub binance: ProviderConfig,
+    pub okx: ProviderConfig,
+}
+
+impl Default for ProvidersConfig {
+    fn default() -> Self {
+        ProvidersConfig {
+            deribit: ProviderConfig::new("https://www.deribit.com/api/v2"),
+            binance: ProviderConfig::new("https://api.binance.com"),
+            okx: ProviderConfig::new("https://www.okx.com"),
+        }
+    }
+}
+
+/// Grid sizes of the numerical pricers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PricerConfig {
+    /// FFT grid points; a power of two.
+    pub fft_points: usize,
+    pub fft_eta: f64,
+    pub fft_alpha: f64,
+    pub mc_paths: usize,
+    pub mc_steps: usize,
+    pub mc_seed: u64,
+}
+
+impl Default for PricerConfig {
+    fn default() -> Self {
+        PricerConfig { fft_points: 4096, fft_eta: 0.25, fft_alpha: 1.5, mc_paths: 100_000, mc_steps: 252, mc_seed: 42 }
+    }
+}
+
+
+/// Token bucket of bid submissions and cancellations: `burst` at once, refilled at
+/// `per_second`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BidLimit {
+    pub per_second: f64,
+    pub burst: u32,
+}
+
+impl BidLimit {
+    pub fn new(per_second: f64, burst: u32) -> Self {
+        BidLimit { per_second, burst }
+    }
+}
+
+/// Limits on how fast bids may be submitted and cancelled, off unless `enabled`. Each
+/// user gets `per_user`, tightened to `final_rounds` on a basket whose CCA is running;
+/// API clients are also held to `per_ip` per address.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    pub per_user: BidLimit,
+    pub final_rounds: BidLimit,
+    pub per_ip: BidLimit,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            enabled: false,
+            per_user: BidLimit::new(10.0, 20),
+            final_rounds: BidLimit::new(1.0, 2),
+            per_ip: BidLimit::new(50.0, 100),
+        }
+    }
+}
+
+/// Pre-trade checks on every bid, off unless `enabled`. A user's resting bids may add up
+/// to `max_notional`, and what they hold and stand to win of an asset, by base, to its
+/// entry in `position_limits`; assets not listed are unlimited. A bid may be at most
+/// `price_collar`, as a fraction, above or below its share of the basket's value at the
+/// latest marks. Callers with `override_scope` or above skip the checks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RiskConfig {
+    pub enabled: bool,
+    pub max_notional: f64,
+    pub position_limits: BTreeMap<String, f64>,
+    pub price_collar: f64,
+    pub override_scope: String,
+}
+
+impl Default for RiskConfig {
+    fn default() -> Self {
+        RiskConfig {
+            enabled: false,
+            max_notional: 10_000_000.0,
+            position_limits: BTreeMap::new(),
+            price_collar: 0.5,
+            override_scope: "admin".to_string(),
+        }
+    }
+}
+
+impl RiskConfig {
+    /// The position limit of the asset with base `base`, if it has one.
+    pub fn position_limit(&self, base: &str) -> Option<f64> {
+        self.position_limits.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(base))
+            .map(|(_, limit)| *limit)
+    }
+}
+
+/// Streaming of auction events to a message broker, off unless `enabled`. `backend` is
+/// `kafka` or `nats` and `servers` a comma-separated list of its brokers or URLs; topics
+/// are named under `topic_prefix`. A failed delivery is tried `max_attempts` times in
+/// all, waiting `retry_backoff_ms` after the first failure and twice as long after each
+/// one since.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PublishConfig {
+    pub enabled: bool,
+    pub backend: String,
+    pub servers: String,
+    pub topic_prefix: String,
+    pub max_attempts: u32,
+    pub retry_backoff_ms: u64,
+}
+
+impl Default for PublishConfig {
+    fn default() -> Self {
+        PublishConfig {
+            enabled: false,
+            backend: "kafka".to_string(),
+            servers: "localhost:9092".to_string(),
+            topic_prefix: "combidex".to_string(),
+            max_attempts: 5,
+            retry_backoff_ms: 100,
+        }
+    }
+}
+
+impl PublishConfig {
+    pub fn servers(&self) -> Vec<&str> {
+        self.servers.split(',').map(str::trim).filter(|server| !server.is_empty()).collect()
+    }
+}
+
+/// Marks streamed from the Deribit price feed, off unless `enabled`. Every basket's NAV
+/// is snapshotted on the first mark at least `nav_interval_secs` after the last.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MarketDataConfig {
+    pub enabled: bool,
+    pub nav_interval_secs: u64,
+}
+
+impl Default for MarketDataConfig {
+    fn default() -> Self {
+        MarketDataConfig { enabled: false, nav_interval_secs: 3600 }
+    }
+}
+
+
+/// Every tunable of the exchange, read from TOML with environment overrides. Sections
+/// left out of the file keep their defaults.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub auction: AuctionConfig,
+    pub fees: FeeSchedule,
+    pub providers: ProvidersConfig,
+    pub pricer: PricerConfig,
+    pub rate_limits: RateLimitConfig,
+    pub risk: RiskConfig,
+    pub publish: PublishConfig,
+    pub settlement: SettlementConfig,
+    pub market_data: MarketDataConfig,
+}
+
+impl Config {
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        let config: Config = toml::from_str(text).map_err(|error| ConfigError::Parse(error.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The file at `path`, if any, then `COMBIDEX_` variables from the environment.
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let config = match path {
+            Some(path) => {
+                let text = fs::read_to_string(path).map_err(|error| ConfigError::Io(format!("cannot read {}: {}", path.display(), error)))?;
+                Config::from_toml(&text)?
+            }
+            None => Config::default(),
+        };
+        config.with_overrides(std::env::vars())
+    }
+
+    /// Applies `ENV_PREFIX` variables among `vars`. Sections and fields are separated by
+    /// a double underscore and matched case-insensitively; values are read as the type of
+    /// the setting they replace.
+    pub fn with_overrides(self, vars: impl IntoIterator<Item = (String, String)>) -> Result<Self, ConfigError> {
+        let mut table = Table::try_from(&self).map_err(|error| ConfigError::Parse(error.to_string()))?;
+        let mut overridden = false;
+        for (name, value) in vars {
+            let Some(key) = name.strip_prefix(ENV_PREFIX) else { continue };
+            let path: Vec<String> = key.split("__").map(str::to_lowercase).collect();
+            match set(&mut table, &path, &value) {
+                Some(true) => {}
+                Some(false) => return Err(ConfigError::Parse(format!("{} is not a valid value for {}", value, name))),
+                None => return Err(ConfigError::UnknownSetting(name)),
+            }
+            overridden = true;
+        }
+        if !overridden {
+            return Ok(self);
+        }
+        let config: Config = table.try_into().map_err(|error: toml::de::Error| ConfigError::Parse(error.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let auction = &self.auction;
+        check("auction.price_increment", auction.price_increment > 0.0 && auction.price_increment <= 1.0, "must be in (0, 1]")?;
+        check("auction.max_rounds", auction.max_rounds > 0, "must be at least 1")?;
+        check("auction.soft_close.window_secs", auction.soft_close.window_secs > 0, "must be at least 1")?;
+        check("auction.soft_close.extension_secs", auction.soft_close.extension_secs > 0, "must be at least 1")?;
+        for rate in std::iter::once(&self.fees.rate).chain(self.fees.mechanisms.values()) {
+            check("fees", (0.0..1.0).contains(rate), "rates must be in [0, 1)")?;
+        }
+        for provider in [&self.providers.deribit, &self.providers.binance, &self.providers.okx] {
+            check("providers.base_url", provider.base_url.starts_with("http://") || provider.base_url.starts_with("https://"), "must be an http(s) URL")?;
+            check("providers.requests_per_second", provider.requests_per_second > 0.0, "must be positive")?;
+            check("providers.burst", provider.burst > 0, "must be at least 1")?;
+            check("providers.concurrency", provider.concurrency > 0, "must be at least 1")?;
+            check("providers.client_secret", provider.client_id.is_some() == provider.client_secret.is_some(), "must be set together with client_id")?;
+        }
+        let pricer = &self.pricer;
+        check("pricer.fft_points", pricer.fft_points >= 16 && pricer.fft_points.is_power_of_two(), "must be a power of two of at least 16")?;
+        check("pricer.fft_eta", pricer.fft_eta > 0.0, "must be positive")?;
+        check("pricer.fft_alpha", pricer.fft_alpha > 0.0, "must be positive")?;
+        check("pricer.mc_paths", pricer.mc_paths > 0, "must be at least 1")?;
+        check("pricer.mc_steps", pricer.mc_steps > 0, "must be at least 1")?;
+        let limits = &self.rate_limits;
+        for limit in [&limits.per_user, &limits.final_rounds, &limits.per_ip] {
+            check("rate_limits.per_second", limit.per_second > 0.0, "must be positive")?;
+            check("rate_limits.burst", limit.burst > 0, "must be at least 1")?;
+        }
+        let risk = &self.risk;
+        check("risk.max_notional", risk.max_notional > 0.0, "must be positive")?;
+        check("risk.position_limits", risk.position_limits.values().all(|limit| *limit >= 0.0), "must not be negative")?;
+        check("risk.price_collar", risk.price_collar > 0.0, "must be positive")?;
+        check("risk.override_scope", ["read_only", "trade", "admin"].contains(&risk.override_scope.as_str()), "must be read_only, trade or admin")?;
+        let publish = &self.publish;
+        check("publish.backend", ["kafka", "nats"].contains(&publish.backend.as_str()), "must be kafka or nats")?;
+        check("publish.servers", !publish.servers().is_empty(), "must name at least one server")?;
+        check("publish.topic_prefix", !publish.topic_prefix.is_empty(), "must not be empty")?;
+        check("publish.max_attempts", publish.max_attempts > 0, "must be at least 1")?;
+        let settlement = &self.settlement;
+        check("settlement.fixing_time", settlement.fixing_time.is_none() || settlement.fixing_minute().is_some(), "must be HH:MM")?;
+        check("market_data.nav_interval_secs", self.market_data.nav_interval_secs > 0, "must be at least 1")?;
+        Ok(())
+    }
+}
+
+/// Sets the setting at `path` from `text`, parsed like the value it replaces; new entries
+/// are checked when the table is read back. Returns `None` if a section does not exist
+/// and `Some(false)` if `text` does not parse.
+fn set(table: &mut Table, path: &[String], text: &str) -> Option<bool> {
+    let (field, sections) = path.split_last()?;
+    let mut table = table;
+    for section in sections {
+        table = table.get_mut(section)?.as_table_mut()?;
+    }
+    let value = match table.get(field) {
+        Some(Value::Float(_)) => text.parse().ok().map(Value::Float),
+        Some(Value::Integer(_)) => text.parse().ok().map(Value::Integer),
+        Some(Value::Boolean(_)) => text.parse().ok().map(Value::Boolean),
+        Some(Value::String(_)) => Some(Value::String(text.to_string())),
+        Some(_) => return None,
+        None => Some(text.parse::<f64>().map(Value::Float).unwrap_or_else(|_| Value::String(text.to_string()))),
+    };
+    let parsed = value.is_some();
+    if let Some(value) = value {
+        table.insert(field.clone(), value);
+    }
+    Some(parsed)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::money::Usd;
+    use crate::corporate_actions::{CorporateActionKind, EntitledParty};
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_file_then_environment() {
+        let config = Config::from_toml(r#"
+            [auction]
+            max_rounds = 40
+
+            [auction.soft_close]
+            enabled = true
+
+            [fees]
+            rate = 0.002
+            mechanisms = { vcg = 0.001 }
+
+            [providers.deribit]
+            base_url = "https://test.deribit.com/api/v2"
+            client_id = "id"
+            client_secret = "secret"
+        "#).unwrap();
+        let soft_close = SoftCloseConfig { enabled: true, ..SoftCloseConfig::default() };
+        assert_eq!(config.auction, AuctionConfig { max_rounds: 40, soft_close: soft_close.clone(), ..AuctionConfig::default() });
+        assert_eq!(config.fees.rate_for("VCG"), 0.001);
+        assert_eq!(config.fees.rate_for("xor"), 0.002);
+        assert_eq!(config.fees.fee("vcg", Money::new(10_000.0, Usd)), Money::new(10.0, Usd));
+        assert_eq!(config.providers.deribit.credentials(), Some(("id", "secret")));
+        assert_eq!(config.providers.okx, ProvidersConfig::default().okx);
+        assert!(!format!("{:?}", config).contains("secret\""));
+
+        let overridden = config.with_overrides(vars(&[
+            ("COMBIDEX_AUCTION__PRICE_INCREMENT", "0.1"),
+            ("COMBIDEX_AUCTION__ACTIVITY_RULE", "false"),
+            ("COMBIDEX_AUCTION__SOFT_CLOSE__MAX_EXTENSIONS", "3"),
+            ("COMBIDEX_FEES__MECHANISMS__CCA", "0.003"),
+            ("COMBIDEX_PRICER__FFT_POINTS", "8192"),
+            ("COMBIDEX_RATE_LIMITS__ENABLED", "true"),
+            ("COMBIDEX_RATE_LIMITS__FINAL_ROUNDS__BURST", "1"),
+            ("COMBIDEX_RISK__POSITION_LIMITS__BTC", "5"),
+            ("COMBIDEX_PUBLISH__BACKEND", "nats"),
+            ("COMBIDEX_PUBLISH__SERVERS", "nats://a:4222, nats://b:4222"),
+            ("COMBIDEX_SETTLEMENT__MODE", "cash"),
+            ("COMBIDEX_SETTLEMENT__FIXING_TIME", "16:00"),
+            ("COMBIDEX_SETTLEMENT__ACCRUALS__COUPON", "seller"),
+            ("PATH", "/usr/bin"),
+        ])).unwrap();
+        assert_eq!(overridden.auction, AuctionConfig {
+            price_increment: 0.1,
+            max_rounds: 40,
+            activity_rule: false,
+            soft_close: SoftCloseConfig { max_extensions: 3, ..soft_close },
+            partial_fills: false,
+        });
+        assert_eq!(overridden.fees.rate_for("cca"), 0.003);
+        assert_eq!(overridden.pricer.fft_points, 8192);
+        assert!(overridden.rate_limits.enabled);
+        assert_eq!(overridden.rate_limits.final_rounds, BidLimit::new(1.0, 1));
+        assert_eq!(overridden.risk.position_limit("BTC"), Some(5.0));
+        assert_eq!(overridden.publish.servers(), ["nats://a:4222", "nats://b:4222"]);
+        assert_eq!(overridden.settlement.mode, SettlementMode::Cash);
+        assert_eq!(overridden.settlement.fixing_minute(), Some(960));
+        assert_eq!(overridden.settlement.accruals.entitled(CorporateActionKind::Coupon), EntitledParty::Seller);
+    }
+
+    #[test]
+    fn test_invalid_settings_are_rejected() {
+        assert!(matches!(Config::from_toml("[auction]\nmax_round = 3"), Err(ConfigError::Parse(_))));
+        assert_eq!(Config::from_toml("[fees]\nrate = 1.5"), Err(ConfigError::Invalid { field: "fees", reason: "rates must be in [0, 1)" }));
+        assert!(matches!(Config::from_toml("[auction.soft_close]\nwindow_secs = 0"), Err(ConfigError::Invalid { field: "auction.soft_close.window_secs", .. })));
+        assert!(matches!(Config::from_toml("[pricer]\nfft_points = 1000"), Err(ConfigError::Invalid { field: "pricer.fft_points", .. })));
+        assert!(matches!(Config::from_toml("[settlement]\nfixing_time = \"25:00\""), Err(ConfigError::Invalid { field: "settlement.fixing_time", .. })));
+
+        let config = Config::default();
+        assert_eq!(config.clone().with_overrides(vars(&[("COMBIDEX_AUCTIONS__MAX_ROUNDS", "3")])), Err(ConfigError::UnknownSetting("COMBIDEX_AUCTIONS__MAX_ROUNDS".to_string())));
+        assert!(matches!(config.clone().with_overrides(vars(&[("COMBIDEX_AUCTION__ROUNDS", "3")])), Err(ConfigError::Parse(_))));
+        assert!(matches!(config.clone().with_overrides(vars(&[("COMBIDEX_AUCTION__MAX_ROUNDS", "many")])), Err(ConfigError::Parse(_))));
+        assert!(matches!(config.with_overrides(vars(&[("COMBIDEX_AUCTION__MAX_ROUNDS", "0")])), Err(ConfigError::Invalid { .. })));
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::{BTreeMap, HashMap};
+use crate::wdp::WDPSolver;
+use model::model::{Bid, Basket, AssetInfo};
+use model::ids::UserId;
+use model::helpers::{allocate_basket};
+
+/// Winners with more than this many users are only checked against coalitions of one
+/// winner and of all of them, rather than every subset.
+const MAX_ENUMERATED_WINNERS: usize = 10;
+
+/// Winning bids, their allocation and core payments by user.
+type CoreOutcome<'a> = (Vec<&'a Bid>, HashMap<UserId, Vec<AssetInfo>>, HashMap<UserId, f64>);
+
+
+/// Core-selecting pricing: the welfare-maximizing allocation, with payments raised from
+/// VCG's just far enough that no group of losers could have offered the seller more for
+/// what the winners got.
+pub struct CorePricing;
+
+impl CorePricing {
+    /// Winners, allocation and core payments by user, without settlement. Payments start
+    /// at VCG and every blocking coalition's shortfall, largest first, is spread equally
+    /// over the winners it excludes, none of whom pays above its bid.
+    pub fn outcome<'a>(bids: &'a [Bid], basket: &'a Basket) -> CoreOutcome<'a> {
+        let (winning_bids, total_welfare) = WDPSolver::branch_and_bound(bids, basket);
+        let mut values: BTreeMap<UserId, f64> = BTreeMap::new();
+        for bid in &winning_bids {
+            *values.entry(bid.user.id).or_insert(0.0) += bid.price;
+        }
+        let winners: Vec<UserId> = values.keys().copied().collect();
+
+        let coalitions: Vec<Vec<usize>> = if winners.len() <= MAX_ENUMERATED_WINNERS {
+            (1..1usize << winners.len())
+                .map(|mask| (0..winners.len()).filter(|index| mask & (1 << index) != 0).collect())
+                .collect()
+        } else {
+            (0..winners.len()).map(|index| vec![index]).chain(std::iter::once((0..winners.len()).collect())).collect()
+        };
+        // What the seller could get without the winners in each coalition, less what the
+        // other winners would have kept paying
+        let mut constraints: Vec<(Vec<usize>, f64)> = coalitions.into_iter()
+            .map(|coalition| {
+                let remaining: Vec<Bid> = bids.iter()
+                    .filter(|bid| !coalition.iter().any(|index| winners[*index] == bid.user.id))
+                    .cloned()
+                    .collect();
+                let (_, welfare_without) = WDPSolver::branch_and_bound(&remaining, basket);
+                let kept: f64 = coalition.iter().map(|index| values[&winners[*index]]).sum();
+                (coalition, welfare_without - (total_welfare - kept))
+            })
+            .collect();
+
+        let mut payments: Vec<f64> = winners.iter()
+            .map(|user_id| constraints.iter()
+                .find(|(coalition, _)| coalition.len() == 1 && winners[coalition[0]] == *user_id)
+                .map_or(0.0, |(_, bound)| bound.clamp(0.0, values[user_id])))
+            .collect();
+        while let Some((coalition, shortfall)) = constraints.iter()
+            .map(|(coalition, bound)| (coalition, bound - coalition.iter().map(|index| payments[*index]).sum::<f64>()))
+            .filter(|(_, shortfall)| *shortfall > 1e-9)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        {
+            let caps: Vec<f64> = coalition.iter().map(|index| values[&winners[*index]]).collect();
+            let raised = raise_equally(coalition.iter().map(|index| payments[*index]).collect(), &caps, shortfall);
+            for (index, payment) in coalition.clone().into_iter().zip(raised) {
+                payments[index] = payment;
+            }
+            let coalition = coalition.clone();
+            constraints.retain(|(other, _)| *other != coalition);
+        }
+
+        let allocation = allocate_basket(&winning_bids, basket);
+        let payments = winners.into_iter().zip(payments).collect();
+        (winning_bids, allocation, payments)
+    }
+}
+
+/// Adds `amount` to `payments`, the same to each until one reaches its cap in `caps`,
+/// then the same to each of the rest.
+fn raise_equally(mut payments: Vec<f64>, caps: &[f64], mut amount: f64) -> Vec<f64> {
+    let mut open: Vec<usize> = (0..payments.len()).filter(|index| caps[*index] > payments[*index]).collect();
+    open.sort_by(|a, b| (caps[*a] - payments[*a]).total_cmp(&(caps[*b] - payments[*b])));
+    for (taken, index) in open.iter().enumerate() {
+        let share = amount / (open.len() - taken) as f64;
+        let raise = share.min(caps[*index] - payments[*index]);
+        payments[*index] += raise;
+        amount -= raise;
+    }
+    payments
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::ids::BasketId;
+    use model::model::{User, Asset, BidType};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_core_payments_rise_above_vcg_to_block_the_losing_package_bid() {
+        let basket = Basket { id: BasketId(1), assets: vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60000.0)] };
+        let bid = |id, price, quantity| Bid::new(Arc::new(User::new(id, "Bidder", 1000000.0)), 1, BidType::XOR, price, Some(quantity));
+        let bids = vec![bid(1, 40000.0, 0.5), bid(2, 40000.0, 0.5), bid(3, 70000.0, 1.0)];
+
+        // VCG would charge each half 30,000, 10,000 less than the whole basket's bidder offers
+        let (winners, allocation, payments) = CorePricing::outcome(&bids, &basket);
+        assert_eq!(winners.len(), 2);
+        assert!(!allocation.contains_key(&UserId(3)));
+        assert!((payments[&UserId(1)] - 35000.0).abs() < 1e-6 && (payments[&UserId(2)] - 35000.0).abs() < 1e-6);
+
+        // Without competition for the package the core is VCG
+        let (_, _, payments) = CorePricing::outcome(&bids[..2], &basket);
+        assert_eq!(payments.values().sum::<f64>(), 0.0);
+    }
+
+    #[test]
+    fn test_raise_equally_stops_each_payment_at_its_bid() {
+        assert_eq!(raise_equally(vec![10.0, 10.0, 0.0], &[12.0, 30.0, 30.0], 20.0), vec![12.0, 19.0, 9.0]);
+    }
+}
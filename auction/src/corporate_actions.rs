@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+use model::model::Asset;
+use crate::error::ClearingError;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CorporateActionKind {
+    StakingReward,
+    Coupon,
+    Airdrop,
+}
+
+/// What a holder receives per unit held: cash in the asset's quote, or units of an asset,
+/// the same one for staking rewards or another for airdrops.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Payout {
+    Cash(f64),
+    Units { asset: Asset, quantity: f64 },
+}
+
+impl Payout {
+    fn scaled(&self, by: f64) -> Payout {
+        match self {
+            Payout::Cash(amount) => Payout::Cash(amount * by),
+            Payout::Units { asset, quantity } => Payout::Units { asset: asset.clone(), quantity: quantity * by },
+        }
+    }
+
+    fn amount(&self) -> f64 {
+        match self {
+            Payout::Cash(amount) => *amount,
+            Payout::Units { quantity, .. } => *quantity,
+        }
+    }
+}
+
+/// A payout to holders of `base` as of `ex_time`, in Unix seconds like settlement dates.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CorporateAction {
+    pub id: u64,
+    pub kind: CorporateActionKind,
+    pub base: String,
+    pub ex_time: u64,
+    pub per_unit: Payout,
+}
+
+impl CorporateAction {
+    pub fn validate(&self) -> Result<(), ClearingError> {
+        let amount = self.per_unit.amount();
+        if !amount.is_finite() || amount <= 0.0 {
+            return Err(ClearingError::NonPositive("corporate action payout"));
+        }
+        Ok(())
+    }
+}
+
+/// Who keeps what accrues on a basket sold but not yet settled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntitledParty {
+    Seller,
+    #[default]
+    Winner,
+}
+
+/// Who is entitled to each kind of action falling between an auction and its settlement.
+/// By default the winner is, having bought the basket with what accrues on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccrualRules {
+    pub staking_reward: EntitledParty,
+    pub coupon: EntitledParty,
+    pub airdrop: EntitledParty,
+}
+
+impl AccrualRules {
+    pub fn entitled(&self, kind: CorporateActionKind) -> EntitledParty {
+        match kind {
+            CorporateActionKind::StakingReward => self.staking_reward,
+            CorporateActionKind::Coupon => self.coupon,
+            CorporateActionKind::Airdrop => self.airdrop,
+        }
+    }
+}
+
+/// What one action paid on one pending obligation, and to whom.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Accrual {
+    pub action_id: u64,
+    pub kind: CorporateActionKind,
+    pub entitled: EntitledParty,
+    pub payout: Payout,
+}
+
+impl Accrual {
+    /// The accrual of `action` on `quantity` units of its asset under `rules`.
+    pub fn of(action: &CorporateAction, quantity: f64, rules: &AccrualRules) -> Self {
+        Accrual {
+            action_id: action.id,
+            kind: action.kind,
+            entitled: rules.entitled(action.kind),
+            payout: action.per_unit.scaled(quantity),
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::HashSet;
+use serde::{Serialize, Deserialize};
+use model::model::AssetInfo;
+use model::ids::{AuctionId, BasketId, UserId};
+use crate::corporate_actions::{Accrual, AccrualRules, CorporateAction, EntitledParty, Payout};
+use crate::error::ClearingError;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+
+/// Business-day calendar used to roll trade dates forward to settlement dates.
+/// Days are counted since the Unix epoch (UTC).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SettlementCalendar {
+    pub holidays: HashSet<u64>,
+    pub settle_on_weekends: bool,
+}
+
+impl SettlementCalendar {
+    pub fn new() -> Self {
+        SettlementCalendar::default()
+    }
+
+    /// A calendar where every day is a settlement day, as for 24/7 crypto venues.
+    pub fn continuous() -> Self {
+        SettlementCalendar {
+            holidays: HashSet::new(),
+            settle_on_weekends: true,
+        }
+    }
+
+    pub fn add_holiday(&mut self, day: u64) {
+        self.holidays.insert(day);
+    }
+
+    pub fn is_business_day(&self, day: u64) -> bool {
+        // 1970-01-01 was a Thursday; Monday is 0
+        let weekday = (day + 3) % 7;
+        (self.settle_on_weekends || weekday < 5) && !self.holidays.contains(&day)
+    }
+
+    /// Timestamp of the start of the `lag_days`-th business day after `trade_time`.
+    pub fn settlement_time(&self, trade_time: u64, lag_days: u32) -> u64 {
+        let mut day = trade_time / SECONDS_PER_DAY;
+        let mut remaining = lag_days;
+        while remaining > 0 {
+            day += 1;
+            if self.is_business_day(day) {
+                remaining -= 1;
+            }
+        }
+        if lag_days == 0 {
+            trade_time
+        } else {
+            day * SECONDS_PER_DAY
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingObligation {
+    pub id: u64,
+    pub user_id: UserId,
+    pub auction_id: AuctionId,
+    pub basket_id: BasketId,
+    pub amount: f64,
+    pub settled_amount: f64,
+    pub settles_at: u64,
+    pub assets: Vec<AssetInfo>,
+    /// What corporate actions paid on the assets before settlement.
+    #[serde(default)]
+    pub accruals: Vec<Accrual>,
+}
+impl PendingObligation {
+    pub fn outstanding(&self) -> f64 {
+        self.amount - self.settled_amount
+    }
+
+    /// Cash accrued to the winner, which settlement nets against what it owes.
+    pub fn winner_cash(&self) -> f64 {
+        self.accruals.iter()
+            .filter(|accrual| accrual.entitled == EntitledParty::Winner)
+            .map(|accrual| match accrual.payout {
+                Payout::Cash(amount) => amount,
+                Payout::Units { .. } => 0.0,
+            })
+            .sum()
+    }
+
+    /// Adds the units accrued to the winner to the assets it receives, at no cost.
+    pub(crate) fn deliver_accrued_units(&mut self) {
+        for accrual in self.accruals.iter().filter(|accrual| accrual.entitled == EntitledParty::Winner) {
+            let Payout::Units { asset, quantity } = &accrual.payout else {
+                continue;
+            };
+            match self.assets.iter_mut().find(|info| info.asset == *asset) {
+                Some(info) => info.quantity += quantity,
+                None => self.assets.push(AssetInfo::new(asset.clone(), *quantity, 0.0)),
+            }
+        }
+    }
+
+    pub fn is_due(&self, now: u64) -> bool {
+        self.settles_at <= now
+    }
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct DueSettlement {
+    pub settled: Vec<PendingObligation>,
+    pub failed: Vec<u64>,
+}
+
+
+/// Obligations booked at clearing that settle at a later date.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PendingObligations {
+    next_id: u64,
+    obligations: Vec<PendingObligation>,
+}
+
+impl PendingObligations {
+    pub fn new() -> Self {
+        PendingObligations::default()
+    }
+
+    pub fn book(
+        &mut self,
+        user_id: UserId,
+        auction_id: AuctionId,
+        basket_id: BasketId,
+        amount: f64,
+        settles_at: u64,
+        assets: Vec<AssetInfo>,
+    ) -> u64 {
+        self.next_id += 1;
+        self.obligations.push(PendingObligation {
+            id: self.next_id,
+            user_id,
+            auction_id,
+            basket_id,
+            amount,
+            settled_amount: 0.0,
+            settles_at,
+            assets,
+            accruals: Vec::new(),
+        });
+        self.next_id
+    }
+
+    pub fn get(&self, id: u64) -> Option<&PendingObligation> {
+        self.obligations.iter().find(|o| o.id == id)
+    }
+
+    pub fn for_user(&self, user_id: UserId) -> Vec<&PendingObligation> {
+        self.obligations.iter().filter(|o| o.user_id == user_id).collect()
+    }
+
+    pub fn outstanding_for_user(&self, user_id: UserId) -> f64 {
+        self.for_user(user_id).iter().map(|o| o.outstanding()).sum()
+    }
+
+    pub fn len(&self) -> usize {
+        self.obligations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.obligations.is_empty()
+    }
+
+    /// Records `amount` paid on obligation `id` ahead of its settlement date, removing it
+    /// once nothing is outstanding.
+    pub(crate) fn record_payment(&mut self, id: u64, amount: f64) {
+        if let Some(index) = self.obligations.iter().position(|o| o.id == id) {
+            self.obligations[index].settled_amount += amount;
+            if self.obligations[index].outstanding() <= 0.0 {
+                self.obligations.remove(index);
+            }
+        }
+    }
+
+    /// Removes and returns every obligation that has matured by `now`.
+    pub(crate) fn take_due(&mut self, now: u64) -> Vec<PendingObligation> {
+        let (due, remaining) = self.obligations.drain(..).partition(|o| o.is_due(now));
+        self.obligations = remaining;
+        due
+    }
+
+    /// Puts back an obligation taken with `take_due` that could not be settled.
+    pub(crate) fn restore(&mut self, obligation: PendingObligation) {
+        self.obligations.push(obligation);
+        self.obligations.sort_by_key(|o| o.id);
+    }
+
+    /// Accrues `action` on every obligation holding its asset that settles after it goes
+    /// ex, once per obligation, with `rules` deciding who it accrues to. Returns what
+    /// accrued by obligation id; [`crate::clearing::Clearing::apply_corporate_action`]
+    /// posts it.
+    pub(crate) fn apply_corporate_action(&mut self, action: &CorporateAction, rules: &AccrualRules) -> Result<Vec<(u64, Accrual)>, ClearingError> {
+        action.validate()?;
+        let mut applied = Vec::new();
+        for obligation in &mut self.obligations {
+            if obligation.settles_at <= action.ex_time || obligation.accruals.iter().any(|accrual| accrual.action_id == action.id) {
+                continue;
+            }
+            let quantity: f64 = obligation.assets.iter().filter(|info| info.asset.base == action.base).map(|info| info.quantity).sum();
+            if quantity <= 0.0 {
+                continue;
+            }
+            let accrual = Accrual::of(action, quantity, rules);
+            obligation.accruals.push(accrual.clone());
+            applied.push((obligation.id, accrual));
+        }
+        Ok(applied)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use model::model::{Asset, User};
+    use crate::clearing::Clearing;
+    use crate::corporate_actions::CorporateActionKind;
+    use crate::ledger::{LedgerAccount, HOUSE_ACCOUNT, ISSUER_ACCOUNT};
+
+    #[test]
+    fn test_calendar_skips_weekends_and_holidays() {
+        let mut calendar = SettlementCalendar::new();
+        let friday = 1;  // 1970-01-02
+        calendar.add_holiday(5);  // Tuesday 1970-01-06
+
+        assert!(!calendar.is_business_day(3));  // Sunday
+        let settles_at = calendar.settlement_time(friday * SECONDS_PER_DAY + 3600, 2);
+        assert_eq!(settles_at, 6 * SECONDS_PER_DAY);  // Mon, (Tue holiday), Wed
+
+        assert_eq!(SettlementCalendar::continuous().settlement_time(0, 2), 2 * SECONDS_PER_DAY);
+        assert_eq!(calendar.settlement_time(100, 0), 100);
+    }
+
+    #[test]
+    fn test_settle_due() {
+        let mut users = HashMap::from([
+            (UserId(1), Arc::new(User::new(1, "Alice", 100000.0))),
+            (UserId(2), Arc::new(User::new(2, "Bob", 10000.0))),
+        ]);
+        let assets = vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 30000.0)];
+
+        let mut pending = PendingObligations::new();
+        pending.book(UserId(1), AuctionId(1), BasketId(1), 30000.0, 100, assets.clone());
+        pending.book(UserId(1), AuctionId(1), BasketId(1), 20000.0, 500, assets.clone());
+        let failing = pending.book(UserId(2), AuctionId(1), BasketId(1), 30000.0, 100, assets);
+
+        let mut clearing = Clearing::new();
+        let result = clearing.settle_due(&mut pending, 200, &mut users);
+        assert_eq!(result.settled.len(), 1);
+        assert_eq!(result.failed, vec![failing]);
+        assert_eq!(pending.len(), 2);
+        assert_eq!(users.get(&UserId(1)).unwrap().balance, 70000.0);
+        assert_eq!(users.get(&UserId(2)).unwrap().balance, 10000.0);
+        assert_eq!(clearing.ledger.cash_balance(HOUSE_ACCOUNT), 30000.0);
+        assert!(clearing.ledger.is_balanced());
+    }
+
+    #[test]
+    fn test_partial_early_settlement() {
+        let mut users = HashMap::from([(UserId(1), Arc::new(User::new(1, "Alice", 100000.0)))]);
+
+        let mut pending = PendingObligations::new();
+        let id = pending.book(UserId(1), AuctionId(1), BasketId(1), 30000.0, 1000, vec![]);
+
+        let mut clearing = Clearing::new();
+        assert_eq!(clearing.settle_early(&mut pending, id, 10000.0, &mut users).unwrap(), 10000.0);
+        assert_eq!(pending.outstanding_for_user(UserId(1)), 20000.0);
+        assert_eq!(clearing.settle_early(&mut pending, id, 0.0, &mut users), Err(ClearingError::NonPositive("early settlement amount")));
+
+        let result = clearing.settle_due(&mut pending, 1000, &mut users);
+        assert_eq!(result.settled[0].settled_amount, 30000.0);
+        assert_eq!(users.get(&UserId(1)).unwrap().balance, 70000.0);
+        assert!(pending.is_empty());
+        let journaled: Vec<f64> = clearing.ledger.statement(&LedgerAccount::Cash(HOUSE_ACCOUNT)).iter().map(|line| line.credit).collect();
+        assert_eq!(journaled, vec![10000.0, 20000.0]);
+    }
+
+    #[test]
+    fn test_corporate_actions_accrue_between_auction_and_settlement() {
+        let mut users = HashMap::from([(UserId(1), Arc::new(User::new(1, "Alice", 100000.0)))]);
+        let assets = vec![
+            AssetInfo::new(Asset::new("ETH", "USD"), 10.0, 3000.0),
+            AssetInfo::new(Asset::new("BOND", "USD"), 20.0, 100.0),
+        ];
+        let mut pending = PendingObligations::new();
+        let id = pending.book(UserId(1), AuctionId(1), BasketId(1), 32000.0, 1000, assets);
+        pending.book(UserId(1), AuctionId(2), BasketId(2), 100.0, 400, vec![AssetInfo::new(Asset::new("ETH", "USD"), 1.0, 100.0)]);
+
+        let mut clearing = Clearing::new();
+        clearing.accruals = AccrualRules { airdrop: EntitledParty::Seller, ..AccrualRules::default() };
+        let staking = CorporateAction { id: 1, kind: CorporateActionKind::StakingReward, base: "ETH".to_string(), ex_time: 500, per_unit: Payout::Units { asset: Asset::new("ETH", "USD"), quantity: 0.01 } };
+        let airdrop = CorporateAction { id: 2, kind: CorporateActionKind::Airdrop, base: "ETH".to_string(), ex_time: 500, per_unit: Payout::Units { asset: Asset::new("ARB", "USD"), quantity: 5.0 } };
+        let coupon = CorporateAction { id: 3, kind: CorporateActionKind::Coupon, base: "BOND".to_string(), ex_time: 600, per_unit: Payout::Cash(2.5) };
+        // The second obligation settles before the actions go ex
+        assert_eq!(clearing.apply_corporate_action(&mut pending, &staking).unwrap().len(), 1);
+        assert!(clearing.apply_corporate_action(&mut pending, &staking).unwrap().is_empty());
+        assert_eq!(clearing.apply_corporate_action(&mut pending, &airdrop).unwrap()[0].1.entitled, EntitledParty::Seller);
+        clearing.apply_corporate_action(&mut pending, &coupon).unwrap();
+        let worthless = CorporateAction { id: 4, per_unit: Payout::Cash(0.0), ..coupon };
+        assert_eq!(clearing.apply_corporate_action(&mut pending, &worthless), Err(ClearingError::NonPositive("corporate action payout")));
+        // The seller holds the assets until delivery, so the issuers pay it
+        let units = |clearing: &Clearing, user_id, base: &str| clearing.ledger.balance(&LedgerAccount::Inventory(user_id, Asset::new(base, "USD")));
+        assert_eq!((clearing.ledger.cash_balance(HOUSE_ACCOUNT), units(&clearing, HOUSE_ACCOUNT, "ARB")), (50.0, 50.0));
+        assert_eq!(clearing.ledger.cash_balance(ISSUER_ACCOUNT), -50.0);
+
+        let result = clearing.settle_due(&mut pending, 1000, &mut users);
+        let settled = result.settled.iter().find(|obligation| obligation.id == id).unwrap();
+        assert_eq!((settled.winner_cash(), settled.accruals.len()), (50.0, 3));
+        assert!((settled.assets[0].quantity - 10.1).abs() < 1e-12);
+        // The airdrop stays with the seller and the coupon is netted against the payment
+        assert_eq!(settled.assets.len(), 2);
+        assert_eq!(users[&UserId(1)].balance, 100000.0 - 100.0 - (32000.0 - 50.0));
+        // The seller passed on what accrued to the winner and kept the airdrop
+        assert_eq!(clearing.ledger.cash_balance(HOUSE_ACCOUNT), 32000.0 + 100.0);
+        assert!((units(&clearing, UserId(1), "ETH") - 11.1).abs() < 1e-12);
+        assert_eq!((units(&clearing, UserId(1), "ARB"), units(&clearing, HOUSE_ACCOUNT, "ARB")), (0.0, 50.0));
+        assert!(clearing.ledger.is_balanced());
+    }
+}
@@ -0,0 +1,127 @@
+use thiserror::Error;
+use model::error::ModelError;
+use model::ids::{BasketId, UserId};
+use crate::config::ConfigError;
+use crate::hooks::HookRejection;
+
+
+/// Why escrow, the ledger, netting or clearing refused to move funds. Nothing is posted
+/// when one of these is returned.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ClearingError {
+    #[error("{0} must be a non-negative number")]
+    InvalidAmount(&'static str),
+    #[error("{0} must be positive")]
+    NonPositive(&'static str),
+    #[error("journal entry does not balance")]
+    Unbalanced,
+    #[error("journal entry {found} is out of sequence, expected {expected}")]
+    OutOfSequence { expected: u64, found: u64 },
+    #[error("no FX rate from {from} to {to}")]
+    NoFxRate { from: String, to: String },
+    /// Only `clear_with_report` converts currencies; the other clearing paths move base cash.
+    #[error("user {0} settles in a foreign currency")]
+    ForeignCurrency(UserId),
+    #[error("user {user_id} cannot afford {what}")]
+    InsufficientFunds { user_id: UserId, what: &'static str },
+    #[error("no escrowed funds for the bid of user {user_id} on basket {basket_id}")]
+    NotEscrowed { user_id: UserId, basket_id: BasketId },
+    #[error("settlement {0} was already used for a different auction")]
+    SettlementReused(u64),
+    #[error("settlement rejected by hook {}: {}", .0.hook, .0.reason)]
+    Rejected(HookRejection),
+    #[error("unknown settlement {0}")]
+    UnknownSettlement(u64),
+    #[error("user {user_id} has no allocation in settlement {settlement_id}")]
+    NotAllocated { settlement_id: u64, user_id: UserId },
+    #[error("unknown user {0}")]
+    UnknownUser(UserId),
+    #[error("unknown obligation {0}")]
+    UnknownObligation(u64),
+    #[error("malformed waterfall entry")]
+    MalformedWaterfall,
+    #[error("{0} overflows")]
+    Overflow(&'static str),
+    #[error("no fixing mark for {0} to cash settle at")]
+    NoMark(String),
+    #[error("user {0} has no registered wallet")]
+    NoWallet(UserId),
+    #[error("asset {0} has no registered token")]
+    NoToken(String),
+    /// The chain refused or dropped a transfer, or could not be reached.
+    #[error("on-chain settlement failed: {0}")]
+    Onchain(String),
+    #[error(transparent)]
+    Model(#[from] ModelError),
+}
+
+impl ClearingError {
+    /// A short, fixed name for the variant, e.g. for metric labels.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ClearingError::InvalidAmount(_) | ClearingError::NonPositive(_) | ClearingError::Overflow(_) => "invalid_amount",
+            ClearingError::Unbalanced | ClearingError::OutOfSequence { .. } | ClearingError::MalformedWaterfall => "ledger",
+            ClearingError::NoFxRate { .. } | ClearingError::ForeignCurrency(_) => "fx",
+            ClearingError::InsufficientFunds { .. } => "insufficient_funds",
+            ClearingError::NotEscrowed { .. } => "not_escrowed",
+            ClearingError::SettlementReused(_) => "settlement_reused",
+            ClearingError::Rejected(_) => "rejected_by_hook",
+            ClearingError::UnknownSettlement(_) | ClearingError::NotAllocated { .. }
+            | ClearingError::UnknownUser(_) | ClearingError::UnknownObligation(_) => "unknown",
+            ClearingError::NoMark(_) => "no_mark",
+            ClearingError::NoWallet(_) | ClearingError::NoToken(_) | ClearingError::Onchain(_) => "onchain",
+            ClearingError::Model(_) => "model",
+        }
+    }
+}
+
+
+/// Why a sealed-bid commitment or reveal was refused.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum SealedBidError {
+    #[error("commitments on basket {0} are closed")]
+    CommitmentsClosed(BasketId),
+    #[error("reveals on basket {0} are not open")]
+    NotRevealing(BasketId),
+    #[error("user {0} has already committed")]
+    AlreadyCommitted(UserId),
+    #[error("user {0} has no commitment")]
+    NoCommitment(UserId),
+    #[error("user {0} has already revealed")]
+    AlreadyRevealed(UserId),
+    #[error("the reveal of user {0} does not match its commitment")]
+    Mismatch(UserId),
+    #[error("a commitment is 64 hex digits")]
+    MalformedCommitment,
+    #[error(transparent)]
+    Clearing(#[from] ClearingError),
+}
+
+
+/// Why an auction could not be run to settlement.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum AuctionError {
+    #[error("invalid auction configuration: {0}")]
+    Config(#[from] ConfigError),
+    #[error("clearing failed: {0}")]
+    Clearing(#[from] ClearingError),
+    #[error(transparent)]
+    Model(#[from] ModelError),
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_convert_and_describe_themselves() {
+        let error: AuctionError = ClearingError::InsufficientFunds { user_id: UserId(7), what: "the payment" }.into();
+        assert_eq!(error.to_string(), "clearing failed: user 7 cannot afford the payment");
+        assert!(matches!(error, AuctionError::Clearing(ClearingError::InsufficientFunds { user_id: UserId(7), .. })));
+
+        let error: ClearingError = ModelError::InvalidAsset("BTC".to_string()).into();
+        assert_eq!(error.kind(), "model");
+        assert_eq!(error.to_string(), "invalid asset 'BTC', expected BASE/QUOTE");
+    }
+}
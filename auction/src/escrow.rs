@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use model::model::{Bid, User};
+use model::ids::{BasketId, UserId};
+use crate::error::ClearingError;
+use crate::margin::MarginEngine;
+
+
+/// Funds reserved against open bids. A lock does not move money out of the user's
+/// balance; it reduces the balance that is available for new bids until the lock is
+/// released (cancellation or loss) or consumed as payment at clearing.
+#[derive(Clone)]
+pub struct Escrow {
+    pub margin_rate: f64,
+    engine: Option<Arc<dyn MarginEngine>>,
+    locks: HashMap<(UserId, BasketId), f64>,
+}
+
+impl fmt::Debug for Escrow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Escrow")
+            .field("margin_rate", &self.margin_rate)
+            .field("engine", &self.engine.is_some())
+            .field("locks", &self.locks)
+            .finish()
+    }
+}
+
+impl Default for Escrow {
+    fn default() -> Self {
+        Escrow::new()
+    }
+}
+
+impl Escrow {
+    /// Escrow that reserves the full bid price.
+    pub fn new() -> Self {
+        Escrow {
+            margin_rate: 1.0,
+            engine: None,
+            locks: HashMap::new(),
+        }
+    }
+
+    /// Escrow that only reserves `margin_rate` of the bid price.
+    pub fn with_margin(margin_rate: f64) -> Self {
+        Escrow {
+            margin_rate: margin_rate.clamp(0.0, 1.0),
+            engine: None,
+            locks: HashMap::new(),
+        }
+    }
+
+    /// Escrow that reserves whatever initial margin `engine` requires for each bid.
+    pub fn with_engine(engine: Arc<dyn MarginEngine>) -> Self {
+        Escrow {
+            margin_rate: 1.0,
+            engine: Some(engine),
+            locks: HashMap::new(),
+        }
+    }
+
+    pub fn required_amount(&self, bid: &Bid) -> f64 {
+        match &self.engine {
+            Some(engine) => engine.initial_margin(bid),
+            None => bid.price * self.margin_rate,
+        }
+    }
+
+    /// Collateral missing from a bid's lock relative to its maintenance margin.
+    pub fn maintenance_shortfall(&self, bid: &Bid) -> f64 {
+        let maintenance = match &self.engine {
+            Some(engine) => engine.maintenance_margin(bid),
+            None => bid.price * self.margin_rate,
+        };
+        (maintenance - self.locked(bid.user.id, bid.basket_id)).max(0.0)
+    }
+
+    pub fn locked(&self, user_id: UserId, basket_id: BasketId) -> f64 {
+        *self.locks.get(&(user_id, basket_id)).unwrap_or(&0.0)
+    }
+
+    /// Every lock as (user, basket, amount), in no particular order.
+    pub fn locks(&self) -> impl Iterator<Item = (UserId, BasketId, f64)> + '_ {
+        self.locks.iter().map(|(&(user_id, basket_id), &amount)| (user_id, basket_id, amount))
+    }
+
+    pub fn total_locked(&self, user_id: UserId) -> f64 {
+        self.locks.iter()
+            .filter(|((id, _), _)| *id == user_id)
+            .map(|(_, amount)| amount)
+            .sum()
+    }
+
+    pub fn available_balance(&self, user: &User) -> f64 {
+        user.balance - self.total_locked(user.id)
+    }
+
+    /// Reserves funds for a bid entering the book.
+    pub fn lock(&mut self, bid: &Bid) -> Result<f64, ClearingError> {
+        let amount = self.required_amount(bid);
+        if amount <= 0.0 {
+            return Err(ClearingError::NonPositive("bid price"));
+        }
+        self.lock_amount(&bid.user, bid.basket_id, amount)
+    }
+
+    /// Reserves `amount` for the user's bids on a basket, on top of what they already lock,
+    /// e.g. when only some of those bids can win and the caller works out how much to hold.
+    pub fn lock_amount(&mut self, user: &User, basket_id: BasketId, amount: f64) -> Result<f64, ClearingError> {
+        if self.available_balance(user) < amount {
+            return Err(ClearingError::InsufficientFunds { user_id: user.id, what: "the escrow for its bid" });
+        }
+
+        *self.locks.entry((user.id, basket_id)).or_insert(0.0) += amount;
+        Ok(amount)
+    }
+
+    /// Releases the funds reserved for a cancelled bid.
+    pub fn release(&mut self, bid: &Bid) -> f64 {
+        let key = (bid.user.id, bid.basket_id);
+        let amount = self.required_amount(bid);
+
+        match self.locks.get_mut(&key) {
+            Some(locked) if *locked > amount => {
+                *locked -= amount;
+                amount
+            }
+            Some(_) => self.locks.remove(&key).unwrap_or(0.0),
+            None => 0.0,
+        }
+    }
+
+    /// Releases everything the user locks on a basket.
+    pub fn unlock(&mut self, user_id: UserId, basket_id: BasketId) -> f64 {
+        self.locks.remove(&(user_id, basket_id)).unwrap_or(0.0)
+    }
+
+    /// Releases every remaining lock on a basket, e.g. for the losing bidders once the
+    /// auction has cleared. Returns the released amount per user.
+    pub fn release_basket(&mut self, basket_id: BasketId) -> HashMap<UserId, f64> {
+        let keys: Vec<(UserId, BasketId)> = self.locks.keys()
+            .filter(|(_, id)| *id == basket_id)
+            .copied()
+            .collect();
+
+        let mut released = HashMap::new();
+        for key in keys {
+            if let Some(amount) = self.locks.remove(&key) {
+                *released.entry(key.0).or_insert(0.0) += amount;
+            }
+        }
+        released
+    }
+
+    /// Consumes the lock held for a winning bid so it can be converted into payment.
+    /// Any reservation beyond the bid price is implicitly released.
+    pub fn settle(&mut self, bid: &Bid) -> Result<f64, ClearingError> {
+        match self.locks.remove(&(bid.user.id, bid.basket_id)) {
+            Some(locked) => Ok(locked.min(bid.price)),
+            None => Err(ClearingError::NotEscrowed { user_id: bid.user.id, basket_id: bid.basket_id }),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::model::BidType;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_lock_reduces_available_balance() {
+        let user = Arc::new(User::new(1, "Alice", 100000.0));
+        let bid = Bid::new(user.clone(), 1, BidType::XOR, 60000.0, Some(1.0));
+
+        let mut escrow = Escrow::new();
+        assert_eq!(escrow.lock(&bid).unwrap(), 60000.0);
+        assert_eq!(escrow.locked(UserId(1), BasketId(1)), 60000.0);
+        assert_eq!(escrow.available_balance(&user), 40000.0);
+        assert_eq!(user.balance, 100000.0);  // Funds are reserved, not withdrawn
+    }
+
+    #[test]
+    fn test_lock_rejects_over_commitment() {
+        let user = Arc::new(User::new(1, "Alice", 100000.0));
+        let bid1 = Bid::new(user.clone(), 1, BidType::XOR, 60000.0, None);
+        let bid2 = Bid::new(user.clone(), 2, BidType::XOR, 50000.0, None);
+
+        let mut escrow = Escrow::new();
+        assert!(escrow.lock(&bid1).is_ok());
+        assert!(escrow.lock(&bid2).is_err());
+        assert_eq!(escrow.total_locked(UserId(1)), 60000.0);
+    }
+
+    struct FixedMargin {
+        initial: f64,
+        maintenance: f64,
+    }
+
+    impl MarginEngine for FixedMargin {
+        fn initial_margin(&self, _bid: &Bid) -> f64 {
+            self.initial
+        }
+
+        fn maintenance_margin(&self, _bid: &Bid) -> f64 {
+            self.maintenance
+        }
+    }
+
+    #[test]
+    fn test_margin_engine_lock() {
+        let user = Arc::new(User::new(1, "Alice", 100000.0));
+        let bid = Bid::new(user.clone(), 1, BidType::XOR, 10000.0, Some(1.0));
+
+        // A risky basket can require more than its price
+        let mut escrow = Escrow::with_engine(Arc::new(FixedMargin { initial: 25000.0, maintenance: 20000.0 }));
+        assert_eq!(escrow.lock(&bid).unwrap(), 25000.0);
+        assert_eq!(escrow.maintenance_shortfall(&bid), 0.0);
+
+        let tighter = Escrow { engine: Some(Arc::new(FixedMargin { initial: 40000.0, maintenance: 30000.0 })), ..escrow.clone() };
+        assert_eq!(tighter.maintenance_shortfall(&bid), 5000.0);
+        assert_eq!(escrow.settle(&bid).unwrap(), 10000.0);
+    }
+
+    #[test]
+    fn test_margin_lock() {
+        let user = Arc::new(User::new(1, "Alice", 10000.0));
+        let bid = Bid::new(user.clone(), 1, BidType::XOR, 60000.0, None);
+
+        let mut escrow = Escrow::with_margin(0.1);
+        assert_eq!(escrow.lock(&bid).unwrap(), 6000.0);
+        assert_eq!(escrow.available_balance(&user), 4000.0);
+    }
+
+    #[test]
+    fn test_release_and_settle() {
+        let alice = Arc::new(User::new(1, "Alice", 100000.0));
+        let bob = Arc::new(User::new(2, "Bob", 100000.0));
+        let bid1 = Bid::new(alice.clone(), 1, BidType::XOR, 60000.0, None);
+        let bid2 = Bid::new(bob.clone(), 1, BidType::XOR, 70000.0, None);
+
+        let mut escrow = Escrow::new();
+        escrow.lock(&bid1).unwrap();
+        escrow.lock(&bid2).unwrap();
+
+        assert_eq!(escrow.settle(&bid2).unwrap(), 70000.0);
+        assert!(escrow.settle(&bid2).is_err());
+
+        let released = escrow.release_basket(BasketId(1));
+        assert_eq!(released.get(&UserId(1)), Some(&60000.0));
+        assert_eq!(escrow.total_locked(UserId(1)), 0.0);
+
+        escrow.lock(&bid1).unwrap();
+        assert_eq!(escrow.release(&bid1), 60000.0);
+        assert_eq!(escrow.locked(UserId(1), BasketId(1)), 0.0);
+
+        escrow.lock_amount(&alice, BasketId(1), 30000.0).unwrap();
+        escrow.lock_amount(&alice, BasketId(1), 20000.0).unwrap();
+        assert_eq!(escrow.locks().collect::<Vec<_>>(), vec![(UserId(1), BasketId(1), 50000.0)]);
+        assert!(escrow.lock_amount(&alice, BasketId(2), 60000.0).is_err());
+        assert_eq!(escrow.unlock(UserId(1), BasketId(1)), 50000.0);
+        assert_eq!(escrow.total_locked(UserId(1)), 0.0);
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use model::ids::UserId;
+use model::money::{Code, Money};
+use crate::error::ClearingError;
+
+
+/// Source of mid FX rates, implemented by the market-data layer.
+pub trait FxRateSource: Send + Sync {
+    /// Units of `quote` per unit of `base`, if the pair is known.
+    fn mid_rate(&self, base: &str, quote: &str) -> Option<f64>;
+}
+
+
+/// Snapshot of FX rates, e.g. filled from index prices fetched by the pricer's data layer.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FxRates {
+    rates: HashMap<(String, String), f64>,
+}
+
+impl FxRates {
+    pub fn new() -> Self {
+        FxRates::default()
+    }
+
+    pub fn from_rates(rates: HashMap<(String, String), f64>) -> Self {
+        FxRates { rates }
+    }
+
+    pub fn set_rate(&mut self, base: &str, quote: &str, rate: f64) {
+        self.rates.insert((base.to_string(), quote.to_string()), rate);
+    }
+}
+
+impl FxRateSource for FxRates {
+    /// Falls back to the inverse of the opposite pair.
+    fn mid_rate(&self, base: &str, quote: &str) -> Option<f64> {
+        if base == quote {
+            return Some(1.0);
+        }
+        self.rates.get(&(base.to_string(), quote.to_string())).copied()
+            .or_else(|| self.rates.get(&(quote.to_string(), base.to_string())).map(|rate| 1.0 / rate))
+            .filter(|rate| rate.is_finite() && *rate > 0.0)
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FxConversion {
+    pub user_id: UserId,
+    pub from: String,
+    pub to: String,
+    /// Amount in `from`.
+    pub amount: f64,
+    pub mid_rate: f64,
+    /// Rate applied after the conversion spread.
+    pub rate: f64,
+    /// Amount in `to` charged to the user.
+    pub converted: f64,
+}
+impl FxConversion {
+    /// Spread earned by the house, in `to`.
+    pub fn spread_amount(&self) -> f64 {
+        self.converted - self.amount * self.mid_rate
+    }
+
+    /// What the user is charged, in `to`.
+    pub fn charged(&self) -> Money {
+        Money::new(self.converted, Code::new(&self.to))
+    }
+}
+
+
+/// Per-user settlement currencies. Auction prices are quoted in `base_currency`; users
+/// settling in another currency are charged the converted amount plus a per-pair spread.
+#[derive(Clone)]
+pub struct FxSettlement {
+    pub base_currency: String,
+    pub default_spread: f64,
+    source: Arc<dyn FxRateSource>,
+    spreads: HashMap<(String, String), f64>,
+    currencies: HashMap<UserId, String>,
+}
+
+impl fmt::Debug for FxSettlement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FxSettlement")
+            .field("base_currency", &self.base_currency)
+            .field("default_spread", &self.default_spread)
+            .field("spreads", &self.spreads)
+            .field("currencies", &self.currencies)
+            .finish()
+    }
+}
+
+impl FxSettlement {
+    pub fn new(base_currency: &str, source: Arc<dyn FxRateSource>) -> Self {
+        FxSettlement {
+            base_currency: base_currency.to_string(),
+            default_spread: 0.0,
+            source,
+            spreads: HashMap::new(),
+            currencies: HashMap::new(),
+        }
+    }
+
+    pub fn set_source(&mut self, source: Arc<dyn FxRateSource>) {
+        self.source = source;
+    }
+
+    /// Spread as a fraction of the mid rate, e.g. 0.002 for 20bp.
+    pub fn set_spread(&mut self, from: &str, to: &str, spread: f64) {
+        self.spreads.insert((from.to_string(), to.to_string()), spread.max(0.0));
+    }
+
+    pub fn spread(&self, from: &str, to: &str) -> f64 {
+        *self.spreads.get(&(from.to_string(), to.to_string())).unwrap_or(&self.default_spread)
+    }
+
+    pub fn set_settlement_currency(&mut self, user_id: UserId, currency: &str) {
+        self.currencies.insert(user_id, currency.to_string());
+    }
+
+    pub fn settlement_currency(&self, user_id: UserId) -> &str {
+        self.currencies.get(&user_id).unwrap_or(&self.base_currency)
+    }
+
+    /// The user's settlement currency, if it differs from the base currency.
+    pub fn foreign_currency(&self, user_id: UserId) -> Option<&str> {
+        Some(self.settlement_currency(user_id)).filter(|currency| *currency != self.base_currency)
+    }
+
+    /// An amount in the base currency, which auction prices and fees are quoted in.
+    pub fn base(&self, amount: f64) -> Money {
+        Money::new(amount, Code::new(&self.base_currency))
+    }
+
+    /// Converts at the mid rate, without a spread.
+    pub fn exchange(&self, money: Money, to: &str) -> Result<Money, ClearingError> {
+        let rate = self.source.mid_rate(money.code(), to)
+            .ok_or_else(|| ClearingError::NoFxRate { from: money.code().to_string(), to: to.to_string() })?;
+        Ok(money.convert(Code::new(to), rate))
+    }
+
+    /// Converts an amount in the base currency into what the user is charged in its
+    /// settlement currency.
+    pub fn convert(&self, user_id: UserId, money: Money) -> Result<FxConversion, ClearingError> {
+        let amount = money.amount_in(&Code::new(&self.base_currency))?;
+        let to = self.settlement_currency(user_id);
+        let mid_rate = self.source.mid_rate(&self.base_currency, to)
+            .ok_or_else(|| ClearingError::NoFxRate { from: self.base_currency.clone(), to: to.to_string() })?;
+        let rate = mid_rate * (1.0 + self.spread(&self.base_currency, to));
+
+        Ok(FxConversion {
+            user_id,
+            from: self.base_currency.clone(),
+            to: to.to_string(),
+            amount,
+            mid_rate,
+            rate,
+            converted: amount * rate,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rates_invert_and_identity() {
+        let mut rates = FxRates::new();
+        rates.set_rate("EUR", "USD", 1.25);
+
+        assert_eq!(rates.mid_rate("EUR", "USD"), Some(1.25));
+        assert_eq!(rates.mid_rate("USD", "EUR"), Some(0.8));
+        assert_eq!(rates.mid_rate("USD", "USD"), Some(1.0));
+        assert_eq!(rates.mid_rate("USD", "JPY"), None);
+    }
+
+    #[test]
+    fn test_convert_applies_pair_spread() {
+        let mut rates = FxRates::new();
+        rates.set_rate("USD", "EUR", 0.8);
+        let mut fx = FxSettlement::new("USD", Arc::new(rates));
+        fx.set_settlement_currency(UserId(1), "EUR");
+        fx.set_spread("USD", "EUR", 0.01);
+
+        let conversion = fx.convert(UserId(1), fx.base(1000.0)).unwrap();
+        assert_eq!(conversion.to, "EUR");
+        assert!((conversion.converted - 808.0).abs() < 1e-9);
+        assert!((conversion.spread_amount() - 8.0).abs() < 1e-9);
+        assert_eq!(conversion.charged().code(), "EUR");
+        assert!((fx.exchange(fx.base(1000.0), "EUR").unwrap().amount() - 800.0).abs() < 1e-9);
+        // Amounts must come in the base currency
+        assert!(matches!(fx.convert(UserId(1), conversion.charged()), Err(ClearingError::Model(_))));
+
+        // Users without a settlement currency pay in the base currency
+        assert_eq!(fx.foreign_currency(UserId(2)), None);
+        assert_eq!(fx.convert(UserId(2), fx.base(1000.0)).unwrap().converted, 1000.0);
+
+        fx.set_settlement_currency(UserId(3), "JPY");
+        assert!(fx.convert(UserId(3), fx.base(1000.0)).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::runtime::{Handle, RuntimeFlavor};
+use model::model::AssetInfo;
+use model::ids::{AuctionId, BasketId, UserId};
+use crate::report::SettlementReport;
+
+
+/// A single transfer about to be settled, as seen by pre-settlement hooks.
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    pub settlement_id: u64,
+    pub auction_id: AuctionId,
+    pub user_id: UserId,
+    pub basket_id: BasketId,
+    pub amount: f64,
+    pub assets: Vec<AssetInfo>,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HookDecision {
+    Approve,
+    Reject(String),
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HookRejection {
+    pub hook: String,
+    pub user_id: UserId,
+    pub reason: String,
+}
+
+
+/// Extension point for external custody, AML checks or notifications. A rejection from
+/// `pre_settlement` aborts the clearing before any balance is touched.
+pub trait SettlementHook: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn pre_settlement(&self, _transfer: &Transfer) -> HookDecision {
+        HookDecision::Approve
+    }
+
+    fn post_settlement(&self, _report: &SettlementReport) {}
+}
+
+
+pub type HookFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Asynchronous variant of `SettlementHook`, for integrations that call out over the network.
+/// Register it with clearing through [`BlockingHook`].
+pub trait AsyncSettlementHook: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn pre_settlement<'a>(&'a self, _transfer: &'a Transfer) -> HookFuture<'a, HookDecision> {
+        Box::pin(async { HookDecision::Approve })
+    }
+
+    fn post_settlement<'a>(&'a self, _report: &'a SettlementReport) -> HookFuture<'a, ()> {
+        Box::pin(async {})
+    }
+}
+
+
+/// Runs an `AsyncSettlementHook` on a tokio runtime while the clearing thread waits for
+/// it. Clearing called from a worker of a multi-threaded runtime hands the worker's other
+/// tasks off while it waits; called from anywhere else, the hook is driven from a thread
+/// of its own. Either way clearing may be called from async code.
+pub struct BlockingHook<H: AsyncSettlementHook> {
+    hook: H,
+    handle: Handle,
+}
+
+impl<H: AsyncSettlementHook> BlockingHook<H> {
+    /// Runs `hook` on the runtime behind `handle`.
+    ///
+    /// # Panics
+    ///
+    /// If `handle` is a current-thread runtime's, which only makes progress inside its
+    /// own `block_on` and so could be the very thread clearing blocks.
+    pub fn new(hook: H, handle: Handle) -> Self {
+        assert!(handle.runtime_flavor() != RuntimeFlavor::CurrentThread, "settlement hooks need a multi-threaded runtime");
+        BlockingHook { hook, handle }
+    }
+
+    fn wait<T: Send>(&self, future: HookFuture<'_, T>) -> T {
+        match Handle::try_current() {
+            Ok(current) if current.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                tokio::task::block_in_place(|| self.handle.block_on(future))
+            }
+            Ok(_) => std::thread::scope(|scope| {
+                scope.spawn(|| self.handle.block_on(future)).join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            }),
+            Err(_) => self.handle.block_on(future),
+        }
+    }
+}
+
+impl<H: AsyncSettlementHook> SettlementHook for BlockingHook<H> {
+    fn name(&self) -> &str {
+        self.hook.name()
+    }
+
+    fn pre_settlement(&self, transfer: &Transfer) -> HookDecision {
+        self.wait(self.hook.pre_settlement(transfer))
+    }
+
+    fn post_settlement(&self, report: &SettlementReport) {
+        self.wait(self.hook.post_settlement(report))
+    }
+}
+
+
+#[derive(Clone, Default)]
+pub struct SettlementHooks {
+    hooks: Vec<Arc<dyn SettlementHook>>,
+}
+
+impl fmt::Debug for SettlementHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.hooks.iter().map(|hook| hook.name())).finish()
+    }
+}
+
+impl SettlementHooks {
+    pub fn add(&mut self, hook: Arc<dyn SettlementHook>) {
+        self.hooks.push(hook);
+    }
+
+    pub fn len(&self) -> usize {
+        self.hooks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Runs every pre-settlement hook over every transfer, stopping at the first rejection.
+    pub fn approve(&self, transfers: &[Transfer]) -> Result<(), HookRejection> {
+        for transfer in transfers {
+            for hook in &self.hooks {
+                if let HookDecision::Reject(reason) = hook.pre_settlement(transfer) {
+                    return Err(HookRejection {
+                        hook: hook.name().to_string(),
+                        user_id: transfer.user_id,
+                        reason,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn notify(&self, report: &SettlementReport) {
+        for hook in &self.hooks {
+            hook.post_settlement(report);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    struct Blocklist(Vec<UserId>);
+
+    impl SettlementHook for Blocklist {
+        fn name(&self) -> &str {
+            "blocklist"
+        }
+
+        fn pre_settlement(&self, transfer: &Transfer) -> HookDecision {
+            if self.0.contains(&transfer.user_id) {
+                HookDecision::Reject(format!("user {} is blocked", transfer.user_id))
+            } else {
+                HookDecision::Approve
+            }
+        }
+    }
+
+    struct AsyncLimit(f64);
+
+    impl AsyncSettlementHook for AsyncLimit {
+        fn name(&self) -> &str {
+            "limit"
+        }
+
+        fn pre_settlement<'a>(&'a self, transfer: &'a Transfer) -> HookFuture<'a, HookDecision> {
+            Box::pin(async move {
+                // Needs the runtime's timer, as a network call would its I/O
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+                if transfer.amount > self.0 {
+                    HookDecision::Reject(String::from("over limit"))
+                } else {
+                    HookDecision::Approve
+                }
+            })
+        }
+    }
+
+    fn transfer(user_id: UserId, amount: f64) -> Transfer {
+        Transfer { settlement_id: 1, auction_id: AuctionId(1), user_id, basket_id: BasketId(1), amount, assets: vec![] }
+    }
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_multi_thread().worker_threads(1).enable_all().build().unwrap()
+    }
+
+    #[test]
+    fn test_first_rejection_wins() {
+        let runtime = runtime();
+        let mut hooks = SettlementHooks::default();
+        hooks.add(Arc::new(Blocklist(vec![UserId(2)])));
+        hooks.add(Arc::new(BlockingHook::new(AsyncLimit(1000.0), runtime.handle().clone())));
+
+        assert!(hooks.approve(&[transfer(UserId(1), 500.0)]).is_ok());
+
+        let rejection = hooks.approve(&[transfer(UserId(1), 500.0), transfer(UserId(2), 500.0)]).unwrap_err();
+        assert_eq!(rejection.hook, "blocklist");
+        assert_eq!(rejection.user_id, UserId(2));
+
+        let rejection = hooks.approve(&[transfer(UserId(1), 5000.0)]).unwrap_err();
+        assert_eq!(rejection.hook, "limit");
+        assert_eq!(format!("{:?}", hooks), "[\"blocklist\", \"limit\"]");
+    }
+
+    #[test]
+    fn test_blocking_hook_runs_from_inside_a_runtime() {
+        let runtime = runtime();
+        let mut hooks = SettlementHooks::default();
+        hooks.add(Arc::new(BlockingHook::new(AsyncLimit(1000.0), runtime.handle().clone())));
+        let hooks = Arc::new(hooks);
+
+        // From a task on the runtime's only worker, which must not stall the hook's timer
+        let on_worker = hooks.clone();
+        let approved = runtime.block_on(async move {
+            tokio::spawn(async move { on_worker.approve(&[transfer(UserId(1), 500.0)]).is_ok() }).await.unwrap()
+        });
+        assert!(approved);
+
+        // From a single-threaded runtime, e.g. a test's
+        let current = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        assert!(current.block_on(async { hooks.approve(&[transfer(UserId(1), 5000.0)]) }).is_err());
+    }
+
+    #[test]
+    fn test_post_settlement_notification() {
+        struct Recorder(Mutex<Vec<u64>>);
+        impl SettlementHook for Recorder {
+            fn name(&self) -> &str {
+                "recorder"
+            }
+            fn post_settlement(&self, report: &SettlementReport) {
+                self.0.lock().unwrap().push(report.metadata.settlement_id);
+            }
+        }
+
+        let recorder = Arc::new(Recorder(Mutex::new(Vec::new())));
+        let mut hooks = SettlementHooks::default();
+        hooks.add(recorder.clone());
+
+        let metadata = crate::report::AuctionMetadata::new(BasketId(1), "XOR");
+        let settlement_id = metadata.settlement_id;
+        hooks.notify(&SettlementReport::new(metadata, &[], &Default::default(), 0.0));
+
+        assert_eq!(*recorder.0.lock().unwrap(), vec![settlement_id]);
+    }
+}
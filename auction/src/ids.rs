@@ -0,0 +1,46 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use model::ids::AuctionId;
+
+static NEXT_AUCTION_ID: AtomicU64 = AtomicU64::new(1);
+static NEXT_SETTLEMENT_ID: AtomicU64 = AtomicU64::new(1);
+
+
+/// Process-wide unique auction id.
+pub fn next_auction_id() -> AuctionId {
+    AuctionId(NEXT_AUCTION_ID.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Process-wide unique settlement id. Retries of the same settlement must reuse the id
+/// they were first cleared with.
+pub fn next_settlement_id() -> u64 {
+    NEXT_SETTLEMENT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Makes later ids start above `auction_id` and `settlement_id`, e.g. after recovering
+/// auctions from storage.
+pub fn advance_past(auction_id: AuctionId, settlement_id: u64) {
+    NEXT_AUCTION_ID.fetch_max(auction_id.get() + 1, Ordering::Relaxed);
+    NEXT_SETTLEMENT_ID.fetch_max(settlement_id + 1, Ordering::Relaxed);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_are_unique() {
+        let first = next_auction_id();
+        let second = next_auction_id();
+        assert!(second > first);
+        assert_ne!(next_settlement_id(), next_settlement_id());
+    }
+
+    #[test]
+    fn test_advance_past_never_goes_back() {
+        advance_past(AuctionId(1_000_000), 2_000_000);
+        assert!(next_auction_id() > AuctionId(1_000_000));
+        advance_past(AuctionId(1), 1);
+        assert!(next_settlement_id() > 2_000_000);
+    }
+}
@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use model::model::{Asset, AssetInfo, User};
+use model::ids::UserId;
+use crate::fx::FxConversion;
+use crate::error::ClearingError;
+
+/// Account of the exchange itself, acting as seller of auctioned baskets and fee collector.
+pub const HOUSE_ACCOUNT: UserId = UserId(0);
+/// Cash account of the mutualised default fund.
+pub const DEFAULT_FUND_ACCOUNT: UserId = UserId(u64::MAX);
+/// Issuers paying corporate actions on assets the exchange holds; its balances go negative
+/// by what they have paid out.
+pub const ISSUER_ACCOUNT: UserId = UserId(u64::MAX - 1);
+/// Sealed-bid commitment deposits, held until their bids are revealed or forfeited.
+pub const SEALED_DEPOSIT_ACCOUNT: UserId = UserId(u64::MAX - 2);
+
+const EPSILON: f64 = 1e-9;
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LedgerAccount {
+    Cash(UserId),
+    Inventory(UserId, Asset),
+    /// Cash held in a currency other than the settlement base currency.
+    Currency(UserId, String),
+    /// Counterpart for money and assets entering or leaving the exchange.
+    External,
+    /// `External` for foreign-currency cash.
+    ExternalCurrency(String),
+}
+impl LedgerAccount {
+    /// Unit the account is denominated in. Debits and credits must balance per unit.
+    pub fn unit(&self) -> String {
+        match self {
+            LedgerAccount::Cash(_) | LedgerAccount::External => String::from("CASH"),
+            LedgerAccount::Inventory(_, asset) => format!("{}/{}", asset.base, asset.quote),
+            LedgerAccount::Currency(_, currency) | LedgerAccount::ExternalCurrency(currency) => currency.clone(),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Posting {
+    pub account: LedgerAccount,
+    pub debit: f64,
+    pub credit: f64,
+}
+impl Posting {
+    pub fn debit(account: LedgerAccount, amount: f64) -> Self {
+        Posting { account, debit: amount, credit: 0.0 }
+    }
+
+    pub fn credit(account: LedgerAccount, amount: f64) -> Self {
+        Posting { account, debit: 0.0, credit: amount }
+    }
+
+    /// Credits increase what the exchange holds on the account's behalf, debits decrease it.
+    pub fn net(&self) -> f64 {
+        self.credit - self.debit
+    }
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: u64,
+    pub memo: String,
+    pub postings: Vec<Posting>,
+}
+impl JournalEntry {
+    pub fn is_balanced(&self) -> bool {
+        let mut per_unit: HashMap<String, f64> = HashMap::new();
+        for posting in &self.postings {
+            *per_unit.entry(posting.account.unit()).or_insert(0.0) += posting.net();
+        }
+        per_unit.values().all(|total| total.abs() < EPSILON)
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatementLine {
+    pub entry_id: u64,
+    pub memo: String,
+    pub debit: f64,
+    pub credit: f64,
+    pub balance: f64,
+}
+
+
+/// Double-entry ledger underpinning every balance change made by clearing.
+#[derive(Debug, Clone, Default)]
+pub struct Ledger {
+    entries: Vec<JournalEntry>,
+    balances: HashMap<LedgerAccount, f64>,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Ledger::default()
+    }
+
+    /// Rebuilds a ledger by posting `entries` again, e.g. from a backup. Fails if any of
+    /// them does not balance or they are not numbered 1, 2, ...
+    pub fn from_entries(entries: Vec<JournalEntry>) -> Result<Self, ClearingError> {
+        let mut ledger = Ledger::new();
+        for entry in entries {
+            let expected = ledger.entries.len() as u64 + 1;
+            if entry.id != expected {
+                return Err(ClearingError::OutOfSequence { expected, found: entry.id });
+            }
+            ledger.post(&entry.memo, entry.postings)?;
+        }
+        Ok(ledger)
+    }
+
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    pub fn balance(&self, account: &LedgerAccount) -> f64 {
+        *self.balances.get(account).unwrap_or(&0.0)
+    }
+
+    /// Whether anything has ever been posted to `account`.
+    pub fn has_account(&self, account: &LedgerAccount) -> bool {
+        self.balances.contains_key(account)
+    }
+
+    pub fn cash_balance(&self, user_id: UserId) -> f64 {
+        self.balance(&LedgerAccount::Cash(user_id))
+    }
+
+    /// Records a journal entry, rejecting it unless debits equal credits in every unit.
+    pub fn post(&mut self, memo: &str, postings: Vec<Posting>) -> Result<u64, ClearingError> {
+        if postings.iter().any(|p| !p.debit.is_finite() || !p.credit.is_finite() || p.debit < 0.0 || p.credit < 0.0) {
+            return Err(ClearingError::InvalidAmount("posting amount"));
+        }
+
+        let entry = JournalEntry {
+            id: self.entries.len() as u64 + 1,
+            memo: memo.to_string(),
+            postings,
+        };
+        if !entry.is_balanced() {
+            return Err(ClearingError::Unbalanced);
+        }
+
+        for posting in &entry.postings {
+            *self.balances.entry(posting.account.clone()).or_insert(0.0) += posting.net();
+        }
+        let id = entry.id;
+        self.entries.push(entry);
+        Ok(id)
+    }
+
+    pub fn transfer_cash(&mut self, from: UserId, to: UserId, amount: f64, memo: &str) -> Result<u64, ClearingError> {
+        self.post(memo, vec![
+            Posting::debit(LedgerAccount::Cash(from), amount),
+            Posting::credit(LedgerAccount::Cash(to), amount),
+        ])
+    }
+
+    /// Brings the ledger's cash account in line with a user's balance, booking any
+    /// difference (e.g. an external deposit) against the external account.
+    pub fn sync_cash(&mut self, user: &User) -> Result<(), ClearingError> {
+        self.sync_account(LedgerAccount::Cash(user.id), user.balance)
+    }
+
+    /// Same as `sync_cash` for any account, with the external counterpart booked in the
+    /// account's unit.
+    pub fn sync_account(&mut self, account: LedgerAccount, balance: f64) -> Result<(), ClearingError> {
+        let difference = balance - self.balance(&account);
+        if difference.abs() < EPSILON {
+            return Ok(());
+        }
+
+        let external = match &account {
+            LedgerAccount::Currency(_, currency) => LedgerAccount::ExternalCurrency(currency.clone()),
+            _ => LedgerAccount::External,
+        };
+        let postings = if difference > 0.0 {
+            vec![Posting::debit(external, difference), Posting::credit(account, difference)]
+        } else {
+            vec![Posting::debit(account, -difference), Posting::credit(external, -difference)]
+        };
+        self.post("balance sync", postings).map(|_| ())
+    }
+
+    /// Books an FX conversion: the user pays `converted` in the foreign currency to
+    /// `house` and receives `amount` in base cash from it.
+    pub fn post_conversion(&mut self, house: UserId, conversion: &FxConversion, memo: &str) -> Result<u64, ClearingError> {
+        self.post(memo, vec![
+            Posting::debit(LedgerAccount::Currency(conversion.user_id, conversion.to.clone()), conversion.converted),
+            Posting::credit(LedgerAccount::Currency(house, conversion.to.clone()), conversion.converted),
+            Posting::debit(LedgerAccount::Cash(house), conversion.amount),
+            Posting::credit(LedgerAccount::Cash(conversion.user_id), conversion.amount),
+        ])
+    }
+
+    /// Postings unwinding `post_conversion` at the original rate.
+    pub fn conversion_reversal(house: UserId, conversion: &FxConversion) -> Vec<Posting> {
+        vec![
+            Posting::debit(LedgerAccount::Currency(house, conversion.to.clone()), conversion.converted),
+            Posting::credit(LedgerAccount::Currency(conversion.user_id, conversion.to.clone()), conversion.converted),
+            Posting::debit(LedgerAccount::Cash(conversion.user_id), conversion.amount),
+            Posting::credit(LedgerAccount::Cash(house), conversion.amount),
+        ]
+    }
+
+    /// Books the delivery of a purchase: buyer cash to seller, seller inventory to buyer.
+    pub fn post_trade(
+        &mut self,
+        buyer: UserId,
+        seller: UserId,
+        price: f64,
+        assets: &[AssetInfo],
+        memo: &str,
+    ) -> Result<u64, ClearingError> {
+        self.post(memo, Ledger::trade_postings(buyer, seller, price, assets))
+    }
+
+    /// Postings of `post_trade`, for booking a delivery within a larger entry.
+    pub fn trade_postings(buyer: UserId, seller: UserId, price: f64, assets: &[AssetInfo]) -> Vec<Posting> {
+        let mut postings = vec![
+            Posting::debit(LedgerAccount::Cash(buyer), price),
+            Posting::credit(LedgerAccount::Cash(seller), price),
+        ];
+        for asset_info in assets {
+            postings.push(Posting::debit(LedgerAccount::Inventory(seller, asset_info.asset.clone()), asset_info.quantity));
+            postings.push(Posting::credit(LedgerAccount::Inventory(buyer, asset_info.asset.clone()), asset_info.quantity));
+        }
+        postings
+    }
+
+    /// Every entry balances and, per unit, all account balances sum to zero.
+    pub fn is_balanced(&self) -> bool {
+        self.entries.iter().all(|entry| entry.is_balanced())
+            && self.trial_balance().values().all(|total| total.abs() < EPSILON)
+    }
+
+    pub fn trial_balance(&self) -> HashMap<String, f64> {
+        let mut totals: HashMap<String, f64> = HashMap::new();
+        for (account, balance) in &self.balances {
+            *totals.entry(account.unit()).or_insert(0.0) += balance;
+        }
+        totals
+    }
+
+    pub fn statement(&self, account: &LedgerAccount) -> Vec<StatementLine> {
+        let mut balance = 0.0;
+        let mut lines = Vec::new();
+
+        for entry in &self.entries {
+            for posting in entry.postings.iter().filter(|p| p.account == *account) {
+                balance += posting.net();
+                lines.push(StatementLine {
+                    entry_id: entry.id,
+                    memo: entry.memo.clone(),
+                    debit: posting.debit,
+                    credit: posting.credit,
+                    balance,
+                });
+            }
+        }
+        lines
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejects_unbalanced_entry() {
+        let mut ledger = Ledger::new();
+        let result = ledger.post("bad", vec![
+            Posting::debit(LedgerAccount::Cash(UserId(1)), 100.0),
+            Posting::credit(LedgerAccount::Cash(UserId(2)), 90.0),
+        ]);
+        assert_eq!(result, Err(ClearingError::Unbalanced));
+        assert!(ledger.entries().is_empty());
+
+        // Cash cannot balance against inventory
+        let result = ledger.post("bad", vec![
+            Posting::debit(LedgerAccount::Cash(UserId(1)), 1.0),
+            Posting::credit(LedgerAccount::Inventory(UserId(2), Asset::new("BTC", "USD")), 1.0),
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_post_trade_and_statement() {
+        let mut ledger = Ledger::new();
+        ledger.sync_cash(&User::new(1, "Alice", 100000.0)).unwrap();
+
+        let assets = vec![AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 60000.0)];
+        ledger.post_trade(UserId(1), HOUSE_ACCOUNT, 60000.0, &assets, "auction 1").unwrap();
+
+        assert_eq!(ledger.cash_balance(UserId(1)), 40000.0);
+        assert_eq!(ledger.cash_balance(HOUSE_ACCOUNT), 60000.0);
+        assert_eq!(ledger.balance(&LedgerAccount::Inventory(UserId(1), Asset::new("BTC", "USD"))), 2.0);
+        assert_eq!(ledger.balance(&LedgerAccount::Inventory(HOUSE_ACCOUNT, Asset::new("BTC", "USD"))), -2.0);
+        assert!(ledger.is_balanced());
+
+        let statement = ledger.statement(&LedgerAccount::Cash(UserId(1)));
+        assert_eq!(statement.len(), 2);
+        assert_eq!(statement[1].debit, 60000.0);
+        assert_eq!(statement[1].balance, 40000.0);
+
+        let rebuilt = Ledger::from_entries(ledger.entries().to_vec()).unwrap();
+        assert_eq!(rebuilt.cash_balance(UserId(1)), 40000.0);
+        assert_eq!(rebuilt.entries().len(), 2);
+        assert_eq!(Ledger::from_entries(ledger.entries()[1..].to_vec()).unwrap_err(), ClearingError::OutOfSequence { expected: 1, found: 2 });
+    }
+
+    #[test]
+    fn test_sync_cash_books_external_changes() {
+        let mut ledger = Ledger::new();
+        let mut user = User::new(1, "Alice", 1000.0);
+        ledger.sync_cash(&user).unwrap();
+
+        user.withdraw(400.0).unwrap();
+        ledger.sync_cash(&user).unwrap();
+        ledger.sync_cash(&user).unwrap();  // No-op when already in sync
+
+        assert_eq!(ledger.cash_balance(UserId(1)), 600.0);
+        assert_eq!(ledger.entries().len(), 2);
+        assert!(ledger.is_balanced());
+    }
+}
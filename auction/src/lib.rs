@@ -1,5 +1,30 @@
+pub mod error;
 pub mod wdp;
 pub mod simple_auction;
-mod cca_auction;
-mod vcg_auction;
-mod clearing;
\ No newline at end of file
+pub mod cca_auction;
+pub mod vcg_auction;
+pub mod core_pricing;
+pub mod analysis;
+pub mod multi_unit;
+pub mod scoring;
+pub mod clearing;
+pub mod escrow;
+pub mod margin;
+pub mod netting;
+pub mod report;
+pub mod deferred;
+pub mod corporate_actions;
+pub mod sealed;
+pub mod ledger;
+pub mod ids;
+pub mod hooks;
+pub mod observer;
+pub mod risk;
+pub mod fx;
+pub mod config;
+pub mod stats;
+pub mod sim;
+#[cfg(test)]
+mod properties;
+#[cfg(feature = "onchain")]
+pub mod onchain;
\ No newline at end of file
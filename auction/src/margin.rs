@@ -0,0 +1,28 @@
+use model::model::Bid;
+
+
+/// Collateral a bid must post. The escrow asks the engine instead of applying a flat
+/// margin rate, so baskets holding derivatives can be margined on their risk, e.g. by the
+/// pricer's portfolio margin.
+pub trait MarginEngine: Send + Sync {
+    /// Collateral locked when the bid enters the book.
+    fn initial_margin(&self, bid: &Bid) -> f64;
+
+    /// Collateral below which the bidder is called for more; the initial margin by default.
+    fn maintenance_margin(&self, bid: &Bid) -> f64 {
+        self.initial_margin(bid)
+    }
+}
+
+
+/// Fixed fraction of the bid price.
+#[derive(Debug, Clone, Copy)]
+pub struct RateMargin {
+    pub rate: f64,
+}
+
+impl MarginEngine for RateMargin {
+    fn initial_margin(&self, bid: &Bid) -> f64 {
+        bid.price * self.rate
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use model::model::{AssetInfo, Basket, Bid, BidType, User};
+use model::ids::{BasketId, UserId};
+use crate::wdp::WDPSolver;
+
+
+/// A bid for copies of a basket sold many times over: up to `units` copies at
+/// `unit_price` each. A bid may be filled in part.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnitBid {
+    pub user: Arc<User>,
+    pub basket_id: BasketId,
+    pub unit_price: f64,
+    pub units: u32,
+}
+
+impl UnitBid {
+    pub fn new(user: Arc<User>, basket_id: impl Into<BasketId>, unit_price: f64, units: u32) -> Self {
+        UnitBid { user, basket_id: basket_id.into(), unit_price, units }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.unit_price.is_finite() && self.unit_price > 0.0 && self.units > 0
+    }
+
+    /// The units the bidder can pay for at its own price, at most those it asked for.
+    pub fn affordable_units(&self) -> u32 {
+        let affordable = (self.user.balance / self.unit_price).floor();
+        if affordable >= f64::from(self.units) { self.units } else { affordable.max(0.0) as u32 }
+    }
+}
+
+/// What winners of a multi-unit auction pay. Under uniform pricing every unit goes at one
+/// price, the highest unit price left unfilled or the reserve if higher; under
+/// discriminatory pricing each winner pays its own bid for each unit it won.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PricingRule {
+    #[default]
+    Uniform,
+    Discriminatory,
+}
+
+/// The units one bid won and what they cost.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnitAward {
+    /// Index of the bid among those auctioned.
+    pub bid: usize,
+    pub user_id: UserId,
+    pub units: u32,
+    pub unit_price: f64,
+    pub payment: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultiUnitOutcome {
+    pub units_sold: u32,
+    /// The price of every unit under uniform pricing.
+    pub clearing_price: Option<f64>,
+    pub awards: Vec<UnitAward>,
+    /// The copies each winner receives, with the value it pays stored in `price` as the
+    /// other allocators do.
+    pub allocation: HashMap<UserId, Vec<AssetInfo>>,
+    pub welfare: f64,
+}
+
+impl MultiUnitOutcome {
+    pub fn revenue(&self) -> f64 {
+        self.awards.iter().map(|award| award.payment).sum()
+    }
+
+    /// The awards as bids at their payments, ready for clearing.
+    pub fn winning_bids(&self, bids: &[UnitBid]) -> Vec<Bid> {
+        self.awards.iter()
+            .map(|award| {
+                let bid = &bids[award.bid];
+                Bid::new(bid.user.clone(), bid.basket_id, BidType::XOR, award.payment, None)
+            })
+            .collect()
+    }
+}
+
+
+/// Sells `units` identical copies of one basket, e.g. ten of the same structured product,
+/// among bids stating how many copies they want and what they pay per copy.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MultiUnitAuction {
+    pub units: u32,
+    pub pricing: PricingRule,
+    /// No unit sells below this price.
+    pub reserve_price: f64,
+}
+
+impl MultiUnitAuction {
+    pub fn new(units: u32, pricing: PricingRule) -> Self {
+        MultiUnitAuction { units, pricing, reserve_price: 0.0 }
+    }
+
+    pub fn with_reserve(mut self, reserve_price: f64) -> Self {
+        self.reserve_price = reserve_price;
+        self
+    }
+
+    pub fn run(&self, bids: &[UnitBid], basket: &Basket) -> MultiUnitOutcome {
+        let (won, welfare) = WDPSolver::allocate_units(bids, self.units, self.reserve_price);
+        let clearing_price = match self.pricing {
+            PricingRule::Uniform => Some(self.highest_unfilled(bids, &won).max(self.reserve_price)),
+            PricingRule::Discriminatory => None,
+        };
+
+        let mut awards = Vec::new();
+        let mut allocation: HashMap<UserId, Vec<AssetInfo>> = HashMap::new();
+        for (index, units) in won {
+            let bid = &bids[index];
+            let unit_price = clearing_price.unwrap_or(bid.unit_price);
+            let payment = f64::from(units) * unit_price;
+            let assets = allocation.entry(bid.user.id).or_default();
+            for (info, share) in basket.assets.iter().zip(value_shares(basket)) {
+                let quantity = f64::from(units) * info.quantity;
+                match assets.iter_mut().find(|held| held.asset == info.asset) {
+                    Some(held) => {
+                        held.quantity += quantity;
+                        held.price += payment * share;
+                    }
+                    None => assets.push(AssetInfo::new(info.asset.clone(), quantity, payment * share)),
+                }
+            }
+            awards.push(UnitAward { bid: index, user_id: bid.user.id, units, unit_price, payment });
+        }
+        let units_sold = awards.iter().map(|award| award.units).sum();
+        tracing::debug!(units = self.units, units_sold, ?clearing_price, "multi-unit auction allocated");
+        MultiUnitOutcome { units_sold, clearing_price, awards, allocation, welfare }
+    }
+
+    /// The highest unit price of demand the supply could not fill, 0 if it filled all.
+    fn highest_unfilled(&self, bids: &[UnitBid], won: &[(usize, u32)]) -> f64 {
+        bids.iter().enumerate()
+            .filter(|(_, bid)| bid.is_valid() && bid.unit_price >= self.reserve_price)
+            .filter(|(index, bid)| {
+                let units = won.iter().find(|(won, _)| won == index).map_or(0, |(_, units)| *units);
+                bid.affordable_units() > units
+            })
+            .map(|(_, bid)| bid.unit_price)
+            .fold(0.0, f64::max)
+    }
+}
+
+/// Each asset's share of the basket's value, equal shares if it has none.
+fn value_shares(basket: &Basket) -> Vec<f64> {
+    let total: f64 = basket.assets.iter().map(|info| info.quantity * info.price).sum();
+    basket.assets.iter()
+        .map(|info| if total > 0.0 { info.quantity * info.price / total } else { 1.0 / basket.assets.len() as f64 })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::model::Asset;
+    use crate::clearing::Clearing;
+    use crate::report::AuctionMetadata;
+
+    fn setup() -> (Basket, Vec<UnitBid>) {
+        let basket = Basket {
+            id: BasketId(1),
+            assets: vec![
+                AssetInfo::new(Asset::new("BTC", "USD"), 0.01, 60_000.0),
+                AssetInfo::new(Asset::new("ETH", "USD"), 0.1, 2_000.0),
+            ],
+        };
+        let bids = vec![
+            UnitBid::new(Arc::new(User::new(1, "Alice", 100_000.0)), 1, 1_000.0, 4),
+            UnitBid::new(Arc::new(User::new(2, "Bob", 100_000.0)), 1, 900.0, 5),
+            UnitBid::new(Arc::new(User::new(3, "Carol", 100_000.0)), 1, 800.0, 3),
+            // Dave can only pay for one unit
+            UnitBid::new(Arc::new(User::new(4, "Dave", 1_000.0)), 1, 850.0, 2),
+            UnitBid::new(Arc::new(User::new(5, "Erin", 100_000.0)), 1, 700.0, 0),
+        ];
+        (basket, bids)
+    }
+
+    #[test]
+    fn test_units_go_to_the_highest_unit_prices() {
+        let (basket, bids) = setup();
+        let outcome = MultiUnitAuction::new(10, PricingRule::Discriminatory).run(&bids, &basket);
+        let won: Vec<(UserId, u32, f64)> = outcome.awards.iter().map(|award| (award.user_id, award.units, award.payment)).collect();
+        assert_eq!(won, [(UserId(1), 4, 4_000.0), (UserId(2), 5, 4_500.0), (UserId(4), 1, 850.0)]);
+        assert_eq!(outcome.units_sold, 10);
+        assert_eq!(outcome.clearing_price, None);
+        assert_eq!(outcome.welfare, outcome.revenue());
+
+        let alice = &outcome.allocation[&UserId(1)];
+        assert!((alice[0].quantity - 0.04).abs() < 1e-12);
+        assert!((alice[0].price + alice[1].price - 4_000.0).abs() < 1e-9);
+
+        let mut clearing = Clearing::new();
+        let cleared = clearing.clear_with_report(AuctionMetadata::new(basket.id, "MULTI_UNIT"), outcome.winning_bids(&bids), outcome.allocation.clone(), 0.0).unwrap();
+        assert_eq!(cleared.report.total_payments(), 9_350.0);
+        assert_eq!(cleared.users[&UserId(4)].balance, 150.0);
+    }
+
+    #[test]
+    fn test_uniform_pricing_charges_the_highest_unfilled_price() {
+        let (basket, bids) = setup();
+        let outcome = MultiUnitAuction::new(10, PricingRule::Uniform).run(&bids, &basket);
+        assert_eq!(outcome.clearing_price, Some(800.0));
+        assert!(outcome.awards.iter().all(|award| award.unit_price == 800.0));
+        assert_eq!(outcome.revenue(), 8_000.0);
+
+        // With supply to spare the reserve sets the price
+        let outcome = MultiUnitAuction::new(20, PricingRule::Uniform).with_reserve(750.0).run(&bids, &basket);
+        assert_eq!((outcome.units_sold, outcome.clearing_price), (13, Some(750.0)));
+        let outcome = MultiUnitAuction::new(20, PricingRule::Uniform).with_reserve(950.0).run(&bids, &basket);
+        assert_eq!((outcome.units_sold, outcome.revenue()), (4, 3_800.0));
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use model::model::Bid;
+use model::ids::{AuctionId, BasketId, UserId};
+use crate::error::ClearingError;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ObligationKind {
+    Purchase,
+    Sale,
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Obligation {
+    pub user_id: UserId,
+    pub auction_id: AuctionId,
+    pub basket_id: BasketId,
+    pub kind: ObligationKind,
+    pub amount: f64,
+}
+impl Obligation {
+    /// Cash flow from the user's perspective: purchases are debits, sales are credits.
+    pub fn signed_amount(&self) -> f64 {
+        match self.kind {
+            ObligationKind::Purchase => -self.amount,
+            ObligationKind::Sale => self.amount,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetPosition {
+    pub user_id: UserId,
+    pub cycle: u64,
+    pub gross_debit: f64,
+    pub gross_credit: f64,
+    pub net: f64,
+}
+impl NetPosition {
+    pub fn is_debit(&self) -> bool {
+        self.net < 0.0
+    }
+}
+
+
+/// Collects obligations from every auction settling in the same cycle and reduces them to
+/// one net debit or credit per account.
+#[derive(Debug, Clone, Default)]
+pub struct NettingEngine {
+    pub cycle: u64,
+    obligations: Vec<Obligation>,
+}
+
+impl NettingEngine {
+    pub fn new(cycle: u64) -> Self {
+        NettingEngine {
+            cycle,
+            obligations: Vec::new(),
+        }
+    }
+
+    pub fn obligations(&self) -> &[Obligation] {
+        &self.obligations
+    }
+
+    pub fn add_obligation(&mut self, obligation: Obligation) -> Result<(), ClearingError> {
+        if !obligation.amount.is_finite() || obligation.amount < 0.0 {
+            return Err(ClearingError::InvalidAmount("obligation amount"));
+        }
+        self.obligations.push(obligation);
+        Ok(())
+    }
+
+    /// Books a purchase obligation for every winning bid of an auction.
+    pub fn add_winning_bids(&mut self, auction_id: AuctionId, winning_bids: &[Bid]) -> Result<(), ClearingError> {
+        for bid in winning_bids {
+            self.add_obligation(Obligation {
+                user_id: bid.user.id,
+                auction_id,
+                basket_id: bid.basket_id,
+                kind: ObligationKind::Purchase,
+                amount: bid.price,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Books the proceeds owed to the seller of a basket.
+    pub fn add_sale(&mut self, seller_id: UserId, auction_id: AuctionId, basket_id: BasketId, proceeds: f64) -> Result<(), ClearingError> {
+        self.add_obligation(Obligation {
+            user_id: seller_id,
+            auction_id,
+            basket_id,
+            kind: ObligationKind::Sale,
+            amount: proceeds,
+        })
+    }
+
+    pub fn net_positions(&self) -> Vec<NetPosition> {
+        let mut positions: HashMap<UserId, NetPosition> = HashMap::new();
+
+        for obligation in &self.obligations {
+            let position = positions.entry(obligation.user_id).or_insert(NetPosition {
+                user_id: obligation.user_id,
+                cycle: self.cycle,
+                gross_debit: 0.0,
+                gross_credit: 0.0,
+                net: 0.0,
+            });
+            match obligation.kind {
+                ObligationKind::Purchase => position.gross_debit += obligation.amount,
+                ObligationKind::Sale => position.gross_credit += obligation.amount,
+            }
+            position.net += obligation.signed_amount();
+        }
+
+        let mut positions: Vec<NetPosition> = positions.into_values().collect();
+        positions.sort_by_key(|position| position.user_id);
+        positions
+    }
+
+    /// Nets the current cycle, then starts the next one with an empty obligation set.
+    pub fn close_cycle(&mut self) -> Vec<NetPosition> {
+        let positions = self.net_positions();
+        self.obligations.clear();
+        self.cycle += 1;
+        positions
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::model::{User, BidType};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_net_positions_across_auctions() {
+        let alice = Arc::new(User::new(1, "Alice", 100000.0));
+        let bob = Arc::new(User::new(2, "Bob", 100000.0));
+
+        let mut engine = NettingEngine::new(7);
+        engine.add_winning_bids(AuctionId(1), &[
+            Bid::new(alice.clone(), 10, BidType::XOR, 60000.0, None),
+            Bid::new(bob.clone(), 11, BidType::XOR, 20000.0, None),
+        ]).unwrap();
+        engine.add_winning_bids(AuctionId(2), &[Bid::new(alice.clone(), 12, BidType::XOR, 15000.0, None)]).unwrap();
+        engine.add_sale(UserId(1), AuctionId(3), BasketId(13), 50000.0).unwrap();  // Alice also sold a basket
+
+        let positions = engine.net_positions();
+        assert_eq!(positions.len(), 2);
+
+        let alice_position = &positions[0];
+        assert_eq!(alice_position.cycle, 7);
+        assert_eq!(alice_position.gross_debit, 75000.0);
+        assert_eq!(alice_position.gross_credit, 50000.0);
+        assert_eq!(alice_position.net, -25000.0);
+        assert!(alice_position.is_debit());
+
+        assert_eq!(positions[1].net, -20000.0);
+    }
+
+    #[test]
+    fn test_close_cycle_resets_obligations() {
+        let mut engine = NettingEngine::new(0);
+        engine.add_sale(UserId(1), AuctionId(1), BasketId(1), 1000.0).unwrap();
+
+        let positions = engine.close_cycle();
+        assert_eq!(positions.len(), 1);
+        assert!(!positions[0].is_debit());
+        assert_eq!(engine.cycle, 1);
+        assert!(engine.obligations().is_empty());
+    }
+
+    #[test]
+    fn test_rejects_invalid_amounts() {
+        let mut engine = NettingEngine::new(0);
+        assert!(engine.add_sale(UserId(1), AuctionId(1), BasketId(1), -5.0).is_err());
+        assert!(engine.add_sale(UserId(1), AuctionId(1), BasketId(1), f64::NAN).is_err());
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use model::model::AssetInfo;
+use model::ids::{AuctionId, BasketId, BidId, UserId};
+use crate::report::SettlementReport;
+
+
+/// Maps keyed by user id. Internally tagged enums cannot read integer map keys back from
+/// JSON, so the keys are parsed here.
+mod user_keyed {
+    use std::collections::HashMap;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde::de::Error;
+    use model::ids::UserId;
+
+    pub fn serialize<S: Serializer, V: Serialize>(map: &HashMap<UserId, V>, serializer: S) -> Result<S::Ok, S::Error> {
+        map.iter().map(|(user_id, value)| (user_id.to_string(), value)).collect::<HashMap<_, _>>().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, V: Deserialize<'de>>(deserializer: D) -> Result<HashMap<UserId, V>, D::Error> {
+        HashMap::<String, V>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(user_id, value)| user_id.parse().map(|user_id| (user_id, value)).map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+
+/// Where a bid stands, from its submission to the end of its auction. Filled, cancelled,
+/// expired and rejected bids do not change again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderState {
+    PendingNew,
+    Active,
+    Amended,
+    ProvisionalWinner,
+    Filled,
+    Cancelled,
+    Expired,
+    Rejected,
+}
+
+impl OrderState {
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, OrderState::Filled | OrderState::Cancelled | OrderState::Expired | OrderState::Rejected)
+    }
+}
+
+
+/// What happened in an auction, in the order it happens. Prices and demand are keyed by
+/// asset base, allocations and payments by user id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuctionEvent {
+    /// One CCA clock round: the prices bid at and the demand left over at them, and each
+    /// bidder's eligibility points after it.
+    Round {
+        basket_id: BasketId,
+        round: usize,
+        prices: HashMap<String, f64>,
+        excess_demand: HashMap<String, f64>,
+        active_bidders: usize,
+        #[serde(default, with = "user_keyed")]
+        eligibility: HashMap<UserId, f64>,
+    },
+    /// A bidder dropped out under the activity rule and may not bid in later rounds.
+    BidderEliminated {
+        basket_id: BasketId,
+        round: usize,
+        user_id: UserId,
+    },
+    /// The allocation the auction would settle if it ended now.
+    ProvisionalAllocation {
+        basket_id: BasketId,
+        #[serde(with = "user_keyed")]
+        allocation: HashMap<UserId, Vec<AssetInfo>>,
+    },
+    Winners {
+        basket_id: BasketId,
+        auction_id: AuctionId,
+        #[serde(with = "user_keyed")]
+        payments: HashMap<UserId, f64>,
+    },
+    Settled {
+        report: SettlementReport,
+    },
+    /// A bid moved to `state`, e.g. it was amended or lost the auction.
+    OrderUpdated {
+        basket_id: BasketId,
+        bid_id: BidId,
+        user_id: UserId,
+        state: OrderState,
+    },
+}
+
+impl AuctionEvent {
+    pub fn basket_id(&self) -> BasketId {
+        match self {
+            AuctionEvent::Round { basket_id, .. }
+            | AuctionEvent::BidderEliminated { basket_id, .. }
+            | AuctionEvent::ProvisionalAllocation { basket_id, .. }
+            | AuctionEvent::Winners { basket_id, .. }
+            | AuctionEvent::OrderUpdated { basket_id, .. } => *basket_id,
+            AuctionEvent::Settled { report } => report.metadata.basket_id,
+        }
+    }
+}
+
+
+/// Extension point for live feeds of an auction's progress. Observers are told as events
+/// happen and cannot influence the outcome.
+pub trait AuctionObserver: Send + Sync {
+    fn on_event(&self, event: &AuctionEvent);
+}
+
+
+#[derive(Clone, Default)]
+pub struct AuctionObservers {
+    observers: Vec<Arc<dyn AuctionObserver>>,
+}
+
+impl fmt::Debug for AuctionObservers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AuctionObservers({})", self.observers.len())
+    }
+}
+
+impl AuctionObservers {
+    pub fn add(&mut self, observer: Arc<dyn AuctionObserver>) {
+        self.observers.push(observer);
+    }
+
+    pub fn len(&self) -> usize {
+        self.observers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+}
+
+impl AuctionObserver for AuctionObservers {
+    fn on_event(&self, event: &AuctionEvent) {
+        for observer in &self.observers {
+            observer.on_event(event);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use crate::report::AuctionMetadata;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<BasketId>>);
+
+    impl AuctionObserver for Recorder {
+        fn on_event(&self, event: &AuctionEvent) {
+            self.0.lock().unwrap().push(event.basket_id());
+        }
+    }
+
+    #[test]
+    fn test_every_observer_sees_every_event() {
+        let first = Arc::new(Recorder::default());
+        let second = Arc::new(Recorder::default());
+        let mut observers = AuctionObservers::default();
+        observers.add(first.clone());
+        observers.add(second.clone());
+
+        observers.on_event(&AuctionEvent::ProvisionalAllocation { basket_id: BasketId(4), allocation: HashMap::new() });
+        let report = SettlementReport::new(AuctionMetadata::new(BasketId(5), "XOR"), &[], &HashMap::new(), 0.0);
+        observers.on_event(&AuctionEvent::Settled { report });
+        assert_eq!(*first.0.lock().unwrap(), vec![BasketId(4), BasketId(5)]);
+        assert_eq!(*second.0.lock().unwrap(), vec![BasketId(4), BasketId(5)]);
+        assert_eq!(format!("{:?}", observers), "AuctionObservers(2)");
+    }
+
+    #[test]
+    fn test_events_are_tagged_json() {
+        let event = AuctionEvent::Winners { basket_id: BasketId(1), auction_id: AuctionId(2), payments: HashMap::from([(UserId(3), 10.0)]) };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json, serde_json::json!({"event": "winners", "basket_id": 1, "auction_id": 2, "payments": {"3": 10.0}}));
+        let parsed: AuctionEvent = serde_json::from_value(json).unwrap();
+        assert!(matches!(parsed, AuctionEvent::Winners { auction_id: AuctionId(2), .. }));
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use ethers::prelude::*;
+use model::model::Asset;
+use model::ids::UserId;
+use crate::error::ClearingError;
+use crate::hooks::{AsyncSettlementHook, HookDecision, HookFuture, Transfer};
+use crate::ledger::{Ledger, LedgerAccount};
+use crate::report::SettlementReport;
+
+abigen!(
+    Disperse,
+    r#"[
+        function disperseToken(address token, address[] recipients, uint256[] values) external
+    ]"#
+);
+
+abigen!(
+    Erc20,
+    r#"[
+        function balanceOf(address owner) external view returns (uint256)
+    ]"#
+);
+
+
+#[derive(Debug, Clone, Copy)]
+pub struct TokenInfo {
+    pub address: Address,
+    pub decimals: u32,
+}
+
+
+/// Maps the exchange's assets and users onto tokens and wallets of one EVM chain.
+#[derive(Debug, Clone)]
+pub struct OnchainConfig {
+    pub tokens: HashMap<Asset, TokenInfo>,
+    pub wallets: HashMap<UserId, Address>,
+    /// Disperse-style contract used to batch all transfers of one token into one transaction.
+    pub disperse: Address,
+    pub confirmations: usize,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenBatch {
+    pub token: Address,
+    pub recipients: Vec<Address>,
+    pub amounts: Vec<U256>,
+}
+
+
+/// Transfers of a settlement that did not go through after clearing committed it to the
+/// ledger, kept until they are retried or reconciled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailedSettlement {
+    pub settlement_id: u64,
+    /// Batches not yet confirmed, starting with the one that failed. Empty when the
+    /// transfers could not be planned at all.
+    pub pending: Vec<TokenBatch>,
+    pub error: ClearingError,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconciliationBreak {
+    pub user_id: UserId,
+    pub asset: Asset,
+    pub ledger_quantity: f64,
+    pub onchain_quantity: f64,
+}
+
+
+pub fn to_base_units(quantity: f64, decimals: u32) -> Result<U256, ClearingError> {
+    if !quantity.is_finite() || quantity < 0.0 {
+        return Err(ClearingError::InvalidAmount("token quantity"));
+    }
+    let scaled = (quantity * 10f64.powi(decimals as i32)).round();
+    if scaled >= u128::MAX as f64 {
+        return Err(ClearingError::Overflow("token quantity"));
+    }
+    Ok(U256::from(scaled as u128))
+}
+
+pub fn from_base_units(amount: U256, decimals: u32) -> Result<f64, ClearingError> {
+    let amount: u128 = amount.try_into().map_err(|_| ClearingError::Overflow("token amount"))?;
+    Ok(amount as f64 / 10f64.powi(decimals as i32))
+}
+
+
+impl OnchainConfig {
+    /// Groups every allocated asset of a settlement into one batch per token.
+    pub fn plan_batches(&self, report: &SettlementReport) -> Result<Vec<TokenBatch>, ClearingError> {
+        let mut batches: Vec<TokenBatch> = Vec::new();
+
+        for settlement in &report.settlements {
+            let wallet = *self.wallets.get(&settlement.user_id).ok_or(ClearingError::NoWallet(settlement.user_id))?;
+            for allocated in &settlement.assets {
+                let asset = Asset::new(&allocated.base, &allocated.quote);
+                let token = self.tokens.get(&asset).ok_or_else(|| ClearingError::NoToken(format!("{}/{}", asset.base, asset.quote)))?;
+                let amount = to_base_units(allocated.quantity, token.decimals)?;
+
+                match batches.iter_mut().find(|batch| batch.token == token.address) {
+                    Some(batch) => {
+                        batch.recipients.push(wallet);
+                        batch.amounts.push(amount);
+                    }
+                    None => batches.push(TokenBatch {
+                        token: token.address,
+                        recipients: vec![wallet],
+                        amounts: vec![amount],
+                    }),
+                }
+            }
+        }
+        Ok(batches)
+    }
+}
+
+
+/// Settles cleared allocations as ERC-20 transfers once clearing has posted them to the
+/// ledger. Transfers that fail are kept as [`FailedSettlement`]s for
+/// [`OnchainSettlement::retry_failed`] or reconciliation rather than dropped.
+pub struct OnchainSettlement<M: Middleware> {
+    pub config: OnchainConfig,
+    client: Arc<M>,
+    submitted: Mutex<Vec<TxHash>>,
+    failures: Mutex<Vec<FailedSettlement>>,
+}
+
+impl<M: Middleware + 'static> OnchainSettlement<M> {
+    pub fn new(config: OnchainConfig, client: Arc<M>) -> Self {
+        OnchainSettlement {
+            config,
+            client,
+            submitted: Mutex::new(Vec::new()),
+            failures: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn submitted(&self) -> Vec<TxHash> {
+        self.submitted.lock().map(|hashes| hashes.clone()).unwrap_or_default()
+    }
+
+    /// Settlements whose transfers have not all gone through.
+    pub fn failures(&self) -> Vec<FailedSettlement> {
+        self.failures.lock().map(|failures| failures.clone()).unwrap_or_default()
+    }
+
+    /// Sends one disperse transaction per batch and waits for the configured confirmations.
+    pub async fn submit(&self, batches: Vec<TokenBatch>) -> Result<Vec<TxHash>, ClearingError> {
+        let (hashes, failed) = self.submit_until_failure(batches).await;
+        match failed {
+            Some((_, error)) => Err(error),
+            None => Ok(hashes),
+        }
+    }
+
+    /// Submits the pending batches of every failed settlement again, in order, keeping
+    /// those that still fail. Returns the hashes of the transactions confirmed.
+    pub async fn retry_failed(&self) -> Vec<TxHash> {
+        let failed = self.failures.lock().map(|mut failures| std::mem::take(&mut *failures)).unwrap_or_default();
+        let mut hashes = Vec::new();
+        for failure in failed {
+            if failure.pending.is_empty() {
+                // Nothing to send: only reconciliation can resolve it
+                self.record_failure(failure);
+                continue;
+            }
+            let (confirmed, failed) = self.submit_until_failure(failure.pending).await;
+            hashes.extend(confirmed);
+            if let Some((pending, error)) = failed {
+                self.record_failure(FailedSettlement { settlement_id: failure.settlement_id, pending, error });
+            }
+        }
+        hashes
+    }
+
+    /// Sends the batches in order until one fails, returning the hashes confirmed and,
+    /// on failure, the batches left from the failed one on with the error.
+    async fn submit_until_failure(&self, batches: Vec<TokenBatch>) -> (Vec<TxHash>, Option<(Vec<TokenBatch>, ClearingError)>) {
+        let disperse = Disperse::new(self.config.disperse, self.client.clone());
+        let mut hashes = Vec::new();
+        let mut failed = None;
+
+        for (index, batch) in batches.iter().enumerate() {
+            let call = disperse.disperse_token(batch.token, batch.recipients.clone(), batch.amounts.clone());
+            let confirmed = async {
+                let pending = call.send().await.map_err(|e| ClearingError::Onchain(e.to_string()))?;
+                let receipt = pending
+                    .confirmations(self.config.confirmations)
+                    .await
+                    .map_err(|e| ClearingError::Onchain(e.to_string()))?
+                    .ok_or_else(|| ClearingError::Onchain("transaction dropped from the mempool".to_string()))?;
+                if receipt.status != Some(U64::from(1)) {
+                    return Err(ClearingError::Onchain(format!("transaction {:?} reverted", receipt.transaction_hash)));
+                }
+                Ok(receipt.transaction_hash)
+            }.await;
+            match confirmed {
+                Ok(hash) => hashes.push(hash),
+                Err(error) => {
+                    failed = Some((batches[index..].to_vec(), error));
+                    break;
+                }
+            }
+        }
+
+        if let Ok(mut submitted) = self.submitted.lock() {
+            submitted.extend(hashes.iter().copied());
+        }
+        (hashes, failed)
+    }
+
+    fn record_failure(&self, failure: FailedSettlement) {
+        tracing::warn!(settlement_id = failure.settlement_id, pending = failure.pending.len(), error = %failure.error, "on-chain settlement failed");
+        if let Ok(mut failures) = self.failures.lock() {
+            failures.push(failure);
+        }
+    }
+
+    /// Compares every registered wallet's token balances with the ledger's inventory accounts.
+    pub async fn reconcile(&self, ledger: &Ledger, tolerance: f64) -> Result<Vec<ReconciliationBreak>, ClearingError> {
+        let mut breaks = Vec::new();
+
+        for (user_id, wallet) in &self.config.wallets {
+            for (asset, token) in &self.config.tokens {
+                let erc20 = Erc20::new(token.address, self.client.clone());
+                let balance = erc20.balance_of(*wallet).call().await.map_err(|e| ClearingError::Onchain(e.to_string()))?;
+
+                let onchain_quantity = from_base_units(balance, token.decimals)?;
+                let ledger_quantity = ledger.balance(&LedgerAccount::Inventory(*user_id, asset.clone()));
+                if (onchain_quantity - ledger_quantity).abs() > tolerance {
+                    breaks.push(ReconciliationBreak {
+                        user_id: *user_id,
+                        asset: asset.clone(),
+                        ledger_quantity,
+                        onchain_quantity,
+                    });
+                }
+            }
+        }
+        Ok(breaks)
+    }
+}
+
+impl<M: Middleware + 'static> AsyncSettlementHook for OnchainSettlement<M> {
+    fn name(&self) -> &str {
+        "onchain"
+    }
+
+    fn pre_settlement<'a>(&'a self, transfer: &'a Transfer) -> HookFuture<'a, HookDecision> {
+        Box::pin(async move {
+            if !self.config.wallets.contains_key(&transfer.user_id) {
+                return HookDecision::Reject(format!("user {} has no registered wallet", transfer.user_id));
+            }
+            match transfer.assets.iter().find(|a| !self.config.tokens.contains_key(&a.asset)) {
+                Some(missing) => HookDecision::Reject(format!(
+                    "asset {}/{} has no registered token", missing.asset.base, missing.asset.quote
+                )),
+                None => HookDecision::Approve,
+            }
+        })
+    }
+
+    fn post_settlement<'a>(&'a self, report: &'a SettlementReport) -> HookFuture<'a, ()> {
+        Box::pin(async move {
+            let settlement_id = report.metadata.settlement_id;
+            match self.config.plan_batches(report) {
+                Ok(batches) => {
+                    if let (_, Some((pending, error))) = self.submit_until_failure(batches).await {
+                        self.record_failure(FailedSettlement { settlement_id, pending, error });
+                    }
+                }
+                Err(error) => self.record_failure(FailedSettlement { settlement_id, pending: Vec::new(), error }),
+            }
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::report::{AuctionMetadata, SettlementReport};
+    use model::ids::BasketId;
+    use model::model::{AssetInfo, Bid, BidType, User};
+
+    fn config() -> OnchainConfig {
+        OnchainConfig {
+            tokens: HashMap::from([
+                (Asset::new("BTC", "USD"), TokenInfo { address: Address::from_low_u64_be(100), decimals: 8 }),
+                (Asset::new("ETH", "USD"), TokenInfo { address: Address::from_low_u64_be(200), decimals: 18 }),
+            ]),
+            wallets: HashMap::from([(UserId(1), Address::from_low_u64_be(1)), (UserId(2), Address::from_low_u64_be(2))]),
+            disperse: Address::from_low_u64_be(999),
+            confirmations: 1,
+        }
+    }
+
+    #[test]
+    fn test_base_unit_conversion() {
+        assert_eq!(to_base_units(1.5, 8).unwrap(), U256::from(150_000_000u64));
+        assert_eq!(from_base_units(U256::from(150_000_000u64), 8), Ok(1.5));
+        assert!(to_base_units(-1.0, 8).is_err());
+        // A balance past u128 is refused rather than panicking
+        assert_eq!(from_base_units(U256::MAX, 18), Err(ClearingError::Overflow("token amount")));
+    }
+
+    #[test]
+    fn test_plan_batches_groups_by_token() {
+        let alice = Arc::new(User::new(1, "Alice", 100000.0));
+        let bob = Arc::new(User::new(2, "Bob", 100000.0));
+        let bids = vec![
+            Bid::new(alice, 1, BidType::OR, 30000.0, Some(0.5)),
+            Bid::new(bob, 1, BidType::OR, 30000.0, Some(0.5)),
+        ];
+        let assets = vec![
+            AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 30000.0),
+            AssetInfo::new(Asset::new("ETH", "USD"), 2.5, 5000.0),
+        ];
+        let allocation = HashMap::from([(UserId(1), assets.clone()), (UserId(2), assets)]);
+        let report = SettlementReport::new(AuctionMetadata::new(BasketId(1), "OR"), &bids, &allocation, 0.0);
+
+        let batches = config().plan_batches(&report).unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].recipients.len(), 2);
+        assert_eq!(batches[0].amounts[0], U256::from(100_000_000u64));
+    }
+}
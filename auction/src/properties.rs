@@ -0,0 +1,113 @@
+//! Property tests of winner determination and clearing over random users, baskets and bids.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use proptest::prelude::*;
+use model::model::{Asset, AssetInfo, Basket, Bid, BidType, User};
+use model::ids::{BasketId, UserId};
+use model::helpers::{allocate_basket, can_fulfill};
+use crate::clearing::Clearing;
+use crate::ledger::HOUSE_ACCOUNT;
+use crate::report::AuctionMetadata;
+use crate::wdp::WDPSolver;
+
+const BASKET_ID: BasketId = BasketId(1);
+const EPSILON: f64 = 1e-6;
+
+
+fn users() -> impl Strategy<Value = Vec<Arc<User>>> {
+    prop::collection::vec(0.0..200_000.0f64, 1..5).prop_map(|balances| {
+        balances.into_iter().enumerate()
+            .map(|(index, balance)| Arc::new(User::new(index as u64 + 1, &format!("user{}", index + 1), balance)))
+            .collect()
+    })
+}
+
+/// One to three distinct assets.
+fn basket() -> impl Strategy<Value = Basket> {
+    prop::sample::subsequence(vec!["BTC", "ETH", "SOL"], 1..=3)
+        .prop_flat_map(|bases| {
+            let count = bases.len();
+            (Just(bases), prop::collection::vec((0.1..10.0f64, 1.0..50_000.0f64), count))
+        })
+        .prop_map(|(bases, sizes)| Basket {
+            id: BASKET_ID,
+            assets: bases.into_iter().zip(sizes)
+                .map(|(base, (quantity, price))| AssetInfo::new(Asset::new(base, "USD"), quantity, price))
+                .collect(),
+        })
+}
+
+/// Up to eight bids by `users`, a few of them for another basket or for a bad quantity.
+fn bids(users: Vec<Arc<User>>) -> impl Strategy<Value = Vec<Bid>> {
+    let bid = (
+        prop::sample::select(users),
+        prop::sample::select(vec![BASKET_ID, BASKET_ID, BASKET_ID, BasketId(2)]),
+        prop::sample::select(vec![BidType::XOR, BidType::OR]),
+        1.0..100_000.0f64,
+        prop::option::weighted(0.8, 0.05..=1.2f64),
+    );
+    prop::collection::vec(bid, 0..8).prop_map(|bids| {
+        bids.into_iter()
+            .map(|(user, basket_id, bid_type, price, quantity)| Bid::new(user, basket_id, bid_type, price, quantity))
+            .collect()
+    })
+}
+
+fn auction() -> impl Strategy<Value = (Basket, Vec<Bid>)> {
+    (basket(), users()).prop_flat_map(|(basket, users)| (Just(basket), bids(users)))
+}
+
+/// Units of each asset the bids take, which must stay within the basket's supply.
+fn demand(bids: &[&Bid], basket: &Basket) -> HashMap<String, f64> {
+    let mut demand = HashMap::new();
+    for bid in bids {
+        for asset in &basket.assets {
+            *demand.entry(asset.asset.base.clone()).or_insert(0.0) += bid.quantity.unwrap_or(1.0) * asset.quantity;
+        }
+    }
+    demand
+}
+
+fn within_supply(bids: &[&Bid], basket: &Basket) -> bool {
+    let demand = demand(bids, basket);
+    can_fulfill(bids, basket) && basket.assets.iter().all(|asset| demand.get(&asset.asset.base).is_none_or(|units| *units <= asset.quantity + EPSILON))
+}
+
+
+proptest! {
+    #[test]
+    fn allocation_never_exceeds_supply((basket, bids) in auction()) {
+        let (greedy, _) = WDPSolver::maximize_welfare_cca(&bids, &basket);
+        prop_assert!(within_supply(&greedy, &basket));
+        let (exact, _) = WDPSolver::branch_and_bound(&bids, &basket);
+        prop_assert!(within_supply(&exact, &basket));
+    }
+
+    #[test]
+    fn exact_welfare_is_at_least_greedy_welfare((basket, bids) in auction()) {
+        let (_, greedy) = WDPSolver::maximize_welfare_cca(&bids, &basket);
+        let (exact_bids, exact) = WDPSolver::branch_and_bound(&bids, &basket);
+        prop_assert!(exact + EPSILON >= greedy, "exact {} < greedy {}", exact, greedy);
+        prop_assert!((exact_bids.iter().map(|bid| bid.price).sum::<f64>() - exact).abs() < EPSILON);
+    }
+
+    #[test]
+    fn clearing_conserves_money((basket, bids) in auction(), fee_rate in 0.0..0.01f64) {
+        let (winners, _) = WDPSolver::maximize_welfare_cca(&bids, &basket);
+        let allocation = allocate_basket(&winners, &basket);
+        let winners: Vec<Bid> = winners.into_iter().cloned().collect();
+        let before: HashMap<UserId, f64> = winners.iter().map(|bid| (bid.user.id, bid.user.balance)).collect();
+
+        let mut clearing = Clearing::new();
+        match clearing.clear_with_report(AuctionMetadata::new(BasketId(1), "CCA"), winners, allocation, fee_rate) {
+            Ok(settlement) => {
+                let paid: f64 = before.iter().map(|(user_id, balance)| balance - settlement.users[user_id].balance).sum();
+                prop_assert!((paid - clearing.ledger.cash_balance(HOUSE_ACCOUNT)).abs() < EPSILON);
+                prop_assert!(settlement.users.values().all(|user| user.balance >= -EPSILON));
+                prop_assert!(clearing.ledger.is_balanced());
+            }
+            Err(_) => prop_assert!(clearing.ledger.entries().is_empty()),
+        }
+    }
+}
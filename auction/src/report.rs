@@ -0,0 +1,289 @@
+use std::collections::{BTreeMap, HashMap};
+use serde::{Serialize, Deserialize};
+use model::model::{Bid, AssetInfo};
+use model::ids::{AuctionId, BasketId, UserId};
+use crate::error::ClearingError;
+use crate::ids::{next_auction_id, next_settlement_id};
+use crate::sim::{Clock, SystemClock};
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuctionMetadata {
+    pub auction_id: AuctionId,
+    pub settlement_id: u64,
+    pub basket_id: BasketId,
+    pub mechanism: String,
+    pub timestamp: u64,
+}
+impl AuctionMetadata {
+    /// Metadata for a new auction with fresh auction and settlement ids.
+    pub fn new(basket_id: BasketId, mechanism: &str) -> Self {
+        AuctionMetadata::at(basket_id, mechanism, &SystemClock)
+    }
+
+    /// Metadata stamped with the time of `clock`.
+    pub fn at(basket_id: BasketId, mechanism: &str, clock: &dyn Clock) -> Self {
+        AuctionMetadata {
+            auction_id: next_auction_id(),
+            settlement_id: next_settlement_id(),
+            basket_id,
+            mechanism: mechanism.to_string(),
+            timestamp: clock.now_secs(),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AllocatedAsset {
+    pub base: String,
+    pub quote: String,
+    pub quantity: f64,
+    pub unit_price: f64,
+    pub value: f64,
+}
+impl AllocatedAsset {
+    /// The allocators store the allocated value in `AssetInfo::price`.
+    pub fn from_allocation(asset_info: &AssetInfo) -> Self {
+        let unit_price = if asset_info.quantity > 0.0 { asset_info.price / asset_info.quantity } else { 0.0 };
+        AllocatedAsset {
+            base: asset_info.asset.base.clone(),
+            quote: asset_info.asset.quote.clone(),
+            quantity: asset_info.quantity,
+            unit_price,
+            value: asset_info.price,
+        }
+    }
+}
+
+
+/// How the winners of an auction settle. Physical delivery transfers the allocated
+/// assets against the payments; cash settlement transfers no assets, and each winner
+/// instead pays the difference between its payment and what its allocation is worth at
+/// the fixing marks, or receives it when the marks are higher.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettlementMode {
+    #[default]
+    Physical,
+    Cash,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserSettlement {
+    pub user_id: UserId,
+    pub payment: f64,
+    pub fee: f64,
+    pub assets: Vec<AllocatedAsset>,
+    /// Under cash settlement, the allocation valued at the fixing marks.
+    #[serde(default)]
+    pub reference_value: Option<f64>,
+}
+impl UserSettlement {
+    /// Cash the user owes before fees: the payment, less the reference value under cash
+    /// settlement. Negative when the user is owed the difference.
+    pub fn cash_due(&self) -> f64 {
+        self.payment - self.reference_value.unwrap_or(0.0)
+    }
+
+    pub fn total_debit(&self) -> f64 {
+        self.cash_due() + self.fee
+    }
+}
+
+
+/// Full payment and allocation breakdown of a cleared auction, for reconciliation with
+/// external accounting systems.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SettlementReport {
+    pub metadata: AuctionMetadata,
+    pub fee_rate: f64,
+    pub settlements: Vec<UserSettlement>,
+    #[serde(default)]
+    pub mode: SettlementMode,
+    /// The marks, by base, cash settlement fixed the allocations at.
+    #[serde(default)]
+    pub fixing_marks: BTreeMap<String, f64>,
+}
+
+impl SettlementReport {
+    pub fn new(
+        metadata: AuctionMetadata,
+        winning_bids: &[Bid],
+        allocation: &HashMap<UserId, Vec<AssetInfo>>,
+        fee_rate: f64,
+    ) -> Self {
+        let mut settlements: Vec<UserSettlement> = Vec::new();
+
+        for bid in winning_bids {
+            match settlements.iter_mut().find(|s| s.user_id == bid.user.id) {
+                Some(settlement) => {
+                    settlement.payment += bid.price;
+                    settlement.fee += bid.price * fee_rate;
+                }
+                None => settlements.push(UserSettlement {
+                    user_id: bid.user.id,
+                    payment: bid.price,
+                    fee: bid.price * fee_rate,
+                    assets: allocation
+                        .get(&bid.user.id)
+                        .map(|assets| assets.iter().map(AllocatedAsset::from_allocation).collect())
+                        .unwrap_or_default(),
+                    reference_value: None,
+                }),
+            }
+        }
+        settlements.sort_by_key(|s| s.user_id);
+
+        SettlementReport {
+            metadata,
+            fee_rate,
+            settlements,
+            mode: SettlementMode::Physical,
+            fixing_marks: BTreeMap::new(),
+        }
+    }
+
+    /// The report cash settled at `marks`, the fixing price of each asset by base. Every
+    /// allocated asset needs a mark.
+    pub fn cash_settled(mut self, marks: &HashMap<String, f64>) -> Result<Self, ClearingError> {
+        let mut fixing_marks = BTreeMap::new();
+        for settlement in &mut self.settlements {
+            let mut reference_value = 0.0;
+            for asset in &settlement.assets {
+                let mark = *marks.get(&asset.base).ok_or_else(|| ClearingError::NoMark(asset.base.clone()))?;
+                reference_value += asset.quantity * mark;
+                fixing_marks.insert(asset.base.clone(), mark);
+            }
+            settlement.reference_value = Some(reference_value);
+        }
+        self.mode = SettlementMode::Cash;
+        self.fixing_marks = fixing_marks;
+        Ok(self)
+    }
+
+    pub fn total_payments(&self) -> f64 {
+        self.settlements.iter().map(|s| s.payment).sum()
+    }
+
+    pub fn total_fees(&self) -> f64 {
+        self.settlements.iter().map(|s| s.fee).sum()
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// One row per allocated asset; users without allocated assets get a single row with
+    /// empty asset columns.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "auction_id,settlement_id,basket_id,mechanism,timestamp,user_id,payment,fee,base,quote,quantity,unit_price,value\n"
+        );
+        let prefix = format!(
+            "{},{},{},{},{}",
+            self.metadata.auction_id,
+            self.metadata.settlement_id,
+            self.metadata.basket_id,
+            escape_csv(&self.metadata.mechanism),
+            self.metadata.timestamp
+        );
+
+        for settlement in &self.settlements {
+            let user = format!("{},{},{}", settlement.user_id, settlement.payment, settlement.fee);
+            if settlement.assets.is_empty() {
+                csv.push_str(&format!("{},{},,,,,\n", prefix, user));
+            }
+            for asset in &settlement.assets {
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{},{}\n",
+                    prefix,
+                    user,
+                    escape_csv(&asset.base),
+                    escape_csv(&asset.quote),
+                    asset.quantity,
+                    asset.unit_price,
+                    asset.value
+                ));
+            }
+        }
+        csv
+    }
+}
+
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::model::{Asset, BidType, User};
+    use std::sync::Arc;
+
+    fn sample_report() -> SettlementReport {
+        let user1 = Arc::new(User::new(1, "Alice", 100000.0));
+        let user2 = Arc::new(User::new(2, "Bob", 200000.0));
+
+        let bids = vec![
+            Bid::new(user2.clone(), 1, BidType::OR, 70000.0, Some(0.5)),
+            Bid::new(user1.clone(), 1, BidType::OR, 60000.0, Some(0.5)),
+        ];
+        let allocation = HashMap::from([
+            (UserId(1), vec![
+                AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 30000.0),
+                AssetInfo::new(Asset::new("ETH", "USD"), 2.5, 5000.0),
+            ]),
+            (UserId(2), vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 30000.0)]),
+        ]);
+        let metadata = AuctionMetadata {
+            auction_id: AuctionId(42),
+            settlement_id: 7,
+            basket_id: BasketId(1),
+            mechanism: String::from("OR"),
+            timestamp: 1_700_000_000,
+        };
+
+        SettlementReport::new(metadata, &bids, &allocation, 0.001)
+    }
+
+    #[test]
+    fn test_report_breakdown() {
+        let report = sample_report();
+
+        assert_eq!(report.settlements.len(), 2);
+        assert_eq!(report.settlements[0].user_id, UserId(1));  // Sorted by user
+        assert_eq!(report.settlements[0].assets.len(), 2);
+        assert_eq!(report.settlements[0].assets[1].unit_price, 2000.0);
+        assert_eq!(report.total_payments(), 130000.0);
+        assert!((report.total_fees() - 130.0).abs() < 1e-9);
+        assert!((report.settlements[1].total_debit() - 70070.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_report_json_round_trip() {
+        let report = sample_report();
+        let json = report.to_json().unwrap();
+        assert_eq!(SettlementReport::from_json(&json).unwrap(), report);
+    }
+
+    #[test]
+    fn test_report_csv() {
+        let csv = sample_report().to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        assert_eq!(lines.len(), 4);  // Header + 3 allocated assets
+        assert!(lines[0].starts_with("auction_id,settlement_id,basket_id"));
+        assert_eq!(lines[1], "42,7,1,OR,1700000000,1,60000,60,BTC,USD,1,30000,30000");
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use model::model::{Asset, AssetInfo};
+use model::ids::UserId;
+
+const EPSILON: f64 = 1e-9;
+
+
+/// Haircuts applied to non-cash collateral, as a fraction of market value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HaircutSchedule {
+    pub haircuts: HashMap<Asset, f64>,
+    pub default_haircut: f64,
+}
+
+impl Default for HaircutSchedule {
+    fn default() -> Self {
+        HaircutSchedule {
+            haircuts: HashMap::new(),
+            default_haircut: 0.5,
+        }
+    }
+}
+
+impl HaircutSchedule {
+    pub fn new(default_haircut: f64) -> Self {
+        HaircutSchedule {
+            haircuts: HashMap::new(),
+            default_haircut: default_haircut.clamp(0.0, 1.0),
+        }
+    }
+
+    pub fn set_haircut(&mut self, asset: Asset, haircut: f64) {
+        self.haircuts.insert(asset, haircut.clamp(0.0, 1.0));
+    }
+
+    pub fn haircut(&self, asset: &Asset) -> f64 {
+        *self.haircuts.get(asset).unwrap_or(&self.default_haircut)
+    }
+
+    /// Value of a holding after its haircut. Collateral is valued at quantity × unit price.
+    pub fn collateral_value(&self, holding: &AssetInfo) -> f64 {
+        holding.total_value() * (1.0 - self.haircut(&holding.asset))
+    }
+
+    pub fn total_collateral_value(&self, holdings: &[AssetInfo]) -> f64 {
+        holdings.iter().map(|holding| self.collateral_value(holding)).sum()
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum WaterfallStep {
+    DefaulterCash,
+    DefaulterCollateral,
+    DefaultFund,
+    WinnerHaircut,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WaterfallEntry {
+    pub step: WaterfallStep,
+    pub user_id: Option<UserId>,
+    pub asset: Option<Asset>,
+    pub quantity: f64,
+    pub amount: f64,
+}
+
+
+/// Auditable record of how a payment default was covered, layer by layer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WaterfallReport {
+    pub defaulter_id: UserId,
+    pub amount_due: f64,
+    pub entries: Vec<WaterfallEntry>,
+    pub uncovered: f64,
+}
+impl WaterfallReport {
+    pub fn covered_by(&self, step: WaterfallStep) -> f64 {
+        self.entries.iter().filter(|e| e.step == step).map(|e| e.amount).sum()
+    }
+
+    pub fn is_fully_covered(&self) -> bool {
+        self.uncovered < EPSILON
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+
+/// Default management: defaulter's cash → defaulter's collateral (after haircuts) →
+/// default fund → pro-rata haircut of the other winners. `default_fund` is the most the
+/// waterfall may still draw from the fund, which must also be funded to be drawn.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DefaultWaterfall {
+    pub haircuts: HaircutSchedule,
+    pub default_fund: f64,
+}
+
+impl DefaultWaterfall {
+    pub fn new(haircuts: HaircutSchedule, default_fund: f64) -> Self {
+        DefaultWaterfall {
+            haircuts,
+            default_fund,
+        }
+    }
+
+    /// Covers `amount_due` for a defaulting winner. The default fund is drawn only as far
+    /// as `fund_balance`, what is actually in it. `winner_surplus` holds what every
+    /// surviving winner gained from the auction; the residual loss is shared pro rata to
+    /// it, and no winner is charged more than its surplus.
+    pub fn run(
+        &mut self,
+        defaulter_id: UserId,
+        amount_due: f64,
+        defaulter_cash: f64,
+        collateral: &[AssetInfo],
+        fund_balance: f64,
+        winner_surplus: &HashMap<UserId, f64>,
+    ) -> WaterfallReport {
+        let mut remaining = amount_due;
+        let mut entries = Vec::new();
+
+        let cash = defaulter_cash.max(0.0).min(remaining);
+        if cash > 0.0 {
+            entries.push(WaterfallEntry { step: WaterfallStep::DefaulterCash, user_id: Some(defaulter_id), asset: None, quantity: 0.0, amount: cash });
+            remaining -= cash;
+        }
+
+        for holding in collateral {
+            if remaining < EPSILON {
+                break;
+            }
+            let value = self.haircuts.collateral_value(holding);
+            if value <= 0.0 {
+                continue;
+            }
+            let used = value.min(remaining);
+            entries.push(WaterfallEntry {
+                step: WaterfallStep::DefaulterCollateral,
+                user_id: Some(defaulter_id),
+                asset: Some(holding.asset.clone()),
+                quantity: holding.quantity * used / value,
+                amount: used,
+            });
+            remaining -= used;
+        }
+
+        let fund = self.default_fund.min(fund_balance.max(0.0)).min(remaining);
+        if fund > 0.0 {
+            entries.push(WaterfallEntry { step: WaterfallStep::DefaultFund, user_id: None, asset: None, quantity: 0.0, amount: fund });
+            self.default_fund -= fund;
+            remaining -= fund;
+        }
+
+        let total_surplus: f64 = winner_surplus.iter()
+            .filter(|(id, _)| **id != defaulter_id)
+            .map(|(_, surplus)| surplus.max(0.0))
+            .sum();
+        if remaining > EPSILON && total_surplus > 0.0 {
+            let mut winners: Vec<(&UserId, &f64)> = winner_surplus.iter().filter(|(id, _)| **id != defaulter_id).collect();
+            winners.sort_by_key(|(id, _)| **id);
+
+            // At most the total surplus, so each pro-rata share stays within its winner's
+            let loss = remaining.min(total_surplus);
+            for (user_id, surplus) in winners {
+                let share = loss * surplus.max(0.0) / total_surplus;
+                if share > 0.0 {
+                    entries.push(WaterfallEntry { step: WaterfallStep::WinnerHaircut, user_id: Some(*user_id), asset: None, quantity: 0.0, amount: share });
+                }
+            }
+            remaining -= loss;
+        }
+
+        WaterfallReport {
+            defaulter_id,
+            amount_due,
+            entries,
+            uncovered: remaining.max(0.0),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_haircut_valuation() {
+        let mut schedule = HaircutSchedule::new(0.5);
+        schedule.set_haircut(Asset::new("BTC", "USD"), 0.2);
+
+        let btc = AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 30000.0);
+        let doge = AssetInfo::new(Asset::new("DOGE", "USD"), 1000.0, 0.1);
+        assert_eq!(schedule.collateral_value(&btc), 24000.0);
+        assert_eq!(schedule.collateral_value(&doge), 50.0);
+        assert_eq!(schedule.total_collateral_value(&[btc, doge]), 24050.0);
+    }
+
+    #[test]
+    fn test_waterfall_layers_in_order() {
+        let mut schedule = HaircutSchedule::new(0.5);
+        schedule.set_haircut(Asset::new("BTC", "USD"), 0.2);
+        let mut waterfall = DefaultWaterfall::new(schedule, 10000.0);
+
+        let collateral = vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 25000.0)];
+        let surplus = HashMap::from([(UserId(2), 30000.0), (UserId(3), 10000.0)]);
+        let report = waterfall.run(UserId(1), 60000.0, 5000.0, &collateral, 50000.0, &surplus);
+
+        assert_eq!(report.covered_by(WaterfallStep::DefaulterCash), 5000.0);
+        assert_eq!(report.covered_by(WaterfallStep::DefaulterCollateral), 20000.0);
+        assert_eq!(report.covered_by(WaterfallStep::DefaultFund), 10000.0);
+        assert_eq!(report.covered_by(WaterfallStep::WinnerHaircut), 25000.0);
+        assert_eq!(report.entries.last().unwrap().amount, 6250.0);  // User 3 bears a quarter
+        assert!(report.is_fully_covered());
+        assert_eq!(waterfall.default_fund, 0.0);
+    }
+
+    #[test]
+    fn test_unfunded_fund_and_winner_surplus_bound_the_waterfall() {
+        let mut waterfall = DefaultWaterfall::new(HaircutSchedule::new(0.0), 10000.0);
+        let surplus = HashMap::from([(UserId(2), 3000.0), (UserId(3), 1000.0)]);
+
+        // Only 2,000 of the fund is funded, and the winners gained 4,000 between them
+        let report = waterfall.run(UserId(1), 20000.0, 0.0, &[], 2000.0, &surplus);
+        assert_eq!(report.covered_by(WaterfallStep::DefaultFund), 2000.0);
+        let charged: Vec<f64> = report.entries.iter().filter(|e| e.step == WaterfallStep::WinnerHaircut).map(|e| e.amount).collect();
+        assert_eq!(charged, vec![3000.0, 1000.0]);
+        assert_eq!(report.uncovered, 14000.0);
+        assert_eq!(waterfall.default_fund, 8000.0);
+    }
+
+    #[test]
+    fn test_waterfall_partial_collateral_and_uncovered() {
+        let mut waterfall = DefaultWaterfall::new(HaircutSchedule::new(0.0), 0.0);
+        let collateral = vec![AssetInfo::new(Asset::new("ETH", "USD"), 10.0, 2000.0)];
+
+        let report = waterfall.run(UserId(1), 5000.0, 0.0, &collateral, 0.0, &HashMap::new());
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].quantity, 2.5);  // Only the ETH needed is liquidated
+
+        let report = waterfall.run(UserId(1), 50000.0, 0.0, &collateral, 0.0, &HashMap::new());
+        assert_eq!(report.uncovered, 30000.0);
+        assert!(report.to_json().unwrap().contains("DefaulterCollateral"));
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use model::model::Bid;
+use model::ids::UserId;
+
+
+/// What the seller knows of a bidder besides its bid. `credit_score` runs from 0, certain
+/// to default, to 1, no credit risk; `settlement_days` is how long the bidder takes to
+/// pay after winning.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BidderProfile {
+    pub credit_score: f64,
+    pub settlement_days: f64,
+}
+
+impl Default for BidderProfile {
+    fn default() -> Self {
+        BidderProfile { credit_score: 1.0, settlement_days: 0.0 }
+    }
+}
+
+/// A bid's score with everything it was computed from, for the audit trail.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BidScore {
+    pub user_id: UserId,
+    pub price: f64,
+    pub credit_score: f64,
+    pub settlement_days: f64,
+    pub score: f64,
+}
+
+/// Ranks bids for winner determination, where the highest score wins instead of the
+/// highest price. Implementations must be deterministic so an audited score can be
+/// recomputed from its recorded inputs.
+pub trait ScoringRule: Send + Sync + fmt::Debug {
+    fn name(&self) -> &str;
+
+    fn score(&self, bid: &Bid, profile: &BidderProfile) -> f64;
+}
+
+/// Ranks by price alone, as the mechanisms do without a rule.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PriceScore;
+
+impl ScoringRule for PriceScore {
+    fn name(&self) -> &str {
+        "price"
+    }
+
+    fn score(&self, bid: &Bid, _: &BidderProfile) -> f64 {
+        bid.price
+    }
+}
+
+/// Price less the expected credit loss, `(1 - credit_score) * loss_given_default` of it,
+/// discounted at `daily_discount` per day the bidder takes to settle.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RiskAdjustedScore {
+    pub loss_given_default: f64,
+    pub daily_discount: f64,
+}
+
+impl Default for RiskAdjustedScore {
+    fn default() -> Self {
+        RiskAdjustedScore { loss_given_default: 0.6, daily_discount: 0.0002 }
+    }
+}
+
+impl ScoringRule for RiskAdjustedScore {
+    fn name(&self) -> &str {
+        "risk_adjusted"
+    }
+
+    fn score(&self, bid: &Bid, profile: &BidderProfile) -> f64 {
+        let default_risk = (1.0 - profile.credit_score.clamp(0.0, 1.0)) * self.loss_given_default;
+        bid.price * (1.0 - default_risk) / (1.0 + self.daily_discount * profile.settlement_days.max(0.0))
+    }
+}
+
+/// Scores `bids` under `rule`. Bidders without a profile get the default one.
+pub fn score_bids<'a>(bids: impl IntoIterator<Item = &'a Bid>, rule: &dyn ScoringRule, profiles: &HashMap<UserId, BidderProfile>) -> Vec<BidScore> {
+    bids.into_iter()
+        .map(|bid| {
+            let profile = profiles.get(&bid.user.id).copied().unwrap_or_default();
+            BidScore {
+                user_id: bid.user.id,
+                price: bid.price,
+                credit_score: profile.credit_score,
+                settlement_days: profile.settlement_days,
+                score: rule.score(bid, &profile),
+            }
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use model::model::{Asset, AssetInfo, Basket, BidType, User};
+    use model::ids::BasketId;
+    use crate::wdp::WDPSolver;
+
+    #[test]
+    fn test_risk_adjusted_scores_can_outrank_a_higher_price() {
+        let basket = Basket { id: BasketId(1), assets: vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60_000.0)] };
+        let bids = vec![
+            Bid::new(Arc::new(User::new(1, "Alice", 100_000.0)), 1, BidType::XOR, 62_000.0, None),
+            Bid::new(Arc::new(User::new(2, "Bob", 100_000.0)), 1, BidType::XOR, 61_000.0, None),
+        ];
+        let profiles = HashMap::from([(UserId(1), BidderProfile { credit_score: 0.9, settlement_days: 2.0 })]);
+
+        let (winner, scores) = WDPSolver::solve_xor_scored(&bids, &basket, &PriceScore, &profiles).unwrap();
+        assert_eq!(winner.user.id, UserId(1));
+        assert_eq!(scores[0].score, 62_000.0);
+
+        let rule = RiskAdjustedScore::default();
+        let (winner, scores) = WDPSolver::solve_xor_scored(&bids, &basket, &rule, &profiles).unwrap();
+        assert_eq!(winner.user.id, UserId(2));
+        assert_eq!((scores[0].credit_score, scores[0].settlement_days), (0.9, 2.0));
+        assert!((scores[0].score - 62_000.0 * 0.94 / 1.0004).abs() < 1e-6);
+        assert_eq!(scores[1], BidScore { user_id: UserId(2), price: 61_000.0, credit_score: 1.0, settlement_days: 0.0, score: 61_000.0 });
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use model::model::{Bid, BidType, User};
+use model::ids::{BasketId, UserId};
+use crate::clearing::Clearing;
+use crate::error::{ClearingError, SealedBidError};
+use crate::ledger::SEALED_DEPOSIT_ACCOUNT;
+
+
+/// SHA-256 of a bid, its bidder and basket, and a secret salt. Published while bids are
+/// sealed, it reveals nothing about the bid but binds the bidder to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Commitment([u8; 32]);
+
+impl fmt::Display for Commitment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+impl FromStr for Commitment {
+    type Err = SealedBidError;
+
+    fn from_str(s: &str) -> Result<Self, SealedBidError> {
+        if s.len() != 64 || !s.is_ascii() {
+            return Err(SealedBidError::MalformedCommitment);
+        }
+        let mut bytes = [0u8; 32];
+        for (byte, digits) in bytes.iter_mut().zip(s.as_bytes().chunks(2)) {
+            let digits = std::str::from_utf8(digits).map_err(|_| SealedBidError::MalformedCommitment)?;
+            *byte = u8::from_str_radix(digits, 16).map_err(|_| SealedBidError::MalformedCommitment)?;
+        }
+        Ok(Commitment(bytes))
+    }
+}
+
+impl Serialize for Commitment {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Commitment {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+
+/// A sealed bid opened: the bid and the salt it was committed with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SealedReveal {
+    pub bid_type: BidType,
+    pub price: f64,
+    #[serde(default)]
+    pub quantity: Option<f64>,
+    pub salt: String,
+}
+
+impl SealedReveal {
+    /// What `user_id` commits to before bidding this on `basket_id`. Bidders compute it
+    /// themselves; the exchange only ever sees it and, later, the reveal.
+    pub fn commitment(&self, user_id: UserId, basket_id: BasketId) -> Commitment {
+        let quantity = self.quantity.map(|quantity| quantity.to_string()).unwrap_or_default();
+        let preimage = format!("{}|{}|{:?}|{}|{}|{}", basket_id, user_id, self.bid_type, self.price, quantity, self.salt);
+        Commitment(hmac_sha256::Hash::hash(preimage.as_bytes()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SealedPhase {
+    Commit,
+    Reveal,
+    Closed,
+}
+
+/// The opened bids once reveals close, and the deposits of bidders who never revealed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SealedOutcome {
+    pub revealed: BTreeMap<UserId, SealedReveal>,
+    pub forfeited: BTreeMap<UserId, f64>,
+}
+
+impl SealedOutcome {
+    pub fn forfeited_total(&self) -> f64 {
+        self.forfeited.values().sum()
+    }
+
+    /// The revealed bids, ready for a mechanism. Bidders missing from `users` are left out.
+    pub fn bids(&self, basket_id: BasketId, users: &HashMap<UserId, Arc<User>>) -> Vec<Bid> {
+        self.revealed.iter()
+            .filter_map(|(user_id, reveal)| {
+                let user = users.get(user_id)?;
+                Some(Bid::new(user.clone(), basket_id, reveal.bid_type.clone(), reveal.price, reveal.quantity))
+            })
+            .collect()
+    }
+}
+
+
+/// Two-phase sealed bidding on one basket. While committing, bidders post only a
+/// [`Commitment`] and a deposit, so neither the auctioneer nor other bidders can see a
+/// bid early. Once commitments close, bidders reveal bid and salt; a reveal that matches
+/// returns the deposit, and the deposits of those who never reveal go to the seller.
+/// Deposits are held on the clearing ledger's `SEALED_DEPOSIT_ACCOUNT` in between.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedBidBook {
+    basket_id: BasketId,
+    deposit: f64,
+    phase: SealedPhase,
+    commitments: BTreeMap<UserId, Commitment>,
+    revealed: BTreeMap<UserId, SealedReveal>,
+}
+
+impl SealedBidBook {
+    /// A book taking commitments, each backed by `deposit`.
+    pub fn new(basket_id: BasketId, deposit: f64) -> Result<Self, ClearingError> {
+        if !deposit.is_finite() || deposit < 0.0 {
+            return Err(ClearingError::InvalidAmount("commitment deposit"));
+        }
+        Ok(SealedBidBook { basket_id, deposit, phase: SealedPhase::Commit, commitments: BTreeMap::new(), revealed: BTreeMap::new() })
+    }
+
+    pub fn basket_id(&self) -> BasketId {
+        self.basket_id
+    }
+
+    pub fn deposit(&self) -> f64 {
+        self.deposit
+    }
+
+    pub fn phase(&self) -> SealedPhase {
+        self.phase
+    }
+
+    pub fn commitments(&self) -> &BTreeMap<UserId, Commitment> {
+        &self.commitments
+    }
+
+    /// Takes the user's one commitment and moves the deposit from their balance into
+    /// the held deposits.
+    pub fn commit(
+        &mut self,
+        user_id: UserId,
+        commitment: Commitment,
+        users: &mut HashMap<UserId, Arc<User>>,
+        clearing: &mut Clearing,
+    ) -> Result<(), SealedBidError> {
+        if self.phase != SealedPhase::Commit {
+            return Err(SealedBidError::CommitmentsClosed(self.basket_id));
+        }
+        if self.commitments.contains_key(&user_id) {
+            return Err(SealedBidError::AlreadyCommitted(user_id));
+        }
+        let user = users.get_mut(&user_id).ok_or(ClearingError::UnknownUser(user_id))?;
+        if !clearing.current(user).can_afford(self.deposit) {
+            return Err(ClearingError::InsufficientFunds { user_id, what: "the commitment deposit" }.into());
+        }
+        clearing.open_account(user)?;
+        clearing.ledger.transfer_cash(user_id, SEALED_DEPOSIT_ACCOUNT, self.deposit, &format!("sealed-bid deposit on basket {}", self.basket_id))?;
+        Arc::make_mut(user).balance = clearing.ledger.cash_balance(user_id);
+        self.commitments.insert(user_id, commitment);
+        Ok(())
+    }
+
+    /// Ends the commit phase; from now on bidders reveal.
+    pub fn close_commitments(&mut self) {
+        if self.phase == SealedPhase::Commit {
+            self.phase = SealedPhase::Reveal;
+        }
+    }
+
+    /// Opens the user's bid if it hashes to their commitment, returning their deposit.
+    pub fn reveal(
+        &mut self,
+        user_id: UserId,
+        reveal: SealedReveal,
+        users: &mut HashMap<UserId, Arc<User>>,
+        clearing: &mut Clearing,
+    ) -> Result<(), SealedBidError> {
+        if self.phase != SealedPhase::Reveal {
+            return Err(SealedBidError::NotRevealing(self.basket_id));
+        }
+        let commitment = self.commitments.get(&user_id).ok_or(SealedBidError::NoCommitment(user_id))?;
+        if self.revealed.contains_key(&user_id) {
+            return Err(SealedBidError::AlreadyRevealed(user_id));
+        }
+        if reveal.commitment(user_id, self.basket_id) != *commitment {
+            return Err(SealedBidError::Mismatch(user_id));
+        }
+        let user = users.get_mut(&user_id).ok_or(ClearingError::UnknownUser(user_id))?;
+        clearing.ledger.transfer_cash(SEALED_DEPOSIT_ACCOUNT, user_id, self.deposit, &format!("sealed-bid deposit returned on basket {}", self.basket_id))?;
+        Arc::make_mut(user).balance = clearing.ledger.cash_balance(user_id);
+        self.revealed.insert(user_id, reveal);
+        Ok(())
+    }
+
+    /// Ends the reveal phase. Every commitment left unrevealed forfeits its deposit to
+    /// the clearing's seller; closing again returns the same outcome without paying twice.
+    pub fn close(&mut self, clearing: &mut Clearing) -> Result<SealedOutcome, ClearingError> {
+        let forfeited: BTreeMap<UserId, f64> = self.commitments.keys()
+            .filter(|user_id| !self.revealed.contains_key(user_id))
+            .map(|user_id| (*user_id, self.deposit))
+            .collect();
+        let outcome = SealedOutcome { revealed: self.revealed.clone(), forfeited };
+        if self.phase == SealedPhase::Closed {
+            return Ok(outcome);
+        }
+        if !outcome.forfeited.is_empty() {
+            let memo = format!("sealed-bid deposits forfeited on basket {}", self.basket_id);
+            clearing.ledger.transfer_cash(SEALED_DEPOSIT_ACCOUNT, clearing.seller_id, outcome.forfeited_total(), &memo)?;
+            tracing::warn!(basket_id = self.basket_id.get(), unrevealed = outcome.forfeited.len(), "sealed bids never revealed, deposits forfeited");
+        }
+        self.phase = SealedPhase::Closed;
+        Ok(outcome)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reveal(price: f64, salt: &str) -> SealedReveal {
+        SealedReveal { bid_type: BidType::XOR, price, quantity: None, salt: salt.to_string() }
+    }
+
+    #[test]
+    fn test_commitments_hide_and_bind_the_bid() {
+        let sealed = reveal(61_000.0, "pepper");
+        let commitment = sealed.commitment(UserId(1), BasketId(9));
+        assert_eq!(commitment.to_string().len(), 64);
+        assert_eq!(commitment.to_string().parse::<Commitment>().unwrap(), commitment);
+        assert_ne!(reveal(61_000.0, "salt").commitment(UserId(1), BasketId(9)), commitment);
+        assert_ne!(sealed.commitment(UserId(2), BasketId(9)), commitment);
+        assert_eq!("abc".parse::<Commitment>(), Err(SealedBidError::MalformedCommitment));
+        assert_eq!(serde_json::to_string(&commitment).unwrap(), format!("\"{}\"", commitment));
+    }
+
+    #[test]
+    fn test_unrevealed_commitments_forfeit_their_deposit() {
+        let basket = BasketId(9);
+        let mut users = HashMap::from([
+            (UserId(1), Arc::new(User::new(1, "Alice", 100_000.0))),
+            (UserId(2), Arc::new(User::new(2, "Bob", 100_000.0))),
+            (UserId(3), Arc::new(User::new(3, "Carol", 50.0))),
+        ]);
+        let mut clearing = Clearing::new();
+        let mut book = SealedBidBook::new(basket, 1_000.0).unwrap();
+        let (alice, bob) = (reveal(61_000.0, "a"), reveal(62_000.0, "b"));
+        book.commit(UserId(1), alice.commitment(UserId(1), basket), &mut users, &mut clearing).unwrap();
+        book.commit(UserId(2), bob.commitment(UserId(2), basket), &mut users, &mut clearing).unwrap();
+        assert!(matches!(book.commit(UserId(3), alice.commitment(UserId(3), basket), &mut users, &mut clearing), Err(SealedBidError::Clearing(ClearingError::InsufficientFunds { .. }))));
+        assert_eq!(book.reveal(UserId(1), alice.clone(), &mut users, &mut clearing), Err(SealedBidError::NotRevealing(basket)));
+        assert_eq!(users[&UserId(1)].balance, 99_000.0);
+
+        book.close_commitments();
+        assert_eq!(book.commit(UserId(3), alice.commitment(UserId(3), basket), &mut users, &mut clearing), Err(SealedBidError::CommitmentsClosed(basket)));
+        assert_eq!(book.reveal(UserId(1), reveal(60_000.0, "a"), &mut users, &mut clearing), Err(SealedBidError::Mismatch(UserId(1))));
+        book.reveal(UserId(1), alice, &mut users, &mut clearing).unwrap();
+        assert_eq!(users[&UserId(1)].balance, 100_000.0);
+
+        let outcome = book.close(&mut clearing).unwrap();
+        assert_eq!(book.phase(), SealedPhase::Closed);
+        assert_eq!(outcome.forfeited, BTreeMap::from([(UserId(2), 1_000.0)]));
+        assert_eq!(users[&UserId(2)].balance, 99_000.0);
+
+        // Bob's deposit goes to the seller, once, and the ledger agrees with the balances
+        assert_eq!(book.close(&mut clearing).unwrap(), outcome);
+        assert_eq!(clearing.ledger.cash_balance(clearing.seller_id), 1_000.0);
+        assert_eq!(clearing.ledger.cash_balance(SEALED_DEPOSIT_ACCOUNT), 0.0);
+        assert_eq!(clearing.ledger.cash_balance(UserId(1)), 100_000.0);
+        assert_eq!(clearing.ledger.cash_balance(UserId(2)), 99_000.0);
+        assert!(clearing.ledger.is_balanced());
+        let bids = outcome.bids(basket, &users);
+        assert_eq!(bids.len(), 1);
+        assert_eq!((bids[0].user.id, bids[0].price), (UserId(1), 61_000.0));
+    }
+}
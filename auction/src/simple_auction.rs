@@ -1,9 +1,30 @@
 use std::collections::HashMap;
 
+use serde::{Deserialize, Serialize};
+use crate::scoring::{BidScore, BidderProfile, ScoringRule};
+use crate::sim::SimRng;
 use crate::wdp::WDPSolver;
 use model::model::{Bid, Basket, AssetInfo};
+use model::ids::UserId;
+use model::error::ModelError;
 use model::helpers::{allocate_basket};
 
+type ScoredAward<'a> = (&'a Bid, HashMap<UserId, Vec<AssetInfo>>, Vec<BidScore>);
+type Allocation = HashMap<UserId, Vec<AssetInfo>>;
+
+
+/// One award of an XOR cascade: the share of the basket a bid won and what it pays, its
+/// price scaled to that share.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PartialFill {
+    pub user_id: UserId,
+    /// Fraction of the basket, from 0 to 1.
+    pub share: f64,
+    pub payment: f64,
+    /// Whether the bidder's balance rather than its bid limited the share.
+    pub budget_bound: bool,
+}
+
 
 pub struct XorAuction;
 
@@ -17,7 +38,7 @@ impl XorAuction {
     pub fn evaluate_partial_bids<'a>(
         bids: &'a [Bid],
         basket: &'a Basket
-    ) -> Option<(&'a Bid, HashMap<u64, Vec<AssetInfo>>)> {
+    ) -> Option<(&'a Bid, HashMap<UserId, Vec<AssetInfo>>)> {
         if let Some(highest_bid) = WDPSolver::solve_xor(bids, basket) {
             let allocation = allocate_basket(&[highest_bid], basket);
             Some((highest_bid, allocation))
@@ -25,6 +46,89 @@ impl XorAuction {
             None
         }
     }
+
+    /// Like [`XorAuction::evaluate_partial_bids`], with ties for the highest bid broken by `rng`.
+    pub fn evaluate_partial_bids_seeded<'a>(
+        bids: &'a [Bid],
+        basket: &'a Basket,
+        rng: &mut SimRng,
+    ) -> Option<(&'a Bid, HashMap<UserId, Vec<AssetInfo>>)> {
+        let highest_bid = WDPSolver::solve_xor_seeded(bids, basket, rng)?;
+        let allocation = allocate_basket(&[highest_bid], basket);
+        Some((highest_bid, allocation))
+    }
+
+    /// Like [`XorAuction::evaluate_partial_bids`], with the bid scoring highest under
+    /// `rule` winning. Also returns every valid bid's score.
+    pub fn evaluate_scored_bids<'a>(
+        bids: &'a [Bid],
+        basket: &'a Basket,
+        rule: &dyn ScoringRule,
+        profiles: &HashMap<UserId, BidderProfile>,
+    ) -> Option<ScoredAward<'a>> {
+        let (winner, scores) = WDPSolver::solve_xor_scored(bids, basket, rule, profiles)?;
+        let allocation = allocate_basket(&[winner], basket);
+        Some((winner, allocation, scores))
+    }
+
+    /// Like [`XorAuction::evaluate_partial_bids`], but a highest bidder that cannot pay its
+    /// bid plus `fee_rate` wins the share it can afford at its price, and the rest of the
+    /// share it bid for is offered down the bids by price in the same way until it is sold
+    /// or the bids run out. Ties go to the later bid and each bidder wins at most once, so
+    /// the cascade is deterministic. Returns the fills in cascade order.
+    pub fn evaluate_cascade<'a>(
+        bids: &'a [Bid],
+        basket: &'a Basket,
+        fee_rate: f64,
+    ) -> (Vec<(&'a Bid, PartialFill)>, Allocation) {
+        let mut candidates: Vec<(usize, &Bid)> = bids.iter().enumerate()
+            .filter(|(_, bid)| bid.basket_id == basket.id)
+            .filter(|(_, bid)| matches!(bid.validate(), Ok(()) | Err(ModelError::InsufficientBalance { .. })))
+            .collect();
+        candidates.sort_by(|(a_index, a), (b_index, b)| b.price.total_cmp(&a.price).then(b_index.cmp(a_index)));
+
+        let mut fills: Vec<(&Bid, PartialFill)> = Vec::new();
+        let mut allocation = HashMap::new();
+        let mut remaining = candidates.first().map_or(0.0, |(_, bid)| bid.quantity.unwrap_or(1.0));
+        for (_, bid) in candidates {
+            if remaining <= 1e-12 {
+                break;
+            }
+            if fills.iter().any(|(won, _)| won.user.id == bid.user.id) {
+                continue;
+            }
+            let wanted = bid.quantity.unwrap_or(1.0);
+            let share = wanted.min(remaining);
+            let price = bid.price * share / wanted;
+            let budget = affordable_payment(bid.user.balance, fee_rate);
+            let (share, payment, budget_bound) = if price <= budget {
+                (share, price, false)
+            } else {
+                (wanted * budget / bid.price, budget, true)
+            };
+            if payment <= 0.0 {
+                continue;
+            }
+            remaining -= share;
+            let assets = basket.assets.iter()
+                .map(|info| AssetInfo::new(info.asset.clone(), info.quantity * share, info.price * info.quantity * share))
+                .collect();
+            allocation.insert(bid.user.id, assets);
+            fills.push((bid, PartialFill { user_id: bid.user.id, share, payment, budget_bound }));
+        }
+        tracing::debug!(fills = fills.len(), unsold = remaining.max(0.0), "XOR cascade allocated");
+        (fills, allocation)
+    }
+}
+
+/// The most `balance` pays with `fee_rate` on top, rounded down so clearing never finds
+/// it a cent, or an ulp, short.
+fn affordable_payment(balance: f64, fee_rate: f64) -> f64 {
+    let mut payment = balance.max(0.0) / (1.0 + fee_rate);
+    while payment > 0.0 && payment * (1.0 + fee_rate) > balance {
+        payment = payment.next_down();
+    }
+    payment
 }
 
 
@@ -32,7 +136,7 @@ pub struct OrAuction;
 
 impl OrAuction {
     /// Evaluates bids for an OR auction, returning all valid bids.
-    pub fn evaluate_bids<'a>(bids: &'a [Bid], basket: &'a Basket) -> (Vec<&'a Bid>, HashMap<u64, Vec<AssetInfo>>) {
+    pub fn evaluate_bids<'a>(bids: &'a [Bid], basket: &'a Basket) -> (Vec<&'a Bid>, HashMap<UserId, Vec<AssetInfo>>) {
         WDPSolver::solve_or(bids, basket)
     }
 
@@ -40,7 +144,7 @@ impl OrAuction {
     pub fn evaluate_partial_bids<'a>(
         bids: &'a [Bid],
         basket: &'a Basket
-    ) -> (Vec<&'a Bid>, HashMap<u64, Vec<AssetInfo>>) {
+    ) -> (Vec<&'a Bid>, HashMap<UserId, Vec<AssetInfo>>) {
         WDPSolver::solve_or(bids, basket)
     }
 }
@@ -49,6 +153,7 @@ impl OrAuction {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use model::ids::BasketId;
     use model::model::{Bid, User, Basket, AssetInfo, Asset, BidType};
     use std::sync::Arc;
 
@@ -58,7 +163,7 @@ mod tests {
         let user2 = Arc::new(User::new(2, "Bob", 2000000.0));
 
         let basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![
                 AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0),
                 AssetInfo::new(Asset::new("ETH", "USD"), 5.0, 2000.0),
@@ -70,7 +175,48 @@ mod tests {
 
         let bids = [bid1, bid2];
         let highest_bid = XorAuction::evaluate_bids(&bids, &basket).unwrap();
-        assert_eq!(highest_bid.user.id, 2);  // Bob should win with the higher bid
+        assert_eq!(highest_bid.user.id, UserId(2));  // Bob should win with the higher bid
+    }
+
+    #[test]
+    fn test_xor_cascade_fills_what_the_winner_cannot_afford() {
+        let basket = Basket { id: BasketId(1), assets: vec![AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0)] };
+        let bids = [
+            Bid::new(Arc::new(User::new(1, "Alice", 33000.0)), 1, BidType::XOR, 66000.0, None),
+            Bid::new(Arc::new(User::new(2, "Bob", 1000000.0)), 1, BidType::XOR, 62000.0, None),
+            Bid::new(Arc::new(User::new(3, "Carol", 1000000.0)), 1, BidType::XOR, 61000.0, None),
+        ];
+
+        // Alice affords half at her price, Bob takes the other half at his
+        let (fills, allocation) = XorAuction::evaluate_cascade(&bids, &basket, 0.0);
+        let users: Vec<(UserId, bool)> = fills.iter().map(|(_, fill)| (fill.user_id, fill.budget_bound)).collect();
+        assert_eq!(users, vec![(UserId(1), true), (UserId(2), false)]);
+        assert!((fills[0].1.share - 0.5).abs() < 1e-12 && (fills[1].1.share - 0.5).abs() < 1e-12);
+        assert!((fills[0].1.payment - 33000.0).abs() < 1e-6 && (fills[1].1.payment - 31000.0).abs() < 1e-6);
+        assert!((allocation[&UserId(2)][0].quantity - 1.0).abs() < 1e-12);
+
+        // A fee leaves Alice less, never more than her balance covers
+        let (fills, _) = XorAuction::evaluate_cascade(&bids, &basket, 0.01);
+        assert!(fills[0].1.payment * 1.01 <= 33000.0);
+        assert_eq!(fills.len(), 2);
+
+        // A winner who can pay takes everything
+        let (fills, _) = XorAuction::evaluate_cascade(&bids[1..], &basket, 0.0);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].1, PartialFill { user_id: UserId(2), share: 1.0, payment: 62000.0, budget_bound: false });
+    }
+
+    #[test]
+    fn test_xor_ties_are_broken_by_seed() {
+        let basket = Basket { id: BasketId(1), assets: vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 30000.0)] };
+        let bids: Vec<Bid> = (1..=4)
+            .map(|id| Bid::new(Arc::new(User::new(id, "Tied", 100000.0)), 1, BidType::XOR, 50000.0, None))
+            .collect();
+
+        let winner = |seed| XorAuction::evaluate_partial_bids_seeded(&bids, &basket, &mut SimRng::new(seed)).unwrap().0.user.id;
+        assert_eq!(winner(3), winner(3));
+        let winners: std::collections::HashSet<UserId> = (0..32).map(winner).collect();
+        assert!(winners.len() > 1);
     }
 
     #[test]
@@ -79,7 +225,7 @@ mod tests {
         let user2 = Arc::new(User::new(2, "Bob", 2000000.0));
 
         let basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![
                 AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0),
                 AssetInfo::new(Asset::new("ETH", "USD"), 5.0, 2000.0),
@@ -93,7 +239,7 @@ mod tests {
         let (valid_bids, allocation) = OrAuction::evaluate_partial_bids(&bids, &basket);
 
         assert_eq!(valid_bids.len(), 2);  // Both bids should be valid
-        assert!(allocation.contains_key(&1));  // Check allocation for Alice
-        assert!(allocation.contains_key(&2));  // Check allocation for Bob
+        assert!(allocation.contains_key(&UserId(1)));  // Check allocation for Alice
+        assert!(allocation.contains_key(&UserId(2)));  // Check allocation for Bob
     }
 }
\ No newline at end of file
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use metrics::{Counter, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder, SharedString, Unit};
+
+/// Bids entered into an auction, by mechanism.
+pub const AUCTION_BIDS: &str = "combidex_auction_bids";
+/// Bids accepted by the exchange.
+pub const BIDS_SUBMITTED: &str = "combidex_bids_submitted_total";
+/// Bid submissions and cancellations refused by a rate limit, by limit and auction phase.
+pub const BIDS_RATE_LIMITED: &str = "combidex_bids_rate_limited_total";
+/// Bids refused by a pre-trade risk check, by check.
+pub const BIDS_RISK_REJECTED: &str = "combidex_bids_risk_rejected_total";
+/// Clock rounds a CCA ran before demand cleared or the rounds ran out.
+pub const CCA_ROUNDS: &str = "combidex_cca_rounds";
+/// Time to solve a winner determination problem, by solver.
+pub const WDP_SOLVE_SECONDS: &str = "combidex_wdp_solve_seconds";
+/// Settlements clearing refused, by reason.
+pub const CLEARING_FAILURES: &str = "combidex_clearing_failures_total";
+/// Time to price one option by FFT.
+pub const FFT_PRICING_SECONDS: &str = "combidex_fft_pricing_seconds";
+/// Market data requests that failed after retries, by kind of error.
+pub const FETCH_ERRORS: &str = "combidex_fetch_errors_total";
+/// Events delivered to the message broker, by topic.
+pub const EVENTS_PUBLISHED: &str = "combidex_events_published_total";
+/// Deliveries to the message broker retried after a failure.
+pub const EVENTS_PUBLISH_RETRIES: &str = "combidex_events_publish_retries_total";
+/// Events given up on after every delivery attempt failed, by topic.
+pub const EVENTS_PUBLISH_FAILED: &str = "combidex_events_publish_failed_total";
+
+
+/// `name{label="value",...}`, the Prometheus spelling of a key.
+fn series(key: &Key) -> String {
+    let labels: Vec<String> = key.labels().map(|label| format!("{}=\"{}\"", label.key(), label.value())).collect();
+    if labels.is_empty() {
+        key.name().to_string()
+    } else {
+        format!("{}{{{}}}", key.name(), labels.join(","))
+    }
+}
+
+/// Count, sum and maximum of a histogram's samples.
+#[derive(Debug, Default)]
+struct Summary(Mutex<(u64, f64, f64)>);
+
+impl HistogramFn for Summary {
+    fn record(&self, value: f64) {
+        let mut summary = self.0.lock().unwrap();
+        summary.0 += 1;
+        summary.1 += value;
+        summary.2 = summary.2.max(value);
+    }
+}
+
+
+/// A recorder keeping every metric in memory, for embedding the crates without a
+/// Prometheus exporter. Install it with [`metrics::set_global_recorder`] or
+/// [`metrics::with_local_recorder`] and read it back with [`MemoryRecorder::counter`],
+/// [`MemoryRecorder::histogram`] or [`MemoryRecorder::render`].
+#[derive(Debug, Default)]
+pub struct MemoryRecorder {
+    counters: Mutex<BTreeMap<String, Arc<AtomicU64>>>,
+    gauges: Mutex<BTreeMap<String, Arc<AtomicU64>>>,
+    histograms: Mutex<BTreeMap<String, Arc<Summary>>>,
+}
+
+impl MemoryRecorder {
+    pub fn new() -> Self {
+        MemoryRecorder::default()
+    }
+
+    /// Value of the counter `series`, e.g. `combidex_clearing_failures_total{reason="..."}`.
+    pub fn counter(&self, series: &str) -> u64 {
+        self.counters.lock().unwrap().get(series).map_or(0, |counter| counter.load(Ordering::Relaxed))
+    }
+
+    pub fn gauge(&self, series: &str) -> Option<f64> {
+        self.gauges.lock().unwrap().get(series).map(|gauge| f64::from_bits(gauge.load(Ordering::Relaxed)))
+    }
+
+    /// Sample count and sum of the histogram `series`.
+    pub fn histogram(&self, series: &str) -> Option<(u64, f64)> {
+        self.histograms.lock().unwrap().get(series).map(|summary| {
+            let summary = summary.0.lock().unwrap();
+            (summary.0, summary.1)
+        })
+    }
+
+    /// Everything recorded, in the Prometheus text format. Histograms are reported as
+    /// `_count`, `_sum` and `_max` series.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        for (series, counter) in self.counters.lock().unwrap().iter() {
+            let _ = writeln!(output, "{} {}", series, counter.load(Ordering::Relaxed));
+        }
+        for (series, gauge) in self.gauges.lock().unwrap().iter() {
+            let _ = writeln!(output, "{} {}", series, f64::from_bits(gauge.load(Ordering::Relaxed)));
+        }
+        for (series, summary) in self.histograms.lock().unwrap().iter() {
+            let (count, sum, max) = *summary.0.lock().unwrap();
+            let (name, labels) = series.find('{').map_or((series.as_str(), ""), |at| series.split_at(at));
+            let _ = writeln!(output, "{}_count{} {}\n{}_sum{} {}\n{}_max{} {}", name, labels, count, name, labels, sum, name, labels, max);
+        }
+        output
+    }
+}
+
+impl Recorder for MemoryRecorder {
+    fn describe_counter(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn describe_gauge(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn describe_histogram(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+        Counter::from_arc(self.counters.lock().unwrap().entry(series(key)).or_default().clone())
+    }
+
+    fn register_gauge(&self, key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+        Gauge::from_arc(self.gauges.lock().unwrap().entry(series(key)).or_default().clone())
+    }
+
+    fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+        Histogram::from_arc(self.histograms.lock().unwrap().entry(series(key)).or_default().clone())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use model::ids::BasketId;
+    use model::model::{Asset, AssetInfo, Basket, Bid, BidType, User};
+    use crate::cca_auction::CombiClockAuction;
+    use crate::clearing::Clearing;
+    use crate::observer::AuctionObservers;
+    use crate::report::AuctionMetadata;
+
+    #[test]
+    fn test_auctions_are_measured() {
+        let recorder = MemoryRecorder::new();
+        metrics::with_local_recorder(&recorder, || {
+            let basket = Basket { id: BasketId(1), assets: vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 30000.0)] };
+            let bids = vec![
+                Bid::new(Arc::new(User::new(1, "Alice", 100000.0)), 1, BidType::XOR, 60000.0, Some(1.0)),
+                Bid::new(Arc::new(User::new(2, "Bob", 100000.0)), 1, BidType::XOR, 40000.0, Some(1.0)),
+            ];
+            CombiClockAuction::observed_outcome(&bids, &basket, HashMap::from([("BTC", 30000.0)]), 0.1, 5, &AuctionObservers::default());
+
+            let poor = Bid::new(Arc::new(User::new(3, "Carol", 10.0)), 1, BidType::XOR, 500.0, None);
+            assert!(Clearing::new().clear_winning_bids(AuctionMetadata::new(BasketId(1), "XOR"), vec![poor], HashMap::new()).is_err());
+        });
+
+        let (runs, rounds) = recorder.histogram(CCA_ROUNDS).unwrap();
+        assert_eq!(runs, 1);
+        assert!(rounds >= 1.0);
+        assert!(recorder.histogram(&format!("{}{{solver=\"cca\"}}", WDP_SOLVE_SECONDS)).is_some());
+        assert_eq!(recorder.counter(&format!("{}{{reason=\"insufficient_funds\"}}", CLEARING_FAILURES)), 1);
+        assert!(recorder.render().contains(&format!("{}_count 1", CCA_ROUNDS)));
+    }
+}
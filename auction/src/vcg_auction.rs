@@ -2,9 +2,15 @@ use std::collections::HashMap;
 use std::sync::Arc;
 use crate::wdp::WDPSolver;
 use crate::clearing::Clearing;
+use crate::error::AuctionError;
+use crate::report::AuctionMetadata;
 use model::model::{Bid, Basket, AssetInfo, User};
+use model::ids::UserId;
 use model::helpers::{allocate_basket};
 
+/// Winning bids, their allocation and VCG payments, then the users as settled by clearing.
+type ClearedAuction = (Vec<Bid>, HashMap<UserId, Vec<AssetInfo>>, HashMap<UserId, f64>, HashMap<UserId, Arc<User>>);
+
 
 
 pub struct VCGAuction;
@@ -16,8 +22,8 @@ impl VCGAuction {
         basket: &'a Basket,
         winning_bids: &[&'a Bid],
         total_welfare: f64
-    ) -> HashMap<u64, f64> {
-        let mut payments: HashMap<u64, f64> = HashMap::new();
+    ) -> HashMap<UserId, f64> {
+        let mut payments: HashMap<UserId, f64> = HashMap::new();
 
         for &winning_bid in winning_bids {
             let remaining_bids: Vec<Bid> = bids.iter()
@@ -37,15 +43,16 @@ impl VCGAuction {
     fn allocate_assets<'a>(
         winning_bids: Vec<&'a Bid>,
         basket: &'a Basket
-    ) -> HashMap<u64, Vec<AssetInfo>> {
+    ) -> HashMap<UserId, Vec<AssetInfo>> {
         allocate_basket(&winning_bids, basket)
     }
 
 
-    pub fn run_auction<'a>(
+    /// Winners, allocation and VCG payments by user, without settlement.
+    pub fn outcome<'a>(
         bids: &'a [Bid],
-        basket: &'a Basket
-    ) -> (Vec<Bid>, HashMap<u64, Vec<AssetInfo>>, HashMap<u64, f64>, HashMap<u64, Arc<User>>) {
+        basket: &'a Basket,
+    ) -> (Vec<&'a Bid>, HashMap<UserId, Vec<AssetInfo>>, HashMap<UserId, f64>) {
         // Step 1: Maximize social welfare by selecting the winning bids
         let (winning_bids, total_welfare) = WDPSolver::maximize_welfare_vcg(bids, basket);
 
@@ -54,14 +61,26 @@ impl VCGAuction {
 
         // Step 3: Allocate the basket to the winning bidders (use references)
         let allocation = VCGAuction::allocate_assets(winning_bids.clone(), basket);
+        (winning_bids, allocation, payments)
+    }
+
+    /// Solves the auction and clears it under `metadata`, which the caller keeps for
+    /// retries: clearing it again returns the users as first settled.
+    pub fn run_auction<'a>(
+        bids: &'a [Bid],
+        basket: &'a Basket,
+        metadata: AuctionMetadata,
+        clearing: &mut Clearing,
+    ) -> Result<ClearedAuction, AuctionError> {
+        let (winning_bids, allocation, payments) = VCGAuction::outcome(bids, basket);
 
         // Step 4: Clone owned bids to clear (convert references to owned Bids)
         let winning_bids_owned: Vec<Bid> = winning_bids.into_iter().cloned().collect();
 
         // Call Clearing to settle payments and distribute assets
-        let result = Clearing::clear_winning_bids(winning_bids_owned.clone(), allocation.clone()).unwrap();
+        let result = clearing.clear_winning_bids(metadata, winning_bids_owned.clone(), allocation.clone())?.users;
 
-        (winning_bids_owned, allocation, payments, result)
+        Ok((winning_bids_owned, allocation, payments, result))
     }
 }
 
@@ -69,6 +88,7 @@ impl VCGAuction {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use model::ids::BasketId;
     use model::model::{Bid, User, Basket, AssetInfo, Asset, BidType};
     use std::sync::Arc;
 
@@ -79,7 +99,7 @@ mod tests {
         let user3 = Arc::new(User::new(3, "Charlie", 300000.0));
 
         let basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![
                 AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0),
                 AssetInfo::new(Asset::new("ETH", "USD"), 5.0, 2000.0),
@@ -91,7 +111,7 @@ mod tests {
         let bid3 = Bid::new(user3.clone(), 1, BidType::XOR, 80000.0, Some(1.0));
 
         let bids = vec![bid1, bid2, bid3];
-        let (winning_bids, allocation, payments, result) = VCGAuction::run_auction(&bids, &basket);
+        let (winning_bids, allocation, payments, result) = VCGAuction::run_auction(&bids, &basket, AuctionMetadata::new(basket.id, "VCG"), &mut Clearing::new()).unwrap();
 
         assert_eq!(winning_bids.len(), 3);
 
@@ -101,4 +121,20 @@ mod tests {
 
         println!("{:?}", allocation);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_retried_auction_settles_once() {
+        let basket = Basket { id: BasketId(2), assets: vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60000.0)] };
+        let bids = vec![
+            Bid::new(Arc::new(User::new(1, "Alice", 100000.0)), 2, BidType::XOR, 60000.0, Some(1.0)),
+            Bid::new(Arc::new(User::new(2, "Bob", 100000.0)), 2, BidType::XOR, 70000.0, Some(1.0)),
+        ];
+        let mut clearing = Clearing::new();
+        let metadata = AuctionMetadata::new(basket.id, "VCG");
+        let (_, _, _, first) = VCGAuction::run_auction(&bids, &basket, metadata.clone(), &mut clearing).unwrap();
+        let collected = clearing.ledger.cash_balance(crate::ledger::HOUSE_ACCOUNT);
+        let (_, _, _, retried) = VCGAuction::run_auction(&bids, &basket, metadata, &mut clearing).unwrap();
+        assert_eq!(clearing.ledger.cash_balance(crate::ledger::HOUSE_ACCOUNT), collected);
+        assert_eq!(first, retried);
+    }
+}
@@ -1,47 +1,93 @@
 use std::collections::{HashMap, HashSet};
+use std::time::Instant;
 
 use model::model::{Bid, Basket, AssetInfo};
-use model::helpers::{filter_valid_bids, allocate_basket, can_fulfill};
+use model::ids::UserId;
+use model::helpers::{filter_valid_bids, allocate_basket, can_fulfill_in};
+use model::numeric::Numeric;
+use crate::multi_unit::UnitBid;
+use crate::scoring::{self, BidScore, BidderProfile, ScoringRule};
+use crate::sim::SimRng;
+use crate::stats::WDP_SOLVE_SECONDS;
 
 pub struct WDPSolver;
 
 impl WDPSolver {
+    fn record_solve(solver: &'static str, started: Instant) {
+        metrics::histogram!(WDP_SOLVE_SECONDS, "solver" => solver).record(started.elapsed().as_secs_f64());
+    }
 
     pub fn solve_xor<'a>(bids: &'a [Bid], basket: &'a Basket) -> Option<&'a Bid> {
         let valid_bids = filter_valid_bids(bids, basket);
         valid_bids.into_iter()
-            .max_by(|a, b| a.price.partial_cmp(&b.price).unwrap())
+            .max_by(|a, b| a.price.total_cmp(&b.price))
+    }
+
+    /// Like [`WDPSolver::solve_xor`], but breaks ties between the highest bids by `rng`
+    /// rather than by their order.
+    pub fn solve_xor_seeded<'a>(bids: &'a [Bid], basket: &'a Basket, rng: &mut SimRng) -> Option<&'a Bid> {
+        let valid_bids = filter_valid_bids(bids, basket);
+        let highest = valid_bids.iter().map(|bid| bid.price).fold(f64::NEG_INFINITY, f64::max);
+        let tied: Vec<&Bid> = valid_bids.into_iter().filter(|bid| bid.price == highest).collect();
+        rng.choose(&tied).copied()
+    }
+
+    /// Like [`WDPSolver::solve_xor`], but the highest score under `rule` wins rather than
+    /// the highest price. Returns the scores of every valid bid, in order, with the winner.
+    pub fn solve_xor_scored<'a>(
+        bids: &'a [Bid],
+        basket: &'a Basket,
+        rule: &dyn ScoringRule,
+        profiles: &HashMap<UserId, BidderProfile>,
+    ) -> Option<(&'a Bid, Vec<BidScore>)> {
+        let valid_bids = filter_valid_bids(bids, basket);
+        let scores = scoring::score_bids(valid_bids.iter().copied(), rule, profiles);
+        let best = scores.iter().enumerate().max_by(|(_, a), (_, b)| a.score.total_cmp(&b.score)).map(|(index, _)| index)?;
+        Some((valid_bids[best], scores))
     }
 
-    pub fn solve_or<'a>(bids: &'a [Bid], basket: &'a Basket) -> (Vec<&'a Bid>, HashMap<u64, Vec<AssetInfo>>) {
+    pub fn solve_or<'a>(bids: &'a [Bid], basket: &'a Basket) -> (Vec<&'a Bid>, HashMap<UserId, Vec<AssetInfo>>) {
         let valid_bids = filter_valid_bids(bids, basket);
         let allocation = allocate_basket(&valid_bids, basket);
         (valid_bids, allocation)
     }
 
     pub fn maximize_welfare_vcg<'a>(bids: &'a [Bid], basket: &'a Basket) -> (Vec<&'a Bid>, f64) {
+        WDPSolver::maximize_welfare_vcg_in::<f64>(bids, basket)
+    }
+
+    /// [`WDPSolver::maximize_welfare_vcg`] with welfare summed in `N`.
+    pub fn maximize_welfare_vcg_in<'a, N: Numeric>(bids: &'a [Bid], basket: &'a Basket) -> (Vec<&'a Bid>, N) {
+        let started = Instant::now();
         let valid_bids = filter_valid_bids(bids, basket);
 
-        let mut total_value = 0.0;
+        let mut total_value = N::ZERO;
         let mut selected_bids = Vec::new();
 
         for bid in valid_bids.iter() {
             selected_bids.push(*bid);
-            total_value += bid.price;
+            total_value += N::from_float(bid.price);
         }
 
+        WDPSolver::record_solve("vcg", started);
         (selected_bids, total_value)
     }
 
     pub fn maximize_welfare_cca<'a>(bids: &'a [Bid], basket: &'a Basket) -> (Vec<&'a Bid>, f64) {
+        WDPSolver::maximize_welfare_cca_in::<f64>(bids, basket)
+    }
+
+    /// [`WDPSolver::maximize_welfare_cca`] with welfare and remaining quantities in `N`.
+    pub fn maximize_welfare_cca_in<'a, N: Numeric>(bids: &'a [Bid], basket: &'a Basket) -> (Vec<&'a Bid>, N) {
+        let started = Instant::now();
         let valid_bids = filter_valid_bids(bids, basket);
 
-        let mut total_value = 0.0;
+        let mut total_value = N::ZERO;
         let mut selected_bids = Vec::new();
-        let mut remaining_assets: HashMap<String, f64> = basket
+        let mut remaining_assets: HashMap<String, N> = basket
             .assets
             .iter()
-            .map(|asset_info| (asset_info.asset.base.clone(), asset_info.quantity))
+            .map(|asset_info| (asset_info.asset.base.clone(), N::from_float(asset_info.quantity)))
             .collect();
         let mut selected_users = HashSet::new();
 
@@ -50,12 +96,13 @@ impl WDPSolver {
                 continue;
             }
 
+            let proportion = N::from_float(bid.quantity.unwrap_or(1.0));
             let mut can_fulfill_bid = true;
             for asset_info in &basket.assets {
-                let available_quantity = remaining_assets.get(&asset_info.asset.base).unwrap_or(&0.0);
-                let bid_demand = bid.quantity.unwrap_or(1.0) * asset_info.quantity;
+                let available_quantity = remaining_assets.get(&asset_info.asset.base).copied().unwrap_or(N::ZERO);
+                let bid_demand = proportion * N::from_float(asset_info.quantity);
 
-                if bid_demand > *available_quantity {
+                if bid_demand > available_quantity {
                     can_fulfill_bid = false;
                     break;
                 }
@@ -64,62 +111,78 @@ impl WDPSolver {
             if can_fulfill_bid {
                 // Select this bid
                 selected_bids.push(*bid);
-                total_value += bid.price;
+                total_value += N::from_float(bid.price);
                 selected_users.insert(bid.user.id);
 
                 for asset_info in &basket.assets {
-                    let available_quantity = remaining_assets.get_mut(&asset_info.asset.base).unwrap();
-                    let bid_demand = bid.quantity.unwrap_or(1.0) * asset_info.quantity;
-                    *available_quantity -= bid_demand;
+                    if let Some(available_quantity) = remaining_assets.get_mut(&asset_info.asset.base) {
+                        *available_quantity -= proportion * N::from_float(asset_info.quantity);
+                    }
                 }
             }
         }
 
+        WDPSolver::record_solve("cca", started);
         (selected_bids, total_value)
     }
 
     pub fn branch_and_bound<'a>(bids: &'a [Bid], basket: &'a Basket) -> (Vec<&'a Bid>, f64) {
+        WDPSolver::branch_and_bound_in::<f64>(bids, basket)
+    }
+
+    /// [`WDPSolver::branch_and_bound`] with values and feasibility checks in `N`.
+    pub fn branch_and_bound_in<'a, N: Numeric>(bids: &'a [Bid], basket: &'a Basket) -> (Vec<&'a Bid>, N) {
+        let started = Instant::now();
         let valid_bids = filter_valid_bids(bids, basket);
         let mut selected_bids = Vec::new();
-        let mut best_solution = (Vec::new(), 0.0);  // (Bids, total value)
+        let mut best_solution = (Vec::new(), N::ZERO);  // (Bids, total value)
 
-        fn recursive_solve<'b>(
+        fn recursive_solve<'b, N: Numeric>(
             bids: &[&'b Bid],
             basket: &Basket,
             current_solution: &mut Vec<&'b Bid>,
-            best_solution: &mut (Vec<&'b Bid>, f64),
-            current_value: f64,
+            best_solution: &mut (Vec<&'b Bid>, N),
+            current_value: N,
             level: usize
         ) {
-            // Base case: if we reach the end of the bids or basket capacity is exceeded
-            if level == bids.len() || !can_fulfill(current_solution, basket) {
+            // Base case: every bid has been decided
+            if level == bids.len() {
                 if current_value > best_solution.1 {
                     *best_solution = (current_solution.clone(), current_value);
                 }
                 return;
             }
 
-            // Recursive case: Include or exclude current bid
+            // Recursive case: Include or exclude current bid, including it only if the
+            // basket can still supply every bid taken so far
             recursive_solve(bids, basket, current_solution, best_solution, current_value, level + 1);
 
             current_solution.push(bids[level]);
-            let new_value = current_value + bids[level].price;
-            recursive_solve(bids, basket, current_solution, best_solution, new_value, level + 1);
+            if can_fulfill_in::<N>(current_solution, basket) {
+                let new_value = current_value + N::from_float(bids[level].price);
+                recursive_solve(bids, basket, current_solution, best_solution, new_value, level + 1);
+            }
             current_solution.pop();
         }
 
-        let valid_bids_refs: Vec<&Bid> = valid_bids.iter().map(|&bid| bid).collect();
-        recursive_solve(&valid_bids_refs, basket, &mut selected_bids, &mut best_solution, 0.0, 0);
+        recursive_solve(&valid_bids, basket, &mut selected_bids, &mut best_solution, N::ZERO, 0);
 
+        WDPSolver::record_solve("branch_and_bound", started);
         best_solution
     }
 
     pub fn dynamic_programming<'a>(bids: &'a [Bid], basket: &'a Basket) -> (Vec<&'a Bid>, f64) {
+        WDPSolver::dynamic_programming_in::<f64>(bids, basket)
+    }
+
+    /// [`WDPSolver::dynamic_programming`] with the table kept in `N`.
+    pub fn dynamic_programming_in<'a, N: Numeric>(bids: &'a [Bid], basket: &'a Basket) -> (Vec<&'a Bid>, N) {
+        let started = Instant::now();
         let valid_bids = filter_valid_bids(bids, basket);
 
         // Initialize DP table (knapsack-like problem)
         let n = valid_bids.len();
-        let mut dp: Vec<Vec<f64>> = vec![vec![0.0; n + 1]; n + 1];
+        let mut dp: Vec<Vec<N>> = vec![vec![N::ZERO; n + 1]; n + 1];
         let mut selected_bids: Vec<&'a Bid> = Vec::new();
 
         for i in 1..=n {
@@ -128,19 +191,51 @@ impl WDPSolver {
                 dp[i][j] = dp[i - 1][j];
 
                 // Case 2: Taking the current bid if feasible
-                let bid_quantity = valid_bids[i - 1].quantity.unwrap_or(1.0);
-                let available_quantity = basket.assets.iter().map(|a| a.quantity).sum::<f64>();
+                let bid_quantity = N::from_float(valid_bids[i - 1].quantity.unwrap_or(1.0));
+                let available_quantity = basket.assets.iter().map(|a| N::from_float(a.quantity)).sum::<N>();
 
                 if bid_quantity <= available_quantity {
-                    dp[i][j] = dp[i - 1][j - 1] + valid_bids[i - 1].price;
-                    selected_bids.push(&valid_bids[i - 1]);
+                    dp[i][j] = dp[i - 1][j - 1] + N::from_float(valid_bids[i - 1].price);
+                    selected_bids.push(valid_bids[i - 1]);
                 }
             }
         }
 
         let max_value = dp[n][n];
+        WDPSolver::record_solve("dynamic_programming", started);
         (selected_bids, max_value)
     }
+
+    /// Allocates `supply` identical copies of a basket among unit bids, highest unit price
+    /// first; ties go to the earlier bid. Each bid wins at most the units it asks for and
+    /// its bidder can pay for at its own price, and bids below `reserve_price` win
+    /// nothing. Returns the units won by bid index and the welfare. With a flat value per
+    /// unit the greedy fill is optimal.
+    pub fn allocate_units(bids: &[UnitBid], supply: u32, reserve_price: f64) -> (Vec<(usize, u32)>, f64) {
+        let started = Instant::now();
+        let mut ranked: Vec<usize> = (0..bids.len())
+            .filter(|index| bids[*index].is_valid() && bids[*index].unit_price >= reserve_price)
+            .collect();
+        ranked.sort_by(|a, b| bids[*b].unit_price.total_cmp(&bids[*a].unit_price));
+
+        let mut remaining = supply;
+        let mut awards = Vec::new();
+        let mut welfare = 0.0;
+        for index in ranked {
+            if remaining == 0 {
+                break;
+            }
+            let units = bids[index].affordable_units().min(remaining);
+            if units > 0 {
+                remaining -= units;
+                welfare += f64::from(units) * bids[index].unit_price;
+                awards.push((index, units));
+            }
+        }
+
+        WDPSolver::record_solve("multi_unit", started);
+        (awards, welfare)
+    }
 }
 
 
@@ -148,7 +243,9 @@ impl WDPSolver {
 mod tests {
     use std::sync::Arc;
     use super::*;
+    use model::ids::BasketId;
     use model::model::{User, Asset, BidType};
+    use model::numeric::Decimal;
 
     #[test]
     fn test_solve_xor() {
@@ -204,6 +301,23 @@ mod tests {
         assert_eq!(total_value, 130000.0);  // Total value = 60,000 + 70,000
     }
 
+    #[test]
+    fn test_exact_welfare_in_decimals() {
+        let basket = Basket { id: BasketId(1), assets: vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 0.3)] };
+        let bids = vec![
+            Bid::new(Arc::new(User::new(1, "Alice", 1.0)), 1, BidType::OR, 0.1, Some(0.5)),
+            Bid::new(Arc::new(User::new(2, "Bob", 1.0)), 1, BidType::OR, 0.2, Some(0.5)),
+        ];
+
+        assert_ne!(WDPSolver::maximize_welfare_vcg(&bids, &basket).1, 0.3);
+        let exact = Decimal::new(3, 1);
+        assert_eq!(WDPSolver::maximize_welfare_vcg_in::<Decimal>(&bids, &basket).1, exact);
+        assert_eq!(WDPSolver::maximize_welfare_cca_in::<Decimal>(&bids, &basket).1, exact);
+        let (winning_bids, welfare) = WDPSolver::branch_and_bound_in::<Decimal>(&bids, &basket);
+        assert_eq!(winning_bids.len(), 2);
+        assert_eq!(welfare, exact);
+    }
+
     // Utility function to set up sample data for the tests
     fn setup_sample_data() -> (Basket, Vec<Bid>) {
         let user1 = Arc::new(User::new(1, "Alice", 1000000.0));
@@ -211,7 +325,7 @@ mod tests {
         let user3 = Arc::new(User::new(3, "Charlie", 3000000.0));
 
         let basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![
                 AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0), // 2 BTC
                 AssetInfo::new(Asset::new("ETH", "USD"), 5.0, 2000.0),  // 5 ETH
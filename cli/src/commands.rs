@@ -0,0 +1,256 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use api::error::ApiError;
+use api::exchange::{AuctionRequest, Exchange};
+use api::storage::StorageError;
+use auction::config::{Config, ConfigError};
+use model::model::AssetInfo;
+use quanto_pricer::builder::InputError;
+use quanto_pricer::chain::price_chain_with;
+use quanto_pricer::data::DeribitOptionData;
+use quanto_pricer::fetch::{FetchError, FetchSettings};
+use quanto_pricer::fourier::{FftSettings, QuantoOption};
+use crate::{AuctionCommand, BasketCommand, BidCommand, Cli, Command, DataCommand, FetchCommand, PriceCommand, QuantoArgs, UserCommand};
+
+
+/// Why a command failed.
+#[derive(Debug)]
+pub enum CliError {
+    Io(String),
+    Json(String),
+    Config(ConfigError),
+    Exchange(ApiError),
+    Storage(StorageError),
+    Pricing(InputError),
+    Fetch(FetchError),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Io(message) | CliError::Json(message) => write!(f, "{}", message),
+            CliError::Config(error) => write!(f, "{}", error),
+            CliError::Exchange(error) => write!(f, "{}", error),
+            CliError::Storage(error) => write!(f, "{}", error),
+            CliError::Pricing(error) => write!(f, "{}", error),
+            CliError::Fetch(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<ConfigError> for CliError {
+    fn from(error: ConfigError) -> Self {
+        CliError::Config(error)
+    }
+}
+
+impl From<ApiError> for CliError {
+    fn from(error: ApiError) -> Self {
+        CliError::Exchange(error)
+    }
+}
+
+impl From<StorageError> for CliError {
+    fn from(error: StorageError) -> Self {
+        CliError::Storage(error)
+    }
+}
+
+impl From<InputError> for CliError {
+    fn from(error: InputError) -> Self {
+        CliError::Pricing(error)
+    }
+}
+
+impl From<FetchError> for CliError {
+    fn from(error: FetchError) -> Self {
+        CliError::Fetch(error)
+    }
+}
+
+impl From<serde_json::Error> for CliError {
+    fn from(error: serde_json::Error) -> Self {
+        CliError::Json(error.to_string())
+    }
+}
+
+
+/// Parses a flag the way the type is spelled in JSON, e.g. `cca` or `XOR`.
+pub fn parse_json_name<T: DeserializeOwned>(name: &str) -> Result<T, String> {
+    serde_json::from_value(Value::String(name.to_string())).map_err(|_| format!("unknown value '{}'", name))
+}
+
+fn to_json(value: &impl Serialize) -> Result<Value, CliError> {
+    Ok(serde_json::to_value(value)?)
+}
+
+/// Reads `path`, or stdin for `-`.
+fn read_input(path: &Path) -> Result<String, CliError> {
+    let mut input = String::new();
+    let read = if path == Path::new("-") { io::stdin().read_to_string(&mut input).map(|_| ()) } else { fs::read_to_string(path).map(|text| input = text) };
+    read.map_err(|error| CliError::Io(format!("cannot read {}: {}", path.display(), error)))?;
+    Ok(input)
+}
+
+fn load(state: &Path, config: &Config) -> Result<Exchange, CliError> {
+    let exchange = match fs::read(state) {
+        Ok(bytes) => Exchange::restore(&bytes)?,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Exchange::new(),
+        Err(error) => return Err(CliError::Io(format!("cannot read {}: {}", state.display(), error))),
+    };
+    Ok(exchange.with_config(config))
+}
+
+/// Writes through a temporary file so an interrupted save leaves the old state intact.
+fn save(state: &Path, exchange: &Exchange) -> Result<(), CliError> {
+    let partial = state.with_extension("partial");
+    let bytes = exchange.snapshot()?;
+    fs::write(&partial, bytes)
+        .and_then(|_| fs::rename(&partial, state))
+        .map_err(|error| CliError::Io(format!("cannot write {}: {}", state.display(), error)))
+}
+
+/// Runs `cli`, returning what to print. Commands that change the exchange save it back
+/// to `cli.state` only if they succeed.
+pub async fn run(cli: Cli) -> Result<Value, CliError> {
+    let config = Config::load(cli.config.as_deref())?;
+    match cli.command {
+        Command::Price(PriceCommand::Quanto(args)) => price_quanto(&args, &FftSettings::from(&config.pricer)),
+        Command::Data(DataCommand::Fetch(FetchCommand::Deribit { currency, output })) => {
+            fetch_deribit(&currency, &FetchSettings::from(&config.providers.deribit), output.as_deref()).await
+        }
+        command => {
+            let mut exchange = load(&cli.state, &config)?;
+            let (output, changed) = operate(&mut exchange, command)?;
+            if changed {
+                save(&cli.state, &exchange)?;
+            }
+            Ok(output)
+        }
+    }
+}
+
+/// Runs an exchange command; the flag says whether it changed the exchange.
+fn operate(exchange: &mut Exchange, command: Command) -> Result<(Value, bool), CliError> {
+    let output = match command {
+        Command::User(UserCommand::Register { name, balance }) => return Ok((to_json(&exchange.register_user(&name, balance)?)?, true)),
+        Command::User(UserCommand::Show { id }) => to_json(exchange.user(id)?)?,
+        Command::Basket(BasketCommand::Create { assets }) => {
+            let assets: Vec<AssetInfo> = serde_json::from_str(&read_input(&assets)?)?;
+            return Ok((to_json(&exchange.create_basket(assets)?)?, true));
+        }
+        Command::Basket(BasketCommand::List) => to_json(&exchange.baskets())?,
+        Command::Bid(BidCommand::Submit { user, basket, bid_type, price, quantity }) => {
+            return Ok((to_json(&exchange.submit_bid(user, basket, bid_type, price, quantity)?)?, true));
+        }
+        Command::Bid(BidCommand::Cancel { id }) => return Ok((to_json(&exchange.cancel_bid(id)?)?, true)),
+        Command::Bid(BidCommand::List { basket }) => to_json(&exchange.bids_for(basket))?,
+        Command::Auction(AuctionCommand::Run { basket, mechanism, price_increment, max_rounds, settlement }) => {
+            let request = AuctionRequest { basket_id: basket, mechanism, price_increment, max_rounds, settlement };
+            return Ok((to_json(&exchange.run_auction(&request)?)?, true));
+        }
+        Command::Auction(AuctionCommand::Show { id }) => to_json(exchange.outcome(id)?)?,
+        Command::Price(_) | Command::Data(_) => unreachable!("handled without the exchange"),
+    };
+    Ok((output, false))
+}
+
+fn price_quanto(args: &QuantoArgs, settings: &FftSettings) -> Result<Value, CliError> {
+    let options = args.strike.iter()
+        .map(|strike| {
+            QuantoOption::builder()
+                .spot(args.spot)
+                .strike(*strike)
+                .volatility(args.volatility)
+                .time_to_maturity(args.maturity)
+                .domestic_rate(args.domestic_rate)
+                .foreign_rate(args.foreign_rate)
+                .quanto(args.fx_volatility, args.correlation)
+                .dividend_yield(args.dividend_yield)
+                .build()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let prices = price_chain_with(&options, settings)?;
+    let rows: Vec<Value> = options.iter().zip(prices)
+        .map(|(option, price)| json!({ "strike": option.strike, "call": price.call, "put": price.put }))
+        .collect();
+    Ok(Value::Array(rows))
+}
+
+/// Prints the chain, or writes it to `output` and prints a summary.
+async fn fetch_deribit(currency: &str, settings: &FetchSettings, output: Option<&Path>) -> Result<Value, CliError> {
+    let report = DeribitOptionData::fetch_data_with(currency, settings).await?;
+    if !report.is_complete() {
+        eprintln!("warning: {}", report.summary());
+    }
+    match output {
+        Some(path) => {
+            fs::write(path, serde_json::to_vec_pretty(&report.data)?).map_err(|error| CliError::Io(format!("cannot write {}: {}", path.display(), error)))?;
+            Ok(json!({ "options": report.data.len(), "output": path.display().to_string() }))
+        }
+        None => to_json(&report.data),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn command(args: &[&str]) -> Command {
+        Cli::try_parse_from([&["combi-dex"], args].concat()).unwrap().command
+    }
+
+    fn id(output: Value) -> u64 {
+        output["id"].as_u64().unwrap()
+    }
+
+    #[test]
+    fn test_commands_run_an_auction_through_saved_state() {
+        let dir = std::env::temp_dir().join(format!("combidex-cli-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let state = dir.join("state.json");
+        let assets = dir.join("assets.json");
+        fs::write(&assets, r#"[{"asset": {"base": "BTC", "quote": "USD"}, "quantity": 1.0, "price": 60000.0}]"#).unwrap();
+
+        // Each step reloads the state the previous one saved, as separate invocations would
+        let step = |args: &[&str]| {
+            let mut exchange = load(&state, &Config::default()).unwrap();
+            let (output, changed) = operate(&mut exchange, command(args)).unwrap();
+            if changed {
+                save(&state, &exchange).unwrap();
+            }
+            output
+        };
+        let alice = id(step(&["user", "register", "--name", "Alice", "--balance", "100000"]));
+        let basket = id(step(&["basket", "create", "--assets", assets.to_str().unwrap()]));
+        step(&["bid", "submit", "--user", &alice.to_string(), "--basket", &basket.to_string(), "--type", "OR", "--price", "30000", "--quantity", "0.5"]);
+        let outcome = step(&["auction", "run", "--basket", &basket.to_string(), "--mechanism", "cca"]);
+        assert_eq!(outcome["mechanism"], "cca");
+        assert_eq!(outcome["payments"][alice.to_string()], 30_000.0);
+        assert_eq!(step(&["user", "show", &alice.to_string()])["balance"], 70_000.0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_bad_input_is_refused() {
+        assert!(Cli::try_parse_from(["combi-dex", "auction", "run", "--basket", "1", "--mechanism", "dutch"]).is_err());
+        let mut exchange = Exchange::new();
+        assert!(matches!(operate(&mut exchange, command(&["user", "show", "7"])), Err(CliError::Exchange(ApiError::NotFound("user")))));
+
+        let Command::Price(PriceCommand::Quanto(args)) = command(&["price", "quanto", "--spot", "100", "--strike", "90", "--strike", "110", "--volatility", "0.5", "--maturity", "0.5"]) else {
+            panic!("expected a pricing command");
+        };
+        let prices = price_quanto(&args, &FftSettings::default()).unwrap();
+        assert!(prices[0]["call"].as_f64().unwrap() > prices[1]["call"].as_f64().unwrap());
+        assert!(matches!(price_quanto(&QuantoArgs { volatility: -1.0, ..args }, &FftSettings::default()), Err(CliError::Pricing(_))));
+    }
+}
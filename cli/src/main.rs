@@ -0,0 +1,179 @@
+mod commands;
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use clap::{Args, Parser, Subcommand};
+use api::exchange::Mechanism;
+use auction::report::SettlementMode;
+use model::ids::{AuctionId, BasketId, BidId, UserId};
+use model::model::BidType;
+use crate::commands::parse_json_name;
+
+
+/// Operates a combi-dex exchange from the shell. Exchange state lives in a snapshot file
+/// that every command reads and, if it changed anything, writes back; results are
+/// printed as JSON.
+#[derive(Debug, Parser)]
+#[command(name = "combi-dex", version)]
+pub struct Cli {
+    /// Snapshot file holding the exchange state; created on first use.
+    #[arg(long, global = true, default_value = "combidex.json")]
+    pub state: PathBuf,
+    /// TOML configuration; `COMBIDEX_` environment variables override it.
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Registers and looks up users.
+    #[command(subcommand)]
+    User(UserCommand),
+    /// Creates and lists baskets.
+    #[command(subcommand)]
+    Basket(BasketCommand),
+    /// Submits, cancels and lists resting bids.
+    #[command(subcommand)]
+    Bid(BidCommand),
+    /// Auctions a basket or shows a cleared auction.
+    #[command(subcommand)]
+    Auction(AuctionCommand),
+    /// Prices options with the quanto pricer; needs no exchange state.
+    #[command(subcommand)]
+    Price(PriceCommand),
+    /// Fetches market data; needs no exchange state.
+    #[command(subcommand)]
+    Data(DataCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum UserCommand {
+    Register {
+        #[arg(long)]
+        name: String,
+        #[arg(long)]
+        balance: f64,
+    },
+    Show { id: UserId },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BasketCommand {
+    /// Creates a basket from a JSON array of assets, e.g.
+    /// `[{"asset": {"base": "BTC", "quote": "USD"}, "quantity": 1.0, "price": 60000.0}]`.
+    Create {
+        /// File to read the assets from, or `-` for stdin.
+        #[arg(long)]
+        assets: PathBuf,
+    },
+    List,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BidCommand {
+    Submit {
+        #[arg(long)]
+        user: UserId,
+        #[arg(long)]
+        basket: BasketId,
+        #[arg(long = "type", value_parser = parse_json_name::<BidType>, default_value = "XOR")]
+        bid_type: BidType,
+        #[arg(long)]
+        price: f64,
+        /// Fraction of the basket an OR bid accepts, in (0, 1].
+        #[arg(long)]
+        quantity: Option<f64>,
+    },
+    Cancel { id: BidId },
+    List {
+        #[arg(long)]
+        basket: BasketId,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AuctionCommand {
+    Run {
+        #[arg(long)]
+        basket: BasketId,
+        /// xor, or, vcg or cca.
+        #[arg(long, value_parser = parse_json_name::<Mechanism>)]
+        mechanism: Mechanism,
+        /// CCA only: relative price rise per round; defaults to the configured one.
+        #[arg(long)]
+        price_increment: Option<f64>,
+        /// CCA only; defaults to the configured limit.
+        #[arg(long)]
+        max_rounds: Option<usize>,
+        /// physical or cash; defaults to the configured mode.
+        #[arg(long, value_parser = parse_json_name::<SettlementMode>)]
+        settlement: Option<SettlementMode>,
+    },
+    Show { id: AuctionId },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PriceCommand {
+    /// Prices quanto calls and puts, one per `--strike`.
+    Quanto(QuantoArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct QuantoArgs {
+    #[arg(long)]
+    pub spot: f64,
+    #[arg(long, required = true)]
+    pub strike: Vec<f64>,
+    /// Annualised, as a decimal.
+    #[arg(long)]
+    pub volatility: f64,
+    /// In years.
+    #[arg(long)]
+    pub maturity: f64,
+    #[arg(long, default_value_t = 0.0)]
+    pub domestic_rate: f64,
+    #[arg(long, default_value_t = 0.0)]
+    pub foreign_rate: f64,
+    #[arg(long, default_value_t = 0.0)]
+    pub fx_volatility: f64,
+    #[arg(long, default_value_t = 0.0)]
+    pub correlation: f64,
+    #[arg(long, default_value_t = 0.0)]
+    pub dividend_yield: f64,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DataCommand {
+    #[command(subcommand)]
+    Fetch(FetchCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum FetchCommand {
+    /// Fetches the live option chain of `currency`.
+    Deribit {
+        #[arg(long, default_value = "BTC")]
+        currency: String,
+        /// Writes the chain here instead of stdout.
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match commands::run(cli).await {
+        Ok(output) => {
+            println!("{}", serde_json::to_string_pretty(&output).expect("JSON values serialize"));
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
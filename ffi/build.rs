@@ -0,0 +1,13 @@
+// Regenerates include/combidex.h from the extern "C" functions in src/lib.rs.
+fn main() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    match cbindgen::generate(&crate_dir) {
+        Ok(bindings) => {
+            bindings.write_to_file(format!("{}/include/combidex.h", crate_dir));
+        }
+        // A half-edited lib.rs should fail on rustc's error, not cbindgen's
+        Err(error) => println!("cargo:warning=combidex.h not regenerated: {}", error),
+    }
+}
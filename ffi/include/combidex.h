@@ -0,0 +1,50 @@
+#ifndef COMBIDEX_H
+#define COMBIDEX_H
+
+/* Generated by cbindgen from src/lib.rs; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Prices a quanto option by FFT. The request holds `spot`, `strike`, `volatility` and
+// `time_to_maturity`, and optionally `domestic_rate`, `foreign_rate`, `fx_volatility`,
+// `correlation` and `dividend_yield` (zero when left out). Responds with `call` and `put`.
+//
+// # Safety
+// `request` must be null or a valid NUL-terminated string.
+char *combidex_price_quanto(const char *request);
+
+// Black–Scholes implied volatility. The request holds `spot`, `strike`,
+// `time_to_maturity` and `market_price`, and optionally `rate`, `dividend_yield` and
+// `is_call` (true when left out). Responds with `implied_volatility`.
+//
+// # Safety
+// `request` must be null or a valid NUL-terminated string.
+char *combidex_implied_vol(const char *request);
+
+// Winner determination. The request holds a `solver` ("xor", "or", "vcg", "cca",
+// "branch_and_bound" or "dynamic_programming"), a `basket` and its `bids`, shaped like
+// their JSON elsewhere in the exchange. Responds with `winners`, the positions of the
+// winning bids in `bids`, and their `welfare`.
+//
+// # Safety
+// `request` must be null or a valid NUL-terminated string.
+char *combidex_solve_wdp(const char *request);
+
+// Releases a response returned by this library. Null is ignored.
+//
+// # Safety
+// `response` must be null or a pointer returned by this library, released only once.
+void combidex_free_string(char *response);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* COMBIDEX_H */
@@ -0,0 +1,257 @@
+//! C ABI of the pricer and winner determination, for embedding in C, C++ or C# systems.
+//! Every function takes a NUL-terminated JSON request and returns a JSON response that
+//! the caller owns and must release with `combidex_free_string`. Failures come back as
+//! `{"error": "..."}` rather than as null, so callers have a single path to parse.
+//! The header is include/combidex.h, regenerated by cbindgen on each build.
+
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use auction::wdp::WDPSolver;
+use model::model::{Basket, Bid};
+use quanto_pricer::fourier::QuantoOption;
+use quanto_pricer::implied_vol::ImpliedVolatility;
+
+
+#[derive(Debug, Deserialize)]
+struct PriceRequest {
+    spot: f64,
+    strike: f64,
+    volatility: f64,
+    time_to_maturity: f64,
+    #[serde(default)]
+    domestic_rate: f64,
+    #[serde(default)]
+    foreign_rate: f64,
+    #[serde(default)]
+    fx_volatility: f64,
+    #[serde(default)]
+    correlation: f64,
+    #[serde(default)]
+    dividend_yield: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct PriceResponse {
+    call: f64,
+    put: f64,
+}
+
+fn call() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+struct ImpliedVolRequest {
+    spot: f64,
+    strike: f64,
+    time_to_maturity: f64,
+    market_price: f64,
+    #[serde(default)]
+    rate: f64,
+    #[serde(default = "call")]
+    is_call: bool,
+    #[serde(default)]
+    dividend_yield: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct WdpRequest {
+    solver: String,
+    basket: Basket,
+    bids: Vec<Bid>,
+}
+
+#[derive(Debug, Serialize)]
+struct WdpResponse {
+    /// Positions of the winning bids in the request's `bids`.
+    winners: Vec<usize>,
+    welfare: f64,
+}
+
+
+fn price_quanto(request: PriceRequest) -> Result<PriceResponse, String> {
+    let option = QuantoOption::builder()
+        .spot(request.spot)
+        .strike(request.strike)
+        .volatility(request.volatility)
+        .time_to_maturity(request.time_to_maturity)
+        .domestic_rate(request.domestic_rate)
+        .foreign_rate(request.foreign_rate)
+        .quanto(request.fx_volatility, request.correlation)
+        .dividend_yield(request.dividend_yield)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let price = option.calculate_price_fft().map_err(|e| e.to_string())?;
+    Ok(PriceResponse { call: price.call, put: price.put })
+}
+
+fn implied_vol(request: ImpliedVolRequest) -> Result<Value, String> {
+    let problem = ImpliedVolatility {
+        spot: request.spot,
+        strike: request.strike,
+        r: request.rate,
+        time_to_maturity: request.time_to_maturity,
+        market_price: request.market_price,
+        is_call: request.is_call,
+        dividend_yield: request.dividend_yield,
+    };
+    problem.validate().map_err(|e| e.to_string())?;
+    let volatility = problem.implied_volatility().map_err(|e| e.to_string())?;
+    Ok(json!({ "implied_volatility": volatility }))
+}
+
+fn solve_wdp(request: WdpRequest) -> Result<WdpResponse, String> {
+    let (bids, basket) = (&request.bids, &request.basket);
+    let (winners, welfare): (Vec<&Bid>, f64) = match request.solver.as_str() {
+        "xor" => {
+            let winners: Vec<&Bid> = WDPSolver::solve_xor(bids, basket).into_iter().collect();
+            let welfare = winners.iter().map(|bid| bid.price).sum();
+            (winners, welfare)
+        }
+        "or" => {
+            let (winners, _) = WDPSolver::solve_or(bids, basket);
+            let welfare = winners.iter().map(|bid| bid.price).sum();
+            (winners, welfare)
+        }
+        "vcg" => WDPSolver::maximize_welfare_vcg(bids, basket),
+        "cca" => WDPSolver::maximize_welfare_cca(bids, basket),
+        "branch_and_bound" => WDPSolver::branch_and_bound(bids, basket),
+        "dynamic_programming" => WDPSolver::dynamic_programming(bids, basket),
+        other => return Err(format!("unknown solver '{}'", other)),
+    };
+    let winners = winners.iter()
+        .filter_map(|winner| bids.iter().position(|bid| std::ptr::eq(bid, *winner)))
+        .collect();
+    Ok(WdpResponse { winners, welfare })
+}
+
+
+/// Parses `request`, runs `handler` and serializes its answer or error, never unwinding
+/// into the caller.
+///
+/// # Safety
+/// `request` must be null or a valid NUL-terminated string.
+unsafe fn respond<T, R, F>(request: *const c_char, handler: F) -> *mut c_char
+where
+    T: DeserializeOwned,
+    R: Serialize,
+    F: FnOnce(T) -> Result<R, String>,
+{
+    let response = if request.is_null() {
+        json!({ "error": "request is null" })
+    } else {
+        let text = CStr::from_ptr(request).to_string_lossy();
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+            let request: T = serde_json::from_str(&text).map_err(|e| format!("invalid request: {}", e))?;
+            let answer = handler(request)?;
+            serde_json::to_value(answer).map_err(|e| e.to_string())
+        }));
+        match outcome {
+            Ok(Ok(answer)) => answer,
+            Ok(Err(error)) => json!({ "error": error }),
+            Err(_) => json!({ "error": "internal error" }),
+        }
+    };
+    // serde_json escapes NUL, so the text never contains an interior one
+    CString::new(response.to_string()).map(CString::into_raw).unwrap_or(std::ptr::null_mut())
+}
+
+
+/// Prices a quanto option by FFT. The request holds `spot`, `strike`, `volatility` and
+/// `time_to_maturity`, and optionally `domestic_rate`, `foreign_rate`, `fx_volatility`,
+/// `correlation` and `dividend_yield` (zero when left out). Responds with `call` and `put`.
+///
+/// # Safety
+/// `request` must be null or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn combidex_price_quanto(request: *const c_char) -> *mut c_char {
+    respond(request, price_quanto)
+}
+
+/// Black–Scholes implied volatility. The request holds `spot`, `strike`,
+/// `time_to_maturity` and `market_price`, and optionally `rate`, `dividend_yield` and
+/// `is_call` (true when left out). Responds with `implied_volatility`.
+///
+/// # Safety
+/// `request` must be null or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn combidex_implied_vol(request: *const c_char) -> *mut c_char {
+    respond(request, implied_vol)
+}
+
+/// Winner determination. The request holds a `solver` ("xor", "or", "vcg", "cca",
+/// "branch_and_bound" or "dynamic_programming"), a `basket` and its `bids`, shaped like
+/// their JSON elsewhere in the exchange. Responds with `winners`, the positions of the
+/// winning bids in `bids`, and their `welfare`.
+///
+/// # Safety
+/// `request` must be null or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn combidex_solve_wdp(request: *const c_char) -> *mut c_char {
+    respond(request, solve_wdp)
+}
+
+/// Releases a response returned by this library. Null is ignored.
+///
+/// # Safety
+/// `response` must be null or a pointer returned by this library, released only once.
+#[no_mangle]
+pub unsafe extern "C" fn combidex_free_string(response: *mut c_char) {
+    if !response.is_null() {
+        drop(CString::from_raw(response));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call_json(function: unsafe extern "C" fn(*const c_char) -> *mut c_char, request: &str) -> Value {
+        let request = CString::new(request).unwrap();
+        unsafe {
+            let response = function(request.as_ptr());
+            let value = serde_json::from_str(CStr::from_ptr(response).to_str().unwrap()).unwrap();
+            combidex_free_string(response);
+            value
+        }
+    }
+
+    #[test]
+    fn test_pricing_round_trip() {
+        let price = call_json(combidex_price_quanto, r#"{"spot": 100.0, "strike": 110.0, "volatility": 0.5, "time_to_maturity": 0.5, "domestic_rate": 0.03}"#);
+        let call = price["call"].as_f64().unwrap();
+        assert!(call > 0.0 && price["put"].as_f64().unwrap() > 0.0);
+
+        let iv = call_json(combidex_implied_vol, &format!(r#"{{"spot": 100.0, "strike": 110.0, "time_to_maturity": 0.5, "market_price": {}, "rate": 0.03}}"#, call));
+        assert!((iv["implied_volatility"].as_f64().unwrap() - 0.5).abs() < 1e-3);
+
+        let error = call_json(combidex_price_quanto, r#"{"spot": -1.0, "strike": 110.0, "volatility": 0.5, "time_to_maturity": 0.5}"#);
+        assert!(error["error"].is_string());
+        assert!(call_json(combidex_implied_vol, "not json")["error"].as_str().unwrap().starts_with("invalid request"));
+        unsafe {
+            let response = combidex_price_quanto(std::ptr::null());
+            assert_eq!(CStr::from_ptr(response).to_str().unwrap(), r#"{"error":"request is null"}"#);
+            combidex_free_string(response);
+        }
+    }
+
+    #[test]
+    fn test_solve_wdp() {
+        let request = r#"{
+            "solver": "branch_and_bound",
+            "basket": {"id": 1, "assets": [{"asset": {"base": "BTC", "quote": "USD"}, "quantity": 1.0, "price": 60000.0}]},
+            "bids": [
+                {"user": {"id": 7, "name": "Alice", "balance": 100000.0}, "basket_id": 1, "bid_type": "XOR", "price": 59000.0, "quantity": null},
+                {"user": {"id": 8, "name": "Bob", "balance": 100000.0}, "basket_id": 1, "bid_type": "XOR", "price": 61000.0, "quantity": null}
+            ]
+        }"#;
+        let response = call_json(combidex_solve_wdp, request);
+        assert_eq!(response, json!({ "winners": [1], "welfare": 61000.0 }));
+        let unknown = call_json(combidex_solve_wdp, &request.replace("branch_and_bound", "dutch"));
+        assert_eq!(unknown["error"], "unknown solver 'dutch'");
+    }
+}
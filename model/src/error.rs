@@ -0,0 +1,26 @@
+use thiserror::Error;
+use crate::ids::UserId;
+
+
+/// Why a user, asset or bid could not be built or changed.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ModelError {
+    #[error("invalid asset '{0}', expected BASE/QUOTE")]
+    InvalidAsset(String),
+    #[error("{what} must be a non-negative number, got {value}")]
+    InvalidAmount { what: &'static str, value: f64 },
+    #[error("{what} must be positive, got {value}")]
+    NonPositive { what: &'static str, value: f64 },
+    #[error("{what} must be finite, got {value}")]
+    NotFinite { what: &'static str, value: f64 },
+    #[error("{0} overflows")]
+    Overflow(&'static str),
+    #[error("user {user_id} has {available} but needs {needed}")]
+    InsufficientBalance { user_id: UserId, needed: f64, available: f64 },
+    #[error("bid price must be positive, got {0}")]
+    NonPositivePrice(f64),
+    #[error("bid quantity must be in (0, 1], got {0}")]
+    InvalidQuantity(f64),
+    #[error("amount is in {found}, expected {expected}")]
+    CurrencyMismatch { expected: String, found: String },
+}
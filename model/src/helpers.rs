@@ -1,6 +1,10 @@
 use std::collections::HashMap;
 use std::hash::Hash;
 use crate::model::{Bid, Basket, AssetInfo};
+use crate::ids::UserId;
+use crate::numeric::Numeric;
+use crate::error::ModelError;
+use crate::safe_math;
 
 
 pub fn filter_valid_bids<'a>(bids: &'a [Bid], basket: &'a Basket) -> Vec<&'a Bid> {
@@ -13,14 +17,14 @@ pub fn filter_valid_bids<'a>(bids: &'a [Bid], basket: &'a Basket) -> Vec<&'a Bid
 
 pub fn sort_bids_by_price<'a>(bids: &'a [&'a Bid]) -> Vec<&'a Bid> {
     let mut sorted_bids = bids.to_vec();
-    sorted_bids.sort_by(|a, b| b.price.partial_cmp(&a.price).unwrap());
+    sorted_bids.sort_by(|a, b| b.price.total_cmp(&a.price));
     sorted_bids
 }
 
 
 pub fn get_highest_bid(bids: Vec<&Bid>) -> Option<&Bid> {
     bids.into_iter()
-        .max_by(|a, b| a.price.partial_cmp(&b.price).unwrap())
+        .max_by(|a, b| a.price.total_cmp(&b.price))
 }
 
 
@@ -30,7 +34,7 @@ pub fn evaluate_xor_bids<'a>(bids: &'a [Bid], basket: &'a Basket) -> Option<&'a
 }
 
 
-pub fn evaluate_partial_xor_bids<'a>(bids: &'a [Bid], basket: &'a Basket) -> Option<(&'a Bid, HashMap<u64, Vec<AssetInfo>>)> {
+pub fn evaluate_partial_xor_bids<'a>(bids: &'a [Bid], basket: &'a Basket) -> Option<(&'a Bid, HashMap<UserId, Vec<AssetInfo>>)> {
     let valid_bids = filter_valid_bids(bids, basket);
     if let Some(highest_bid) = get_highest_bid(valid_bids) {
         let allocation = allocate_basket(&[highest_bid], basket);
@@ -46,23 +50,24 @@ pub fn evaluate_or_bids<'a>(bids: &'a [Bid], basket: &'a Basket) -> Vec<&'a Bid>
 }
 
 
-pub fn evaluate_partial_or_bids<'a>(bids: &'a [Bid], basket: &'a Basket) -> (Vec<&'a Bid>, HashMap<u64, Vec<AssetInfo>>) {
+pub fn evaluate_partial_or_bids<'a>(bids: &'a [Bid], basket: &'a Basket) -> (Vec<&'a Bid>, HashMap<UserId, Vec<AssetInfo>>) {
     let valid_bids = filter_valid_bids(bids, basket);
     let allocation = allocate_basket(&valid_bids, basket);
     (valid_bids, allocation)
 }
 
 
-pub fn total_value_of_bids_for_basket(bids: &[Bid], basket: &Basket) -> f64 {
-    filter_valid_bids(bids, basket)
+pub fn total_value_of_bids_for_basket(bids: &[Bid], basket: &Basket) -> Result<f64, ModelError> {
+    let values = filter_valid_bids(bids, basket)
         .iter()
         .map(|bid| bid.estimate_value_of_bid(basket))
-        .sum()
+        .collect::<Result<Vec<f64>, ModelError>>()?;
+    safe_math::checked_sum("total bid value", values)
 }
 
 
-pub fn allocate_basket(bids: &[&Bid], basket: &Basket) -> HashMap<u64, Vec<AssetInfo>> {
-    let mut allocation: HashMap<u64, Vec<AssetInfo>> = HashMap::new();
+pub fn allocate_basket(bids: &[&Bid], basket: &Basket) -> HashMap<UserId, Vec<AssetInfo>> {
+    let mut allocation: HashMap<UserId, Vec<AssetInfo>> = HashMap::new();
     for bid in bids {
         let mut allocated_assets = Vec::new();
         let proportion = bid.quantity.unwrap_or(1.0);
@@ -79,22 +84,25 @@ pub fn allocate_basket(bids: &[&Bid], basket: &Basket) -> HashMap<u64, Vec<Asset
 }
 
 pub fn can_fulfill<'a>(bids: &[&'a Bid], basket: &'a Basket) -> bool {
-    let mut remaining_assets: HashMap<String, f64> = basket
+    can_fulfill_in::<f64>(bids, basket)
+}
+
+/// Whether the basket can supply every bid at once, with quantities summed in `N`.
+pub fn can_fulfill_in<'a, N: Numeric>(bids: &[&'a Bid], basket: &'a Basket) -> bool {
+    let mut remaining_assets: HashMap<String, N> = basket
         .assets
         .iter()
-        .map(|asset_info| (asset_info.asset.base.clone(), asset_info.quantity))
+        .map(|asset_info| (asset_info.asset.base.clone(), N::from_float(asset_info.quantity)))
         .collect();
 
     for bid in bids.iter() {
+        let proportion = N::from_float(bid.quantity.unwrap_or(1.0));
         for asset_info in &basket.assets {
-            let available_quantity = remaining_assets.get_mut(&asset_info.asset.base).unwrap();
-            let bid_demand = bid.quantity.unwrap_or(1.0) * asset_info.quantity;
-
-            if bid_demand > *available_quantity {
-                return false;
+            let bid_demand = proportion * N::from_float(asset_info.quantity);
+            match remaining_assets.get_mut(&asset_info.asset.base) {
+                Some(available_quantity) if bid_demand <= *available_quantity => *available_quantity -= bid_demand,
+                _ => return false,
             }
-
-            *available_quantity -= bid_demand;
         }
     }
 
@@ -107,11 +115,12 @@ mod tests {
     use super::*;
     use std::sync::Arc;
 
+    use crate::ids::BasketId;
     use crate::model::{Asset, AssetInfo, Bid, BidType, User};
 
     fn create_user(can_afford: bool) -> Arc<User> {
         Arc::new(User {
-            id: 1,
+            id: UserId(1),
             name: String::from("Test User"),
             balance: if can_afford { 1000.0 } else { 10.0 },
         })
@@ -122,7 +131,7 @@ mod tests {
     }
 
     fn create_basket(id: u64, assets: Vec<AssetInfo>) -> Basket {
-        Basket { id, assets }
+        Basket { id: BasketId(id), assets }
     }
 
     #[test]
@@ -207,7 +216,7 @@ mod tests {
         let bid2 = create_bid(user.clone(), 1, BidType::XOR, 200.0, None); // Full basket
 
         let bids = vec![bid1, bid2];
-        let total_value = total_value_of_bids_for_basket(&bids, &basket);
+        let total_value = total_value_of_bids_for_basket(&bids, &basket).unwrap();
 
         let expected_value = (0.5 * asset_info.total_value()) + asset_info.total_value();
         assert_eq!(total_value, expected_value);
@@ -219,7 +228,7 @@ mod tests {
         let user2 = Arc::new(User::new(2, "Bob", 200000.0));
 
         let basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![
                 AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0),
                 AssetInfo::new(Asset::new("ETH", "USD"), 5.0, 2000.0),
@@ -233,7 +242,7 @@ mod tests {
 
         let result = evaluate_xor_bids(&bids, &basket);
         assert!(result.is_some());
-        assert_eq!(result.unwrap().user.id, 2);  // Bob should win with the higher bid
+        assert_eq!(result.unwrap().user.id, UserId(2));  // Bob should win with the higher bid
     }
 
     #[test]
@@ -242,7 +251,7 @@ mod tests {
         let user2 = Arc::new(User::new(2, "Bob", 200000.0));
 
         let basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![
                 AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0),
                 AssetInfo::new(Asset::new("ETH", "USD"), 5.0, 2000.0),
@@ -263,7 +272,7 @@ mod tests {
         let user2 = Arc::new(User::new(2, "Bob", 2000000.0));
 
         let basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![
                 AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0),  // 60,000 total
                 AssetInfo::new(Asset::new("ETH", "USD"), 5.0, 2000.0),   // 10,000 total
@@ -280,10 +289,10 @@ mod tests {
         // Check that the highest bid is returned
         assert!(result.is_some());
         let (highest_bid, allocation) = result.unwrap();
-        assert_eq!(highest_bid.user.id, 2);  // Bob should win with the higher bid
+        assert_eq!(highest_bid.user.id, UserId(2));  // Bob should win with the higher bid
 
         // Check that the allocation is correct
-        let allocated_assets = allocation.get(&2).unwrap();  // Allocation for Bob (user 2)
+        let allocated_assets = allocation.get(&UserId(2)).unwrap();  // Allocation for Bob (user 2)
         assert_eq!(allocated_assets.len(), 2);  // Should have 2 assets allocated
         assert_eq!(allocated_assets[0].quantity, 2.0);  // Full BTC quantity
         assert_eq!(allocated_assets[1].quantity, 5.0);  // Full ETH quantity
@@ -295,7 +304,7 @@ mod tests {
         let user2 = Arc::new(User::new(2, "Bob", 2000000.0));
 
         let basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![
                 AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0),  // 60,000 total
                 AssetInfo::new(Asset::new("ETH", "USD"), 5.0, 2000.0),   // 10,000 total
@@ -313,13 +322,13 @@ mod tests {
         assert_eq!(valid_bids.len(), 2);
 
         // Check that user 1 (Alice) has 50% of the basket allocated
-        let alice_allocated_assets = allocation.get(&1).unwrap();  // Allocation for Alice (user 1)
+        let alice_allocated_assets = allocation.get(&UserId(1)).unwrap();  // Allocation for Alice (user 1)
         assert_eq!(alice_allocated_assets.len(), 2);  // Should have 2 assets allocated
         assert_eq!(alice_allocated_assets[0].quantity, 1.0);  // 50% of BTC (2.0 * 0.5)
         assert_eq!(alice_allocated_assets[1].quantity, 2.5);  // 50% of ETH (5.0 * 0.5)
 
         // Check that user 2 (Bob) has the full basket allocated
-        let bob_allocated_assets = allocation.get(&2).unwrap();  // Allocation for Bob (user 2)
+        let bob_allocated_assets = allocation.get(&UserId(2)).unwrap();  // Allocation for Bob (user 2)
         assert_eq!(bob_allocated_assets.len(), 2);  // Should have 2 assets allocated
         assert_eq!(bob_allocated_assets[0].quantity, 2.0);  // Full BTC quantity
         assert_eq!(bob_allocated_assets[1].quantity, 5.0);  // Full ETH quantity
@@ -331,7 +340,7 @@ mod tests {
         let user2 = Arc::new(User::new(2, "Bob", 2000000.0));
 
         let basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![
                 AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0),
                 AssetInfo::new(Asset::new("ETH", "USD"), 5.0, 2000.0),
@@ -346,7 +355,7 @@ mod tests {
         let (valid_bids, allocation) = evaluate_partial_or_bids(&bids, &basket);
 
         assert_eq!(valid_bids.len(), 2);
-        assert_eq!(allocation.get(&1).unwrap().len(), 2); // Check user 1 has two allocated assets
-        assert_eq!(allocation.get(&2).unwrap().len(), 2); // Check user 2 has two allocated assets
+        assert_eq!(allocation.get(&UserId(1)).unwrap().len(), 2); // Check user 1 has two allocated assets
+        assert_eq!(allocation.get(&UserId(2)).unwrap().len(), 2); // Check user 2 has two allocated assets
     }
 }
\ No newline at end of file
@@ -0,0 +1,81 @@
+use std::fmt;
+use std::num::ParseIntError;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+
+
+/// Declares a `u64` newtype id. Ids serialize as the bare number, so stored and wire
+/// formats are unchanged, but one kind of id cannot be passed where another is expected.
+macro_rules! id_type {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+        #[serde(transparent)]
+        pub struct $name(pub u64);
+
+        impl $name {
+            pub fn get(self) -> u64 {
+                self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+
+        impl From<u64> for $name {
+            fn from(id: u64) -> Self {
+                $name(id)
+            }
+        }
+
+        impl From<$name> for u64 {
+            fn from(id: $name) -> Self {
+                id.0
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = ParseIntError;
+
+            fn from_str(s: &str) -> Result<Self, ParseIntError> {
+                s.parse().map($name)
+            }
+        }
+    };
+}
+
+id_type!(
+    /// A user, and the ledger accounts it holds.
+    UserId
+);
+id_type!(
+    /// A basket of assets, auctioned at most once.
+    BasketId
+);
+id_type!(
+    /// A bid in the order book.
+    BidId
+);
+id_type!(
+    /// One run of an auction mechanism over a basket.
+    AuctionId
+);
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_ids_serialize_as_numbers() {
+        assert_eq!(serde_json::to_string(&UserId(7)).unwrap(), "7");
+        let map: HashMap<BasketId, f64> = serde_json::from_str("{\"3\": 1.5}").unwrap();
+        assert_eq!(map[&BasketId(3)], 1.5);
+        assert_eq!("42".parse::<BidId>(), Ok(BidId(42)));
+        assert_eq!(AuctionId::from(9).to_string(), "9");
+    }
+}
@@ -1,2 +1,7 @@
 pub mod model;
 pub mod helpers;
+pub mod error;
+pub mod ids;
+pub mod money;
+pub mod numeric;
+pub mod safe_math;
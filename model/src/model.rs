@@ -1,31 +1,43 @@
 use std::cmp::{PartialEq, Ordering};
 use std::collections::HashMap;
 use std::hash::Hash;
+use std::str::FromStr;
 use std::sync::Arc;
 use serde::{Serialize, Serializer, Deserialize, Deserializer};
 use serde::ser::SerializeStruct;
+use crate::error::ModelError;
+use crate::ids::{BasketId, UserId};
+use crate::money::{Code, Currency, Money};
+use crate::safe_math;
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
-    pub id: u64,
+    pub id: UserId,
     pub name: String,
     pub balance: f64,
 
 }
 impl User {
-    pub fn new(id: u64, name: &str, balance: f64) -> Self {
+    pub fn new(id: impl Into<UserId>, name: &str, balance: f64) -> Self {
         User {
-            id,
+            id: id.into(),
             name: name.to_string(),
             balance,
         }
     }
-    pub fn deposit(&mut self, amount: f64) {
-        self.balance += amount;
+    pub fn deposit(&mut self, amount: f64) -> Result<(), ModelError> {
+        let amount = safe_math::non_negative("deposit", amount)?;
+        self.balance = safe_math::checked_add("balance", self.balance, amount)?;
+        Ok(())
     }
-    pub fn withdraw(&mut self, amount: f64) {
-        self.balance -= amount;
+    pub fn withdraw(&mut self, amount: f64) -> Result<(), ModelError> {
+        let amount = safe_math::non_negative("withdrawal", amount)?;
+        if !self.can_afford(amount) {
+            return Err(ModelError::InsufficientBalance { user_id: self.id, needed: amount, available: self.balance });
+        }
+        self.balance = safe_math::checked_sub("balance", self.balance, amount)?;
+        Ok(())
     }
     pub fn can_afford(&self, amount: f64) -> bool {
         self.balance >= amount
@@ -50,9 +62,15 @@ impl Asset {
             quote: quote.to_string(),
         }
     }
-    pub fn from_str(s: &str) -> Self {
-        let parts: Vec<&str> = s.split('/').collect();
-        Asset::new(parts[0], parts[1])
+}
+impl FromStr for Asset {
+    type Err = ModelError;
+
+    fn from_str(s: &str) -> Result<Self, ModelError> {
+        match s.split_once('/') {
+            Some((base, quote)) if !base.is_empty() && !quote.is_empty() && !quote.contains('/') => Ok(Asset::new(base, quote)),
+            _ => Err(ModelError::InvalidAsset(s.to_string())),
+        }
     }
 }
 impl Eq for Asset{}
@@ -69,6 +87,49 @@ impl Hash for Asset {
 }
 
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum OptionKind {
+    Call,
+    Put
+}
+
+
+/// A tradable contract on an underlying pair. Expiries are Unix milliseconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Instrument {
+    Spot(Asset),
+    Perpetual { underlying: Asset },
+    Future { underlying: Asset, expiry: u64 },
+    Option { underlying: Asset, expiry: u64, strike: f64, kind: OptionKind },
+}
+impl Instrument {
+    pub fn underlying(&self) -> &Asset {
+        match self {
+            Instrument::Spot(asset) => asset,
+            Instrument::Perpetual { underlying } | Instrument::Future { underlying, .. } | Instrument::Option { underlying, .. } => underlying,
+        }
+    }
+    pub fn expiry(&self) -> Option<u64> {
+        match self {
+            Instrument::Future { expiry, .. } | Instrument::Option { expiry, .. } => Some(*expiry),
+            _ => None,
+        }
+    }
+    pub fn strike(&self) -> Option<f64> {
+        match self {
+            Instrument::Option { strike, .. } => Some(*strike),
+            _ => None,
+        }
+    }
+    pub fn option_kind(&self) -> Option<OptionKind> {
+        match self {
+            Instrument::Option { kind, .. } => Some(*kind),
+            _ => None,
+        }
+    }
+}
+
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AssetInfo {
     pub asset: Asset,
@@ -83,16 +144,25 @@ impl AssetInfo {
             price,
         }
     }
-    pub fn from_str(s: &str, quantity: f64, price: f64) -> Self {
-        AssetInfo {
-            asset: Asset::from_str(s),
+    pub fn from_str(s: &str, quantity: f64, price: f64) -> Result<Self, ModelError> {
+        Ok(AssetInfo {
+            asset: s.parse()?,
             quantity,
             price,
-        }
+        })
     }
+    /// Value in the quote currency as a bare number; `value` keeps the currency.
     pub fn total_value(&self) -> f64 {
         self.quantity * self.price
     }
+    /// The quantity held, in the base asset.
+    pub fn holding(&self) -> Money {
+        Money::new(self.quantity, Code::new(&self.asset.base))
+    }
+    /// The holding priced in the quote currency.
+    pub fn value(&self) -> Money {
+        self.holding().convert(Code::new(&self.asset.quote), self.price)
+    }
     pub fn update_price(&mut self, price: f64) {
         self.price = price;
     }
@@ -101,13 +171,18 @@ impl AssetInfo {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Basket {
-    pub id: u64,
+    pub id: BasketId,
     pub assets: Vec<AssetInfo>,
 }
 impl Basket {
     pub fn total_value(&self) -> f64 {
         self.assets.iter().map(|asset| asset.total_value()).sum()
     }
+    /// Total value in `currency`; fails if an asset is quoted in another one, which
+    /// `total_value` would silently add up.
+    pub fn value_in(&self, currency: &Code) -> Result<Money, ModelError> {
+        Money::total(currency.clone(), self.assets.iter().map(AssetInfo::value))
+    }
     pub fn update_price(&mut self, asset_str: &Asset, new_price: f64) {
         if let Some(asset) = self.assets.iter_mut().find(|a| a.asset == *asset_str) {
             asset.update_price(new_price);
@@ -147,7 +222,7 @@ impl PartialEq for BidType {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Bid {
     pub user: Arc<User>,
-    pub basket_id: u64,
+    pub basket_id: BasketId,
     pub bid_type: BidType,
     pub price: f64,
     pub quantity: Option<f64>
@@ -155,29 +230,50 @@ pub struct Bid {
 impl Bid {
     pub fn new(
         user: Arc<User>,
-        basket_id: u64,
+        basket_id: impl Into<BasketId>,
         bid_type: BidType,
         price: f64,
         quantity: Option<f64>
     ) -> Self {
         Bid {
             user,
-            basket_id,
+            basket_id: basket_id.into(),
             bid_type,
             price,
             quantity
         }
     }
     pub fn is_valid(&self) -> bool {
-        self.user.can_afford(self.price) && self.price > 0.0 && self.quantity.map_or(true, |q| q > 0.0 && q <= 1.0)
+        self.validate().is_ok()
+    }
+    /// Why the bid cannot take part in an auction, if it cannot.
+    pub fn validate(&self) -> Result<(), ModelError> {
+        if self.price.is_nan() || self.price <= 0.0 {
+            return Err(ModelError::NonPositivePrice(self.price));
+        }
+        if let Some(quantity) = self.quantity.filter(|q| q.is_nan() || *q <= 0.0 || *q > 1.0) {
+            return Err(ModelError::InvalidQuantity(quantity));
+        }
+        if !self.user.can_afford(self.price) {
+            return Err(ModelError::InsufficientBalance { user_id: self.user.id, needed: self.price, available: self.user.balance });
+        }
+        Ok(())
+    }
+    /// The price in the currency the auction quotes in.
+    pub fn price_in<C: Currency>(&self, currency: C) -> Money<C> {
+        Money::new(self.price, currency)
     }
     pub fn match_basket<'a>(&self, baskets: &'a [Basket]) -> Option<&'a Basket> {
         baskets.iter().find(|basket| basket.id == self.basket_id)
     }
-    pub fn estimate_value_of_bid(&self, basket: &Basket) -> f64 {
-        let basket_value = basket.total_value();
-        let proportion = self.quantity.unwrap_or(1.0);
-        proportion * basket_value
+    /// The value at basket prices of the share the bid asks for. Fails on a non-finite
+    /// quantity or price instead of returning NaN.
+    pub fn estimate_value_of_bid(&self, basket: &Basket) -> Result<f64, ModelError> {
+        let basket_value = safe_math::checked_sum("basket value", basket.assets.iter()
+            .map(|asset| safe_math::checked_mul("asset value", asset.quantity, asset.price))
+            .collect::<Result<Vec<f64>, ModelError>>()?)?;
+        let proportion = safe_math::finite("bid quantity", self.quantity.unwrap_or(1.0))?;
+        safe_math::checked_mul("bid value", proportion, basket_value)
     }
 }
 impl PartialEq for Bid {
@@ -204,7 +300,7 @@ mod tests {
     #[test]
     fn test_user_creation() {
         let user = User::new(1, "Alice", 1000.0);
-        assert_eq!(user.id, 1);
+        assert_eq!(user.id, UserId(1));
         assert_eq!(user.name, "Alice");
         assert_eq!(user.balance, 1000.0);
     }
@@ -212,9 +308,12 @@ mod tests {
     #[test]
     fn test_user_deposit_withdraw() {
         let mut user = User::new(1, "Alice", 1000.0);
-        user.deposit(500.0);
+        user.deposit(500.0).unwrap();
         assert_eq!(user.balance, 1500.0);
-        user.withdraw(300.0);
+        user.withdraw(300.0).unwrap();
+        assert_eq!(user.balance, 1200.0);
+        assert_eq!(user.withdraw(5000.0), Err(ModelError::InsufficientBalance { user_id: UserId(1), needed: 5000.0, available: 1200.0 }));
+        assert!(matches!(user.deposit(f64::NAN), Err(ModelError::InvalidAmount { what: "deposit", .. })));
         assert_eq!(user.balance, 1200.0);
     }
 
@@ -230,6 +329,17 @@ mod tests {
         let asset = Asset::new("BTC", "USD");
         assert_eq!(asset.base, "BTC");
         assert_eq!(asset.quote, "USD");
+        assert_eq!("ETH/USDC".parse(), Ok(Asset::new("ETH", "USDC")));
+        assert_eq!("BTCUSD".parse::<Asset>(), Err(ModelError::InvalidAsset("BTCUSD".to_string())));
+    }
+
+    #[test]
+    fn test_instrument_accessors() {
+        let option = Instrument::Option { underlying: Asset::new("BTC", "USD"), expiry: 1727596800000, strike: 56000.0, kind: OptionKind::Call };
+        assert_eq!(option.underlying(), &Asset::new("BTC", "USD"));
+        assert_eq!((option.expiry(), option.strike(), option.option_kind()), (Some(1727596800000), Some(56000.0), Some(OptionKind::Call)));
+        let perpetual = Instrument::Perpetual { underlying: Asset::new("ETH", "USDC") };
+        assert_eq!((perpetual.expiry(), perpetual.strike()), (None, None));
     }
 
     #[test]
@@ -237,6 +347,8 @@ mod tests {
         let asset = Asset::new("BTC", "USD");
         let asset_info = AssetInfo::new(asset, 2.0, 30000.0);
         assert_eq!(asset_info.total_value(), 60000.0);
+        assert_eq!(asset_info.holding(), Money::new(2.0, Code::new("BTC")));
+        assert_eq!(asset_info.value(), Money::new(60000.0, Code::new("USD")));
     }
 
     #[test]
@@ -253,10 +365,23 @@ mod tests {
         let asset1 = AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0);
         let asset2 = AssetInfo::new(Asset::new("ETH", "USD"), 5.0, 2000.0);
         let basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![asset1, asset2],
         };
         assert_eq!(basket.total_value(), 70000.0);
+        assert_eq!(basket.value_in(&Code::new("USD")).unwrap().amount(), 70000.0);
+    }
+
+    #[test]
+    fn test_basket_value_refuses_mixed_quote_currencies() {
+        let basket = Basket {
+            id: BasketId(1),
+            assets: vec![AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60000.0), AssetInfo::new(Asset::new("ETH", "BTC"), 10.0, 0.05)],
+        };
+        assert_eq!(
+            basket.value_in(&Code::new("USD")),
+            Err(ModelError::CurrencyMismatch { expected: "USD".to_string(), found: "BTC".to_string() })
+        );
     }
 
     #[test]
@@ -264,7 +389,7 @@ mod tests {
         let asset = Asset::new("BTC", "USD");
         let asset_info = AssetInfo::new(asset.clone(), 2.0, 30000.0);
         let mut basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![asset_info],
         };
         basket.update_price(&asset, 35000.0);
@@ -276,7 +401,7 @@ mod tests {
     fn test_basket_is_asset_in_basket() {
         let asset_info = AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0);
         let basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![asset_info.clone()],
         };
         assert!(basket.is_asset_in_basket(&asset_info));
@@ -289,7 +414,7 @@ mod tests {
     fn test_basket_asset_amount_in_basket() {
         let asset_info = AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0);
         let basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![asset_info.clone()],
         };
         assert_eq!(basket.asset_amount_in_basket(&asset_info), 2.0);
@@ -302,7 +427,7 @@ mod tests {
     fn test_basket_asset_value_in_basket() {
         let asset_info = AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0);
         let basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![asset_info.clone()],
         };
         assert_eq!(basket.asset_value_in_basket(&asset_info), 60000.0);
@@ -316,7 +441,7 @@ mod tests {
         let asset1 = AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0);
         let asset2 = AssetInfo::new(Asset::new("ETH", "USD"), 5.0, 2000.0);
         let basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![asset1.clone(), asset2.clone()],
         };
         let valuation = basket.assets_valuation();
@@ -333,8 +458,8 @@ mod tests {
     fn test_bid_creation() {
         let user = Arc::new(User::new(1, "Alice", 1000.0));
         let bid = Bid::new(user.clone(), 1, BidType::XOR, 500.0, Some(0.2));
-        assert_eq!(bid.user.id, 1);
-        assert_eq!(bid.basket_id, 1);
+        assert_eq!(bid.user.id, UserId(1));
+        assert_eq!(bid.basket_id, BasketId(1));
         assert_eq!(bid.bid_type, BidType::XOR);
         assert_eq!(bid.price, 500.0);
         assert_eq!(bid.quantity, Some(0.2));
@@ -348,6 +473,8 @@ mod tests {
 
         let invalid_bid = Bid::new(user.clone(), 1, BidType::XOR, 1500.0, Some(0.2));
         assert!(!invalid_bid.is_valid());
+        assert_eq!(invalid_bid.validate(), Err(ModelError::InsufficientBalance { user_id: UserId(1), needed: 1500.0, available: 1000.0 }));
+        assert_eq!(Bid::new(user, 1, BidType::XOR, 500.0, Some(1.5)).validate(), Err(ModelError::InvalidQuantity(1.5)));
     }
 
     #[test]
@@ -356,18 +483,18 @@ mod tests {
         let bid = Bid::new(user.clone(), 1, BidType::XOR, 500.0, Some(0.2));
 
         let basket1 = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![],
         };
         let basket2 = Basket {
-            id: 2,
+            id: BasketId(2),
             assets: vec![],
         };
         let baskets = vec![basket1, basket2];
 
         let matched_basket = bid.match_basket(&baskets);
         assert!(matched_basket.is_some());
-        assert_eq!(matched_basket.unwrap().id, 1);
+        assert_eq!(matched_basket.unwrap().id, BasketId(1));
     }
 
     #[test]
@@ -422,18 +549,25 @@ mod tests {
         let asset1 = AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 30000.0);
         let asset2 = AssetInfo::new(Asset::new("ETH", "USD"), 5.0, 2000.0);
         let basket = Basket {
-            id: 1,
+            id: BasketId(1),
             assets: vec![asset1, asset2],
         };
 
         // Bid for 50% of the basket
         let bid_half = Bid::new(user.clone(), 1, BidType::XOR, 500.0, Some(0.5));
-        let estimated_value_half = bid_half.estimate_value_of_bid(&basket);
+        let estimated_value_half = bid_half.estimate_value_of_bid(&basket).unwrap();
         assert_eq!(estimated_value_half, 35000.0);
 
         // Bid for the entire basket (None quantity)
         let bid_full = Bid::new(user.clone(), 1, BidType::XOR, 1000.0, None);
-        let estimated_value_full = bid_full.estimate_value_of_bid(&basket);
+        let estimated_value_full = bid_full.estimate_value_of_bid(&basket).unwrap();
         assert_eq!(estimated_value_full, 70000.0);
+
+        // A corrupted price is rejected rather than turned into a NaN value
+        let mut corrupted = basket.clone();
+        corrupted.update_price(&Asset::new("ETH", "USD"), f64::INFINITY);
+        assert!(matches!(bid_full.estimate_value_of_bid(&corrupted), Err(ModelError::NotFinite { what: "asset value", .. })));
+        let bid_nan = Bid::new(user.clone(), 1, BidType::XOR, 1000.0, Some(f64::NAN));
+        assert!(bid_nan.estimate_value_of_bid(&basket).is_err());
     }
 }
\ No newline at end of file
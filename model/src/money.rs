@@ -0,0 +1,212 @@
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, Neg, Sub};
+use serde::{Serialize, Deserialize};
+use crate::error::ModelError;
+
+
+/// A unit amounts are counted in: a fiat currency or an asset such as BTC.
+pub trait Currency: Clone + PartialEq + fmt::Debug {
+    fn code(&self) -> &str;
+}
+
+/// A currency fixed by the type. Amounts in one add up with `+`; adding amounts in two
+/// different ones does not compile.
+pub trait StaticCurrency: Currency + Copy + Default {}
+
+macro_rules! static_currency {
+    ($($(#[$meta:meta])* $name:ident => $code:literal),* $(,)?) => {
+        $(
+            $(#[$meta])*
+            #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+            pub struct $name;
+
+            impl Currency for $name {
+                fn code(&self) -> &str {
+                    $code
+                }
+            }
+
+            impl StaticCurrency for $name {}
+        )*
+    };
+}
+
+static_currency!(
+    Usd => "USD",
+    Eur => "EUR",
+    Btc => "BTC",
+    Eth => "ETH",
+);
+
+/// A currency only known at runtime, e.g. the quote currency of an `Asset`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Code(String);
+
+impl Code {
+    pub fn new(code: &str) -> Self {
+        Code(code.to_string())
+    }
+}
+
+impl Currency for Code {
+    fn code(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+
+/// An amount of one currency. Amounts in different currencies never mix: with a
+/// `StaticCurrency` the compiler rejects it, with a runtime `Code` the checked operations
+/// return `CurrencyMismatch`. Going from one currency to another takes an explicit rate,
+/// see `convert` and the FX module.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Money<C: Currency = Code> {
+    amount: f64,
+    currency: C,
+}
+
+impl<C: Currency> Money<C> {
+    pub fn new(amount: f64, currency: C) -> Self {
+        Money { amount, currency }
+    }
+
+    pub fn zero(currency: C) -> Self {
+        Money::new(0.0, currency)
+    }
+
+    pub fn amount(&self) -> f64 {
+        self.amount
+    }
+
+    pub fn currency(&self) -> &C {
+        &self.currency
+    }
+
+    pub fn code(&self) -> &str {
+        self.currency.code()
+    }
+
+    /// The bare amount, if it is in `currency`.
+    pub fn amount_in(&self, currency: &C) -> Result<f64, ModelError> {
+        if self.code() != currency.code() {
+            return Err(ModelError::CurrencyMismatch { expected: currency.code().to_string(), found: self.code().to_string() });
+        }
+        Ok(self.amount)
+    }
+
+    pub fn checked_add(self, other: Money<C>) -> Result<Money<C>, ModelError> {
+        let amount = self.amount + other.amount_in(&self.currency)?;
+        Ok(Money::new(amount, self.currency))
+    }
+
+    pub fn checked_sub(self, other: Money<C>) -> Result<Money<C>, ModelError> {
+        let amount = self.amount - other.amount_in(&self.currency)?;
+        Ok(Money::new(amount, self.currency))
+    }
+
+    /// Sums amounts that must all be in `currency`.
+    pub fn total(currency: C, amounts: impl IntoIterator<Item = Money<C>>) -> Result<Money<C>, ModelError> {
+        amounts.into_iter().try_fold(Money::zero(currency), Money::checked_add)
+    }
+
+    /// Multiplies by a plain number, e.g. a quantity or a fee rate.
+    pub fn scale(self, factor: f64) -> Money<C> {
+        Money::new(self.amount * factor, self.currency)
+    }
+
+    /// The amount in `to` at `rate` units of `to` per unit of this currency.
+    pub fn convert<D: Currency>(self, to: D, rate: f64) -> Money<D> {
+        Money::new(self.amount * rate, to)
+    }
+
+    /// Forgets a static currency, keeping its code.
+    pub fn tagged(&self) -> Money<Code> {
+        Money::new(self.amount, Code::new(self.code()))
+    }
+}
+
+impl Money<Code> {
+    /// Recovers a static currency, if this amount is in it.
+    pub fn typed<C: StaticCurrency>(&self) -> Result<Money<C>, ModelError> {
+        let currency = C::default();
+        if currency.code() != self.code() {
+            return Err(ModelError::CurrencyMismatch { expected: currency.code().to_string(), found: self.code().to_string() });
+        }
+        Ok(Money::new(self.amount, currency))
+    }
+}
+
+impl<C: Currency> fmt::Display for Money<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.amount, self.code())
+    }
+}
+
+impl<C: StaticCurrency> Add for Money<C> {
+    type Output = Money<C>;
+
+    fn add(self, other: Money<C>) -> Money<C> {
+        Money::new(self.amount + other.amount, self.currency)
+    }
+}
+
+impl<C: StaticCurrency> Sub for Money<C> {
+    type Output = Money<C>;
+
+    fn sub(self, other: Money<C>) -> Money<C> {
+        Money::new(self.amount - other.amount, self.currency)
+    }
+}
+
+impl<C: StaticCurrency> Neg for Money<C> {
+    type Output = Money<C>;
+
+    fn neg(self) -> Money<C> {
+        Money::new(-self.amount, self.currency)
+    }
+}
+
+impl<C: StaticCurrency> Sum for Money<C> {
+    fn sum<I: Iterator<Item = Money<C>>>(iter: I) -> Money<C> {
+        iter.fold(Money::zero(C::default()), Add::add)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_static_currencies_add_and_convert() {
+        let total: Money<Usd> = [Money::new(100.0, Usd), Money::new(50.0, Usd)].into_iter().sum();
+        assert_eq!(total - Money::new(30.0, Usd), Money::new(120.0, Usd));
+        assert_eq!(total.to_string(), "150 USD");
+
+        let btc = total.convert(Btc, 1.0 / 60_000.0);
+        assert_eq!(btc.code(), "BTC");
+        assert_eq!(btc.tagged().typed::<Btc>().unwrap(), btc);
+        assert!(btc.tagged().typed::<Usd>().is_err());
+    }
+
+    #[test]
+    fn test_runtime_currencies_refuse_to_mix() {
+        let usd = Money::new(100.0, Code::new("USD"));
+        let btc = Money::new(1.0, Code::new("BTC"));
+        assert_eq!(usd.clone().checked_add(usd.clone()).unwrap().amount(), 200.0);
+        assert_eq!(
+            usd.clone().checked_sub(btc.clone()),
+            Err(ModelError::CurrencyMismatch { expected: "USD".to_string(), found: "BTC".to_string() })
+        );
+        assert!(Money::total(Code::new("USD"), [usd.clone(), btc]).is_err());
+        assert_eq!(Money::total(Code::new("USD"), [usd.clone(), usd.scale(0.5)]).unwrap().amount(), 150.0);
+    }
+}
@@ -0,0 +1,65 @@
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Mul, Sub, SubAssign};
+#[cfg(feature = "decimal")]
+pub use rust_decimal::Decimal;
+#[cfg(feature = "decimal")]
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+
+
+/// The number type winner determination and allocation are computed in. Bids and assets
+/// hold `f64`s and are converted on the way in, so the same solver can sum welfare in
+/// `f64` or, with the `decimal` feature, exactly in `Decimal`.
+pub trait Numeric:
+    Copy + PartialOrd + fmt::Debug + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + AddAssign + SubAssign + Sum
+{
+    const ZERO: Self;
+    const ONE: Self;
+
+    fn from_float(value: f64) -> Self;
+    fn to_float(self) -> f64;
+}
+
+impl Numeric for f64 {
+    const ZERO: f64 = 0.0;
+    const ONE: f64 = 1.0;
+
+    fn from_float(value: f64) -> f64 {
+        value
+    }
+
+    fn to_float(self) -> f64 {
+        self
+    }
+}
+
+#[cfg(feature = "decimal")]
+impl Numeric for Decimal {
+    const ZERO: Decimal = Decimal::ZERO;
+    const ONE: Decimal = Decimal::ONE;
+
+    /// Drops the binary noise, so 0.1 becomes exactly 0.1. NaN, infinities and values out
+    /// of a `Decimal`'s range become zero.
+    fn from_float(value: f64) -> Decimal {
+        Decimal::from_f64(value).unwrap_or(Decimal::ZERO)
+    }
+
+    fn to_float(self) -> f64 {
+        self.to_f64().unwrap_or(f64::NAN)
+    }
+}
+
+
+#[cfg(all(test, feature = "decimal"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decimal_sums_exactly() {
+        let tenths: Decimal = [0.1, 0.2].into_iter().map(Decimal::from_float).sum();
+        assert_eq!(tenths, Decimal::from_float(0.3));
+        assert_ne!(0.1 + 0.2, 0.3);
+        assert_eq!(Decimal::from_float(f64::NAN), Decimal::ZERO);
+        assert_eq!(Decimal::from_float(60_000.5).to_float(), 60_000.5);
+    }
+}
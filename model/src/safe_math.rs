@@ -0,0 +1,78 @@
+use crate::error::ModelError;
+
+
+/// Rejects NaN and infinities, e.g. a price or quantity read from outside.
+pub fn finite(what: &'static str, value: f64) -> Result<f64, ModelError> {
+    if !value.is_finite() {
+        return Err(ModelError::NotFinite { what, value });
+    }
+    Ok(value)
+}
+
+/// Rejects negative amounts as well as NaN and infinities.
+pub fn non_negative(what: &'static str, value: f64) -> Result<f64, ModelError> {
+    if !value.is_finite() || value < 0.0 {
+        return Err(ModelError::InvalidAmount { what, value });
+    }
+    Ok(value)
+}
+
+/// Rejects zero, negative amounts, NaN and infinities, e.g. a price something is divided by.
+pub fn positive(what: &'static str, value: f64) -> Result<f64, ModelError> {
+    if !value.is_finite() || value <= 0.0 {
+        return Err(ModelError::NonPositive { what, value });
+    }
+    Ok(value)
+}
+
+fn checked(what: &'static str, lhs: f64, rhs: f64, result: f64) -> Result<f64, ModelError> {
+    finite(what, lhs)?;
+    finite(what, rhs)?;
+    if !result.is_finite() {
+        return Err(ModelError::Overflow(what));
+    }
+    Ok(result)
+}
+
+/// `lhs + rhs` for finite operands, failing instead of overflowing to infinity.
+pub fn checked_add(what: &'static str, lhs: f64, rhs: f64) -> Result<f64, ModelError> {
+    checked(what, lhs, rhs, lhs + rhs)
+}
+
+pub fn checked_sub(what: &'static str, lhs: f64, rhs: f64) -> Result<f64, ModelError> {
+    checked(what, lhs, rhs, lhs - rhs)
+}
+
+pub fn checked_mul(what: &'static str, lhs: f64, rhs: f64) -> Result<f64, ModelError> {
+    checked(what, lhs, rhs, lhs * rhs)
+}
+
+/// Sums finite values, failing on the first one that is not or on overflow.
+pub fn checked_sum(what: &'static str, values: impl IntoIterator<Item = f64>) -> Result<f64, ModelError> {
+    values.into_iter().try_fold(0.0, |total, value| checked_add(what, total, value))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guards_reject_corrupt_inputs() {
+        assert!(matches!(finite("price", f64::NAN), Err(ModelError::NotFinite { what: "price", .. })));
+        assert!(finite("price", f64::INFINITY).is_err());
+        assert_eq!(non_negative("deposit", -1.0), Err(ModelError::InvalidAmount { what: "deposit", value: -1.0 }));
+        assert_eq!(positive("price", 0.0), Err(ModelError::NonPositive { what: "price", value: 0.0 }));
+        assert_eq!(positive("price", 2.5), Ok(2.5));
+    }
+
+    #[test]
+    fn test_checked_arithmetic_refuses_overflow() {
+        assert_eq!(checked_add("balance", 1.0, 2.0), Ok(3.0));
+        assert_eq!(checked_mul("value", f64::MAX, 2.0), Err(ModelError::Overflow("value")));
+        assert_eq!(checked_sub("balance", f64::MIN, f64::MAX), Err(ModelError::Overflow("balance")));
+        assert!(matches!(checked_add("balance", f64::NAN, 1.0), Err(ModelError::NotFinite { what: "balance", .. })));
+        assert_eq!(checked_sum("value", [1.0, 2.0, 3.0]), Ok(6.0));
+        assert_eq!(checked_sum("value", [f64::MAX, f64::MAX]), Err(ModelError::Overflow("value")));
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use pyo3::prelude::*;
+use serde_json::{json, Value};
+use api::exchange::{AuctionRequest, Exchange, Mechanism};
+use auction::config::{Config, FeeSchedule};
+use auction::wdp::WDPSolver;
+use model::ids::{BidId, UserId};
+use model::model::{Basket, Bid};
+use crate::{from_python, to_python, value_error};
+
+
+/// Winners among `bids` by one of the WDP solvers: "xor", "or", "vcg", "cca",
+/// "branch_and_bound" or "dynamic_programming". Baskets and bids are dicts shaped like
+/// their JSON; the result is the positions of the winning bids in `bids` and their welfare.
+#[pyfunction]
+pub fn solve_winners(solver: &str, basket: &Bound<'_, PyAny>, bids: &Bound<'_, PyAny>) -> PyResult<(Vec<usize>, f64)> {
+    let basket: Basket = from_python(basket)?;
+    let bids: Vec<Bid> = from_python(bids)?;
+    let (winners, welfare) = match solver {
+        "xor" => {
+            let winner: Vec<&Bid> = WDPSolver::solve_xor(&bids, &basket).into_iter().collect();
+            let welfare = winner.iter().map(|bid| bid.price).sum();
+            (winner, welfare)
+        }
+        "or" => {
+            let (winners, _) = WDPSolver::solve_or(&bids, &basket);
+            let welfare = winners.iter().map(|bid| bid.price).sum();
+            (winners, welfare)
+        }
+        "vcg" => WDPSolver::maximize_welfare_vcg(&bids, &basket),
+        "cca" => WDPSolver::maximize_welfare_cca(&bids, &basket),
+        "branch_and_bound" => WDPSolver::branch_and_bound(&bids, &basket),
+        "dynamic_programming" => WDPSolver::dynamic_programming(&bids, &basket),
+        other => return Err(value_error(format!("unknown solver '{}'", other))),
+    };
+    let positions = winners.iter()
+        .filter_map(|winner| bids.iter().position(|bid| std::ptr::eq(bid, *winner)))
+        .collect();
+    Ok((positions, welfare))
+}
+
+/// Runs a whole auction, clearing included, on a fresh in-memory exchange: `mechanism` is
+/// "xor", "or", "vcg" or "cca". Returns a dict of the winning bids' positions and each
+/// user's payment and allocation, keyed by the user ids in `bids`.
+#[pyfunction]
+#[pyo3(signature = (mechanism, basket, bids, fee_rate=0.0))]
+pub fn run_auction<'py>(py: Python<'py>, mechanism: &str, basket: &Bound<'py, PyAny>, bids: &Bound<'py, PyAny>, fee_rate: f64) -> PyResult<Bound<'py, PyAny>> {
+    let mechanism: Mechanism = serde_json::from_value(Value::String(mechanism.to_lowercase()))
+        .map_err(|_| value_error(format!("unknown mechanism '{}'", mechanism)))?;
+    let basket: Basket = from_python(basket)?;
+    let bids: Vec<Bid> = from_python(bids)?;
+
+    let config = Config { fees: FeeSchedule { rate: fee_rate, ..FeeSchedule::default() }, ..Config::default() };
+    config.validate().map_err(value_error)?;
+    let mut exchange = Exchange::new().with_config(&config);
+    // Exchange ids of the bidders, by the ids they came with, and the reverse
+    let mut users: HashMap<UserId, UserId> = HashMap::new();
+    let mut callers: HashMap<UserId, UserId> = HashMap::new();
+    for bid in &bids {
+        if let Entry::Vacant(entry) = users.entry(bid.user.id) {
+            let user = exchange.register_user(&bid.user.name, bid.user.balance).map_err(value_error)?;
+            entry.insert(user.id);
+            callers.insert(user.id, bid.user.id);
+        }
+    }
+    let basket_id = exchange.create_basket(basket.assets).map_err(value_error)?.id;
+    let mut positions: HashMap<BidId, usize> = HashMap::new();
+    for (position, bid) in bids.iter().enumerate() {
+        let record = exchange.submit_bid(users[&bid.user.id], basket_id, bid.bid_type.clone(), bid.price, bid.quantity).map_err(value_error)?;
+        positions.insert(record.id, position);
+    }
+
+    let outcome = py.detach(|| exchange.run_auction(&AuctionRequest::new(basket_id, mechanism))).map_err(value_error)?;
+    let by_caller = |values: HashMap<UserId, Value>| -> serde_json::Map<String, Value> {
+        values.into_iter().map(|(user_id, value)| (callers[&user_id].to_string(), value)).collect()
+    };
+    let result = json!({
+        "mechanism": outcome.mechanism,
+        "winners": outcome.winning_bids.iter().map(|id| positions[id]).collect::<Vec<_>>(),
+        "payments": by_caller(outcome.payments.into_iter().map(|(user_id, paid)| (user_id, json!(paid))).collect()),
+        "allocation": by_caller(outcome.allocation.into_iter().map(|(user_id, assets)| (user_id, json!(assets))).collect()),
+        "fees": outcome.report.settlements.iter().map(|settlement| settlement.fee).sum::<f64>(),
+    });
+    to_python(py, &result)
+}
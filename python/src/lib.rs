@@ -0,0 +1,102 @@
+//! Python bindings of the pricer and the auction engines, built with maturin:
+//! `maturin develop --release` from this directory, then `import combidex`.
+
+pub mod auctions;
+pub mod pricer;
+
+use std::fmt::Display;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+
+fn value_error(error: impl Display) -> PyErr {
+    PyValueError::new_err(error.to_string())
+}
+
+/// Reads a Python value shaped like `T`'s JSON, e.g. a dict for a basket.
+fn from_python<T: DeserializeOwned>(value: &Bound<'_, PyAny>) -> PyResult<T> {
+    let text: String = value.py().import("json")?.call_method1("dumps", (value,))?.extract()?;
+    serde_json::from_str(&text).map_err(value_error)
+}
+
+fn to_python<'py>(py: Python<'py>, value: &impl Serialize) -> PyResult<Bound<'py, PyAny>> {
+    let text = serde_json::to_string(value).map_err(value_error)?;
+    py.import("json")?.call_method1("loads", (text,))
+}
+
+
+#[pymodule]
+fn combidex(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<pricer::PyQuantoOption>()?;
+    module.add_class::<pricer::PyImpliedVolatility>()?;
+    module.add_function(wrap_pyfunction!(pricer::price_quanto_chain, module)?)?;
+    module.add_function(wrap_pyfunction!(pricer::implied_volatilities, module)?)?;
+    module.add_function(wrap_pyfunction!(auctions::solve_winners, module)?)?;
+    module.add_function(wrap_pyfunction!(auctions::run_auction, module)?)?;
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+    use pyo3::types::PyDict;
+
+    /// Runs `code` with this module bound to `combidex`.
+    fn run_python(code: &CStr) {
+        Python::initialize();
+        Python::attach(|py| {
+            let globals = PyDict::new(py);
+            globals.set_item("combidex", pyo3::wrap_pymodule!(combidex)(py)).unwrap();
+            if let Err(error) = py.run(code, Some(&globals), None) {
+                panic!("{}", error);
+            }
+        });
+    }
+
+    #[test]
+    fn test_pricer_from_python() {
+        run_python(c"
+option = combidex.QuantoOption(100.0, 110.0, 0.5, 0.5, domestic_rate=0.03, fx_volatility=0.1, correlation=-0.3)
+call, put = option.price()
+assert call > 0 and put > 0
+assert abs(option.implied_volatility(call) - 0.5) < 1e-3
+
+problem = combidex.ImpliedVolatility(100.0, 110.0, 0.5, 1.0, rate=0.03)
+price = problem.black_scholes_price(0.4)
+assert abs(combidex.ImpliedVolatility(100.0, 110.0, 0.5, price, rate=0.03).solve() - 0.4) < 1e-6
+try:
+    combidex.QuantoOption(-1.0, 100.0, 0.5, 0.5)
+    raise AssertionError('a negative spot was accepted')
+except ValueError:
+    pass
+");
+    }
+
+    #[test]
+    fn test_auctions_from_python() {
+        run_python(c"
+basket = {'id': 1, 'assets': [{'asset': {'base': 'BTC', 'quote': 'USD'}, 'quantity': 1.0, 'price': 60000.0}]}
+bids = [
+    {'user': {'id': 7, 'name': 'Alice', 'balance': 100000.0}, 'basket_id': 1, 'bid_type': 'XOR', 'price': 61000.0, 'quantity': None},
+    {'user': {'id': 8, 'name': 'Bob', 'balance': 100000.0}, 'basket_id': 1, 'bid_type': 'XOR', 'price': 59000.0, 'quantity': None},
+]
+assert combidex.solve_winners('xor', basket, bids) == ([0], 61000.0)
+assert combidex.solve_winners('branch_and_bound', basket, bids)[1] == 61000.0
+
+outcome = combidex.run_auction('XOR', basket, bids, fee_rate=0.01)
+assert outcome['winners'] == [0]
+assert outcome['payments'] == {'7': 61000.0}
+assert abs(outcome['fees'] - 610.0) < 1e-9
+assert outcome['allocation']['7'][0]['quantity'] == 1.0
+try:
+    combidex.run_auction('dutch', basket, bids)
+    raise AssertionError('an unknown mechanism was accepted')
+except ValueError:
+    pass
+");
+    }
+}
@@ -0,0 +1,168 @@
+use numpy::{IntoPyArray, PyArray1, PyReadonlyArray1};
+use pyo3::prelude::*;
+use quanto_pricer::chain::price_chain_with;
+use quanto_pricer::fourier::{FftSettings, QuantoOption};
+use quanto_pricer::implied_vol::ImpliedVolatility;
+use crate::value_error;
+
+
+/// A quanto option, priced by FFT.
+#[pyclass(name = "QuantoOption", module = "combidex", frozen)]
+#[derive(Clone)]
+pub struct PyQuantoOption {
+    inner: QuantoOption,
+}
+
+#[pymethods]
+impl PyQuantoOption {
+    #[new]
+    #[pyo3(signature = (spot, strike, volatility, time_to_maturity, domestic_rate=0.0, foreign_rate=0.0, fx_volatility=0.0, correlation=0.0, dividend_yield=0.0))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        spot: f64,
+        strike: f64,
+        volatility: f64,
+        time_to_maturity: f64,
+        domestic_rate: f64,
+        foreign_rate: f64,
+        fx_volatility: f64,
+        correlation: f64,
+        dividend_yield: f64,
+    ) -> PyResult<Self> {
+        let inner = QuantoOption::builder()
+            .spot(spot)
+            .strike(strike)
+            .volatility(volatility)
+            .time_to_maturity(time_to_maturity)
+            .domestic_rate(domestic_rate)
+            .foreign_rate(foreign_rate)
+            .quanto(fx_volatility, correlation)
+            .dividend_yield(dividend_yield)
+            .build()
+            .map_err(value_error)?;
+        Ok(PyQuantoOption { inner })
+    }
+
+    #[getter]
+    fn spot(&self) -> f64 {
+        self.inner.spot
+    }
+
+    #[getter]
+    fn strike(&self) -> f64 {
+        self.inner.strike
+    }
+
+    #[getter]
+    fn volatility(&self) -> f64 {
+        self.inner.volatility
+    }
+
+    #[getter]
+    fn time_to_maturity(&self) -> f64 {
+        self.inner.time_to_maturity
+    }
+
+    /// `(call, put)` prices.
+    fn price(&self, py: Python<'_>) -> PyResult<(f64, f64)> {
+        let price = py.detach(|| self.inner.calculate_price_fft()).map_err(value_error)?;
+        Ok((price.call, price.put))
+    }
+
+    /// The volatility at which this option prices at `market_price`.
+    #[pyo3(signature = (market_price, is_call=true))]
+    fn implied_volatility(&self, market_price: f64, is_call: bool) -> PyResult<f64> {
+        self.inner.implied_volatility(market_price, is_call).map_err(value_error)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("QuantoOption(spot={}, strike={}, volatility={}, time_to_maturity={})", self.inner.spot, self.inner.strike, self.inner.volatility, self.inner.time_to_maturity)
+    }
+}
+
+
+/// A Black–Scholes implied volatility problem.
+#[pyclass(name = "ImpliedVolatility", module = "combidex", frozen)]
+#[derive(Clone)]
+pub struct PyImpliedVolatility {
+    inner: ImpliedVolatility,
+}
+
+#[pymethods]
+impl PyImpliedVolatility {
+    #[new]
+    #[pyo3(signature = (spot, strike, time_to_maturity, market_price, rate=0.0, is_call=true, dividend_yield=0.0))]
+    fn new(spot: f64, strike: f64, time_to_maturity: f64, market_price: f64, rate: f64, is_call: bool, dividend_yield: f64) -> PyResult<Self> {
+        let builder = ImpliedVolatility::builder()
+            .spot(spot)
+            .strike(strike)
+            .time_to_maturity(time_to_maturity)
+            .market_price(market_price)
+            .rate(rate)
+            .dividend_yield(dividend_yield);
+        let inner = if is_call { builder.build() } else { builder.put().build() }.map_err(value_error)?;
+        Ok(PyImpliedVolatility { inner })
+    }
+
+    fn solve(&self) -> PyResult<f64> {
+        self.inner.implied_volatility().map_err(value_error)
+    }
+
+    fn black_scholes_price(&self, sigma: f64) -> f64 {
+        self.inner.black_scholes_price(sigma)
+    }
+}
+
+
+/// Calls and puts at each of `strikes` on one underlying and expiry, as numpy arrays. The
+/// chain is priced on one FFT grid without holding the GIL.
+#[pyfunction]
+#[pyo3(signature = (spot, strikes, volatility, time_to_maturity, domestic_rate=0.0, foreign_rate=0.0, fx_volatility=0.0, correlation=0.0, dividend_yield=0.0))]
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn price_quanto_chain<'py>(
+    py: Python<'py>,
+    spot: f64,
+    strikes: PyReadonlyArray1<'py, f64>,
+    volatility: f64,
+    time_to_maturity: f64,
+    domestic_rate: f64,
+    foreign_rate: f64,
+    fx_volatility: f64,
+    correlation: f64,
+    dividend_yield: f64,
+) -> PyResult<(Bound<'py, PyArray1<f64>>, Bound<'py, PyArray1<f64>>)> {
+    let options = strikes.as_array().iter()
+        .map(|strike| PyQuantoOption::new(spot, *strike, volatility, time_to_maturity, domestic_rate, foreign_rate, fx_volatility, correlation, dividend_yield).map(|option| option.inner))
+        .collect::<PyResult<Vec<_>>>()?;
+    let prices = py.detach(|| price_chain_with(&options, &FftSettings::default())).map_err(value_error)?;
+    let (calls, puts): (Vec<f64>, Vec<f64>) = prices.into_iter().map(|price| (price.call, price.put)).unzip();
+    Ok((calls.into_pyarray(py), puts.into_pyarray(py)))
+}
+
+/// Black–Scholes implied volatilities of options at `strikes` priced at `prices`, as a
+/// numpy array with NaN where no volatility fits.
+#[pyfunction]
+#[pyo3(signature = (spot, strikes, prices, time_to_maturity, rate=0.0, is_call=true, dividend_yield=0.0))]
+#[allow(clippy::too_many_arguments)]
+pub fn implied_volatilities<'py>(
+    py: Python<'py>,
+    spot: f64,
+    strikes: PyReadonlyArray1<'py, f64>,
+    prices: PyReadonlyArray1<'py, f64>,
+    time_to_maturity: f64,
+    rate: f64,
+    is_call: bool,
+    dividend_yield: f64,
+) -> PyResult<Bound<'py, PyArray1<f64>>> {
+    let (strikes, prices) = (strikes.as_array(), prices.as_array());
+    if strikes.len() != prices.len() {
+        return Err(value_error("strikes and prices differ in length"));
+    }
+    let problems: Vec<ImpliedVolatility> = strikes.iter().zip(prices.iter())
+        .map(|(strike, price)| ImpliedVolatility { spot, strike: *strike, r: rate, time_to_maturity, market_price: *price, is_call, dividend_yield })
+        .collect();
+    let volatilities: Vec<f64> = py.detach(|| {
+        problems.iter().map(|problem| problem.validate().ok().and_then(|_| problem.implied_volatility().ok()).unwrap_or(f64::NAN)).collect()
+    });
+    Ok(volatilities.into_pyarray(py))
+}
@@ -0,0 +1,42 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use quanto_pricer::chain::price_chain;
+use quanto_pricer::fourier::QuantoOption;
+
+
+/// A Deribit-sized chain: 8 expiries with 40 strikes each.
+fn chain() -> Vec<QuantoOption> {
+    let mut options = Vec::new();
+    for expiry in [0.01, 0.02, 0.05, 0.1, 0.25, 0.5, 0.75, 1.0] {
+        for i in 0..40 {
+            options.push(QuantoOption {
+                spot: 60000.0,
+                strike: 30000.0 + 1500.0 * i as f64,
+                domestic_rate: 0.03,
+                foreign_rate: 0.0,
+                volatility: 0.55,
+                fx_volatility: 0.1,
+                time_to_maturity: expiry,
+                correlation: 0.2,
+                dividend_yield: 0.0,
+            });
+        }
+    }
+    options
+}
+
+fn bench_chain(c: &mut Criterion) {
+    let options = chain();
+    let mut group = c.benchmark_group("price_chain");
+    group.sample_size(10);
+
+    group.bench_function("one_fft_per_option", |b| {
+        b.iter(|| options.iter().map(|o| o.calculate_price_fft().unwrap()).collect::<Vec<_>>())
+    });
+    group.bench_function("one_fft_per_expiry", |b| {
+        b.iter(|| price_chain(black_box(&options)).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_chain);
+criterion_main!(benches);
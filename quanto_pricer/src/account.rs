@@ -0,0 +1,399 @@
+use std::collections::HashSet;
+use std::fmt;
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::Mutex;
+use crate::fetch::{FetchError, FetchSettings, RateLimiter, get_json, get_json_authorized};
+use crate::fourier::QuantoOption;
+use crate::freshness::now_millis;
+use crate::history::{TimeRange, Trade};
+use crate::instrument::{Venue, parse_instrument, time_to_maturity};
+use crate::margin::{Portfolio, SpotPosition};
+use crate::provider::{OptionQuote, malformed};
+use crate::scenarios::OptionPosition;
+use model::model::{Instrument, OptionKind};
+use auction::config::ProviderConfig;
+
+/// Deribit's largest page of user trades.
+const TRADES_PER_PAGE: usize = 1000;
+/// Tokens this close to expiry are refreshed before use.
+const TOKEN_MARGIN_MS: u64 = 60_000;
+
+
+/// A Deribit API key.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+impl Credentials {
+    pub fn new(client_id: &str, client_secret: &str) -> Self {
+        Credentials { client_id: client_id.to_string(), client_secret: client_secret.to_string() }
+    }
+
+    /// The key configured for a venue, if any.
+    pub fn from_config(config: &ProviderConfig) -> Option<Self> {
+        config.credentials().map(|(client_id, client_secret)| Credentials::new(client_id, client_secret))
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials").field("client_id", &self.client_id).field("client_secret", &"***").finish()
+    }
+}
+
+/// How private requests prove who they are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Auth {
+    /// Each request signed with the key's secret; nothing to expire.
+    Hmac(Credentials),
+    /// A bearer token from `client_credentials`, refreshed shortly before it expires.
+    OAuth(Credentials),
+}
+
+impl Auth {
+    fn credentials(&self) -> &Credentials {
+        match self {
+            Auth::Hmac(credentials) | Auth::OAuth(credentials) => credentials,
+        }
+    }
+}
+
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Deribit's `deri-hmac-sha256` header for a bodiless GET of `uri`, the path and query
+/// as sent, at Unix millisecond `timestamp`.
+pub fn hmac_authorization(credentials: &Credentials, uri: &str, timestamp: u64, nonce: &str) -> String {
+    let signed = format!("{}\n{}\nGET\n{}\n\n", timestamp, nonce, uri);
+    let signature = hmac_sha256::HMAC::mac(signed.as_bytes(), credentials.client_secret.as_bytes());
+    format!("deri-hmac-sha256 id={},ts={},sig={},nonce={}", credentials.client_id, timestamp, hex(&signature), nonce)
+}
+
+fn nonce() -> String {
+    rand::thread_rng().sample_iter(&Alphanumeric).take(12).map(char::from).collect()
+}
+
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AccessToken {
+    pub access_token: String,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    /// Seconds from issue.
+    pub expires_in: u64,
+    /// Unix milliseconds, set on arrival.
+    #[serde(skip)]
+    pub expires_at: u64,
+}
+
+
+/// Balances and margin of one currency's subaccount, all in that currency.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AccountSummary {
+    pub currency: String,
+    pub equity: f64,
+    pub balance: f64,
+    pub available_funds: f64,
+    pub margin_balance: f64,
+    pub initial_margin: f64,
+    pub maintenance_margin: f64,
+    #[serde(default)]
+    pub delta_total: f64,
+    #[serde(default)]
+    pub options_value: f64,
+    #[serde(default)]
+    pub options_pl: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstrumentKind {
+    Future,
+    Option,
+    Spot,
+    FutureCombo,
+    OptionCombo,
+}
+
+impl InstrumentKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            InstrumentKind::Future => "future",
+            InstrumentKind::Option => "option",
+            InstrumentKind::Spot => "spot",
+            InstrumentKind::FutureCombo => "future_combo",
+            InstrumentKind::OptionCombo => "option_combo",
+        }
+    }
+}
+
+/// An open position. `size` is negative when short: underlying units for options, USD for
+/// futures, whose size in the underlying is `size_currency`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Position {
+    pub instrument_name: String,
+    pub kind: InstrumentKind,
+    pub size: f64,
+    #[serde(default)]
+    pub size_currency: Option<f64>,
+    pub average_price: f64,
+    pub mark_price: f64,
+    pub index_price: f64,
+    #[serde(default)]
+    pub delta: f64,
+    #[serde(default)]
+    pub floating_profit_loss: f64,
+    #[serde(default)]
+    pub initial_margin: f64,
+    #[serde(default)]
+    pub maintenance_margin: f64,
+}
+
+/// One of the account's own fills, with what it cost in fees.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct UserTrade {
+    #[serde(flatten)]
+    pub trade: Trade,
+    pub order_id: String,
+    pub fee: f64,
+    pub fee_currency: String,
+}
+
+
+/// Deribit's private API for one key: balances, positions and fills.
+#[derive(Debug)]
+pub struct DeribitAccount {
+    settings: FetchSettings,
+    client: Client,
+    limiter: RateLimiter,
+    auth: Auth,
+    token: Mutex<Option<AccessToken>>,
+}
+
+impl DeribitAccount {
+    pub fn new(settings: FetchSettings, auth: Auth) -> Self {
+        let limiter = settings.rate_limiter();
+        DeribitAccount { settings, client: Client::new(), limiter, auth, token: Mutex::new(None) }
+    }
+
+    /// A bearer token valid for at least another minute, from `/public/auth` if the cached
+    /// one is missing or about to expire.
+    pub async fn access_token(&self) -> Result<String, FetchError> {
+        let mut cached = self.token.lock().await;
+        let now = now_millis();
+        if let Some(token) = cached.as_ref().filter(|token| token.expires_at > now + TOKEN_MARGIN_MS) {
+            return Ok(token.access_token.clone());
+        }
+        let credentials = self.auth.credentials();
+        let grant = match cached.as_ref().and_then(|token| token.refresh_token.as_ref()) {
+            Some(refresh_token) => format!("grant_type=refresh_token&refresh_token={}", refresh_token),
+            None => format!("grant_type=client_credentials&client_id={}&client_secret={}", credentials.client_id, credentials.client_secret),
+        };
+        let url = format!("{}/public/auth?{}", self.settings.base_url, grant);
+        let response = get_json(&self.client, &url, &self.limiter, &self.settings.retry).await?;
+        let mut token: AccessToken = serde_json::from_value(response.get("result").cloned().ok_or_else(|| malformed("auth"))?)
+            .map_err(|e| FetchError::Api(e.to_string()))?;
+        token.expires_at = now_millis() + token.expires_in * 1000;
+        let access_token = token.access_token.clone();
+        *cached = Some(token);
+        Ok(access_token)
+    }
+
+    /// GETs a private endpoint, e.g. "/private/get_positions?currency=BTC".
+    pub async fn get(&self, path: &str) -> Result<Value, FetchError> {
+        let url = format!("{}{}", self.settings.base_url, path);
+        match &self.auth {
+            Auth::Hmac(credentials) => {
+                let parsed = Url::parse(&url).map_err(|e| FetchError::Request(e.to_string()))?;
+                let uri = match parsed.query() {
+                    Some(query) => format!("{}?{}", parsed.path(), query),
+                    None => parsed.path().to_string(),
+                };
+                let sign = || Some(hmac_authorization(credentials, &uri, now_millis(), &nonce()));
+                get_json_authorized(&self.client, &url, &self.limiter, &self.settings.retry, sign).await
+            }
+            Auth::OAuth(_) => {
+                let bearer = format!("Bearer {}", self.access_token().await?);
+                get_json_authorized(&self.client, &url, &self.limiter, &self.settings.retry, || Some(bearer.clone())).await
+            }
+        }
+    }
+
+    async fn result<T: serde::de::DeserializeOwned>(&self, path: &str, what: &str) -> Result<T, FetchError> {
+        let response = self.get(path).await?;
+        let result = response.get("result").cloned().ok_or_else(|| malformed(what))?;
+        serde_json::from_value(result).map_err(|e| FetchError::Api(e.to_string()))
+    }
+
+    pub async fn account_summary(&self, currency: &str) -> Result<AccountSummary, FetchError> {
+        self.result(&format!("/private/get_account_summary?currency={}", currency), "account summary").await
+    }
+
+    /// Open positions in `currency`, of every kind unless `kind` narrows them.
+    pub async fn positions(&self, currency: &str, kind: Option<InstrumentKind>) -> Result<Vec<Position>, FetchError> {
+        let mut path = format!("/private/get_positions?currency={}", currency);
+        if let Some(kind) = kind {
+            path.push_str(&format!("&kind={}", kind.name()));
+        }
+        self.result(&path, "positions").await
+    }
+
+    /// The account's fills in `currency` during `range`, oldest first, paged like
+    /// public trades.
+    pub async fn user_trades(&self, currency: &str, range: TimeRange) -> Result<Vec<UserTrade>, FetchError> {
+        let mut trades: Vec<UserTrade> = Vec::new();
+        let mut seen = HashSet::new();
+        let mut start = range.start;
+        loop {
+            let path = format!(
+                "/private/get_user_trades_by_currency_and_time?currency={}&start_timestamp={}&end_timestamp={}&count={}&sorting=asc",
+                currency, start, range.end.saturating_sub(1), TRADES_PER_PAGE,
+            );
+            let response = self.get(&path).await?;
+            let result = response.get("result").ok_or_else(|| malformed("user trades"))?;
+            let page: Vec<UserTrade> = serde_json::from_value(result.get("trades").cloned().unwrap_or(Value::Null))
+                .map_err(|e| FetchError::Api(e.to_string()))?;
+            let has_more = result.get("has_more").and_then(Value::as_bool).unwrap_or(false);
+
+            let last = page.last().map(|fill| fill.trade.timestamp);
+            trades.extend(page.into_iter().filter(|fill| seen.insert(fill.trade.trade_id.clone())));
+            match last {
+                Some(last) if has_more => start = if last > start { last } else { start + 1 },
+                _ => break,
+            }
+        }
+        trades.retain(|fill| range.contains(fill.trade.timestamp));
+        Ok(trades)
+    }
+}
+
+
+/// Seeds a margin [`Portfolio`] from Deribit positions at `now`. Options are priced at
+/// their index with the mark volatility from `chain`; futures count as their size in the
+/// underlying, ignoring basis. Returns the portfolio and the instruments left out for
+/// having no mark, having expired or being combos.
+pub fn portfolio(positions: &[Position], chain: &[OptionQuote], now: u64) -> (Portfolio, Vec<String>) {
+    let mut portfolio = Portfolio::default();
+    let mut skipped = Vec::new();
+    for position in positions {
+        let added = match position.kind {
+            InstrumentKind::Option => option_position(position, chain, now).map(|option| portfolio.options.push(option)),
+            InstrumentKind::Future | InstrumentKind::Spot => {
+                let quantity = position.size_currency.unwrap_or(position.size / position.mark_price);
+                portfolio.spots.push(SpotPosition { spot: position.index_price, quantity });
+                Some(())
+            }
+            InstrumentKind::FutureCombo | InstrumentKind::OptionCombo => None,
+        };
+        if added.is_none() {
+            skipped.push(position.instrument_name.clone());
+        }
+    }
+    (portfolio, skipped)
+}
+
+fn option_position(position: &Position, chain: &[OptionQuote], now: u64) -> Option<OptionPosition> {
+    let instrument = parse_instrument(Venue::Deribit, &position.instrument_name).ok()?;
+    let Instrument::Option { strike, kind, .. } = instrument else { return None };
+    let volatility = chain.iter()
+        .find(|quote| quote.instrument_name == position.instrument_name)?
+        .implied_volatility? / 100.0;
+    let option = QuantoOption::builder()
+        .spot(position.index_price)
+        .strike(strike)
+        .volatility(volatility)
+        .time_to_maturity(time_to_maturity(&instrument, now)?)
+        .build()
+        .ok()?;
+    Some(OptionPosition::new(option, kind == OptionKind::Call, position.size))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex as StdMutex};
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+    use crate::fetch::mock;
+
+    #[test]
+    fn test_hmac_signature_matches_reference() {
+        // RFC 4231 test case 2
+        assert_eq!(hex(&hmac_sha256::HMAC::mac(b"what do ya want for nothing?", b"Jefe")), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+
+        let credentials = Credentials::new("AMANDA", "SECRET");
+        let header = hmac_authorization(&credentials, "/api/v2/private/get_positions?currency=BTC", 1_700_000_000_000, "abc");
+        let expected = hmac_sha256::HMAC::mac(b"1700000000000\nabc\nGET\n/api/v2/private/get_positions?currency=BTC\n\n", b"SECRET");
+        assert_eq!(header, format!("deri-hmac-sha256 id=AMANDA,ts=1700000000000,sig={},nonce=abc", hex(&expected)));
+        assert!(!format!("{:?}", credentials).contains("SECRET"));
+    }
+
+    #[test]
+    fn test_private_endpoints_send_credentials() {
+        Runtime::new().unwrap().block_on(async {
+            let heads = Arc::new(StdMutex::new(Vec::new()));
+            let seen = heads.clone();
+            let (base_url, _) = mock::serve_with_head(Duration::ZERO, move |path, head, _| {
+                seen.lock().unwrap().push(head.to_string());
+                if path.starts_with("/public/auth") {
+                    (200, r#"{"result":{"access_token":"tok","refresh_token":"ref","expires_in":900}}"#.to_string())
+                } else if path.starts_with("/private/get_account_summary") {
+                    (200, r#"{"result":{"currency":"BTC","equity":2.5,"balance":2.4,"available_funds":2.0,"margin_balance":2.5,"initial_margin":0.5,"maintenance_margin":0.3}}"#.to_string())
+                } else {
+                    (200, r#"{"result":{"trades":[{"trade_id":"1","instrument_name":"BTC-PERPETUAL","timestamp":5000,"price":64000.0,"amount":10.0,"direction":"sell","index_price":64010.0,"mark_price":64005.0,"order_id":"o1","fee":0.0001,"fee_currency":"BTC"}],"has_more":false}}"#.to_string())
+                }
+            }).await;
+            let settings = FetchSettings { base_url, ..FetchSettings::default() };
+
+            let oauth = DeribitAccount::new(settings.clone(), Auth::OAuth(Credentials::new("id", "secret")));
+            assert_eq!(oauth.account_summary("BTC").await.unwrap().maintenance_margin, 0.3);
+            oauth.account_summary("BTC").await.unwrap();
+            {
+                let heads = heads.lock().unwrap();
+                assert_eq!(heads.iter().filter(|head| head.contains("/public/auth")).count(), 1);
+                assert!(heads.last().unwrap().to_lowercase().contains("authorization: bearer tok"));
+            }
+
+            let hmac = DeribitAccount::new(settings, Auth::Hmac(Credentials::new("id", "secret")));
+            let fills = hmac.user_trades("BTC", TimeRange::new(0, 10_000)).await.unwrap();
+            assert_eq!((fills.len(), fills[0].order_id.as_str(), fills[0].trade.direction), (1, "o1", crate::history::Direction::Sell));
+            assert!(heads.lock().unwrap().last().unwrap().to_lowercase().contains("authorization: deri-hmac-sha256 id=id,ts="));
+        });
+    }
+
+    #[test]
+    fn test_positions_seed_a_margin_portfolio() {
+        let now = 1_727_000_000_000;
+        let position = |name: &str, kind, size, size_currency| Position {
+            instrument_name: name.to_string(), kind, size, size_currency, average_price: 0.0, mark_price: 64000.0,
+            index_price: 64000.0, delta: 0.0, floating_profit_loss: 0.0, initial_margin: 0.0, maintenance_margin: 0.0,
+        };
+        let positions = vec![
+            position("BTC-29SEP24-60000-P", InstrumentKind::Option, -2.0, None),
+            position("BTC-29SEP24-70000-C", InstrumentKind::Option, 1.0, None),
+            position("BTC-PERPETUAL", InstrumentKind::Future, 32000.0, Some(0.5)),
+        ];
+        let quote: OptionQuote = serde_json::from_value(serde_json::json!({
+            "instrument_name": "BTC-29SEP24-60000-P", "strike": 60000.0, "expiration_timestamp": 1_727_596_800_000u64,
+            "option_type": "put", "price_index": "btc_usd", "settlement_currency": "BTC", "implied_volatility": 50.0,
+            "market_price": 0.01, "delta": null, "gamma": null, "vega": null, "theta": null,
+        })).unwrap();
+
+        let (portfolio, skipped) = portfolio(&positions, &[quote], now);
+        assert_eq!(skipped, vec!["BTC-29SEP24-70000-C".to_string()]);
+        assert_eq!(portfolio.options.len(), 1);
+        let put = &portfolio.options[0];
+        assert!(!put.is_call);
+        assert_eq!((put.quantity, put.option.strike, put.option.volatility), (-2.0, 60000.0, 0.5));
+        assert_eq!((portfolio.spots[0].spot, portfolio.spots[0].quantity), (64000.0, 0.5));
+    }
+}
@@ -0,0 +1,218 @@
+use rand::Rng;
+use rand_distr::StandardNormal;
+use statrs::distribution::{Normal, ContinuousCDF};
+use crate::curve::YieldCurve;
+use crate::fourier::{OptionPrice, quanto_carry};
+use crate::monte_carlo::{McEstimate, McOptionPrice, McSettings};
+
+
+/// Average-price option on `fixings` equally spaced fixings, the last one at maturity.
+pub struct AsianOption {
+    pub spot: f64,
+    pub strike: f64,
+    pub domestic_rate: f64,
+    pub foreign_rate: f64,
+    pub volatility: f64,
+    pub fx_volatility: f64,
+    pub time_to_maturity: f64,
+    pub correlation: f64,
+    pub fixings: usize,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct ConvergenceDiagnostics {
+    /// Control-variate coefficients for the call and the put.
+    pub beta: OptionPrice,
+    /// Standard error of crude Monte Carlo over that of the control-variate estimate.
+    pub variance_reduction: OptionPrice,
+    /// Estimates after 1/8, 1/4, 1/2 and all of the paths.
+    pub checkpoints: Vec<(usize, OptionPrice)>,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct AsianMcPrice {
+    pub price: McOptionPrice,
+    /// Crude Monte Carlo estimate from the same paths, without the control variate.
+    pub crude: McOptionPrice,
+    pub diagnostics: ConvergenceDiagnostics,
+}
+
+
+impl AsianOption {
+    /// Takes the domestic and foreign rates from curves at this option's maturity; the
+    /// term structure between monitoring dates is flattened to the maturity zero rates.
+    pub fn with_curves(mut self, domestic: &YieldCurve, foreign: &YieldCurve) -> Self {
+        self.domestic_rate = domestic.zero_rate(self.time_to_maturity);
+        self.foreign_rate = foreign.zero_rate(self.time_to_maturity);
+        self
+    }
+
+    fn carry(&self) -> f64 {
+        quanto_carry(self.domestic_rate, self.foreign_rate, self.volatility, self.fx_volatility, self.correlation)
+    }
+
+    /// Mean and variance of the log of the geometric average.
+    fn geometric_moments(&self) -> (f64, f64) {
+        let n = self.fixings.max(1) as f64;
+        let t = self.time_to_maturity;
+        let mean = self.spot.ln() + (self.carry() - 0.5 * self.volatility.powi(2)) * t * (n + 1.0) / (2.0 * n);
+        let variance = self.volatility.powi(2) * t * (n + 1.0) * (2.0 * n + 1.0) / (6.0 * n * n);
+        (mean, variance)
+    }
+
+    /// Closed form for the geometric average, which is lognormal under Black–Scholes.
+    pub fn price_geometric(&self) -> OptionPrice {
+        let normal = Normal::new(0.0, 1.0).unwrap();
+        let (mean, variance) = self.geometric_moments();
+        let discount = (-self.domestic_rate * self.time_to_maturity).exp();
+        let forward = (mean + 0.5 * variance).exp();
+
+        let d1 = (mean - self.strike.ln() + variance) / variance.sqrt();
+        let d2 = d1 - variance.sqrt();
+
+        OptionPrice::new(
+            discount * (forward * normal.cdf(d1) - self.strike * normal.cdf(d2)),
+            discount * (self.strike * normal.cdf(-d2) - forward * normal.cdf(-d1)),
+        )
+    }
+
+    /// Arithmetic average by Monte Carlo, with the geometric average as control variate.
+    /// Paths are simulated exactly at the fixing dates, so `settings.steps` is not used.
+    pub fn price_arithmetic(&self, settings: &McSettings) -> AsianMcPrice {
+        let mut rng = settings.rng();
+        let n = self.fixings.max(1);
+        let dt = self.time_to_maturity / n as f64;
+        let drift = (self.carry() - 0.5 * self.volatility.powi(2)) * dt;
+        let vol_dt = self.volatility * dt.sqrt();
+        let discount = (-self.domestic_rate * self.time_to_maturity).exp();
+
+        let mut arithmetic = Vec::with_capacity(settings.paths);
+        let mut geometric = Vec::with_capacity(settings.paths);
+        for _ in 0..settings.paths {
+            let mut log_price = self.spot.ln();
+            let mut sum = 0.0;
+            let mut log_sum = 0.0;
+            for _ in 0..n {
+                let z: f64 = rng.sample(StandardNormal);
+                log_price += drift + vol_dt * z;
+                sum += log_price.exp();
+                log_sum += log_price;
+            }
+            arithmetic.push(sum / n as f64);
+            geometric.push((log_sum / n as f64).exp());
+        }
+
+        let exact = self.price_geometric();
+        let call_payoff = |average: f64| discount * (average - self.strike).max(0.0);
+        let put_payoff = |average: f64| discount * (self.strike - average).max(0.0);
+
+        let (call, crude_call, beta_call) = control_variate(&arithmetic, &geometric, call_payoff, exact.call);
+        let (put, crude_put, beta_put) = control_variate(&arithmetic, &geometric, put_payoff, exact.put);
+
+        let checkpoints = [8, 4, 2, 1].iter()
+            .map(|fraction| settings.paths / fraction)
+            .filter(|paths| *paths > 1)
+            .map(|paths| {
+                let call = control_variate(&arithmetic[..paths], &geometric[..paths], call_payoff, exact.call).0;
+                let put = control_variate(&arithmetic[..paths], &geometric[..paths], put_payoff, exact.put).0;
+                (paths, OptionPrice::new(call.value, put.value))
+            })
+            .collect();
+
+        AsianMcPrice {
+            price: McOptionPrice { call, put },
+            crude: McOptionPrice { call: crude_call, put: crude_put },
+            diagnostics: ConvergenceDiagnostics {
+                beta: OptionPrice::new(beta_call, beta_put),
+                variance_reduction: OptionPrice::new(
+                    crude_call.std_error / call.std_error,
+                    crude_put.std_error / put.std_error,
+                ),
+                checkpoints,
+            },
+        }
+    }
+}
+
+/// Returns the control-variate estimate, the crude estimate and the coefficient used.
+fn control_variate(
+    arithmetic: &[f64],
+    geometric: &[f64],
+    payoff: impl Fn(f64) -> f64,
+    exact: f64,
+) -> (McEstimate, McEstimate, f64) {
+    let target: Vec<f64> = arithmetic.iter().map(|a| payoff(*a)).collect();
+    let control: Vec<f64> = geometric.iter().map(|g| payoff(*g)).collect();
+    let n = target.len() as f64;
+
+    let target_mean = target.iter().sum::<f64>() / n;
+    let control_mean = control.iter().sum::<f64>() / n;
+    let covariance: f64 = target.iter().zip(&control).map(|(y, x)| (y - target_mean) * (x - control_mean)).sum();
+    let variance: f64 = control.iter().map(|x| (x - control_mean).powi(2)).sum();
+    let beta = if variance > 0.0 { covariance / variance } else { 0.0 };
+
+    let adjusted: Vec<f64> = target.iter().zip(&control).map(|(y, x)| y - beta * (x - exact)).collect();
+    (McEstimate::from_samples(&adjusted), McEstimate::from_samples(&target), beta)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fourier::QuantoOption;
+
+    fn asian(strike: f64) -> AsianOption {
+        AsianOption {
+            spot: 100.0,
+            strike,
+            domestic_rate: 0.05,
+            foreign_rate: 0.0,
+            volatility: 0.3,
+            fx_volatility: 0.0,
+            time_to_maturity: 1.0,
+            correlation: 0.0,
+            fixings: 12,
+        }
+    }
+
+    #[test]
+    fn test_single_fixing_geometric_is_european() {
+        let mut option = asian(100.0);
+        option.fixings = 1;
+        let european = QuantoOption {
+            spot: 100.0,
+            strike: 100.0,
+            domestic_rate: 0.05,
+            foreign_rate: 0.0,
+            volatility: 0.3,
+            fx_volatility: 0.0,
+            time_to_maturity: 1.0,
+            correlation: 0.0,
+            dividend_yield: 0.0,
+        }.calculate_price_fft().unwrap();
+
+        let price = option.price_geometric();
+        assert!((price.call - european.call).abs() < 1e-5);
+        assert!((price.put - european.put).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_arithmetic_with_control_variate() {
+        let option = asian(100.0);
+        let settings = McSettings { paths: 20_000, steps: 0, seed: 11 };
+        let result = option.price_arithmetic(&settings);
+        let geometric = option.price_geometric();
+
+        // AM-GM: the arithmetic call is worth more and the put less than the geometric one
+        assert!(result.price.call.value > geometric.call);
+        assert!(result.price.put.value < geometric.put);
+        assert!((result.price.call.value - result.crude.call.value).abs() < 4.0 * result.crude.call.std_error);
+
+        assert!(result.diagnostics.variance_reduction.call > 5.0);
+        assert!(result.diagnostics.beta.call > 0.9);
+        assert_eq!(result.diagnostics.checkpoints.len(), 4);
+        assert_eq!(result.diagnostics.checkpoints[3].0, 20_000);
+    }
+}
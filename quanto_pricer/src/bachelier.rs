@@ -0,0 +1,193 @@
+use std::f64::consts::PI;
+use statrs::distribution::{Normal, Continuous, ContinuousCDF};
+use crate::implied_vol::{ImpliedVolError, ImpliedVolatility, black};
+
+const MAX_ITERATIONS: usize = 100;
+
+
+/// Distribution assumed for the underlying at expiry. Black–Scholes volatilities are
+/// relative; Bachelier (normal) volatilities are absolute, in price units per √year, and
+/// allow forwards and strikes at or below zero, as for spreads and basis trades.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PricingModel {
+    #[default]
+    BlackScholes,
+    Bachelier,
+}
+
+impl PricingModel {
+    /// Undiscounted price of an option on `forward`.
+    pub fn forward_price(&self, forward: f64, strike: f64, volatility: f64, time_to_maturity: f64, is_call: bool) -> f64 {
+        let s = volatility * time_to_maturity.sqrt();
+        match self {
+            PricingModel::BlackScholes => black(forward, strike, s, is_call),
+            PricingModel::Bachelier => bachelier(forward, strike, s, is_call),
+        }
+    }
+
+    /// Whether the model is defined for this forward and strike.
+    pub fn supports(&self, forward: f64, strike: f64) -> bool {
+        match self {
+            PricingModel::BlackScholes => forward > 0.0 && strike > 0.0,
+            PricingModel::Bachelier => forward.is_finite() && strike.is_finite(),
+        }
+    }
+}
+
+
+/// Undiscounted Bachelier price of an option on `forward` with total standard deviation `s`.
+pub fn bachelier(forward: f64, strike: f64, s: f64, is_call: bool) -> f64 {
+    let sign = if is_call { 1.0 } else { -1.0 };
+    let moneyness = sign * (forward - strike);
+    if s <= 0.0 {
+        return moneyness.max(0.0);
+    }
+    let normal = Normal::new(0.0, 1.0).unwrap();
+    let d = moneyness / s;
+    moneyness * normal.cdf(d) + s * normal.pdf(d)
+}
+
+
+/// Total normal standard deviation σ_N√T matching an undiscounted price. The option is
+/// priced from its time value, which is the out-of-the-money price by parity. Since that
+/// price is at most s/√(2π), the at-the-money inversion is a lower bound, and Newton's
+/// method runs from there inside a bracket, in log space for small time values.
+pub(crate) fn implied_normal_std_dev(forward: f64, strike: f64, price: f64, is_call: bool) -> Result<f64, ImpliedVolError> {
+    let intrinsic = if is_call { (forward - strike).max(0.0) } else { (strike - forward).max(0.0) };
+    let distance = (forward - strike).abs();
+    let tolerance = 1e-14 * forward.abs().max(strike.abs()).max(price.abs());
+    if price < intrinsic - tolerance {
+        return Err(ImpliedVolError::BelowIntrinsic { price, intrinsic });
+    }
+    let target = price - intrinsic;
+    if target <= tolerance {
+        return Err(ImpliedVolError::NoTimeValue);
+    }
+
+    let normal = Normal::new(0.0, 1.0).unwrap();
+    let time_value = |s: f64| bachelier(distance, 0.0, s, false);
+    let mut lower = target * (2.0 * PI).sqrt();
+    let mut upper = 2.0 * lower;
+    while time_value(upper) < target {
+        lower = upper;
+        upper *= 2.0;
+        if !upper.is_finite() {
+            return Err(ImpliedVolError::NoConvergence { iterations: 0, last_vol: lower });
+        }
+    }
+
+    let log_space = target < 1e-4 * distance;
+    let mut s = lower;
+    for _ in 0..MAX_ITERATIONS {
+        let value = time_value(s);
+        if value > target {
+            upper = s;
+        } else {
+            lower = s;
+        }
+
+        let vega = normal.pdf(distance / s);
+        let step = if log_space {
+            if value <= 0.0 { f64::NAN } else { (value.ln() - target.ln()) * value / vega }
+        } else {
+            (value - target) / vega
+        };
+
+        if step.abs() <= 1e-15 * s || (value - target).abs() <= 1e-15 * target {
+            return Ok(s - step);
+        }
+        let next = s - step;
+        s = if next.is_finite() && next > lower && next < upper { next } else { 0.5 * (lower + upper) };
+    }
+    Err(ImpliedVolError::NoConvergence { iterations: MAX_ITERATIONS, last_vol: s })
+}
+
+
+impl ImpliedVolatility {
+    /// Price at volatility `sigma` under `model`; see [`ImpliedVolatility::black_scholes_price`].
+    pub fn model_price(&self, model: PricingModel, sigma: f64) -> f64 {
+        let discount = (-self.r * self.time_to_maturity).exp();
+        discount * model.forward_price(self.forward(), self.strike, sigma, self.time_to_maturity, self.is_call)
+    }
+
+    /// Implied volatility under `model`. Bachelier volatilities are absolute, and spot and
+    /// strike may then be zero or negative.
+    pub fn implied_volatility_with(&self, model: PricingModel) -> Result<f64, ImpliedVolError> {
+        match model {
+            PricingModel::BlackScholes => self.implied_volatility(),
+            PricingModel::Bachelier => {
+                if !(self.spot.is_finite() && self.strike.is_finite()) {
+                    return Err(ImpliedVolError::InvalidInput("spot and strike must be finite"));
+                }
+                if self.time_to_maturity.is_nan() || self.time_to_maturity <= 0.0 {
+                    return Err(ImpliedVolError::InvalidInput("time to maturity must be positive"));
+                }
+                if !self.market_price.is_finite() || !self.r.is_finite() || !self.dividend_yield.is_finite() {
+                    return Err(ImpliedVolError::InvalidInput("price, rate and yield must be finite"));
+                }
+                let discount = (-self.r * self.time_to_maturity).exp();
+                let s = implied_normal_std_dev(self.forward(), self.strike, self.market_price / discount, self.is_call)
+                    .map_err(|error| match error {
+                        ImpliedVolError::BelowIntrinsic { price, intrinsic } => {
+                            ImpliedVolError::BelowIntrinsic { price: price * discount, intrinsic: intrinsic * discount }
+                        }
+                        other => other,
+                    })?;
+                Ok(s / self.time_to_maturity.sqrt())
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problem(spot: f64, strike: f64, market_price: f64, is_call: bool) -> ImpliedVolatility {
+        ImpliedVolatility { spot, strike, r: 0.02, time_to_maturity: 0.75, market_price, is_call, dividend_yield: 0.0 }
+    }
+
+    #[test]
+    fn test_bachelier_price_and_parity() {
+        // At the money the price is s/√(2π)
+        assert!((bachelier(100.0, 100.0, 10.0, true) - 10.0 / (2.0 * PI).sqrt()).abs() < 1e-12);
+        for (forward, strike) in [(5.0, -3.0), (-2.0, 1.0), (0.0, 0.0)] {
+            let parity = bachelier(forward, strike, 4.0, true) - bachelier(forward, strike, 4.0, false);
+            assert!((parity - (forward - strike)).abs() < 1e-12);
+        }
+        assert_eq!(bachelier(-1.0, -3.0, 0.0, true), 2.0);
+
+        // For small vols the models agree at the money once the normal vol is σF
+        let lognormal = PricingModel::BlackScholes.forward_price(100.0, 100.0, 0.01, 1.0, true);
+        let normal = PricingModel::Bachelier.forward_price(100.0, 100.0, 1.0, 1.0, true);
+        assert!((lognormal - normal).abs() / normal < 1e-4);
+        assert!(!PricingModel::BlackScholes.supports(-1.0, 1.0) && PricingModel::Bachelier.supports(-1.0, 1.0));
+    }
+
+    #[test]
+    fn test_implied_normal_vol_round_trip() {
+        for (spot, strike) in [(10.0, 12.0), (-4.0, 1.0), (0.0, 0.5), (3.0, -8.0), (1.0, 25.0)] {
+            for is_call in [true, false] {
+                let price = problem(spot, strike, 0.0, is_call).model_price(PricingModel::Bachelier, 6.0);
+                let vol = problem(spot, strike, price, is_call).implied_volatility_with(PricingModel::Bachelier).unwrap();
+                assert!((vol - 6.0).abs() < 1e-8, "{} {} {}: {}", spot, strike, is_call, vol);
+            }
+        }
+
+        // Black–Scholes is still available through the same entry point
+        let price = problem(100.0, 110.0, 0.0, true).model_price(PricingModel::BlackScholes, 0.4);
+        let vol = problem(100.0, 110.0, price, true).implied_volatility_with(PricingModel::BlackScholes).unwrap();
+        assert!((vol - 0.4).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_implied_normal_vol_failures() {
+        let below = problem(10.0, 5.0, 1.0, true).implied_volatility_with(PricingModel::Bachelier);
+        assert!(matches!(below, Err(ImpliedVolError::BelowIntrinsic { .. })));
+        let intrinsic = problem(-5.0, 0.0, 0.0, false).model_price(PricingModel::Bachelier, 0.0);
+        let flat = problem(-5.0, 0.0, intrinsic, false).implied_volatility_with(PricingModel::Bachelier);
+        assert_eq!(flat, Err(ImpliedVolError::NoTimeValue));
+        assert!(problem(f64::NAN, 0.0, 1.0, true).implied_volatility_with(PricingModel::Bachelier).is_err());
+    }
+}
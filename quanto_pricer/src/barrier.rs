@@ -0,0 +1,271 @@
+use rand::Rng;
+use rand_distr::StandardNormal;
+use statrs::distribution::{Normal, ContinuousCDF};
+use crate::curve::YieldCurve;
+use crate::fourier::{OptionPrice, quanto_carry};
+use crate::monte_carlo::{McEstimate, McOptionPrice, McSettings};
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BarrierDirection {
+    Up,
+    Down,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BarrierKind {
+    In,
+    Out,
+}
+
+
+/// Continuously monitored barrier option. The rebate is paid at expiry for knock-ins that
+/// never knocked in, and at the hitting time for knock-outs.
+pub struct BarrierOption {
+    pub spot: f64,
+    pub strike: f64,
+    pub barrier: f64,
+    pub rebate: f64,
+    pub direction: BarrierDirection,
+    pub kind: BarrierKind,
+    pub domestic_rate: f64,
+    pub foreign_rate: f64,
+    pub volatility: f64,
+    pub fx_volatility: f64,
+    pub time_to_maturity: f64,
+    pub correlation: f64,
+}
+
+
+impl BarrierOption {
+    /// Takes the domestic and foreign rates from curves at this option's maturity; the
+    /// term structure between monitoring dates is flattened to the maturity zero rates.
+    pub fn with_curves(mut self, domestic: &YieldCurve, foreign: &YieldCurve) -> Self {
+        self.domestic_rate = domestic.zero_rate(self.time_to_maturity);
+        self.foreign_rate = foreign.zero_rate(self.time_to_maturity);
+        self
+    }
+
+    fn carry(&self) -> f64 {
+        quanto_carry(self.domestic_rate, self.foreign_rate, self.volatility, self.fx_volatility, self.correlation)
+    }
+
+    pub fn is_breached(&self, price: f64) -> bool {
+        match self.direction {
+            BarrierDirection::Up => price >= self.barrier,
+            BarrierDirection::Down => price <= self.barrier,
+        }
+    }
+
+    fn vanilla(&self, phi: f64) -> f64 {
+        let normal = Normal::new(0.0, 1.0).unwrap();
+        let t = self.time_to_maturity;
+        let vol_t = self.volatility * t.sqrt();
+        let d1 = ((self.spot / self.strike).ln() + (self.carry() + 0.5 * self.volatility.powi(2)) * t) / vol_t;
+        let d2 = d1 - vol_t;
+
+        phi * self.spot * ((self.carry() - self.domestic_rate) * t).exp() * normal.cdf(phi * d1)
+            - phi * self.strike * (-self.domestic_rate * t).exp() * normal.cdf(phi * d2)
+    }
+
+    /// Reiner–Rubinstein closed form under Black–Scholes, with the quanto drift as the
+    /// cost of carry.
+    pub fn price_closed_form(&self) -> OptionPrice {
+        if self.is_breached(self.spot) {
+            // Already knocked: an in option is a vanilla, an out option pays its rebate now
+            return match self.kind {
+                BarrierKind::In => OptionPrice::new(self.vanilla(1.0), self.vanilla(-1.0)),
+                BarrierKind::Out => OptionPrice::new(self.rebate, self.rebate),
+            };
+        }
+
+        OptionPrice::new(self.reiner_rubinstein(1.0), self.reiner_rubinstein(-1.0))
+    }
+
+    fn reiner_rubinstein(&self, phi: f64) -> f64 {
+        let normal = Normal::new(0.0, 1.0).unwrap();
+        let n = |x: f64| normal.cdf(x);
+
+        let (s, x, h, k) = (self.spot, self.strike, self.barrier, self.rebate);
+        let (r, b, t) = (self.domestic_rate, self.carry(), self.time_to_maturity);
+        let sigma2 = self.volatility.powi(2);
+        let vol_t = self.volatility * t.sqrt();
+        let eta = match self.direction {
+            BarrierDirection::Down => 1.0,
+            BarrierDirection::Up => -1.0,
+        };
+
+        let mu = (b - 0.5 * sigma2) / sigma2;
+        let lambda = (mu * mu + 2.0 * r / sigma2).sqrt();
+        let x1 = (s / x).ln() / vol_t + (1.0 + mu) * vol_t;
+        let x2 = (s / h).ln() / vol_t + (1.0 + mu) * vol_t;
+        let y1 = (h * h / (s * x)).ln() / vol_t + (1.0 + mu) * vol_t;
+        let y2 = (h / s).ln() / vol_t + (1.0 + mu) * vol_t;
+        let z = (h / s).ln() / vol_t + lambda * vol_t;
+
+        let carry_discount = ((b - r) * t).exp();
+        let discount = (-r * t).exp();
+        let ratio = h / s;
+
+        let a = phi * s * carry_discount * n(phi * x1) - phi * x * discount * n(phi * x1 - phi * vol_t);
+        let bb = phi * s * carry_discount * n(phi * x2) - phi * x * discount * n(phi * x2 - phi * vol_t);
+        let c = phi * s * carry_discount * ratio.powf(2.0 * (mu + 1.0)) * n(eta * y1)
+            - phi * x * discount * ratio.powf(2.0 * mu) * n(eta * y1 - eta * vol_t);
+        let d = phi * s * carry_discount * ratio.powf(2.0 * (mu + 1.0)) * n(eta * y2)
+            - phi * x * discount * ratio.powf(2.0 * mu) * n(eta * y2 - eta * vol_t);
+        let e = k * discount * (n(eta * x2 - eta * vol_t) - ratio.powf(2.0 * mu) * n(eta * y2 - eta * vol_t));
+        let f = k * (ratio.powf(mu + lambda) * n(eta * z) + ratio.powf(mu - lambda) * n(eta * z - 2.0 * eta * lambda * vol_t));
+
+        let call = phi > 0.0;
+        let strike_above = x > h;
+        match (self.kind, self.direction, call, strike_above) {
+            (BarrierKind::In, BarrierDirection::Down, true, true) => c + e,
+            (BarrierKind::In, BarrierDirection::Down, true, false) => a - bb + d + e,
+            (BarrierKind::In, BarrierDirection::Up, true, true) => a + e,
+            (BarrierKind::In, BarrierDirection::Up, true, false) => bb - c + d + e,
+            (BarrierKind::In, BarrierDirection::Down, false, true) => bb - c + d + e,
+            (BarrierKind::In, BarrierDirection::Down, false, false) => a + e,
+            (BarrierKind::In, BarrierDirection::Up, false, true) => a - bb + d + e,
+            (BarrierKind::In, BarrierDirection::Up, false, false) => c + e,
+            (BarrierKind::Out, BarrierDirection::Down, true, true) => a - c + f,
+            (BarrierKind::Out, BarrierDirection::Down, true, false) => bb - d + f,
+            (BarrierKind::Out, BarrierDirection::Up, true, true) => f,
+            (BarrierKind::Out, BarrierDirection::Up, true, false) => a - bb + c - d + f,
+            (BarrierKind::Out, BarrierDirection::Down, false, true) => a - bb + c - d + f,
+            (BarrierKind::Out, BarrierDirection::Down, false, false) => f,
+            (BarrierKind::Out, BarrierDirection::Up, false, true) => bb - d + f,
+            (BarrierKind::Out, BarrierDirection::Up, false, false) => a - c + f,
+        }
+    }
+
+    /// Monte Carlo on a time grid. Between grid points the barrier is checked with the
+    /// Brownian-bridge crossing probability, which removes the bias of discrete monitoring.
+    pub fn price_monte_carlo(&self, settings: &McSettings) -> McOptionPrice {
+        let mut rng = settings.rng();
+        let steps = settings.steps.max(1);
+        let dt = self.time_to_maturity / steps as f64;
+        let drift = (self.carry() - 0.5 * self.volatility.powi(2)) * dt;
+        let vol_dt = self.volatility * dt.sqrt();
+        let log_barrier = self.barrier.ln();
+        let discount = (-self.domestic_rate * self.time_to_maturity).exp();
+
+        let mut calls = Vec::with_capacity(settings.paths);
+        let mut puts = Vec::with_capacity(settings.paths);
+
+        for _ in 0..settings.paths {
+            let mut log_price = self.spot.ln();
+            let mut hit_time = if self.is_breached(self.spot) { Some(0.0) } else { None };
+
+            for step in 0..steps {
+                if hit_time.is_some() && self.kind == BarrierKind::Out {
+                    break;
+                }
+                let z: f64 = rng.sample(StandardNormal);
+                let next = log_price + drift + vol_dt * z;
+
+                if hit_time.is_none() {
+                    let crossed = self.is_breached(next.exp()) || {
+                        let distance = (log_barrier - log_price) * (log_barrier - next);
+                        let probability = (-2.0 * distance / (vol_dt * vol_dt)).exp();
+                        rng.gen::<f64>() < probability
+                    };
+                    if crossed {
+                        hit_time = Some((step + 1) as f64 * dt);
+                    }
+                }
+                log_price = next;
+            }
+
+            let terminal = log_price.exp();
+            let (call, put) = match (self.kind, hit_time) {
+                (BarrierKind::Out, Some(time)) => {
+                    let rebate = self.rebate * (-self.domestic_rate * time).exp();
+                    (rebate, rebate)
+                }
+                (BarrierKind::In, None) => (self.rebate * discount, self.rebate * discount),
+                _ => (
+                    discount * (terminal - self.strike).max(0.0),
+                    discount * (self.strike - terminal).max(0.0),
+                ),
+            };
+            calls.push(call);
+            puts.push(put);
+        }
+
+        McOptionPrice {
+            call: McEstimate::from_samples(&calls),
+            put: McEstimate::from_samples(&puts),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn haug(strike: f64, barrier: f64, direction: BarrierDirection, kind: BarrierKind) -> BarrierOption {
+        // Haug's test setup: r = 8%, cost of carry 4% (via the foreign rate), rebate 3
+        BarrierOption {
+            spot: 100.0,
+            strike,
+            barrier,
+            rebate: 3.0,
+            direction,
+            kind,
+            domestic_rate: 0.08,
+            foreign_rate: 0.04,
+            volatility: 0.25,
+            fx_volatility: 0.1,
+            time_to_maturity: 0.5,
+            correlation: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_closed_form_matches_reference_values() {
+        use BarrierDirection::*;
+        use BarrierKind::*;
+
+        let cases = [
+            (90.0, 95.0, Down, Out, 9.0246, 2.2798),
+            (100.0, 95.0, Down, Out, 6.7924, 2.2947),
+            (110.0, 105.0, Up, Out, 2.3453, 7.5187),
+            (90.0, 95.0, Down, In, 7.7627, 2.9586),
+            (100.0, 105.0, Up, In, 8.4482, 3.3721),
+            (110.0, 95.0, Down, In, 2.0576, 11.9752),
+        ];
+        for (strike, barrier, direction, kind, call, put) in cases {
+            let price = haug(strike, barrier, direction, kind).price_closed_form();
+            assert!((price.call - call).abs() < 1e-4, "{:?} {:?} call: {} vs {}", direction, kind, price.call, call);
+            assert!((price.put - put).abs() < 1e-4, "{:?} {:?} put: {} vs {}", direction, kind, price.put, put);
+        }
+    }
+
+    #[test]
+    fn test_in_out_parity_without_rebate() {
+        let mut knock_in = haug(100.0, 90.0, BarrierDirection::Down, BarrierKind::In);
+        knock_in.rebate = 0.0;
+        let mut knock_out = haug(100.0, 90.0, BarrierDirection::Down, BarrierKind::Out);
+        knock_out.rebate = 0.0;
+
+        let sum = knock_in.price_closed_form().call + knock_out.price_closed_form().call;
+        assert!((sum - knock_in.vanilla(1.0)).abs() < 1e-10);
+
+        // Starting beyond the barrier
+        knock_out.spot = 85.0;
+        assert_eq!(knock_out.price_closed_form().call, 0.0);
+    }
+
+    #[test]
+    fn test_monte_carlo_with_bridge_matches_closed_form() {
+        let option = haug(100.0, 95.0, BarrierDirection::Down, BarrierKind::Out);
+        let settings = McSettings { paths: 20_000, steps: 50, seed: 7 };
+
+        let closed = option.price_closed_form();
+        let estimate = option.price_monte_carlo(&settings);
+        assert!((estimate.call.value - closed.call).abs() < 4.0 * estimate.call.std_error + 0.02);
+        assert!((estimate.put.value - closed.put).abs() < 4.0 * estimate.put.std_error + 0.02);
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use ndarray::Array2;
+use rand::Rng;
+use rand_distr::StandardNormal;
+use statrs::distribution::{Normal, ContinuousCDF};
+use model::model::{Asset, Basket};
+use crate::bachelier::{PricingModel, bachelier};
+use crate::curve::YieldCurve;
+use crate::fourier::OptionPrice;
+use crate::monte_carlo::{McEstimate, McOptionPrice, McSettings};
+use crate::error::PricingError;
+
+
+/// Options on the total value of an auction basket, Σ quantity × price over its assets.
+pub struct BasketOptionPricer {
+    pub basket: Basket,
+    /// Volatility of each asset, in the order of `basket.assets`.
+    pub volatilities: Vec<f64>,
+    pub correlation: Array2<f64>,
+    pub strike: f64,
+    pub domestic_rate: f64,
+    pub time_to_maturity: f64,
+    cholesky: Array2<f64>,
+}
+
+
+/// Lower-triangular factor L with L Lᵀ = matrix.
+fn cholesky(matrix: &Array2<f64>) -> Result<Array2<f64>, PricingError> {
+    let n = matrix.nrows();
+    let mut lower = Array2::<f64>::zeros((n, n));
+
+    for i in 0..n {
+        for j in 0..=i {
+            let sum: f64 = (0..j).map(|k| lower[[i, k]] * lower[[j, k]]).sum();
+            if i == j {
+                let diagonal = matrix[[i, i]] - sum;
+                if diagonal <= 0.0 {
+                    return Err(PricingError::InvalidInput("correlation matrix is not positive definite"));
+                }
+                lower[[i, j]] = diagonal.sqrt();
+            } else {
+                lower[[i, j]] = (matrix[[i, j]] - sum) / lower[[j, j]];
+            }
+        }
+    }
+    Ok(lower)
+}
+
+
+impl BasketOptionPricer {
+    /// `correlation` is indexed in the order of `basket.assets`.
+    pub fn new(
+        basket: Basket,
+        volatilities: &HashMap<Asset, f64>,
+        correlation: Array2<f64>,
+        strike: f64,
+        domestic_rate: f64,
+        time_to_maturity: f64,
+    ) -> Result<Self, PricingError> {
+        let n = basket.assets.len();
+        if n == 0 {
+            return Err(PricingError::InvalidInput("basket has no assets"));
+        }
+        if correlation.dim() != (n, n) {
+            return Err(PricingError::InvalidInput("correlation matrix does not match the basket"));
+        }
+        for i in 0..n {
+            if (correlation[[i, i]] - 1.0).abs() > 1e-12 {
+                return Err(PricingError::InvalidInput("correlation matrix must have a unit diagonal"));
+            }
+            for j in 0..i {
+                if (correlation[[i, j]] - correlation[[j, i]]).abs() > 1e-12 || correlation[[i, j]].abs() > 1.0 {
+                    return Err(PricingError::InvalidInput("correlation matrix must be symmetric with entries in [-1, 1]"));
+                }
+            }
+        }
+
+        let volatilities = basket.assets.iter()
+            .map(|info| volatilities.get(&info.asset).copied().filter(|vol| *vol > 0.0))
+            .collect::<Option<Vec<f64>>>()
+            .ok_or(PricingError::InvalidInput("missing or non-positive volatility for a basket asset"))?;
+        let cholesky = cholesky(&correlation)?;
+
+        Ok(BasketOptionPricer {
+            basket,
+            volatilities,
+            correlation,
+            strike,
+            domestic_rate,
+            time_to_maturity,
+            cholesky,
+        })
+    }
+
+    /// Takes the domestic rate from a curve at the option's maturity.
+    pub fn with_curve(mut self, curve: &YieldCurve) -> Self {
+        self.domestic_rate = curve.zero_rate(self.time_to_maturity);
+        self
+    }
+
+    /// Forward value of each basket position.
+    fn forwards(&self) -> Vec<f64> {
+        let growth = (self.domestic_rate * self.time_to_maturity).exp();
+        self.basket.assets.iter().map(|info| info.total_value() * growth).collect()
+    }
+
+    /// Lognormal approximation matching the first two moments of the basket value.
+    pub fn moment_matching(&self) -> OptionPrice {
+        let forwards = self.forwards();
+        let t = self.time_to_maturity;
+        let n = forwards.len();
+
+        let m1: f64 = forwards.iter().sum();
+        let mut m2 = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let covariance = self.correlation[[i, j]] * self.volatilities[i] * self.volatilities[j] * t;
+                m2 += forwards[i] * forwards[j] * covariance.exp();
+            }
+        }
+        let total_variance = (m2 / (m1 * m1)).ln();
+
+        let normal = Normal::new(0.0, 1.0).unwrap();
+        let discount = (-self.domestic_rate * t).exp();
+        let std_dev = total_variance.sqrt();
+        let d1 = ((m1 / self.strike).ln() + 0.5 * total_variance) / std_dev;
+        let d2 = d1 - std_dev;
+
+        OptionPrice::new(
+            discount * (m1 * normal.cdf(d1) - self.strike * normal.cdf(d2)),
+            discount * (self.strike * normal.cdf(-d2) - m1 * normal.cdf(-d1)),
+        )
+    }
+
+    /// Normal approximation matching the mean and variance of the basket value. Unlike the
+    /// lognormal fit it holds when short positions let the basket value reach zero or below.
+    pub fn normal_matching(&self) -> OptionPrice {
+        let forwards = self.forwards();
+        let t = self.time_to_maturity;
+        let n = forwards.len();
+
+        let m1: f64 = forwards.iter().sum();
+        let mut m2 = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let covariance = self.correlation[[i, j]] * self.volatilities[i] * self.volatilities[j] * t;
+                m2 += forwards[i] * forwards[j] * covariance.exp();
+            }
+        }
+        let std_dev = (m2 - m1 * m1).max(0.0).sqrt();
+
+        let discount = (-self.domestic_rate * t).exp();
+        OptionPrice::new(
+            discount * bachelier(m1, self.strike, std_dev, true),
+            discount * bachelier(m1, self.strike, std_dev, false),
+        )
+    }
+
+    pub fn approximate(&self, model: PricingModel) -> OptionPrice {
+        match model {
+            PricingModel::BlackScholes => self.moment_matching(),
+            PricingModel::Bachelier => self.normal_matching(),
+        }
+    }
+
+    /// Correlated terminal values simulated exactly in one step.
+    pub fn price_monte_carlo(&self, settings: &McSettings) -> McOptionPrice {
+        let mut rng = settings.rng();
+        let t = self.time_to_maturity;
+        let discount = (-self.domestic_rate * t).exp();
+        let forwards = self.forwards();
+        let n = forwards.len();
+
+        let mut calls = Vec::with_capacity(settings.paths);
+        let mut puts = Vec::with_capacity(settings.paths);
+        let mut independent = vec![0.0; n];
+
+        for _ in 0..settings.paths {
+            for z in independent.iter_mut() {
+                *z = rng.sample(StandardNormal);
+            }
+            let value: f64 = (0..n)
+                .map(|i| {
+                    let z: f64 = (0..=i).map(|k| self.cholesky[[i, k]] * independent[k]).sum();
+                    let vol = self.volatilities[i];
+                    forwards[i] * (-0.5 * vol * vol * t + vol * t.sqrt() * z).exp()
+                })
+                .sum();
+
+            calls.push(discount * (value - self.strike).max(0.0));
+            puts.push(discount * (self.strike - value).max(0.0));
+        }
+
+        McOptionPrice {
+            call: McEstimate::from_samples(&calls),
+            put: McEstimate::from_samples(&puts),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+    use model::ids::BasketId;
+    use model::model::AssetInfo;
+
+    fn basket() -> Basket {
+        Basket {
+            id: BasketId(1),
+            assets: vec![
+                AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60.0),
+                AssetInfo::new(Asset::new("ETH", "USD"), 10.0, 4.0),
+            ],
+        }
+    }
+
+    fn volatilities() -> HashMap<Asset, f64> {
+        HashMap::from([(Asset::new("BTC", "USD"), 0.6), (Asset::new("ETH", "USD"), 0.8)])
+    }
+
+    #[test]
+    fn test_rejects_invalid_inputs() {
+        let singular = array![[1.0, 1.0], [1.0, 1.0]];
+        assert!(BasketOptionPricer::new(basket(), &volatilities(), singular, 100.0, 0.03, 1.0).is_err());
+        assert!(BasketOptionPricer::new(basket(), &volatilities(), array![[1.0]], 100.0, 0.03, 1.0).is_err());
+        assert!(BasketOptionPricer::new(basket(), &HashMap::new(), array![[1.0, 0.5], [0.5, 1.0]], 100.0, 0.03, 1.0).is_err());
+    }
+
+    #[test]
+    fn test_moment_matching_against_monte_carlo() {
+        let pricer = BasketOptionPricer::new(basket(), &volatilities(), array![[1.0, 0.7], [0.7, 1.0]], 100.0, 0.03, 1.0).unwrap();
+        let approximation = pricer.moment_matching();
+        let simulated = pricer.price_monte_carlo(&McSettings { paths: 50_000, steps: 1, seed: 3 });
+
+        // Put-call parity on the basket forward
+        let parity = approximation.call - approximation.put - (100.0 - 100.0 * (-0.03f64).exp());
+        assert!(parity.abs() < 1e-9);
+
+        // Moment matching is an approximation: agreement within a few percent
+        assert!((approximation.call - simulated.call.value).abs() / simulated.call.value < 0.03);
+        assert!((approximation.put - simulated.put.value).abs() / simulated.put.value < 0.03);
+    }
+
+    #[test]
+    fn test_normal_matching_prices_spreads() {
+        // Long BTC against short ETH: a spread worth 20 whose forward can turn negative
+        let spread = Basket {
+            id: BasketId(3),
+            assets: vec![
+                AssetInfo::new(Asset::new("BTC", "USD"), 1.0, 60.0),
+                AssetInfo::new(Asset::new("ETH", "USD"), -10.0, 4.0),
+            ],
+        };
+        let pricer = BasketOptionPricer::new(spread, &volatilities(), array![[1.0, 0.8], [0.8, 1.0]], 0.0, 0.0, 0.25).unwrap();
+        let normal = pricer.approximate(PricingModel::Bachelier);
+        let simulated = pricer.price_monte_carlo(&McSettings { paths: 50_000, steps: 1, seed: 5 });
+
+        assert!((normal.call - normal.put - 20.0).abs() < 1e-9);
+        // The spread of two lognormals is skewed, which a normal fit only roughly captures
+        assert!((normal.put - simulated.put.value).abs() / simulated.put.value < 0.25);
+    }
+
+    #[test]
+    fn test_single_asset_basket_is_black_scholes() {
+        let basket = Basket { id: BasketId(2), assets: vec![AssetInfo::new(Asset::new("BTC", "USD"), 2.0, 50.0)] };
+        let vols = HashMap::from([(Asset::new("BTC", "USD"), 0.2)]);
+        let pricer = BasketOptionPricer::new(basket, &vols, array![[1.0]], 100.0, 0.05, 1.0).unwrap();
+
+        assert!((pricer.moment_matching().call - 10.4506).abs() < 1e-4);
+    }
+}
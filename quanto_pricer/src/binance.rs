@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::Value;
+use model::model::Asset;
+use crate::candles::{BARS_PER_REQUEST, Granularity, candle_rows, request_ranges};
+use crate::fetch::{FetchError, FetchReport, FetchSettings, RateLimiter, get_json};
+use crate::freshness::now_millis;
+use crate::history::TimeRange;
+use crate::provider::{MarketDataProvider, OptionQuote, OrderBook, levels, malformed, number, unsupported};
+use crate::realized::Candle;
+
+pub const BINANCE_API_URL: &str = "https://api.binance.com";
+pub const BINANCE_OPTIONS_URL: &str = "https://eapi.binance.com";
+
+
+/// Binance: spot and spot books from the main API, options (names like
+/// "BTC-241227-60000-C", settled in USDT) from the separate options API.
+#[derive(Debug)]
+pub struct BinanceProvider {
+    settings: FetchSettings,
+    options_url: String,
+    client: Client,
+    limiter: RateLimiter,
+}
+
+impl BinanceProvider {
+    /// `settings.base_url` is the spot API; both hosts share one rate limit.
+    pub fn new(settings: FetchSettings, options_url: &str) -> Self {
+        let limiter = settings.rate_limiter();
+        BinanceProvider { settings, options_url: options_url.to_string(), client: Client::new(), limiter }
+    }
+
+    async fn get(&self, base_url: &str, path: &str) -> Result<Value, FetchError> {
+        let url = format!("{}{}", base_url, path);
+        get_json(&self.client, &url, &self.limiter, &self.settings.retry).await
+    }
+
+    fn is_option(instrument_name: &str) -> bool {
+        instrument_name.contains('-')
+    }
+}
+
+impl Default for BinanceProvider {
+    fn default() -> Self {
+        let settings = FetchSettings { base_url: BINANCE_API_URL.to_string(), ..FetchSettings::default() };
+        BinanceProvider::new(settings, BINANCE_OPTIONS_URL)
+    }
+}
+
+#[async_trait]
+impl MarketDataProvider for BinanceProvider {
+    fn venue(&self) -> &'static str {
+        "binance"
+    }
+
+    async fn spot_price(&self, asset: &Asset) -> Result<f64, FetchError> {
+        let symbol = format!("{}{}", asset.base, asset.quote).to_uppercase();
+        let response = self.get(&self.settings.base_url, &format!("/api/v3/ticker/price?symbol={}", symbol)).await?;
+        response.get("price").and_then(number).ok_or_else(|| malformed("ticker"))
+    }
+
+    /// Lists the underlying's options, then marks them all from one bulk request.
+    async fn option_chain(&self, underlying: &str) -> Result<FetchReport<OptionQuote>, FetchError> {
+        let info = self.get(&self.options_url, "/eapi/v1/exchangeInfo").await?;
+        let symbols = info.get("optionSymbols").and_then(Value::as_array).ok_or_else(|| malformed("exchange info"))?;
+        let prefix = format!("{}-", underlying.to_uppercase());
+
+        let marks = self.get(&self.options_url, "/eapi/v1/mark").await?;
+        let marks: HashMap<&str, &Value> = marks.as_array().ok_or_else(|| malformed("mark"))?.iter()
+            .filter_map(|mark| Some((mark.get("symbol")?.as_str()?, mark)))
+            .collect();
+
+        let received_at = now_millis();
+        let data = symbols.iter()
+            .filter_map(|symbol| {
+                let name = symbol.get("symbol")?.as_str()?;
+                if !name.starts_with(&prefix) {
+                    return None;
+                }
+                let quote_asset = symbol.get("quoteAsset").and_then(Value::as_str).unwrap_or("USDT");
+                let mark = marks.get(name);
+                let field = |key: &str| mark.and_then(|mark| mark.get(key)).and_then(number);
+                Some(OptionQuote {
+                    instrument_name: name.to_string(),
+                    strike: symbol.get("strikePrice").and_then(number)?,
+                    expiration_timestamp: symbol.get("expiryDate")?.as_u64()?,
+                    option_type: if symbol.get("side")?.as_str()? == "CALL" { "call" } else { "put" }.to_string(),
+                    price_index: format!("{}_{}", underlying, quote_asset).to_lowercase(),
+                    settlement_currency: quote_asset.to_string(),
+                    implied_volatility: field("markIV").map(|vol| 100.0 * vol),
+                    market_price: field("markPrice"),
+                    delta: field("delta"),
+                    gamma: field("gamma"),
+                    vega: field("vega"),
+                    theta: field("theta"),
+                    open_interest: None,
+                    exchange_timestamp: None,
+                    received_at: Some(received_at),
+                })
+            })
+            .collect();
+        Ok(FetchReport { data, failures: Vec::new() })
+    }
+
+    async fn order_book(&self, instrument_name: &str, depth: usize) -> Result<OrderBook, FetchError> {
+        let response = if Self::is_option(instrument_name) {
+            self.get(&self.options_url, &format!("/eapi/v1/depth?symbol={}&limit={}", instrument_name, depth)).await?
+        } else {
+            self.get(&self.settings.base_url, &format!("/api/v3/depth?symbol={}&limit={}", instrument_name, depth)).await?
+        };
+        if response.get("bids").is_none() {
+            return Err(malformed("depth"));
+        }
+        Ok(OrderBook {
+            instrument_name: instrument_name.to_string(),
+            timestamp: response.get("T").and_then(Value::as_u64),
+            bids: levels(response.get("bids")),
+            asks: levels(response.get("asks")),
+            received_at: Some(now_millis()),
+        })
+    }
+
+    /// Spot klines only; options have their own kline format and are not supported.
+    async fn candles(&self, instrument_name: &str, granularity: Granularity, range: TimeRange) -> Result<Vec<Candle>, FetchError> {
+        if Self::is_option(instrument_name) {
+            return Err(unsupported(self.venue(), "option candles"));
+        }
+        let mut candles = Vec::new();
+        for window in request_ranges(range, granularity, BARS_PER_REQUEST) {
+            let path = format!(
+                "/api/v3/klines?symbol={}&interval={}&startTime={}&endTime={}&limit={}",
+                instrument_name, granularity.name(), window.start, window.end - 1, BARS_PER_REQUEST,
+            );
+            let response = self.get(&self.settings.base_url, &path).await?;
+            candles.extend(candle_rows(response.as_array().ok_or_else(|| malformed("klines"))?));
+        }
+        candles.retain(|candle| range.contains(candle.timestamp));
+        Ok(candles)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+    use crate::fetch::mock;
+
+    async fn provider() -> BinanceProvider {
+        let (url, _) = mock::serve(Duration::ZERO, |path, _| {
+            let body = match path.split('?').next().unwrap() {
+                "/api/v3/ticker/price" => r#"{"symbol":"BTCUSDT","price":"64000.50"}"#,
+                "/api/v3/depth" => r#"{"lastUpdateId":1,"bids":[["63999.0","1.5"]],"asks":[["64001.0","0.5"]]}"#,
+                "/eapi/v1/exchangeInfo" => r#"{"optionSymbols":[
+                    {"symbol":"BTC-241227-60000-C","side":"CALL","strikePrice":"60000.00000000","underlying":"BTCUSDT","expiryDate":1735286400000,"quoteAsset":"USDT"},
+                    {"symbol":"BTC-241227-60000-P","side":"PUT","strikePrice":"60000.00000000","underlying":"BTCUSDT","expiryDate":1735286400000,"quoteAsset":"USDT"},
+                    {"symbol":"ETH-241227-3000-C","side":"CALL","strikePrice":"3000.00000000","underlying":"ETHUSDT","expiryDate":1735286400000,"quoteAsset":"USDT"}]}"#,
+                "/eapi/v1/mark" => r#"[{"symbol":"BTC-241227-60000-C","markPrice":"7100.5","markIV":"0.5","delta":"0.62","theta":"-40.1","gamma":"0.00002","vega":"120.3"}]"#,
+                _ => return (400, r#"{"code":-1121,"msg":"Invalid symbol."}"#.to_string()),
+            };
+            (200, body.to_string())
+        }).await;
+        BinanceProvider::new(FetchSettings { base_url: url.clone(), ..FetchSettings::default() }, &url)
+    }
+
+    #[test]
+    fn test_binance_spot_and_book() {
+        Runtime::new().unwrap().block_on(async {
+            let provider = provider().await;
+            assert_eq!(provider.spot_price(&Asset::new("BTC", "USDT")).await, Ok(64000.5));
+            let book = provider.order_book("BTCUSDT", 5).await.unwrap();
+            assert_eq!(book.mid(), Some(64000.0));
+            assert_eq!(provider.order_book("BTC-241227-1-C", 5).await, Err(FetchError::Http { status: 400 }));
+        });
+    }
+
+    #[test]
+    fn test_binance_chain_is_filtered_and_marked() {
+        Runtime::new().unwrap().block_on(async {
+            let chain = provider().await.option_chain("BTC").await.unwrap();
+            assert!(chain.is_complete());
+            assert_eq!(chain.data.len(), 2);
+            let call = &chain.data[0];
+            assert_eq!((call.option_type.as_str(), call.strike, call.price_index.as_str()), ("call", 60000.0, "btc_usdt"));
+            assert_eq!((call.market_price, call.implied_volatility, call.delta), (Some(7100.5), Some(50.0), Some(0.62)));
+            assert_eq!(chain.data[1].market_price, None);
+        });
+    }
+}
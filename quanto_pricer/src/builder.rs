@@ -0,0 +1,302 @@
+use std::fmt;
+use crate::curve::YieldCurve;
+use crate::fourier::QuantoOption;
+use crate::implied_vol::ImpliedVolatility;
+
+/// Rates and yields beyond ±100% are almost always percentages entered as decimals.
+const MAX_ABS_RATE: f64 = 1.0;
+/// Volatilities above 1000% likewise.
+const MAX_VOLATILITY: f64 = 10.0;
+
+
+/// Why pricing inputs were rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputError {
+    Missing(&'static str),
+    NotFinite(&'static str),
+    OutOfRange { field: &'static str, value: f64, expected: &'static str },
+}
+
+impl InputError {
+    pub fn field(&self) -> &'static str {
+        match self {
+            InputError::Missing(field) | InputError::NotFinite(field) => field,
+            InputError::OutOfRange { field, .. } => field,
+        }
+    }
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputError::Missing(field) => write!(f, "{} is required", field),
+            InputError::NotFinite(field) => write!(f, "{} must be finite", field),
+            InputError::OutOfRange { field, value, expected } => write!(f, "{} is {}, expected {}", field, value, expected),
+        }
+    }
+}
+
+impl std::error::Error for InputError {}
+
+fn check(field: &'static str, value: f64, valid: bool, expected: &'static str) -> Result<(), InputError> {
+    if !value.is_finite() {
+        Err(InputError::NotFinite(field))
+    } else if !valid {
+        Err(InputError::OutOfRange { field, value, expected })
+    } else {
+        Ok(())
+    }
+}
+
+fn check_rate(field: &'static str, value: f64) -> Result<(), InputError> {
+    check(field, value, value.abs() <= MAX_ABS_RATE, "a decimal rate within ±1, e.g. 0.05 for 5%")
+}
+
+
+impl QuantoOption {
+    pub fn builder() -> QuantoOptionBuilder {
+        QuantoOptionBuilder::default()
+    }
+
+    /// Rejects inputs the pricers cannot handle, before they turn into NaNs in the FFT.
+    pub fn validate(&self) -> Result<(), InputError> {
+        check("spot", self.spot, self.spot > 0.0, "a positive price")?;
+        check("strike", self.strike, self.strike > 0.0, "a positive price")?;
+        check("time_to_maturity", self.time_to_maturity, self.time_to_maturity > 0.0, "a positive number of years")?;
+        check("volatility", self.volatility, self.volatility > 0.0 && self.volatility <= MAX_VOLATILITY, "a decimal volatility in (0, 10]")?;
+        check("fx_volatility", self.fx_volatility, self.fx_volatility >= 0.0 && self.fx_volatility <= MAX_VOLATILITY, "a decimal volatility in [0, 10]")?;
+        check("correlation", self.correlation, self.correlation.abs() <= 1.0, "a correlation in [-1, 1]")?;
+        check_rate("domestic_rate", self.domestic_rate)?;
+        check_rate("foreign_rate", self.foreign_rate)?;
+        check("dividend_yield", self.dividend_yield, true, "")
+    }
+}
+
+
+/// Builds a validated [`QuantoOption`]. Spot, strike, volatility and maturity are required;
+/// rates, FX volatility, correlation and yield default to zero, i.e. a plain option.
+#[derive(Debug, Clone, Default)]
+pub struct QuantoOptionBuilder {
+    spot: Option<f64>,
+    strike: Option<f64>,
+    volatility: Option<f64>,
+    time_to_maturity: Option<f64>,
+    domestic_rate: f64,
+    foreign_rate: f64,
+    fx_volatility: f64,
+    correlation: f64,
+    dividend_yield: f64,
+    curves: Option<(YieldCurve, YieldCurve)>,
+}
+
+impl QuantoOptionBuilder {
+    pub fn spot(mut self, spot: f64) -> Self {
+        self.spot = Some(spot);
+        self
+    }
+
+    pub fn strike(mut self, strike: f64) -> Self {
+        self.strike = Some(strike);
+        self
+    }
+
+    pub fn volatility(mut self, volatility: f64) -> Self {
+        self.volatility = Some(volatility);
+        self
+    }
+
+    pub fn time_to_maturity(mut self, time_to_maturity: f64) -> Self {
+        self.time_to_maturity = Some(time_to_maturity);
+        self
+    }
+
+    pub fn domestic_rate(mut self, rate: f64) -> Self {
+        self.domestic_rate = rate;
+        self
+    }
+
+    pub fn foreign_rate(mut self, rate: f64) -> Self {
+        self.foreign_rate = rate;
+        self
+    }
+
+    /// FX volatility and its correlation with the asset: the quanto leg.
+    pub fn quanto(mut self, fx_volatility: f64, correlation: f64) -> Self {
+        self.fx_volatility = fx_volatility;
+        self.correlation = correlation;
+        self
+    }
+
+    pub fn dividend_yield(mut self, dividend_yield: f64) -> Self {
+        self.dividend_yield = dividend_yield;
+        self
+    }
+
+    /// Rates read off the curves at maturity, overriding any flat rates.
+    pub fn curves(mut self, domestic: &YieldCurve, foreign: &YieldCurve) -> Self {
+        self.curves = Some((domestic.clone(), foreign.clone()));
+        self
+    }
+
+    pub fn build(self) -> Result<QuantoOption, InputError> {
+        let mut option = QuantoOption {
+            spot: self.spot.ok_or(InputError::Missing("spot"))?,
+            strike: self.strike.ok_or(InputError::Missing("strike"))?,
+            domestic_rate: self.domestic_rate,
+            foreign_rate: self.foreign_rate,
+            volatility: self.volatility.ok_or(InputError::Missing("volatility"))?,
+            fx_volatility: self.fx_volatility,
+            time_to_maturity: self.time_to_maturity.ok_or(InputError::Missing("time_to_maturity"))?,
+            correlation: self.correlation,
+            dividend_yield: self.dividend_yield,
+        };
+        option.validate()?;
+        if let Some((domestic, foreign)) = &self.curves {
+            option = option.with_curves(domestic, foreign);
+            option.validate()?;
+        }
+        Ok(option)
+    }
+}
+
+
+impl ImpliedVolatility {
+    pub fn builder() -> ImpliedVolatilityBuilder {
+        ImpliedVolatilityBuilder::default()
+    }
+
+    pub fn validate(&self) -> Result<(), InputError> {
+        check("spot", self.spot, self.spot > 0.0, "a positive price")?;
+        check("strike", self.strike, self.strike > 0.0, "a positive price")?;
+        check("time_to_maturity", self.time_to_maturity, self.time_to_maturity > 0.0, "a positive number of years")?;
+        check("market_price", self.market_price, self.market_price >= 0.0, "a non-negative price")?;
+        check_rate("r", self.r)?;
+        check("dividend_yield", self.dividend_yield, true, "")
+    }
+}
+
+
+/// Builds a validated [`ImpliedVolatility`] problem. Spot, strike, maturity and price are
+/// required; the option is a call on a non-paying asset at a zero rate unless set otherwise.
+#[derive(Debug, Clone)]
+pub struct ImpliedVolatilityBuilder {
+    spot: Option<f64>,
+    strike: Option<f64>,
+    time_to_maturity: Option<f64>,
+    market_price: Option<f64>,
+    r: f64,
+    is_call: bool,
+    dividend_yield: f64,
+}
+
+impl Default for ImpliedVolatilityBuilder {
+    fn default() -> Self {
+        ImpliedVolatilityBuilder {
+            spot: None,
+            strike: None,
+            time_to_maturity: None,
+            market_price: None,
+            r: 0.0,
+            is_call: true,
+            dividend_yield: 0.0,
+        }
+    }
+}
+
+impl ImpliedVolatilityBuilder {
+    pub fn spot(mut self, spot: f64) -> Self {
+        self.spot = Some(spot);
+        self
+    }
+
+    pub fn strike(mut self, strike: f64) -> Self {
+        self.strike = Some(strike);
+        self
+    }
+
+    pub fn time_to_maturity(mut self, time_to_maturity: f64) -> Self {
+        self.time_to_maturity = Some(time_to_maturity);
+        self
+    }
+
+    pub fn market_price(mut self, price: f64) -> Self {
+        self.market_price = Some(price);
+        self
+    }
+
+    pub fn rate(mut self, r: f64) -> Self {
+        self.r = r;
+        self
+    }
+
+    pub fn put(mut self) -> Self {
+        self.is_call = false;
+        self
+    }
+
+    pub fn dividend_yield(mut self, dividend_yield: f64) -> Self {
+        self.dividend_yield = dividend_yield;
+        self
+    }
+
+    pub fn build(self) -> Result<ImpliedVolatility, InputError> {
+        let problem = ImpliedVolatility {
+            spot: self.spot.ok_or(InputError::Missing("spot"))?,
+            strike: self.strike.ok_or(InputError::Missing("strike"))?,
+            r: self.r,
+            time_to_maturity: self.time_to_maturity.ok_or(InputError::Missing("time_to_maturity"))?,
+            market_price: self.market_price.ok_or(InputError::Missing("market_price"))?,
+            is_call: self.is_call,
+            dividend_yield: self.dividend_yield,
+        };
+        problem.validate()?;
+        Ok(problem)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_defaults_and_validation() {
+        let option = QuantoOption::builder().spot(100.0).strike(110.0).volatility(0.5).time_to_maturity(0.5).build().unwrap();
+        assert_eq!(option.domestic_rate, 0.0);
+        assert_eq!(option.correlation, 0.0);
+        assert!(option.calculate_price_fft().unwrap().call > 0.0);
+
+        let base = || QuantoOption::builder().spot(100.0).strike(110.0).volatility(0.5).time_to_maturity(0.5);
+        assert_eq!(QuantoOption::builder().strike(1.0).build(), Err(InputError::Missing("spot")));
+        assert_eq!(base().volatility(-0.2).build().unwrap_err().field(), "volatility");
+        assert_eq!(base().volatility(60.0).build().unwrap_err().field(), "volatility");
+        assert_eq!(base().time_to_maturity(0.0).build().unwrap_err().field(), "time_to_maturity");
+        assert_eq!(base().domestic_rate(5.0).build().unwrap_err().field(), "domestic_rate");
+        assert_eq!(base().quanto(0.1, 1.5).build().unwrap_err().field(), "correlation");
+        assert_eq!(base().spot(f64::NAN).build(), Err(InputError::NotFinite("spot")));
+
+        let curve = YieldCurve::flat(0.04);
+        let option = base().quanto(0.1, 0.3).curves(&curve, &YieldCurve::flat(0.01)).build().unwrap();
+        assert!((option.domestic_rate - 0.04).abs() < 1e-12 && (option.foreign_rate - 0.01).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_pricing_rejects_invalid_options() {
+        let mut option = QuantoOption::builder().spot(100.0).strike(100.0).volatility(0.5).time_to_maturity(1.0).build().unwrap();
+        option.volatility = -0.5;
+        assert!(matches!(option.calculate_price_fft(), Err(InputError::OutOfRange { field: "volatility", .. })));
+        assert!(option.price_with(&Default::default()).is_err());
+        assert!(option.calculate_price_fft().unwrap_err().to_string().contains("volatility"));
+    }
+
+    #[test]
+    fn test_implied_volatility_builder() {
+        let problem = ImpliedVolatility::builder().spot(100.0).strike(100.0).time_to_maturity(1.0).market_price(10.4506).rate(0.05).build().unwrap();
+        assert!(problem.is_call);
+        assert!((problem.implied_volatility().unwrap() - 0.2).abs() < 1e-4);
+
+        assert_eq!(ImpliedVolatility::builder().spot(100.0).build().unwrap_err(), InputError::Missing("strike"));
+        let negative = ImpliedVolatility::builder().spot(100.0).strike(100.0).time_to_maturity(1.0).market_price(-1.0).put().build();
+        assert_eq!(negative.unwrap_err().field(), "market_price");
+    }
+}
@@ -0,0 +1,460 @@
+use crate::cos::{CosSettings, price_cos};
+use crate::curve::YieldCurve;
+use crate::data::DeribitOptionData;
+use crate::fourier::CharacteristicModel;
+use crate::heston::HestonModel;
+use crate::implied_vol::ImpliedVolatility;
+use crate::jumps::{MertonModel, VarianceGammaModel};
+use crate::optimize::levenberg_marquardt;
+use crate::sabr::SabrParams;
+use crate::vol_surface::MILLIS_PER_YEAR;
+use crate::error::PricingError;
+
+/// Residual assigned to quotes the model cannot reproduce at all.
+const FAILED_RESIDUAL: f64 = 1.0;
+
+
+/// Which quotes are liquid enough to calibrate to. Only the out-of-the-money side of each
+/// strike is kept: calls at or above the forward, puts below it.
+#[derive(Debug, Clone, Copy)]
+pub struct QuoteFilter {
+    /// Shortest expiry in years; the last days before expiry are dominated by noise.
+    pub min_expiry: f64,
+    pub max_abs_log_moneyness: f64,
+    pub min_vol: f64,
+    pub max_vol: f64,
+    pub min_quotes_per_expiry: usize,
+}
+
+impl Default for QuoteFilter {
+    fn default() -> Self {
+        QuoteFilter {
+            min_expiry: 2.0 / 365.0,
+            max_abs_log_moneyness: 0.5,
+            min_vol: 0.05,
+            max_vol: 3.0,
+            min_quotes_per_expiry: 5,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationQuote {
+    pub instrument_name: String,
+    pub strike: f64,
+    pub expiry: f64,
+    pub implied_vol: f64,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HestonParams {
+    pub v0: f64,
+    pub kappa: f64,
+    pub theta: f64,
+    pub sigma: f64,
+    pub rho: f64,
+}
+
+impl HestonParams {
+    pub fn model(&self, spot: f64, strike: f64, rate: f64, expiry: f64) -> HestonModel {
+        HestonModel {
+            spot,
+            strike,
+            domestic_rate: rate,
+            foreign_rate: 0.0,
+            v0: self.v0,
+            kappa: self.kappa,
+            theta: self.theta,
+            sigma: self.sigma,
+            rho: self.rho,
+            time_to_maturity: expiry,
+            fx_volatility: 0.0,
+            correlation: 0.0,
+        }
+    }
+
+    /// Black–Scholes implied vol of the model price, priced with the COS method.
+    pub fn implied_vol(&self, spot: f64, strike: f64, rate: f64, expiry: f64) -> Option<f64> {
+        model_implied_vol(&self.model(spot, strike, rate, expiry), spot, strike, rate, expiry)
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MertonParams {
+    pub volatility: f64,
+    pub jump_intensity: f64,
+    pub jump_mean: f64,
+    pub jump_volatility: f64,
+}
+
+impl MertonParams {
+    pub fn model(&self, spot: f64, strike: f64, rate: f64, expiry: f64) -> MertonModel {
+        MertonModel {
+            spot,
+            strike,
+            domestic_rate: rate,
+            foreign_rate: 0.0,
+            volatility: self.volatility,
+            jump_intensity: self.jump_intensity,
+            jump_mean: self.jump_mean,
+            jump_volatility: self.jump_volatility,
+            time_to_maturity: expiry,
+            fx_volatility: 0.0,
+            correlation: 0.0,
+        }
+    }
+
+    pub fn implied_vol(&self, spot: f64, strike: f64, rate: f64, expiry: f64) -> Option<f64> {
+        model_implied_vol(&self.model(spot, strike, rate, expiry), spot, strike, rate, expiry)
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VarianceGammaParams {
+    pub sigma: f64,
+    pub nu: f64,
+    pub theta: f64,
+}
+
+impl VarianceGammaParams {
+    pub fn model(&self, spot: f64, strike: f64, rate: f64, expiry: f64) -> VarianceGammaModel {
+        VarianceGammaModel {
+            spot,
+            strike,
+            domestic_rate: rate,
+            foreign_rate: 0.0,
+            sigma: self.sigma,
+            nu: self.nu,
+            theta: self.theta,
+            time_to_maturity: expiry,
+            fx_volatility: 0.0,
+            correlation: 0.0,
+        }
+    }
+
+    pub fn implied_vol(&self, spot: f64, strike: f64, rate: f64, expiry: f64) -> Option<f64> {
+        model_implied_vol(&self.model(spot, strike, rate, expiry), spot, strike, rate, expiry)
+    }
+}
+
+
+/// Black–Scholes implied vol of the out-of-the-money price of `model`, priced with the COS method.
+fn model_implied_vol<M: CharacteristicModel>(model: &M, spot: f64, strike: f64, rate: f64, expiry: f64) -> Option<f64> {
+    let forward = spot * (rate * expiry).exp();
+    let is_call = strike >= forward;
+    let price = price_cos(model, strike, &CosSettings::default());
+    ImpliedVolatility {
+        spot,
+        strike,
+        r: rate,
+        time_to_maturity: expiry,
+        market_price: if is_call { price.call } else { price.put },
+        is_call,
+        dividend_yield: 0.0,
+    }.implied_volatility().ok()
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CalibrationModel {
+    Heston,
+    /// SABR with beta held fixed, as is usual.
+    Sabr { beta: f64 },
+    Merton,
+    VarianceGamma,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CalibratedParams {
+    Heston(HestonParams),
+    Sabr(SabrParams),
+    Merton(MertonParams),
+    VarianceGamma(VarianceGammaParams),
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuoteResidual {
+    pub instrument_name: String,
+    pub strike: f64,
+    pub market_vol: f64,
+    /// `None` if the model price has no implied volatility.
+    pub model_vol: Option<f64>,
+    pub residual: f64,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpiryCalibration {
+    pub expiry: f64,
+    pub forward: f64,
+    pub params: CalibratedParams,
+    /// Root mean square of the residuals, in implied volatility.
+    pub rmse: f64,
+    pub residuals: Vec<QuoteResidual>,
+    pub converged: bool,
+}
+
+
+impl QuoteFilter {
+    /// Liquid quotes as of `now_ms`, with Deribit's percentage IVs converted to decimals.
+    pub fn apply(&self, options: &[DeribitOptionData], spot: f64, curve: &YieldCurve, now_ms: u64) -> Vec<CalibrationQuote> {
+        options.iter()
+            .filter(|o| o.expiration_timestamp > now_ms && o.market_price.is_some_and(|p| p > 0.0))
+            .filter_map(|o| {
+                let expiry = (o.expiration_timestamp - now_ms) as f64 / MILLIS_PER_YEAR;
+                let implied_vol = o.implied_volatility? / 100.0;
+                let log_moneyness = (o.strike * curve.discount(expiry) / spot).ln();
+                let out_of_the_money = if o.option_type == "call" { log_moneyness >= 0.0 } else { log_moneyness < 0.0 };
+
+                let liquid = expiry >= self.min_expiry
+                    && log_moneyness.abs() <= self.max_abs_log_moneyness
+                    && implied_vol >= self.min_vol
+                    && implied_vol <= self.max_vol
+                    && out_of_the_money;
+                liquid.then(|| CalibrationQuote {
+                    instrument_name: o.instrument_name.clone(),
+                    strike: o.strike,
+                    expiry,
+                    implied_vol,
+                })
+            })
+            .collect()
+    }
+}
+
+
+/// Filters the quotes and calibrates `model` separately to every expiry with enough of
+/// them, by Levenberg–Marquardt on implied volatility residuals.
+pub fn calibrate(
+    options: &[DeribitOptionData],
+    spot: f64,
+    curve: &YieldCurve,
+    now_ms: u64,
+    model: CalibrationModel,
+    filter: &QuoteFilter,
+) -> Result<Vec<ExpiryCalibration>, PricingError> {
+    if spot <= 0.0 {
+        return Err(PricingError::InvalidInput("spot must be positive"));
+    }
+    let quotes = filter.apply(options, spot, curve, now_ms);
+
+    let mut expiries: Vec<f64> = Vec::new();
+    for quote in &quotes {
+        if !expiries.iter().any(|e| (e - quote.expiry).abs() < 1e-9) {
+            expiries.push(quote.expiry);
+        }
+    }
+    expiries.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+    let calibrations: Vec<ExpiryCalibration> = expiries.iter()
+        .filter_map(|expiry| {
+            let slice: Vec<&CalibrationQuote> = quotes.iter().filter(|q| (q.expiry - expiry).abs() < 1e-9).collect();
+            if slice.len() < filter.min_quotes_per_expiry {
+                return None;
+            }
+            Some(calibrate_expiry(*expiry, &slice, spot, curve.zero_rate(*expiry), model))
+        })
+        .collect();
+
+    if calibrations.is_empty() {
+        return Err(PricingError::InsufficientData("no expiry has enough liquid quotes to calibrate"));
+    }
+    Ok(calibrations)
+}
+
+
+fn calibrate_expiry(expiry: f64, quotes: &[&CalibrationQuote], spot: f64, rate: f64, model: CalibrationModel) -> ExpiryCalibration {
+    let forward = spot * (rate * expiry).exp();
+    let atm_vol = quotes.iter()
+        .min_by(|a, b| (a.strike - forward).abs().partial_cmp(&(b.strike - forward).abs()).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|q| q.implied_vol)
+        .unwrap_or(0.5);
+
+    // Unconstrained parametrisations keep positive parameters positive and correlations in (-1, 1)
+    let (params, converged) = match model {
+        CalibrationModel::Heston => {
+            let params = |x: &[f64]| HestonParams { v0: x[0].exp(), kappa: x[1].exp(), theta: x[2].exp(), sigma: x[3].exp(), rho: x[4].tanh() };
+            let residuals = |x: &[f64]| -> Vec<f64> {
+                let p = params(x);
+                quotes.iter()
+                    .map(|q| p.implied_vol(spot, q.strike, rate, expiry).map(|vol| vol - q.implied_vol).unwrap_or(FAILED_RESIDUAL))
+                    .collect()
+            };
+            let variance = (atm_vol * atm_vol).ln();
+            let minimum = levenberg_marquardt(residuals, &[variance, 1.0f64.ln(), variance, 0.5f64.ln(), -0.3f64.atanh()], 1e-14, 200);
+            (CalibratedParams::Heston(params(&minimum.x)), minimum.converged)
+        }
+        CalibrationModel::Sabr { beta } => {
+            let params = |x: &[f64]| SabrParams { alpha: x[0].exp(), beta, rho: x[1].tanh(), nu: x[2].exp() };
+            let residuals = |x: &[f64]| -> Vec<f64> {
+                let p = params(x);
+                quotes.iter().map(|q| p.implied_vol(forward, q.strike, expiry) - q.implied_vol).collect()
+            };
+            let start = [(atm_vol * forward.powf(1.0 - beta)).ln(), 0.0, 0.5f64.ln()];
+            let minimum = levenberg_marquardt(residuals, &start, 1e-14, 200);
+            (CalibratedParams::Sabr(params(&minimum.x)), minimum.converged)
+        }
+        CalibrationModel::Merton => {
+            let params = |x: &[f64]| MertonParams { volatility: x[0].exp(), jump_intensity: x[1].exp(), jump_mean: x[2], jump_volatility: x[3].exp() };
+            let residuals = |x: &[f64]| -> Vec<f64> {
+                let p = params(x);
+                quotes.iter()
+                    .map(|q| p.implied_vol(spot, q.strike, rate, expiry).map(|vol| vol - q.implied_vol).unwrap_or(FAILED_RESIDUAL))
+                    .collect()
+            };
+            let start = [(0.8 * atm_vol).ln(), 1.0f64.ln(), -0.1, 0.2f64.ln()];
+            let minimum = levenberg_marquardt(residuals, &start, 1e-14, 200);
+            (CalibratedParams::Merton(params(&minimum.x)), minimum.converged)
+        }
+        CalibrationModel::VarianceGamma => {
+            let params = |x: &[f64]| VarianceGammaParams { sigma: x[0].exp(), nu: x[1].exp(), theta: x[2] };
+            let residuals = |x: &[f64]| -> Vec<f64> {
+                let p = params(x);
+                quotes.iter()
+                    .map(|q| p.implied_vol(spot, q.strike, rate, expiry).map(|vol| vol - q.implied_vol).unwrap_or(FAILED_RESIDUAL))
+                    .collect()
+            };
+            let minimum = levenberg_marquardt(residuals, &[atm_vol.ln(), 0.2f64.ln(), -0.1], 1e-14, 200);
+            (CalibratedParams::VarianceGamma(params(&minimum.x)), minimum.converged)
+        }
+    };
+
+    let residuals: Vec<QuoteResidual> = quotes.iter()
+        .map(|q| {
+            let model_vol = match &params {
+                CalibratedParams::Heston(p) => p.implied_vol(spot, q.strike, rate, expiry),
+                CalibratedParams::Sabr(p) => Some(p.implied_vol(forward, q.strike, expiry)).filter(|vol| vol.is_finite()),
+                CalibratedParams::Merton(p) => p.implied_vol(spot, q.strike, rate, expiry),
+                CalibratedParams::VarianceGamma(p) => p.implied_vol(spot, q.strike, rate, expiry),
+            };
+            QuoteResidual {
+                instrument_name: q.instrument_name.clone(),
+                strike: q.strike,
+                market_vol: q.implied_vol,
+                model_vol,
+                residual: model_vol.map(|vol| vol - q.implied_vol).unwrap_or(FAILED_RESIDUAL),
+            }
+        })
+        .collect();
+    let rmse = (residuals.iter().map(|r| r.residual * r.residual).sum::<f64>() / residuals.len() as f64).sqrt();
+
+    ExpiryCalibration { expiry, forward, params, rmse, residuals, converged }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000_000;
+
+    fn option(strike: f64, expiry: f64, option_type: &str, vol: f64) -> DeribitOptionData {
+        DeribitOptionData {
+            instrument_name: format!("BTC-{}-{}", expiry, strike),
+            strike,
+            expiration_timestamp: NOW + (expiry * MILLIS_PER_YEAR) as u64,
+            option_type: option_type.to_string(),
+            price_index: "btc_usd".to_string(),
+            settlement_currency: "BTC".to_string(),
+            implied_volatility: Some(vol * 100.0),
+            market_price: Some(0.01),
+            delta: None,
+            gamma: None,
+            vega: None,
+            theta: None,
+            open_interest: None,
+            exchange_timestamp: None,
+            received_at: None,
+        }
+    }
+
+    /// Both sides of every strike, with the implied vols of `vol(strike, expiry)`.
+    fn chain(expiries: &[f64], vol: impl Fn(f64, f64) -> f64) -> Vec<DeribitOptionData> {
+        let mut options = Vec::new();
+        for expiry in expiries {
+            for strike in [70.0, 80.0, 90.0, 95.0, 100.0, 105.0, 110.0, 120.0, 135.0] {
+                options.push(option(strike, *expiry, "call", vol(strike, *expiry)));
+                options.push(option(strike, *expiry, "put", vol(strike, *expiry)));
+            }
+        }
+        options
+    }
+
+    #[test]
+    fn test_filter_keeps_liquid_out_of_the_money_quotes() {
+        let mut options = chain(&[0.5], |_, _| 0.6);
+        options.push(option(100.0, 1.0 / 365.0, "call", 0.6)); // Too close to expiry
+        options.push(option(300.0, 0.5, "call", 0.6));         // Too far from the money
+        options.push(option(125.0, 0.5, "call", 5.0));         // Implausible vol
+        let mut unpriced = option(125.0, 0.5, "call", 0.6);
+        unpriced.market_price = None;
+        options.push(unpriced);
+
+        let quotes = QuoteFilter::default().apply(&options, 100.0, &YieldCurve::flat(0.0), NOW);
+        assert_eq!(quotes.len(), 9);
+        assert!(quotes.iter().all(|q| (q.expiry - 0.5).abs() < 1e-6 && (q.implied_vol - 0.6).abs() < 1e-12));
+    }
+
+    #[test]
+    fn test_sabr_calibration_per_expiry() {
+        let short = SabrParams { alpha: 0.6, beta: 1.0, rho: -0.3, nu: 1.2 };
+        let long = SabrParams { alpha: 0.5, beta: 1.0, rho: -0.1, nu: 0.6 };
+        let options = chain(&[0.25, 1.0], |strike, expiry| {
+            let params = if expiry < 0.5 { short } else { long };
+            params.implied_vol(100.0 * (0.02 * expiry).exp(), strike, expiry)
+        });
+
+        let calibrations = calibrate(&options, 100.0, &YieldCurve::flat(0.02), NOW, CalibrationModel::Sabr { beta: 1.0 }, &QuoteFilter::default()).unwrap();
+        assert_eq!(calibrations.len(), 2);
+        for (calibration, expected) in calibrations.iter().zip([short, long]) {
+            assert!(calibration.converged);
+            assert!(calibration.rmse < 1e-6, "rmse {}", calibration.rmse);
+            assert_eq!(calibration.residuals.len(), 9);
+            let CalibratedParams::Sabr(params) = calibration.params else { panic!("expected SABR") };
+            assert!((params.rho - expected.rho).abs() < 1e-3);
+            assert!((params.nu - expected.nu).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_heston_calibration_fits_heston_smile() {
+        let truth = HestonParams { v0: 0.36, kappa: 2.0, theta: 0.4, sigma: 1.0, rho: -0.4 };
+        let options = chain(&[0.5], |strike, expiry| truth.implied_vol(100.0, strike, 0.0, expiry).unwrap());
+
+        let calibrations = calibrate(&options, 100.0, &YieldCurve::flat(0.0), NOW, CalibrationModel::Heston, &QuoteFilter::default()).unwrap();
+        let calibration = &calibrations[0];
+        assert!(calibration.rmse < 1e-4, "rmse {}", calibration.rmse);
+        assert!(calibration.residuals.iter().all(|r| r.model_vol.is_some() && r.residual.abs() < 1e-3));
+        let CalibratedParams::Heston(params) = calibration.params else { panic!("expected Heston") };
+        assert!((params.rho - truth.rho).abs() < 0.1, "rho {}", params.rho);
+    }
+
+    #[test]
+    fn test_jump_model_calibration_fits_own_smiles() {
+        let merton = MertonParams { volatility: 0.5, jump_intensity: 1.5, jump_mean: -0.15, jump_volatility: 0.2 };
+        let options = chain(&[0.5], |strike, expiry| merton.implied_vol(100.0, strike, 0.0, expiry).unwrap());
+        let calibration = &calibrate(&options, 100.0, &YieldCurve::flat(0.0), NOW, CalibrationModel::Merton, &QuoteFilter::default()).unwrap()[0];
+        assert!(calibration.rmse < 1e-4, "rmse {}", calibration.rmse);
+        assert!(matches!(calibration.params, CalibratedParams::Merton(_)));
+
+        let vg = VarianceGammaParams { sigma: 0.6, nu: 0.3, theta: -0.2 };
+        let options = chain(&[0.5], |strike, expiry| vg.implied_vol(100.0, strike, 0.0, expiry).unwrap());
+        let calibration = &calibrate(&options, 100.0, &YieldCurve::flat(0.0), NOW, CalibrationModel::VarianceGamma, &QuoteFilter::default()).unwrap()[0];
+        assert!(calibration.rmse < 1e-5, "rmse {}", calibration.rmse);
+        let CalibratedParams::VarianceGamma(params) = calibration.params else { panic!("expected variance gamma") };
+        assert!((params.nu - vg.nu).abs() < 0.01 && (params.theta - vg.theta).abs() < 0.01, "{:?}", params);
+    }
+
+    #[test]
+    fn test_rejects_thin_chains() {
+        let options = chain(&[0.5], |_, _| 0.6);
+        let filter = QuoteFilter { min_quotes_per_expiry: 20, ..QuoteFilter::default() };
+        assert!(calibrate(&options, 100.0, &YieldCurve::flat(0.0), NOW, CalibrationModel::Heston, &filter).is_err());
+    }
+}
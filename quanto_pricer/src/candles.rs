@@ -0,0 +1,244 @@
+use serde_json::Value;
+use crate::fetch::FetchError;
+use crate::history::{MILLIS_PER_DAY, TimeRange};
+use crate::provider::{DeribitProvider, MarketDataProvider, malformed, number};
+use crate::realized::Candle;
+
+/// Bars asked for per request; within every venue's page limit.
+pub(crate) const BARS_PER_REQUEST: u64 = 1000;
+
+
+/// Bar length. Bars open on multiples of it since the Unix epoch, so days are UTC days.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Granularity {
+    Minute1,
+    Minute5,
+    Minute15,
+    Hour1,
+    Hour4,
+    Day1,
+}
+
+impl Granularity {
+    pub fn millis(&self) -> u64 {
+        match self {
+            Granularity::Minute1 => 60_000,
+            Granularity::Minute5 => 300_000,
+            Granularity::Minute15 => 900_000,
+            Granularity::Hour1 => 3_600_000,
+            Granularity::Hour4 => 14_400_000,
+            Granularity::Day1 => MILLIS_PER_DAY,
+        }
+    }
+
+    /// Short name, e.g. "1m" or "4h", as Binance spells intervals.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Granularity::Minute1 => "1m",
+            Granularity::Minute5 => "5m",
+            Granularity::Minute15 => "15m",
+            Granularity::Hour1 => "1h",
+            Granularity::Hour4 => "4h",
+            Granularity::Day1 => "1d",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Granularity> {
+        [Granularity::Minute1, Granularity::Minute5, Granularity::Minute15, Granularity::Hour1, Granularity::Hour4, Granularity::Day1]
+            .into_iter()
+            .find(|granularity| granularity.name() == name)
+    }
+
+    /// Deribit's `resolution`: minutes, or "1D".
+    pub fn deribit_resolution(&self) -> String {
+        match self {
+            Granularity::Day1 => "1D".to_string(),
+            other => (other.millis() / 60_000).to_string(),
+        }
+    }
+
+    /// OKX's `bar`, with days aligned to UTC rather than Hong Kong time.
+    pub fn okx_bar(&self) -> &'static str {
+        match self {
+            Granularity::Minute1 => "1m",
+            Granularity::Minute5 => "5m",
+            Granularity::Minute15 => "15m",
+            Granularity::Hour1 => "1H",
+            Granularity::Hour4 => "4H",
+            Granularity::Day1 => "1Dutc",
+        }
+    }
+
+    /// Open time of the bar containing `timestamp`.
+    pub fn floor(&self, timestamp: u64) -> u64 {
+        timestamp - timestamp % self.millis()
+    }
+}
+
+
+/// Splits `range` into consecutive ranges of at most `bars` bars each.
+pub(crate) fn request_ranges(range: TimeRange, granularity: Granularity, bars: u64) -> Vec<TimeRange> {
+    let step = granularity.millis() * bars.max(1);
+    let mut ranges = Vec::new();
+    let mut start = range.start;
+    while start < range.end {
+        let end = (start + step).min(range.end);
+        ranges.push(TimeRange::new(start, end));
+        start = end;
+    }
+    ranges
+}
+
+/// Reads `[open_time, open, high, low, close, volume, ...]` rows, as Binance and OKX send
+/// them, skipping malformed ones.
+pub(crate) fn candle_rows(rows: &[Value]) -> Vec<Candle> {
+    rows.iter()
+        .filter_map(|row| {
+            let row = row.as_array()?;
+            let field = |i: usize| row.get(i).and_then(number);
+            Some(Candle {
+                timestamp: field(0)? as u64,
+                open: field(1)?,
+                high: field(2)?,
+                low: field(3)?,
+                close: field(4)?,
+                volume: field(5)?,
+            })
+        })
+        .collect()
+}
+
+
+/// Aggregates `candles`, oldest first, into bars of `granularity`: first open, highest
+/// high, lowest low, last close and summed volume. A coarser input passes through with
+/// its timestamps floored to the new bars.
+pub fn resample(candles: &[Candle], granularity: Granularity) -> Vec<Candle> {
+    let mut bars: Vec<Candle> = Vec::new();
+    for candle in candles {
+        let timestamp = granularity.floor(candle.timestamp);
+        match bars.last_mut() {
+            Some(bar) if bar.timestamp == timestamp => {
+                bar.high = bar.high.max(candle.high);
+                bar.low = bar.low.min(candle.low);
+                bar.close = candle.close;
+                bar.volume += candle.volume;
+            }
+            _ => bars.push(Candle { timestamp, ..*candle }),
+        }
+    }
+    bars
+}
+
+/// Candles of `target` over `range`, fetched at the finer `source` the venue serves and
+/// resampled here, e.g. 1h bars built from 1m bars.
+pub async fn fetch_resampled(provider: &dyn MarketDataProvider, instrument_name: &str, source: Granularity, target: Granularity, range: TimeRange) -> Result<Vec<Candle>, FetchError> {
+    let range = TimeRange::new(target.floor(range.start), range.end);
+    let candles = provider.candles(instrument_name, source, range).await?;
+    Ok(resample(&candles, target))
+}
+
+
+impl DeribitProvider {
+    /// Bars from the TradingView chart endpoint, which returns columns rather than rows.
+    pub(crate) async fn download_candles(&self, instrument_name: &str, granularity: Granularity, range: TimeRange) -> Result<Vec<Candle>, FetchError> {
+        let mut candles = Vec::new();
+        for window in request_ranges(range, granularity, BARS_PER_REQUEST) {
+            let path = format!(
+                "/public/get_tradingview_chart_data?instrument_name={}&start_timestamp={}&end_timestamp={}&resolution={}",
+                instrument_name, window.start, window.end - 1, granularity.deribit_resolution(),
+            );
+            let response = self.get(&path).await?;
+            let result = response.get("result").ok_or_else(|| malformed("chart data"))?;
+            let column = |key: &str| result.get(key).and_then(Value::as_array).cloned().unwrap_or_default();
+            let (ticks, open, high, low, close, volume) = (column("ticks"), column("open"), column("high"), column("low"), column("close"), column("volume"));
+            for (i, tick) in ticks.iter().enumerate() {
+                let field = |values: &[Value]| values.get(i).and_then(number);
+                let (Some(timestamp), Some(open), Some(high), Some(low), Some(close)) = (tick.as_u64(), field(&open), field(&high), field(&low), field(&close)) else {
+                    return Err(malformed("chart data"));
+                };
+                candles.push(Candle { timestamp, open, high, low, close, volume: field(&volume).unwrap_or(0.0) });
+            }
+        }
+        candles.retain(|candle| range.contains(candle.timestamp));
+        candles.sort_by_key(|candle| candle.timestamp);
+        candles.dedup_by_key(|candle| candle.timestamp);
+        Ok(candles)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+    use crate::binance::BinanceProvider;
+    use crate::fetch::{FetchSettings, mock};
+    use crate::okx::OkxProvider;
+    use crate::realized::{VolEstimator, realized_volatility};
+
+    fn candle(timestamp: u64, open: f64, high: f64, low: f64, close: f64) -> Candle {
+        Candle { timestamp, open, high, low, close, volume: 1.0 }
+    }
+
+    #[test]
+    fn test_resample_minutes_to_hours() {
+        let minutes: Vec<Candle> = (0..180)
+            .map(|i| {
+                let price = 100.0 + (i as f64 * 0.3).sin();
+                candle(i * 60_000, price, price + 0.5, price - 0.5, price + 0.1)
+            })
+            .collect();
+        let hours = resample(&minutes, Granularity::Hour1);
+        assert_eq!(hours.len(), 3);
+        assert_eq!(hours[1].timestamp, 3_600_000);
+        assert_eq!(hours[1].open, minutes[60].open);
+        assert_eq!(hours[1].close, minutes[119].close);
+        assert_eq!(hours[1].volume, 60.0);
+        assert_eq!(hours[1].high, minutes[60..120].iter().map(|c| c.high).fold(f64::MIN, f64::max));
+        assert_eq!(resample(&hours, Granularity::Hour4).len(), 1);
+        assert!(realized_volatility(&resample(&minutes, Granularity::Minute15), VolEstimator::Parkinson).is_ok());
+
+        assert_eq!(Granularity::from_name("4h"), Some(Granularity::Hour4));
+        assert_eq!((Granularity::Hour1.deribit_resolution(), Granularity::Day1.deribit_resolution()), ("60".to_string(), "1D".to_string()));
+        let ranges = request_ranges(TimeRange::new(0, 2_500 * 60_000), Granularity::Minute1, BARS_PER_REQUEST);
+        assert_eq!(ranges.iter().map(|r| r.end).collect::<Vec<_>>(), vec![60_000_000, 120_000_000, 150_000_000]);
+    }
+
+    #[test]
+    fn test_venues_return_candles_oldest_first() {
+        Runtime::new().unwrap().block_on(async {
+            let (base_url, _) = mock::serve(Duration::ZERO, |path, _| {
+                if path.starts_with("/public/get_tradingview_chart_data") {
+                    (200, r#"{"result":{"status":"ok","ticks":[0,60000],"open":[1.0,2.0],"high":[1.5,2.5],"low":[0.5,1.5],"close":[1.2,2.2],"volume":[3.0,4.0]}}"#.to_string())
+                } else if path.starts_with("/api/v3/klines") {
+                    (200, r#"[[0,"1.0","1.5","0.5","1.2","3.0",59999],[60000,"2.0","2.5","1.5","2.2","4.0",119999]]"#.to_string())
+                } else {
+                    // OKX pages newest first, then returns an empty page
+                    let body = if path.contains("after=120000") {
+                        r#"{"code":"0","msg":"","data":[["60000","2.0","2.5","1.5","2.2","4.0"],["0","1.0","1.5","0.5","1.2","3.0"]]}"#
+                    } else {
+                        r#"{"code":"0","msg":"","data":[]}"#
+                    };
+                    (200, body.to_string())
+                }
+            }).await;
+            let settings = FetchSettings { base_url: base_url.clone(), ..FetchSettings::default() };
+            let expected = vec![
+                Candle { timestamp: 0, open: 1.0, high: 1.5, low: 0.5, close: 1.2, volume: 3.0 },
+                Candle { timestamp: 60_000, open: 2.0, high: 2.5, low: 1.5, close: 2.2, volume: 4.0 },
+            ];
+            let range = TimeRange::new(0, 120_000);
+
+            let deribit = DeribitProvider::new(settings.clone());
+            assert_eq!(deribit.candles("BTC-PERPETUAL", Granularity::Minute1, range).await.unwrap(), expected);
+            let binance = BinanceProvider::new(settings.clone(), &base_url);
+            assert_eq!(binance.candles("BTCUSDT", Granularity::Minute1, range).await.unwrap(), expected);
+            let okx = OkxProvider::new(settings);
+            assert_eq!(okx.candles("BTC-USDT", Granularity::Minute1, range).await.unwrap(), expected);
+
+            let two_minutes = fetch_resampled(&deribit, "BTC-PERPETUAL", Granularity::Minute1, Granularity::Minute5, range).await.unwrap();
+            assert_eq!(two_minutes, vec![Candle { timestamp: 0, open: 1.0, high: 2.5, low: 0.5, close: 2.2, volume: 7.0 }]);
+        });
+    }
+}
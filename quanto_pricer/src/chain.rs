@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use rayon::prelude::*;
+use crate::builder::InputError;
+use crate::fourier::{CharacteristicModel, FftSettings, OptionPrice, QuantoOption, call_curve, price_fft};
+
+
+/// Everything but the strike: options agreeing on it share one characteristic function,
+/// i.e. the same underlying and expiry.
+fn group_key(option: &QuantoOption) -> [u64; 8] {
+    [
+        option.spot,
+        option.domestic_rate,
+        option.foreign_rate,
+        option.volatility,
+        option.fx_volatility,
+        option.time_to_maturity,
+        option.correlation,
+        option.dividend_yield,
+    ].map(f64::to_bits)
+}
+
+
+pub fn price_chain(options: &[QuantoOption]) -> Result<Vec<OptionPrice>, InputError> {
+    price_chain_with(options, &FftSettings::default())
+}
+
+/// Prices a whole chain with one FFT per (underlying, expiry) group, interpolating each
+/// strike on the group's grid, and spreads the groups over threads. Prices come back in
+/// the order of `options`; strikes off the grid are priced with their own FFT. Fails on
+/// the first invalid option, before any pricing.
+pub fn price_chain_with(options: &[QuantoOption], settings: &FftSettings) -> Result<Vec<OptionPrice>, InputError> {
+    options.iter().try_for_each(QuantoOption::validate)?;
+    let mut groups: HashMap<[u64; 8], Vec<usize>> = HashMap::new();
+    for (i, option) in options.iter().enumerate() {
+        groups.entry(group_key(option)).or_default().push(i);
+    }
+    let groups: Vec<Vec<usize>> = groups.into_values().collect();
+
+    let priced: Vec<Vec<(usize, OptionPrice)>> = groups.par_iter()
+        .map(|indices| {
+            let model = &options[indices[0]];
+            let forward = model.forward();
+            let discount = model.discount();
+            let curve = call_curve(model, settings, forward.ln());
+
+            indices.iter()
+                .map(|i| {
+                    let strike = options[*i].strike;
+                    let price = curve.price(strike, forward, discount)
+                        .unwrap_or_else(|| price_fft(&options[*i], strike, settings));
+                    (*i, price)
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut prices = vec![OptionPrice::new(f64::NAN, f64::NAN); options.len()];
+    for (i, price) in priced.into_iter().flatten() {
+        prices[i] = price;
+    }
+    Ok(prices)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn option(strike: f64, time_to_maturity: f64) -> QuantoOption {
+        QuantoOption {
+            spot: 30000.0,
+            strike,
+            domestic_rate: 0.03,
+            foreign_rate: 0.01,
+            volatility: 0.6,
+            fx_volatility: 0.2,
+            time_to_maturity,
+            correlation: 0.4,
+            dividend_yield: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_chain_matches_individual_prices() {
+        let mut chain = Vec::new();
+        for expiry in [0.05, 0.25, 1.0] {
+            for strike in [20000.0, 25000.0, 28000.0, 30000.0, 31500.0, 35000.0, 45000.0] {
+                chain.push(option(strike, expiry));
+            }
+        }
+        // Another underlying with the same expiries is its own group
+        chain.push(QuantoOption { spot: 2000.0, strike: 2100.0, volatility: 0.8, ..option(0.0, 0.25) });
+        // Off the grid: priced individually
+        chain.push(option(1.0, 0.25));
+
+        let prices = price_chain(&chain).unwrap();
+        assert_eq!(prices.len(), chain.len());
+        for (option, price) in chain.iter().zip(&prices) {
+            let expected = option.calculate_price_fft().unwrap();
+            assert!((price.call - expected.call).abs() < 1e-3 * option.spot / 30000.0 + 1e-3, "K={} T={}", option.strike, option.time_to_maturity);
+            assert!((price.put - expected.put).abs() < 1e-3 * option.spot / 30000.0 + 1e-3);
+        }
+        assert!(price_chain(&[]).unwrap().is_empty());
+        chain.push(option(-1.0, 0.25));
+        assert!(price_chain(&chain).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use crate::error::PricingError;
+
+/// Discount curve defined by discount factors at pillar times, interpolated log-linearly
+/// (piecewise flat forward rates). Before the first pillar the curve starts from a discount
+/// factor of 1 at time 0; beyond the last pillar the last forward rate is held.
+#[derive(Debug, Clone, PartialEq)]
+pub struct YieldCurve {
+    /// Pillar times in years, strictly increasing.
+    pub times: Vec<f64>,
+    pub discount_factors: Vec<f64>,
+}
+
+impl YieldCurve {
+    /// `pillars` are (time in years, discount factor) pairs, in any order.
+    pub fn new(mut pillars: Vec<(f64, f64)>) -> Result<Self, PricingError> {
+        if pillars.is_empty() {
+            return Err(PricingError::InvalidInput("yield curve needs at least one pillar"));
+        }
+        pillars.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+        if pillars.iter().any(|(t, df)| !(t.is_finite() && df.is_finite()) || *t <= 0.0 || *df <= 0.0) {
+            return Err(PricingError::InvalidInput("pillar times and discount factors must be positive"));
+        }
+        if pillars.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return Err(PricingError::InvalidInput("duplicate pillar time"));
+        }
+
+        Ok(YieldCurve {
+            times: pillars.iter().map(|(t, _)| *t).collect(),
+            discount_factors: pillars.iter().map(|(_, df)| *df).collect(),
+        })
+    }
+
+    /// `pillars` are (time in years, continuously compounded zero rate) pairs.
+    pub fn from_zero_rates(pillars: &[(f64, f64)]) -> Result<Self, PricingError> {
+        YieldCurve::new(pillars.iter().map(|(t, rate)| (*t, (-rate * t).exp())).collect())
+    }
+
+    pub fn flat(rate: f64) -> Self {
+        YieldCurve {
+            times: vec![1.0],
+            discount_factors: vec![(-rate).exp()],
+        }
+    }
+
+    pub fn discount(&self, t: f64) -> f64 {
+        if t <= 0.0 {
+            return 1.0;
+        }
+        let next = self.times.iter().position(|pillar| *pillar >= t);
+        let (t0, df0, t1, df1) = match next {
+            Some(0) => (0.0, 1.0, self.times[0], self.discount_factors[0]),
+            Some(i) => (self.times[i - 1], self.discount_factors[i - 1], self.times[i], self.discount_factors[i]),
+            None => {
+                let n = self.times.len();
+                if n == 1 {
+                    (0.0, 1.0, self.times[0], self.discount_factors[0])
+                } else {
+                    (self.times[n - 2], self.discount_factors[n - 2], self.times[n - 1], self.discount_factors[n - 1])
+                }
+            }
+        };
+        let weight = (t - t0) / (t1 - t0);
+        (df0.ln() + weight * (df1.ln() - df0.ln())).exp()
+    }
+
+    /// Continuously compounded zero rate to `t`; at `t = 0` the short rate.
+    pub fn zero_rate(&self, t: f64) -> f64 {
+        let t = if t <= 0.0 { 1e-8 } else { t };
+        -self.discount(t).ln() / t
+    }
+
+    /// Continuously compounded forward rate between `start` and `end`.
+    pub fn forward_rate(&self, start: f64, end: f64) -> f64 {
+        (self.discount(start) / self.discount(end)).ln() / (end - start)
+    }
+}
+
+impl From<f64> for YieldCurve {
+    fn from(rate: f64) -> Self {
+        YieldCurve::flat(rate)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flat_curve() {
+        let curve = YieldCurve::flat(0.05);
+        for t in [0.1, 1.0, 7.5] {
+            assert!((curve.discount(t) - (-0.05 * t).exp()).abs() < 1e-14);
+            assert!((curve.zero_rate(t) - 0.05).abs() < 1e-12);
+        }
+        assert_eq!(curve.discount(0.0), 1.0);
+    }
+
+    #[test]
+    fn test_log_linear_interpolation() {
+        let curve = YieldCurve::from_zero_rates(&[(0.5, 0.02), (1.0, 0.03), (2.0, 0.04)]).unwrap();
+
+        // Pillars are reproduced exactly
+        assert!((curve.zero_rate(1.0) - 0.03).abs() < 1e-14);
+        // Forward rates are flat between pillars: 1y→2y forward is 2 × 0.04 - 0.03
+        assert!((curve.forward_rate(1.2, 1.7) - 0.05).abs() < 1e-12);
+        assert!((curve.forward_rate(0.5, 1.0) - 0.04).abs() < 1e-12);
+        // The last forward is held beyond the last pillar
+        assert!((curve.forward_rate(3.0, 5.0) - 0.05).abs() < 1e-12);
+        // Before the first pillar the zero rate is flat
+        assert!((curve.zero_rate(0.25) - 0.02).abs() < 1e-12);
+
+        assert!(YieldCurve::new(vec![]).is_err());
+        assert!(YieldCurve::new(vec![(1.0, 0.9), (1.0, 0.95)]).is_err());
+        assert!(YieldCurve::new(vec![(1.0, -0.9)]).is_err());
+    }
+
+    #[test]
+    fn test_pricers_use_rate_at_their_expiry() {
+        use crate::fourier::QuantoOption;
+
+        let domestic = YieldCurve::from_zero_rates(&[(0.25, 0.01), (2.0, 0.05)]).unwrap();
+        let foreign = YieldCurve::flat(0.0);
+        let option = |time_to_maturity: f64| QuantoOption {
+            spot: 100.0,
+            strike: 100.0,
+            domestic_rate: 0.0,
+            foreign_rate: 0.0,
+            volatility: 0.2,
+            fx_volatility: 0.0,
+            time_to_maturity,
+            correlation: 0.0,
+            dividend_yield: 0.0,
+        }.with_curves(&domestic, &foreign);
+
+        let short = option(0.25);
+        let long = option(2.0);
+        assert!((short.domestic_rate - 0.01).abs() < 1e-12);
+        assert!((long.domestic_rate - 0.05).abs() < 1e-12);
+        // Discounting of the option matches the curve at each expiry
+        let parity = |o: &QuantoOption| {
+            let price = o.calculate_price_fft().unwrap();
+            price.call - price.put
+        };
+        assert!((parity(&short) - (100.0 - 100.0 * domestic.discount(0.25))).abs() < 1e-6);
+        assert!((parity(&long) - (100.0 - 100.0 * domestic.discount(2.0))).abs() < 1e-6);
+    }
+}
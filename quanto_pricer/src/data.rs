@@ -1,8 +1,19 @@
-use serde::Deserialize;
-use reqwest::Error;
+#[cfg(feature = "network")]
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "network")]
+use futures_util::{StreamExt, stream};
+#[cfg(feature = "network")]
+use reqwest::Client;
+use model::model::{Asset, Basket};
+#[cfg(feature = "network")]
+use crate::fetch::{FetchError, FetchFailure, FetchReport, FetchSettings, RateLimiter, get_json};
+use crate::fourier::QuantoOption;
+#[cfg(feature = "network")]
+use crate::freshness::{Checked, StaleInput, StalenessPolicy, now_millis};
 
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DeribitOptionData {
     pub instrument_name: String,     // Option name (e.g., "BTC-29SEP24-56000-C")
     pub strike: f64,                 // Strike price of the option
@@ -16,6 +27,14 @@ pub struct DeribitOptionData {
     pub gamma: Option<f64>,
     pub vega: Option<f64>,
     pub theta: Option<f64>,
+    /// Contracts outstanding, where the venue reports it.
+    #[serde(default)]
+    pub open_interest: Option<f64>,
+    /// Exchange time of the mark, and when it arrived, in Unix milliseconds.
+    #[serde(default)]
+    pub exchange_timestamp: Option<u64>,
+    #[serde(default)]
+    pub received_at: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -23,10 +42,33 @@ pub struct DeribitApiResponse {
     pub result: Vec<DeribitOptionData>,
 }
 
+#[cfg(feature = "network")]
 impl DeribitOptionData {
-    pub async fn fetch_data(asset: &str) -> Result<Vec<DeribitOptionData>, Error> {
-        let url = format!("https://www.deribit.com/api/v2/public/get_instruments?currency={}&kind=option&expired=false", asset);
-        let response: DeribitApiResponse = reqwest::get(&url).await?.json().await?;
+    pub async fn fetch_data(asset: &str) -> Result<Vec<DeribitOptionData>, FetchError> {
+        Ok(Self::fetch_data_with(asset, &FetchSettings::default()).await?.data)
+    }
+
+    /// Lists the asset's live options, then fills in each one's mark price, IV and greeks
+    /// from its order book, fetching `settings.concurrency` books at a time. Only the instrument list is essential: an order book that still
+    /// fails after retries leaves that option unpriced and is reported as a failure.
+    pub async fn fetch_data_with(asset: &str, settings: &FetchSettings) -> Result<FetchReport<DeribitOptionData>, FetchError> {
+        Self::fetch_chain(asset, settings, &Client::new(), &settings.rate_limiter()).await
+    }
+
+    /// [`DeribitOptionData::fetch_data_with`] on a client and rate limit shared with other requests.
+    pub(crate) async fn fetch_chain(asset: &str, settings: &FetchSettings, client: &Client, limiter: &RateLimiter) -> Result<FetchReport<DeribitOptionData>, FetchError> {
+        Self::fetch_chain_where(asset, settings, client, limiter, |_| true).await
+    }
+
+    /// [`DeribitOptionData::fetch_chain`] pricing only the listed options `keep` accepts,
+    /// so unwanted quotes cost no order book requests.
+    pub(crate) async fn fetch_chain_where<F>(asset: &str, settings: &FetchSettings, client: &Client, limiter: &RateLimiter, keep: F) -> Result<FetchReport<DeribitOptionData>, FetchError>
+    where
+        F: Fn(&DeribitOptionData) -> bool,
+    {
+        let url = format!("{}/public/get_instruments?currency={}&kind=option&expired=false", settings.base_url, asset);
+        let response = get_json(client, &url, limiter, &settings.retry).await?;
+        let response: DeribitApiResponse = serde_json::from_value(response).map_err(|e| FetchError::Api(e.to_string()))?;
 
         let mut options_data: Vec<DeribitOptionData> = response.result.into_iter()
             .map(|data| DeribitOptionData {
@@ -42,39 +84,232 @@ impl DeribitOptionData {
                 gamma: None,
                 theta: None,
                 vega: None,
+                open_interest: None,
+                exchange_timestamp: None,
+                received_at: None,
             })
+            .filter(|option| keep(option))
+            .collect();
+
+        let orderbook_urls: Vec<String> = options_data.iter()
+            .map(|option| format!("{}/public/get_order_book?instrument_name={}", settings.base_url, option.instrument_name))
             .collect();
+        let books: Vec<(usize, Result<serde_json::Value, FetchError>)> = stream::iter(orderbook_urls.into_iter().enumerate())
+            .map(|(i, orderbook_url)| {
+                async move { (i, get_json(client, &orderbook_url, limiter, &settings.retry).await) }
+            })
+            .buffer_unordered(settings.concurrency.max(1))
+            .collect()
+            .await;
 
-        for option in &mut options_data {
-            let orderbook_url = format!("https://www.deribit.com/api/v2/public/get_order_book?instrument_name={}", option.instrument_name);
-            if let Ok(orderbook_response) = reqwest::get(&orderbook_url).await?.json::<serde_json::Value>().await {
-                if let Some(result) = orderbook_response.get("result") {
-                    // Parse market price, best bid, and implied volatility
-                    option.market_price = result.get("mark_price").and_then(|p| p.as_f64());
-                    option.implied_volatility = result.get("mark_iv").and_then(|iv| iv.as_f64());
-
-                    // Parse Greeks (delta, gamma, theta, vega)
-                    if let Some(greeks) = result.get("greeks") {
-                        option.delta = greeks.get("delta").and_then(|v| v.as_f64());
-                        option.gamma = greeks.get("gamma").and_then(|v| v.as_f64());
-                        option.theta = greeks.get("theta").and_then(|v| v.as_f64());
-                        option.vega = greeks.get("vega").and_then(|v| v.as_f64());
+        let mut failures = Vec::new();
+        for (i, book) in books {
+            match book {
+                Ok(orderbook_response) => {
+                    if let Some(result) = orderbook_response.get("result") {
+                        options_data[i].apply_order_book(result);
                     }
                 }
+                Err(error) => failures.push(FetchFailure { item: options_data[i].instrument_name.clone(), error }),
             }
         }
+        failures.sort_by(|a, b| a.item.cmp(&b.item));
 
-        Ok(options_data)
+        Ok(FetchReport { data: options_data, failures })
     }
+
+    fn apply_order_book(&mut self, result: &serde_json::Value) {
+        // Parse market price, best bid, and implied volatility
+        self.market_price = result.get("mark_price").and_then(|p| p.as_f64());
+        self.implied_volatility = result.get("mark_iv").and_then(|iv| iv.as_f64());
+        self.open_interest = result.get("open_interest").and_then(|oi| oi.as_f64());
+        self.exchange_timestamp = result.get("timestamp").and_then(|t| t.as_u64());
+        self.received_at = Some(now_millis());
+
+        // Parse Greeks (delta, gamma, theta, vega)
+        if let Some(greeks) = result.get("greeks") {
+            self.delta = greeks.get("delta").and_then(|v| v.as_f64());
+            self.gamma = greeks.get("gamma").and_then(|v| v.as_f64());
+            self.theta = greeks.get("theta").and_then(|v| v.as_f64());
+            self.vega = greeks.get("vega").and_then(|v| v.as_f64());
+        }
+    }
+}
+
+
+/// A Deribit price index, e.g. "btc_usd": the reference spot for options, futures and
+/// settlement on that pair.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct DeribitIndexPrice {
+    pub index_price: f64,
+    /// Price the index would settle at now, smoothed over the final half hour before expiry.
+    #[serde(default)]
+    pub estimated_delivery_price: Option<f64>,
+    /// When Deribit answered, and when the answer arrived, in Unix milliseconds.
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    #[serde(default)]
+    pub received_at: Option<u64>,
 }
 
+impl DeribitIndexPrice {
+    /// Splits an index name such as "btc_usd" into its ("BTC", "USD") currency pair.
+    pub fn currency_pair(index_name: &str) -> Option<(String, String)> {
+        let (base, quote) = index_name.split_once('_')?;
+        Some((base.to_uppercase(), quote.to_uppercase()))
+    }
 
-#[cfg(test)]
+    #[cfg(feature = "network")]
+    pub async fn fetch(index_name: &str, settings: &FetchSettings) -> Result<DeribitIndexPrice, FetchError> {
+        let url = format!("{}/public/get_index_price?index_name={}", settings.base_url, index_name);
+        let response = get_json(&Client::new(), &url, &settings.rate_limiter(), &settings.retry).await?;
+        // JSON-RPC responses carry the server's send time in microseconds
+        let timestamp = response.get("usOut").and_then(|us| us.as_u64()).map(|us| us / 1000);
+        let index: DeribitIndexPrice = parse_result(response)?;
+        Ok(DeribitIndexPrice { timestamp, received_at: Some(now_millis()), ..index })
+    }
+
+    /// Fetches index prices as FX rates keyed by currency pair, e.g. to feed
+    /// `auction::fx::FxRates` for multi-currency settlement.
+    #[cfg(feature = "network")]
+    pub async fn fetch_fx_rates(index_names: &[&str]) -> Result<HashMap<(String, String), f64>, FetchError> {
+        let settings = FetchSettings::default();
+        let client = Client::new();
+        let limiter = settings.rate_limiter();
+        let mut rates = HashMap::new();
+
+        for index_name in index_names {
+            let url = format!("{}/public/get_index_price?index_name={}", settings.base_url, index_name);
+            let response = get_json(&client, &url, &limiter, &settings.retry).await?;
+            let index: Option<DeribitIndexPrice> = response.get("result").and_then(|r| serde_json::from_value(r.clone()).ok());
+
+            if let (Some(pair), Some(index)) = (DeribitIndexPrice::currency_pair(index_name), index) {
+                rates.insert(pair, index.index_price);
+            }
+        }
+
+        Ok(rates)
+    }
+
+    /// Reprices `asset` in `basket` at the index.
+    pub fn update_basket(&self, basket: &mut Basket, asset: &Asset) {
+        basket.update_price(asset, self.index_price);
+    }
+}
+
+
+/// Deribit funds perpetuals continuously, quoted per 8 hours.
+const FUNDING_PERIODS_PER_YEAR: f64 = 3.0 * 365.0;
+
+/// Current state of a Deribit perpetual, e.g. "BTC-PERPETUAL", from its ticker.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DeribitPerpetual {
+    pub instrument_name: String,
+    /// Exchange time in Unix milliseconds.
+    pub timestamp: u64,
+    pub index_price: f64,
+    pub mark_price: f64,
+    /// Instantaneous funding rate, as a fraction per 8 hours.
+    pub current_funding: f64,
+    /// Funding accrued over the last 8 hours, as a fraction.
+    pub funding_8h: f64,
+    #[serde(default)]
+    pub received_at: Option<u64>,
+}
+
+impl DeribitPerpetual {
+    #[cfg(feature = "network")]
+    pub async fn fetch(instrument_name: &str, settings: &FetchSettings) -> Result<DeribitPerpetual, FetchError> {
+        let url = format!("{}/public/ticker?instrument_name={}", settings.base_url, instrument_name);
+        let response = get_json(&Client::new(), &url, &settings.rate_limiter(), &settings.retry).await?;
+        let perpetual: DeribitPerpetual = parse_result(response)?;
+        Ok(DeribitPerpetual { received_at: Some(now_millis()), ..perpetual })
+    }
+
+    /// Last 8 hours' funding as an annual rate paid by longs to shorts.
+    pub fn annualized_funding(&self) -> f64 {
+        self.funding_8h * FUNDING_PERIODS_PER_YEAR
+    }
+}
+
+
+/// One hourly point of a perpetual's funding history.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct DeribitFundingRate {
+    /// Unix milliseconds at the end of the hour.
+    pub timestamp: u64,
+    pub index_price: f64,
+    pub prev_index_price: f64,
+    /// Funding over the hour, and over the 8 hours to `timestamp`, as fractions.
+    pub interest_1h: f64,
+    pub interest_8h: f64,
+}
+
+impl DeribitFundingRate {
+    /// Funding between two Unix-millisecond timestamps, oldest first.
+    #[cfg(feature = "network")]
+    pub async fn fetch_history(instrument_name: &str, start_timestamp: u64, end_timestamp: u64, settings: &FetchSettings) -> Result<Vec<DeribitFundingRate>, FetchError> {
+        let url = format!(
+            "{}/public/get_funding_rate_history?instrument_name={}&start_timestamp={}&end_timestamp={}",
+            settings.base_url, instrument_name, start_timestamp, end_timestamp,
+        );
+        let response = get_json(&Client::new(), &url, &settings.rate_limiter(), &settings.retry).await?;
+        let mut history: Vec<DeribitFundingRate> = parse_result(response)?;
+        history.sort_by_key(|rate| rate.timestamp);
+        Ok(history)
+    }
+
+    pub fn annualized(&self) -> f64 {
+        self.interest_8h * FUNDING_PERIODS_PER_YEAR
+    }
+
+    /// Mean annualised funding over `history`, a steadier carry estimate than the latest point.
+    pub fn mean_annualized(history: &[DeribitFundingRate]) -> Option<f64> {
+        if history.is_empty() {
+            return None;
+        }
+        Some(history.iter().map(|rate| rate.interest_1h).sum::<f64>() / history.len() as f64 * 24.0 * 365.0)
+    }
+}
+
+
+#[cfg(feature = "network")]
+fn parse_result<T: serde::de::DeserializeOwned>(response: serde_json::Value) -> Result<T, FetchError> {
+    let result = response.get("result").cloned().ok_or_else(|| FetchError::Api("response has no result".to_string()))?;
+    serde_json::from_value(result).map_err(|e| FetchError::Api(e.to_string()))
+}
+
+
+impl QuantoOption {
+    /// Spot at the exchange index and carry from perpetual funding, in place of manual
+    /// constants. Longs pay positive `annualized_funding`, which prices like a negative yield.
+    pub fn with_reference(mut self, index: &DeribitIndexPrice, annualized_funding: f64) -> Self {
+        self.spot = index.index_price;
+        self.dividend_yield = -annualized_funding;
+        self
+    }
+
+    /// [`QuantoOption::with_reference`] from a perpetual's current funding, provided both
+    /// inputs are fresh enough for `policy` at `now`.
+    #[cfg(feature = "network")]
+    pub fn with_checked_reference(self, index: &DeribitIndexPrice, perpetual: &DeribitPerpetual, now: u64, policy: &StalenessPolicy) -> Result<Checked<QuantoOption>, StaleInput> {
+        let warnings = policy.check_all(&[("index price", index), (&perpetual.instrument_name, perpetual)], now)?;
+        Ok(Checked { value: self.with_reference(index, perpetual.annualized_funding()), warnings })
+    }
+}
+
+
+#[cfg(all(test, feature = "network"))]
 mod tests {
     use super::*;
+    use std::time::{Duration, Instant};
+    use crate::fourier::CharacteristicModel;
+    use crate::provider::MarketDataProvider;
+    use crate::replay::MockProvider;
     use tokio::runtime::Runtime;
 
     #[test]
+    #[ignore = "hits the live Deribit API; test_fixture_option_chain covers the same checks offline"]
     fn test_fetch_deribit_data() {
         // Create a tokio runtime to run the async test
         let rt = Runtime::new().unwrap();
@@ -104,4 +339,136 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_fixture_option_chain() {
+        Runtime::new().unwrap().block_on(async {
+            let provider = MockProvider::from_file(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/deribit_btc.json")).unwrap();
+            let option_data = provider.option_chain("BTC").await.unwrap().data;
+            assert!(!option_data.is_empty(), "No options data in the fixture");
+            assert!(option_data.iter().all(|option| option.strike > 0.0 && option.implied_volatility.unwrap() > 0.0));
+            assert_eq!(provider.spot_price(&Asset::new("BTC", "USD")).await, Ok(68250.5));
+        });
+    }
+
+    #[test]
+    fn test_fetch_tolerates_failed_order_books() {
+        Runtime::new().unwrap().block_on(async {
+            let (base_url, _) = crate::fetch::mock::serve(Duration::ZERO, |path, _| {
+                let instrument = |name: &str| format!(
+                    r#"{{"instrument_name":"{}","strike":60000.0,"expiration_timestamp":1735286400000,"option_type":"call","price_index":"btc_usd","settlement_currency":"BTC"}}"#,
+                    name,
+                );
+                if path.starts_with("/public/get_instruments") {
+                    (200, format!(r#"{{"result":[{},{}]}}"#, instrument("BTC-A"), instrument("BTC-B")))
+                } else if path.ends_with("BTC-A") {
+                    (200, r#"{"result":{"mark_price":0.05,"mark_iv":60.0,"greeks":{"delta":0.4,"gamma":0.0,"theta":-1.0,"vega":2.0}}}"#.to_string())
+                } else {
+                    (400, String::new())
+                }
+            }).await;
+            let settings = FetchSettings { base_url, requests_per_second: 1000.0, ..FetchSettings::default() };
+
+            let report = DeribitOptionData::fetch_data_with("BTC", &settings).await.unwrap();
+            assert_eq!(report.data.len(), 2);
+            assert_eq!(report.data[0].implied_volatility, Some(60.0));
+            assert_eq!(report.data[1].market_price, None);
+            assert_eq!(report.failures, vec![FetchFailure { item: "BTC-B".to_string(), error: FetchError::Http { status: 400 } }]);
+            assert_eq!(report.summary(), "1 failed: 1 × HTTP 400");
+        });
+    }
+
+    #[test]
+    fn test_order_books_are_fetched_concurrently() {
+        Runtime::new().unwrap().block_on(async {
+            let (base_url, requests) = crate::fetch::mock::serve(Duration::from_millis(100), |path, _| {
+                if path.starts_with("/public/get_instruments") {
+                    let instruments: Vec<String> = (0..16).map(|i| format!(
+                        r#"{{"instrument_name":"BTC-{}","strike":{}.0,"expiration_timestamp":1735286400000,"option_type":"put","price_index":"btc_usd","settlement_currency":"BTC"}}"#,
+                        i, 50000 + 1000 * i,
+                    )).collect();
+                    (200, format!(r#"{{"result":[{}]}}"#, instruments.join(",")))
+                } else {
+                    (200, r#"{"result":{"mark_price":0.01,"mark_iv":55.0}}"#.to_string())
+                }
+            }).await;
+            let settings = FetchSettings { base_url, requests_per_second: 1000.0, concurrency: 8, ..FetchSettings::default() };
+
+            let start = Instant::now();
+            let report = DeribitOptionData::fetch_data_with("BTC", &settings).await.unwrap();
+            let elapsed = start.elapsed();
+            // One listing and two waves of eight books, where one at a time would take 1.7s
+            assert!(elapsed < Duration::from_millis(800), "{:?}", elapsed);
+            assert!(report.is_complete());
+            assert_eq!(requests.load(std::sync::atomic::Ordering::SeqCst), 17);
+            assert!(report.data.iter().enumerate().all(|(i, o)| o.strike == (50000 + 1000 * i) as f64 && o.market_price == Some(0.01)));
+        });
+    }
+
+    #[test]
+    fn test_index_and_funding_endpoints() {
+        Runtime::new().unwrap().block_on(async {
+            let (base_url, _) = crate::fetch::mock::serve(Duration::ZERO, |path, _| {
+                let result = match path.split('?').next().unwrap() {
+                    "/public/get_index_price" => r#"{"index_price":64000.0,"estimated_delivery_price":63990.0}"#,
+                    "/public/ticker" => r#"{"instrument_name":"BTC-PERPETUAL","timestamp":1700000000000,"index_price":64000.0,"mark_price":64020.0,"current_funding":0.00002,"funding_8h":0.0001,"open_interest":1.0}"#,
+                    "/public/get_funding_rate_history" => r#"[
+                        {"timestamp":1700003600000,"index_price":64100.0,"prev_index_price":64000.0,"interest_1h":0.00002,"interest_8h":0.00012},
+                        {"timestamp":1700000000000,"index_price":64000.0,"prev_index_price":63900.0,"interest_1h":0.00001,"interest_8h":0.0001}]"#,
+                    _ => return (400, String::new()),
+                };
+                (200, format!(r#"{{"result":{}}}"#, result))
+            }).await;
+            let settings = FetchSettings { base_url, ..FetchSettings::default() };
+
+            let index = DeribitIndexPrice::fetch("btc_usd", &settings).await.unwrap();
+            assert_eq!((index.index_price, index.estimated_delivery_price), (64000.0, Some(63990.0)));
+            assert!(index.received_at.is_some());
+            let perpetual = DeribitPerpetual::fetch("BTC-PERPETUAL", &settings).await.unwrap();
+            assert!((perpetual.annualized_funding() - 0.1095).abs() < 1e-12);
+
+            let history = DeribitFundingRate::fetch_history("BTC-PERPETUAL", 1700000000000, 1700003600000, &settings).await.unwrap();
+            assert_eq!(history.iter().map(|rate| rate.timestamp).collect::<Vec<_>>(), vec![1700000000000, 1700003600000]);
+            assert!((DeribitFundingRate::mean_annualized(&history).unwrap() - 0.1314).abs() < 1e-12);
+            assert!(DeribitFundingRate::mean_annualized(&[]).is_none());
+        });
+    }
+
+    #[test]
+    fn test_reference_prices_set_spot_and_carry() {
+        let index = DeribitIndexPrice { index_price: 64000.0, estimated_delivery_price: None, timestamp: None, received_at: None };
+        let option = QuantoOption::builder().spot(1.0).strike(60000.0).volatility(0.6).time_to_maturity(0.5).domestic_rate(0.04).build().unwrap()
+            .with_reference(&index, 0.1);
+        // Positive funding lifts the forward above the rate-only carry
+        assert_eq!(option.spot, 64000.0);
+        assert!((option.forward() - 64000.0 * (0.14f64 * 0.5).exp()).abs() < 1e-6);
+
+        // Checked, the reference must be recent enough for the policy
+        let now = 1_700_000_010_000;
+        let index = DeribitIndexPrice { timestamp: Some(now - 1_000), ..index };
+        let perpetual = DeribitPerpetual {
+            instrument_name: "BTC-PERPETUAL".to_string(),
+            timestamp: now - 60_000,
+            index_price: 64000.0,
+            mark_price: 64010.0,
+            current_funding: 0.0,
+            funding_8h: 0.0001,
+            received_at: Some(now - 60_000),
+        };
+        let policy = StalenessPolicy::default();
+        let error = option.with_checked_reference(&index, &perpetual, now, &policy).unwrap_err();
+        assert_eq!(error.item, "BTC-PERPETUAL");
+        let fresh = DeribitPerpetual { timestamp: now - 500, ..perpetual };
+        let checked = option.with_checked_reference(&index, &fresh, now, &policy).unwrap();
+        assert!(checked.is_fresh() && checked.value.dividend_yield == -fresh.annualized_funding());
+
+        let mut basket = Basket { id: model::ids::BasketId(1), assets: vec![model::model::AssetInfo::from_str("BTC/USD", 2.0, 1.0).unwrap()] };
+        index.update_basket(&mut basket, &Asset::new("BTC", "USD"));
+        assert_eq!(basket.total_value(), 128000.0);
+    }
+
+    #[test]
+    fn test_index_currency_pair() {
+        assert_eq!(DeribitIndexPrice::currency_pair("btc_usd"), Some((String::from("BTC"), String::from("USD"))));
+        assert_eq!(DeribitIndexPrice::currency_pair("btcusd"), None);
+    }
 }
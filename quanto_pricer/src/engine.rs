@@ -0,0 +1,96 @@
+use crate::cos::{CosSettings, price_cos};
+use crate::fourier::{CharacteristicModel, FftSettings, OptionPrice, RefinementSettings, price_fft, price_fft_refined};
+use crate::monte_carlo::{McSettings, TerminalSampler, price_european};
+
+
+/// Numerical method used to price a European option, chosen per request.
+#[derive(Debug, Clone, Copy)]
+pub enum PricingEngine {
+    Fft(FftSettings),
+    /// FFT that grows its grid until the price's error estimate meets a tolerance.
+    RefinedFft(RefinementSettings),
+    Cos(CosSettings),
+    MonteCarlo(McSettings),
+}
+
+impl Default for PricingEngine {
+    fn default() -> Self {
+        PricingEngine::Fft(FftSettings::default())
+    }
+}
+
+impl PricingEngine {
+    pub fn price<M: CharacteristicModel + TerminalSampler + ?Sized>(&self, model: &M, strike: f64) -> OptionPrice {
+        match self {
+            PricingEngine::Fft(settings) => price_fft(model, strike, settings),
+            PricingEngine::RefinedFft(settings) => price_fft_refined(model, strike, settings),
+            PricingEngine::Cos(settings) => price_cos(model, strike, settings),
+            PricingEngine::MonteCarlo(settings) => price_european(model, strike, settings).to_option_price(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fourier::QuantoOption;
+    use crate::heston::HestonModel;
+
+    fn engines() -> [PricingEngine; 3] {
+        [
+            PricingEngine::default(),
+            PricingEngine::Cos(CosSettings::default()),
+            PricingEngine::MonteCarlo(McSettings { paths: 100_000, steps: 50, seed: 11 }),
+        ]
+    }
+
+    #[test]
+    fn test_engines_agree_on_quanto_option() {
+        let option = QuantoOption {
+            spot: 30000.0,
+            strike: 33000.0,
+            domestic_rate: 0.03,
+            foreign_rate: 0.01,
+            volatility: 0.6,
+            fx_volatility: 0.2,
+            time_to_maturity: 0.5,
+            correlation: 0.4,
+            dividend_yield: 0.0,
+        };
+        let prices = engines().map(|engine| option.price_with(&engine).unwrap());
+
+        assert!((prices[0].call - prices[1].call).abs() < 1e-3);
+        assert!((prices[0].put - prices[1].put).abs() < 1e-3);
+        assert!((prices[2].call - prices[1].call).abs() / prices[1].call < 0.01);
+        assert!((prices[2].put - prices[1].put).abs() / prices[1].put < 0.01);
+        // Monte Carlo reports its standard error, which covers the gap to the exact price
+        let diagnostics = prices[2].diagnostics.unwrap();
+        assert_eq!(diagnostics.grid_size, 100_000);
+        assert!((prices[2].call - prices[1].call).abs() < 4.0 * diagnostics.std_error);
+        assert_eq!(prices[1].diagnostics.unwrap().grid_size, 256);
+    }
+
+    #[test]
+    fn test_engines_agree_on_heston() {
+        let model = HestonModel {
+            spot: 100.0,
+            strike: 90.0,
+            domestic_rate: 0.02,
+            foreign_rate: 0.0,
+            v0: 0.04,
+            kappa: 2.0,
+            theta: 0.05,
+            sigma: 0.4,
+            rho: -0.6,
+            time_to_maturity: 1.0,
+            fx_volatility: 0.0,
+            correlation: 0.0,
+        };
+        let prices = engines().map(|engine| model.price_with(&engine));
+
+        assert!((prices[0].call - prices[1].call).abs() < 1e-4);
+        assert!((prices[2].call - prices[1].call).abs() / prices[1].call < 0.01);
+        assert!((prices[2].put - prices[1].put).abs() / prices[1].put < 0.03);
+    }
+}
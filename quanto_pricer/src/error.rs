@@ -0,0 +1,59 @@
+use thiserror::Error;
+use model::error::ModelError;
+use crate::builder::InputError;
+use crate::implied_vol::ImpliedVolError;
+
+
+/// Why a price, fit or calibration could not be produced.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum PricingError {
+    /// A parameter is out of its domain, e.g. a non-positive forward.
+    #[error("invalid input: {0}")]
+    InvalidInput(&'static str),
+    /// Too few quotes, candles or points to fit anything to.
+    #[error("insufficient data: {0}")]
+    InsufficientData(&'static str),
+    /// The fit ran but its result is unusable.
+    #[error("calibration failed: {0}")]
+    Calibration(&'static str),
+    #[error("no forward for {0}")]
+    MissingForward(String),
+    #[error(transparent)]
+    Input(#[from] InputError),
+    #[error(transparent)]
+    ImpliedVol(#[from] ImpliedVolError),
+    #[error(transparent)]
+    Data(#[from] DataError),
+    #[error(transparent)]
+    Model(#[from] ModelError),
+}
+
+
+/// Why market data could not be read, e.g. an instrument name no venue uses.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum DataError {
+    #[error("malformed {what} in '{text}'")]
+    Malformed { what: &'static str, text: String },
+    #[error("unrecognised {venue} instrument '{name}'")]
+    UnrecognisedInstrument { venue: &'static str, name: String },
+    #[error("unsupported: {0}")]
+    Unsupported(&'static str),
+    #[error("bad candles: {0}")]
+    BadCandles(&'static str),
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curve::YieldCurve;
+
+    #[test]
+    fn test_errors_are_matchable_and_convert() {
+        assert_eq!(YieldCurve::new(Vec::new()), Err(PricingError::InvalidInput("yield curve needs at least one pillar")));
+        let error: PricingError = InputError::Missing("spot").into();
+        assert_eq!(error.to_string(), "spot is required");
+        let error: PricingError = DataError::Unsupported("Binance perpetuals share their spot symbol").into();
+        assert!(matches!(error, PricingError::Data(DataError::Unsupported(_))));
+    }
+}
@@ -0,0 +1,374 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+use reqwest::Client;
+use serde_json::Value;
+use tokio::sync::Mutex;
+use tokio::time::{Instant, sleep};
+use auction::config::ProviderConfig;
+use auction::stats::FETCH_ERRORS;
+
+pub const DERIBIT_API_URL: &str = "https://www.deribit.com/api/v2";
+
+
+/// Why a REST request failed, after any retries.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FetchError {
+    /// The request never completed: connection, timeout or body errors.
+    Request(String),
+    Http { status: u16 },
+    /// The exchange answered with a JSON-RPC error.
+    Api(String),
+    /// Reading or writing locally cached data failed.
+    Storage(String),
+}
+
+impl FetchError {
+    /// Transient failures worth retrying: network errors, throttling and server errors.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            FetchError::Request(_) => true,
+            FetchError::Http { status } => *status == 429 || *status >= 500,
+            FetchError::Api(_) | FetchError::Storage(_) => false,
+        }
+    }
+
+    /// Short name of the variant, e.g. for metric labels.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FetchError::Request(_) => "request",
+            FetchError::Http { .. } => "http",
+            FetchError::Api(_) => "api",
+            FetchError::Storage(_) => "storage",
+        }
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Request(reason) => write!(f, "request failed: {}", reason),
+            FetchError::Http { status } => write!(f, "HTTP {}", status),
+            FetchError::Api(reason) => write!(f, "API error: {}", reason),
+            FetchError::Storage(reason) => write!(f, "storage error: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+
+/// Token bucket shared by all requests to one exchange: `burst` requests at once, refilled
+/// at `requests_per_second`.
+#[derive(Debug)]
+pub struct RateLimiter {
+    requests_per_second: f64,
+    burst: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: f64, burst: u32) -> Self {
+        let burst = burst.max(1) as f64;
+        RateLimiter {
+            requests_per_second: requests_per_second.max(1e-9),
+            burst,
+            state: Mutex::new((burst, Instant::now())),
+        }
+    }
+
+    /// Waits until a request may be sent.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().await;
+                let now = Instant::now();
+                let (tokens, last) = *state;
+                let tokens = (tokens + (now - last).as_secs_f64() * self.requests_per_second).min(self.burst);
+                if tokens >= 1.0 {
+                    *state = (tokens - 1.0, now);
+                    return;
+                }
+                *state = (tokens, now);
+                Duration::from_secs_f64((1.0 - tokens) / self.requests_per_second)
+            };
+            sleep(wait).await;
+        }
+    }
+}
+
+
+/// Exponential backoff: retry n waits `base_delay` × 2ⁿ, capped at `max_delay`, shortened
+/// by a random fraction of up to `jitter` so that clients throttled together spread out.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 4,
+            base_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(8),
+            jitter: 0.5,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry `attempt` (from 0), with `unit` a uniform draw from [0, 1).
+    pub fn delay(&self, attempt: u32, unit: f64) -> Duration {
+        let backoff = self.base_delay.saturating_mul(1u32 << attempt.min(16)).min(self.max_delay);
+        backoff.mul_f64(1.0 - self.jitter.clamp(0.0, 1.0) * unit)
+    }
+}
+
+
+/// Where and how fast to fetch. Deribit allows about 20 public requests per second
+/// sustained; the default stays well below. Up to `concurrency` requests are in flight at
+/// once, all drawing on the same rate limit.
+#[derive(Debug, Clone)]
+pub struct FetchSettings {
+    pub base_url: String,
+    pub requests_per_second: f64,
+    pub burst: u32,
+    pub concurrency: usize,
+    pub retry: RetryPolicy,
+}
+
+impl Default for FetchSettings {
+    fn default() -> Self {
+        FetchSettings {
+            base_url: DERIBIT_API_URL.to_string(),
+            requests_per_second: 10.0,
+            burst: 20,
+            concurrency: 8,
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+impl FetchSettings {
+    pub fn rate_limiter(&self) -> RateLimiter {
+        RateLimiter::new(self.requests_per_second, self.burst)
+    }
+}
+
+impl From<&ProviderConfig> for FetchSettings {
+    fn from(config: &ProviderConfig) -> Self {
+        FetchSettings {
+            base_url: config.base_url.clone(),
+            requests_per_second: config.requests_per_second,
+            burst: config.burst,
+            concurrency: config.concurrency,
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+
+/// GETs `url` as JSON under `limiter`, retrying transient failures per `retry`.
+pub async fn get_json(client: &Client, url: &str, limiter: &RateLimiter, retry: &RetryPolicy) -> Result<Value, FetchError> {
+    get_json_authorized(client, url, limiter, retry, || None).await
+}
+
+/// [`get_json`] with an `Authorization` header from `authorize`, which is called again for
+/// every attempt so that signed requests carry a fresh timestamp and nonce.
+pub async fn get_json_authorized<A>(client: &Client, url: &str, limiter: &RateLimiter, retry: &RetryPolicy, authorize: A) -> Result<Value, FetchError>
+where
+    A: Fn() -> Option<String>,
+{
+    let mut attempt = 0;
+    loop {
+        limiter.acquire().await;
+        let mut request = client.get(url);
+        if let Some(authorization) = authorize() {
+            request = request.header(reqwest::header::AUTHORIZATION, authorization);
+        }
+        let error = match request.send().await {
+            Ok(response) if response.status().is_success() => match response.json::<Value>().await {
+                Ok(body) => match body.get("error") {
+                    Some(error) => FetchError::Api(error.to_string()),
+                    None => return Ok(body),
+                },
+                Err(error) => FetchError::Request(error.to_string()),
+            },
+            Ok(response) => FetchError::Http { status: response.status().as_u16() },
+            Err(error) => FetchError::Request(error.to_string()),
+        };
+
+        if !error.is_retryable() || attempt >= retry.max_retries {
+            tracing::warn!(url, attempt, %error, "request failed");
+            let host = reqwest::Url::parse(url).ok().and_then(|url| url.host_str().map(str::to_string)).unwrap_or_default();
+            metrics::counter!(FETCH_ERRORS, "host" => host, "kind" => error.kind()).increment(1);
+            return Err(error);
+        }
+        tracing::debug!(url, attempt, %error, "retrying request");
+        sleep(retry.delay(attempt, rand::random())).await;
+        attempt += 1;
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchFailure {
+    /// What was being fetched, e.g. an instrument name.
+    pub item: String,
+    pub error: FetchError,
+}
+
+/// Results of a fetch that tolerates failures of individual items.
+#[derive(Debug)]
+pub struct FetchReport<T> {
+    pub data: Vec<T>,
+    pub failures: Vec<FetchFailure>,
+}
+
+impl<T> FetchReport<T> {
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// Failure counts by error, e.g. "2 failed: 1 × HTTP 400, 1 × HTTP 503".
+    pub fn summary(&self) -> String {
+        if self.failures.is_empty() {
+            return "all requests succeeded".to_string();
+        }
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for failure in &self.failures {
+            *counts.entry(failure.error.to_string()).or_default() += 1;
+        }
+        let parts: Vec<String> = counts.iter().map(|(error, count)| format!("{} × {}", count, error)).collect();
+        format!("{} failed: {}", self.failures.len(), parts.join(", "))
+    }
+}
+
+
+/// Minimal HTTP server for tests: answers each request with `respond(path, request_number)`
+/// after `delay`.
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    pub async fn serve<F>(delay: Duration, respond: F) -> (String, Arc<AtomicUsize>)
+    where
+        F: Fn(&str, usize) -> (u16, String) + Send + Sync + 'static,
+    {
+        serve_with_head(delay, move |path, _, number| respond(path, number)).await
+    }
+
+    /// [`serve`], also handing `respond` the request line and headers.
+    pub async fn serve_with_head<F>(delay: Duration, respond: F) -> (String, Arc<AtomicUsize>)
+    where
+        F: Fn(&str, &str, usize) -> (u16, String) + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let respond = Arc::new(respond);
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let number = counter.fetch_add(1, Ordering::SeqCst);
+                let respond = respond.clone();
+                tokio::spawn(async move {
+                    let mut buffer = Vec::new();
+                    let mut chunk = [0u8; 1024];
+                    while !buffer.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match socket.read(&mut chunk).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+                        }
+                    }
+                    let head = String::from_utf8_lossy(&buffer);
+                    let path = head.split_whitespace().nth(1).unwrap_or("/").to_string();
+                    tokio::time::sleep(delay).await;
+                    let (status, body) = respond(&path, &head, number);
+                    let response = format!(
+                        "HTTP/1.1 {} Status\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status, body.len(), body,
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        (url, requests)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+
+    fn fast_retries() -> RetryPolicy {
+        RetryPolicy { base_delay: Duration::from_millis(1), ..RetryPolicy::default() }
+    }
+
+    #[test]
+    fn test_default_config_matches_default_settings() {
+        let configured = FetchSettings::from(&auction::config::ProvidersConfig::default().deribit);
+        let default = FetchSettings::default();
+        assert_eq!(configured.base_url, DERIBIT_API_URL);
+        assert_eq!((configured.requests_per_second, configured.burst, configured.concurrency), (default.requests_per_second, default.burst, default.concurrency));
+    }
+
+    #[test]
+    fn test_backoff_grows_caps_and_jitters() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.delay(0, 0.0), Duration::from_millis(250));
+        assert_eq!(policy.delay(2, 0.0), Duration::from_secs(1));
+        assert_eq!(policy.delay(10, 0.0), Duration::from_secs(8));
+        assert_eq!(policy.delay(2, 0.999_999).as_millis(), 500);
+        assert!(FetchError::Http { status: 429 }.is_retryable() && !FetchError::Http { status: 404 }.is_retryable());
+    }
+
+    #[test]
+    fn test_rate_limiter_spaces_requests() {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let limiter = RateLimiter::new(20.0, 2);
+            let start = Instant::now();
+            for _ in 0..6 {
+                limiter.acquire().await;
+            }
+            // Two from the burst, then four at 50ms intervals
+            let elapsed = start.elapsed();
+            assert!(elapsed >= Duration::from_millis(190) && elapsed < Duration::from_millis(400), "{:?}", elapsed);
+        });
+    }
+
+    #[test]
+    fn test_get_json_retries_transient_errors_only() {
+        let recorder = auction::stats::MemoryRecorder::new();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        metrics::with_local_recorder(&recorder, || runtime.block_on(async {
+            let (url, requests) = mock::serve(Duration::ZERO, |path, number| match path {
+                "/flaky" if number < 2 => (503, String::new()),
+                "/flaky" => (200, r#"{"result":42}"#.to_string()),
+                "/rpc-error" => (200, r#"{"error":{"code":10001,"message":"bad"}}"#.to_string()),
+                _ => (404, String::new()),
+            }).await;
+            let client = Client::new();
+            let limiter = RateLimiter::new(1000.0, 10);
+
+            let body = get_json(&client, &format!("{}/flaky", url), &limiter, &fast_retries()).await.unwrap();
+            assert_eq!(body["result"], 42);
+            assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+            assert_eq!(get_json(&client, &format!("{}/missing", url), &limiter, &fast_retries()).await, Err(FetchError::Http { status: 404 }));
+            assert_eq!(requests.load(Ordering::SeqCst), 4);
+            assert!(matches!(get_json(&client, &format!("{}/rpc-error", url), &limiter, &fast_retries()).await, Err(FetchError::Api(_))));
+        }));
+        assert_eq!(recorder.counter(&format!("{}{{host=\"127.0.0.1\",kind=\"http\"}}", FETCH_ERRORS)), 1);
+        assert_eq!(recorder.counter(&format!("{}{{host=\"127.0.0.1\",kind=\"api\"}}", FETCH_ERRORS)), 1);
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use num_complex::Complex;
+use model::model::{Asset, Basket};
+use crate::curve::YieldCurve;
+use crate::fourier::{CharacteristicModel, QuantoOption, quanto_carry};
+use crate::error::PricingError;
+
+
+/// Fair forward of an asset paid out in the domestic currency. With an FX leg the
+/// forward carries the quanto adjustment e^{ρ σ σ_X T} relative to the foreign forward.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuantoForward {
+    pub spot: f64,
+    pub domestic_rate: f64,
+    pub foreign_rate: f64,
+    pub volatility: f64,
+    pub fx_volatility: f64,
+    pub correlation: f64,
+    pub dividend_yield: f64,
+    pub time_to_maturity: f64,
+}
+
+impl QuantoForward {
+    pub fn from_option(option: &QuantoOption) -> Self {
+        QuantoForward {
+            spot: option.spot,
+            domestic_rate: option.domestic_rate,
+            foreign_rate: option.foreign_rate,
+            volatility: option.volatility,
+            fx_volatility: option.fx_volatility,
+            correlation: option.correlation,
+            dividend_yield: option.dividend_yield,
+            time_to_maturity: option.time_to_maturity,
+        }
+    }
+
+    /// Takes the domestic and foreign rates from curves at this forward's maturity.
+    pub fn with_curves(mut self, domestic: &YieldCurve, foreign: &YieldCurve) -> Self {
+        self.domestic_rate = domestic.zero_rate(self.time_to_maturity);
+        self.foreign_rate = foreign.zero_rate(self.time_to_maturity);
+        self
+    }
+
+    pub fn carry(&self) -> f64 {
+        quanto_carry(self.domestic_rate, self.foreign_rate, self.volatility, self.fx_volatility, self.correlation)
+            - self.dividend_yield
+    }
+
+    /// Forward per unit of spot.
+    pub fn growth(&self) -> f64 {
+        (self.carry() * self.time_to_maturity).exp()
+    }
+
+    pub fn forward_price(&self) -> f64 {
+        self.spot * self.growth()
+    }
+
+    /// Ratio of the quanto forward to the same forward without the FX correlation term.
+    pub fn quanto_adjustment(&self) -> f64 {
+        (self.correlation * self.volatility * self.fx_volatility * self.time_to_maturity).exp()
+    }
+
+    pub fn basis(&self) -> f64 {
+        self.forward_price() - self.spot
+    }
+
+    /// Basis as a continuously compounded annual rate.
+    pub fn annualized_basis(&self) -> f64 {
+        (self.forward_price() / self.spot).ln() / self.time_to_maturity
+    }
+
+    /// Mark of a futures position entered at `entry_price`. Futures are settled daily, so
+    /// the gain is not discounted.
+    pub fn futures_value(&self, entry_price: f64, quantity: f64) -> f64 {
+        quantity * (self.forward_price() - entry_price)
+    }
+
+    /// Value of a forward contract struck at `delivery_price`, paid at maturity.
+    pub fn forward_contract_value(&self, delivery_price: f64, quantity: f64) -> f64 {
+        (-self.domestic_rate * self.time_to_maturity).exp() * self.futures_value(delivery_price, quantity)
+    }
+
+    /// Relative difference between E[S_T] implied by a model's characteristic function,
+    /// φ(-i), and this forward. A pricer whose drift is consistent returns about zero.
+    pub fn drift_mismatch<M: CharacteristicModel + ?Sized>(&self, model: &M) -> f64 {
+        model.log_characteristic(Complex::new(0.0, -1.0)).re / self.forward_price() - 1.0
+    }
+}
+
+
+/// Delivery value of a basket at maturity, each position growing from its current
+/// price at the carry of its asset's forward.
+pub fn basket_forward_value(basket: &Basket, forwards: &HashMap<Asset, QuantoForward>) -> Result<f64, PricingError> {
+    basket.assets.iter()
+        .map(|info| forwards.get(&info.asset)
+            .map(|forward| info.total_value() * forward.growth())
+            .ok_or_else(|| PricingError::MissingForward(format!("{}/{}", info.asset.base, info.asset.quote))))
+        .sum()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::ids::BasketId;
+    use model::model::AssetInfo;
+
+    fn forward() -> QuantoForward {
+        QuantoForward {
+            spot: 30000.0,
+            domestic_rate: 0.05,
+            foreign_rate: 0.01,
+            volatility: 0.6,
+            fx_volatility: 0.2,
+            correlation: -0.5,
+            dividend_yield: 0.0,
+            time_to_maturity: 0.5,
+        }
+    }
+
+    #[test]
+    fn test_quanto_forward_and_basis() {
+        let forward = forward();
+        let expected = 30000.0 * ((0.01 - 0.5 * 0.6 * 0.2) * 0.5f64).exp();
+        assert!((forward.forward_price() - expected).abs() < 1e-9);
+        assert!((forward.quanto_adjustment() - (-0.03f64).exp()).abs() < 1e-12);
+        assert!(forward.basis() < 0.0);
+        assert!((forward.annualized_basis() - (0.01 - 0.06)).abs() < 1e-12);
+
+        assert!((forward.futures_value(expected - 100.0, 2.0) - 200.0).abs() < 1e-9);
+        assert!((forward.forward_contract_value(expected - 100.0, 2.0) - 200.0 * (-0.025f64).exp()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_option_drift_matches_forward() {
+        let option = QuantoOption {
+            spot: 30000.0,
+            strike: 32000.0,
+            domestic_rate: 0.05,
+            foreign_rate: 0.01,
+            volatility: 0.6,
+            fx_volatility: 0.2,
+            time_to_maturity: 0.5,
+            correlation: -0.5,
+            dividend_yield: 0.02,
+        };
+        let forward = QuantoForward::from_option(&option);
+        assert!(forward.drift_mismatch(&option).abs() < 1e-12);
+        assert!((option.forward() - forward.forward_price()).abs() < 1e-8);
+
+        // A pricer that ignored the quanto term would be caught
+        let unadjusted = QuantoForward { correlation: 0.0, fx_volatility: 0.0, ..forward };
+        assert!(unadjusted.drift_mismatch(&option).abs() > 1e-3);
+    }
+
+    #[test]
+    fn test_basket_forward_value() {
+        let btc = Asset::new("BTC", "USD");
+        let eth = Asset::new("ETH", "USD");
+        let basket = Basket {
+            id: BasketId(1),
+            assets: vec![AssetInfo::new(btc.clone(), 1.0, 30000.0), AssetInfo::new(eth.clone(), 10.0, 2000.0)],
+        };
+        let eth_forward = QuantoForward { spot: 2000.0, volatility: 0.8, correlation: 0.0, fx_volatility: 0.0, ..forward() };
+        let forwards = HashMap::from([(btc.clone(), forward()), (eth.clone(), eth_forward)]);
+
+        let value = basket_forward_value(&basket, &forwards).unwrap();
+        assert!((value - (forward().forward_price() + 10.0 * eth_forward.forward_price())).abs() < 1e-8);
+        assert!(basket_forward_value(&basket, &HashMap::from([(btc, forward())])).is_err());
+    }
+}
@@ -1,14 +1,69 @@
+use std::f64::consts::PI;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+use rand::Rng;
+use rand::rngs::StdRng;
+use rand_distr::StandardNormal;
 use rustfft::FftPlanner;
-use ndarray::Array1;
 use num_complex::Complex;
+use auction::config::PricerConfig;
+#[cfg(not(target_arch = "wasm32"))]
+use auction::stats::FFT_PRICING_SECONDS;
+use crate::builder::InputError;
+use crate::curve::YieldCurve;
+use crate::engine::PricingEngine;
+use crate::monte_carlo::TerminalSampler;
+use crate::validation::validate_strikes;
 
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct OptionPrice{
     pub call: f64,
-    pub put: f64
+    pub put: f64,
+    /// Error estimates of the numerical method; `None` for closed-form prices.
+    pub diagnostics: Option<PricingDiagnostics>
 }
 
+impl OptionPrice {
+    pub fn new(call: f64, put: f64) -> Self {
+        OptionPrice { call, put, diagnostics: None }
+    }
+
+    pub fn with_diagnostics(mut self, diagnostics: PricingDiagnostics) -> Self {
+        self.diagnostics = Some(diagnostics);
+        self
+    }
+}
+
+
+/// How a numerical price was obtained and how far it may be off, in price units. Estimates
+/// a method does not incur are zero.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PricingDiagnostics {
+    /// FFT points, COS terms or Monte Carlo paths.
+    pub grid_size: usize,
+    /// Error from cutting off the Fourier integral or cosine series.
+    pub truncation_error: f64,
+    /// Error from the FFT treating the damped call price as periodic in log strike.
+    pub aliasing_error: f64,
+    /// Disagreement between neighbouring interpolation stencils for off-grid strikes.
+    pub interpolation_residual: f64,
+    /// Larger of the call and put Monte Carlo standard errors.
+    pub std_error: f64,
+    /// Grid refinements made to meet a tolerance.
+    pub refinements: usize,
+    /// No-arbitrage checks the price fails; see [`crate::validation`].
+    pub violations: usize,
+}
+
+impl PricingDiagnostics {
+    pub fn error_estimate(&self) -> f64 {
+        self.truncation_error + self.aliasing_error + self.interpolation_residual + self.std_error
+    }
+}
 
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct QuantoOption {
     pub spot: f64,
     pub strike: f64,
@@ -17,63 +72,365 @@ pub struct QuantoOption {
     pub volatility: f64,
     pub fx_volatility: f64,
     pub time_to_maturity: f64,
-    pub correlation: f64
+    pub correlation: f64,
+    /// Continuous yield paid by holding the asset: dividends, perp funding or borrow cost.
+    pub dividend_yield: f64
 }
 
 
-impl QuantoOption {
-    pub fn characteristic_function(&self, u: f64) -> Complex<f64> {
-        let i = Complex::new(0.0, 1.0);
-        let drift = if self.correlation == 0.0 && self.fx_volatility == 0.0 {
-            self.domestic_rate - 0.5 * self.volatility.powi(2)
-        } else {
-            self.foreign_rate - 0.5 * self.volatility.powi(2)
-                + self.correlation * self.volatility * self.fx_volatility
-        };
-        let vol = -0.5 * self.volatility.powi(2) * u.powi(2) * self.time_to_maturity;
+/// Parameters of the Carr–Madan FFT: `n` grid points spaced `eta` apart in frequency,
+/// damping `alpha` applied to the call price so that it is square integrable.
+#[derive(Debug, Clone, Copy)]
+pub struct FftSettings {
+    pub n: usize,
+    pub eta: f64,
+    pub alpha: f64,
+    /// Check each price against no-arbitrage bounds and count violations in its diagnostics.
+    pub validate: bool,
+}
 
-        let exponent = i * u * (self.spot.ln() + drift * self.time_to_maturity) + vol;
-        exponent.exp()
+impl Default for FftSettings {
+    fn default() -> Self {
+        FftSettings {
+            n: 4096,
+            eta: 0.25,
+            alpha: 1.5,
+            validate: true,
+        }
     }
+}
 
-    pub fn calculate_price_fft(&self) -> OptionPrice {
-        let n: usize = 1024;
-        let ln_k_min = (self.spot * 0.1).ln();
-        let ln_k_max = (self.spot * 10.0).ln();
-        let dk = (ln_k_max - ln_k_min) / n as f64;
-        let damping_factor = 0.05;
-
-        let mut grid = Array1::<f64>::zeros(n);
-        for i in 0..n {
-            let u = i as f64 * dk;
-            if u == 0.0 {
-                grid[i] = 0.0;
-            } else {
-                let phi = self.characteristic_function(u);
-                let complex_exp = Complex::new(0.0, -u * ln_k_min);
-                let integrand = ((phi * complex_exp / Complex::new(0.0, u)) * (damping_factor * u).exp()).re;
-                grid[i] = integrand;
+impl From<&PricerConfig> for FftSettings {
+    fn from(config: &PricerConfig) -> Self {
+        FftSettings { n: config.fft_points, eta: config.fft_eta, alpha: config.fft_alpha, ..FftSettings::default() }
+    }
+}
+
+impl FftSettings {
+    /// Log-strike spacing implied by the Nyquist relation `lambda * eta = 2π / n`.
+    pub fn lambda(&self) -> f64 {
+        2.0 * PI / (self.n as f64 * self.eta)
+    }
+}
+
+
+/// Call prices on an evenly spaced log-strike grid.
+#[derive(Debug, Clone)]
+pub struct CallCurve {
+    pub log_strikes: Vec<f64>,
+    pub calls: Vec<f64>,
+    /// Damping exponent the grid was computed with.
+    pub alpha: f64,
+    /// Integrand mass over the top tenth of the frequency grid, before undoing the damping.
+    pub frequency_tail: f64,
+}
+
+impl CallCurve {
+    /// Cubic Lagrange interpolation in log-strike between the four nearest grid points.
+    pub fn call(&self, strike: f64) -> Option<f64> {
+        self.interpolate(strike).map(|(call, _)| call)
+    }
+
+    /// Interpolated call with the gap to the cubic through the neighbouring four points.
+    pub fn interpolate(&self, strike: f64) -> Option<(f64, f64)> {
+        let k = strike.ln();
+        let n = self.log_strikes.len();
+        if n < 5 || !k.is_finite() {
+            return None;
+        }
+        let dk = self.log_strikes[1] - self.log_strikes[0];
+        let position = (k - self.log_strikes[0]) / dk;
+        if position < 1.0 || position > (n - 3) as f64 {
+            return None;
+        }
+
+        let start = position.floor() as usize - 1;
+        let neighbour = if start + 4 < n { start + 1 } else { start - 1 };
+        let call = self.lagrange(start, k);
+        Some((call, (call - self.lagrange(neighbour, k)).abs()))
+    }
+
+    fn lagrange(&self, start: usize, k: f64) -> f64 {
+        let mut price = 0.0;
+        for j in start..start + 4 {
+            let mut weight = 1.0;
+            for m in start..start + 4 {
+                if m != j {
+                    weight *= (k - self.log_strikes[m]) / (self.log_strikes[j] - self.log_strikes[m]);
+                }
             }
+            price += weight * self.calls[j];
         }
+        price
+    }
 
-        let mut planner = FftPlanner::new();
-        let fft = planner.plan_fft_forward(n);
-        let mut fft_input: Vec<Complex<f64>> = grid.mapv(|x| Complex::new(x, 0.0)).to_vec();
-        fft.process(&mut fft_input);
+    /// Truncation and aliasing error estimates at `strike`. Aliasing is the damped price
+    /// at the grid ends, which the FFT wraps around onto every other strike.
+    pub fn error_estimates(&self, strike: f64, forward: f64, discount: f64) -> (f64, f64) {
+        let k = strike.ln();
+        let n = self.log_strikes.len();
+        let (low, high) = (self.log_strikes[0], self.log_strikes[n - 1]);
+        let truncation = (-self.alpha * k).exp() / PI * self.frequency_tail;
+        let wrapped = ((self.alpha * low).exp() * discount * (forward - low.exp()).max(0.0))
+            .max((self.alpha * high).exp() * self.calls[n - 1]);
+        (truncation, (-self.alpha * k).exp() * wrapped)
+    }
 
-        let option_price_index = ((self.strike.ln() - ln_k_min) / dk).round();
-        let index = option_price_index.max(0.0).min((n - 1) as f64) as usize;
+    /// Call, put by parity and diagnostics at an interpolated strike.
+    pub fn price(&self, strike: f64, forward: f64, discount: f64) -> Option<OptionPrice> {
+        let (call, residual) = self.interpolate(strike)?;
+        let (truncation_error, aliasing_error) = self.error_estimates(strike, forward, discount);
+        Some(OptionPrice::new(call, call - discount * (forward - strike)).with_diagnostics(PricingDiagnostics {
+            grid_size: self.calls.len(),
+            truncation_error,
+            aliasing_error,
+            interpolation_residual: residual,
+            ..Default::default()
+        }))
+    }
+}
+
+
+/// Cost of carry of a quanto asset in the domestic currency. Without an FX leg
+/// (no FX volatility, no correlation) the asset is a plain domestic one.
+pub fn quanto_carry(domestic_rate: f64, foreign_rate: f64, volatility: f64, fx_volatility: f64, correlation: f64) -> f64 {
+    if correlation == 0.0 && fx_volatility == 0.0 {
+        domestic_rate
+    } else {
+        foreign_rate + correlation * volatility * fx_volatility
+    }
+}
+
+
+/// Continuous yield implied by a forward or futures price, e.g. a funding-adjusted
+/// perpetual mark rolled to `time_to_maturity`.
+pub fn implied_yield(spot: f64, forward: f64, domestic_rate: f64, time_to_maturity: f64) -> f64 {
+    domestic_rate - (forward / spot).ln() / time_to_maturity
+}
+
+
+/// A model with an analytic characteristic function of the log terminal price, which is
+/// all the FFT pricer needs.
+pub trait CharacteristicModel {
+    fn log_characteristic(&self, u: Complex<f64>) -> Complex<f64>;
+    fn domestic_rate(&self) -> f64;
+    fn time_to_maturity(&self) -> f64;
+    /// Expected terminal price under the pricing measure.
+    fn forward(&self) -> f64;
+
+    fn discount(&self) -> f64 {
+        (-self.domestic_rate() * self.time_to_maturity()).exp()
+    }
+}
+
+
+/// Carr–Madan FFT over a log-strike grid whose midpoint is `center`.
+pub fn call_curve<M: CharacteristicModel + ?Sized>(model: &M, settings: &FftSettings, center: f64) -> CallCurve {
+    let n = settings.n;
+    let eta = settings.eta;
+    let alpha = settings.alpha;
+    let lambda = settings.lambda();
+    let b = center - 0.5 * n as f64 * lambda;
+    let discount = model.discount();
+    let i = Complex::new(0.0, 1.0);
+
+    let mut frequency_tail = 0.0;
+    let mut input: Vec<Complex<f64>> = Vec::with_capacity(n);
+    for j in 0..n {
+        let v = j as f64 * eta;
+        let psi = discount * model.log_characteristic(Complex::new(v, -(alpha + 1.0)))
+            / Complex::new(alpha * alpha + alpha - v * v, (2.0 * alpha + 1.0) * v);
+        if 10 * j >= 9 * n {
+            frequency_tail += psi.norm() * eta;
+        }
+        // Simpson's rule weights 1/3, 4/3, 2/3, 4/3, ...
+        let simpson = (3.0 + if j % 2 == 0 { -1.0 } else { 1.0 } - if j == 0 { 1.0 } else { 0.0 }) / 3.0;
+        input.push((-i * b * v).exp() * psi * eta * simpson);
+    }
+
+    let mut planner = FftPlanner::new();
+    planner.plan_fft_forward(n).process(&mut input);
+
+    let log_strikes: Vec<f64> = (0..n).map(|m| b + m as f64 * lambda).collect();
+    let calls = log_strikes.iter().zip(&input)
+        .map(|(k, value)| ((-alpha * k).exp() / PI * value.re).max(0.0))
+        .collect();
+    CallCurve { log_strikes, calls, alpha, frequency_tail }
+}
+
+/// The grid is centred on the strike's own log, so no interpolation is needed.
+/// The put follows from put–call parity against the forward.
+pub fn price_fft<M: CharacteristicModel + ?Sized>(model: &M, strike: f64, settings: &FftSettings) -> OptionPrice {
+    // There is no clock to time pricing by in the browser
+    #[cfg(not(target_arch = "wasm32"))]
+    let started = Instant::now();
+    let curve = call_curve(model, settings, strike.ln());
+    let call_price = curve.calls[settings.n / 2];
+    let forward = model.forward();
+    let discount = model.discount();
+    let (truncation_error, aliasing_error) = curve.error_estimates(strike, forward, discount);
+
+    // The neighbouring grid strikes also catch prices that are not monotone in strike
+    let violations = if settings.validate {
+        let strip: Vec<(f64, OptionPrice)> = (settings.n / 2 - 1..=settings.n / 2 + 1)
+            .map(|m| {
+                let (k, call) = (curve.log_strikes[m].exp(), curve.calls[m]);
+                (k, OptionPrice::new(call, call - discount * (forward - k)))
+            })
+            .collect();
+        validate_strikes(model, &strip).len()
+    } else {
+        0
+    };
+
+    let price = OptionPrice::new(call_price, call_price - discount * (forward - strike)).with_diagnostics(PricingDiagnostics {
+        grid_size: settings.n,
+        truncation_error,
+        aliasing_error,
+        violations,
+        ..Default::default()
+    });
+    #[cfg(not(target_arch = "wasm32"))]
+    metrics::histogram!(FFT_PRICING_SECONDS).record(started.elapsed().as_secs_f64());
+    price
+}
+
+/// Tolerance for [`price_fft_refined`], in price units, and the largest grid it may use.
+#[derive(Debug, Clone, Copy)]
+pub struct RefinementSettings {
+    pub start: FftSettings,
+    pub tolerance: f64,
+    pub max_n: usize,
+}
+
+impl Default for RefinementSettings {
+    fn default() -> Self {
+        RefinementSettings {
+            start: FftSettings::default(),
+            tolerance: 1e-6,
+            max_n: 1 << 18,
+        }
+    }
+}
 
-        let call_price = fft_input[index].re * (-self.domestic_rate * self.time_to_maturity).exp();
+/// Doubles the FFT grid until the estimated error and the change from the previous grid
+/// are both within tolerance. When truncation dominates the frequency range is doubled;
+/// when aliasing dominates the log-strike range is.
+pub fn price_fft_refined<M: CharacteristicModel + ?Sized>(model: &M, strike: f64, settings: &RefinementSettings) -> OptionPrice {
+    let mut fft = settings.start;
+    let mut previous: Option<OptionPrice> = None;
+    let mut refinements = 0;
 
-        let discounted_strike = self.strike * (-self.domestic_rate * self.time_to_maturity).exp();
-        let discounted_spot = self.spot * (-self.foreign_rate * self.time_to_maturity).exp();
-        let put_price = call_price + discounted_strike - discounted_spot;
+    loop {
+        let mut price = price_fft(model, strike, &fft);
+        let mut diagnostics = price.diagnostics.unwrap_or_default();
+        diagnostics.refinements = refinements;
+        price.diagnostics = Some(diagnostics);
 
-        OptionPrice {
-            call: call_price,
-            put: put_price
+        let change = previous.map_or(0.0, |p| (p.call - price.call).abs());
+        if (diagnostics.error_estimate() <= settings.tolerance && change <= settings.tolerance)
+            || 2 * fft.n > settings.max_n {
+            return price;
         }
+
+        if diagnostics.aliasing_error > diagnostics.truncation_error {
+            fft.eta /= 2.0;
+        }
+        fft.n *= 2;
+        refinements += 1;
+        previous = Some(price);
+    }
+}
+
+/// Prices several strikes of the same maturity from a single FFT, interpolating
+/// between grid points. Strikes outside the grid are priced with `None`.
+pub fn price_strikes_fft<M: CharacteristicModel + ?Sized>(
+    model: &M,
+    strikes: &[f64],
+    settings: &FftSettings,
+) -> Vec<Option<OptionPrice>> {
+    let forward = model.forward();
+    let discount = model.discount();
+    let curve = call_curve(model, settings, forward.ln());
+
+    strikes.iter()
+        .map(|strike| curve.price(*strike, forward, discount))
+        .collect()
+}
+
+
+impl QuantoOption {
+    /// Takes the domestic and foreign rates from curves at this option's maturity. This is
+    /// exact for European payoffs under deterministic rates.
+    pub fn with_curves(mut self, domestic: &YieldCurve, foreign: &YieldCurve) -> Self {
+        self.domestic_rate = domestic.zero_rate(self.time_to_maturity);
+        self.foreign_rate = foreign.zero_rate(self.time_to_maturity);
+        self
+    }
+
+    /// Drift of the log price under the domestic pricing measure.
+    fn log_drift(&self) -> f64 {
+        quanto_carry(self.domestic_rate, self.foreign_rate, self.volatility, self.fx_volatility, self.correlation)
+            - self.dividend_yield
+            - 0.5 * self.volatility.powi(2)
+    }
+
+    pub fn characteristic_function(&self, u: f64) -> Complex<f64> {
+        self.log_characteristic(Complex::new(u, 0.0))
+    }
+
+    pub fn call_curve(&self, settings: &FftSettings, center: f64) -> CallCurve {
+        call_curve(self, settings, center)
+    }
+
+    pub fn calculate_price_fft(&self) -> Result<OptionPrice, InputError> {
+        self.calculate_price_fft_with(&FftSettings::default())
+    }
+
+    pub fn calculate_price_fft_with(&self, settings: &FftSettings) -> Result<OptionPrice, InputError> {
+        self.validate()?;
+        Ok(price_fft(self, self.strike, settings))
+    }
+
+    pub fn price_strikes_fft(&self, strikes: &[f64], settings: &FftSettings) -> Result<Vec<Option<OptionPrice>>, InputError> {
+        self.validate()?;
+        Ok(price_strikes_fft(self, strikes, settings))
+    }
+
+    pub fn price_with(&self, engine: &PricingEngine) -> Result<OptionPrice, InputError> {
+        self.validate()?;
+        Ok(engine.price(self, self.strike))
+    }
+}
+
+impl CharacteristicModel for QuantoOption {
+    /// Characteristic function of ln S_T, extended to complex arguments.
+    fn log_characteristic(&self, u: Complex<f64>) -> Complex<f64> {
+        let i = Complex::new(0.0, 1.0);
+        let exponent = i * u * (self.spot.ln() + self.log_drift() * self.time_to_maturity)
+            - 0.5 * self.volatility.powi(2) * u * u * self.time_to_maturity;
+        exponent.exp()
+    }
+
+    fn domestic_rate(&self) -> f64 {
+        self.domestic_rate
+    }
+
+    fn time_to_maturity(&self) -> f64 {
+        self.time_to_maturity
+    }
+
+    fn forward(&self) -> f64 {
+        self.spot * ((self.log_drift() + 0.5 * self.volatility.powi(2)) * self.time_to_maturity).exp()
+    }
+}
+
+impl TerminalSampler for QuantoOption {
+    /// Lognormal terminal price, exact in a single step.
+    fn sample_terminal(&self, rng: &mut StdRng, _steps: usize) -> f64 {
+        let z: f64 = rng.sample(StandardNormal);
+        let t = self.time_to_maturity;
+        self.spot * (self.log_drift() * t + self.volatility * t.sqrt() * z).exp()
     }
 }
 
@@ -82,6 +439,8 @@ impl QuantoOption {
 mod tests {
     use super::*;
 
+    use statrs::distribution::{Normal, ContinuousCDF};
+
     fn assert_approx_eq(a: f64, b: f64, epsilon: f64) {
         assert!(
             (a - b).abs() < epsilon,
@@ -121,6 +480,7 @@ mod tests {
             fx_volatility: 0.0,   // No FX volatility
             time_to_maturity: 1.0,  // 1 year to maturity
             correlation: 0.0,     // No correlation
+            dividend_yield: 0.0,
         };
 
         // Input for the characteristic function (u in Fourier space)
@@ -134,9 +494,10 @@ mod tests {
         println!("Characteristic function result (real): {}", result.re);
         println!("Characteristic function result (imaginary): {}", result.im);
 
-        // We can test if the result matches expected values (hard to give exact values)
-        let expected = Complex::new(0.951229424500714, 0.190255392000331);
-        assert_complex_approx_eq(result, expected, 1e-4);
+        // Closed form: exp(i u (ln S + (r - σ²/2) T) - σ² u² T / 2)
+        let phase = 100f64.ln() + (0.05 - 0.5 * 0.2f64.powi(2)) * 1.0;
+        let expected = Complex::new(0.0, phase).exp() * (-0.5 * 0.2f64.powi(2)).exp();
+        assert_complex_approx_eq(result, expected, 1e-12);
     }
 
     #[test]
@@ -150,9 +511,10 @@ mod tests {
             volatility: 0.6,        // Volatility of BTC
             correlation: 0.5,       // Correlation between BTC and USDC/BTC rate
             fx_volatility: 0.2,     // Volatility of the USDC/BTC exchange rate
+            dividend_yield: 0.0,
         };
 
-        let price = quanto.calculate_price_fft();
+        let price = quanto.calculate_price_fft().unwrap();
         assert!(price.call > 0.0);
         assert!(price.put > 0.0);
     }
@@ -169,13 +531,158 @@ mod tests {
             volatility: 0.2,        // 20% volatility
             correlation: 0.0,       // No correlation (mimicking European option)
             fx_volatility: 0.0,     // No exchange rate volatility (no quanto effect)
+            dividend_yield: 0.0,
         };
 
-        let price = quanto_call.calculate_price_fft();
+        let price = quanto_call.calculate_price_fft().unwrap();
         let expected_call = 10.4506;
-        let expected_price = 5.5735;
+        let expected_put = 5.5735;
 
-        assert_approx_eq(price.call, expected_price, 1e-4);
-        assert_approx_eq(price.put, expected_call, 1e-4);
+        assert_approx_eq(price.call, expected_call, 1e-4);
+        assert_approx_eq(price.put, expected_put, 1e-4);
     }
-}
\ No newline at end of file
+
+    fn black_scholes(spot: f64, strike: f64, rate: f64, volatility: f64, time_to_maturity: f64) -> (f64, f64) {
+        let normal = Normal::new(0.0, 1.0).unwrap();
+        let d1 = ((spot / strike).ln() + (rate + 0.5 * volatility.powi(2)) * time_to_maturity)
+            / (volatility * time_to_maturity.sqrt());
+        let d2 = d1 - volatility * time_to_maturity.sqrt();
+        let discounted_strike = strike * (-rate * time_to_maturity).exp();
+
+        let call = spot * normal.cdf(d1) - discounted_strike * normal.cdf(d2);
+        (call, call + discounted_strike - spot)
+    }
+
+    #[test]
+    fn test_fft_matches_black_scholes_across_grid() {
+        for time_to_maturity in [0.1, 0.5, 1.0, 2.0] {
+            for strike in [60.0, 80.0, 95.0, 100.0, 105.0, 120.0, 150.0] {
+                let option = QuantoOption {
+                    spot: 100.0,
+                    strike,
+                    domestic_rate: 0.03,
+                    foreign_rate: 0.0,
+                    volatility: 0.25,
+                    fx_volatility: 0.0,
+                    time_to_maturity,
+                    correlation: 0.0,
+                    dividend_yield: 0.0,
+                };
+                let (call, put) = black_scholes(100.0, strike, 0.03, 0.25, time_to_maturity);
+                let price = option.calculate_price_fft().unwrap();
+
+                assert_approx_eq(price.call, call, 1e-5);
+                assert_approx_eq(price.put, put, 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_interpolated_strikes_match_black_scholes() {
+        let option = QuantoOption {
+            spot: 100.0,
+            strike: 100.0,
+            domestic_rate: 0.05,
+            foreign_rate: 0.0,
+            volatility: 0.2,
+            fx_volatility: 0.0,
+            time_to_maturity: 1.0,
+            correlation: 0.0,
+            dividend_yield: 0.0,
+        };
+        let strikes = [70.0, 90.0, 101.3, 115.0, 140.0];
+        let prices = option.price_strikes_fft(&strikes, &FftSettings::default()).unwrap();
+
+        for (strike, price) in strikes.iter().zip(prices) {
+            let (call, put) = black_scholes(100.0, *strike, 0.05, 0.2, 1.0);
+            let price = price.unwrap();
+            assert_approx_eq(price.call, call, 1e-4);
+            assert_approx_eq(price.put, put, 1e-4);
+        }
+        assert!(option.price_strikes_fft(&[1e-9], &FftSettings::default()).unwrap()[0].is_none());
+    }
+
+    #[test]
+    fn test_dividend_yield_carry_and_parity() {
+        let option = QuantoOption {
+            spot: 100.0,
+            strike: 95.0,
+            domestic_rate: 0.04,
+            foreign_rate: 0.0,
+            volatility: 0.5,
+            fx_volatility: 0.0,
+            time_to_maturity: 0.75,
+            correlation: 0.0,
+            dividend_yield: 0.1, // e.g. annualised perp funding received by the long
+        };
+        let prepaid_spot = 100.0 * (-0.1f64 * 0.75).exp();
+        let (call, put) = black_scholes(prepaid_spot, 95.0, 0.04, 0.5, 0.75);
+        let price = option.calculate_price_fft().unwrap();
+
+        assert_approx_eq(option.forward(), 100.0 * ((0.04 - 0.1) * 0.75f64).exp(), 1e-10);
+        assert_approx_eq(price.call, call, 1e-5);
+        assert_approx_eq(price.put, put, 1e-5);
+        // C - P = S e^{-qT} - K e^{-rT}
+        assert_approx_eq(price.call - price.put, prepaid_spot - 95.0 * (-0.04f64 * 0.75).exp(), 1e-10);
+        assert_approx_eq(implied_yield(100.0, option.forward(), 0.04, 0.75), 0.1, 1e-12);
+    }
+
+    #[test]
+    fn test_diagnostics_flag_and_refine_coarse_grids() {
+        // A week-dated, low-vol option: the characteristic function decays slowly in frequency
+        let option = QuantoOption {
+            spot: 100.0,
+            strike: 100.5,
+            domestic_rate: 0.03,
+            foreign_rate: 0.0,
+            volatility: 0.05,
+            fx_volatility: 0.0,
+            time_to_maturity: 7.0 / 365.0,
+            correlation: 0.0,
+            dividend_yield: 0.0,
+        };
+        let (call, _) = black_scholes(100.0, 100.5, 0.03, 0.05, 7.0 / 365.0);
+
+        let coarse = FftSettings { n: 256, ..FftSettings::default() };
+        let price = option.calculate_price_fft_with(&coarse).unwrap();
+        let diagnostics = price.diagnostics.unwrap();
+        assert_eq!(diagnostics.grid_size, 256);
+        assert!(diagnostics.truncation_error > 1e-3);
+        assert!((price.call - call).abs() > 1e-4);
+
+        let settings = RefinementSettings { start: coarse, ..RefinementSettings::default() };
+        let refined = price_fft_refined(&option, 100.5, &settings);
+        let diagnostics = refined.diagnostics.unwrap();
+        assert!(diagnostics.refinements > 0 && diagnostics.grid_size > 256);
+        assert!(diagnostics.error_estimate() <= 1e-6);
+        assert_approx_eq(refined.call, call, 1e-5);
+
+        // The default grid is already fine for it
+        assert!(option.calculate_price_fft().unwrap().diagnostics.unwrap().error_estimate() < 1e-6);
+    }
+
+    #[test]
+    fn test_interpolation_residual() {
+        let option = QuantoOption {
+            spot: 100.0,
+            strike: 100.0,
+            domestic_rate: 0.05,
+            foreign_rate: 0.0,
+            volatility: 0.2,
+            fx_volatility: 0.0,
+            time_to_maturity: 1.0,
+            correlation: 0.0,
+            dividend_yield: 0.0,
+        };
+        let fine = option.price_strikes_fft(&[101.3], &FftSettings::default()).unwrap()[0].unwrap();
+        let coarse = option.price_strikes_fft(&[101.3], &FftSettings { n: 1024, eta: 0.05, ..FftSettings::default() }).unwrap()[0].unwrap();
+        let (call, _) = black_scholes(100.0, 101.3, 0.05, 0.2, 1.0);
+
+        let fine_residual = fine.diagnostics.unwrap().interpolation_residual;
+        let coarse_residual = coarse.diagnostics.unwrap().interpolation_residual;
+        assert!(fine_residual < coarse_residual);
+        // The residual is of the order of the actual interpolation error
+        assert!((coarse.call - call).abs() < 10.0 * coarse_residual + 1e-6);
+        assert_eq!(option.calculate_price_fft().unwrap().diagnostics.unwrap().interpolation_residual, 0.0);
+    }
+}
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use model::model::{Asset, Basket};
+use crate::data::{DeribitIndexPrice, DeribitOptionData, DeribitPerpetual};
+use crate::fetch::FetchFailure;
+use crate::provider::{MarketDataProvider, OrderBook, SpotQuote};
+use crate::stream::{BookUpdate, TickerUpdate};
+
+
+/// Milliseconds since the Unix epoch on the local clock.
+pub fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_millis() as u64).unwrap_or(0)
+}
+
+
+/// Market data that knows when it was true. Both times are Unix milliseconds.
+pub trait Timestamped {
+    /// When the exchange produced the data, where it says.
+    fn exchange_timestamp(&self) -> Option<u64>;
+    /// When it arrived here.
+    fn received_at(&self) -> Option<u64>;
+
+    /// Age at `now`, from the exchange's time if known, else arrival; `None` if neither is.
+    fn age(&self, now: u64) -> Option<Duration> {
+        let timestamp = self.exchange_timestamp().or(self.received_at())?;
+        Some(Duration::from_millis(now.saturating_sub(timestamp)))
+    }
+}
+
+macro_rules! timestamped {
+    ($type:ty, $exchange:ident, $received:ident) => {
+        impl Timestamped for $type {
+            fn exchange_timestamp(&self) -> Option<u64> {
+                self.$exchange.into()
+            }
+
+            fn received_at(&self) -> Option<u64> {
+                self.$received
+            }
+        }
+    };
+}
+
+timestamped!(SpotQuote, exchange_timestamp, received_at);
+timestamped!(DeribitOptionData, exchange_timestamp, received_at);
+timestamped!(OrderBook, timestamp, received_at);
+timestamped!(DeribitIndexPrice, timestamp, received_at);
+timestamped!(DeribitPerpetual, timestamp, received_at);
+timestamped!(TickerUpdate, timestamp, received_at);
+timestamped!(BookUpdate, timestamp, received_at);
+
+
+/// What to do with inputs older than the threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StaleAction {
+    #[default]
+    Reject,
+    /// Use them, but report them alongside the result.
+    Warn,
+}
+
+/// How old market data may be before it is reported. Data with no timestamp at all
+/// counts as stale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StalenessPolicy {
+    pub max_age: Duration,
+    pub action: StaleAction,
+}
+
+impl Default for StalenessPolicy {
+    fn default() -> Self {
+        StalenessPolicy { max_age: Duration::from_secs(10), action: StaleAction::Reject }
+    }
+}
+
+/// An input older than the policy allows; `age` is `None` when it carries no timestamp.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaleInput {
+    pub item: String,
+    pub age: Option<Duration>,
+    pub max_age: Duration,
+}
+
+impl fmt::Display for StaleInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.age {
+            Some(age) => write!(f, "{} is {}ms old, limit {}ms", self.item, age.as_millis(), self.max_age.as_millis()),
+            None => write!(f, "{} has no timestamp", self.item),
+        }
+    }
+}
+
+impl std::error::Error for StaleInput {}
+
+/// A result computed from market data, with any stale inputs the policy let through.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checked<T> {
+    pub value: T,
+    pub warnings: Vec<StaleInput>,
+}
+
+impl<T> Checked<T> {
+    pub fn is_fresh(&self) -> bool {
+        self.warnings.is_empty()
+    }
+}
+
+impl StalenessPolicy {
+    /// `Ok(None)` if `data` is fresh at `now`, `Ok(Some(_))` if stale under `Warn`, and
+    /// `Err` if stale under `Reject`.
+    pub fn check(&self, item: &str, data: &dyn Timestamped, now: u64) -> Result<Option<StaleInput>, StaleInput> {
+        let age = data.age(now);
+        if age.is_some_and(|age| age <= self.max_age) {
+            return Ok(None);
+        }
+        let stale = StaleInput { item: item.to_string(), age, max_age: self.max_age };
+        match self.action {
+            StaleAction::Reject => Err(stale),
+            StaleAction::Warn => Ok(Some(stale)),
+        }
+    }
+
+    /// Checks every input, failing on the first rejected one.
+    pub fn check_all(&self, inputs: &[(&str, &dyn Timestamped)], now: u64) -> Result<Vec<StaleInput>, StaleInput> {
+        let mut warnings = Vec::new();
+        for (item, data) in inputs {
+            warnings.extend(self.check(item, *data, now)?);
+        }
+        Ok(warnings)
+    }
+}
+
+
+/// Timestamped spot quotes by asset, for valuing baskets.
+#[derive(Debug, Clone, Default)]
+pub struct MarketSnapshot {
+    quotes: HashMap<Asset, SpotQuote>,
+}
+
+impl MarketSnapshot {
+    pub fn new() -> Self {
+        MarketSnapshot::default()
+    }
+
+    pub fn insert(&mut self, asset: Asset, quote: SpotQuote) {
+        self.quotes.insert(asset, quote);
+    }
+
+    pub fn get(&self, asset: &Asset) -> Option<&SpotQuote> {
+        self.quotes.get(asset)
+    }
+
+    /// Quotes every asset of `basket` from `provider`, reporting those it could not get.
+    pub async fn fetch(provider: &dyn MarketDataProvider, basket: &Basket) -> (MarketSnapshot, Vec<FetchFailure>) {
+        let mut snapshot = MarketSnapshot::new();
+        let mut failures = Vec::new();
+        for info in &basket.assets {
+            match provider.spot_quote(&info.asset).await {
+                Ok(quote) => snapshot.insert(info.asset.clone(), quote),
+                Err(error) => failures.push(FetchFailure { item: format!("{}/{}", info.asset.base, info.asset.quote), error }),
+            }
+        }
+        (snapshot, failures)
+    }
+
+    /// Checks the quote of each asset in `basket`; an asset without one is stale.
+    fn check_basket(&self, basket: &Basket, now: u64, policy: &StalenessPolicy) -> Result<Vec<StaleInput>, StaleInput> {
+        let mut warnings = Vec::new();
+        for info in &basket.assets {
+            let item = format!("{}/{}", info.asset.base, info.asset.quote);
+            let stale = match self.quotes.get(&info.asset) {
+                Some(quote) => policy.check(&item, quote, now)?,
+                None => policy.check(&item, &SpotQuote::untimed(info.price), now)?,
+            };
+            warnings.extend(stale);
+        }
+        Ok(warnings)
+    }
+
+    /// Value of `basket` at these quotes, keeping its own price for any asset not quoted,
+    /// or an error if the policy rejects an input.
+    pub fn value_basket(&self, basket: &Basket, now: u64, policy: &StalenessPolicy) -> Result<Checked<f64>, StaleInput> {
+        let warnings = self.check_basket(basket, now, policy)?;
+        let value = basket.assets.iter()
+            .map(|info| info.quantity * self.quotes.get(&info.asset).map_or(info.price, |quote| quote.price))
+            .sum();
+        Ok(Checked { value, warnings })
+    }
+
+    /// Reprices `basket` at these quotes, or leaves it untouched if any input is rejected.
+    pub fn update_basket(&self, basket: &mut Basket, now: u64, policy: &StalenessPolicy) -> Result<Vec<StaleInput>, StaleInput> {
+        let warnings = self.check_basket(basket, now, policy)?;
+        for (asset, quote) in &self.quotes {
+            basket.update_price(asset, quote.price);
+        }
+        Ok(warnings)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::ids::BasketId;
+    use model::model::AssetInfo;
+
+    const NOW: u64 = 1_700_000_060_000;
+
+    fn quote(price: f64, exchange_timestamp: Option<u64>, received_at: Option<u64>) -> SpotQuote {
+        SpotQuote { price, exchange_timestamp, received_at }
+    }
+
+    #[test]
+    fn test_policy_warns_or_rejects_old_and_untimed_data() {
+        let fresh = quote(1.0, Some(NOW - 2_000), Some(NOW - 1_000));
+        let old = quote(1.0, Some(NOW - 30_000), Some(NOW - 1_000));
+        let received_only = quote(1.0, None, Some(NOW - 5_000));
+        assert_eq!(old.age(NOW), Some(Duration::from_secs(30)));
+        assert_eq!(received_only.age(NOW), Some(Duration::from_secs(5)));
+        assert_eq!(quote(1.0, Some(NOW + 500), None).age(NOW), Some(Duration::ZERO));
+
+        let reject = StalenessPolicy::default();
+        assert_eq!(reject.check("fresh", &fresh, NOW), Ok(None));
+        assert_eq!(reject.check("received", &received_only, NOW), Ok(None));
+        let error = reject.check("old", &old, NOW).unwrap_err();
+        assert_eq!(error.to_string(), "old is 30000ms old, limit 10000ms");
+        assert_eq!(reject.check("untimed", &SpotQuote::untimed(1.0), NOW).unwrap_err().age, None);
+
+        let warn = StalenessPolicy { action: StaleAction::Warn, ..StalenessPolicy::default() };
+        let warnings = warn.check_all(&[("fresh", &fresh), ("old", &old), ("untimed", &SpotQuote::untimed(1.0))], NOW).unwrap();
+        assert_eq!(warnings.iter().map(|w| w.item.as_str()).collect::<Vec<_>>(), vec!["old", "untimed"]);
+    }
+
+    #[test]
+    fn test_basket_valuation_checks_every_asset() {
+        let btc = Asset::new("BTC", "USD");
+        let eth = Asset::new("ETH", "USD");
+        let mut basket = Basket { id: BasketId(1), assets: vec![AssetInfo::new(btc.clone(), 1.0, 60000.0), AssetInfo::new(eth.clone(), 10.0, 3000.0)] };
+        let mut snapshot = MarketSnapshot::new();
+        snapshot.insert(btc.clone(), quote(64000.0, Some(NOW - 1_000), Some(NOW)));
+
+        // ETH has no quote: its stored price is of unknown age
+        let reject = StalenessPolicy::default();
+        assert_eq!(snapshot.value_basket(&basket, NOW, &reject).unwrap_err().item, "ETH/USD");
+        assert!(snapshot.update_basket(&mut basket, NOW, &reject).is_err());
+        assert_eq!(basket.total_value(), 90000.0);
+
+        let warn = StalenessPolicy { action: StaleAction::Warn, ..StalenessPolicy::default() };
+        let valued = snapshot.value_basket(&basket, NOW, &warn).unwrap();
+        assert_eq!((valued.value, valued.warnings.len()), (94000.0, 1));
+
+        snapshot.insert(eth.clone(), quote(3100.0, None, Some(NOW - 2_000)));
+        let valued = snapshot.value_basket(&basket, NOW, &reject).unwrap();
+        assert!(valued.is_fresh());
+        assert_eq!(snapshot.update_basket(&mut basket, NOW, &reject), Ok(Vec::new()));
+        assert_eq!(basket.total_value(), 95000.0);
+        assert!(snapshot.value_basket(&basket, NOW + 60_000, &reject).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use crate::engine::PricingEngine;
+use crate::fourier::QuantoOption;
+use crate::scenarios::OptionPosition;
+
+/// Relative spot bump for delta and gamma.
+const SPOT_BUMP: f64 = 1e-3;
+/// Absolute volatility bump for vega.
+const VOL_BUMP: f64 = 1e-3;
+/// Absolute rate bump for rho.
+const RATE_BUMP: f64 = 1e-4;
+/// Time bump for theta, in years; halved near expiry.
+const TIME_BUMP: f64 = 1.0 / 365.0;
+
+
+/// Sensitivities per unit change: vega and rho per 1.00 of vol and rate, theta per year
+/// of calendar time passing.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Greeks {
+    pub delta: f64,
+    pub gamma: f64,
+    pub vega: f64,
+    pub theta: f64,
+    pub rho: f64,
+}
+
+impl Greeks {
+    pub fn scale(&self, factor: f64) -> Greeks {
+        Greeks {
+            delta: factor * self.delta,
+            gamma: factor * self.gamma,
+            vega: factor * self.vega,
+            theta: factor * self.theta,
+            rho: factor * self.rho,
+        }
+    }
+}
+
+
+impl QuantoOption {
+    /// Spot delta alone, which needs two repricings rather than the eight of [`QuantoOption::greeks`].
+    pub fn delta(&self, is_call: bool, engine: &PricingEngine) -> f64 {
+        let h = SPOT_BUMP * self.spot;
+        let value = |spot: f64| {
+            let price = engine.price(&QuantoOption { spot, ..*self }, self.strike);
+            if is_call { price.call } else { price.put }
+        };
+        (value(self.spot + h) - value(self.spot - h)) / (2.0 * h)
+    }
+
+    /// Greeks by central differences, repricing with `engine`. Engines with random noise
+    /// should use a fixed seed so that bumped prices share their paths. The option itself
+    /// is not validated; see [`QuantoOption::validate`].
+    pub fn greeks(&self, is_call: bool, engine: &PricingEngine) -> Greeks {
+        let value = |option: QuantoOption| {
+            let price = engine.price(&option, option.strike);
+            if is_call { price.call } else { price.put }
+        };
+        let base = value(*self);
+
+        let h = SPOT_BUMP * self.spot;
+        let up = value(QuantoOption { spot: self.spot + h, ..*self });
+        let down = value(QuantoOption { spot: self.spot - h, ..*self });
+
+        let vol_up = value(QuantoOption { volatility: self.volatility + VOL_BUMP, ..*self });
+        let vol_down = value(QuantoOption { volatility: (self.volatility - VOL_BUMP).max(0.0), ..*self });
+
+        let dt = TIME_BUMP.min(0.5 * self.time_to_maturity);
+        let later = value(QuantoOption { time_to_maturity: self.time_to_maturity - dt, ..*self });
+
+        let rate_up = value(QuantoOption { domestic_rate: self.domestic_rate + RATE_BUMP, ..*self });
+        let rate_down = value(QuantoOption { domestic_rate: self.domestic_rate - RATE_BUMP, ..*self });
+
+        Greeks {
+            delta: (up - down) / (2.0 * h),
+            gamma: (up - 2.0 * base + down) / (h * h),
+            vega: (vol_up - vol_down) / (self.volatility + VOL_BUMP - (self.volatility - VOL_BUMP).max(0.0)),
+            theta: if dt > 0.0 { (later - base) / dt } else { 0.0 },
+            rho: (rate_up - rate_down) / (2.0 * RATE_BUMP),
+        }
+    }
+}
+
+impl OptionPosition {
+    /// Greeks of the whole position, scaled by its signed quantity.
+    pub fn greeks(&self, engine: &PricingEngine) -> Greeks {
+        self.option.greeks(self.is_call, engine).scale(self.quantity)
+    }
+
+    pub fn delta(&self, engine: &PricingEngine) -> f64 {
+        self.quantity * self.option.delta(self.is_call, engine)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use statrs::distribution::{Continuous, ContinuousCDF, Normal};
+
+    #[test]
+    fn test_greeks_match_black_scholes() {
+        let (spot, strike, rate, vol, t) = (100.0, 105.0, 0.03, 0.3, 0.5);
+        let option = QuantoOption {
+            spot,
+            strike,
+            domestic_rate: rate,
+            foreign_rate: 0.0,
+            volatility: vol,
+            fx_volatility: 0.0,
+            time_to_maturity: t,
+            correlation: 0.0,
+            dividend_yield: 0.0,
+        };
+        let normal = Normal::new(0.0, 1.0).unwrap();
+        let d1 = ((spot / strike).ln() + (rate + 0.5 * vol * vol) * t) / (vol * t.sqrt());
+        let d2 = d1 - vol * t.sqrt();
+        let discounted_strike = strike * (-rate * t).exp();
+
+        let call = option.greeks(true, &PricingEngine::default());
+        assert!((call.delta - normal.cdf(d1)).abs() < 1e-5);
+        assert!((call.gamma - normal.pdf(d1) / (spot * vol * t.sqrt())).abs() < 1e-4);
+        assert!((call.vega - spot * normal.pdf(d1) * t.sqrt()).abs() < 1e-3);
+        assert!((call.rho - discounted_strike * t * normal.cdf(d2)).abs() < 1e-3);
+        let theta = -spot * normal.pdf(d1) * vol / (2.0 * t.sqrt()) - rate * discounted_strike * normal.cdf(d2);
+        assert!((call.theta - theta).abs() < 0.05, "theta {} vs {}", call.theta, theta);
+
+        let put = option.greeks(false, &PricingEngine::default());
+        assert!((put.delta - (normal.cdf(d1) - 1.0)).abs() < 1e-5);
+        assert!((put.gamma - call.gamma).abs() < 1e-4);
+
+        let short = OptionPosition::new(option, true, -2.0).greeks(&PricingEngine::default());
+        assert!((short.delta + 2.0 * call.delta).abs() < 1e-12);
+        assert_eq!(OptionPosition::new(option, true, -2.0).delta(&PricingEngine::default()), short.delta);
+    }
+}
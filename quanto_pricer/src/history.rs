@@ -0,0 +1,448 @@
+use std::collections::HashSet;
+use std::fs;
+use std::future::Future;
+use std::path::PathBuf;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use model::model::Asset;
+use crate::candles::Granularity;
+use crate::fetch::{FetchError, FetchReport};
+use crate::freshness::now_millis;
+use crate::provider::{DeribitProvider, MarketDataProvider, OptionQuote, OrderBook, malformed, number};
+use crate::realized::Candle;
+
+pub const MILLIS_PER_DAY: u64 = 86_400_000;
+/// Deribit's largest page of trades.
+const TRADES_PER_PAGE: usize = 1000;
+
+
+/// Half-open interval [start, end) of Unix milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl TimeRange {
+    pub fn new(start: u64, end: u64) -> Self {
+        TimeRange { start, end }
+    }
+
+    /// The whole UTC day `day`, counted from the Unix epoch.
+    pub fn day(day: u64) -> Self {
+        TimeRange { start: day * MILLIS_PER_DAY, end: (day + 1) * MILLIS_PER_DAY }
+    }
+
+    pub fn contains(&self, timestamp: u64) -> bool {
+        self.start <= timestamp && timestamp < self.end
+    }
+
+    /// UTC days that overlap the range.
+    pub fn days(&self) -> std::ops::Range<u64> {
+        if self.end <= self.start {
+            return 0..0;
+        }
+        self.start / MILLIS_PER_DAY..(self.end - 1) / MILLIS_PER_DAY + 1
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Buy,
+    Sell,
+}
+
+/// One print on the tape. `iv` is in percent, as Deribit quotes it, and only set for options.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trade {
+    pub trade_id: String,
+    pub instrument_name: String,
+    pub timestamp: u64,
+    pub price: f64,
+    pub amount: f64,
+    pub direction: Direction,
+    pub index_price: f64,
+    pub mark_price: f64,
+    #[serde(default)]
+    pub iv: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MarkPoint {
+    pub timestamp: u64,
+    pub mark_price: f64,
+}
+
+
+impl DeribitProvider {
+    /// Pages through the trades in `range`, resuming from the last timestamp seen and
+    /// skipping trades already returned at that millisecond.
+    pub(crate) async fn download_trades(&self, instrument_name: &str, range: TimeRange) -> Result<Vec<Trade>, FetchError> {
+        let mut trades: Vec<Trade> = Vec::new();
+        let mut seen = HashSet::new();
+        let mut start = range.start;
+        loop {
+            let path = format!(
+                "/public/get_last_trades_by_instrument_and_time?instrument_name={}&start_timestamp={}&end_timestamp={}&count={}&sorting=asc",
+                instrument_name, start, range.end.saturating_sub(1), TRADES_PER_PAGE,
+            );
+            let response = self.get(&path).await?;
+            let result = response.get("result").ok_or_else(|| malformed("trades"))?;
+            let page: Vec<Trade> = serde_json::from_value(result.get("trades").cloned().unwrap_or(Value::Null))
+                .map_err(|e| FetchError::Api(e.to_string()))?;
+            let has_more = result.get("has_more").and_then(Value::as_bool).unwrap_or(false);
+
+            let last = page.last().map(|trade| trade.timestamp);
+            trades.extend(page.into_iter().filter(|trade| seen.insert(trade.trade_id.clone())));
+            match last {
+                Some(last) if has_more => start = if last > start { last } else { start + 1 },
+                _ => break,
+            }
+        }
+        trades.retain(|trade| range.contains(trade.timestamp));
+        Ok(trades)
+    }
+
+    pub(crate) async fn download_marks(&self, instrument_name: &str, range: TimeRange) -> Result<Vec<MarkPoint>, FetchError> {
+        let path = format!(
+            "/public/get_mark_price_history?instrument_name={}&start_timestamp={}&end_timestamp={}",
+            instrument_name, range.start, range.end.saturating_sub(1),
+        );
+        let response = self.get(&path).await?;
+        let points = response.get("result").and_then(Value::as_array).ok_or_else(|| malformed("mark history"))?;
+        let mut marks: Vec<MarkPoint> = points.iter()
+            .filter_map(|point| Some(MarkPoint { timestamp: point.get(0)?.as_u64()?, mark_price: number(point.get(1)?)? }))
+            .filter(|mark| range.contains(mark.timestamp))
+            .collect();
+        marks.sort_by_key(|mark| mark.timestamp);
+        Ok(marks)
+    }
+}
+
+
+/// A record stored one per line in the cache's CSV files.
+trait CsvRecord: Sized {
+    const HEADER: &'static str;
+    fn timestamp(&self) -> u64;
+    fn to_row(&self) -> String;
+    fn from_row(row: &str) -> Option<Self>;
+}
+
+impl CsvRecord for Trade {
+    const HEADER: &'static str = "trade_id,instrument_name,timestamp,price,amount,direction,index_price,mark_price,iv";
+
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    fn to_row(&self) -> String {
+        let direction = match self.direction { Direction::Buy => "buy", Direction::Sell => "sell" };
+        let iv = self.iv.map(|iv| iv.to_string()).unwrap_or_default();
+        format!(
+            "{},{},{},{},{},{},{},{},{}",
+            self.trade_id, self.instrument_name, self.timestamp, self.price, self.amount, direction, self.index_price, self.mark_price, iv,
+        )
+    }
+
+    fn from_row(row: &str) -> Option<Self> {
+        let fields: Vec<&str> = row.split(',').collect();
+        if fields.len() != 9 {
+            return None;
+        }
+        Some(Trade {
+            trade_id: fields[0].to_string(),
+            instrument_name: fields[1].to_string(),
+            timestamp: fields[2].parse().ok()?,
+            price: fields[3].parse().ok()?,
+            amount: fields[4].parse().ok()?,
+            direction: match fields[5] { "buy" => Direction::Buy, "sell" => Direction::Sell, _ => return None },
+            index_price: fields[6].parse().ok()?,
+            mark_price: fields[7].parse().ok()?,
+            iv: if fields[8].is_empty() { None } else { Some(fields[8].parse().ok()?) },
+        })
+    }
+}
+
+impl CsvRecord for MarkPoint {
+    const HEADER: &'static str = "timestamp,mark_price";
+
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    fn to_row(&self) -> String {
+        format!("{},{}", self.timestamp, self.mark_price)
+    }
+
+    fn from_row(row: &str) -> Option<Self> {
+        let (timestamp, mark_price) = row.split_once(',')?;
+        Some(MarkPoint { timestamp: timestamp.parse().ok()?, mark_price: mark_price.parse().ok()? })
+    }
+}
+
+impl CsvRecord for Candle {
+    const HEADER: &'static str = "timestamp,open,high,low,close,volume";
+
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    fn to_row(&self) -> String {
+        format!("{},{},{},{},{},{}", self.timestamp, self.open, self.high, self.low, self.close, self.volume)
+    }
+
+    fn from_row(row: &str) -> Option<Self> {
+        let fields: Vec<&str> = row.split(',').collect();
+        if fields.len() != 6 {
+            return None;
+        }
+        Some(Candle {
+            timestamp: fields[0].parse().ok()?,
+            open: fields[1].parse().ok()?,
+            high: fields[2].parse().ok()?,
+            low: fields[3].parse().ok()?,
+            close: fields[4].parse().ok()?,
+            volume: fields[5].parse().ok()?,
+        })
+    }
+}
+
+
+/// (year, month, day) of a day counted from the Unix epoch (Hinnant's civil-from-days).
+pub fn civil_date(day: u64) -> (i64, u32, u32) {
+    let z = day as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day_of_month = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    (year_of_era + era * 400 + i64::from(month <= 2), month as u32, day_of_month as u32)
+}
+
+/// Day counted from the Unix epoch of a date on or after 1970-01-01, if the date exists.
+pub fn day_number(year: i64, month: u32, day: u32) -> Option<u64> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let shifted_month = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let day_of_year = (153 * shifted_month + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = u64::try_from(era * 146_097 + day_of_era - 719_468).ok()?;
+    // Dates such as 31 April roll into the next month
+    (civil_date(days).2 == day).then_some(days)
+}
+
+/// "YYYY-MM-DD" of a day counted from the Unix epoch.
+pub fn date(day: u64) -> String {
+    let (year, month, day_of_month) = civil_date(day);
+    format!("{:04}-{:02}-{:02}", year, month, day_of_month)
+}
+
+fn storage(error: std::io::Error) -> FetchError {
+    FetchError::Storage(error.to_string())
+}
+
+
+/// Wraps a provider, keeping the history it downloads as one CSV file per instrument, kind
+/// and UTC day under `dir`, e.g. `deribit/BTC-PERPETUAL/trades/2024-01-01.csv`. Queries
+/// download whole days; only days that have ended are written, so today is always fresh.
+/// Live data passes straight through.
+#[derive(Debug)]
+pub struct HistoryCache<P> {
+    inner: P,
+    dir: PathBuf,
+}
+
+impl<P: MarketDataProvider> HistoryCache<P> {
+    pub fn new(inner: P, dir: impl Into<PathBuf>) -> Self {
+        HistoryCache { inner, dir: dir.into() }
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    fn path(&self, instrument_name: &str, kind: &str, day: u64) -> PathBuf {
+        self.dir.join(self.inner.venue()).join(instrument_name.replace(['/', '\\'], "_")).join(kind).join(format!("{}.csv", date(day)))
+    }
+
+    fn read<T: CsvRecord>(path: &PathBuf) -> Result<Vec<T>, FetchError> {
+        let text = fs::read_to_string(path).map_err(storage)?;
+        text.lines().skip(1)
+            .map(|row| T::from_row(row).ok_or_else(|| FetchError::Storage(format!("bad row in {}: {}", path.display(), row))))
+            .collect()
+    }
+
+    fn write<T: CsvRecord>(path: &PathBuf, records: &[T]) -> Result<(), FetchError> {
+        let mut text = String::from(T::HEADER);
+        text.push('\n');
+        for record in records {
+            text.push_str(&record.to_row());
+            text.push('\n');
+        }
+        fs::create_dir_all(path.parent().expect("cache paths have parents")).map_err(storage)?;
+        // Write then rename, so an interrupted write never leaves a truncated day behind
+        let partial = path.with_extension("csv.partial");
+        fs::write(&partial, text).map_err(storage)?;
+        fs::rename(&partial, path).map_err(storage)
+    }
+
+    async fn load<T, F, Fut>(&self, instrument_name: &str, kind: &str, range: TimeRange, download: F) -> Result<Vec<T>, FetchError>
+    where
+        T: CsvRecord,
+        F: Fn(TimeRange) -> Fut,
+        Fut: Future<Output = Result<Vec<T>, FetchError>>,
+    {
+        let now = now_millis();
+        let mut records = Vec::new();
+        for day in range.days() {
+            let path = self.path(instrument_name, kind, day);
+            let day_records = if path.exists() {
+                Self::read(&path)?
+            } else {
+                let downloaded = download(TimeRange::day(day)).await?;
+                if TimeRange::day(day).end <= now {
+                    Self::write(&path, &downloaded)?;
+                }
+                downloaded
+            };
+            records.extend(day_records.into_iter().filter(|record| range.contains(record.timestamp())));
+        }
+        Ok(records)
+    }
+}
+
+#[async_trait]
+impl<P: MarketDataProvider> MarketDataProvider for HistoryCache<P> {
+    fn venue(&self) -> &'static str {
+        self.inner.venue()
+    }
+
+    async fn spot_price(&self, asset: &Asset) -> Result<f64, FetchError> {
+        self.inner.spot_price(asset).await
+    }
+
+    async fn option_chain(&self, underlying: &str) -> Result<FetchReport<OptionQuote>, FetchError> {
+        self.inner.option_chain(underlying).await
+    }
+
+    async fn order_book(&self, instrument_name: &str, depth: usize) -> Result<OrderBook, FetchError> {
+        self.inner.order_book(instrument_name, depth).await
+    }
+
+    async fn trades(&self, instrument_name: &str, range: TimeRange) -> Result<Vec<Trade>, FetchError> {
+        self.load(instrument_name, "trades", range, |day| self.inner.trades(instrument_name, day)).await
+    }
+
+    async fn mark_history(&self, instrument_name: &str, range: TimeRange) -> Result<Vec<MarkPoint>, FetchError> {
+        self.load(instrument_name, "marks", range, |day| self.inner.mark_history(instrument_name, day)).await
+    }
+
+    /// Kept per granularity, e.g. under `candles-1h`.
+    async fn candles(&self, instrument_name: &str, granularity: Granularity, range: TimeRange) -> Result<Vec<Candle>, FetchError> {
+        let kind = format!("candles-{}", granularity.name());
+        self.load(instrument_name, &kind, range, |day| self.inner.candles(instrument_name, granularity, day)).await
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+    use crate::fetch::{FetchSettings, mock};
+
+    /// 2024-01-01T00:00:00Z
+    const DAY: u64 = 19_723;
+
+    fn trade(id: usize, timestamp: u64) -> String {
+        format!(
+            r#"{{"trade_id":"{}","instrument_name":"BTC-PERPETUAL","timestamp":{},"price":42000.5,"amount":10.0,"direction":"{}","index_price":42001.0,"mark_price":42000.0}}"#,
+            id, timestamp, if id.is_multiple_of(2) { "buy" } else { "sell" },
+        )
+    }
+
+    /// Serves two trades per hour of 2024-01-01, three per page, with the page boundary
+    /// timestamp repeated as Deribit does when resuming.
+    async fn deribit() -> (DeribitProvider, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+        let (base_url, requests) = mock::serve(Duration::ZERO, |path, _| {
+            let query = |key: &str| -> u64 {
+                path.split(['?', '&']).find_map(|pair| pair.strip_prefix(key)?.strip_prefix('=')?.parse().ok()).unwrap()
+            };
+            if path.starts_with("/public/get_last_trades_by_instrument_and_time") {
+                let (start, end) = (query("start_timestamp"), query("end_timestamp"));
+                let all: Vec<(usize, u64)> = (0..48).map(|i| (i, DAY * MILLIS_PER_DAY + i as u64 * 1_800_000)).collect();
+                let page: Vec<String> = all.iter().filter(|(_, t)| *t >= start && *t <= end).take(3).map(|(i, t)| trade(*i, *t)).collect();
+                let has_more = all.iter().filter(|(_, t)| *t >= start && *t <= end).count() > 3;
+                (200, format!(r#"{{"result":{{"trades":[{}],"has_more":{}}}}}"#, page.join(","), has_more))
+            } else {
+                let points: Vec<String> = (0..24).map(|h| format!("[{},{}]", DAY * MILLIS_PER_DAY + h * 3_600_000, 42000 + h)).collect();
+                (200, format!(r#"{{"result":[{}]}}"#, points.join(",")))
+            }
+        }).await;
+        (DeribitProvider::new(FetchSettings { base_url, ..FetchSettings::default() }), requests)
+    }
+
+    #[test]
+    fn test_dates_and_day_ranges() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(DAY), "2024-01-01");
+        assert_eq!(date(DAY + 59), "2024-02-29");
+        assert_eq!(date(11_016), "2000-02-29");
+        assert_eq!(day_number(2024, 2, 29), Some(DAY + 59));
+        assert_eq!((day_number(2023, 2, 29), day_number(2024, 4, 31), day_number(1969, 12, 31)), (None, None, None));
+        let range = TimeRange::new(DAY * MILLIS_PER_DAY - 1, (DAY + 1) * MILLIS_PER_DAY);
+        assert_eq!(range.days(), DAY - 1..DAY + 1);
+        assert_eq!(TimeRange::new(5, 5).days(), 0..0);
+    }
+
+    #[test]
+    fn test_deribit_trades_page_without_duplicates() {
+        Runtime::new().unwrap().block_on(async {
+            let (provider, _) = deribit().await;
+            let trades = provider.trades("BTC-PERPETUAL", TimeRange::day(DAY)).await.unwrap();
+            assert_eq!(trades.len(), 48);
+            assert!(trades.windows(2).all(|pair| pair[0].timestamp < pair[1].timestamp));
+            assert_eq!((trades[0].direction, trades[1].direction), (Direction::Buy, Direction::Sell));
+
+            let marks = provider.mark_history("BTC-PERPETUAL", TimeRange::new(DAY * MILLIS_PER_DAY, DAY * MILLIS_PER_DAY + 7_200_000)).await.unwrap();
+            assert_eq!(marks.iter().map(|mark| mark.mark_price).collect::<Vec<_>>(), vec![42000.0, 42001.0]);
+        });
+    }
+
+    #[test]
+    fn test_cache_serves_repeat_queries_from_disk() {
+        Runtime::new().unwrap().block_on(async {
+            let dir = std::env::temp_dir().join(format!("quanto-history-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            let (provider, requests) = deribit().await;
+            let cache = HistoryCache::new(provider, &dir);
+
+            let morning = TimeRange::new(DAY * MILLIS_PER_DAY, DAY * MILLIS_PER_DAY + 12 * 3_600_000);
+            let trades = cache.trades("BTC-PERPETUAL", morning).await.unwrap();
+            assert_eq!(trades.len(), 24);
+            let downloaded = requests.load(Ordering::SeqCst);
+            assert!(dir.join("deribit/BTC-PERPETUAL/trades/2024-01-01.csv").exists());
+
+            // The rest of the day, and the marks once fetched, come from disk
+            let day = cache.trades("BTC-PERPETUAL", TimeRange::day(DAY)).await.unwrap();
+            assert_eq!(day.len(), 48);
+            assert_eq!(day[..24], trades[..]);
+            assert_eq!(requests.load(Ordering::SeqCst), downloaded);
+            let marks = cache.mark_history("BTC-PERPETUAL", TimeRange::day(DAY)).await.unwrap();
+            assert_eq!(cache.mark_history("BTC-PERPETUAL", TimeRange::day(DAY)).await.unwrap(), marks);
+            assert_eq!(requests.load(Ordering::SeqCst), downloaded + 1);
+            fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+}
@@ -1,45 +1,284 @@
-use roots::{find_root_brent, find_root_secant};
-use statrs::distribution::{Normal, ContinuousCDF};
+use std::f64::consts::PI;
+use std::fmt;
+use statrs::distribution::{Normal, Continuous, ContinuousCDF};
+use crate::curve::YieldCurve;
+use crate::fourier::{CharacteristicModel, QuantoOption, quanto_carry};
 
+const MAX_ITERATIONS: usize = 100;
+/// Upper bracket for the total standard deviation σ√T.
+const MAX_STD_DEV: f64 = 50.0;
+/// Log-spaced volatility grid that brackets quanto implied volatilities.
+const MIN_QUANTO_VOL: f64 = 1e-4;
+const MAX_QUANTO_VOL: f64 = 10.0;
+const QUANTO_GRID_POINTS: usize = 100;
 
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImpliedVolError {
+    InvalidInput(&'static str),
+    BelowIntrinsic { price: f64, intrinsic: f64 },
+    AboveUpperBound { price: f64, bound: f64 },
+    /// The price equals its intrinsic value, so any volatility down to zero fits.
+    NoTimeValue,
+    NoConvergence { iterations: usize, last_vol: f64 },
+}
+
+impl fmt::Display for ImpliedVolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImpliedVolError::InvalidInput(reason) => write!(f, "invalid input: {}", reason),
+            ImpliedVolError::BelowIntrinsic { price, intrinsic } => write!(f, "price {} is below intrinsic value {}", price, intrinsic),
+            ImpliedVolError::AboveUpperBound { price, bound } => write!(f, "price {} is above the no-arbitrage bound {}", price, bound),
+            ImpliedVolError::NoTimeValue => write!(f, "price has no time value"),
+            ImpliedVolError::NoConvergence { iterations, last_vol } => write!(f, "no convergence after {} iterations (last vol {})", iterations, last_vol),
+        }
+    }
+}
+
+impl std::error::Error for ImpliedVolError {}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct ImpliedVolatility {
     pub spot: f64,
     pub strike: f64,
     pub r: f64,
     pub time_to_maturity: f64,
     pub market_price: f64,
-    pub is_call: bool
+    pub is_call: bool,
+    /// Continuous dividend, funding or borrow yield of the underlying.
+    pub dividend_yield: f64
+}
+
+
+/// Undiscounted Black price of an option on `forward` with total standard deviation `s`.
+pub(crate) fn black(forward: f64, strike: f64, s: f64, is_call: bool) -> f64 {
+    let normal = Normal::new(0.0, 1.0).unwrap();
+    let d1 = (forward / strike).ln() / s + 0.5 * s;
+    let d2 = d1 - s;
+    if is_call {
+        forward * normal.cdf(d1) - strike * normal.cdf(d2)
+    } else {
+        strike * normal.cdf(-d2) - forward * normal.cdf(-d1)
+    }
 }
 
 
 impl ImpliedVolatility {
-    fn black_scholes_price(&self, sigma: f64) -> f64 {
-        let d1 = ((self.spot / self.strike).ln() + (self.r + 0.5 * sigma.powi(2)) * self.time_to_maturity)
-            / (sigma * (self.time_to_maturity).sqrt());
-        let d2 = d1 - sigma * (self.time_to_maturity).sqrt();
+    /// Takes the rate from a curve at this option's maturity.
+    pub fn with_curve(mut self, curve: &YieldCurve) -> Self {
+        self.r = curve.zero_rate(self.time_to_maturity);
+        self
+    }
+
+    pub fn black_scholes_price(&self, sigma: f64) -> f64 {
+        let discount = (-self.r * self.time_to_maturity).exp();
+        discount * black(self.forward(), self.strike, sigma * self.time_to_maturity.sqrt(), self.is_call)
+    }
 
-        let rng = Normal::new(0.0, 1.0).unwrap();
+    pub(crate) fn forward(&self) -> f64 {
+        self.spot * ((self.r - self.dividend_yield) * self.time_to_maturity).exp()
+    }
 
-        let call_price = self.spot * rng.cdf(d1) - self.strike * (-self.r * self.time_to_maturity).exp() * rng.cdf(d2);
-        if self.is_call {
-            call_price
+    /// Solves for the volatility in total standard deviation σ√T, which keeps near-expiry
+    /// options well scaled. The option is first converted to its out-of-the-money side by
+    /// parity. The Corrado–Miller rational approximation provides the initial guess, and
+    /// Newton's method refines it inside a shrinking bracket. Deep out-of-the-money prices
+    /// are matched in log space, where the Black function is close to linear.
+    pub fn implied_volatility(&self) -> Result<f64, ImpliedVolError> {
+        if self.spot.is_nan() || self.strike.is_nan() || self.spot <= 0.0 || self.strike <= 0.0 {
+            return Err(ImpliedVolError::InvalidInput("spot and strike must be positive"));
+        }
+        if self.time_to_maturity.is_nan() || self.time_to_maturity <= 0.0 {
+            return Err(ImpliedVolError::InvalidInput("time to maturity must be positive"));
+        }
+        if !self.market_price.is_finite() || !self.r.is_finite() || !self.dividend_yield.is_finite() {
+            return Err(ImpliedVolError::InvalidInput("price, rate and yield must be finite"));
+        }
+
+        let discount = (-self.r * self.time_to_maturity).exp();
+        let forward = self.forward();
+        let strike = self.strike;
+        let price = self.market_price / discount;
+
+        let (intrinsic, bound) = if self.is_call {
+            ((forward - strike).max(0.0), forward)
         } else {
-            call_price + self.strike * (-self.r * self.time_to_maturity).exp() - self.spot
+            ((strike - forward).max(0.0), strike)
+        };
+        let tolerance = 1e-14 * bound;
+        if price < intrinsic - tolerance {
+            return Err(ImpliedVolError::BelowIntrinsic { price: self.market_price, intrinsic: intrinsic * discount });
         }
+        if price >= bound {
+            return Err(ImpliedVolError::AboveUpperBound { price: self.market_price, bound: bound * discount });
+        }
+
+        // Out-of-the-money side: the call if the strike is above the forward
+        let otm_call = strike >= forward;
+        let target = if otm_call == self.is_call {
+            price
+        } else if self.is_call {
+            price - (forward - strike)
+        } else {
+            price - (strike - forward)
+        };
+        if target <= tolerance {
+            return Err(ImpliedVolError::NoTimeValue);
+        }
+
+        let s = self.solve(forward, strike, target, otm_call)?;
+        Ok(s / self.time_to_maturity.sqrt())
+    }
+
+    fn solve(&self, forward: f64, strike: f64, target: f64, otm_call: bool) -> Result<f64, ImpliedVolError> {
+        let normal = Normal::new(0.0, 1.0).unwrap();
+        let log_space = target < 1e-4 * forward.min(strike);
+        let last_vol = |s: f64| s / self.time_to_maturity.sqrt();
+
+        let mut lower = 0.0;
+        let mut upper = MAX_STD_DEV;
+        let mut s = initial_guess(forward, strike, target, otm_call).clamp(1e-8, MAX_STD_DEV);
+
+        for _ in 0..MAX_ITERATIONS {
+            let value = black(forward, strike, s, otm_call);
+            if value > target {
+                upper = s;
+            } else {
+                lower = s;
+            }
+
+            let vega = forward * normal.pdf((forward / strike).ln() / s + 0.5 * s);
+            let step = if log_space {
+                if value <= 0.0 {
+                    f64::NAN
+                } else {
+                    (value.ln() - target.ln()) * value / vega
+                }
+            } else {
+                (value - target) / vega
+            };
+
+            if step.abs() <= 1e-15 * s || (value - target).abs() <= 1e-15 * target {
+                return Ok(s - step);
+            }
+            let next = s - step;
+            s = if next.is_finite() && next > lower && next < upper { next } else { 0.5 * (lower + upper) };
+        }
+
+        Err(ImpliedVolError::NoConvergence { iterations: MAX_ITERATIONS, last_vol: last_vol(s) })
     }
+}
+
+
+/// Implied volatility under the quanto model: the asset volatility that reproduces a
+/// `QuantoOption` price, with the FX volatility, correlation and rates held as given. The
+/// quanto drift ρσσ_X moves the forward with σ, so plain Black–Scholes inversion of such
+/// prices is biased whenever ρσ_X ≠ 0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuantoImpliedVolatility {
+    /// The option's own `volatility` is ignored.
+    pub option: QuantoOption,
+    pub market_price: f64,
+    pub is_call: bool,
+}
+
+impl QuantoImpliedVolatility {
+    pub fn new(option: QuantoOption, market_price: f64, is_call: bool) -> Self {
+        QuantoImpliedVolatility { option, market_price, is_call }
+    }
+
+    /// Closed-form quanto price at asset volatility `sigma`.
+    pub fn price(&self, sigma: f64) -> f64 {
+        let option = QuantoOption { volatility: sigma, ..self.option };
+        let t = option.time_to_maturity;
+        option.discount() * black(option.forward(), option.strike, sigma * t.sqrt(), self.is_call)
+    }
+
+    /// When ρσ_X = 0 the carry does not depend on σ and the problem is plain Black–Scholes
+    /// with the carry folded into the yield. Otherwise the root is bracketed on a log grid
+    /// of volatilities and refined by the Illinois method.
+    pub fn implied_volatility(&self) -> Result<f64, ImpliedVolError> {
+        let o = self.option;
+        QuantoOption { volatility: 1.0, ..o }.validate().map_err(|_| ImpliedVolError::InvalidInput("option inputs failed validation"))?;
+        if !(self.market_price.is_finite() && self.market_price >= 0.0) {
+            return Err(ImpliedVolError::InvalidInput("price must be finite and non-negative"));
+        }
+
+        if o.correlation * o.fx_volatility == 0.0 {
+            let carry = quanto_carry(o.domestic_rate, o.foreign_rate, 0.0, o.fx_volatility, o.correlation);
+            return ImpliedVolatility {
+                spot: o.spot,
+                strike: o.strike,
+                r: o.domestic_rate,
+                time_to_maturity: o.time_to_maturity,
+                market_price: self.market_price,
+                is_call: self.is_call,
+                dividend_yield: o.domestic_rate - carry + o.dividend_yield,
+            }.implied_volatility();
+        }
 
-    pub fn implied_volatility(&self) -> f64 {
-        let f = |volatility: f64| -> f64 {
-            self.black_scholes_price(volatility) - self.market_price
+        let target = self.market_price;
+        let grid: Vec<f64> = (0..=QUANTO_GRID_POINTS)
+            .map(|i| MIN_QUANTO_VOL * (MAX_QUANTO_VOL / MIN_QUANTO_VOL).powf(i as f64 / QUANTO_GRID_POINTS as f64))
+            .collect();
+        let values: Vec<f64> = grid.iter().map(|sigma| self.price(*sigma) - target).collect();
+        if values[0] > 0.0 {
+            return Err(ImpliedVolError::BelowIntrinsic { price: target, intrinsic: values[0] + target });
+        }
+        let Some(i) = (0..QUANTO_GRID_POINTS).find(|i| values[*i] <= 0.0 && values[i + 1] >= 0.0) else {
+            let bound = values.iter().fold(f64::NEG_INFINITY, |a, b| a.max(*b)) + target;
+            return Err(ImpliedVolError::AboveUpperBound { price: target, bound });
         };
+        if values[i] == 0.0 {
+            return Ok(grid[i]);
+        }
 
-        match find_root_brent(0.001, 3.0, &f, &mut 1e-6) {
-            Ok(root) => root,
-            Err(_) => {
-                let secant_result = find_root_secant(0.001, 3.0, &f, &mut 1e-6);
-                secant_result.unwrap_or_else(|_| 0.0)
+        let (mut a, mut fa, mut b, mut fb) = (grid[i], values[i], grid[i + 1], values[i + 1]);
+        let mut side = 0;
+        for _ in 0..MAX_ITERATIONS {
+            let c = (a * fb - b * fa) / (fb - fa);
+            let fc = self.price(c) - target;
+            if fc.abs() <= 1e-14 * o.strike.max(o.spot) || (b - a).abs() <= 1e-15 * c {
+                return Ok(c);
+            }
+            // Illinois: halve the stale endpoint's value when the same side is kept twice
+            if (fc < 0.0) == (fa < 0.0) {
+                a = c;
+                fa = fc;
+                if side == -1 { fb *= 0.5; }
+                side = -1;
+            } else {
+                b = c;
+                fb = fc;
+                if side == 1 { fa *= 0.5; }
+                side = 1;
             }
         }
+        Err(ImpliedVolError::NoConvergence { iterations: MAX_ITERATIONS, last_vol: 0.5 * (a + b) })
+    }
+}
+
+impl QuantoOption {
+    /// Asset volatility implied by `market_price` under this option's quanto model.
+    pub fn implied_volatility(&self, market_price: f64, is_call: bool) -> Result<f64, ImpliedVolError> {
+        QuantoImpliedVolatility::new(*self, market_price, is_call).implied_volatility()
+    }
+}
+
+
+/// Corrado–Miller rational approximation of σ√T, falling back to the inflection point of
+/// the Black function where the approximation breaks down (far from the money).
+fn initial_guess(forward: f64, strike: f64, otm_price: f64, otm_call: bool) -> f64 {
+    let call = if otm_call { otm_price } else { otm_price + forward - strike };
+    let half_moneyness = 0.5 * (forward - strike);
+    let radicand = (call - half_moneyness).powi(2) - (forward - strike).powi(2) / PI;
+
+    let guess = (2.0 * PI).sqrt() / (forward + strike) * (call - half_moneyness + radicand.max(0.0).sqrt());
+    if guess.is_finite() && guess > 0.0 && radicand >= 0.0 {
+        guess
+    } else {
+        (2.0 * (forward / strike).ln().abs()).sqrt().max(1e-4)
     }
 }
 
@@ -48,6 +287,18 @@ impl ImpliedVolatility {
 mod tests {
     use super::*;
 
+    fn option(strike: f64, time_to_maturity: f64, is_call: bool) -> ImpliedVolatility {
+        ImpliedVolatility {
+            spot: 100.0,
+            strike,
+            r: 0.05,
+            time_to_maturity,
+            market_price: 0.0,
+            is_call,
+            dividend_yield: 0.0,
+        }
+    }
+
     #[test]
     fn test_black_scholes_price_for_call() {
         // Given parameters for an at-the-money call option
@@ -58,6 +309,7 @@ mod tests {
             time_to_maturity: 1.0,  // 1 year to maturity
             market_price: 0.0,      // Not needed for this test
             is_call: true,          // This is a call option
+            dividend_yield: 0.0,
         };
 
         let volatility = 0.2;
@@ -77,6 +329,7 @@ mod tests {
             time_to_maturity: 1.0,  // 1 year to maturity
             market_price: 0.0,      // Not needed for this test
             is_call: false,         // This is a put option
+            dividend_yield: 0.0,
         };
 
         let volatility = 0.2;
@@ -96,9 +349,10 @@ mod tests {
             time_to_maturity: 1.0,
             market_price: 10.4506, // Market price for an at-the-money European call option
             is_call: true,
+            dividend_yield: 0.0,
         };
 
-        let implied_vol = option.implied_volatility();
+        let implied_vol = option.implied_volatility().unwrap();
         println!("Implied volatility (call): {}", implied_vol);
 
         assert!((implied_vol - 0.2).abs() < 1e-2);
@@ -113,11 +367,131 @@ mod tests {
             time_to_maturity: 1.0,
             market_price: 5.5735, // Market price for an at-the-money European put option
             is_call: false,
+            dividend_yield: 0.0,
         };
 
-        let implied_vol = option.implied_volatility();
+        let implied_vol = option.implied_volatility().unwrap();
         println!("Implied volatility (put): {}", implied_vol);
 
         assert!((implied_vol - 0.2).abs() < 1e-2);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_round_trip_across_moneyness_and_expiry() {
+        let one_hour = 1.0 / (365.0 * 24.0);
+        for (strike, time_to_maturity, vol) in [
+            (100.0, 1.0, 0.2),
+            (300.0, 0.5, 0.8),      // Deep out of the money
+            (20.0, 0.5, 0.8),       // Deep in the money call / far out of the money put
+            (101.0, one_hour, 0.6), // Near expiry
+            (60.0, 2.0, 1.5),
+        ] {
+            for is_call in [true, false] {
+                let mut option = option(strike, time_to_maturity, is_call);
+                option.market_price = option.black_scholes_price(vol);
+
+                let implied = option.implied_volatility().unwrap();
+                assert!((implied - vol).abs() < 1e-8, "K={} T={} call={}: {} vs {}", strike, time_to_maturity, is_call, implied, vol);
+            }
+        }
+    }
+
+    #[test]
+    fn test_failure_reasons() {
+        let mut call = option(100.0, 1.0, true);
+        call.market_price = 1.0;
+        call.strike = 50.0;
+        assert!(matches!(call.implied_volatility(), Err(ImpliedVolError::BelowIntrinsic { .. })));
+
+        call.market_price = 150.0;
+        assert!(matches!(call.implied_volatility(), Err(ImpliedVolError::AboveUpperBound { .. })));
+
+        call.market_price = 100.0 - 50.0 * (-0.05f64).exp();
+        assert_eq!(call.implied_volatility(), Err(ImpliedVolError::NoTimeValue));
+
+        call.time_to_maturity = 0.0;
+        assert!(matches!(call.implied_volatility(), Err(ImpliedVolError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_dividend_yield_round_trip_and_parity() {
+        let mut call = option(90.0, 0.5, true);
+        call.dividend_yield = 0.08;
+        let mut put = option(90.0, 0.5, false);
+        put.dividend_yield = 0.08;
+
+        // C - P = S e^{-qT} - K e^{-rT}
+        let parity = call.black_scholes_price(0.7) - put.black_scholes_price(0.7);
+        assert!((parity - (100.0 * (-0.04f64).exp() - 90.0 * (-0.025f64).exp())).abs() < 1e-10);
+
+        call.market_price = call.black_scholes_price(0.7);
+        put.market_price = put.black_scholes_price(0.7);
+        assert!((call.implied_volatility().unwrap() - 0.7).abs() < 1e-8);
+        assert!((put.implied_volatility().unwrap() - 0.7).abs() < 1e-8);
+
+        // Ignoring the yield misreads the same price
+        call.dividend_yield = 0.0;
+        assert!((call.implied_volatility().unwrap() - 0.7).abs() > 1e-3);
+    }
+
+    #[test]
+    fn test_quanto_round_trip_is_exact() {
+        let quanto = QuantoOption {
+            spot: 30000.0,
+            strike: 33000.0,
+            domestic_rate: 0.03,
+            foreign_rate: 0.01,
+            volatility: 0.65,
+            fx_volatility: 0.25,
+            time_to_maturity: 0.5,
+            correlation: -0.6,
+            dividend_yield: 0.0,
+        };
+        for (strike, is_call) in [(20000.0, false), (28000.0, false), (33000.0, true), (45000.0, true)] {
+            let option = QuantoOption { strike, ..quanto };
+            let price = option.calculate_price_fft().unwrap();
+            let market_price = if is_call { price.call } else { price.put };
+            let vol = option.implied_volatility(market_price, is_call).unwrap();
+            assert!((vol - 0.65).abs() < 1e-6, "strike {}: {}", strike, vol);
+
+            let repriced = QuantoImpliedVolatility::new(option, market_price, is_call).price(vol);
+            assert!((repriced - market_price).abs() < 1e-8 * market_price.max(1.0));
+        }
+
+        // Plain Black–Scholes on the same price misses the quanto drift
+        let price = quanto.calculate_price_fft().unwrap().call;
+        let plain = ImpliedVolatility {
+            spot: 30000.0,
+            strike: 33000.0,
+            r: 0.03,
+            time_to_maturity: 0.5,
+            market_price: price,
+            is_call: true,
+            dividend_yield: 0.0,
+        }.implied_volatility().unwrap();
+        assert!((plain - 0.65).abs() > 0.05);
+    }
+
+    #[test]
+    fn test_quanto_solver_without_quanto_drift_and_bounds() {
+        // ρσ_X = 0: the carry is the foreign rate whatever σ is
+        let option = QuantoOption {
+            spot: 100.0,
+            strike: 95.0,
+            domestic_rate: 0.05,
+            foreign_rate: 0.01,
+            volatility: 0.3,
+            fx_volatility: 0.2,
+            time_to_maturity: 1.0,
+            correlation: 0.0,
+            dividend_yield: 0.02,
+        };
+        let price = option.calculate_price_fft().unwrap().put;
+        assert!((option.implied_volatility(price, false).unwrap() - 0.3).abs() < 1e-6);
+
+        let quanto = QuantoOption { correlation: 0.5, ..option };
+        assert!(matches!(quanto.implied_volatility(1e6, true), Err(ImpliedVolError::AboveUpperBound { .. })));
+        assert!(matches!(QuantoOption { strike: 130.0, ..quanto }.implied_volatility(1.0, false), Err(ImpliedVolError::BelowIntrinsic { .. })));
+        assert!(QuantoOption { spot: -1.0, ..quanto }.implied_volatility(5.0, true).is_err());
+    }
+}
@@ -0,0 +1,315 @@
+use model::model::{Asset, Instrument, OptionKind};
+use crate::data::DeribitOptionData;
+use crate::error::DataError;
+use crate::history::{MILLIS_PER_DAY, civil_date, day_number};
+use crate::vol_surface::MILLIS_PER_YEAR;
+
+/// Deribit, Binance and OKX all expire contracts at 08:00 UTC.
+const EXPIRY_OFFSET_MILLIS: u64 = 8 * 3_600_000;
+const MONTHS: [&str; 12] = ["JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC"];
+/// Quote currencies recognised at the end of Binance spot symbols such as "BTCUSDT".
+const BINANCE_QUOTES: [&str; 8] = ["FDUSD", "USDT", "USDC", "TUSD", "BUSD", "EUR", "BTC", "ETH"];
+
+
+/// An exchange whose instrument names we read and write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Venue {
+    Deribit,
+    Binance,
+    Okx,
+}
+
+impl Venue {
+    /// The name its [`crate::provider::MarketDataProvider`] reports.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Venue::Deribit => "deribit",
+            Venue::Binance => "binance",
+            Venue::Okx => "okx",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Venue> {
+        [Venue::Deribit, Venue::Binance, Venue::Okx].into_iter().find(|venue| venue.name() == name)
+    }
+}
+
+
+fn malformed(what: &'static str, text: &str) -> DataError {
+    DataError::Malformed { what, text: text.to_string() }
+}
+
+fn expiry(year: i64, month: u32, day: u32) -> Option<u64> {
+    Some(day_number(year, month, day)? * MILLIS_PER_DAY + EXPIRY_OFFSET_MILLIS)
+}
+
+/// "29SEP24" or "5JUL24".
+fn parse_deribit_date(text: &str) -> Result<u64, DataError> {
+    let digits = text.chars().take_while(char::is_ascii_digit).count();
+    if !(1..=2).contains(&digits) || text.len() != digits + 5 {
+        return Err(malformed("expiry date", text));
+    }
+    let parsed = || {
+        let day = text[..digits].parse().ok()?;
+        let month = MONTHS.iter().position(|month| *month == &text[digits..digits + 3])?;
+        let year: i64 = text[digits + 3..].parse().ok()?;
+        expiry(2000 + year, month as u32 + 1, day)
+    };
+    parsed().ok_or_else(|| malformed("expiry date", text))
+}
+
+/// "241227", as Binance and OKX write dates.
+fn parse_compact_date(text: &str) -> Result<u64, DataError> {
+    if text.len() != 6 || !text.chars().all(|c| c.is_ascii_digit()) {
+        return Err(malformed("expiry date", text));
+    }
+    let field = |range: std::ops::Range<usize>| text[range].parse::<u32>().ok();
+    let parsed = || expiry(2000 + field(0..2)? as i64, field(2..4)?, field(4..6)?);
+    parsed().ok_or_else(|| malformed("expiry date", text))
+}
+
+fn format_deribit_date(expiry: u64) -> String {
+    let (year, month, day) = civil_date(expiry / MILLIS_PER_DAY);
+    format!("{}{}{:02}", day, MONTHS[month as usize - 1], year % 100)
+}
+
+fn format_compact_date(expiry: u64) -> String {
+    let (year, month, day) = civil_date(expiry / MILLIS_PER_DAY);
+    format!("{:02}{:02}{:02}", year % 100, month, day)
+}
+
+/// Strikes as "56000" or "0.625", or "0d625" on Deribit, which reserves the dot.
+fn parse_strike(text: &str, venue: Venue) -> Result<f64, DataError> {
+    let decimal = if venue == Venue::Deribit { text.replace('d', ".") } else { text.to_string() };
+    match decimal.parse::<f64>() {
+        Ok(strike) if strike.is_finite() && strike > 0.0 => Ok(strike),
+        _ => Err(malformed("strike", text)),
+    }
+}
+
+fn format_strike(strike: f64, venue: Venue) -> String {
+    let text = if strike.fract() == 0.0 { format!("{:.0}", strike) } else { strike.to_string() };
+    if venue == Venue::Deribit { text.replace('.', "d") } else { text }
+}
+
+fn parse_kind(text: &str) -> Result<OptionKind, DataError> {
+    match text {
+        "C" => Ok(OptionKind::Call),
+        "P" => Ok(OptionKind::Put),
+        _ => Err(malformed("option kind", text)),
+    }
+}
+
+fn kind_letter(kind: OptionKind) -> &'static str {
+    match kind {
+        OptionKind::Call => "C",
+        OptionKind::Put => "P",
+    }
+}
+
+
+/// Reads a venue's instrument name, e.g. Deribit's "BTC-29SEP24-56000-C", Binance's
+/// "BTC-240929-56000-C" or OKX's "BTC-USD-240929-56000-C". Deribit's inverse contracts
+/// are on the USD pair and Binance's options on USDT.
+pub fn parse_instrument(venue: Venue, name: &str) -> Result<Instrument, DataError> {
+    let parts: Vec<&str> = name.split('-').collect();
+    let unrecognised = || DataError::UnrecognisedInstrument { venue: venue.name(), name: name.to_string() };
+    match venue {
+        Venue::Deribit => {
+            let underlying = match parts[0].split_once('_') {
+                Some((base, quote)) => Asset::new(base, quote),
+                None => Asset::new(parts[0], "USD"),
+            };
+            match parts[1..] {
+                [] if parts[0].contains('_') => Ok(Instrument::Spot(underlying)),
+                ["PERPETUAL"] => Ok(Instrument::Perpetual { underlying }),
+                [date] => Ok(Instrument::Future { underlying, expiry: parse_deribit_date(date)? }),
+                [date, strike, kind] => Ok(Instrument::Option {
+                    underlying,
+                    expiry: parse_deribit_date(date)?,
+                    strike: parse_strike(strike, venue)?,
+                    kind: parse_kind(kind)?,
+                }),
+                _ => Err(unrecognised()),
+            }
+        }
+        Venue::Binance => match parts[..] {
+            [base, date, strike, kind] => Ok(Instrument::Option {
+                underlying: Asset::new(base, "USDT"),
+                expiry: parse_compact_date(date)?,
+                strike: parse_strike(strike, venue)?,
+                kind: parse_kind(kind)?,
+            }),
+            [symbol] => {
+                let (symbol, date) = match symbol.split_once('_') {
+                    Some((symbol, date)) => (symbol, Some(date)),
+                    None => (symbol, None),
+                };
+                let quote = BINANCE_QUOTES.iter().find(|quote| symbol.len() > quote.len() && symbol.ends_with(*quote))
+                    .ok_or_else(|| malformed("quote currency", symbol))?;
+                let underlying = Asset::new(&symbol[..symbol.len() - quote.len()], quote);
+                match date {
+                    Some(date) => Ok(Instrument::Future { underlying, expiry: parse_compact_date(date)? }),
+                    None => Ok(Instrument::Spot(underlying)),
+                }
+            }
+            _ => Err(unrecognised()),
+        },
+        Venue::Okx => {
+            if parts.len() < 2 {
+                return Err(unrecognised());
+            }
+            let underlying = Asset::new(parts[0], parts[1]);
+            match parts[2..] {
+                [] => Ok(Instrument::Spot(underlying)),
+                ["SWAP"] => Ok(Instrument::Perpetual { underlying }),
+                [date] => Ok(Instrument::Future { underlying, expiry: parse_compact_date(date)? }),
+                [date, strike, kind] => Ok(Instrument::Option {
+                    underlying,
+                    expiry: parse_compact_date(date)?,
+                    strike: parse_strike(strike, venue)?,
+                    kind: parse_kind(kind)?,
+                }),
+                _ => Err(unrecognised()),
+            }
+        }
+    }
+}
+
+
+/// The venue's name for `instrument`; the inverse of [`parse_instrument`].
+pub fn instrument_name(venue: Venue, instrument: &Instrument) -> Result<String, DataError> {
+    let asset = instrument.underlying();
+    match venue {
+        Venue::Deribit => {
+            let prefix = if asset.quote == "USD" { asset.base.clone() } else { format!("{}_{}", asset.base, asset.quote) };
+            Ok(match instrument {
+                Instrument::Spot(_) => format!("{}_{}", asset.base, asset.quote),
+                Instrument::Perpetual { .. } => format!("{}-PERPETUAL", prefix),
+                Instrument::Future { expiry, .. } => format!("{}-{}", prefix, format_deribit_date(*expiry)),
+                Instrument::Option { expiry, strike, kind, .. } => {
+                    format!("{}-{}-{}-{}", prefix, format_deribit_date(*expiry), format_strike(*strike, venue), kind_letter(*kind))
+                }
+            })
+        }
+        Venue::Binance => match instrument {
+            Instrument::Spot(_) => Ok(format!("{}{}", asset.base, asset.quote)),
+            Instrument::Perpetual { .. } => Err(DataError::Unsupported("Binance perpetuals share their spot symbol")),
+            Instrument::Future { expiry, .. } => Ok(format!("{}{}_{}", asset.base, asset.quote, format_compact_date(*expiry))),
+            Instrument::Option { expiry, strike, kind, .. } => {
+                Ok(format!("{}-{}-{}-{}", asset.base, format_compact_date(*expiry), format_strike(*strike, venue), kind_letter(*kind)))
+            }
+        },
+        Venue::Okx => {
+            let pair = format!("{}-{}", asset.base, asset.quote);
+            Ok(match instrument {
+                Instrument::Spot(_) => pair,
+                Instrument::Perpetual { .. } => format!("{}-SWAP", pair),
+                Instrument::Future { expiry, .. } => format!("{}-{}", pair, format_compact_date(*expiry)),
+                Instrument::Option { expiry, strike, kind, .. } => {
+                    format!("{}-{}-{}-{}", pair, format_compact_date(*expiry), format_strike(*strike, venue), kind_letter(*kind))
+                }
+            })
+        }
+    }
+}
+
+
+/// Years from `now` (Unix milliseconds) to expiry; `None` for undated or expired instruments.
+pub fn time_to_maturity(instrument: &Instrument, now: u64) -> Option<f64> {
+    let expiry = instrument.expiry()?;
+    (expiry > now).then(|| (expiry - now) as f64 / MILLIS_PER_YEAR)
+}
+
+
+impl DeribitOptionData {
+    /// This quote's contract, read from its name on `venue`.
+    pub fn instrument(&self, venue: Venue) -> Result<Instrument, DataError> {
+        parse_instrument(venue, &self.instrument_name)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-09-29T08:00:00Z
+    const EXPIRY: u64 = 1_727_596_800_000;
+
+    #[test]
+    fn test_names_round_trip_on_every_venue() {
+        let cases = [
+            (Venue::Deribit, "BTC-29SEP24-56000-C"),
+            (Venue::Deribit, "XRP_USDC-5JUL24-0d625-P"),
+            (Venue::Deribit, "ETH_USDC-PERPETUAL"),
+            (Venue::Deribit, "BTC-27DEC24"),
+            (Venue::Deribit, "BTC_USDC"),
+            (Venue::Binance, "BTC-240929-56000-C"),
+            (Venue::Binance, "ETHUSDT"),
+            (Venue::Binance, "BTCUSDT_241227"),
+            (Venue::Okx, "BTC-USD-240929-56000-C"),
+            (Venue::Okx, "BTC-USDT-SWAP"),
+            (Venue::Okx, "ETH-USD-241227"),
+            (Venue::Okx, "SOL-USDC"),
+        ];
+        for (venue, name) in cases {
+            let instrument = parse_instrument(venue, name).unwrap();
+            assert_eq!(instrument_name(venue, &instrument).unwrap(), name);
+        }
+
+        let option = Instrument::Option { underlying: Asset::new("BTC", "USD"), expiry: EXPIRY, strike: 56000.0, kind: OptionKind::Call };
+        assert_eq!(parse_instrument(Venue::Deribit, "BTC-29SEP24-56000-C").unwrap(), option);
+        assert_eq!(instrument_name(Venue::Okx, &option).unwrap(), "BTC-USD-240929-56000-C");
+        assert_eq!(parse_instrument(Venue::Deribit, "XRP_USDC-5JUL24-0d625-P").unwrap().strike(), Some(0.625));
+        assert_eq!(parse_instrument(Venue::Binance, "ETHUSDT").unwrap(), Instrument::Spot(Asset::new("ETH", "USDT")));
+    }
+
+    #[test]
+    fn test_malformed_names_are_rejected() {
+        for (venue, name) in [
+            (Venue::Deribit, "BTC-31SEP24-56000-C"),
+            (Venue::Deribit, "BTC-29XYZ24-56000-C"),
+            (Venue::Deribit, "BTC-29SEP24-abc-C"),
+            (Venue::Deribit, "BTC-29SEP24-56000-X"),
+            (Venue::Deribit, "BTC"),
+            (Venue::Binance, "BTCXYZ"),
+            (Venue::Okx, "BTC"),
+            (Venue::Okx, "BTC-USD-2409-1-C"),
+        ] {
+            assert!(parse_instrument(venue, name).is_err(), "{}", name);
+        }
+        assert_eq!(parse_instrument(Venue::Deribit, "BTC-29SEP24-abc-C"), Err(DataError::Malformed { what: "strike", text: "abc".to_string() }));
+        assert_eq!(parse_instrument(Venue::Okx, "BTC").unwrap_err().to_string(), "unrecognised okx instrument 'BTC'");
+        assert!(instrument_name(Venue::Binance, &Instrument::Perpetual { underlying: Asset::new("BTC", "USDT") }).is_err());
+        assert_eq!(Venue::from_name("okx"), Some(Venue::Okx));
+    }
+
+    #[test]
+    fn test_quotes_feed_pricers_directly() {
+        let quote = DeribitOptionData {
+            instrument_name: "BTC-29SEP24-56000-P".to_string(),
+            strike: 56000.0,
+            expiration_timestamp: EXPIRY,
+            option_type: "put".to_string(),
+            price_index: "btc_usd".to_string(),
+            settlement_currency: "BTC".to_string(),
+            implied_volatility: None,
+            market_price: None,
+            delta: None,
+            gamma: None,
+            vega: None,
+            theta: None,
+            open_interest: None,
+            exchange_timestamp: None,
+            received_at: None,
+        };
+        let instrument = quote.instrument(Venue::Deribit).unwrap();
+        // The name's date agrees with the exchange's own expiry timestamp
+        assert_eq!(instrument.expiry(), Some(quote.expiration_timestamp));
+        assert_eq!(instrument.option_kind(), Some(OptionKind::Put));
+        let now = EXPIRY - 73 * MILLIS_PER_DAY;
+        assert!((time_to_maturity(&instrument, now).unwrap() - 73.0 * MILLIS_PER_DAY as f64 / MILLIS_PER_YEAR).abs() < 1e-12);
+        assert_eq!(time_to_maturity(&instrument, EXPIRY), None);
+    }
+}
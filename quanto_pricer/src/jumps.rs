@@ -0,0 +1,292 @@
+use num_complex::Complex;
+use rand::Rng;
+use rand::rngs::StdRng;
+use rand_distr::{Gamma, Poisson, StandardNormal};
+use crate::fourier::{CharacteristicModel, FftSettings, OptionPrice, price_fft, price_strikes_fft, quanto_carry};
+use crate::curve::YieldCurve;
+use crate::engine::PricingEngine;
+use crate::monte_carlo::TerminalSampler;
+
+
+/// Merton jump diffusion: geometric Brownian motion with volatility `volatility`, plus jumps
+/// arriving at rate `jump_intensity` per year that multiply the price by e^J, with
+/// J ~ N(`jump_mean`, `jump_volatility`²). The quanto adjustment applies to the diffusion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MertonModel {
+    pub spot: f64,
+    pub strike: f64,
+    pub domestic_rate: f64,
+    pub foreign_rate: f64,
+    pub volatility: f64,
+    pub jump_intensity: f64,
+    pub jump_mean: f64,
+    pub jump_volatility: f64,
+    pub time_to_maturity: f64,
+    pub fx_volatility: f64,
+    pub correlation: f64,
+}
+
+
+/// Variance gamma (Madan, Carr & Chang): Brownian motion with drift `theta` and volatility
+/// `sigma` run on a gamma clock whose variance rate is `nu`. `theta` skews the returns and
+/// `nu` fattens their tails. The quanto adjustment uses `sigma`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VarianceGammaModel {
+    pub spot: f64,
+    pub strike: f64,
+    pub domestic_rate: f64,
+    pub foreign_rate: f64,
+    pub sigma: f64,
+    pub nu: f64,
+    pub theta: f64,
+    pub time_to_maturity: f64,
+    pub fx_volatility: f64,
+    pub correlation: f64,
+}
+
+
+impl MertonModel {
+    fn carry(&self) -> f64 {
+        quanto_carry(self.domestic_rate, self.foreign_rate, self.volatility, self.fx_volatility, self.correlation)
+    }
+
+    /// Drift correction that makes e^{-carry t} S_t a martingale.
+    fn compensator(&self) -> f64 {
+        let jump = (self.jump_mean + 0.5 * self.jump_volatility * self.jump_volatility).exp() - 1.0;
+        -0.5 * self.volatility * self.volatility - self.jump_intensity * jump
+    }
+
+    /// Takes the domestic and foreign rates from curves at the option's maturity.
+    pub fn with_curves(mut self, domestic: &YieldCurve, foreign: &YieldCurve) -> Self {
+        self.domestic_rate = domestic.zero_rate(self.time_to_maturity);
+        self.foreign_rate = foreign.zero_rate(self.time_to_maturity);
+        self
+    }
+
+    pub fn characteristic_function(&self, u: f64) -> Complex<f64> {
+        self.log_characteristic(Complex::new(u, 0.0))
+    }
+
+    pub fn calculate_price_fft(&self) -> OptionPrice {
+        price_fft(self, self.strike, &FftSettings::default())
+    }
+
+    pub fn price_strikes_fft(&self, strikes: &[f64], settings: &FftSettings) -> Vec<Option<OptionPrice>> {
+        price_strikes_fft(self, strikes, settings)
+    }
+
+    pub fn price_with(&self, engine: &PricingEngine) -> OptionPrice {
+        engine.price(self, self.strike)
+    }
+}
+
+impl CharacteristicModel for MertonModel {
+    fn log_characteristic(&self, u: Complex<f64>) -> Complex<f64> {
+        let i = Complex::new(0.0, 1.0);
+        let t = self.time_to_maturity;
+        let delta2 = self.jump_volatility * self.jump_volatility;
+        let jumps = self.jump_intensity * ((i * u * self.jump_mean - 0.5 * delta2 * u * u).exp() - 1.0);
+        let exponent = -0.5 * self.volatility * self.volatility * u * u + jumps;
+
+        (i * u * (self.spot.ln() + (self.carry() + self.compensator()) * t) + exponent * t).exp()
+    }
+
+    fn domestic_rate(&self) -> f64 {
+        self.domestic_rate
+    }
+
+    fn time_to_maturity(&self) -> f64 {
+        self.time_to_maturity
+    }
+
+    fn forward(&self) -> f64 {
+        self.spot * (self.carry() * self.time_to_maturity).exp()
+    }
+}
+
+impl TerminalSampler for MertonModel {
+    /// Exact in one step: the jumps' sum is normal given their Poisson count.
+    fn sample_terminal(&self, rng: &mut StdRng, _steps: usize) -> f64 {
+        let t = self.time_to_maturity;
+        let count = if self.jump_intensity > 0.0 {
+            rng.sample(Poisson::new(self.jump_intensity * t).unwrap())
+        } else {
+            0.0
+        };
+        let z: f64 = rng.sample(StandardNormal);
+        let jump_z: f64 = rng.sample(StandardNormal);
+
+        let log_return = (self.carry() + self.compensator()) * t
+            + self.volatility * t.sqrt() * z
+            + count * self.jump_mean
+            + self.jump_volatility * count.sqrt() * jump_z;
+        self.spot * log_return.exp()
+    }
+}
+
+
+impl VarianceGammaModel {
+    fn carry(&self) -> f64 {
+        quanto_carry(self.domestic_rate, self.foreign_rate, self.sigma, self.fx_volatility, self.correlation)
+    }
+
+    /// Drift correction that makes e^{-carry t} S_t a martingale. Finite only while
+    /// θν + σ²ν/2 < 1, which bounds the exponential moment.
+    fn compensator(&self) -> f64 {
+        (1.0 - self.theta * self.nu - 0.5 * self.sigma * self.sigma * self.nu).ln() / self.nu
+    }
+
+    /// Takes the domestic and foreign rates from curves at the option's maturity.
+    pub fn with_curves(mut self, domestic: &YieldCurve, foreign: &YieldCurve) -> Self {
+        self.domestic_rate = domestic.zero_rate(self.time_to_maturity);
+        self.foreign_rate = foreign.zero_rate(self.time_to_maturity);
+        self
+    }
+
+    pub fn characteristic_function(&self, u: f64) -> Complex<f64> {
+        self.log_characteristic(Complex::new(u, 0.0))
+    }
+
+    pub fn calculate_price_fft(&self) -> OptionPrice {
+        price_fft(self, self.strike, &FftSettings::default())
+    }
+
+    pub fn price_strikes_fft(&self, strikes: &[f64], settings: &FftSettings) -> Vec<Option<OptionPrice>> {
+        price_strikes_fft(self, strikes, settings)
+    }
+
+    pub fn price_with(&self, engine: &PricingEngine) -> OptionPrice {
+        engine.price(self, self.strike)
+    }
+}
+
+impl CharacteristicModel for VarianceGammaModel {
+    fn log_characteristic(&self, u: Complex<f64>) -> Complex<f64> {
+        let i = Complex::new(0.0, 1.0);
+        let t = self.time_to_maturity;
+        let base = 1.0 - i * u * self.theta * self.nu + 0.5 * self.sigma * self.sigma * self.nu * u * u;
+        let log_clock = -(t / self.nu) * base.ln();
+
+        (i * u * (self.spot.ln() + (self.carry() + self.compensator()) * t) + log_clock).exp()
+    }
+
+    fn domestic_rate(&self) -> f64 {
+        self.domestic_rate
+    }
+
+    fn time_to_maturity(&self) -> f64 {
+        self.time_to_maturity
+    }
+
+    fn forward(&self) -> f64 {
+        self.spot * (self.carry() * self.time_to_maturity).exp()
+    }
+}
+
+impl TerminalSampler for VarianceGammaModel {
+    /// Exact in one step: Brownian motion sampled at a gamma-distributed time.
+    fn sample_terminal(&self, rng: &mut StdRng, _steps: usize) -> f64 {
+        let t = self.time_to_maturity;
+        let clock: f64 = rng.sample(Gamma::new(t / self.nu, self.nu).unwrap());
+        let z: f64 = rng.sample(StandardNormal);
+
+        let log_return = (self.carry() + self.compensator()) * t + self.theta * clock + self.sigma * clock.sqrt() * z;
+        self.spot * log_return.exp()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cos::CosSettings;
+    use crate::implied_vol::ImpliedVolatility;
+    use crate::monte_carlo::McSettings;
+
+    fn merton(strike: f64) -> MertonModel {
+        MertonModel {
+            spot: 100.0,
+            strike,
+            domestic_rate: 0.05,
+            foreign_rate: 0.0,
+            volatility: 0.4,
+            jump_intensity: 2.0,
+            jump_mean: -0.1,
+            jump_volatility: 0.15,
+            time_to_maturity: 0.5,
+            fx_volatility: 0.0,
+            correlation: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_merton_matches_poisson_series() {
+        // Merton's formula: Black–Scholes prices weighted by the number of jumps
+        let model = merton(110.0);
+        let t = model.time_to_maturity;
+        let k = (model.jump_mean + 0.5 * model.jump_volatility * model.jump_volatility).exp() - 1.0;
+        let intensity = model.jump_intensity * (1.0 + k);
+        let mut expected = 0.0;
+        let mut weight = (-intensity * t).exp();
+        for n in 0..40 {
+            if n > 0 {
+                weight *= intensity * t / n as f64;
+            }
+            let nf = n as f64;
+            let vol = (model.volatility.powi(2) + nf * model.jump_volatility.powi(2) / t).sqrt();
+            let rate = model.domestic_rate - model.jump_intensity * k + nf * (1.0 + k).ln() / t;
+            let problem = ImpliedVolatility { spot: 100.0, strike: 110.0, r: rate, time_to_maturity: t, market_price: 0.0, is_call: true, dividend_yield: 0.0 };
+            expected += weight * problem.black_scholes_price(vol);
+        }
+
+        let price = model.calculate_price_fft();
+        assert!((price.call - expected).abs() < 1e-5, "{} vs {}", price.call, expected);
+        let cos = model.price_with(&PricingEngine::Cos(CosSettings::default()));
+        assert!((cos.call - expected).abs() < 1e-5);
+        assert!((model.characteristic_function(0.0) - Complex::new(1.0, 0.0)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_variance_gamma_reference_prices() {
+        // Fang & Oosterlee (2008), with reference values 10.993703187 and 19.099354724
+        for (t, reference) in [(0.1, 10.993703187), (1.0, 19.099354724)] {
+            let model = VarianceGammaModel {
+                spot: 100.0,
+                strike: 90.0,
+                domestic_rate: 0.1,
+                foreign_rate: 0.0,
+                sigma: 0.12,
+                nu: 0.2,
+                theta: -0.14,
+                time_to_maturity: t,
+                fx_volatility: 0.0,
+                correlation: 0.0,
+            };
+            let cos = model.price_with(&PricingEngine::Cos(CosSettings { n: 1024, ..CosSettings::default() }));
+            assert!((cos.call - reference).abs() < 1e-5, "T {}: {}", t, cos.call);
+            let fft = model.calculate_price_fft();
+            assert!((fft.call - reference).abs() < 1e-3, "T {}: {}", t, fft.call);
+        }
+    }
+
+    #[test]
+    fn test_monte_carlo_agrees_and_tails_are_fat() {
+        let model = merton(100.0);
+        let settings = McSettings { paths: 100_000, steps: 1, seed: 17 };
+        let exact = model.calculate_price_fft();
+        let simulated = model.price_with(&PricingEngine::MonteCarlo(settings));
+        assert!((simulated.call - exact.call).abs() < 4.0 * simulated.diagnostics.unwrap().std_error);
+
+        let vg = VarianceGammaModel { spot: 100.0, strike: 100.0, domestic_rate: 0.05, foreign_rate: 0.0, sigma: 0.5, nu: 0.3, theta: -0.3, time_to_maturity: 0.5, fx_volatility: 0.0, correlation: 0.0 };
+        let simulated = vg.price_with(&PricingEngine::MonteCarlo(settings));
+        assert!((simulated.put - vg.calculate_price_fft().put).abs() < 4.0 * simulated.diagnostics.unwrap().std_error);
+
+        // Negative jumps give a downward skew: low strikes trade at higher implied vols
+        let vol = |strike: f64| {
+            let price = merton(strike).calculate_price_fft();
+            ImpliedVolatility { spot: 100.0, strike, r: 0.05, time_to_maturity: 0.5, market_price: price.put, is_call: false, dividend_yield: 0.0 }
+                .implied_volatility().unwrap()
+        };
+        assert!(vol(70.0) > vol(100.0) + 0.01 && vol(100.0) > vol(130.0));
+    }
+}
@@ -1,3 +1,59 @@
-mod fourier;
-mod implied_vol;
-mod data;
+pub mod builder;
+pub mod error;
+pub mod curve;
+pub mod fourier;
+pub mod forward;
+pub mod cos;
+pub mod heston;
+pub mod jumps;
+pub mod monte_carlo;
+pub mod engine;
+pub mod greeks;
+pub mod chain;
+pub mod smile;
+pub mod validation;
+pub mod barrier;
+pub mod asian;
+pub mod basket;
+pub mod optimize;
+pub mod vol_surface;
+pub mod sabr;
+pub mod variance_swap;
+pub mod realized;
+pub mod hedging;
+pub mod scenarios;
+pub mod margin;
+pub mod calibration;
+pub mod implied_vol;
+pub mod bachelier;
+#[cfg(feature = "network")]
+pub mod fetch;
+pub mod data;
+#[cfg(feature = "network")]
+pub mod provider;
+#[cfg(feature = "network")]
+pub mod binance;
+#[cfg(feature = "network")]
+pub mod okx;
+#[cfg(feature = "network")]
+pub mod history;
+#[cfg(feature = "network")]
+pub mod candles;
+#[cfg(feature = "network")]
+pub mod query;
+#[cfg(feature = "network")]
+pub mod account;
+#[cfg(feature = "network")]
+pub mod instrument;
+#[cfg(feature = "network")]
+pub mod replay;
+#[cfg(feature = "network")]
+pub mod freshness;
+#[cfg(feature = "network")]
+pub mod stream;
+#[cfg(feature = "network")]
+pub mod price_feed;
+#[cfg(feature = "network")]
+pub mod live_surface;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -0,0 +1,269 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use crate::curve::YieldCurve;
+use crate::data::DeribitOptionData;
+use crate::freshness::now_millis;
+use crate::stream::{Channel, DeribitStream, StreamSettings, StreamUpdate, TickerUpdate};
+use crate::vol_surface::{MILLIS_PER_YEAR, SviSlice, VolSurface};
+use crate::error::PricingError;
+
+
+/// An immutable surface as of one refit. Pricers hold the `Arc` for as long as they need
+/// a consistent view; later refits publish new snapshots rather than changing this one.
+#[derive(Debug, Clone)]
+pub struct SurfaceSnapshot {
+    /// Increases by one with every published refit.
+    pub version: u64,
+    /// When the refit ran, in Unix milliseconds.
+    pub as_of: u64,
+    pub surface: VolSurface,
+    /// Expiry timestamp of each of `surface.slices`, in the same order.
+    pub expiries: Vec<u64>,
+}
+
+struct State {
+    spot: f64,
+    quotes: BTreeMap<u64, HashMap<String, DeribitOptionData>>,
+    /// Last good fit per expiry timestamp.
+    slices: BTreeMap<u64, SviSlice>,
+    dirty: BTreeSet<u64>,
+    failures: BTreeMap<u64, PricingError>,
+}
+
+
+/// Keeps an SVI surface fitted to streaming option quotes. Ticks only mark their expiry
+/// for refitting; [`LiveSurface::refit`] refits just those smiles and publishes a new
+/// [`SurfaceSnapshot`].
+pub struct LiveSurface {
+    curve: YieldCurve,
+    state: Mutex<State>,
+    snapshot: RwLock<Option<Arc<SurfaceSnapshot>>>,
+    stream: Mutex<Option<DeribitStream>>,
+}
+
+impl LiveSurface {
+    /// Seeds the surface from a fetched chain, e.g. [`crate::provider::MarketDataProvider::option_chain`].
+    /// Nothing is fitted until the first [`LiveSurface::refit`].
+    pub fn new(chain: Vec<DeribitOptionData>, spot: f64, curve: YieldCurve) -> Self {
+        let mut quotes: BTreeMap<u64, HashMap<String, DeribitOptionData>> = BTreeMap::new();
+        for option in chain {
+            quotes.entry(option.expiration_timestamp).or_default().insert(option.instrument_name.clone(), option);
+        }
+        let dirty = quotes.keys().copied().collect();
+        let state = State { spot, quotes, slices: BTreeMap::new(), dirty, failures: BTreeMap::new() };
+        LiveSurface { curve, state: Mutex::new(state), snapshot: RwLock::new(None), stream: Mutex::new(None) }
+    }
+
+    /// The latest published surface, or `None` before any expiry has been fitted.
+    pub fn snapshot(&self) -> Option<Arc<SurfaceSnapshot>> {
+        self.snapshot.read().unwrap().clone()
+    }
+
+    pub fn version(&self) -> u64 {
+        self.snapshot().map_or(0, |snapshot| snapshot.version)
+    }
+
+    /// Expiries whose last refit failed, with why; they keep their previous smile if any.
+    pub fn failures(&self) -> BTreeMap<u64, PricingError> {
+        self.state.lock().unwrap().failures.clone()
+    }
+
+    /// Ticker channels for every tracked option.
+    pub fn channels(&self) -> Vec<Channel> {
+        self.state.lock().unwrap().quotes.values()
+            .flat_map(|options| options.keys())
+            .map(|name| Channel::ticker(name))
+            .collect()
+    }
+
+    /// Updates the quote `ticker` is for and marks its expiry for refitting; the index
+    /// price it carries becomes the spot. Returns whether the instrument is tracked.
+    pub fn apply(&self, ticker: &TickerUpdate) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if let Some(index) = ticker.index_price.filter(|index| *index > 0.0) {
+            state.spot = index;
+        }
+        let expiry = state.quotes.iter_mut()
+            .find_map(|(expiry, options)| options.get_mut(&ticker.instrument_name).map(|option| (expiry, option)))
+            .and_then(|(expiry, option)| ticker.apply(option).then_some(*expiry));
+        match expiry {
+            Some(expiry) => {
+                state.dirty.insert(expiry);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Refits the smiles of expiries that ticked since the last refit, re-anchors the rest
+    /// at the current spot and `now`, and publishes the result. Returns the new version, or
+    /// `None` if nothing changed or no expiry could be fitted.
+    pub fn refit(&self, now: u64) -> Option<u64> {
+        let mut state = self.state.lock().unwrap();
+        let State { spot, quotes, slices, dirty, failures } = &mut *state;
+        if dirty.is_empty() {
+            return None;
+        }
+        slices.retain(|expiry, _| *expiry > now);
+        for expiry in std::mem::take(dirty) {
+            if expiry <= now {
+                continue;
+            }
+            let years = (expiry - now) as f64 / MILLIS_PER_YEAR;
+            let points: Vec<(f64, f64)> = quotes.get(&expiry).into_iter()
+                .flat_map(|options| options.values())
+                .filter_map(|option| option.implied_volatility.filter(|iv| *iv > 0.0).map(|iv| (option.strike, iv / 100.0)))
+                .collect();
+            match SviSlice::fit(years, *spot / self.curve.discount(years), &points) {
+                Ok(slice) => {
+                    slices.insert(expiry, slice);
+                    failures.remove(&expiry);
+                }
+                Err(error) => {
+                    failures.insert(expiry, error);
+                }
+            }
+        }
+        if slices.is_empty() {
+            return None;
+        }
+
+        // Smiles that did not tick keep their total variance at each log-moneyness
+        let surface_slices = slices.iter()
+            .map(|(expiry, slice)| {
+                let years = (expiry - now) as f64 / MILLIS_PER_YEAR;
+                SviSlice { expiry: years, forward: *spot / self.curve.discount(years), ..slice.clone() }
+            })
+            .collect();
+        let version = self.version() + 1;
+        let snapshot = SurfaceSnapshot {
+            version,
+            as_of: now,
+            surface: VolSurface { spot: *spot, curve: self.curve.clone(), slices: surface_slices },
+            expiries: slices.keys().copied().collect(),
+        };
+        *self.snapshot.write().unwrap() = Some(Arc::new(snapshot));
+        Some(version)
+    }
+
+    /// Applies tickers from `updates`, refitting at most once per `refit_interval`, until
+    /// the stream ends.
+    pub async fn run(&self, mut updates: mpsc::Receiver<StreamUpdate>, refit_interval: Duration) {
+        let mut ticks = tokio::time::interval(refit_interval);
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                update = updates.recv() => match update {
+                    Some(StreamUpdate::Ticker(ticker)) => {
+                        self.apply(&ticker);
+                    }
+                    Some(_) => {}
+                    None => break,
+                },
+                _ = ticks.tick() => {
+                    self.refit(now_millis());
+                }
+            }
+        }
+        self.refit(now_millis());
+    }
+
+    /// Streams tickers for every tracked option in the background.
+    pub fn start(self: &Arc<Self>, settings: StreamSettings, refit_interval: Duration) -> JoinHandle<()> {
+        let (stream, updates) = DeribitStream::connect(self.channels(), settings);
+        *self.stream.lock().unwrap() = Some(stream);
+        let surface = self.clone();
+        tokio::spawn(async move { surface.run(updates, refit_interval).await })
+    }
+
+    /// Closes the connection started by [`LiveSurface::start`].
+    pub async fn stop(&self) {
+        let stream = self.stream.lock().unwrap().take();
+        if let Some(stream) = stream {
+            stream.close().await;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vol_surface::SviParams;
+
+    const NOW: u64 = 1_700_000_000_000;
+    const SHORT: SviParams = SviParams { a: 0.01, b: 0.1, rho: -0.4, m: 0.02, sigma: 0.2 };
+    const LONG: SviParams = SviParams { a: 0.04, b: 0.15, rho: -0.3, m: 0.05, sigma: 0.3 };
+
+    fn expiry(years: f64) -> u64 {
+        NOW + (years * MILLIS_PER_YEAR) as u64
+    }
+
+    fn chain(params: &SviParams, years: f64) -> Vec<DeribitOptionData> {
+        [0.6, 0.75, 0.9, 1.0, 1.1, 1.25, 1.5, 1.8].iter()
+            .map(|moneyness| {
+                let strike = 100.0 * moneyness;
+                let vol = (params.total_variance(f64::ln(*moneyness)) / years).sqrt();
+                serde_json::from_value(serde_json::json!({
+                    "instrument_name": format!("X-{}-{}-C", years, strike), "strike": strike, "expiration_timestamp": expiry(years),
+                    "option_type": "call", "price_index": "btc_usd", "settlement_currency": "BTC", "implied_volatility": 100.0 * vol,
+                    "market_price": null, "delta": null, "gamma": null, "vega": null, "theta": null,
+                })).unwrap()
+            })
+            .collect()
+    }
+
+    fn ticker(instrument_name: &str, mark_iv: f64) -> TickerUpdate {
+        TickerUpdate {
+            instrument_name: instrument_name.to_string(),
+            timestamp: NOW,
+            mark_price: 0.0,
+            mark_iv: Some(mark_iv),
+            index_price: Some(100.0),
+            underlying_price: None,
+            best_bid_price: None,
+            best_ask_price: None,
+            greeks: None,
+            received_at: None,
+        }
+    }
+
+    #[test]
+    fn test_ticks_refit_only_their_expiry() {
+        let mut quotes = chain(&SHORT, 0.25);
+        quotes.extend(chain(&LONG, 1.0));
+        let live = LiveSurface::new(quotes, 100.0, YieldCurve::flat(0.0));
+        assert!(live.snapshot().is_none());
+        assert_eq!(live.channels().len(), 16);
+
+        assert_eq!(live.refit(NOW), Some(1));
+        assert_eq!(live.refit(NOW), None);
+        let first = live.snapshot().unwrap();
+        assert_eq!(first.expiries, vec![expiry(0.25), expiry(1.0)]);
+        assert!((first.surface.slices[0].params.rho - SHORT.rho).abs() < 1e-3);
+
+        // A wing lifts on the short expiry only
+        assert!(live.apply(&ticker("X-0.25-60-C", 80.0)));
+        assert!(!live.apply(&ticker("OTHER", 80.0)));
+        assert_eq!(live.refit(NOW), Some(2));
+        let second = live.snapshot().unwrap();
+        assert_ne!(second.surface.slices[0].params, first.surface.slices[0].params);
+        assert_eq!(second.surface.slices[1].params, first.surface.slices[1].params);
+        assert!(second.surface.implied_vol(60.0, 0.25) > first.surface.implied_vol(60.0, 0.25));
+        // Readers of the old snapshot are unaffected
+        assert_eq!(first.version, 1);
+    }
+
+    #[test]
+    fn test_thin_expiries_are_reported_and_skipped() {
+        let mut quotes = chain(&LONG, 1.0);
+        quotes.extend(chain(&SHORT, 0.25).into_iter().take(3));
+        let live = LiveSurface::new(quotes, 100.0, YieldCurve::flat(0.0));
+        assert_eq!(live.refit(NOW), Some(1));
+        assert_eq!(live.snapshot().unwrap().expiries, vec![expiry(1.0)]);
+        assert!(matches!(live.failures().get(&expiry(0.25)), Some(PricingError::InsufficientData(_))));
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use auction::margin::MarginEngine;
+use model::ids::BasketId;
+use model::model::Bid;
+use crate::engine::PricingEngine;
+use crate::scenarios::{OptionPosition, Shock, ShockGrid};
+
+
+/// A holding of the underlying itself; negative quantities are short.
+pub struct SpotPosition {
+    pub spot: f64,
+    pub quantity: f64,
+}
+
+
+/// Option and spot positions on one underlying, margined together so that hedges offset.
+#[derive(Default)]
+pub struct Portfolio {
+    pub options: Vec<OptionPosition>,
+    pub spots: Vec<SpotPosition>,
+}
+
+
+/// SPAN-style risk array parameters: spot moves of 0, ±1/3, ±2/3 and ±1 price scan ranges,
+/// each with volatility up and down by the vol scan range, plus two extreme spot moves of
+/// which only `extreme_cover` of the loss counts.
+#[derive(Debug, Clone, Copy)]
+pub struct RiskArray {
+    /// Relative spot move, e.g. 0.15 for ±15%.
+    pub price_scan_range: f64,
+    /// Absolute volatility move, e.g. 0.1 for ±10 vol points.
+    pub vol_scan_range: f64,
+    pub extreme_multiple: f64,
+    pub extreme_cover: f64,
+    /// Floor charged per short option, as a fraction of its underlying's spot.
+    pub short_option_minimum: f64,
+    /// Initial margin as a multiple of maintenance margin.
+    pub initial_multiplier: f64,
+}
+
+impl Default for RiskArray {
+    fn default() -> Self {
+        RiskArray {
+            price_scan_range: 0.15,
+            vol_scan_range: 0.1,
+            extreme_multiple: 2.0,
+            extreme_cover: 0.35,
+            short_option_minimum: 0.01,
+            initial_multiplier: 1.1,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MarginRequirement {
+    /// Largest weighted loss over the risk array.
+    pub scanning_risk: f64,
+    pub short_option_minimum: f64,
+    pub maintenance: f64,
+    pub initial: f64,
+    pub worst_scenario: Shock,
+}
+
+
+impl RiskArray {
+    /// Scanning grid and extreme-move grid, in that order.
+    pub fn grids(&self) -> (ShockGrid, ShockGrid) {
+        let range = self.price_scan_range;
+        let scanning = ShockGrid::new(
+            [-3.0, -2.0, -1.0, 0.0, 1.0, 2.0, 3.0].iter().map(|step| step / 3.0 * range).collect(),
+            vec![-self.vol_scan_range, self.vol_scan_range],
+            vec![0.0],
+        );
+        let extreme = ShockGrid::new(
+            vec![-self.extreme_multiple * range, self.extreme_multiple * range],
+            vec![0.0],
+            vec![0.0],
+        );
+        (scanning, extreme)
+    }
+
+    pub fn margin(&self, portfolio: &Portfolio, engine: &PricingEngine) -> MarginRequirement {
+        let spot_pnl = |shock: f64| -> f64 {
+            portfolio.spots.iter().map(|p| p.quantity * p.spot * shock).sum()
+        };
+
+        let (scanning, extreme) = self.grids();
+        let mut worst = (0.0, Shock::default());
+        for (grid, weight) in [(scanning, 1.0), (extreme, self.extreme_cover)] {
+            for matrix in grid.run(&portfolio.options, engine) {
+                for ((i, j), pnl) in matrix.pnl.indexed_iter() {
+                    let loss = -weight * (pnl + spot_pnl(matrix.spot_shocks[i]));
+                    if loss > worst.0 {
+                        worst = (loss, Shock { spot: matrix.spot_shocks[i], volatility: matrix.vol_shocks[j], correlation: 0.0 });
+                    }
+                }
+            }
+        }
+
+        let short_option_minimum: f64 = portfolio.options.iter()
+            .filter(|p| p.quantity < 0.0)
+            .map(|p| -p.quantity * p.option.spot * self.short_option_minimum)
+            .sum();
+        let maintenance = worst.0.max(short_option_minimum);
+
+        MarginRequirement {
+            scanning_risk: worst.0,
+            short_option_minimum,
+            maintenance,
+            initial: maintenance * self.initial_multiplier,
+            worst_scenario: worst.1,
+        }
+    }
+}
+
+
+/// Auction margin for baskets that hold option positions. A bid must post at least its
+/// price; baskets whose portfolio can lose more than that require their risk-array margin
+/// per unit bid for instead. Baskets without a registered portfolio post their price.
+pub struct OptionBasketMargin {
+    pub risk_array: RiskArray,
+    pub engine: PricingEngine,
+    portfolios: HashMap<BasketId, Portfolio>,
+}
+
+impl OptionBasketMargin {
+    pub fn new(risk_array: RiskArray, engine: PricingEngine) -> Self {
+        OptionBasketMargin { risk_array, engine, portfolios: HashMap::new() }
+    }
+
+    /// Positions delivered per unit of `basket_id`.
+    pub fn set_portfolio(&mut self, basket_id: BasketId, portfolio: Portfolio) {
+        self.portfolios.insert(basket_id, portfolio);
+    }
+
+    pub fn requirement(&self, basket_id: BasketId) -> Option<MarginRequirement> {
+        self.portfolios.get(&basket_id).map(|portfolio| self.risk_array.margin(portfolio, &self.engine))
+    }
+}
+
+impl MarginEngine for OptionBasketMargin {
+    fn initial_margin(&self, bid: &Bid) -> f64 {
+        let units = bid.quantity.unwrap_or(1.0);
+        match self.requirement(bid.basket_id) {
+            Some(requirement) => bid.price.max(requirement.initial * units),
+            None => bid.price,
+        }
+    }
+
+    fn maintenance_margin(&self, bid: &Bid) -> f64 {
+        let units = bid.quantity.unwrap_or(1.0);
+        match self.requirement(bid.basket_id) {
+            Some(requirement) => bid.price.max(requirement.maintenance * units),
+            None => bid.price,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use auction::escrow::Escrow;
+    use model::model::{BidType, User};
+    use crate::cos::CosSettings;
+    use crate::fourier::QuantoOption;
+
+    fn option(strike: f64) -> QuantoOption {
+        QuantoOption {
+            spot: 2000.0,
+            strike,
+            domestic_rate: 0.03,
+            foreign_rate: 0.0,
+            volatility: 0.7,
+            fx_volatility: 0.0,
+            time_to_maturity: 0.1,
+            correlation: 0.0,
+            dividend_yield: 0.0,
+        }
+    }
+
+    fn engine() -> PricingEngine {
+        PricingEngine::Cos(CosSettings::default())
+    }
+
+    #[test]
+    fn test_naked_short_call_margin() {
+        let portfolio = Portfolio { options: vec![OptionPosition::new(option(2000.0), true, -1.0)], spots: vec![] };
+        let margin = RiskArray::default().margin(&portfolio, &engine());
+
+        // Worst case for a short call is spot and vol up
+        assert!(margin.worst_scenario.spot > 0.0 && margin.worst_scenario.volatility > 0.0);
+        assert!(margin.scanning_risk > margin.short_option_minimum);
+        assert_eq!(margin.maintenance, margin.scanning_risk);
+        assert!((margin.initial - 1.1 * margin.maintenance).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_hedge_reduces_margin() {
+        let naked = Portfolio { options: vec![OptionPosition::new(option(1800.0), true, -1.0)], spots: vec![] };
+        let covered = Portfolio {
+            options: vec![OptionPosition::new(option(1800.0), true, -1.0)],
+            spots: vec![SpotPosition { spot: 2000.0, quantity: 1.0 }],
+        };
+        let risk_array = RiskArray::default();
+        let naked = risk_array.margin(&naked, &engine());
+        let covered = risk_array.margin(&covered, &engine());
+
+        assert!(covered.maintenance < naked.maintenance);
+        // Deep out-of-the-money short options still pay the minimum
+        let far = Portfolio { options: vec![OptionPosition::new(option(20000.0), true, -2.0)], spots: vec![] };
+        let far = risk_array.margin(&far, &engine());
+        assert!((far.maintenance - 2.0 * 2000.0 * 0.01).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_escrow_locks_option_basket_margin() {
+        let mut margin = OptionBasketMargin::new(RiskArray::default(), engine());
+        margin.set_portfolio(BasketId(7), Portfolio { options: vec![OptionPosition::new(option(2000.0), true, -1.0)], spots: vec![] });
+        let requirement = margin.requirement(BasketId(7)).unwrap();
+        let mut escrow = Escrow::with_engine(Arc::new(margin));
+
+        let user = Arc::new(User::new(1, "Alice", 100000.0));
+        let option_bid = Bid::new(user.clone(), 7, BidType::XOR, 50.0, Some(2.0));
+        let plain_bid = Bid::new(user.clone(), 8, BidType::XOR, 500.0, Some(1.0));
+
+        assert!((escrow.lock(&option_bid).unwrap() - 2.0 * requirement.initial).abs() < 1e-9);
+        assert_eq!(escrow.lock(&plain_bid).unwrap(), 500.0);
+        assert_eq!(escrow.maintenance_shortfall(&option_bid), 0.0);
+    }
+}
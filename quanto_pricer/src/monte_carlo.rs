@@ -0,0 +1,138 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use auction::config::PricerConfig;
+use auction::sim::SimRng;
+use crate::fourier::{CharacteristicModel, OptionPrice, PricingDiagnostics};
+
+
+/// Path count, time steps per path and RNG seed. A fixed seed keeps prices reproducible.
+#[derive(Debug, Clone, Copy)]
+pub struct McSettings {
+    pub paths: usize,
+    pub steps: usize,
+    pub seed: u64,
+}
+
+impl Default for McSettings {
+    fn default() -> Self {
+        McSettings {
+            paths: 100_000,
+            steps: 252,
+            seed: 42,
+        }
+    }
+}
+
+impl From<&PricerConfig> for McSettings {
+    fn from(config: &PricerConfig) -> Self {
+        McSettings { paths: config.mc_paths, steps: config.mc_steps, seed: config.mc_seed }
+    }
+}
+
+impl McSettings {
+    pub fn rng(&self) -> StdRng {
+        StdRng::seed_from_u64(self.seed)
+    }
+
+    /// Takes the seed from a simulation's generator, so pricing inside a seeded run
+    /// replays with it.
+    pub fn seeded_by(mut self, rng: &mut SimRng) -> Self {
+        self.seed = rng.fork().next_u64();
+        self
+    }
+}
+
+
+/// Sample mean of discounted payoffs with its standard error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct McEstimate {
+    pub value: f64,
+    pub std_error: f64,
+    pub paths: usize,
+}
+
+impl McEstimate {
+    pub fn from_samples(samples: &[f64]) -> Self {
+        let n = samples.len();
+        if n == 0 {
+            return McEstimate { value: f64::NAN, std_error: f64::NAN, paths: 0 };
+        }
+        let mean = samples.iter().sum::<f64>() / n as f64;
+        let variance = if n > 1 {
+            samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1) as f64
+        } else {
+            0.0
+        };
+
+        McEstimate {
+            value: mean,
+            std_error: (variance / n as f64).sqrt(),
+            paths: n,
+        }
+    }
+
+    /// Interval of `z` standard errors around the estimate, e.g. z = 1.96 for 95%.
+    pub fn confidence_interval(&self, z: f64) -> (f64, f64) {
+        (self.value - z * self.std_error, self.value + z * self.std_error)
+    }
+}
+
+
+#[derive(Debug, Clone, Copy)]
+pub struct McOptionPrice {
+    pub call: McEstimate,
+    pub put: McEstimate,
+}
+
+impl McOptionPrice {
+    pub fn to_option_price(&self) -> OptionPrice {
+        OptionPrice::new(self.call.value, self.put.value).with_diagnostics(PricingDiagnostics {
+            grid_size: self.call.paths,
+            std_error: self.call.std_error.max(self.put.std_error),
+            ..Default::default()
+        })
+    }
+}
+
+
+/// A model that can simulate the terminal price of its underlying.
+pub trait TerminalSampler {
+    fn sample_terminal(&self, rng: &mut StdRng, steps: usize) -> f64;
+}
+
+
+/// European call and put from simulated terminal prices.
+pub fn price_european<M: CharacteristicModel + TerminalSampler + ?Sized>(model: &M, strike: f64, settings: &McSettings) -> McOptionPrice {
+    let mut rng = settings.rng();
+    let discount = model.discount();
+    let mut calls = Vec::with_capacity(settings.paths);
+    let mut puts = Vec::with_capacity(settings.paths);
+
+    for _ in 0..settings.paths {
+        let terminal = model.sample_terminal(&mut rng, settings.steps);
+        calls.push(discount * (terminal - strike).max(0.0));
+        puts.push(discount * (strike - terminal).max(0.0));
+    }
+
+    McOptionPrice {
+        call: McEstimate::from_samples(&calls),
+        put: McEstimate::from_samples(&puts),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_from_samples() {
+        let estimate = McEstimate::from_samples(&[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(estimate.value, 2.5);
+        assert!((estimate.std_error - (5.0f64 / 3.0 / 4.0).sqrt()).abs() < 1e-12);
+
+        let (low, high) = estimate.confidence_interval(2.0);
+        assert!(low < 2.5 && high > 2.5);
+        assert!(McEstimate::from_samples(&[]).value.is_nan());
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::Value;
+use model::model::Asset;
+use crate::candles::{Granularity, candle_rows};
+use crate::fetch::{FetchError, FetchReport, FetchSettings, RateLimiter, get_json};
+use crate::freshness::now_millis;
+use crate::history::TimeRange;
+use crate::provider::{MarketDataProvider, OptionQuote, OrderBook, levels, malformed, number};
+use crate::realized::Candle;
+
+pub const OKX_API_URL: &str = "https://www.okx.com";
+/// Largest page of historical candles.
+const CANDLES_PER_PAGE: usize = 100;
+
+
+/// OKX: instruments are named like "BTC-USDT" (spot) and "BTC-USD-241227-60000-C"
+/// (options, settled in the base coin like Deribit's).
+#[derive(Debug)]
+pub struct OkxProvider {
+    settings: FetchSettings,
+    client: Client,
+    limiter: RateLimiter,
+}
+
+impl OkxProvider {
+    pub fn new(settings: FetchSettings) -> Self {
+        let limiter = settings.rate_limiter();
+        OkxProvider { settings, client: Client::new(), limiter }
+    }
+
+    /// The `data` array of a response, or its message when `code` reports an error.
+    async fn get(&self, path: &str) -> Result<Vec<Value>, FetchError> {
+        let url = format!("{}{}", self.settings.base_url, path);
+        let response = get_json(&self.client, &url, &self.limiter, &self.settings.retry).await?;
+        match response.get("code").and_then(Value::as_str) {
+            Some("0") => response.get("data").and_then(Value::as_array).cloned().ok_or_else(|| malformed("data")),
+            Some(code) => {
+                let message = response.get("msg").and_then(Value::as_str).unwrap_or("");
+                Err(FetchError::Api(format!("{} {}", code, message)))
+            }
+            None => Err(malformed("status")),
+        }
+    }
+}
+
+impl Default for OkxProvider {
+    fn default() -> Self {
+        OkxProvider::new(FetchSettings { base_url: OKX_API_URL.to_string(), ..FetchSettings::default() })
+    }
+}
+
+#[async_trait]
+impl MarketDataProvider for OkxProvider {
+    fn venue(&self) -> &'static str {
+        "okx"
+    }
+
+    async fn spot_price(&self, asset: &Asset) -> Result<f64, FetchError> {
+        let data = self.get(&format!("/api/v5/market/ticker?instId={}-{}", asset.base, asset.quote)).await?;
+        data.first().and_then(|ticker| ticker.get("last")).and_then(number).ok_or_else(|| malformed("ticker"))
+    }
+
+    /// Options on `underlying`-USD, with marks and Black–Scholes greeks from two bulk requests.
+    async fn option_chain(&self, underlying: &str) -> Result<FetchReport<OptionQuote>, FetchError> {
+        let family = format!("{}-USD", underlying.to_uppercase());
+        let instruments = self.get(&format!("/api/v5/public/instruments?instType=OPTION&uly={}", family)).await?;
+        let summaries = self.get(&format!("/api/v5/public/opt-summary?uly={}", family)).await?;
+        let marks = self.get(&format!("/api/v5/public/mark-price?instType=OPTION&uly={}", family)).await?;
+        let by_name = |rows: &[Value]| -> HashMap<String, Value> {
+            rows.iter()
+                .filter_map(|row| Some((row.get("instId")?.as_str()?.to_string(), row.clone())))
+                .collect()
+        };
+        let (summaries, marks) = (by_name(&summaries), by_name(&marks));
+
+        let received_at = now_millis();
+        let data = instruments.iter()
+            .filter_map(|instrument| {
+                let name = instrument.get("instId")?.as_str()?;
+                let summary = summaries.get(name);
+                let greek = |key: &str| summary.and_then(|summary| summary.get(key)).and_then(number);
+                Some(OptionQuote {
+                    instrument_name: name.to_string(),
+                    strike: instrument.get("stk").and_then(number)?,
+                    expiration_timestamp: instrument.get("expTime")?.as_str()?.parse().ok()?,
+                    option_type: if instrument.get("optType")?.as_str()? == "C" { "call" } else { "put" }.to_string(),
+                    price_index: family.replace('-', "_").to_lowercase(),
+                    settlement_currency: instrument.get("settleCcy").and_then(Value::as_str).unwrap_or(underlying).to_string(),
+                    implied_volatility: greek("markVol").map(|vol| 100.0 * vol),
+                    market_price: marks.get(name).and_then(|mark| mark.get("markPx")).and_then(number),
+                    delta: greek("deltaBS"),
+                    gamma: greek("gammaBS"),
+                    vega: greek("vegaBS"),
+                    theta: greek("thetaBS"),
+                    open_interest: None,
+                    exchange_timestamp: summary.and_then(|summary| summary.get("ts")).and_then(Value::as_str).and_then(|ts| ts.parse().ok()),
+                    received_at: Some(received_at),
+                })
+            })
+            .collect();
+        Ok(FetchReport { data, failures: Vec::new() })
+    }
+
+    async fn order_book(&self, instrument_name: &str, depth: usize) -> Result<OrderBook, FetchError> {
+        let data = self.get(&format!("/api/v5/market/books?instId={}&sz={}", instrument_name, depth)).await?;
+        let book = data.first().ok_or_else(|| malformed("order book"))?;
+        Ok(OrderBook {
+            instrument_name: instrument_name.to_string(),
+            timestamp: book.get("ts").and_then(Value::as_str).and_then(|ts| ts.parse().ok()),
+            bids: levels(book.get("bids")),
+            asks: levels(book.get("asks")),
+            received_at: Some(now_millis()),
+        })
+    }
+
+    /// Pages backwards from the end of `range`, since OKX returns the newest bars first.
+    async fn candles(&self, instrument_name: &str, granularity: Granularity, range: TimeRange) -> Result<Vec<Candle>, FetchError> {
+        let mut candles = Vec::new();
+        let mut after = range.end;
+        while after > range.start {
+            let path = format!(
+                "/api/v5/market/history-candles?instId={}&bar={}&after={}&before={}&limit={}",
+                instrument_name, granularity.okx_bar(), after, range.start.saturating_sub(1), CANDLES_PER_PAGE,
+            );
+            let page = candle_rows(&self.get(&path).await?);
+            let full = page.len() >= CANDLES_PER_PAGE;
+            match page.iter().map(|candle| candle.timestamp).min() {
+                Some(oldest) if oldest < after => after = oldest,
+                _ => break,
+            }
+            candles.extend(page);
+            if !full {
+                break;
+            }
+        }
+        candles.retain(|candle| range.contains(candle.timestamp));
+        candles.sort_by_key(|candle| candle.timestamp);
+        candles.dedup_by_key(|candle| candle.timestamp);
+        Ok(candles)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+    use crate::fetch::mock;
+
+    async fn provider() -> OkxProvider {
+        let (base_url, _) = mock::serve(Duration::ZERO, |path, _| {
+            let data = match path.split('?').next().unwrap() {
+                "/api/v5/market/ticker" if path.contains("BTC-USDT") => r#"[{"instId":"BTC-USDT","last":"64010.1","ts":"1700000000000"}]"#,
+                "/api/v5/market/ticker" => return (200, r#"{"code":"51001","msg":"Instrument ID does not exist","data":[]}"#.to_string()),
+                "/api/v5/market/books" => r#"[{"asks":[["0.061","10","0","2"]],"bids":[["0.059","8","0","1"]],"ts":"1700000000123"}]"#,
+                "/api/v5/public/instruments" => r#"[
+                    {"instId":"BTC-USD-241227-60000-C","uly":"BTC-USD","stk":"60000","expTime":"1735286400000","optType":"C","settleCcy":"BTC"},
+                    {"instId":"BTC-USD-241227-60000-P","uly":"BTC-USD","stk":"60000","expTime":"1735286400000","optType":"P","settleCcy":"BTC"}]"#,
+                "/api/v5/public/opt-summary" => r#"[{"instId":"BTC-USD-241227-60000-P","markVol":"0.5","deltaBS":"-0.38","gammaBS":"0.00002","thetaBS":"-35","vegaBS":"118"}]"#,
+                "/api/v5/public/mark-price" => r#"[{"instId":"BTC-USD-241227-60000-P","markPx":"0.045"}]"#,
+                _ => return (404, String::new()),
+            };
+            (200, format!(r#"{{"code":"0","msg":"","data":{}}}"#, data))
+        }).await;
+        OkxProvider::new(FetchSettings { base_url, ..FetchSettings::default() })
+    }
+
+    #[test]
+    fn test_okx_spot_book_and_api_errors() {
+        Runtime::new().unwrap().block_on(async {
+            let provider = provider().await;
+            assert_eq!(provider.spot_price(&Asset::new("BTC", "USDT")).await, Ok(64010.1));
+            assert!(matches!(provider.spot_price(&Asset::new("XYZ", "USDT")).await, Err(FetchError::Api(message)) if message.starts_with("51001")));
+            let book = provider.order_book("BTC-USD-241227-60000-P", 1).await.unwrap();
+            assert_eq!((book.mid(), book.timestamp), (Some(0.06), Some(1700000000123)));
+        });
+    }
+
+    #[test]
+    fn test_okx_chain_joins_marks_and_greeks() {
+        Runtime::new().unwrap().block_on(async {
+            let chain = provider().await.option_chain("btc").await.unwrap();
+            assert_eq!(chain.data.len(), 2);
+            assert_eq!(chain.data[0].market_price, None);
+            let put = &chain.data[1];
+            assert_eq!((put.option_type.as_str(), put.settlement_currency.as_str(), put.price_index.as_str()), ("put", "BTC", "btc_usd"));
+            assert_eq!((put.market_price, put.implied_volatility, put.delta), (Some(0.045), Some(50.0), Some(-0.38)));
+        });
+    }
+}
@@ -0,0 +1,243 @@
+/// Result of a derivative-free minimisation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Minimum {
+    pub x: Vec<f64>,
+    pub value: f64,
+    pub iterations: usize,
+    pub converged: bool,
+}
+
+
+/// Nelder–Mead simplex minimisation starting from `start`, with initial simplex edges
+/// of length `step` along each axis. Stops once the spread of the simplex values falls
+/// below `tolerance`.
+pub fn nelder_mead<F: Fn(&[f64]) -> f64>(
+    f: F,
+    start: &[f64],
+    step: &[f64],
+    tolerance: f64,
+    max_iterations: usize,
+) -> Minimum {
+    let n = start.len();
+    let mut simplex: Vec<Vec<f64>> = vec![start.to_vec()];
+    for i in 0..n {
+        let mut vertex = start.to_vec();
+        vertex[i] += step[i];
+        simplex.push(vertex);
+    }
+    let mut values: Vec<f64> = simplex.iter().map(|x| f(x)).collect();
+
+    let mut iterations = 0;
+    let mut converged = false;
+    while iterations < max_iterations {
+        iterations += 1;
+
+        let mut order: Vec<usize> = (0..=n).collect();
+        order.sort_by(|a, b| values[*a].partial_cmp(&values[*b]).unwrap_or(std::cmp::Ordering::Equal));
+        simplex = order.iter().map(|i| simplex[*i].clone()).collect();
+        values = order.iter().map(|i| values[*i]).collect();
+
+        if (values[n] - values[0]).abs() < tolerance {
+            converged = true;
+            break;
+        }
+
+        let centroid: Vec<f64> = (0..n)
+            .map(|j| simplex[..n].iter().map(|x| x[j]).sum::<f64>() / n as f64)
+            .collect();
+        let along = |t: f64| -> Vec<f64> {
+            centroid.iter().zip(&simplex[n]).map(|(c, w)| c + t * (w - c)).collect()
+        };
+
+        let reflected = along(-1.0);
+        let reflected_value = f(&reflected);
+        if reflected_value < values[0] {
+            let expanded = along(-2.0);
+            let expanded_value = f(&expanded);
+            if expanded_value < reflected_value {
+                simplex[n] = expanded;
+                values[n] = expanded_value;
+            } else {
+                simplex[n] = reflected;
+                values[n] = reflected_value;
+            }
+        } else if reflected_value < values[n - 1] {
+            simplex[n] = reflected;
+            values[n] = reflected_value;
+        } else {
+            let contracted = if reflected_value < values[n] { along(-0.5) } else { along(0.5) };
+            let contracted_value = f(&contracted);
+            if contracted_value < values[n].min(reflected_value) {
+                simplex[n] = contracted;
+                values[n] = contracted_value;
+            } else {
+                // Shrink towards the best vertex
+                for i in 1..=n {
+                    simplex[i] = simplex[0].iter().zip(&simplex[i]).map(|(b, x)| b + 0.5 * (x - b)).collect();
+                    values[i] = f(&simplex[i]);
+                }
+            }
+        }
+    }
+
+    let best = (0..=n)
+        .min_by(|a, b| values[*a].partial_cmp(&values[*b]).unwrap_or(std::cmp::Ordering::Equal))
+        .unwrap_or(0);
+    Minimum {
+        x: simplex[best].clone(),
+        value: values[best],
+        iterations,
+        converged,
+    }
+}
+
+
+/// Solves the square system `a x = b` by Gaussian elimination with partial pivoting.
+pub fn solve_linear(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|i, j| a[*i][col].abs().partial_cmp(&a[*j][col].abs()).unwrap_or(std::cmp::Ordering::Equal))?;
+        if a[pivot][col].abs() < 1e-14 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        let pivot_row = a[col].clone();
+        for row in col + 1..n {
+            let factor = a[row][col] / pivot_row[col];
+            for (value, pivot_value) in a[row].iter_mut().zip(&pivot_row).skip(col) {
+                *value -= factor * pivot_value;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+
+/// Levenberg–Marquardt minimisation of the sum of squared `residuals`, with a
+/// forward-difference Jacobian and Marquardt's diagonal scaling of the damping.
+pub fn levenberg_marquardt<F: Fn(&[f64]) -> Vec<f64>>(
+    residuals: F,
+    start: &[f64],
+    tolerance: f64,
+    max_iterations: usize,
+) -> Minimum {
+    let n = start.len();
+    let mut x = start.to_vec();
+    let mut r = residuals(&x);
+    let mut cost: f64 = r.iter().map(|e| e * e).sum();
+    let mut damping = 1e-3;
+
+    let mut iterations = 0;
+    let mut converged = false;
+    while iterations < max_iterations && cost.is_finite() {
+        iterations += 1;
+
+        let jacobian: Vec<Vec<f64>> = (0..n)
+            .map(|j| {
+                let h = 1e-7 * x[j].abs().max(1.0);
+                let mut shifted = x.clone();
+                shifted[j] += h;
+                residuals(&shifted).iter().zip(&r).map(|(bumped, base)| (bumped - base) / h).collect()
+            })
+            .collect();
+        let mut normal = vec![vec![0.0; n]; n];
+        let mut gradient = vec![0.0; n];
+        for i in 0..n {
+            for j in 0..n {
+                normal[i][j] = jacobian[i].iter().zip(&jacobian[j]).map(|(a, b)| a * b).sum();
+            }
+            gradient[i] = jacobian[i].iter().zip(&r).map(|(a, b)| a * b).sum();
+        }
+        if gradient.iter().all(|g| g.abs() < tolerance) {
+            converged = true;
+            break;
+        }
+
+        let mut improved = false;
+        while damping < 1e12 {
+            let mut damped = normal.clone();
+            for (i, row) in damped.iter_mut().enumerate() {
+                row[i] += damping * normal[i][i].max(1e-12);
+            }
+            let step = solve_linear(damped, gradient.iter().map(|g| -g).collect());
+            let candidate: Option<Vec<f64>> = step.map(|step| x.iter().zip(&step).map(|(a, b)| a + b).collect());
+
+            if let Some(candidate) = candidate {
+                let candidate_r = residuals(&candidate);
+                let candidate_cost: f64 = candidate_r.iter().map(|e| e * e).sum();
+                if candidate_cost < cost {
+                    let decrease = cost - candidate_cost;
+                    x = candidate;
+                    r = candidate_r;
+                    cost = candidate_cost;
+                    damping = (damping / 10.0).max(1e-12);
+                    improved = true;
+                    converged = decrease <= tolerance * cost.max(tolerance);
+                    break;
+                }
+            }
+            damping *= 10.0;
+        }
+        if !improved {
+            // No downhill step at any damping: a (possibly local) minimum
+            converged = true;
+            break;
+        }
+        if converged {
+            break;
+        }
+    }
+
+    Minimum {
+        x,
+        value: cost,
+        iterations,
+        converged,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nelder_mead_rosenbrock() {
+        let rosenbrock = |x: &[f64]| (1.0 - x[0]).powi(2) + 100.0 * (x[1] - x[0] * x[0]).powi(2);
+        let minimum = nelder_mead(rosenbrock, &[-1.2, 1.0], &[0.5, 0.5], 1e-14, 5000);
+
+        assert!(minimum.converged);
+        assert!((minimum.x[0] - 1.0).abs() < 1e-4);
+        assert!((minimum.x[1] - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_levenberg_marquardt_curve_fit() {
+        // Fit y = a e^{b t} to exact data
+        let data: Vec<(f64, f64)> = (0..10).map(|i| (i as f64 * 0.2, 2.0 * (-1.3 * i as f64 * 0.2).exp())).collect();
+        let residuals = |x: &[f64]| data.iter().map(|(t, y)| x[0] * (x[1] * t).exp() - y).collect::<Vec<f64>>();
+        let minimum = levenberg_marquardt(residuals, &[1.0, 0.0], 1e-15, 200);
+
+        assert!(minimum.converged);
+        assert!(minimum.value < 1e-20);
+        assert!((minimum.x[0] - 2.0).abs() < 1e-8);
+        assert!((minimum.x[1] + 1.3).abs() < 1e-8);
+    }
+
+    #[test]
+    fn test_solve_linear() {
+        let x = solve_linear(vec![vec![2.0, 1.0], vec![1.0, 3.0]], vec![3.0, 5.0]).unwrap();
+        assert!((x[0] - 0.8).abs() < 1e-12);
+        assert!((x[1] - 1.4).abs() < 1e-12);
+        assert!(solve_linear(vec![vec![1.0, 2.0], vec![2.0, 4.0]], vec![1.0, 2.0]).is_none());
+    }
+}
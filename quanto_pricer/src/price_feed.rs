@@ -0,0 +1,238 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use model::ids::BasketId;
+use model::model::{Asset, Basket, Instrument};
+use crate::instrument::{Venue, instrument_name, parse_instrument};
+use crate::stream::{Channel, DeribitStream, StreamSettings, StreamUpdate, TickerUpdate};
+
+
+/// A basket's value moved because one of its assets was repriced. `version` counts the
+/// updates applied to the basket since it was added, so consumers can drop stale events.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Revaluation {
+    pub basket_id: BasketId,
+    pub version: u64,
+    pub asset: Asset,
+    pub price: f64,
+    pub previous_value: f64,
+    pub value: f64,
+    /// Exchange time of the ticker, in Unix milliseconds.
+    pub timestamp: u64,
+}
+
+struct Tracked {
+    basket: Basket,
+    version: u64,
+}
+
+
+/// The Deribit perpetual whose ticker prices `asset`, e.g. "BTC-PERPETUAL" for BTC/USD.
+pub fn ticker_channel(asset: &Asset) -> Option<Channel> {
+    let perpetual = Instrument::Perpetual { underlying: asset.clone() };
+    instrument_name(Venue::Deribit, &perpetual).ok().map(|name| Channel::ticker(&name))
+}
+
+
+/// Keeps active baskets priced from streamed spot tickers and broadcasts a
+/// [`Revaluation`] for every basket an update moves.
+pub struct PriceFeedService {
+    baskets: Mutex<HashMap<BasketId, Tracked>>,
+    events: broadcast::Sender<Revaluation>,
+    stream: Mutex<Option<DeribitStream>>,
+}
+
+impl PriceFeedService {
+    /// `capacity` revaluations are kept for slow subscribers before they start to lag.
+    pub fn new(capacity: usize) -> Self {
+        let (events, _) = broadcast::channel(capacity.max(1));
+        PriceFeedService { baskets: Mutex::new(HashMap::new()), events, stream: Mutex::new(None) }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Revaluation> {
+        self.events.subscribe()
+    }
+
+    /// Starts tracking `basket`, subscribing to any of its assets not already streamed.
+    pub fn add_basket(&self, basket: Basket) {
+        let before = self.channels();
+        self.baskets.lock().unwrap().insert(basket.id, Tracked { basket, version: 0 });
+        let added: Vec<Channel> = self.channels().difference(&before).cloned().collect();
+        if let Some(stream) = self.stream.lock().unwrap().as_ref() {
+            if !added.is_empty() {
+                stream.subscribe(added);
+            }
+        }
+    }
+
+    /// Stops tracking a basket, e.g. once its auction closes, and returns it as last priced.
+    pub fn remove_basket(&self, basket_id: BasketId) -> Option<Basket> {
+        let before = self.channels();
+        let removed = self.baskets.lock().unwrap().remove(&basket_id)?;
+        let dropped: Vec<Channel> = before.difference(&self.channels()).cloned().collect();
+        if let Some(stream) = self.stream.lock().unwrap().as_ref() {
+            if !dropped.is_empty() {
+                stream.unsubscribe(dropped);
+            }
+        }
+        Some(removed.basket)
+    }
+
+    pub fn basket(&self, basket_id: BasketId) -> Option<Basket> {
+        self.baskets.lock().unwrap().get(&basket_id).map(|tracked| tracked.basket.clone())
+    }
+
+    pub fn version(&self, basket_id: BasketId) -> Option<u64> {
+        self.baskets.lock().unwrap().get(&basket_id).map(|tracked| tracked.version)
+    }
+
+    /// Ticker channels for every asset in an active basket.
+    pub fn channels(&self) -> BTreeSet<Channel> {
+        self.baskets.lock().unwrap().values()
+            .flat_map(|tracked| tracked.basket.assets.iter())
+            .filter_map(|info| ticker_channel(&info.asset))
+            .collect()
+    }
+
+    /// Reprices the asset `ticker` quotes in every basket holding it, broadcasting and
+    /// returning the revaluations. Tickers for other instruments are ignored.
+    pub fn apply(&self, ticker: &TickerUpdate) -> Vec<Revaluation> {
+        let Ok(Instrument::Perpetual { underlying: asset }) = parse_instrument(Venue::Deribit, &ticker.instrument_name) else {
+            return Vec::new();
+        };
+        let Some(price) = ticker.index_price.or(ticker.underlying_price) else {
+            return Vec::new();
+        };
+
+        let mut revaluations = Vec::new();
+        let mut baskets = self.baskets.lock().unwrap();
+        for tracked in baskets.values_mut() {
+            if !tracked.basket.assets.iter().any(|info| info.asset == asset) {
+                continue;
+            }
+            let previous_value = tracked.basket.total_value();
+            tracked.basket.update_price(&asset, price);
+            tracked.version += 1;
+            revaluations.push(Revaluation {
+                basket_id: tracked.basket.id,
+                version: tracked.version,
+                asset: asset.clone(),
+                price,
+                previous_value,
+                value: tracked.basket.total_value(),
+                timestamp: ticker.timestamp,
+            });
+        }
+        drop(baskets);
+
+        revaluations.sort_by_key(|revaluation| revaluation.basket_id);
+        for revaluation in &revaluations {
+            // No subscribers is not an error: the baskets are still repriced
+            let _ = self.events.send(revaluation.clone());
+        }
+        revaluations
+    }
+
+    /// Applies tickers from `updates` until the stream ends.
+    pub async fn run(&self, mut updates: mpsc::Receiver<StreamUpdate>) {
+        while let Some(update) = updates.recv().await {
+            if let StreamUpdate::Ticker(ticker) = update {
+                self.apply(&ticker);
+            }
+        }
+    }
+
+    /// Connects to Deribit for the current baskets' tickers and applies them in the
+    /// background; baskets added later are subscribed on the same connection.
+    pub fn start(self: &Arc<Self>, settings: StreamSettings) -> JoinHandle<()> {
+        let (stream, updates) = DeribitStream::connect(self.channels().into_iter().collect(), settings);
+        *self.stream.lock().unwrap() = Some(stream);
+        let service = self.clone();
+        tokio::spawn(async move { service.run(updates).await })
+    }
+
+    /// Closes the connection started by [`PriceFeedService::start`].
+    pub async fn stop(&self) {
+        let stream = self.stream.lock().unwrap().take();
+        if let Some(stream) = stream {
+            stream.close().await;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use model::model::AssetInfo;
+
+    fn ticker(instrument_name: &str, index_price: f64) -> TickerUpdate {
+        TickerUpdate {
+            instrument_name: instrument_name.to_string(),
+            timestamp: 1_700_000_000_000,
+            mark_price: index_price,
+            mark_iv: None,
+            index_price: Some(index_price),
+            underlying_price: None,
+            best_bid_price: None,
+            best_ask_price: None,
+            greeks: None,
+            received_at: None,
+        }
+    }
+
+    fn baskets() -> (Basket, Basket) {
+        let first = Basket { id: BasketId(1), assets: vec![AssetInfo::from_str("BTC/USD", 1.0, 60000.0).unwrap(), AssetInfo::from_str("ETH/USD", 10.0, 3000.0).unwrap()] };
+        let second = Basket { id: BasketId(2), assets: vec![AssetInfo::from_str("BTC/USD", 0.5, 60000.0).unwrap()] };
+        (first, second)
+    }
+
+    #[test]
+    fn test_tickers_reprice_every_basket_holding_the_asset() {
+        let service = PriceFeedService::new(16);
+        let mut events = service.subscribe();
+        let (first, second) = baskets();
+        service.add_basket(first);
+        service.add_basket(second);
+        assert_eq!(service.channels().into_iter().map(|channel| channel.name()).collect::<Vec<_>>(), vec!["ticker.BTC-PERPETUAL.100ms", "ticker.ETH-PERPETUAL.100ms"]);
+
+        let revaluations = service.apply(&ticker("BTC-PERPETUAL", 64000.0));
+        assert_eq!(revaluations.iter().map(|r| (r.basket_id, r.version, r.previous_value, r.value)).collect::<Vec<_>>(), vec![(BasketId(1), 1, 90000.0, 94000.0), (BasketId(2), 1, 30000.0, 32000.0)]);
+        assert_eq!(events.try_recv().unwrap(), revaluations[0]);
+        assert_eq!(events.try_recv().unwrap(), revaluations[1]);
+
+        service.apply(&ticker("ETH-PERPETUAL", 3100.0));
+        assert_eq!((service.version(BasketId(1)), service.version(BasketId(2))), (Some(2), Some(1)));
+        assert_eq!(service.basket(BasketId(1)).unwrap().total_value(), 95000.0);
+
+        // Option tickers and unknown instruments leave baskets alone
+        assert!(service.apply(&ticker("BTC-27DEC24-60000-C", 0.05)).is_empty());
+        assert!(service.apply(&ticker("SOL-PERPETUAL", 150.0)).is_empty());
+        assert_eq!(service.remove_basket(BasketId(2)).unwrap().total_value(), 32000.0);
+        assert_eq!(service.version(BasketId(2)), None);
+    }
+
+    #[test]
+    fn test_run_applies_streamed_tickers() {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let service = Arc::new(PriceFeedService::new(16));
+            let mut events = service.subscribe();
+            service.add_basket(baskets().1);
+
+            let (updates, receiver) = mpsc::channel(8);
+            let runner = tokio::spawn({
+                let service = service.clone();
+                async move { service.run(receiver).await }
+            });
+            updates.send(StreamUpdate::Connected).await.unwrap();
+            updates.send(StreamUpdate::Ticker(ticker("BTC-PERPETUAL", 70000.0))).await.unwrap();
+            drop(updates);
+            runner.await.unwrap();
+
+            let event = events.recv().await.unwrap();
+            assert_eq!((event.basket_id, event.value), (BasketId(2), 35000.0));
+            assert_eq!(service.basket(BasketId(2)).unwrap().total_value(), 35000.0);
+        });
+    }
+}
@@ -0,0 +1,278 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use model::model::{Asset, Basket};
+use crate::candles::Granularity;
+use crate::data::DeribitOptionData;
+use crate::fetch::{FetchError, FetchFailure, FetchReport, FetchSettings, RateLimiter, get_json};
+use crate::freshness::now_millis;
+use crate::history::{MarkPoint, TimeRange, Trade};
+use crate::query::{ChainQuery, query_client_side};
+use crate::realized::Candle;
+
+
+/// An option with its mark. Every venue fills in the Deribit fields: `market_price` is in
+/// `settlement_currency` units and `implied_volatility` is in percent.
+pub type OptionQuote = DeribitOptionData;
+
+
+/// A spot price, with the exchange's time for it and when it arrived, in Unix milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SpotQuote {
+    pub price: f64,
+    pub exchange_timestamp: Option<u64>,
+    pub received_at: Option<u64>,
+}
+
+impl SpotQuote {
+    /// A price of unknown age, such as one entered by hand.
+    pub fn untimed(price: f64) -> Self {
+        SpotQuote { price, exchange_timestamp: None, received_at: None }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PriceLevel {
+    pub price: f64,
+    pub amount: f64,
+}
+
+/// Top of an order book, bids from best down and asks from best up.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderBook {
+    pub instrument_name: String,
+    /// Exchange time in Unix milliseconds, where the venue reports one.
+    pub timestamp: Option<u64>,
+    pub bids: Vec<PriceLevel>,
+    pub asks: Vec<PriceLevel>,
+    #[serde(default)]
+    pub received_at: Option<u64>,
+}
+
+impl OrderBook {
+    pub fn best_bid(&self) -> Option<f64> {
+        self.bids.first().map(|level| level.price)
+    }
+
+    pub fn best_ask(&self) -> Option<f64> {
+        self.asks.first().map(|level| level.price)
+    }
+
+    pub fn mid(&self) -> Option<f64> {
+        Some(0.5 * (self.best_bid()? + self.best_ask()?))
+    }
+}
+
+
+/// A venue's public market data, so baskets and pricers don't depend on any one exchange.
+#[async_trait]
+pub trait MarketDataProvider: Send + Sync {
+    /// Short venue name, e.g. "deribit".
+    fn venue(&self) -> &'static str;
+
+    /// Latest price of `asset.base` in `asset.quote`.
+    async fn spot_price(&self, asset: &Asset) -> Result<f64, FetchError>;
+
+    /// [`MarketDataProvider::spot_price`] stamped with its arrival time.
+    async fn spot_quote(&self, asset: &Asset) -> Result<SpotQuote, FetchError> {
+        let price = self.spot_price(asset).await?;
+        Ok(SpotQuote { price, exchange_timestamp: None, received_at: Some(now_millis()) })
+    }
+
+    /// Live options on `underlying`, e.g. "BTC". Quotes that could not be priced are
+    /// returned without a mark and listed as failures.
+    async fn option_chain(&self, underlying: &str) -> Result<FetchReport<OptionQuote>, FetchError>;
+
+    /// The options of [`MarketDataProvider::option_chain`] that `query` selects. Venues
+    /// that can filter before pricing override this; by default the whole chain is
+    /// fetched and filtered here.
+    async fn query_chain(&self, underlying: &str, query: &ChainQuery) -> Result<FetchReport<OptionQuote>, FetchError> {
+        query_client_side(self, underlying, query).await
+    }
+
+    async fn order_book(&self, instrument_name: &str, depth: usize) -> Result<OrderBook, FetchError>;
+
+    /// Trades in `range`, oldest first.
+    async fn trades(&self, _instrument_name: &str, _range: TimeRange) -> Result<Vec<Trade>, FetchError> {
+        Err(unsupported(self.venue(), "trade history"))
+    }
+
+    /// Mark prices in `range`, oldest first.
+    async fn mark_history(&self, _instrument_name: &str, _range: TimeRange) -> Result<Vec<MarkPoint>, FetchError> {
+        Err(unsupported(self.venue(), "mark history"))
+    }
+
+    /// OHLCV bars of `granularity` opening in `range`, oldest first.
+    async fn candles(&self, _instrument_name: &str, _granularity: Granularity, _range: TimeRange) -> Result<Vec<Candle>, FetchError> {
+        Err(unsupported(self.venue(), "candles"))
+    }
+}
+
+
+/// Reprices every asset of `basket` at `provider`'s spot, leaving the old price of any
+/// asset whose request fails.
+pub async fn refresh_basket(provider: &dyn MarketDataProvider, basket: &mut Basket) -> Vec<FetchFailure> {
+    let mut failures = Vec::new();
+    let assets: Vec<Asset> = basket.assets.iter().map(|info| info.asset.clone()).collect();
+    for asset in assets {
+        match provider.spot_price(&asset).await {
+            Ok(price) => basket.update_price(&asset, price),
+            Err(error) => failures.push(FetchFailure { item: format!("{}/{}", asset.base, asset.quote), error }),
+        }
+    }
+    failures
+}
+
+
+/// Reads a JSON number, or a number sent as a string as Binance and OKX do.
+pub(crate) fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::String(text) => text.parse().ok(),
+        other => other.as_f64(),
+    }
+}
+
+/// Reads `[[price, amount, ...], ...]` into levels, skipping malformed entries.
+pub(crate) fn levels(value: Option<&Value>) -> Vec<PriceLevel> {
+    value.and_then(Value::as_array).into_iter().flatten()
+        .filter_map(|level| {
+            let level = level.as_array()?;
+            Some(PriceLevel { price: number(level.first()?)?, amount: number(level.get(1)?)? })
+        })
+        .collect()
+}
+
+pub(crate) fn malformed(what: &str) -> FetchError {
+    FetchError::Api(format!("malformed {} response", what))
+}
+
+pub(crate) fn unsupported(venue: &str, what: &str) -> FetchError {
+    FetchError::Api(format!("{} does not provide {}", venue, what))
+}
+
+
+/// Deribit: spot from the `base_quote` index, chains and books from the public API.
+#[derive(Debug)]
+pub struct DeribitProvider {
+    settings: FetchSettings,
+    client: Client,
+    limiter: RateLimiter,
+}
+
+impl DeribitProvider {
+    pub fn new(settings: FetchSettings) -> Self {
+        let limiter = settings.rate_limiter();
+        DeribitProvider { settings, client: Client::new(), limiter }
+    }
+
+    pub(crate) async fn get(&self, path: &str) -> Result<Value, FetchError> {
+        let url = format!("{}{}", self.settings.base_url, path);
+        get_json(&self.client, &url, &self.limiter, &self.settings.retry).await
+    }
+}
+
+impl Default for DeribitProvider {
+    fn default() -> Self {
+        DeribitProvider::new(FetchSettings::default())
+    }
+}
+
+#[async_trait]
+impl MarketDataProvider for DeribitProvider {
+    fn venue(&self) -> &'static str {
+        "deribit"
+    }
+
+    async fn spot_price(&self, asset: &Asset) -> Result<f64, FetchError> {
+        let index_name = format!("{}_{}", asset.base, asset.quote).to_lowercase();
+        let response = self.get(&format!("/public/get_index_price?index_name={}", index_name)).await?;
+        response["result"].get("index_price").and_then(number).ok_or_else(|| malformed("index price"))
+    }
+
+    async fn option_chain(&self, underlying: &str) -> Result<FetchReport<OptionQuote>, FetchError> {
+        DeribitOptionData::fetch_chain(underlying, &self.settings, &self.client, &self.limiter).await
+    }
+
+    /// Contract filters run on the instrument list, so only matching options are priced;
+    /// open interest comes with the price and is filtered after.
+    async fn query_chain(&self, underlying: &str, query: &ChainQuery) -> Result<FetchReport<OptionQuote>, FetchError> {
+        let spot = if query.needs_spot() { Some(self.spot_price(&Asset::new(underlying, "USD")).await?) } else { None };
+        let report = DeribitOptionData::fetch_chain_where(underlying, &self.settings, &self.client, &self.limiter, |option| query.matches_contract(option, spot)).await?;
+        let data = query.apply(report.data, spot);
+        let failures = report.failures.into_iter()
+            .filter(|failure| data.iter().any(|quote| quote.instrument_name == failure.item))
+            .collect();
+        Ok(FetchReport { data, failures })
+    }
+
+    async fn order_book(&self, instrument_name: &str, depth: usize) -> Result<OrderBook, FetchError> {
+        let response = self.get(&format!("/public/get_order_book?instrument_name={}&depth={}", instrument_name, depth)).await?;
+        let result = response.get("result").ok_or_else(|| malformed("order book"))?;
+        Ok(OrderBook {
+            instrument_name: instrument_name.to_string(),
+            timestamp: result.get("timestamp").and_then(Value::as_u64),
+            bids: levels(result.get("bids")),
+            asks: levels(result.get("asks")),
+            received_at: Some(now_millis()),
+        })
+    }
+
+    async fn trades(&self, instrument_name: &str, range: TimeRange) -> Result<Vec<Trade>, FetchError> {
+        self.download_trades(instrument_name, range).await
+    }
+
+    async fn mark_history(&self, instrument_name: &str, range: TimeRange) -> Result<Vec<MarkPoint>, FetchError> {
+        self.download_marks(instrument_name, range).await
+    }
+
+    async fn candles(&self, instrument_name: &str, granularity: Granularity, range: TimeRange) -> Result<Vec<Candle>, FetchError> {
+        self.download_candles(instrument_name, granularity, range).await
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use model::ids::BasketId;
+    use model::model::AssetInfo;
+    use tokio::runtime::Runtime;
+    use crate::fetch::mock;
+
+    #[test]
+    fn test_levels_accept_numbers_and_strings() {
+        let book = serde_json::json!([[100.5, 2.0], ["100.0", "3.5", "0", "4"], ["bad"], [99.0]]);
+        assert_eq!(levels(Some(&book)), vec![PriceLevel { price: 100.5, amount: 2.0 }, PriceLevel { price: 100.0, amount: 3.5 }]);
+        assert!(levels(None).is_empty());
+
+        let book = OrderBook { instrument_name: "X".to_string(), timestamp: None, bids: levels(Some(&book)), asks: vec![PriceLevel { price: 101.5, amount: 1.0 }], received_at: None };
+        assert_eq!(book.mid(), Some(101.0));
+    }
+
+    #[test]
+    fn test_deribit_provider_refreshes_basket() {
+        Runtime::new().unwrap().block_on(async {
+            let (base_url, _) = mock::serve(Duration::ZERO, |path, _| {
+                if path.ends_with("index_name=btc_usd") {
+                    (200, r#"{"result":{"index_price":64000.0}}"#.to_string())
+                } else if path.starts_with("/public/get_order_book") {
+                    (200, r#"{"result":{"timestamp":1700000000000,"bids":[[0.05,10.0]],"asks":[[0.06,4.0]]}}"#.to_string())
+                } else {
+                    (400, String::new())
+                }
+            }).await;
+            let provider = DeribitProvider::new(FetchSettings { base_url, ..FetchSettings::default() });
+
+            let mut basket = Basket { id: BasketId(1), assets: vec![AssetInfo::from_str("BTC/USD", 1.0, 1.0).unwrap(), AssetInfo::from_str("XYZ/USD", 1.0, 5.0).unwrap()] };
+            let failures = refresh_basket(&provider, &mut basket).await;
+            assert_eq!(basket.total_value(), 64005.0);
+            assert_eq!(failures, vec![FetchFailure { item: "XYZ/USD".to_string(), error: FetchError::Http { status: 400 } }]);
+
+            let book = provider.order_book("BTC-27DEC24-60000-C", 5).await.unwrap();
+            assert_eq!((book.best_bid(), book.best_ask(), book.timestamp), (Some(0.05), Some(0.06), Some(1700000000000)));
+        });
+    }
+}
@@ -0,0 +1,184 @@
+use model::model::{Asset, OptionKind};
+use crate::data::DeribitIndexPrice;
+use crate::fetch::{FetchError, FetchReport};
+use crate::history::TimeRange;
+use crate::provider::{MarketDataProvider, OptionQuote};
+
+
+/// Which options of a chain to pull. Every filter is optional; the defaults select the
+/// whole chain. Matches are ordered by expiry, strike and type before paging.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChainQuery {
+    /// Expiry timestamps, in Unix milliseconds.
+    pub expiry: Option<TimeRange>,
+    /// Inclusive band of strike / spot, e.g. (0.8, 1.2).
+    pub moneyness: Option<(f64, f64)>,
+    pub kind: Option<OptionKind>,
+    /// Quotes without a reported open interest fail this filter.
+    pub min_open_interest: Option<f64>,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+impl ChainQuery {
+    pub fn new() -> Self {
+        ChainQuery::default()
+    }
+
+    pub fn expiring(mut self, range: TimeRange) -> Self {
+        self.expiry = Some(range);
+        self
+    }
+
+    pub fn moneyness(mut self, low: f64, high: f64) -> Self {
+        self.moneyness = Some((low, high));
+        self
+    }
+
+    pub fn kind(mut self, kind: OptionKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    pub fn min_open_interest(mut self, open_interest: f64) -> Self {
+        self.min_open_interest = Some(open_interest);
+        self
+    }
+
+    pub fn page(mut self, offset: usize, limit: usize) -> Self {
+        self.offset = offset;
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Whether spot is needed, i.e. a moneyness band is set.
+    pub fn needs_spot(&self) -> bool {
+        self.moneyness.is_some()
+    }
+
+    /// The filters a venue can apply from its instrument list alone, before anything is
+    /// priced. A moneyness band rejects everything without `spot`.
+    pub fn matches_contract(&self, quote: &OptionQuote, spot: Option<f64>) -> bool {
+        if self.expiry.is_some_and(|range| !range.contains(quote.expiration_timestamp)) {
+            return false;
+        }
+        if let Some(kind) = self.kind {
+            let name = match kind { OptionKind::Call => "call", OptionKind::Put => "put" };
+            if !quote.option_type.eq_ignore_ascii_case(name) {
+                return false;
+            }
+        }
+        match (self.moneyness, spot) {
+            (Some((low, high)), Some(spot)) if spot > 0.0 => (low..=high).contains(&(quote.strike / spot)),
+            (Some(_), _) => false,
+            (None, _) => true,
+        }
+    }
+
+    pub fn matches(&self, quote: &OptionQuote, spot: Option<f64>) -> bool {
+        self.matches_contract(quote, spot)
+            && self.min_open_interest.is_none_or(|min| quote.open_interest.is_some_and(|oi| oi >= min))
+    }
+
+    /// Filters, orders and pages `quotes` here, for venues that can't do it themselves.
+    pub fn apply(&self, mut quotes: Vec<OptionQuote>, spot: Option<f64>) -> Vec<OptionQuote> {
+        quotes.retain(|quote| self.matches(quote, spot));
+        quotes.sort_by(|a, b| {
+            (a.expiration_timestamp, &a.option_type).cmp(&(b.expiration_timestamp, &b.option_type))
+                .then(a.strike.total_cmp(&b.strike))
+        });
+        quotes.into_iter().skip(self.offset).take(self.limit.unwrap_or(usize::MAX)).collect()
+    }
+}
+
+
+/// The spot a chain's moneyness is measured against: its first quote's price index.
+pub(crate) async fn chain_spot(provider: &(impl MarketDataProvider + ?Sized), quotes: &[OptionQuote]) -> Result<Option<f64>, FetchError> {
+    let Some((base, quote)) = quotes.first().and_then(|first| DeribitIndexPrice::currency_pair(&first.price_index)) else {
+        return Ok(None);
+    };
+    Ok(Some(provider.spot_price(&Asset::new(&base, &quote)).await?))
+}
+
+/// [`MarketDataProvider::option_chain`] narrowed by `query` client-side. Failures for
+/// quotes the query excludes are dropped with them.
+pub(crate) async fn query_client_side(provider: &(impl MarketDataProvider + ?Sized), underlying: &str, query: &ChainQuery) -> Result<FetchReport<OptionQuote>, FetchError> {
+    let report = provider.option_chain(underlying).await?;
+    let spot = if query.needs_spot() { chain_spot(provider, &report.data).await? } else { None };
+    let data = query.apply(report.data, spot);
+    let failures = report.failures.into_iter()
+        .filter(|failure| data.iter().any(|quote| quote.instrument_name == failure.item))
+        .collect();
+    Ok(FetchReport { data, failures })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+    use crate::fetch::{FetchSettings, mock};
+    use crate::provider::DeribitProvider;
+    use crate::replay::{Fixtures, MockProvider};
+
+    fn quote(name: &str, strike: f64, expiry: u64, option_type: &str, open_interest: Option<f64>) -> OptionQuote {
+        serde_json::from_value(serde_json::json!({
+            "instrument_name": name, "strike": strike, "expiration_timestamp": expiry, "option_type": option_type,
+            "price_index": "btc_usd", "settlement_currency": "BTC", "implied_volatility": 50.0, "market_price": 0.01,
+            "delta": null, "gamma": null, "vega": null, "theta": null, "open_interest": open_interest,
+        })).unwrap()
+    }
+
+    #[test]
+    fn test_query_filters_orders_and_pages() {
+        let quotes = vec![
+            quote("C-2-60", 60000.0, 2000, "call", Some(5.0)),
+            quote("C-1-70", 70000.0, 1000, "call", Some(50.0)),
+            quote("P-1-60", 60000.0, 1000, "put", None),
+            quote("C-1-60", 60000.0, 1000, "call", Some(10.0)),
+            quote("C-1-90", 90000.0, 1000, "call", Some(10.0)),
+        ];
+        let names = |quotes: Vec<OptionQuote>| quotes.into_iter().map(|q| q.instrument_name).collect::<Vec<_>>();
+
+        assert_eq!(names(ChainQuery::new().apply(quotes.clone(), None)), vec!["C-1-60", "C-1-70", "C-1-90", "P-1-60", "C-2-60"]);
+        let query = ChainQuery::new().expiring(TimeRange::new(0, 1500)).kind(OptionKind::Call).moneyness(0.9, 1.2);
+        assert_eq!(names(query.apply(quotes.clone(), Some(64000.0))), vec!["C-1-60", "C-1-70"]);
+        assert!(query.apply(quotes.clone(), None).is_empty());
+        assert_eq!(names(ChainQuery::new().min_open_interest(10.0).page(1, 2).apply(quotes.clone(), None)), vec!["C-1-70", "C-1-90"]);
+    }
+
+    #[test]
+    fn test_deribit_prices_only_matching_contracts() {
+        Runtime::new().unwrap().block_on(async {
+            let (base_url, requests) = mock::serve(Duration::ZERO, |path, _| {
+                if path.starts_with("/public/get_instruments") {
+                    (200, r#"{"result":[
+                        {"instrument_name":"BTC-1-50000-C","strike":50000.0,"expiration_timestamp":1000,"option_type":"call","price_index":"btc_usd","settlement_currency":"BTC"},
+                        {"instrument_name":"BTC-1-60000-C","strike":60000.0,"expiration_timestamp":1000,"option_type":"call","price_index":"btc_usd","settlement_currency":"BTC"},
+                        {"instrument_name":"BTC-1-60000-P","strike":60000.0,"expiration_timestamp":1000,"option_type":"put","price_index":"btc_usd","settlement_currency":"BTC"}
+                    ]}"#.to_string())
+                } else if path.starts_with("/public/get_index_price") {
+                    (200, r#"{"result":{"index_price":60000.0}}"#.to_string())
+                } else {
+                    (200, r#"{"result":{"mark_price":0.05,"mark_iv":55.0,"open_interest":12.0}}"#.to_string())
+                }
+            }).await;
+            let provider = DeribitProvider::new(FetchSettings { base_url, ..FetchSettings::default() });
+            let query = ChainQuery::new().kind(OptionKind::Call).moneyness(0.9, 1.1).min_open_interest(10.0);
+
+            let report = provider.query_chain("BTC", &query).await.unwrap();
+            assert_eq!(report.data.iter().map(|q| q.instrument_name.as_str()).collect::<Vec<_>>(), vec!["BTC-1-60000-C"]);
+            assert_eq!(report.data[0].open_interest, Some(12.0));
+            // Index, instrument list and the one matching book
+            assert_eq!(requests.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+            // Venues without a native query fetch the chain and filter it here
+            let mut fixtures = Fixtures { venue: "deribit".to_string(), ..Fixtures::default() };
+            fixtures.spot_prices.insert("BTC/USD".to_string(), 60000.0);
+            fixtures.option_chains.insert("BTC".to_string(), vec![quote("A", 60000.0, 1000, "call", Some(20.0)), quote("B", 90000.0, 1000, "call", Some(20.0))]);
+            let replayed = MockProvider::new(fixtures).query_chain("BTC", &query).await.unwrap();
+            assert_eq!(replayed.data.len(), 1);
+        });
+    }
+}
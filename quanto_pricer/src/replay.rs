@@ -0,0 +1,257 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use model::model::Asset;
+use crate::candles::Granularity;
+use crate::fetch::{FetchError, FetchReport};
+use crate::history::{MarkPoint, TimeRange, Trade};
+use crate::instrument::Venue;
+use crate::provider::{MarketDataProvider, OptionQuote, OrderBook};
+use crate::realized::Candle;
+
+
+/// Recorded market data, keyed by what was asked for: assets as "BASE/QUOTE", chains by
+/// underlying, candles as "INSTRUMENT granularity" and everything else by instrument name.
+/// Saved as pretty-printed JSON.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Fixtures {
+    pub venue: String,
+    #[serde(default)]
+    pub spot_prices: BTreeMap<String, f64>,
+    #[serde(default)]
+    pub option_chains: BTreeMap<String, Vec<OptionQuote>>,
+    #[serde(default)]
+    pub order_books: BTreeMap<String, OrderBook>,
+    #[serde(default)]
+    pub trades: BTreeMap<String, Vec<Trade>>,
+    #[serde(default)]
+    pub marks: BTreeMap<String, Vec<MarkPoint>>,
+    #[serde(default)]
+    pub candles: BTreeMap<String, Vec<Candle>>,
+}
+
+impl Fixtures {
+    pub fn load(path: impl AsRef<Path>) -> Result<Fixtures, FetchError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| FetchError::Storage(format!("{}: {}", path.display(), e)))?;
+        serde_json::from_str(&text).map_err(|e| FetchError::Storage(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), FetchError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| FetchError::Storage(e.to_string()))?;
+        }
+        let text = serde_json::to_string_pretty(self).map_err(|e| FetchError::Storage(e.to_string()))?;
+        fs::write(path, text).map_err(|e| FetchError::Storage(format!("{}: {}", path.display(), e)))
+    }
+}
+
+
+fn asset_key(asset: &Asset) -> String {
+    format!("{}/{}", asset.base, asset.quote)
+}
+
+fn missing(kind: &str, key: &str) -> FetchError {
+    FetchError::Api(format!("no {} fixture for {}", kind, key))
+}
+
+/// Adds `new` to `recorded`, oldest first, without repeating a timestamp already there.
+fn merge<T, K: Ord>(recorded: &mut Vec<T>, new: &[T], key: impl Fn(&T) -> K) where T: Clone {
+    recorded.extend(new.iter().cloned());
+    recorded.sort_by_key(&key);
+    recorded.dedup_by(|a, b| key(a) == key(b));
+}
+
+
+/// A [`MarketDataProvider`] for tests that must not touch the network. Replaying, it
+/// answers from [`Fixtures`] and fails on anything not recorded. Recording, it forwards
+/// every call to a live provider and keeps each successful response for [`MockProvider::save`].
+pub struct MockProvider {
+    fixtures: Mutex<Fixtures>,
+    live: Option<Box<dyn MarketDataProvider>>,
+}
+
+impl MockProvider {
+    pub fn new(fixtures: Fixtures) -> Self {
+        MockProvider { fixtures: Mutex::new(fixtures), live: None }
+    }
+
+    /// Replays a fixture file written by [`MockProvider::save`].
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, FetchError> {
+        Ok(MockProvider::new(Fixtures::load(path)?))
+    }
+
+    /// Records `live`'s responses, e.g. from an ignored test run by hand against the exchange.
+    pub fn recording(live: Box<dyn MarketDataProvider>) -> Self {
+        let fixtures = Fixtures { venue: live.venue().to_string(), ..Fixtures::default() };
+        MockProvider { fixtures: Mutex::new(fixtures), live: Some(live) }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.live.is_some()
+    }
+
+    pub fn fixtures(&self) -> Fixtures {
+        self.fixtures.lock().unwrap().clone()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), FetchError> {
+        self.fixtures.lock().unwrap().save(path)
+    }
+}
+
+#[async_trait]
+impl MarketDataProvider for MockProvider {
+    /// The recorded venue, or "mock" for fixtures from no known venue.
+    fn venue(&self) -> &'static str {
+        match &self.live {
+            Some(live) => live.venue(),
+            None => Venue::from_name(&self.fixtures.lock().unwrap().venue).map_or("mock", |venue| venue.name()),
+        }
+    }
+
+    async fn spot_price(&self, asset: &Asset) -> Result<f64, FetchError> {
+        let key = asset_key(asset);
+        if let Some(live) = &self.live {
+            let price = live.spot_price(asset).await?;
+            self.fixtures.lock().unwrap().spot_prices.insert(key, price);
+            return Ok(price);
+        }
+        self.fixtures.lock().unwrap().spot_prices.get(&key).copied().ok_or_else(|| missing("spot", &key))
+    }
+
+    /// Failures are not recorded: a replayed chain is the data as it was received.
+    async fn option_chain(&self, underlying: &str) -> Result<FetchReport<OptionQuote>, FetchError> {
+        if let Some(live) = &self.live {
+            let report = live.option_chain(underlying).await?;
+            self.fixtures.lock().unwrap().option_chains.insert(underlying.to_string(), report.data.clone());
+            return Ok(report);
+        }
+        let data = self.fixtures.lock().unwrap().option_chains.get(underlying).cloned().ok_or_else(|| missing("option chain", underlying))?;
+        Ok(FetchReport { data, failures: Vec::new() })
+    }
+
+    async fn order_book(&self, instrument_name: &str, depth: usize) -> Result<OrderBook, FetchError> {
+        if let Some(live) = &self.live {
+            let book = live.order_book(instrument_name, depth).await?;
+            self.fixtures.lock().unwrap().order_books.insert(instrument_name.to_string(), book.clone());
+            return Ok(book);
+        }
+        let mut book = self.fixtures.lock().unwrap().order_books.get(instrument_name).cloned().ok_or_else(|| missing("order book", instrument_name))?;
+        book.bids.truncate(depth);
+        book.asks.truncate(depth);
+        Ok(book)
+    }
+
+    async fn trades(&self, instrument_name: &str, range: TimeRange) -> Result<Vec<Trade>, FetchError> {
+        if let Some(live) = &self.live {
+            let trades = live.trades(instrument_name, range).await?;
+            let mut fixtures = self.fixtures.lock().unwrap();
+            merge(fixtures.trades.entry(instrument_name.to_string()).or_default(), &trades, |trade| (trade.timestamp, trade.trade_id.clone()));
+            return Ok(trades);
+        }
+        let fixtures = self.fixtures.lock().unwrap();
+        let trades = fixtures.trades.get(instrument_name).ok_or_else(|| missing("trades", instrument_name))?;
+        Ok(trades.iter().filter(|trade| range.contains(trade.timestamp)).cloned().collect())
+    }
+
+    async fn mark_history(&self, instrument_name: &str, range: TimeRange) -> Result<Vec<MarkPoint>, FetchError> {
+        if let Some(live) = &self.live {
+            let marks = live.mark_history(instrument_name, range).await?;
+            let mut fixtures = self.fixtures.lock().unwrap();
+            merge(fixtures.marks.entry(instrument_name.to_string()).or_default(), &marks, |mark| mark.timestamp);
+            return Ok(marks);
+        }
+        let fixtures = self.fixtures.lock().unwrap();
+        let marks = fixtures.marks.get(instrument_name).ok_or_else(|| missing("marks", instrument_name))?;
+        Ok(marks.iter().filter(|mark| range.contains(mark.timestamp)).copied().collect())
+    }
+
+    async fn candles(&self, instrument_name: &str, granularity: Granularity, range: TimeRange) -> Result<Vec<Candle>, FetchError> {
+        let key = format!("{} {}", instrument_name, granularity.name());
+        if let Some(live) = &self.live {
+            let candles = live.candles(instrument_name, granularity, range).await?;
+            let mut fixtures = self.fixtures.lock().unwrap();
+            merge(fixtures.candles.entry(key).or_default(), &candles, |candle| candle.timestamp);
+            return Ok(candles);
+        }
+        let fixtures = self.fixtures.lock().unwrap();
+        let candles = fixtures.candles.get(&key).ok_or_else(|| missing("candles", &key))?;
+        Ok(candles.iter().filter(|candle| range.contains(candle.timestamp)).copied().collect())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::runtime::Runtime;
+    use crate::fetch::{FetchSettings, mock};
+    use crate::provider::{DeribitProvider, PriceLevel};
+
+    #[test]
+    fn test_replay_serves_fixtures_and_rejects_the_rest() {
+        Runtime::new().unwrap().block_on(async {
+            let mut fixtures = Fixtures { venue: "okx".to_string(), ..Fixtures::default() };
+            fixtures.spot_prices.insert("BTC/USDT".to_string(), 64000.0);
+            let level = |price| PriceLevel { price, amount: 1.0 };
+            fixtures.order_books.insert("BTC-USDT".to_string(), OrderBook {
+                instrument_name: "BTC-USDT".to_string(),
+                timestamp: Some(1),
+                bids: vec![level(99.0), level(98.0)],
+                asks: vec![level(101.0), level(102.0)],
+                received_at: None,
+            });
+            fixtures.marks.insert("BTC-USDT".to_string(), (0..5).map(|i| MarkPoint { timestamp: i * 1000, mark_price: 100.0 + i as f64 }).collect());
+            let provider = MockProvider::new(fixtures);
+
+            assert_eq!(provider.venue(), "okx");
+            assert_eq!(provider.spot_price(&Asset::new("BTC", "USDT")).await, Ok(64000.0));
+            assert!(matches!(provider.spot_price(&Asset::new("ETH", "USDT")).await, Err(FetchError::Api(_))));
+            assert_eq!(provider.order_book("BTC-USDT", 1).await.unwrap().asks, vec![level(101.0)]);
+            let marks = provider.mark_history("BTC-USDT", TimeRange::new(1000, 3000)).await.unwrap();
+            assert_eq!(marks.len(), 2);
+            assert!(provider.option_chain("BTC").await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_recording_round_trips_through_a_file() {
+        Runtime::new().unwrap().block_on(async {
+            let (base_url, requests) = mock::serve(Duration::ZERO, |path, _| {
+                if path.starts_with("/public/get_index_price") {
+                    (200, r#"{"result":{"index_price":64000.0}}"#.to_string())
+                } else if path.starts_with("/public/get_order_book") {
+                    (200, r#"{"result":{"timestamp":1700000000000,"bids":[[0.05,10.0]],"asks":[[0.06,4.0]]}}"#.to_string())
+                } else {
+                    (200, r#"{"result":[[1000,0.055],[2000,0.056]]}"#.to_string())
+                }
+            }).await;
+            let live = DeribitProvider::new(FetchSettings { base_url, ..FetchSettings::default() });
+            let recorder = MockProvider::recording(Box::new(live));
+            assert!(recorder.is_recording());
+
+            let spot = recorder.spot_price(&Asset::new("BTC", "USD")).await.unwrap();
+            let book = recorder.order_book("BTC-27DEC24-60000-C", 10).await.unwrap();
+            let marks = recorder.mark_history("BTC-27DEC24-60000-C", TimeRange::new(0, 10_000)).await.unwrap();
+
+            let path = std::env::temp_dir().join(format!("quanto-fixtures-{}.json", std::process::id()));
+            recorder.save(&path).unwrap();
+            let replay = MockProvider::from_file(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            let served = requests.load(std::sync::atomic::Ordering::SeqCst);
+
+            assert_eq!(replay.fixtures(), recorder.fixtures());
+            assert_eq!(replay.venue(), "deribit");
+            assert_eq!(replay.spot_price(&Asset::new("BTC", "USD")).await, Ok(spot));
+            assert_eq!(replay.order_book("BTC-27DEC24-60000-C", 10).await, Ok(book));
+            assert_eq!(replay.mark_history("BTC-27DEC24-60000-C", TimeRange::new(0, 10_000)).await, Ok(marks));
+            assert_eq!(requests.load(std::sync::atomic::Ordering::SeqCst), served);
+        });
+    }
+}
@@ -0,0 +1,193 @@
+use crate::optimize::nelder_mead;
+use crate::error::PricingError;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SabrParams {
+    pub alpha: f64,
+    pub beta: f64,
+    pub rho: f64,
+    pub nu: f64,
+}
+
+impl SabrParams {
+    /// Hagan et al. (2002) lognormal implied volatility approximation.
+    pub fn implied_vol(&self, forward: f64, strike: f64, expiry: f64) -> f64 {
+        let (alpha, beta, rho, nu) = (self.alpha, self.beta, self.rho, self.nu);
+        let one_beta = 1.0 - beta;
+        let fk = forward * strike;
+        let fk_beta = fk.powf(0.5 * one_beta);
+        let log_fk = (forward / strike).ln();
+
+        let correction = 1.0
+            + (one_beta.powi(2) / 24.0 * alpha * alpha / fk.powf(one_beta)
+                + 0.25 * rho * beta * nu * alpha / fk_beta
+                + (2.0 - 3.0 * rho * rho) / 24.0 * nu * nu)
+                * expiry;
+        let denominator = fk_beta * (1.0 + one_beta.powi(2) / 24.0 * log_fk.powi(2) + one_beta.powi(4) / 1920.0 * log_fk.powi(4));
+
+        let z = nu / alpha * fk_beta * log_fk;
+        let z_over_x = if z.abs() < 1e-8 {
+            1.0
+        } else {
+            let x = (((1.0 - 2.0 * rho * z + z * z).sqrt() + z - rho) / (1.0 - rho)).ln();
+            z / x
+        };
+
+        alpha / denominator * z_over_x * correction
+    }
+}
+
+
+/// SABR parameters fitted to one expiry's smile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SabrCalibration {
+    pub expiry: f64,
+    pub forward: f64,
+    pub params: SabrParams,
+    /// Root mean square and largest absolute error of the fit, in implied volatility.
+    pub rmse: f64,
+    pub max_error: f64,
+    pub converged: bool,
+}
+
+impl SabrCalibration {
+    /// Least-squares fit of alpha, rho and nu to (strike, implied vol) quotes with beta fixed.
+    pub fn calibrate(expiry: f64, forward: f64, beta: f64, quotes: &[(f64, f64)]) -> Result<SabrCalibration, PricingError> {
+        if quotes.len() < 3 {
+            return Err(PricingError::InsufficientData("SABR needs at least three quotes per expiry"));
+        }
+        if expiry <= 0.0 || forward <= 0.0 {
+            return Err(PricingError::InvalidInput("expiry and forward must be positive"));
+        }
+
+        // Unconstrained parametrisation: alpha = e^x0, rho = tanh(x1), nu = e^x2
+        let params = |x: &[f64]| SabrParams { alpha: x[0].exp(), beta, rho: x[1].tanh(), nu: x[2].exp() };
+        let objective = |x: &[f64]| -> f64 {
+            let p = params(x);
+            quotes.iter()
+                .map(|(strike, vol)| (p.implied_vol(forward, *strike, expiry) - vol).powi(2))
+                .sum::<f64>()
+        };
+
+        let atm_vol = quotes.iter()
+            .min_by(|a, b| (a.0 - forward).abs().partial_cmp(&(b.0 - forward).abs()).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(_, vol)| *vol)
+            .unwrap_or(0.5);
+        let start = [(atm_vol * forward.powf(1.0 - beta)).ln(), 0.0, 0.5f64.ln()];
+        let minimum = nelder_mead(objective, &start, &[0.1, 0.2, 0.3], 1e-16, 5000);
+        let fitted = params(&minimum.x);
+
+        let errors: Vec<f64> = quotes.iter()
+            .map(|(strike, vol)| (fitted.implied_vol(forward, *strike, expiry) - vol).abs())
+            .collect();
+        if errors.iter().any(|e| !e.is_finite()) {
+            return Err(PricingError::Calibration("SABR calibration produced invalid volatilities"));
+        }
+
+        Ok(SabrCalibration {
+            expiry,
+            forward,
+            params: fitted,
+            rmse: (errors.iter().map(|e| e * e).sum::<f64>() / errors.len() as f64).sqrt(),
+            max_error: errors.iter().cloned().fold(0.0, f64::max),
+            converged: minimum.converged,
+        })
+    }
+}
+
+
+/// Calibrated expiries, with parameters interpolated linearly in time in between so that
+/// expiries with too few quotes of their own can still be priced.
+#[derive(Debug, Clone)]
+pub struct SabrSmile {
+    pub calibrations: Vec<SabrCalibration>,
+}
+
+impl SabrSmile {
+    pub fn new(mut calibrations: Vec<SabrCalibration>) -> Result<SabrSmile, PricingError> {
+        if calibrations.is_empty() {
+            return Err(PricingError::InsufficientData("no calibrated expiries"));
+        }
+        calibrations.sort_by(|a, b| a.expiry.partial_cmp(&b.expiry).unwrap_or(std::cmp::Ordering::Equal));
+        Ok(SabrSmile { calibrations })
+    }
+
+    /// Parameters held flat outside the calibrated expiries.
+    pub fn params_at(&self, expiry: f64) -> SabrParams {
+        let first = &self.calibrations[0];
+        let last = &self.calibrations[self.calibrations.len() - 1];
+        if expiry <= first.expiry {
+            return first.params;
+        }
+        if expiry >= last.expiry {
+            return last.params;
+        }
+
+        let next = self.calibrations.iter().position(|c| c.expiry >= expiry).unwrap_or(self.calibrations.len() - 1);
+        let (before, after) = (&self.calibrations[next - 1].params, &self.calibrations[next].params);
+        let w = (expiry - self.calibrations[next - 1].expiry) / (self.calibrations[next].expiry - self.calibrations[next - 1].expiry);
+        let lerp = |a: f64, b: f64| (1.0 - w) * a + w * b;
+
+        SabrParams {
+            alpha: lerp(before.alpha, after.alpha),
+            beta: lerp(before.beta, after.beta),
+            rho: lerp(before.rho, after.rho),
+            nu: lerp(before.nu, after.nu),
+        }
+    }
+
+    pub fn implied_vol(&self, forward: f64, strike: f64, expiry: f64) -> f64 {
+        self.params_at(expiry).implied_vol(forward, strike, expiry)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRUE: SabrParams = SabrParams { alpha: 0.6, beta: 1.0, rho: -0.3, nu: 1.2 };
+
+    fn quotes(params: &SabrParams, forward: f64, expiry: f64) -> Vec<(f64, f64)> {
+        [0.7, 0.8, 0.9, 1.0, 1.1, 1.25, 1.4].iter()
+            .map(|m| (forward * m, params.implied_vol(forward, forward * m, expiry)))
+            .collect()
+    }
+
+    #[test]
+    fn test_atm_limit_is_continuous() {
+        let atm = TRUE.implied_vol(100.0, 100.0, 0.5);
+        let near = TRUE.implied_vol(100.0, 100.0 + 1e-6, 0.5);
+        assert!((atm - near).abs() < 1e-6);
+        // Lognormal beta with no vol-of-vol reduces to a flat alpha
+        let flat = SabrParams { alpha: 0.4, beta: 1.0, rho: 0.0, nu: 1e-10 };
+        assert!((flat.implied_vol(100.0, 130.0, 1.0) - 0.4).abs() < 1e-8);
+    }
+
+    #[test]
+    fn test_calibration_recovers_parameters() {
+        let calibration = SabrCalibration::calibrate(0.5, 100.0, 1.0, &quotes(&TRUE, 100.0, 0.5)).unwrap();
+
+        assert!(calibration.rmse < 1e-6, "rmse {}", calibration.rmse);
+        assert!((calibration.params.alpha - TRUE.alpha).abs() < 1e-3);
+        assert!((calibration.params.rho - TRUE.rho).abs() < 1e-3);
+        assert!((calibration.params.nu - TRUE.nu).abs() < 1e-3);
+        assert!(SabrCalibration::calibrate(0.5, 100.0, 1.0, &[(100.0, 0.6)]).is_err());
+    }
+
+    #[test]
+    fn test_smile_interpolates_between_expiries() {
+        let long = SabrParams { alpha: 0.5, beta: 1.0, rho: -0.1, nu: 0.6 };
+        let smile = SabrSmile::new(vec![
+            SabrCalibration::calibrate(1.0, 100.0, 1.0, &quotes(&long, 100.0, 1.0)).unwrap(),
+            SabrCalibration::calibrate(0.25, 100.0, 1.0, &quotes(&TRUE, 100.0, 0.25)).unwrap(),
+        ]).unwrap();
+
+        assert_eq!(smile.calibrations[0].expiry, 0.25);
+        let mid = smile.params_at(0.625);
+        assert!((mid.rho - 0.5 * (TRUE.rho + long.rho)).abs() < 1e-3);
+        assert_eq!(smile.params_at(5.0), smile.calibrations[1].params);
+        assert!(smile.implied_vol(100.0, 80.0, 0.625) > smile.implied_vol(100.0, 100.0, 0.625));
+    }
+}
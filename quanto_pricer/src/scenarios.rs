@@ -0,0 +1,207 @@
+use ndarray::Array2;
+use crate::engine::PricingEngine;
+use crate::fourier::QuantoOption;
+
+/// Floor on shocked volatilities, so that down-shocks never price with a negative vol.
+const MIN_VOLATILITY: f64 = 1e-4;
+
+
+/// A signed quantity of calls or puts; negative quantities are short.
+pub struct OptionPosition {
+    pub option: QuantoOption,
+    pub is_call: bool,
+    pub quantity: f64,
+}
+
+impl OptionPosition {
+    pub fn new(option: QuantoOption, is_call: bool, quantity: f64) -> Self {
+        OptionPosition { option, is_call, quantity }
+    }
+
+    pub fn value(&self, engine: &PricingEngine) -> f64 {
+        self.value_of(&self.option, engine)
+    }
+
+    fn value_of(&self, option: &QuantoOption, engine: &PricingEngine) -> f64 {
+        let price = engine.price(option, option.strike);
+        self.quantity * if self.is_call { price.call } else { price.put }
+    }
+}
+
+
+/// One market move: a relative spot change (-0.1 is spot down 10%), an absolute volatility
+/// change (0.05 is +5 vol points) and an additive correlation shift.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Shock {
+    pub spot: f64,
+    pub volatility: f64,
+    pub correlation: f64,
+}
+
+impl Shock {
+    pub fn apply(&self, option: &QuantoOption) -> QuantoOption {
+        QuantoOption {
+            spot: option.spot * (1.0 + self.spot),
+            volatility: (option.volatility + self.volatility).max(MIN_VOLATILITY),
+            correlation: (option.correlation + self.correlation).clamp(-1.0, 1.0),
+            ..*option
+        }
+    }
+}
+
+
+/// Spot × volatility shock grid, repeated for every correlation shift.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShockGrid {
+    pub spot_shocks: Vec<f64>,
+    pub vol_shocks: Vec<f64>,
+    pub correlation_shifts: Vec<f64>,
+}
+
+
+/// P&L of a portfolio over a spot (rows) × volatility (columns) grid at one correlation shift.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PnlMatrix {
+    pub spot_shocks: Vec<f64>,
+    pub vol_shocks: Vec<f64>,
+    pub correlation_shift: f64,
+    pub pnl: Array2<f64>,
+}
+
+impl PnlMatrix {
+    /// Largest loss in the matrix with the shock that causes it; a positive number is a loss.
+    pub fn worst_loss(&self) -> (f64, Shock) {
+        let mut worst = (f64::NEG_INFINITY, Shock::default());
+        for ((i, j), pnl) in self.pnl.indexed_iter() {
+            if -pnl > worst.0 {
+                worst = (-pnl, Shock { spot: self.spot_shocks[i], volatility: self.vol_shocks[j], correlation: self.correlation_shift });
+            }
+        }
+        worst
+    }
+}
+
+
+impl ShockGrid {
+    pub fn new(spot_shocks: Vec<f64>, vol_shocks: Vec<f64>, correlation_shifts: Vec<f64>) -> Self {
+        ShockGrid { spot_shocks, vol_shocks, correlation_shifts }
+    }
+
+    /// Spot ±5/10/20%, vol ±5/10 points, correlation unchanged.
+    pub fn standard() -> Self {
+        ShockGrid::new(
+            vec![-0.2, -0.1, -0.05, 0.0, 0.05, 0.1, 0.2],
+            vec![-0.1, -0.05, 0.0, 0.05, 0.1],
+            vec![0.0],
+        )
+    }
+
+    /// Crypto stress moves: spot ±30/50%, vol ±20 points, correlation ±0.3.
+    pub fn crypto_stress() -> Self {
+        ShockGrid::new(
+            vec![-0.5, -0.3, 0.0, 0.3, 0.5],
+            vec![-0.2, 0.0, 0.2],
+            vec![-0.3, 0.0, 0.3],
+        )
+    }
+
+    pub fn shocks(&self) -> Vec<Shock> {
+        let mut shocks = Vec::new();
+        for correlation in &self.correlation_shifts {
+            for spot in &self.spot_shocks {
+                for volatility in &self.vol_shocks {
+                    shocks.push(Shock { spot: *spot, volatility: *volatility, correlation: *correlation });
+                }
+            }
+        }
+        shocks
+    }
+
+    /// Reprices every position under every shock; one matrix per correlation shift.
+    pub fn run(&self, positions: &[OptionPosition], engine: &PricingEngine) -> Vec<PnlMatrix> {
+        let base: f64 = positions.iter().map(|p| p.value(engine)).sum();
+
+        self.correlation_shifts.iter()
+            .map(|correlation| {
+                let mut pnl = Array2::<f64>::zeros((self.spot_shocks.len(), self.vol_shocks.len()));
+                for (i, spot) in self.spot_shocks.iter().enumerate() {
+                    for (j, volatility) in self.vol_shocks.iter().enumerate() {
+                        let shock = Shock { spot: *spot, volatility: *volatility, correlation: *correlation };
+                        let value: f64 = positions.iter().map(|p| p.value_of(&shock.apply(&p.option), engine)).sum();
+                        pnl[[i, j]] = value - base;
+                    }
+                }
+                PnlMatrix {
+                    spot_shocks: self.spot_shocks.clone(),
+                    vol_shocks: self.vol_shocks.clone(),
+                    correlation_shift: *correlation,
+                    pnl,
+                }
+            })
+            .collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cos::CosSettings;
+
+    fn option(strike: f64) -> QuantoOption {
+        QuantoOption {
+            spot: 30000.0,
+            strike,
+            domestic_rate: 0.03,
+            foreign_rate: 0.0,
+            volatility: 0.6,
+            fx_volatility: 0.2,
+            time_to_maturity: 0.25,
+            correlation: 0.3,
+            dividend_yield: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_unshocked_scenario_has_zero_pnl() {
+        let engine = PricingEngine::Cos(CosSettings::default());
+        let positions = vec![OptionPosition::new(option(30000.0), true, 2.0)];
+        let matrices = ShockGrid::standard().run(&positions, &engine);
+
+        assert_eq!(matrices.len(), 1);
+        assert_eq!(matrices[0].pnl.dim(), (7, 5));
+        assert!(matrices[0].pnl[[3, 2]].abs() < 1e-9);
+        // A long call gains with spot and with vol
+        assert!(matrices[0].pnl[[6, 2]] > 0.0 && matrices[0].pnl[[0, 2]] < 0.0);
+        assert!(matrices[0].pnl[[3, 4]] > 0.0);
+    }
+
+    #[test]
+    fn test_short_straddle_worst_case_is_a_large_move() {
+        let engine = PricingEngine::Cos(CosSettings::default());
+        let positions = vec![
+            OptionPosition::new(option(30000.0), true, -1.0),
+            OptionPosition::new(option(30000.0), false, -1.0),
+        ];
+        let matrices = ShockGrid::crypto_stress().run(&positions, &engine);
+        assert_eq!(matrices.len(), 3);
+
+        let (loss, shock) = matrices[1].worst_loss();
+        assert!(loss > 0.0);
+        assert_eq!(shock.volatility, 0.2);
+        assert_eq!(shock.spot.abs(), 0.5);
+        // Correlation moves the quanto drift, so it changes the P&L of a call
+        let call = [OptionPosition::new(option(30000.0), true, 1.0)];
+        let by_correlation = ShockGrid::new(vec![0.0], vec![0.0], vec![-0.3, 0.3]).run(&call, &engine);
+        assert!(by_correlation[0].pnl[[0, 0]] < 0.0 && by_correlation[1].pnl[[0, 0]] > 0.0);
+    }
+
+    #[test]
+    fn test_shock_application_is_bounded() {
+        let shocked = Shock { spot: -0.1, volatility: -1.0, correlation: 0.9 }.apply(&option(30000.0));
+        assert_eq!(shocked.spot, 27000.0);
+        assert_eq!(shocked.volatility, MIN_VOLATILITY);
+        assert_eq!(shocked.correlation, 1.0);
+        assert_eq!(ShockGrid::crypto_stress().shocks().len(), 45);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::builder::InputError;
+use crate::fourier::{CallCurve, CharacteristicModel, FftSettings, QuantoOption, call_curve};
+use crate::implied_vol::ImpliedVolatility;
+use crate::vol_surface::VolPoint;
+
+
+/// Calls, puts and implied vols at every strike of one FFT grid, for a single expiry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Smile {
+    pub expiry: f64,
+    pub forward: f64,
+    pub discount: f64,
+    pub strikes: Vec<f64>,
+    pub calls: Vec<f64>,
+    pub puts: Vec<f64>,
+    /// Black–Scholes vol of the out-of-the-money side against the forward; `None` where the
+    /// price has no time value left, as in the far wings.
+    pub implied_vols: Vec<Option<f64>>,
+}
+
+
+impl Smile {
+    /// The grid points of `curve` within `max_abs_log_moneyness` of the forward, with puts
+    /// by parity.
+    pub fn from_curve(curve: &CallCurve, expiry: f64, forward: f64, discount: f64, max_abs_log_moneyness: f64) -> Smile {
+        let log_forward = forward.ln();
+        let mut smile = Smile {
+            expiry,
+            forward,
+            discount,
+            strikes: Vec::new(),
+            calls: Vec::new(),
+            puts: Vec::new(),
+            implied_vols: Vec::new(),
+        };
+
+        for (k, call) in curve.log_strikes.iter().zip(&curve.calls) {
+            if (k - log_forward).abs() > max_abs_log_moneyness {
+                continue;
+            }
+            let strike = k.exp();
+            let put = call - discount * (forward - strike);
+            let is_call = strike >= forward;
+            // Spot at the forward with r = q prices off the forward and discounts at r
+            let rate = -discount.ln() / expiry;
+            let implied_vol = ImpliedVolatility {
+                spot: forward,
+                strike,
+                r: rate,
+                time_to_maturity: expiry,
+                market_price: if is_call { *call } else { put },
+                is_call,
+                dividend_yield: rate,
+            }.implied_volatility().ok();
+
+            smile.strikes.push(strike);
+            smile.calls.push(*call);
+            smile.puts.push(put);
+            smile.implied_vols.push(implied_vol);
+        }
+        smile
+    }
+
+    pub fn len(&self) -> usize {
+        self.strikes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strikes.is_empty()
+    }
+
+    /// Strikes with an implied vol, ready for [`crate::vol_surface::VolSurface::fit`].
+    pub fn vol_points(&self) -> Vec<VolPoint> {
+        self.strikes.iter().zip(&self.implied_vols)
+            .filter_map(|(strike, vol)| vol.map(|implied_vol| VolPoint { strike: *strike, expiry: self.expiry, implied_vol }))
+            .collect()
+    }
+}
+
+
+/// One FFT centred on the forward, returned as a whole [`Smile`] rather than a single strike.
+pub fn price_smile<M: CharacteristicModel + ?Sized>(model: &M, settings: &FftSettings, max_abs_log_moneyness: f64) -> Smile {
+    let forward = model.forward();
+    let curve = call_curve(model, settings, forward.ln());
+    Smile::from_curve(&curve, model.time_to_maturity(), forward, model.discount(), max_abs_log_moneyness)
+}
+
+
+impl QuantoOption {
+    /// Smile of this option's underlying and expiry; its own strike plays no part.
+    pub fn smile(&self, settings: &FftSettings, max_abs_log_moneyness: f64) -> Result<Smile, InputError> {
+        self.validate()?;
+        Ok(price_smile(self, settings, max_abs_log_moneyness))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calibration::HestonParams;
+    use crate::curve::YieldCurve;
+    use crate::vol_surface::VolSurface;
+
+    fn option(expiry: f64) -> QuantoOption {
+        QuantoOption {
+            spot: 100.0,
+            strike: 100.0,
+            domestic_rate: 0.03,
+            foreign_rate: 0.0,
+            volatility: 0.55,
+            fx_volatility: 0.0,
+            time_to_maturity: expiry,
+            correlation: 0.0,
+            dividend_yield: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_black_scholes_smile_is_flat() {
+        let smile = option(0.5).smile(&FftSettings::default(), 1.0).unwrap();
+        assert!(smile.len() > 100);
+        assert!(smile.strikes.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(smile.strikes.iter().all(|strike| (strike / smile.forward).ln().abs() <= 1.0));
+        for (strike, vol) in smile.strikes.iter().zip(&smile.implied_vols) {
+            let vol = vol.unwrap();
+            assert!((vol - 0.55).abs() < 1e-4, "strike {}: {}", strike, vol);
+        }
+        let i = smile.len() / 2;
+        let parity = smile.calls[i] - smile.puts[i] - smile.discount * (smile.forward - smile.strikes[i]);
+        assert!(parity.abs() < 1e-9);
+
+        // The far wings are returned too, without vols where no time value survives
+        let full = option(0.5).smile(&FftSettings::default(), f64::INFINITY).unwrap();
+        assert_eq!(full.len(), FftSettings::default().n);
+        assert!(full.implied_vols.iter().any(Option::is_none));
+        assert!(full.vol_points().len() < full.len());
+    }
+
+    #[test]
+    fn test_heston_smile_matches_per_strike_vols_and_fits_surface() {
+        let params = HestonParams { v0: 0.25, kappa: 1.5, theta: 0.25, sigma: 1.5, rho: 0.0 };
+        let mut points = Vec::new();
+        for expiry in [0.25, 1.0] {
+            let smile = price_smile(&params.model(100.0, 100.0, 0.0, expiry), &FftSettings::default(), 0.4);
+            for (strike, vol) in smile.strikes.iter().zip(&smile.implied_vols).step_by(20) {
+                let expected = params.implied_vol(100.0, *strike, 0.0, expiry).unwrap();
+                assert!((vol.unwrap() - expected).abs() < 5e-4, "strike {}: {:?} vs {}", strike, vol, expected);
+            }
+            points.extend(smile.vol_points());
+        }
+
+        let surface = VolSurface::fit(100.0, &YieldCurve::flat(0.0), &points).unwrap();
+        let expected = params.implied_vol(100.0, 110.0, 0.0, 1.0).unwrap();
+        assert!((surface.implied_vol(110.0, 1.0) - expected).abs() < 5e-3);
+    }
+}
@@ -0,0 +1,535 @@
+use std::collections::BTreeSet;
+use std::time::Duration;
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, sleep_until};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+use model::model::{Asset, Basket};
+use crate::data::DeribitOptionData;
+use crate::freshness::now_millis;
+
+pub const DERIBIT_WS_URL: &str = "wss://www.deribit.com/ws/api/v2";
+
+
+/// A Deribit subscription channel for one instrument.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Channel {
+    Ticker { instrument_name: String, interval: String },
+    Book { instrument_name: String, interval: String },
+}
+
+impl Channel {
+    /// Ticker at Deribit's 100ms aggregation, the finest available without authentication.
+    pub fn ticker(instrument_name: &str) -> Channel {
+        Channel::Ticker { instrument_name: instrument_name.to_string(), interval: "100ms".to_string() }
+    }
+
+    pub fn book(instrument_name: &str) -> Channel {
+        Channel::Book { instrument_name: instrument_name.to_string(), interval: "100ms".to_string() }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Channel::Ticker { instrument_name, interval } => format!("ticker.{}.{}", instrument_name, interval),
+            Channel::Book { instrument_name, interval } => format!("book.{}.{}", instrument_name, interval),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct TickerGreeks {
+    pub delta: f64,
+    pub gamma: f64,
+    pub vega: f64,
+    pub theta: f64,
+    pub rho: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TickerUpdate {
+    pub instrument_name: String,
+    pub timestamp: u64,
+    pub mark_price: f64,
+    /// In percent, as Deribit quotes it.
+    pub mark_iv: Option<f64>,
+    pub index_price: Option<f64>,
+    pub underlying_price: Option<f64>,
+    pub best_bid_price: Option<f64>,
+    pub best_ask_price: Option<f64>,
+    pub greeks: Option<TickerGreeks>,
+    /// When the notification arrived, in Unix milliseconds.
+    #[serde(default)]
+    pub received_at: Option<u64>,
+}
+
+impl TickerUpdate {
+    /// Copies the mark price, IV and greeks onto `option` if it is this update's instrument.
+    pub fn apply(&self, option: &mut DeribitOptionData) -> bool {
+        if option.instrument_name != self.instrument_name {
+            return false;
+        }
+        option.market_price = Some(self.mark_price);
+        option.implied_volatility = self.mark_iv.or(option.implied_volatility);
+        option.exchange_timestamp = Some(self.timestamp);
+        option.received_at = self.received_at;
+        if let Some(greeks) = self.greeks {
+            option.delta = Some(greeks.delta);
+            option.gamma = Some(greeks.gamma);
+            option.vega = Some(greeks.vega);
+            option.theta = Some(greeks.theta);
+        }
+        true
+    }
+
+    /// Reprices `asset` in `basket` at the underlying price, or the index if there is none.
+    pub fn update_basket(&self, basket: &mut Basket, asset: &Asset) {
+        if let Some(price) = self.underlying_price.or(self.index_price) {
+            basket.update_price(asset, price);
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookAction {
+    New,
+    Change,
+    Delete,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BookLevel {
+    pub action: BookAction,
+    pub price: f64,
+    pub amount: f64,
+}
+
+/// A full snapshot, sent after every (re)subscription, or the changes since `prev_change_id`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookUpdate {
+    pub instrument_name: String,
+    pub timestamp: u64,
+    pub change_id: u64,
+    pub prev_change_id: Option<u64>,
+    pub is_snapshot: bool,
+    pub bids: Vec<BookLevel>,
+    pub asks: Vec<BookLevel>,
+    pub received_at: Option<u64>,
+}
+
+impl BookUpdate {
+    fn from_value(data: &Value) -> Option<BookUpdate> {
+        let levels = |side: &str| -> Option<Vec<BookLevel>> {
+            data.get(side)?.as_array()?.iter()
+                .map(|level| {
+                    let action = match level.get(0)?.as_str()? {
+                        "new" => BookAction::New,
+                        "change" => BookAction::Change,
+                        "delete" => BookAction::Delete,
+                        _ => return None,
+                    };
+                    Some(BookLevel { action, price: level.get(1)?.as_f64()?, amount: level.get(2)?.as_f64()? })
+                })
+                .collect()
+        };
+        Some(BookUpdate {
+            instrument_name: data.get("instrument_name")?.as_str()?.to_string(),
+            timestamp: data.get("timestamp")?.as_u64()?,
+            change_id: data.get("change_id")?.as_u64()?,
+            prev_change_id: data.get("prev_change_id").and_then(Value::as_u64),
+            is_snapshot: data.get("type").and_then(Value::as_str) == Some("snapshot"),
+            bids: levels("bids")?,
+            asks: levels("asks")?,
+            received_at: None,
+        })
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamUpdate {
+    Ticker(TickerUpdate),
+    Book(BookUpdate),
+    /// Connected and subscribed, initially or after a reconnect. Book consumers should
+    /// discard their books and wait for fresh snapshots.
+    Connected,
+    Disconnected(String),
+}
+
+
+/// What a server message asks of the client.
+#[derive(Debug, Clone, PartialEq)]
+enum Incoming {
+    Update(StreamUpdate),
+    /// Heartbeat probe that must be answered with `public/test`.
+    TestRequest,
+    Error(String),
+    Other,
+}
+
+fn parse_message(text: &str) -> Incoming {
+    let Ok(message) = serde_json::from_str::<Value>(text) else {
+        return Incoming::Error(format!("malformed message: {}", text));
+    };
+    if let Some(error) = message.get("error") {
+        return Incoming::Error(error.to_string());
+    }
+    let params = message.get("params");
+    match message.get("method").and_then(Value::as_str) {
+        Some("heartbeat") if params.and_then(|p| p.get("type")).and_then(Value::as_str) == Some("test_request") => Incoming::TestRequest,
+        Some("subscription") => {
+            let (Some(channel), Some(data)) = (params.and_then(|p| p.get("channel")).and_then(Value::as_str), params.and_then(|p| p.get("data"))) else {
+                return Incoming::Other;
+            };
+            let received_at = Some(now_millis());
+            let update = if channel.starts_with("ticker.") {
+                TickerUpdate::deserialize(data).ok().map(|ticker| StreamUpdate::Ticker(TickerUpdate { received_at, ..ticker }))
+            } else if channel.starts_with("book.") {
+                BookUpdate::from_value(data).map(|book| StreamUpdate::Book(BookUpdate { received_at, ..book }))
+            } else {
+                None
+            };
+            update.map(Incoming::Update).unwrap_or_else(|| Incoming::Error(format!("unreadable {} notification", channel)))
+        }
+        _ => Incoming::Other,
+    }
+}
+
+fn request(id: u64, method: &str, params: Value) -> Message {
+    Message::Text(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }).to_string())
+}
+
+fn channel_names<'a>(channels: impl IntoIterator<Item = &'a Channel>) -> Vec<String> {
+    channels.into_iter().map(Channel::name).collect()
+}
+
+
+/// Connection behaviour. The server is asked for a heartbeat every `heartbeat_interval`
+/// seconds (at least 10 on Deribit); a connection silent for two intervals is dropped.
+/// Reconnects back off exponentially from `reconnect_delay` up to `max_reconnect_delay`.
+#[derive(Debug, Clone)]
+pub struct StreamSettings {
+    pub url: String,
+    pub heartbeat_interval: u64,
+    pub reconnect_delay: Duration,
+    pub max_reconnect_delay: Duration,
+    /// Updates buffered before the connection waits for the consumer.
+    pub buffer: usize,
+}
+
+impl Default for StreamSettings {
+    fn default() -> Self {
+        StreamSettings {
+            url: DERIBIT_WS_URL.to_string(),
+            heartbeat_interval: 30,
+            reconnect_delay: Duration::from_millis(500),
+            max_reconnect_delay: Duration::from_secs(30),
+            buffer: 1024,
+        }
+    }
+}
+
+impl StreamSettings {
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.reconnect_delay.saturating_mul(1u32 << attempt.min(16)).min(self.max_reconnect_delay)
+    }
+}
+
+
+enum Command {
+    Subscribe(Vec<Channel>),
+    Unsubscribe(Vec<Channel>),
+    Close,
+}
+
+enum SessionEnd {
+    Closed,
+    Dropped(String),
+}
+
+
+/// Handle to a background task streaming Deribit channels. Subscriptions survive
+/// reconnects; updates arrive on the receiver returned by [`DeribitStream::connect`].
+pub struct DeribitStream {
+    commands: mpsc::UnboundedSender<Command>,
+    task: JoinHandle<()>,
+}
+
+impl DeribitStream {
+    /// Starts streaming `channels`. Must be called inside a tokio runtime.
+    pub fn connect(channels: Vec<Channel>, settings: StreamSettings) -> (DeribitStream, mpsc::Receiver<StreamUpdate>) {
+        let (commands, command_receiver) = mpsc::unbounded_channel();
+        let (updates, update_receiver) = mpsc::channel(settings.buffer.max(1));
+        let task = tokio::spawn(run(settings, channels.into_iter().collect(), command_receiver, updates));
+        (DeribitStream { commands, task }, update_receiver)
+    }
+
+    pub fn subscribe(&self, channels: Vec<Channel>) {
+        let _ = self.commands.send(Command::Subscribe(channels));
+    }
+
+    pub fn unsubscribe(&self, channels: Vec<Channel>) {
+        let _ = self.commands.send(Command::Unsubscribe(channels));
+    }
+
+    /// Closes the connection and waits for the task to finish.
+    pub async fn close(self) {
+        let _ = self.commands.send(Command::Close);
+        let _ = self.task.await;
+    }
+}
+
+
+async fn run(
+    settings: StreamSettings,
+    mut channels: BTreeSet<Channel>,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+    updates: mpsc::Sender<StreamUpdate>,
+) {
+    let mut attempt = 0;
+    loop {
+        let reason = match connect_async(settings.url.as_str()).await {
+            Ok((socket, _)) => {
+                attempt = 0;
+                match session(socket, &settings, &mut channels, &mut commands, &updates).await {
+                    SessionEnd::Closed => return,
+                    SessionEnd::Dropped(reason) => reason,
+                }
+            }
+            Err(error) => error.to_string(),
+        };
+        if updates.send(StreamUpdate::Disconnected(reason)).await.is_err() {
+            return;
+        }
+
+        // Commands keep arriving while disconnected; subscriptions are applied on reconnect
+        let wake = Instant::now() + settings.backoff(attempt);
+        attempt += 1;
+        loop {
+            tokio::select! {
+                _ = sleep_until(wake) => break,
+                command = commands.recv() => match command {
+                    Some(Command::Subscribe(new)) => channels.extend(new),
+                    Some(Command::Unsubscribe(old)) => old.iter().for_each(|c| { channels.remove(c); }),
+                    Some(Command::Close) | None => return,
+                },
+            }
+        }
+    }
+}
+
+async fn session<S>(
+    socket: tokio_tungstenite::WebSocketStream<S>,
+    settings: &StreamSettings,
+    channels: &mut BTreeSet<Channel>,
+    commands: &mut mpsc::UnboundedReceiver<Command>,
+    updates: &mpsc::Sender<StreamUpdate>,
+) -> SessionEnd
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let (mut write, mut read) = socket.split();
+    let mut next_id = 0u64;
+    let mut id = || { next_id += 1; next_id };
+    let dropped = |error: tokio_tungstenite::tungstenite::Error| SessionEnd::Dropped(error.to_string());
+
+    if let Err(error) = write.send(request(id(), "public/set_heartbeat", json!({ "interval": settings.heartbeat_interval }))).await {
+        return dropped(error);
+    }
+    if !channels.is_empty() {
+        if let Err(error) = write.send(request(id(), "public/subscribe", json!({ "channels": channel_names(channels.iter()) }))).await {
+            return dropped(error);
+        }
+    }
+    if updates.send(StreamUpdate::Connected).await.is_err() {
+        return SessionEnd::Closed;
+    }
+
+    let silence = Duration::from_secs(2 * settings.heartbeat_interval.max(1));
+    let mut deadline = Instant::now() + silence;
+    loop {
+        tokio::select! {
+            message = read.next() => {
+                deadline = Instant::now() + silence;
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None => return SessionEnd::Dropped("connection closed by server".to_string()),
+                    Some(Ok(_)) => continue,
+                    Some(Err(error)) => return dropped(error),
+                };
+                match parse_message(&text) {
+                    Incoming::Update(update) => {
+                        if updates.send(update).await.is_err() {
+                            let _ = write.send(Message::Close(None)).await;
+                            return SessionEnd::Closed;
+                        }
+                    }
+                    Incoming::TestRequest => {
+                        if let Err(error) = write.send(request(id(), "public/test", json!({}))).await {
+                            return dropped(error);
+                        }
+                    }
+                    Incoming::Error(reason) => {
+                        if updates.send(StreamUpdate::Disconnected(reason)).await.is_err() {
+                            return SessionEnd::Closed;
+                        }
+                    }
+                    Incoming::Other => {}
+                }
+            }
+            command = commands.recv() => {
+                let sent = match command {
+                    Some(Command::Subscribe(new)) => {
+                        let names = channel_names(new.iter());
+                        channels.extend(new);
+                        write.send(request(id(), "public/subscribe", json!({ "channels": names }))).await
+                    }
+                    Some(Command::Unsubscribe(old)) => {
+                        old.iter().for_each(|c| { channels.remove(c); });
+                        write.send(request(id(), "public/unsubscribe", json!({ "channels": channel_names(old.iter()) }))).await
+                    }
+                    Some(Command::Close) | None => {
+                        let _ = write.send(Message::Close(None)).await;
+                        return SessionEnd::Closed;
+                    }
+                };
+                if let Err(error) = sent {
+                    return dropped(error);
+                }
+            }
+            _ = sleep_until(deadline) => return SessionEnd::Dropped("no message within two heartbeat intervals".to_string()),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+    use tokio::time::timeout;
+
+    const TICKER: &str = r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"ticker.BTC-27DEC24-60000-C.100ms","data":{
+        "instrument_name":"BTC-27DEC24-60000-C","timestamp":1700000000000,"mark_price":0.052,"mark_iv":61.5,
+        "index_price":36500.0,"underlying_price":36620.5,"best_bid_price":0.051,"best_ask_price":0.0535,
+        "greeks":{"delta":0.31,"gamma":0.00002,"vega":45.1,"theta":-22.3,"rho":8.2},"open_interest":120.0}}}"#;
+
+    const BOOK: &str = r#"{"jsonrpc":"2.0","method":"subscription","params":{"channel":"book.BTC-27DEC24-60000-C.100ms","data":{
+        "type":"change","timestamp":1700000000100,"instrument_name":"BTC-27DEC24-60000-C","change_id":12,"prev_change_id":11,
+        "bids":[["change",0.051,12.0],["delete",0.05,0.0]],"asks":[["new",0.054,3.5]]}}}"#;
+
+    #[test]
+    fn test_parses_notifications_and_heartbeats() {
+        let Incoming::Update(StreamUpdate::Ticker(ticker)) = parse_message(TICKER) else { panic!("expected a ticker") };
+        assert_eq!(ticker.mark_iv, Some(61.5));
+        assert_eq!(ticker.greeks.unwrap().delta, 0.31);
+
+        let Incoming::Update(StreamUpdate::Book(book)) = parse_message(BOOK) else { panic!("expected a book update") };
+        assert!(!book.is_snapshot);
+        assert_eq!(book.prev_change_id, Some(11));
+        assert_eq!(book.bids[1], BookLevel { action: BookAction::Delete, price: 0.05, amount: 0.0 });
+
+        assert_eq!(parse_message(r#"{"jsonrpc":"2.0","method":"heartbeat","params":{"type":"test_request"}}"#), Incoming::TestRequest);
+        assert_eq!(parse_message(r#"{"jsonrpc":"2.0","method":"heartbeat","params":{"type":"heartbeat"}}"#), Incoming::Other);
+        assert!(matches!(parse_message(r#"{"jsonrpc":"2.0","id":3,"error":{"code":11050,"message":"bad_request"}}"#), Incoming::Error(_)));
+        assert!(matches!(parse_message("not json"), Incoming::Error(_)));
+    }
+
+    #[test]
+    fn test_ticker_updates_option_and_basket() {
+        let Incoming::Update(StreamUpdate::Ticker(ticker)) = parse_message(TICKER) else { panic!("expected a ticker") };
+        let mut option = DeribitOptionData {
+            instrument_name: "BTC-27DEC24-60000-C".to_string(),
+            strike: 60000.0,
+            expiration_timestamp: 1735286400000,
+            option_type: "call".to_string(),
+            price_index: "btc_usd".to_string(),
+            settlement_currency: "BTC".to_string(),
+            implied_volatility: None,
+            market_price: None,
+            delta: None,
+            gamma: None,
+            vega: None,
+            theta: None,
+            open_interest: None,
+            exchange_timestamp: None,
+            received_at: None,
+        };
+        assert!(ticker.apply(&mut option));
+        assert_eq!((option.market_price, option.implied_volatility, option.delta), (Some(0.052), Some(61.5), Some(0.31)));
+
+        let btc = Asset::new("BTC", "USD");
+        let mut basket = Basket { id: model::ids::BasketId(1), assets: vec![model::model::AssetInfo::new(btc.clone(), 2.0, 30000.0)] };
+        ticker.update_basket(&mut basket, &btc);
+        assert_eq!(basket.total_value(), 2.0 * 36620.5);
+
+        assert_eq!(Channel::book("BTC-PERPETUAL").name(), "book.BTC-PERPETUAL.100ms");
+        let settings = StreamSettings::default();
+        assert_eq!(settings.backoff(0), Duration::from_millis(500));
+        assert_eq!(settings.backoff(3), Duration::from_secs(4));
+        assert_eq!(settings.backoff(40), Duration::from_secs(30));
+    }
+
+    /// Reads client requests until one with `method` arrives.
+    async fn expect_request<S>(socket: &mut tokio_tungstenite::WebSocketStream<S>, method: &str) -> Value
+    where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    {
+        loop {
+            if let Some(Ok(Message::Text(text))) = socket.next().await {
+                let request: Value = serde_json::from_str(&text).unwrap();
+                if request["method"] == method {
+                    return request;
+                }
+            }
+        }
+    }
+
+    async fn next(updates: &mut mpsc::Receiver<StreamUpdate>) -> StreamUpdate {
+        timeout(Duration::from_secs(5), updates.recv()).await.unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_resubscribes_after_reconnect_and_answers_heartbeats() {
+        tokio::runtime::Runtime::new().unwrap().block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("ws://{}", listener.local_addr().unwrap());
+
+            let server = tokio::spawn(async move {
+                let mut first = tokio_tungstenite::accept_async(listener.accept().await.unwrap().0).await.unwrap();
+                let subscribe = expect_request(&mut first, "public/subscribe").await;
+                assert_eq!(subscribe["params"]["channels"][0], "ticker.BTC-27DEC24-60000-C.100ms");
+                first.send(Message::Text(TICKER.to_string())).await.unwrap();
+                first.send(Message::Text(r#"{"jsonrpc":"2.0","method":"heartbeat","params":{"type":"test_request"}}"#.to_string())).await.unwrap();
+                expect_request(&mut first, "public/test").await;
+                drop(first);
+
+                let mut second = tokio_tungstenite::accept_async(listener.accept().await.unwrap().0).await.unwrap();
+                let subscribe = expect_request(&mut second, "public/subscribe").await;
+                assert_eq!(subscribe["params"]["channels"].as_array().unwrap().len(), 2);
+                second.send(Message::Text(BOOK.to_string())).await.unwrap();
+                expect_request(&mut second, "public/unsubscribe").await;
+            });
+
+            let settings = StreamSettings { url, reconnect_delay: Duration::from_millis(10), ..StreamSettings::default() };
+            let (stream, mut updates) = DeribitStream::connect(vec![Channel::ticker("BTC-27DEC24-60000-C")], settings);
+
+            assert_eq!(next(&mut updates).await, StreamUpdate::Connected);
+            assert!(matches!(next(&mut updates).await, StreamUpdate::Ticker(_)));
+            // Subscriptions made while connected are kept for the next connection
+            stream.subscribe(vec![Channel::book("BTC-27DEC24-60000-C")]);
+            assert!(matches!(next(&mut updates).await, StreamUpdate::Disconnected(_)));
+            assert_eq!(next(&mut updates).await, StreamUpdate::Connected);
+            assert!(matches!(next(&mut updates).await, StreamUpdate::Book(_)));
+
+            stream.unsubscribe(vec![Channel::ticker("BTC-27DEC24-60000-C")]);
+            timeout(Duration::from_secs(5), server).await.unwrap().unwrap();
+            stream.close().await;
+        });
+    }
+}
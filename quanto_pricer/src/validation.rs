@@ -0,0 +1,146 @@
+use crate::fourier::{CharacteristicModel, OptionPrice};
+
+/// Slack allowed for numerical noise, relative to the larger of the discounted forward and strike.
+const RELATIVE_TOLERANCE: f64 = 1e-6;
+
+
+/// A no-arbitrage condition a call/put pair breaks.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PriceViolation {
+    NotFinite { strike: f64 },
+    /// C - P differs from D (F - K) by `gap`.
+    ParityGap { strike: f64, gap: f64 },
+    BelowIntrinsic { strike: f64, is_call: bool, price: f64, intrinsic: f64 },
+    /// A call is worth at most the discounted forward, a put at most the discounted strike.
+    AboveUpperBound { strike: f64, is_call: bool, price: f64, bound: f64 },
+    /// Calls must fall and puts rise with strike, by no more than the discounted strike step.
+    NotMonotone { lower_strike: f64, upper_strike: f64, is_call: bool },
+}
+
+
+/// Checks put–call parity and the bounds D (F - K)⁺ ≤ C ≤ D F and D (K - F)⁺ ≤ P ≤ D K.
+pub fn validate_price<M: CharacteristicModel + ?Sized>(model: &M, strike: f64, price: &OptionPrice) -> Vec<PriceViolation> {
+    if !(price.call.is_finite() && price.put.is_finite()) {
+        return vec![PriceViolation::NotFinite { strike }];
+    }
+    let discount = model.discount();
+    let forward = model.forward();
+    let tolerance = RELATIVE_TOLERANCE * discount * forward.max(strike);
+    let mut violations = Vec::new();
+
+    let gap = price.call - price.put - discount * (forward - strike);
+    if gap.abs() > tolerance {
+        violations.push(PriceViolation::ParityGap { strike, gap });
+    }
+    for (is_call, value, intrinsic, bound) in [
+        (true, price.call, discount * (forward - strike).max(0.0), discount * forward),
+        (false, price.put, discount * (strike - forward).max(0.0), discount * strike),
+    ] {
+        if value < intrinsic - tolerance {
+            violations.push(PriceViolation::BelowIntrinsic { strike, is_call, price: value, intrinsic });
+        }
+        if value > bound + tolerance {
+            violations.push(PriceViolation::AboveUpperBound { strike, is_call, price: value, bound });
+        }
+    }
+    violations
+}
+
+/// Validates each price of a strip of one maturity, then monotonicity between neighbouring strikes.
+pub fn validate_strikes<M: CharacteristicModel + ?Sized>(model: &M, prices: &[(f64, OptionPrice)]) -> Vec<PriceViolation> {
+    let mut violations: Vec<PriceViolation> = prices.iter()
+        .flat_map(|(strike, price)| validate_price(model, *strike, price))
+        .collect();
+
+    let mut sorted: Vec<&(f64, OptionPrice)> = prices.iter().collect();
+    sorted.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    let discount = model.discount();
+
+    for pair in sorted.windows(2) {
+        let ((lower_strike, lower), (upper_strike, upper)) = (pair[0], pair[1]);
+        let step = discount * (upper_strike - lower_strike);
+        let tolerance = RELATIVE_TOLERANCE * discount * model.forward().max(*upper_strike);
+        let call_drop = lower.call - upper.call;
+        let put_rise = upper.put - lower.put;
+
+        if call_drop < -tolerance || call_drop > step + tolerance {
+            violations.push(PriceViolation::NotMonotone { lower_strike: *lower_strike, upper_strike: *upper_strike, is_call: true });
+        }
+        if put_rise < -tolerance || put_rise > step + tolerance {
+            violations.push(PriceViolation::NotMonotone { lower_strike: *lower_strike, upper_strike: *upper_strike, is_call: false });
+        }
+    }
+    violations
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fourier::{FftSettings, QuantoOption};
+
+    fn option() -> QuantoOption {
+        QuantoOption {
+            spot: 100.0,
+            strike: 100.0,
+            domestic_rate: 0.05,
+            foreign_rate: 0.0,
+            volatility: 0.2,
+            fx_volatility: 0.0,
+            time_to_maturity: 1.0,
+            correlation: 0.0,
+            dividend_yield: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_fft_prices_pass_validation() {
+        let option = option();
+        let strikes = [60.0, 80.0, 95.0, 100.0, 105.0, 130.0];
+        let prices: Vec<(f64, OptionPrice)> = strikes.iter()
+            .zip(option.price_strikes_fft(&strikes, &FftSettings::default()).unwrap())
+            .map(|(strike, price)| (*strike, price.unwrap()))
+            .collect();
+
+        assert!(validate_strikes(&option, &prices).is_empty());
+        assert_eq!(option.calculate_price_fft().unwrap().diagnostics.unwrap().violations, 0);
+    }
+
+    #[test]
+    fn test_detects_broken_prices() {
+        let option = option();
+        let discount = (-0.05f64).exp();
+        let fair = option.calculate_price_fft().unwrap();
+
+        let below = OptionPrice::new(0.5 * discount * 20.0, fair.put);
+        let violations = validate_price(&option, 80.0, &below);
+        assert!(violations.iter().any(|v| matches!(v, PriceViolation::BelowIntrinsic { is_call: true, .. })));
+        assert!(violations.iter().any(|v| matches!(v, PriceViolation::ParityGap { .. })));
+
+        let above = OptionPrice::new(fair.call, 200.0);
+        assert!(validate_price(&option, 100.0, &above).iter()
+            .any(|v| matches!(v, PriceViolation::AboveUpperBound { is_call: false, .. })));
+        assert_eq!(validate_price(&option, 100.0, &OptionPrice::new(f64::NAN, 1.0)), vec![PriceViolation::NotFinite { strike: 100.0 }]);
+
+        // A call that rises with strike; under parity the put then rises faster than the strike
+        let strip = [(100.0, fair), (110.0, OptionPrice::new(fair.call + 1.0, fair.put + 1.0 + 10.0 * discount))];
+        assert_eq!(
+            validate_strikes(&option, &strip),
+            vec![
+                PriceViolation::NotMonotone { lower_strike: 100.0, upper_strike: 110.0, is_call: true },
+                PriceViolation::NotMonotone { lower_strike: 100.0, upper_strike: 110.0, is_call: false },
+            ],
+        );
+    }
+
+    #[test]
+    fn test_fft_blowup_is_flagged() {
+        // Too much damping for a long-dated, high-vol option: the damped moment E[S^(α+1)] overflows
+        let option = QuantoOption { volatility: 1.5, time_to_maturity: 5.0, ..option() };
+        let settings = FftSettings { alpha: 40.0, ..FftSettings::default() };
+        assert!(option.calculate_price_fft_with(&settings).unwrap().diagnostics.unwrap().violations > 0);
+
+        let unchecked = FftSettings { validate: false, ..settings };
+        assert_eq!(option.calculate_price_fft_with(&unchecked).unwrap().diagnostics.unwrap().violations, 0);
+    }
+}
@@ -0,0 +1,308 @@
+use crate::curve::YieldCurve;
+use crate::data::DeribitOptionData;
+use crate::implied_vol::black;
+use crate::vol_surface::MILLIS_PER_YEAR;
+use crate::error::PricingError;
+
+
+/// Undiscounted price of the out-of-the-money option at `strike`: a put below the forward,
+/// a call at or above it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StripQuote {
+    pub strike: f64,
+    pub price: f64,
+}
+
+
+/// Out-of-the-money options of one expiry, which together replicate the log contract and
+/// so a variance swap.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplicationStrip {
+    pub forward: f64,
+    /// Years to expiry.
+    pub expiry: f64,
+    pub discount: f64,
+    /// Sorted by strike.
+    pub quotes: Vec<StripQuote>,
+}
+
+impl ReplicationStrip {
+    pub fn new(forward: f64, expiry: f64, discount: f64, mut quotes: Vec<StripQuote>) -> Result<Self, PricingError> {
+        if forward <= 0.0 || expiry <= 0.0 || discount <= 0.0 {
+            return Err(PricingError::InvalidInput("forward, expiry and discount factor must be positive"));
+        }
+        if quotes.iter().any(|q| !(q.strike > 0.0 && q.price >= 0.0)) {
+            return Err(PricingError::InvalidInput("strikes must be positive and prices non-negative"));
+        }
+        quotes.sort_by(|a, b| a.strike.partial_cmp(&b.strike).unwrap_or(std::cmp::Ordering::Equal));
+        quotes.dedup_by(|a, b| a.strike == b.strike);
+        if quotes.len() < 2 {
+            return Err(PricingError::InsufficientData("strip needs at least two strikes"));
+        }
+        Ok(ReplicationStrip { forward, expiry, discount, quotes })
+    }
+
+    /// Strip priced with Black from (strike, implied vol) pairs.
+    pub fn from_vols(forward: f64, expiry: f64, discount: f64, vols: &[(f64, f64)]) -> Result<Self, PricingError> {
+        let quotes = vols.iter()
+            .map(|(strike, vol)| StripQuote {
+                strike: *strike,
+                price: black(forward, *strike, vol * expiry.sqrt(), *strike >= forward),
+            })
+            .collect();
+        ReplicationStrip::new(forward, expiry, discount, quotes)
+    }
+
+    /// Strip for the Deribit expiry at `expiration_ms`, from the mark implied vols of its
+    /// options. Each strike's vol is taken from its out-of-the-money side when quoted.
+    pub fn from_chain(
+        options: &[DeribitOptionData],
+        spot: f64,
+        curve: &YieldCurve,
+        now_ms: u64,
+        expiration_ms: u64,
+    ) -> Result<Self, PricingError> {
+        if expiration_ms <= now_ms {
+            return Err(PricingError::InvalidInput("expiry must be in the future"));
+        }
+        let expiry = (expiration_ms - now_ms) as f64 / MILLIS_PER_YEAR;
+        let discount = curve.discount(expiry);
+        let forward = spot / discount;
+
+        let mut vols: Vec<(f64, f64, bool)> = Vec::new();
+        for option in options.iter().filter(|o| o.expiration_timestamp == expiration_ms) {
+            let Some(vol) = option.implied_volatility.filter(|v| *v > 0.0) else { continue };
+            let out_of_the_money = (option.option_type == "call") == (option.strike >= forward);
+            match vols.iter_mut().find(|(strike, _, _)| *strike == option.strike) {
+                Some(entry) if out_of_the_money && !entry.2 => *entry = (option.strike, vol / 100.0, true),
+                Some(_) => {}
+                None => vols.push((option.strike, vol / 100.0, out_of_the_money)),
+            }
+        }
+        let vols: Vec<(f64, f64)> = vols.iter().map(|(strike, vol, _)| (*strike, *vol)).collect();
+        ReplicationStrip::from_vols(forward, expiry, discount, &vols)
+    }
+
+    /// Fair variance by the discretised log-contract replication used for the VIX and DVOL:
+    /// (2 / T) Σ ΔK / K² Q(K) - (F / K₀ - 1)² / T, with K₀ the first strike at or below the forward.
+    pub fn fair_variance(&self) -> f64 {
+        let n = self.quotes.len();
+        let k0 = self.quotes.iter().rev()
+            .find(|q| q.strike <= self.forward)
+            .unwrap_or(&self.quotes[0])
+            .strike;
+
+        let mut sum = 0.0;
+        for (i, quote) in self.quotes.iter().enumerate() {
+            let delta = match i {
+                0 => self.quotes[1].strike - quote.strike,
+                i if i == n - 1 => quote.strike - self.quotes[n - 2].strike,
+                _ => 0.5 * (self.quotes[i + 1].strike - self.quotes[i - 1].strike),
+            };
+            // At K₀ the put and call are averaged; the call is the put plus the forward's intrinsic
+            let price = if quote.strike == k0 { quote.price + 0.5 * (self.forward - k0).max(0.0) } else { quote.price };
+            sum += delta / (quote.strike * quote.strike) * price;
+        }
+        (2.0 * sum - (self.forward / k0 - 1.0).powi(2)) / self.expiry
+    }
+
+    /// Square root of the fair variance: the DVOL-style index level as a decimal.
+    pub fn fair_volatility(&self) -> f64 {
+        self.fair_variance().max(0.0).sqrt()
+    }
+}
+
+
+/// Realized variance over the life of a swap: `elapsed` years already realized at
+/// `realized_variance`, the rest lognormal around the strip's fair variance with volatility
+/// `vol_of_variance`. Returns the known and unknown contributions to the expected total and
+/// the unknown part's total log standard deviation.
+fn variance_components(strip: &ReplicationStrip, vol_of_variance: f64, realized_variance: f64, elapsed: f64) -> (f64, f64, f64) {
+    let weight = elapsed / (elapsed + strip.expiry);
+    (weight * realized_variance, (1.0 - weight) * strip.fair_variance(), vol_of_variance * strip.expiry.sqrt())
+}
+
+/// E[min(X, cap)] for lognormal X with mean `mean` and total standard deviation `s`.
+fn capped_mean(mean: f64, cap: f64, s: f64) -> f64 {
+    if cap <= 0.0 {
+        cap
+    } else if s <= 0.0 || mean <= 0.0 {
+        mean.min(cap)
+    } else {
+        mean - black(mean, cap, s, true)
+    }
+}
+
+
+/// Pays `vega_notional / (2 × strike_vol)` per unit of annualised realized variance above
+/// `strike_vol²`. A cap, as a multiple of the strike vol, limits realized vol at `cap × strike_vol`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VarianceSwap {
+    pub strike_vol: f64,
+    pub vega_notional: f64,
+    pub cap: Option<f64>,
+}
+
+impl VarianceSwap {
+    pub fn new(strike_vol: f64, vega_notional: f64, cap: Option<f64>) -> Self {
+        VarianceSwap { strike_vol, vega_notional, cap }
+    }
+
+    pub fn variance_notional(&self) -> f64 {
+        self.vega_notional / (2.0 * self.strike_vol)
+    }
+
+    /// Expected capped realized variance of a new swap.
+    pub fn expected_variance(&self, strip: &ReplicationStrip, vol_of_variance: f64) -> f64 {
+        self.expected_seasoned_variance(strip, vol_of_variance, 0.0, 0.0)
+    }
+
+    fn expected_seasoned_variance(&self, strip: &ReplicationStrip, vol_of_variance: f64, realized_variance: f64, elapsed: f64) -> f64 {
+        let (known, unknown, s) = variance_components(strip, vol_of_variance, realized_variance, elapsed);
+        match self.cap {
+            None => known + unknown,
+            Some(multiple) => known + capped_mean(unknown, (multiple * self.strike_vol).powi(2) - known, s),
+        }
+    }
+
+    /// Strike vol at which a new swap with this cap multiple is worth zero. The fixed point
+    /// is found by iteration, since the cap level moves with the strike.
+    pub fn fair_strike(&self, strip: &ReplicationStrip, vol_of_variance: f64) -> f64 {
+        let mut strike = strip.fair_volatility();
+        for _ in 0..50 {
+            let next = VarianceSwap { strike_vol: strike, ..*self }.expected_variance(strip, vol_of_variance).sqrt();
+            if (next - strike).abs() < 1e-12 {
+                return next;
+            }
+            strike = next;
+        }
+        strike
+    }
+
+    /// Present value to the long of a swap that has run for `elapsed` years, realizing
+    /// `realized_variance`, with the strip's expiry left to run.
+    pub fn value(&self, strip: &ReplicationStrip, vol_of_variance: f64, realized_variance: f64, elapsed: f64) -> f64 {
+        let expected = self.expected_seasoned_variance(strip, vol_of_variance, realized_variance, elapsed);
+        strip.discount * self.variance_notional() * (expected - self.strike_vol.powi(2))
+    }
+}
+
+
+/// Pays `notional` per unit of annualised realized vol above `strike_vol`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VolatilitySwap {
+    pub strike_vol: f64,
+    pub notional: f64,
+}
+
+impl VolatilitySwap {
+    pub fn new(strike_vol: f64, notional: f64) -> Self {
+        VolatilitySwap { strike_vol, notional }
+    }
+
+    /// E[√V] to second order: √E[V] - Var[V] / (8 E[V]^{3/2}), the concavity discount
+    /// of a vol swap to its variance swap.
+    pub fn expected_volatility(strip: &ReplicationStrip, vol_of_variance: f64, realized_variance: f64, elapsed: f64) -> f64 {
+        let (known, unknown, s) = variance_components(strip, vol_of_variance, realized_variance, elapsed);
+        let mean = known + unknown;
+        if mean <= 0.0 {
+            return 0.0;
+        }
+        let variance = unknown * unknown * ((s * s).exp() - 1.0);
+        (mean.sqrt() - variance / (8.0 * mean.powf(1.5))).max(0.0)
+    }
+
+    pub fn fair_strike(strip: &ReplicationStrip, vol_of_variance: f64) -> f64 {
+        VolatilitySwap::expected_volatility(strip, vol_of_variance, 0.0, 0.0)
+    }
+
+    pub fn value(&self, strip: &ReplicationStrip, vol_of_variance: f64, realized_variance: f64, elapsed: f64) -> f64 {
+        let expected = VolatilitySwap::expected_volatility(strip, vol_of_variance, realized_variance, elapsed);
+        strip.discount * self.notional * (expected - self.strike_vol)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip(vol: impl Fn(f64) -> f64) -> ReplicationStrip {
+        let vols: Vec<(f64, f64)> = (10..=600).map(|k| (k as f64 * 0.5, vol(k as f64 * 0.5))).collect();
+        ReplicationStrip::from_vols(100.3, 0.25, 0.99, &vols).unwrap()
+    }
+
+    #[test]
+    fn test_flat_smile_replicates_its_variance() {
+        let strip = strip(|_| 0.4);
+        assert!((strip.fair_variance() - 0.16).abs() < 2e-5, "variance {}", strip.fair_variance());
+
+        // Skew adds out-of-the-money put premium, raising fair variance above ATM variance
+        let skewed = strip_skewed();
+        assert!(skewed.fair_volatility() > 0.41);
+        assert!(ReplicationStrip::new(100.0, 0.25, 0.99, vec![StripQuote { strike: 100.0, price: 1.0 }]).is_err());
+    }
+
+    fn strip_skewed() -> ReplicationStrip {
+        strip(|k| 0.4 - 0.2 * (k / 100.3).ln())
+    }
+
+    #[test]
+    fn test_cap_and_vol_swap_convexity() {
+        let strip = strip_skewed();
+        let uncapped = VarianceSwap::new(strip.fair_volatility(), 10_000.0, None);
+        assert!(uncapped.value(&strip, 1.0, 0.0, 0.0).abs() < 1e-9);
+
+        let capped = VarianceSwap::new(0.4, 10_000.0, Some(1.2));
+        let fair = capped.fair_strike(&strip, 1.5);
+        assert!(fair < strip.fair_volatility());
+        // A generous cap is worth next to nothing
+        let loose = VarianceSwap::new(0.4, 10_000.0, Some(10.0));
+        assert!((loose.fair_strike(&strip, 1.5) - strip.fair_volatility()).abs() < 1e-6);
+        assert!(VarianceSwap { strike_vol: fair, ..capped }.value(&strip, 1.5, 0.0, 0.0).abs() < 1e-6);
+
+        let vol_fair = VolatilitySwap::fair_strike(&strip, 1.5);
+        assert!(vol_fair < strip.fair_volatility());
+        assert!((VolatilitySwap::fair_strike(&strip, 0.0) - strip.fair_volatility()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_seasoned_swap_and_deribit_chain() {
+        use crate::data::DeribitOptionData;
+
+        let now = 1_700_000_000_000u64;
+        let expiration = now + (0.25 * MILLIS_PER_YEAR) as u64;
+        let mut options = Vec::new();
+        for k in 10..=600 {
+            for option_type in ["call", "put"] {
+                options.push(DeribitOptionData {
+                    instrument_name: format!("BTC-{}", k),
+                    strike: k as f64 * 0.5,
+                    expiration_timestamp: expiration,
+                    option_type: option_type.to_string(),
+                    price_index: "btc_usd".to_string(),
+                    settlement_currency: "BTC".to_string(),
+                    implied_volatility: Some(40.0),
+                    market_price: Some(0.01),
+                    delta: None,
+                    gamma: None,
+                    vega: None,
+                    theta: None,
+                    open_interest: None,
+                    exchange_timestamp: None,
+                    received_at: None,
+                });
+            }
+        }
+        let strip = ReplicationStrip::from_chain(&options, 100.0, &YieldCurve::flat(0.03), now, expiration).unwrap();
+        assert_eq!(strip.quotes.len(), 591);
+        assert!((strip.fair_volatility() - 0.4).abs() < 1e-3);
+
+        // Half the life has realized 60% vol against a 40% strike
+        let swap = VarianceSwap::new(0.4, 10_000.0, None);
+        let value = swap.value(&strip, 1.0, 0.36, 0.25);
+        let expected = strip.discount * swap.variance_notional() * (0.5 * 0.36 + 0.5 * strip.fair_variance() - 0.16);
+        assert!((value - expected).abs() < 1e-9 && value > 0.0);
+        assert!(VolatilitySwap::new(0.4, 10_000.0).value(&strip, 1.0, 0.36, 0.25) > 0.0);
+    }
+}
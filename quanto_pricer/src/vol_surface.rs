@@ -0,0 +1,472 @@
+use statrs::distribution::{Normal, ContinuousCDF};
+use crate::curve::YieldCurve;
+use crate::data::DeribitOptionData;
+use crate::optimize::{nelder_mead, solve_linear};
+use crate::error::PricingError;
+
+pub const MILLIS_PER_YEAR: f64 = 365.25 * 24.0 * 3600.0 * 1000.0;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VolPoint {
+    pub strike: f64,
+    /// Time to expiry in years.
+    pub expiry: f64,
+    pub implied_vol: f64,
+}
+
+
+/// Raw SVI: total implied variance w(k) = a + b (rho (k - m) + sqrt((k - m)² + sigma²)),
+/// with k the log-moneyness ln(K / F).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SviParams {
+    pub a: f64,
+    pub b: f64,
+    pub rho: f64,
+    pub m: f64,
+    pub sigma: f64,
+}
+
+impl SviParams {
+    pub fn total_variance(&self, k: f64) -> f64 {
+        let x = k - self.m;
+        self.a + self.b * (self.rho * x + (x * x + self.sigma * self.sigma).sqrt())
+    }
+
+    fn first_derivative(&self, k: f64) -> f64 {
+        let x = k - self.m;
+        self.b * (self.rho + x / (x * x + self.sigma * self.sigma).sqrt())
+    }
+
+    fn second_derivative(&self, k: f64) -> f64 {
+        let x = k - self.m;
+        let s2 = self.sigma * self.sigma;
+        self.b * s2 / (x * x + s2).powf(1.5)
+    }
+
+    /// b ≥ 0, |rho| < 1, sigma > 0 and a non-negative minimum variance.
+    pub fn is_valid(&self) -> bool {
+        self.b >= 0.0
+            && self.rho.abs() < 1.0
+            && self.sigma > 0.0
+            && self.a + self.b * self.sigma * (1.0 - self.rho * self.rho).sqrt() >= 0.0
+    }
+
+    /// Gatheral's density function g(k); a negative value means butterfly arbitrage.
+    pub fn butterfly_density(&self, k: f64) -> f64 {
+        let w = self.total_variance(k);
+        let w1 = self.first_derivative(k);
+        let w2 = self.second_derivative(k);
+        (1.0 - k * w1 / (2.0 * w)).powi(2) - w1 * w1 / 4.0 * (1.0 / w + 0.25) + w2 / 2.0
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SviSlice {
+    pub expiry: f64,
+    pub forward: f64,
+    pub params: SviParams,
+    /// Root mean square error of the fit, in implied volatility.
+    pub rmse: f64,
+}
+
+impl SviSlice {
+    /// Fits raw SVI to one expiry with the quasi-explicit method: for fixed (m, sigma) the
+    /// remaining parameters solve a linear least-squares problem, and (m, sigma) are
+    /// found by Nelder–Mead.
+    pub fn fit(expiry: f64, forward: f64, points: &[(f64, f64)]) -> Result<SviSlice, PricingError> {
+        if points.len() < 5 {
+            return Err(PricingError::InsufficientData("SVI needs at least five quotes per expiry"));
+        }
+        if expiry <= 0.0 || forward <= 0.0 {
+            return Err(PricingError::InvalidInput("expiry and forward must be positive"));
+        }
+        let observations: Vec<(f64, f64)> = points.iter()
+            .map(|(strike, vol)| ((strike / forward).ln(), vol * vol * expiry))
+            .collect();
+
+        let inner = |m: f64, sigma: f64| -> Option<(SviParams, f64)> {
+            if sigma <= 1e-6 {
+                return None;
+            }
+            // w = a + d y + c sqrt(y² + 1) with y = (k - m) / sigma
+            let mut normal = vec![vec![0.0; 3]; 3];
+            let mut rhs = vec![0.0; 3];
+            for (k, w) in &observations {
+                let y = (k - m) / sigma;
+                let basis = [1.0, y, (y * y + 1.0).sqrt()];
+                for i in 0..3 {
+                    for j in 0..3 {
+                        normal[i][j] += basis[i] * basis[j];
+                    }
+                    rhs[i] += basis[i] * w;
+                }
+            }
+            let solution = solve_linear(normal, rhs)?;
+            let (a, d, c) = (solution[0], solution[1], solution[2]);
+            if c <= 0.0 {
+                return None;
+            }
+            let params = SviParams { a, b: c / sigma, rho: d / c, m, sigma };
+            if !params.is_valid() {
+                return None;
+            }
+            let error: f64 = observations.iter().map(|(k, w)| (params.total_variance(*k) - w).powi(2)).sum();
+            Some((params, error))
+        };
+
+        let objective = |x: &[f64]| inner(x[0], x[1]).map(|(_, error)| error).unwrap_or(1e10);
+        let minimum = nelder_mead(objective, &[0.0, 0.1], &[0.1, 0.1], 1e-16, 2000);
+        let (params, _) = inner(minimum.x[0], minimum.x[1]).ok_or(PricingError::Calibration("SVI fit did not converge to valid parameters"))?;
+
+        let rmse = (points.iter()
+            .map(|(strike, vol)| {
+                let fitted = (params.total_variance((strike / forward).ln()).max(0.0) / expiry).sqrt();
+                (fitted - vol).powi(2)
+            })
+            .sum::<f64>() / points.len() as f64)
+            .sqrt();
+
+        Ok(SviSlice { expiry, forward, params, rmse })
+    }
+
+    pub fn implied_vol(&self, strike: f64) -> f64 {
+        (self.params.total_variance((strike / self.forward).ln()).max(0.0) / self.expiry).sqrt()
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArbitrageViolation {
+    /// Negative density at log-moneyness `k`.
+    Butterfly { expiry: f64, k: f64, density: f64 },
+    /// Total variance decreases from `expiry` to `next_expiry` at log-moneyness `k`.
+    Calendar { expiry: f64, next_expiry: f64, k: f64 },
+}
+
+
+/// Static arbitrage in the raw quotes. Each variant names the quote to drop.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QuoteViolation {
+    /// The call price at `strike` lies above the chord of its neighbouring strikes.
+    Butterfly { expiry: f64, strike: f64 },
+    /// The quote at `strike` has more total variance than `next_expiry` at the same
+    /// log-moneyness.
+    Calendar { expiry: f64, next_expiry: f64, strike: f64 },
+}
+
+impl QuoteViolation {
+    /// (strike, expiry) of the offending quote.
+    pub fn quote(&self) -> (f64, f64) {
+        match self {
+            QuoteViolation::Butterfly { expiry, strike } => (*strike, *expiry),
+            QuoteViolation::Calendar { expiry, strike, .. } => (*strike, *expiry),
+        }
+    }
+}
+
+
+/// Undiscounted Black call price.
+fn black_call(forward: f64, strike: f64, std_dev: f64) -> f64 {
+    let normal = Normal::new(0.0, 1.0).unwrap();
+    let d1 = ((forward / strike).ln() + 0.5 * std_dev * std_dev) / std_dev;
+    forward * normal.cdf(d1) - strike * normal.cdf(d1 - std_dev)
+}
+
+
+/// Implied volatility surface made of one SVI slice per expiry, interpolated linearly in
+/// total variance along constant log-moneyness.
+#[derive(Debug, Clone)]
+pub struct VolSurface {
+    pub spot: f64,
+    pub curve: YieldCurve,
+    pub slices: Vec<SviSlice>,
+}
+
+impl VolSurface {
+    /// Groups the points by expiry and fits one slice per expiry, with forwards
+    /// spot / discount(expiry) from the curve.
+    pub fn fit(spot: f64, curve: &YieldCurve, points: &[VolPoint]) -> Result<VolSurface, PricingError> {
+        let mut expiries: Vec<f64> = Vec::new();
+        for point in points {
+            if !expiries.iter().any(|e| (e - point.expiry).abs() < 1e-9) {
+                expiries.push(point.expiry);
+            }
+        }
+        expiries.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        let slices = expiries.iter()
+            .map(|expiry| {
+                let quotes: Vec<(f64, f64)> = points.iter()
+                    .filter(|p| (p.expiry - expiry).abs() < 1e-9)
+                    .map(|p| (p.strike, p.implied_vol))
+                    .collect();
+                SviSlice::fit(*expiry, spot / curve.discount(*expiry), &quotes)
+            })
+            .collect::<Result<Vec<SviSlice>, PricingError>>()?;
+        if slices.is_empty() {
+            return Err(PricingError::InsufficientData("no quotes to build a surface from"));
+        }
+
+        Ok(VolSurface { spot, curve: curve.clone(), slices })
+    }
+
+    /// Checks raw quotes for static arbitrage before fitting: convexity of call prices in
+    /// strike within each expiry, and non-decreasing total variance along constant
+    /// log-moneyness between consecutive expiries.
+    pub fn validate(spot: f64, curve: &YieldCurve, points: &[VolPoint]) -> Vec<QuoteViolation> {
+        let mut expiries: Vec<f64> = Vec::new();
+        for point in points {
+            if !expiries.iter().any(|e| (e - point.expiry).abs() < 1e-9) {
+                expiries.push(point.expiry);
+            }
+        }
+        expiries.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        // (log-moneyness, strike, total variance, undiscounted call) per expiry, by strike
+        let slices: Vec<Vec<(f64, f64, f64, f64)>> = expiries.iter()
+            .map(|expiry| {
+                let forward = spot / curve.discount(*expiry);
+                let mut slice: Vec<(f64, f64, f64, f64)> = points.iter()
+                    .filter(|p| (p.expiry - expiry).abs() < 1e-9)
+                    .map(|p| {
+                        let variance = p.implied_vol * p.implied_vol * expiry;
+                        ((p.strike / forward).ln(), p.strike, variance, black_call(forward, p.strike, variance.sqrt()))
+                    })
+                    .collect();
+                slice.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+                slice
+            })
+            .collect();
+
+        let mut violations = Vec::new();
+        for (expiry, slice) in expiries.iter().zip(&slices) {
+            let tolerance = 1e-10 * spot / curve.discount(*expiry);
+            for triple in slice.windows(3) {
+                let ((_, k1, _, c1), (_, k2, _, c2), (_, k3, _, c3)) = (triple[0], triple[1], triple[2]);
+                let chord = ((k3 - k2) * c1 + (k2 - k1) * c3) / (k3 - k1);
+                if c2 > chord + tolerance {
+                    violations.push(QuoteViolation::Butterfly { expiry: *expiry, strike: k2 });
+                }
+            }
+        }
+
+        for (pair, slices) in expiries.windows(2).zip(slices.windows(2)) {
+            let later = &slices[1];
+            for (k, strike, variance, _) in &slices[0] {
+                // Total variance of the later expiry interpolated linearly in log-moneyness,
+                // skipping quotes outside its strike range
+                let Some(right) = later.iter().position(|q| q.0 >= *k) else { continue };
+                let next_variance = if later[right].0 == *k {
+                    later[right].2
+                } else if right == 0 {
+                    continue;
+                } else {
+                    let (left, right) = (&later[right - 1], &later[right]);
+                    left.2 + (right.2 - left.2) * (k - left.0) / (right.0 - left.0)
+                };
+                if *variance > next_variance + 1e-12 {
+                    violations.push(QuoteViolation::Calendar { expiry: pair[0], next_expiry: pair[1], strike: *strike });
+                }
+            }
+        }
+        violations
+    }
+
+    /// `points` without the quotes named by `violations`.
+    pub fn without_violations(points: &[VolPoint], violations: &[QuoteViolation]) -> Vec<VolPoint> {
+        points.iter()
+            .filter(|p| !violations.iter().any(|v| {
+                let (strike, expiry) = v.quote();
+                strike == p.strike && (expiry - p.expiry).abs() < 1e-9
+            }))
+            .copied()
+            .collect()
+    }
+
+    /// Converts Deribit quotes (mark IV in percent) into surface points as of `now_ms`.
+    pub fn points_from_deribit(options: &[DeribitOptionData], now_ms: u64) -> Vec<VolPoint> {
+        options.iter()
+            .filter(|o| o.expiration_timestamp > now_ms)
+            .filter_map(|o| o.implied_volatility.filter(|iv| *iv > 0.0).map(|iv| VolPoint {
+                strike: o.strike,
+                expiry: (o.expiration_timestamp - now_ms) as f64 / MILLIS_PER_YEAR,
+                implied_vol: iv / 100.0,
+            }))
+            .collect()
+    }
+
+    pub fn forward(&self, expiry: f64) -> f64 {
+        self.spot / self.curve.discount(expiry)
+    }
+
+    /// Total implied variance at log-moneyness `k`. Before the first expiry the implied
+    /// vol of the first slice is held, beyond the last one that of the last slice.
+    pub fn total_variance(&self, k: f64, expiry: f64) -> f64 {
+        let first = &self.slices[0];
+        let last = &self.slices[self.slices.len() - 1];
+        if expiry <= first.expiry {
+            return first.params.total_variance(k) * expiry / first.expiry;
+        }
+        if expiry >= last.expiry {
+            return last.params.total_variance(k) * expiry / last.expiry;
+        }
+
+        let next = self.slices.iter().position(|s| s.expiry >= expiry).unwrap_or(self.slices.len() - 1);
+        let (before, after) = (&self.slices[next - 1], &self.slices[next]);
+        let weight = (expiry - before.expiry) / (after.expiry - before.expiry);
+        (1.0 - weight) * before.params.total_variance(k) + weight * after.params.total_variance(k)
+    }
+
+    pub fn implied_vol(&self, strike: f64, expiry: f64) -> f64 {
+        let k = (strike / self.forward(expiry)).ln();
+        (self.total_variance(k, expiry).max(0.0) / expiry).sqrt()
+    }
+
+    /// Black–Scholes price using the surface's implied volatility.
+    pub fn price(&self, strike: f64, expiry: f64, is_call: bool) -> f64 {
+        let forward = self.forward(expiry);
+        let call = black_call(forward, strike, self.implied_vol(strike, expiry) * expiry.sqrt());
+        let discount = self.curve.discount(expiry);
+
+        if is_call {
+            discount * call
+        } else {
+            discount * (call - forward + strike)
+        }
+    }
+
+    /// Checks every slice for butterfly arbitrage and every pair of consecutive slices for
+    /// calendar arbitrage on a log-moneyness grid over [-k_max, k_max].
+    pub fn arbitrage_violations(&self, k_max: f64, points: usize) -> Vec<ArbitrageViolation> {
+        let grid: Vec<f64> = (0..points.max(2))
+            .map(|i| -k_max + 2.0 * k_max * i as f64 / (points.max(2) - 1) as f64)
+            .collect();
+        let mut violations = Vec::new();
+
+        for slice in &self.slices {
+            for k in &grid {
+                let density = slice.params.butterfly_density(*k);
+                if density < 0.0 {
+                    violations.push(ArbitrageViolation::Butterfly { expiry: slice.expiry, k: *k, density });
+                }
+            }
+        }
+        for pair in self.slices.windows(2) {
+            for k in &grid {
+                if pair[1].params.total_variance(*k) < pair[0].params.total_variance(*k) - 1e-12 {
+                    violations.push(ArbitrageViolation::Calendar { expiry: pair[0].expiry, next_expiry: pair[1].expiry, k: *k });
+                }
+            }
+        }
+        violations
+    }
+
+    pub fn is_arbitrage_free(&self) -> bool {
+        self.arbitrage_violations(1.5, 61).is_empty()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHORT: SviParams = SviParams { a: 0.01, b: 0.1, rho: -0.4, m: 0.02, sigma: 0.2 };
+    const LONG: SviParams = SviParams { a: 0.04, b: 0.15, rho: -0.3, m: 0.05, sigma: 0.3 };
+
+    fn quotes(params: &SviParams, expiry: f64, forward: f64) -> Vec<VolPoint> {
+        [0.6, 0.75, 0.9, 1.0, 1.1, 1.25, 1.5, 1.8].iter()
+            .map(|moneyness| {
+                let strike = forward * moneyness;
+                let vol = (params.total_variance(moneyness.ln()) / expiry).sqrt();
+                VolPoint { strike, expiry, implied_vol: vol }
+            })
+            .collect()
+    }
+
+    fn surface() -> VolSurface {
+        let rate: f64 = 0.02;
+        let mut points = quotes(&SHORT, 0.25, 100.0 * (rate * 0.25).exp());
+        points.extend(quotes(&LONG, 1.0, 100.0 * rate.exp()));
+        VolSurface::fit(100.0, &YieldCurve::flat(rate), &points).unwrap()
+    }
+
+    #[test]
+    fn test_svi_fit_recovers_parameters() {
+        let surface = surface();
+        assert_eq!(surface.slices.len(), 2);
+
+        let fitted = surface.slices[0].params;
+        assert!(surface.slices[0].rmse < 1e-6);
+        assert!((fitted.rho - SHORT.rho).abs() < 1e-3);
+        assert!((fitted.sigma - SHORT.sigma).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_interpolation_in_strike_and_time() {
+        let surface = surface();
+        let forward = surface.forward(0.25);
+        let expected = (SHORT.total_variance((95.0 / forward).ln()) / 0.25).sqrt();
+        assert!((surface.implied_vol(95.0, 0.25) - expected).abs() < 1e-5);
+
+        // Halfway in time the total variance is the average of the neighbouring slices
+        let k = 0.1;
+        let w = 0.5 * (SHORT.total_variance(k) + LONG.total_variance(k));
+        assert!((surface.total_variance(k, 0.625) - w).abs() < 1e-5);
+
+        let call = surface.price(100.0, 0.5, true);
+        let put = surface.price(100.0, 0.5, false);
+        let parity = call - put - (-0.02f64 * 0.5).exp() * (surface.forward(0.5) - 100.0);
+        assert!(parity.abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_validate_flags_bad_quotes() {
+        let rate: f64 = 0.02;
+        let mut points = quotes(&SHORT, 0.25, 100.0 * (rate * 0.25).exp());
+        points.extend(quotes(&LONG, 1.0, 100.0 * rate.exp()));
+        let curve = YieldCurve::flat(rate);
+        assert!(VolSurface::validate(100.0, &curve, &points).is_empty());
+
+        // A spiked vol at one strike breaks convexity
+        let mut spiked = points.clone();
+        spiked[3].implied_vol += 0.3;
+        let violations = VolSurface::validate(100.0, &curve, &spiked);
+        assert!(violations.contains(&QuoteViolation::Butterfly { expiry: 0.25, strike: spiked[3].strike }));
+
+        // A short-dated vol above the long-dated total variance is a calendar violation
+        let mut inverted = points.clone();
+        inverted[4].implied_vol = 0.9;
+        let violations = VolSurface::validate(100.0, &curve, &inverted);
+        assert!(violations.iter().any(|v| matches!(v, QuoteViolation::Calendar { strike, .. } if *strike == inverted[4].strike)));
+
+        let cleaned = VolSurface::without_violations(&spiked, &VolSurface::validate(100.0, &curve, &spiked));
+        assert_eq!(cleaned.len(), points.len() - 1);
+        assert!(VolSurface::validate(100.0, &curve, &cleaned).is_empty());
+    }
+
+    #[test]
+    fn test_arbitrage_diagnostics() {
+        let mut surface = surface();
+        assert!(surface.is_arbitrage_free());
+
+        // A later slice with less total variance is a calendar spread arbitrage
+        surface.slices[1].params.a = 0.0;
+        surface.slices[1].params.b = 0.01;
+        assert!(surface.arbitrage_violations(1.0, 11).iter()
+            .any(|v| matches!(v, ArbitrageViolation::Calendar { .. })));
+
+        // Vogt's example of a valid SVI slice with butterfly arbitrage
+        let vogt = SviParams { a: -0.0410, b: 0.1331, rho: 0.3060, m: 0.3586, sigma: 0.4153 };
+        assert!(vogt.is_valid());
+        let surface = VolSurface {
+            spot: 100.0,
+            curve: YieldCurve::flat(0.0),
+            slices: vec![SviSlice { expiry: 1.0, forward: 100.0, params: vogt, rmse: 0.0 }],
+        };
+        assert!(surface.arbitrage_violations(1.5, 61).iter()
+            .any(|v| matches!(v, ArbitrageViolation::Butterfly { .. })));
+    }
+}
@@ -0,0 +1,144 @@
+//! wasm-bindgen wrappers so web front-ends can price client-side. Errors reach
+//! JavaScript as thrown `Error`s carrying the pricer's message.
+
+use wasm_bindgen::prelude::*;
+use crate::engine::PricingEngine;
+use crate::fourier::QuantoOption;
+use crate::implied_vol::ImpliedVolatility;
+
+
+/// Call and put prices of one option.
+#[wasm_bindgen(js_name = "Price")]
+#[derive(Debug, Clone, Copy)]
+pub struct WasmPrice {
+    pub call: f64,
+    pub put: f64,
+}
+
+/// See [`crate::greeks::Greeks`] for the units.
+#[wasm_bindgen(js_name = "Greeks")]
+#[derive(Debug, Clone, Copy)]
+pub struct WasmGreeks {
+    pub delta: f64,
+    pub gamma: f64,
+    pub vega: f64,
+    pub theta: f64,
+    pub rho: f64,
+}
+
+/// A quanto option. Rates, quanto terms and dividend yield default to zero and are set
+/// by chaining, e.g. `new QuantoOption(100, 110, 0.5, 0.5).withRates(0.03, 0).withQuanto(0.1, -0.3)`.
+#[wasm_bindgen(js_name = "QuantoOption")]
+#[derive(Debug, Clone, Copy)]
+pub struct WasmQuantoOption {
+    spot: f64,
+    strike: f64,
+    volatility: f64,
+    time_to_maturity: f64,
+    domestic_rate: f64,
+    foreign_rate: f64,
+    fx_volatility: f64,
+    correlation: f64,
+    dividend_yield: f64,
+}
+
+#[wasm_bindgen(js_class = "QuantoOption")]
+impl WasmQuantoOption {
+    #[wasm_bindgen(constructor)]
+    pub fn new(spot: f64, strike: f64, volatility: f64, time_to_maturity: f64) -> WasmQuantoOption {
+        WasmQuantoOption {
+            spot,
+            strike,
+            volatility,
+            time_to_maturity,
+            domestic_rate: 0.0,
+            foreign_rate: 0.0,
+            fx_volatility: 0.0,
+            correlation: 0.0,
+            dividend_yield: 0.0,
+        }
+    }
+
+    #[wasm_bindgen(js_name = "withRates")]
+    pub fn with_rates(mut self, domestic_rate: f64, foreign_rate: f64) -> WasmQuantoOption {
+        self.domestic_rate = domestic_rate;
+        self.foreign_rate = foreign_rate;
+        self
+    }
+
+    #[wasm_bindgen(js_name = "withQuanto")]
+    pub fn with_quanto(mut self, fx_volatility: f64, correlation: f64) -> WasmQuantoOption {
+        self.fx_volatility = fx_volatility;
+        self.correlation = correlation;
+        self
+    }
+
+    #[wasm_bindgen(js_name = "withDividendYield")]
+    pub fn with_dividend_yield(mut self, dividend_yield: f64) -> WasmQuantoOption {
+        self.dividend_yield = dividend_yield;
+        self
+    }
+
+    /// Prices by FFT; see [`QuantoOption::calculate_price_fft`].
+    #[wasm_bindgen(js_name = "calculatePriceFft")]
+    pub fn calculate_price_fft(&self) -> Result<WasmPrice, JsError> {
+        let price = self.option()?.calculate_price_fft().map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(WasmPrice { call: price.call, put: price.put })
+    }
+
+    /// Greeks of the call, or of the put when `is_call` is false, repriced by FFT.
+    pub fn greeks(&self, is_call: bool) -> Result<WasmGreeks, JsError> {
+        let greeks = self.option()?.greeks(is_call, &PricingEngine::default());
+        Ok(WasmGreeks { delta: greeks.delta, gamma: greeks.gamma, vega: greeks.vega, theta: greeks.theta, rho: greeks.rho })
+    }
+
+    /// The volatility at which this option prices at `market_price`.
+    #[wasm_bindgen(js_name = "impliedVolatility")]
+    pub fn implied_volatility(&self, market_price: f64, is_call: bool) -> Result<f64, JsError> {
+        self.option()?.implied_volatility(market_price, is_call).map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    fn option(&self) -> Result<QuantoOption, JsError> {
+        QuantoOption::builder()
+            .spot(self.spot)
+            .strike(self.strike)
+            .volatility(self.volatility)
+            .time_to_maturity(self.time_to_maturity)
+            .domestic_rate(self.domestic_rate)
+            .foreign_rate(self.foreign_rate)
+            .quanto(self.fx_volatility, self.correlation)
+            .dividend_yield(self.dividend_yield)
+            .build()
+            .map_err(|e| JsError::new(&e.to_string()))
+    }
+}
+
+/// Black–Scholes implied volatility of a call, or a put when `is_call` is false.
+#[wasm_bindgen(js_name = "impliedVolatility")]
+pub fn implied_volatility(spot: f64, strike: f64, time_to_maturity: f64, market_price: f64, rate: f64, is_call: bool) -> Result<f64, JsError> {
+    let problem = ImpliedVolatility { spot, strike, r: rate, time_to_maturity, market_price, is_call, dividend_yield: 0.0 };
+    problem.validate().map_err(|e| JsError::new(&e.to_string()))?;
+    problem.implied_volatility().map_err(|e| JsError::new(&e.to_string()))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Only the success paths: a JsError needs a JavaScript host to be created
+    #[test]
+    fn test_wrappers_match_the_pricer() {
+        let option = WasmQuantoOption::new(100.0, 110.0, 0.5, 0.5).with_rates(0.03, 0.0).with_quanto(0.1, -0.3);
+        let price = option.calculate_price_fft().unwrap();
+        let expected = QuantoOption::builder().spot(100.0).strike(110.0).volatility(0.5).time_to_maturity(0.5)
+            .domestic_rate(0.03).quanto(0.1, -0.3).build().unwrap().calculate_price_fft().unwrap();
+        assert_eq!((price.call, price.put), (expected.call, expected.put));
+        assert!((option.implied_volatility(price.call, true).unwrap() - 0.5).abs() < 1e-3);
+
+        let greeks = option.greeks(true).unwrap();
+        assert!(greeks.delta > 0.0 && greeks.delta < 1.0 && greeks.vega > 0.0);
+        let iv = implied_volatility(100.0, 100.0, 1.0, 10.0, 0.0, true).unwrap();
+        assert!(iv > 0.2 && iv < 0.3);
+    }
+}